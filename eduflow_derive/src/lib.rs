@@ -1,38 +1,27 @@
 use proc_macro::TokenStream;
-use quote::quote;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{ToTokens, quote};
 use syn::{
-    Data, DataStruct, DeriveInput, Fields, GenericArgument, PathArguments, Type, parse_macro_input,
+    Attribute, Data, DataStruct, DeriveInput, Error, Fields, FieldsNamed, GenericArgument, LitStr,
+    PathArguments, Token, Type, meta::ParseNestedMeta, parenthesized, parse_macro_input,
 };
 
+/// implements Sendable (get_id, with_id and strip_id), the first field has to be `id: Option<i32>`, unset for new objects
 #[proc_macro_derive(SendObject)]
 pub fn send_object_derive(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
+    send_object_impl(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
 
+fn send_object_impl(input: &DeriveInput) -> syn::Result<TokenStream2> {
     // get struct name
-    let struct_name = input.ident;
+    let struct_name = &input.ident;
     //get fields
-    let fields = if let Data::Struct(DataStruct {
-        fields: Fields::Named(ref fields),
-        ..
-    }) = input.data
-    {
-        fields
-    } else {
-        panic!("SendObject needs named struct fields");
-    };
-
-    // first field has to be id
-    let id_field_name = fields
-        .named
-        .get(0)
-        .expect("SendObject needs at least one field")
-        .ident
-        .as_ref()
-        .unwrap()
-        .to_string();
-    if id_field_name != "id" {
-        panic!("SendObject first field must be \"id\"!");
-    }
+    let fields = get_named_fields(input, "SendObject")?;
+    // first field has to be id, unset for new objects
+    check_id_field(fields, "SendObject", "Option<i32>")?;
 
     let generator = quote! {
         impl crate::data_handler::Sendable for #struct_name {
@@ -40,77 +29,439 @@ pub fn send_object_derive(input: TokenStream) -> TokenStream {
             fn get_id(&self) -> Option<i32> {
                 self.id
             }
+
+            fn with_id(self, id: i32) -> Self {
+                Self { id: Some(id), ..self }
+            }
+
+            fn strip_id(self) -> Self {
+                Self { id: None, ..self }
+            }
         }
 
     };
 
-    generator.into()
+    Ok(generator)
 }
 
-#[proc_macro_derive(DBObject)]
+/// field attributes:
+/// `#[db(references = "CourseDB", on_delete = "cascade")]` adds a foreign key to the id of the other type,
+/// on_delete is optional and one of cascade, set_null, restrict or no_action
+/// `#[db(unique)]` makes the column unique, not allowed on encrypted (BLOB) fields, they would only compare the ciphertext
+/// `#[db(enum_text)]` stores the field as TEXT, the type needs Display and FromStr (e.g. an enum),
+/// its values are SQLValue::Text of the Display output, see sql_value_from_text! for db_param_map!
+/// `#[db(default = "0")]` adds a DEFAULT to the column, used by inserts without the field and when the column is added to
+/// an existing table, a number, a quoted string ('text'), NULL, TRUE, FALSE or CURRENT_TIMESTAMP, CURRENT_DATE, CURRENT_TIME
+/// `#[db(check = "progress BETWEEN 0 AND 100")]` adds a CHECK named after the column, NULL always passes it,
+/// violations are DbError::CheckViolation with the column name
+/// `#[db(append_within = "course_id")]` on an i32 field: inserts without the field get the highest value of the rows
+/// of the user with the same course_id plus one (0 for the first row), so new rows are placed last
+///
+/// struct attributes:
+/// `#[db(soft_delete)]` adds a nullable deleted_at column, rows with deleted_at set are left out by select, count and update
+/// `#[db(timestamps)]` needs the fields `created_at: NaiveDateTime` and `updated_at: Option<NaiveDateTime>`,
+/// they are set by inserts and updates and are never part of to_params
+/// `#[db(table = "course")]` names the table, the struct name by default (references of other types use the table name)
+/// `#[db(ident = "CourseDB")]` overrides the DBObjIdent, the struct name by default
+/// the ident is stored with the local tokens of every user, changing it orphans them, so keep it when renaming the struct
+/// `#[db(unique_together("user_id", "name"))]` makes the combination of the fields unique, can be given several times
+/// unique constraints of existing tables are added as unique indices by verify_schema_for_type
+/// `#[db(check = "start <= end")]` adds a CHECK over several columns, can be given several times,
+/// violations are DbError::CheckViolation with the expression
+/// sqlite can not add checks to existing tables, they only apply to tables created with them
+/// `#[db(order_by("course_id", "position"))]` orders selects without an explicit order by the fields (ascending) and id
+#[proc_macro_derive(DBObject, attributes(db))]
 pub fn db_object_derive(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
+    db_object_impl(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
 
+fn db_object_impl(input: &DeriveInput) -> syn::Result<TokenStream2> {
     // get struct name
-    let struct_name = input.ident;
+    let struct_name = &input.ident;
     let struct_name_string = struct_name.to_string();
+    let StructAttributes {
+        soft_delete,
+        timestamps,
+        table,
+        ident,
+        unique_together,
+        checks,
+        order_by,
+    } = get_struct_attributes(&input.attrs)?;
+    let table_name = table.unwrap_or_else(|| struct_name_string.clone());
+    let ident_name = ident.unwrap_or_else(|| struct_name_string.clone());
 
     // get fields
-    let fields = if let Data::Struct(DataStruct {
-        fields: Fields::Named(ref fields),
-        ..
-    }) = input.data
+    let fields = get_named_fields(input, "DBObject")?;
+    // first field has to be id, the primary key
+    check_id_field(fields, "DBObject", "i32")?;
+    for field in fields.named.iter() {
+        check_sql_type(&field.ty)?;
+    }
+    let attributes = fields
+        .named
+        .iter()
+        .map(|field| get_field_attributes(&field.attrs))
+        .collect::<syn::Result<Vec<_>>>()?;
+    // sql type of every field in order, enum_text fields are TEXT regardless of their type
+    let sql_types: Vec<String> = fields
+        .named
+        .iter()
+        .zip(attributes.iter())
+        .map(|(field, attributes)| {
+            let sql_type = get_sql_type(&field.ty);
+            match (attributes.enum_text, sql_type.ends_with("NOT NULL")) {
+                (true, true) => "TEXT NOT NULL".to_string(),
+                (true, false) => "TEXT".to_string(),
+                (false, _) => sql_type,
+            }
+        })
+        .collect();
+
+    // timestamp fields are filled by the database, not by the caller
+    let is_timestamp =
+        |field_name: &str| timestamps && (field_name == "created_at" || field_name == "updated_at");
+    if timestamps
+        && fields
+            .named
+            .iter()
+            .filter(|field| is_timestamp(&field.ident.as_ref().unwrap().to_string()))
+            .count()
+            != 2
     {
+        return Err(Error::new_spanned(
+            struct_name,
+            "DBObject with timestamps needs created_at and updated_at fields",
+        ));
+    }
+
+    // encrypted values differ for equal texts, a unique constraint on them would never be violated
+    let is_plain = |i: usize| !sql_types[i].starts_with("BLOB");
+    let field_index = |column: &LitStr| {
         fields
-    } else {
-        panic!("DBObject needs named struct fields");
+            .named
+            .iter()
+            .position(|field| field.ident.as_ref().unwrap() == &column.value())
+            .ok_or_else(|| Error::new_spanned(column, "unknown field"))
     };
 
-    // first field has to be id
-    let id_field_name = fields
-        .named
-        .get(0)
-        .expect("DBObject needs at least one field")
-        .ident
-        .as_ref()
-        .unwrap()
-        .to_string();
-    if id_field_name != "id" {
-        panic!("DBObject first field must be \"id\"!");
+    // the ordering of encrypted values would only order the ciphertext
+    for column in order_by.iter() {
+        if !is_plain(field_index(column)?) {
+            return Err(Error::new_spanned(
+                column,
+                "order_by can not contain encrypted fields, they only compare the ciphertext",
+            ));
+        }
+    }
+    let order_by = order_by.iter().map(LitStr::value);
+
+    // at most one field is appended, it is numbered within the rows of its group
+    let mut appended = None;
+    for (i, field) in fields.named.iter().enumerate() {
+        let Some(group) = &attributes[i].append_within else {
+            continue;
+        };
+        if sql_types[i] != "INTEGER NOT NULL" || get_inner_type_name(&field.ty) != "i32" {
+            return Err(Error::new_spanned(
+                &field.ty,
+                "append_within needs an i32 field",
+            ));
+        }
+        if appended.is_some() {
+            return Err(Error::new_spanned(
+                group,
+                "append_within can only be used on one field",
+            ));
+        }
+        if !is_plain(field_index(group)?) {
+            return Err(Error::new_spanned(
+                group,
+                "append_within can not group by encrypted fields, they only compare the ciphertext",
+            ));
+        }
+        appended = Some((field.ident.as_ref().unwrap().to_string(), group.value()));
     }
+    let mut unique_columns = Vec::new();
 
     // prepare sql strings
     // sql string with field name and data type
     let mut db_table = "id INTEGER PRIMARY KEY AUTOINCREMENT".to_string();
     // sql string with comma seperated list of parameters
     let mut parameter_list = "".to_string();
+    // column names with their definition, used to verify existing tables
+    let mut column_defs = Vec::new();
 
     // populate sql strings (without id)
-    fields.named.iter().skip(1).for_each(|field| {
-        let type_str = get_sql_type(&field.ty);
+    for (i, field) in fields.named.iter().enumerate().skip(1) {
+        let type_str = &sql_types[i];
         let field_name = field.ident.as_ref().unwrap().to_string();
+        let mut foreign_key = attributes[i].foreign_key.clone();
+        if is_timestamp(&field_name) && field_name == "created_at" {
+            foreign_key = " DEFAULT CURRENT_TIMESTAMP".to_string();
+        }
+        let default = attributes[i]
+            .default
+            .as_ref()
+            .map(|default| format!(" DEFAULT {}", default))
+            .unwrap_or_default();
+        // named after the column, so a violation can name the field
+        let check = attributes[i]
+            .check
+            .as_ref()
+            .map(|check| format!(" CONSTRAINT {} CHECK ({})", field_name, check))
+            .unwrap_or_default();
+        let unique_constraint = if attributes[i].unique {
+            if !is_plain(i) {
+                return Err(Error::new_spanned(
+                    &field.ty,
+                    "unique can not be used on encrypted fields, they only compare the ciphertext",
+                ));
+            }
+            unique_columns.push(vec![field_name.clone()]);
+            " UNIQUE"
+        } else {
+            ""
+        };
 
-        db_table.push_str(format!(",{} {}", field_name, type_str).as_str());
+        db_table.push_str(
+            format!(
+                ",{} {}{}{}{}{}",
+                field_name, type_str, unique_constraint, default, check, foreign_key
+            )
+            .as_str(),
+        );
         parameter_list.push_str(format!("{field_name},").as_str());
-    });
+        // sqlite can not add unique columns, verify_schema_for_type creates an index instead
+        column_defs.push((
+            field_name,
+            format!("{}{}{}{}", type_str, default, check, foreign_key),
+        ));
+    }
     // remove extra comma
     parameter_list.pop();
+    // not part of the struct, only set while the row is in the trash
+    if soft_delete {
+        db_table.push_str(",deleted_at DATETIME");
+        column_defs.push(("deleted_at".to_string(), "DATETIME".to_string()));
+    }
+    // table constraints follow the columns
+    for columns in unique_together {
+        for column in columns.iter() {
+            let i = fields
+                .named
+                .iter()
+                .position(|field| field.ident.as_ref().unwrap() == &column.value())
+                .ok_or_else(|| Error::new_spanned(column, "unknown field"))?;
+            if !is_plain(i) {
+                return Err(Error::new_spanned(
+                    column,
+                    "unique_together can not contain encrypted fields, they only compare the ciphertext",
+                ));
+            }
+        }
+        let columns: Vec<String> = columns.iter().map(LitStr::value).collect();
+        db_table.push_str(format!(",UNIQUE({})", columns.join(",")).as_str());
+        unique_columns.push(columns);
+    }
+    for check in checks {
+        db_table.push_str(format!(",CHECK ({})", check).as_str());
+    }
+    let unique_columns = unique_columns
+        .iter()
+        .map(|columns| quote! { &[#(#columns),*] });
+    let column_defs = column_defs
+        .iter()
+        .map(|(name, definition)| quote! { (#name.to_string(), #definition.to_string()) });
+
+    // soft deleted rows are hidden by an additional condition, it does not take a placeholder
+    let hide_deleted_clause = if soft_delete {
+        quote! { let where_clause = &where_clause.not_deleted(); }
+    } else {
+        quote! {}
+    };
+    let hide_deleted_fields = if soft_delete {
+        quote! {
+            let deleted_at = "deleted_at".to_string();
+            let mut where_fields = where_fields;
+            where_fields.insert(0, (&deleted_at, crate::db::sql_helper::WhereOp::IsNull));
+        }
+    } else {
+        quote! {}
+    };
+    // inserts set created_at and updates set updated_at, unless the caller sets them
+    let insert_created_at = if timestamps {
+        quote! {
+            if !fields.iter().any(|field| field.as_str() == "created_at") {
+                field_names.push_str(",created_at");
+                field_subst.push_str(",CURRENT_TIMESTAMP");
+            }
+        }
+    } else {
+        quote! {}
+    };
+    // the next value is selected while inserting, user_id and the group column have to be inserted as well
+    let insert_appended = match &appended {
+        Some((column, group)) => {
+            let next = format!(
+                ",(SELECT COALESCE(MAX({column}) + 1, 0) FROM {table_name} WHERE user_id = ?{{}} AND {group} = ?{{}})"
+            );
+            quote! {
+                let position = |name: &str| fields.iter().position(|field| field.as_str() == name);
+                if let (false, Some(user), Some(group)) = (
+                    fields.iter().any(|field| field.as_str() == #column),
+                    position("user_id"),
+                    position(#group),
+                ) {
+                    field_names.push_str(concat!(",", #column));
+                    field_subst.push_str(&format!(#next, user + 1, group + 1));
+                }
+            }
+        }
+        None => quote! {},
+    };
+    let appended = match appended {
+        Some((column, group)) => quote! { Some((#column, #group)) },
+        None => quote! { None },
+    };
+    let update_updated_at = if timestamps {
+        quote! {
+            if !fields.contains(" updated_at =") {
+                fields.push_str(", updated_at = CURRENT_TIMESTAMP");
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let db_restore = if soft_delete {
+        quote! {
+            if where_fields.is_empty() {
+                return Err(crate::db::sql_helper::SQLGenError::MissingWhere("restore"));
+            }
+            let where_fields = crate::db::sql_helper::where_clause(&where_fields, 1);
+            Ok(format!("UPDATE {} SET deleted_at = NULL WHERE deleted_at >= ?1 AND {}", #table_name, where_fields))
+        }
+    } else {
+        quote! { Err(crate::db::sql_helper::SQLGenError::NoSoftDelete) }
+    };
+
+    // every column in table order, including id
+    let field_names = fields
+        .named
+        .iter()
+        .map(|field| field.ident.as_ref().unwrap().to_string());
+    // declared type of every column, the nullability follows from the rust type
+    let field_types = sql_types
+        .iter()
+        .map(|sql_type| sql_type.trim_end_matches(" NOT NULL").to_string());
+    // encrypted values are stored as BLOB, ordering by them would only order the ciphertext
+    let sortable_fields = fields
+        .named
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| is_plain(*i))
+        .map(|(_, field)| field.ident.as_ref().unwrap().to_string());
+
+    // query values are parsed by the rust type of the field (without Option)
+    // "null" selects unset values of nullable fields, even encrypted ones
+    // enum_text values are parsed into their type, so unknown values are refused as well
+    let filter_arms = fields.named.iter().enumerate().map(|(i, field)| {
+        let field_name = field.ident.as_ref().unwrap().to_string();
+        let type_name = get_inner_type_name(&field.ty);
+        let parse = if attributes[i].enum_text {
+            let inner_type = get_inner_type(&field.ty);
+            quote! {
+                value.parse::<#inner_type>().ok().map(|value| crate::db::sql_helper::SQLValue::Text(value.to_string()))
+            }
+        } else {
+            quote! { crate::db::sql_helper::SQLValue::parse_as(#type_name, value) }
+        };
+
+        if sql_types[i].ends_with("NOT NULL") {
+            quote! {
+                #field_name => #parse,
+            }
+        } else {
+            quote! {
+                #field_name if value == "null" => Some(crate::db::sql_helper::SQLValue::Null),
+                #field_name => #parse,
+            }
+        }
+    });
+    // enum_text fields are read as text and parsed, NULL is None for Option fields
+    let enum_from_sql = |i: usize| {
+        if sql_types[i].ends_with("NOT NULL") {
+            quote! { crate::db::sql_helper::enum_from_sql }
+        } else {
+            quote! { crate::db::sql_helper::optional_enum_from_sql }
+        }
+    };
 
     // rusqlite specific
     // rusqlite row assignment
     let field_assignments = fields.named.iter().enumerate().map(|(i, field)| {
         let field_name = field.ident.as_ref().unwrap();
 
+        if attributes[i].enum_text {
+            let enum_from_sql = enum_from_sql(i);
+            quote! {
+                #field_name: #enum_from_sql(row.get_ref(#i)?)?
+            }
+        } else {
+            quote! {
+                #field_name: row.get(#i)?
+            }
+        }
+    });
+
+    let value_assignments = fields.named.iter().enumerate().map(|(i, field)| {
+        let field_name = field.ident.as_ref().unwrap();
+        let field_name_string = field_name.to_string();
+        let from_sql = if attributes[i].enum_text {
+            enum_from_sql(i)
+        } else {
+            quote! { rusqlite::types::FromSql::column_result }
+        };
+
         quote! {
-            #field_name: row.get(#i)?
+            #field_name: #from_sql(
+                values.get(#field_name_string).map_or(rusqlite::types::ValueRef::Null, rusqlite::types::ValueRef::from)
+            )?
         }
     });
 
-    quote! {
+    // every field besides id and user_id, converted into sql values without touching the content
+    let param_entries = fields
+        .named
+        .iter()
+        .enumerate()
+        .map(|(i, field)| (i, field.ident.as_ref().unwrap()))
+        .filter(|(_, field_name)| {
+            *field_name != "id"
+                && *field_name != "user_id"
+                && !is_timestamp(&field_name.to_string())
+        })
+        .map(|(i, field_name)| {
+            let field_name_string = field_name.to_string();
+            let value = match (attributes[i].enum_text, sql_types[i].ends_with("NOT NULL")) {
+                (true, true) => quote! { self.#field_name.to_string() },
+                (true, false) => quote! { self.#field_name.as_ref().map(ToString::to_string) },
+                (false, _) => quote! { self.#field_name.clone() },
+            };
+            quote! {
+                (#field_name_string.to_string(), crate::db::sql_helper::SQLValue::from(#value))
+            }
+        });
+
+    Ok(quote! {
         // trait definition in main crate
         impl crate::db::sql_helper::SQLGenerate for #struct_name {
             fn get_db_table_create() -> String {
-                format!("CREATE TABLE IF NOT EXISTS {} ({})", #struct_name_string, #db_table)
+                format!("CREATE TABLE IF NOT EXISTS {} ({})", #table_name, #db_table)
             }
 
             fn get_db_insert(fields: Vec<&String>) -> String {
@@ -120,68 +471,173 @@ pub fn db_object_derive(input: TokenStream) -> TokenStream {
                 // remove trailing ","
                 field_names.pop();
                 field_subst.pop();
+                #insert_created_at
+                #insert_appended
+
+                format!("INSERT INTO {} ({}) VALUES ({})", #table_name, field_names, field_subst)
+            }
+
+            // generates an insert statement, which updates the row instead if the id already exists
+            // the row is only updated if it belongs to the same user, ids of other users can not be taken over
+            fn get_db_upsert(fields: Vec<&String>) -> String {
+                let db_insert = Self::get_db_insert(fields.clone());
 
-                format!("INSERT INTO {} ({}) VALUES ({})", #struct_name_string, field_names, field_subst)
+                // id and user_id identify the row, they are never changed, neither is the creation time
+                let mut updates: String = fields.iter().filter(|field| field.as_str() != "id" && field.as_str() != "user_id" && field.as_str() != "created_at").map(|field| {
+                    format!(" {} = excluded.{},", field, field)
+                }).collect();
+                updates.pop();
+                if !updates.is_empty() {
+                    let mut fields = updates;
+                    #update_updated_at
+                    updates = fields;
+                }
+
+                if updates.is_empty() {
+                    return format!("{} ON CONFLICT(id) DO NOTHING", db_insert);
+                }
+                format!("{} ON CONFLICT(id) DO UPDATE SET{} WHERE user_id = excluded.user_id", db_insert, updates)
             }
 
-            // generates a sql select statement with a where statement depending on the where_fields (connected with and)
-            fn get_db_select(where_fields: Vec<&String>) -> String {
-                // id is excluded in parameter_list
-                let mut db_select = format!("SELECT id, {} FROM {}", #parameter_list, #struct_name_string);
+            // generates a sql count statement with a where statement depending on the where_fields (connected with and)
+            fn get_db_count(where_fields: Vec<(&String, crate::db::sql_helper::WhereOp)>) -> String {
+                #hide_deleted_fields
+                let mut db_count = format!("SELECT COUNT(*) FROM {}", #table_name);
 
                 if where_fields.is_empty() {
+                    return db_count;
+                }
+
+                db_count.push_str(" WHERE ");
+                db_count.push_str(&crate::db::sql_helper::where_clause(&where_fields, 0));
+                db_count
+            }
+
+            // generates a sql select statement with a where statement of the where clause (nested AND and OR groups)
+            fn get_db_select(where_clause: &crate::db::sql_helper::WhereClause) -> String {
+                #hide_deleted_clause
+                let mut db_select = format!("SELECT id, {} FROM {}", #parameter_list, #table_name);
+
+                if where_clause.is_empty() {
                     return db_select;
                 }
 
-                // we have at least one where condition:
-                db_select.push_str(" WHERE");
+                db_select.push_str(" WHERE ");
+                db_select.push_str(&where_clause.to_sql(0));
+                db_select
+            }
+
+            // select statement like get_db_select, ordered by the given fields and id last, so equal values keep a stable order
+            fn get_db_select_ordered(where_clause: &crate::db::sql_helper::WhereClause, order: &[(String, crate::db::sql_helper::SortDirection)]) -> String {
+                let mut db_select = Self::get_db_select(where_clause);
+                db_select.push_str(" ORDER BY");
 
-                where_fields.iter().enumerate().for_each(|(i, field)| {
-                    // field + 1 because sql parameters substitution begins at 1 and not 0
-                    db_select.push_str(format!(" {} = ?{} AND", field, i + 1).as_str());
+                order.iter().for_each(|(field, direction)| {
+                    // the names end up in the sql, callers have to validate them beforehand
+                    assert!(Self::field_names().contains(&field.as_str()), "Unknown sort field {}", field);
+                    db_select.push_str(format!(" {} {},", field, direction.as_sql()).as_str());
                 });
 
-                // we added one AND to much, return this instantely
-                db_select.strip_suffix(" AND").unwrap().to_string()
+                db_select.push_str(" id");
+                db_select
+            }
+
+            fn get_column_defs() -> Vec<(String, String)> {
+                vec![#(#column_defs),*]
+            }
+
+            fn field_names() -> &'static [&'static str] {
+                &[#(#field_names),*]
+            }
+
+            fn field_types() -> &'static [&'static str] {
+                &[#(#field_types),*]
+            }
+
+            fn sortable_fields() -> &'static [&'static str] {
+                &[#(#sortable_fields),*]
+            }
+
+            fn unique_columns() -> &'static [&'static [&'static str]] {
+                &[#(#unique_columns),*]
+            }
+
+            fn default_order() -> &'static [&'static str] {
+                &[#(#order_by),*]
+            }
+
+            fn appended_column() -> Option<(&'static str, &'static str)> {
+                #appended
+            }
+
+            fn parse_filter_value(field: &str, value: &str) -> Option<crate::db::sql_helper::SQLValue> {
+                match field {
+                    #(#filter_arms)*
+                    _ => None,
+                }
             }
 
             // generates a sql update statement depending on fields (which will be updated) and where_fields (which will be filtered for)
-            fn get_db_update(fields: Vec<&String>, where_fields: Vec<&String>) -> String {
+            fn get_db_update(fields: Vec<&String>, where_fields: Vec<(&String, crate::db::sql_helper::WhereOp)>) -> Result<String, crate::db::sql_helper::SQLGenError> {
                 // calculate offset for ? values (we use 1 to fields.len() for fields and fields.len() + 1 till ... for  where fields)
                 let where_i_offset = fields.len();
+                if fields.is_empty() {
+                    return Err(crate::db::sql_helper::SQLGenError::NoFields);
+                }
+                // an update without where would change every row of the table
+                if where_fields.is_empty() {
+                    return Err(crate::db::sql_helper::SQLGenError::MissingWhere("update"));
+                }
+                #hide_deleted_fields
 
                 // map the fields to the SET sql string
                 let mut fields: String = fields.iter().enumerate().map(|(i, field)| {
                     format!(" {} = ?{},", field, i + 1)
                 }).collect();
                 fields.pop();
+                #update_updated_at
 
                 // map the where fields to the WHERE sql string
-                let where_fields: String = where_fields.iter().enumerate().map(|(i, field)| {
-                    format!(" {} = ?{} AND", field, i + 1 + where_i_offset)
-                }).collect();
-                let where_fields = where_fields.strip_suffix(" AND").unwrap().to_string();
+                let where_fields = crate::db::sql_helper::where_clause(&where_fields, where_i_offset);
 
-                format!("UPDATE {} SET{} WHERE{}", #struct_name_string, fields, where_fields)
+                Ok(format!("UPDATE {} SET{} WHERE {}", #table_name, fields, where_fields))
             }
 
             // generates a sql delete statement depending on fields, which are used for the where clause
-            fn get_db_delete(fields: Vec<&String>) -> String {
+            fn get_db_delete(fields: Vec<(&String, crate::db::sql_helper::WhereOp)>) -> Result<String, crate::db::sql_helper::SQLGenError> {
+                // a delete without where would empty the whole table
+                if fields.is_empty() {
+                    return Err(crate::db::sql_helper::SQLGenError::MissingWhere("delete"));
+                }
                 // map the where fields to the WHERE sql string
-                let fields: String = fields.iter().enumerate().map(|(i, field)| {
-                    format!(" {} = ?{} AND", field, i + 1)
-                }).collect();
-                let fields = fields.strip_suffix(" AND").unwrap().to_string();
+                let fields = crate::db::sql_helper::where_clause(&fields, 0);
+
+                Ok(format!("DELETE FROM {} WHERE {}", #table_name, fields))
+            }
 
-                format!("DELETE FROM {} WHERE{}", #struct_name_string, fields)
+            fn soft_delete() -> bool {
+                #soft_delete
+            }
+
+            // generates a sql statement taking soft deleted rows out of the trash, if they were deleted at or after ?1
+            fn get_db_restore(where_fields: Vec<(&String, crate::db::sql_helper::WhereOp)>) -> Result<String, crate::db::sql_helper::SQLGenError> {
+                #db_restore
+            }
+
+            fn get_db_table() -> &'static str {
+                #table_name
             }
 
             fn get_db_ident() -> crate::db::DBObjIdent {
                 crate::db::DBObjIdent {
-                    db_identifier: #struct_name_string.to_string()
+                    db_identifier: #ident_name.to_string()
                 }
             }
 
+            fn to_params(&self) -> Vec<(String, crate::db::sql_helper::SQLValue)> {
+                vec![#(#param_entries),*]
+            }
+
             // rusqlite specific, converts a ruslite row into the struct itself
             fn row_to_struct(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
                 Ok(Self {
@@ -189,8 +645,322 @@ pub fn db_object_derive(input: TokenStream) -> TokenStream {
                 })
             }
 
+            // converts the column values of a row, which is not read from sqlite, missing columns are NULL
+            fn values_to_struct(values: &std::collections::HashMap<String, rusqlite::types::Value>) -> rusqlite::types::FromSqlResult<Self> {
+                Ok(Self {
+                    #(#value_assignments),*
+                })
+            }
+
+        }
+    })
+}
+
+/// named fields of the struct, tuple structs, unit structs, enums and unions are refused
+fn get_named_fields<'a>(input: &'a DeriveInput, derive: &str) -> syn::Result<&'a FieldsNamed> {
+    let message = format!("{} needs a struct with named fields", derive);
+    match &input.data {
+        Data::Struct(DataStruct {
+            fields: Fields::Named(fields),
+            ..
+        }) => Ok(fields),
+        Data::Struct(DataStruct {
+            fields: fields @ Fields::Unnamed(_),
+            ..
+        }) => Err(Error::new_spanned(fields, message)),
+        Data::Struct(_) => Err(Error::new_spanned(&input.ident, message)),
+        Data::Enum(data) => Err(Error::new_spanned(data.enum_token, message)),
+        Data::Union(data) => Err(Error::new_spanned(data.union_token, message)),
+    }
+}
+
+/// checks that the first field is called id and has the type id_type
+fn check_id_field(fields: &FieldsNamed, derive: &str, id_type: &str) -> syn::Result<()> {
+    let Some(field) = fields.named.first() else {
+        return Err(Error::new_spanned(
+            fields,
+            format!("{} needs at least one field", derive),
+        ));
+    };
+    if field.ident.as_ref().unwrap() != "id" {
+        return Err(Error::new_spanned(
+            &field.ident,
+            format!("{} first field must be \"id\"", derive),
+        ));
+    }
+    // compared without the spaces of the token stream, e.g. "Option < i32 >"
+    if field.ty.to_token_stream().to_string().replace(' ', "") != id_type {
+        return Err(Error::new_spanned(
+            &field.ty,
+            format!("{} id has to be {}", derive, id_type),
+        ));
+    }
+    Ok(())
+}
+
+/// refuses types get_sql_type can not map, e.g. references, tuples or arrays
+/// named types without their own sql type are stored as BLOB, e.g. CryptString
+fn check_sql_type(field_type: &Type) -> syn::Result<()> {
+    let unsupported = || {
+        Error::new_spanned(
+            field_type,
+            "unsupported field type, DBObject fields need an owned named type, e.g. i32, String or Option<CryptString>",
+        )
+    };
+    let Type::Path(type_path) = field_type else {
+        return Err(unsupported());
+    };
+    let segment = type_path.path.segments.last().ok_or_else(unsupported)?;
+    if segment.ident == "Option" {
+        let PathArguments::AngleBracketed(ref args) = segment.arguments else {
+            return Err(unsupported());
+        };
+        let Some(GenericArgument::Type(inner_type)) = args.args.first() else {
+            return Err(unsupported());
+        };
+        return check_sql_type(inner_type);
+    }
+    Ok(())
+}
+
+/// db attributes of a field
+#[derive(Default, Debug, PartialEq)]
+struct FieldAttributes {
+    /// REFERENCES clause, empty without references
+    foreign_key: String,
+    unique: bool,
+    /// stored as TEXT with Display and FromStr
+    enum_text: bool,
+    /// sql expression of the DEFAULT clause
+    default: Option<String>,
+    /// sql expression of the CHECK constraint
+    check: Option<String>,
+    /// column grouping the rows the field is numbered within, kept as literal for the error spans
+    append_within: Option<LitStr>,
+}
+
+/// parses the db attributes of a field
+fn get_field_attributes(attrs: &[Attribute]) -> syn::Result<FieldAttributes> {
+    let (mut references, mut on_delete) = (None, None);
+    let (mut unique, mut enum_text, mut default, mut check) = (false, false, None, None);
+    let mut append_within = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("db")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("unique") {
+                unique = true;
+            } else if meta.path.is_ident("enum_text") {
+                enum_text = true;
+            } else if meta.path.is_ident("default") {
+                let literal = meta.value()?.parse::<LitStr>()?;
+                // the value ends up in the create statement unquoted
+                if !is_sql_default(&literal.value()) {
+                    return Err(Error::new_spanned(
+                        literal,
+                        "default has to be a number, a quoted string, NULL, TRUE, FALSE or CURRENT_TIMESTAMP, CURRENT_DATE, CURRENT_TIME",
+                    ));
+                }
+                default = Some(literal.value());
+            } else if meta.path.is_ident("check") {
+                check = Some(parse_check(&meta)?);
+            } else if meta.path.is_ident("append_within") {
+                append_within = Some(meta.value()?.parse::<LitStr>()?);
+            } else if meta.path.is_ident("references") {
+                references = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("on_delete") {
+                let literal = meta.value()?.parse::<LitStr>()?;
+                let action = match literal.value().as_str() {
+                    "cascade" => "CASCADE",
+                    "set_null" => "SET NULL",
+                    "restrict" => "RESTRICT",
+                    "no_action" => "NO ACTION",
+                    _ => return Err(Error::new_spanned(literal, "unknown on_delete action")),
+                };
+                on_delete = Some((action, literal));
+            } else {
+                return Err(meta.error("unknown db attribute"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let foreign_key = match (references, on_delete) {
+        (None, None) => String::new(),
+        (None, Some((_, literal))) => {
+            return Err(Error::new_spanned(literal, "on_delete needs references"));
+        }
+        (Some(table), None) => format!(" REFERENCES {}(id)", table),
+        (Some(table), Some((action, _))) => {
+            format!(" REFERENCES {}(id) ON DELETE {}", table, action)
+        }
+    };
+    Ok(FieldAttributes {
+        foreign_key,
+        unique,
+        enum_text,
+        default,
+        check,
+        append_within,
+    })
+}
+
+/// db attributes of the struct itself
+#[derive(Default, Debug, PartialEq)]
+struct StructAttributes {
+    soft_delete: bool,
+    timestamps: bool,
+    /// name of the table, the struct name if none
+    table: Option<String>,
+    /// db identifier of the type, the struct name if none
+    ident: Option<String>,
+    /// field names of every unique_together attribute, kept as literals for the error spans
+    unique_together: Vec<Vec<LitStr>>,
+    /// sql expressions of the table CHECK constraints
+    checks: Vec<String>,
+    /// fields of the default order of selects, kept as literals for the error spans
+    order_by: Vec<LitStr>,
+}
+
+/// parses the db attributes of the struct, flags or names
+fn get_struct_attributes(attrs: &[Attribute]) -> syn::Result<StructAttributes> {
+    let mut result = StructAttributes::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("db")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("soft_delete") {
+                result.soft_delete = true;
+            } else if meta.path.is_ident("timestamps") {
+                result.timestamps = true;
+            } else if meta.path.is_ident("table") {
+                let table = meta.value()?.parse::<LitStr>()?.value();
+                // the name ends up in every statement unquoted
+                if !is_sql_identifier(&table) {
+                    return Err(meta.error("table has to be a plain sql identifier"));
+                }
+                result.table = Some(table);
+            } else if meta.path.is_ident("ident") {
+                result.ident = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("unique_together") {
+                let content;
+                parenthesized!(content in meta.input);
+                let columns: Vec<LitStr> = content
+                    .parse_terminated(|input| input.parse::<LitStr>(), Token![,])?
+                    .into_iter()
+                    .collect();
+                if columns.is_empty() {
+                    return Err(meta.error("unique_together needs at least one field"));
+                }
+                result.unique_together.push(columns);
+            } else if meta.path.is_ident("check") {
+                result.checks.push(parse_check(&meta)?);
+            } else if meta.path.is_ident("order_by") {
+                let content;
+                parenthesized!(content in meta.input);
+                result.order_by = content
+                    .parse_terminated(|input| input.parse::<LitStr>(), Token![,])?
+                    .into_iter()
+                    .collect();
+                if result.order_by.is_empty() {
+                    return Err(meta.error("order_by needs at least one field"));
+                }
+            } else {
+                return Err(meta.error("unknown db attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(result)
+}
+
+/// parses the expression of a check attribute
+fn parse_check(meta: &ParseNestedMeta) -> syn::Result<String> {
+    let literal = meta.value()?.parse::<LitStr>()?;
+    // the expression ends up in the create statement inside of CHECK (...)
+    if !is_sql_check(&literal.value()) {
+        return Err(Error::new_spanned(
+            literal,
+            "check has to be a single sql expression with balanced parentheses",
+        ));
+    }
+    Ok(literal.value())
+}
+
+/// true if expression can be put into CHECK (...) without ending it or the statement early
+fn is_sql_check(expression: &str) -> bool {
+    let (mut depth, mut quoted) = (0usize, false);
+    for c in expression.chars() {
+        match c {
+            '\'' => quoted = !quoted,
+            _ if quoted => {}
+            '(' => depth += 1,
+            ')' if depth == 0 => return false,
+            ')' => depth -= 1,
+            ';' => return false,
+            _ => {}
         }
-    }.into()
+    }
+    !expression.trim().is_empty()
+        && depth == 0
+        && !quoted
+        && !expression.contains("--")
+        && !expression.contains("/*")
+}
+
+/// true if name can be used unquoted in sql, letters, digits and underscores, not starting with a digit
+fn is_sql_identifier(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// true if value is a constant sqlite accepts as DEFAULT without parentheses
+fn is_sql_default(value: &str) -> bool {
+    let number = value.strip_prefix('-').unwrap_or(value);
+    let is_number = number.starts_with(|c: char| c.is_ascii_digit())
+        && number.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && number.matches('.').count() <= 1;
+    // quotes inside the string are escaped by doubling them
+    let is_string = value.len() >= 2
+        && value.starts_with('\'')
+        && value.ends_with('\'')
+        && !value[1..value.len() - 1].replace("''", "").contains('\'');
+    let is_keyword = [
+        "NULL",
+        "TRUE",
+        "FALSE",
+        "CURRENT_TIMESTAMP",
+        "CURRENT_DATE",
+        "CURRENT_TIME",
+    ]
+    .contains(&value);
+
+    is_number || is_string || is_keyword
+}
+
+/// the type itself, Option<T> is unwrapped to T
+fn get_inner_type(field_type: &Type) -> &Type {
+    if let Type::Path(type_path) = field_type {
+        let segment = type_path.path.segments.last().unwrap();
+        if segment.ident == "Option"
+            && let PathArguments::AngleBracketed(ref args) = segment.arguments
+            && let Some(GenericArgument::Type(inner_type)) = args.args.first()
+        {
+            return inner_type;
+        }
+    }
+    field_type
+}
+
+/// name of the last path segment of the type, Option<T> is unwrapped to T
+fn get_inner_type_name(field_type: &Type) -> String {
+    match get_inner_type(field_type) {
+        Type::Path(type_path) => type_path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident.to_string())
+            .unwrap_or_default(),
+        _ => String::new(),
+    }
 }
 
 fn get_sql_type(field_type: &Type) -> String {
@@ -203,13 +973,12 @@ fn get_sql_type(field_type: &Type) -> String {
             // check for Option<T>
             if type_path.path.segments.len() == 1 {
                 let segment = &type_path.path.segments[0];
-                if segment.ident == "Option" {
-                    if let PathArguments::AngleBracketed(ref args) = segment.arguments {
-                        if let Some(GenericArgument::Type(inner_type)) = args.args.first() {
-                            check_type = inner_type;
-                            result = "".into();
-                        }
-                    }
+                if segment.ident == "Option"
+                    && let PathArguments::AngleBracketed(ref args) = segment.arguments
+                    && let Some(GenericArgument::Type(inner_type)) = args.args.first()
+                {
+                    check_type = inner_type;
+                    result = "".into();
                 }
             }
 
@@ -271,4 +1040,190 @@ mod tests {
             assert_eq!(sql_type, expected, "Failed for type {}", ty_str);
         }
     }
+
+    #[test]
+    fn test_get_field_attributes() {
+        let foreign_key = |foreign_key: &str, unique| FieldAttributes {
+            foreign_key: foreign_key.to_string(),
+            unique,
+            ..FieldAttributes::default()
+        };
+        let test_cases = vec![
+            ("struct S { a: i32 }", foreign_key("", false)),
+            (
+                "struct S { #[db(references = \"CourseDB\")] a: i32 }",
+                foreign_key(" REFERENCES CourseDB(id)", false),
+            ),
+            (
+                "struct S { #[db(references = \"CourseDB\", on_delete = \"cascade\")] a: i32 }",
+                foreign_key(" REFERENCES CourseDB(id) ON DELETE CASCADE", false),
+            ),
+            (
+                "struct S { #[db(unique, references = \"CourseDB\")] a: i32 }",
+                foreign_key(" REFERENCES CourseDB(id)", true),
+            ),
+            (
+                "struct S { #[db(enum_text)] a: Priority }",
+                FieldAttributes {
+                    enum_text: true,
+                    ..FieldAttributes::default()
+                },
+            ),
+            (
+                "struct S { #[db(default = \"'it''s'\")] a: String }",
+                FieldAttributes {
+                    default: Some("'it''s'".to_string()),
+                    ..FieldAttributes::default()
+                },
+            ),
+            (
+                "struct S { #[db(check = \"a BETWEEN 0 AND 100\")] a: i32 }",
+                FieldAttributes {
+                    check: Some("a BETWEEN 0 AND 100".to_string()),
+                    ..FieldAttributes::default()
+                },
+            ),
+            (
+                "struct S { #[db(append_within = \"course_id\")] a: i32 }",
+                FieldAttributes {
+                    append_within: Some(LitStr::new("course_id", proc_macro2::Span::call_site())),
+                    ..FieldAttributes::default()
+                },
+            ),
+        ];
+
+        for (struct_str, expected) in test_cases {
+            let input: DeriveInput = parse_str(struct_str).expect("Failed to parse struct");
+            let Data::Struct(DataStruct {
+                fields: Fields::Named(fields),
+                ..
+            }) = input.data
+            else {
+                panic!("Expected named fields");
+            };
+            let attributes = get_field_attributes(&fields.named[0].attrs).unwrap();
+            assert_eq!(attributes, expected, "Failed for {}", struct_str);
+        }
+    }
+
+    #[test]
+    fn test_get_struct_attributes() {
+        let literal = |value: &str| LitStr::new(value, proc_macro2::Span::call_site());
+        let test_cases = vec![
+            ("struct S { a: i32 }", StructAttributes::default()),
+            (
+                "#[db(soft_delete)] struct S { a: i32 }",
+                StructAttributes {
+                    soft_delete: true,
+                    ..StructAttributes::default()
+                },
+            ),
+            (
+                "#[db(soft_delete, timestamps)] struct S { a: i32 }",
+                StructAttributes {
+                    soft_delete: true,
+                    timestamps: true,
+                    ..StructAttributes::default()
+                },
+            ),
+            (
+                "#[db(table = \"course\", ident = \"CourseDB\")] struct S { a: i32 }",
+                StructAttributes {
+                    table: Some("course".to_string()),
+                    ident: Some("CourseDB".to_string()),
+                    ..StructAttributes::default()
+                },
+            ),
+            (
+                "#[db(unique_together(\"user_id\", \"name\"), unique_together(\"code\"))] struct S { a: i32 }",
+                StructAttributes {
+                    unique_together: vec![
+                        vec![literal("user_id"), literal("name")],
+                        vec![literal("code")],
+                    ],
+                    ..StructAttributes::default()
+                },
+            ),
+            (
+                "#[db(check = \"a < b\", check = \"a > 0\")] struct S { a: i32 }",
+                StructAttributes {
+                    checks: vec!["a < b".to_string(), "a > 0".to_string()],
+                    ..StructAttributes::default()
+                },
+            ),
+            (
+                "#[db(order_by(\"course_id\", \"position\"))] struct S { a: i32 }",
+                StructAttributes {
+                    order_by: vec![literal("course_id"), literal("position")],
+                    ..StructAttributes::default()
+                },
+            ),
+        ];
+
+        for (struct_str, expected) in test_cases {
+            let input: DeriveInput = parse_str(struct_str).expect("Failed to parse struct");
+            assert_eq!(
+                get_struct_attributes(&input.attrs).unwrap(),
+                expected,
+                "Failed for {}",
+                struct_str
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_sql_default() {
+        for value in ["0", "-1", "2.5", "'text'", "''", "'it''s'", "NULL", "FALSE"] {
+            assert!(is_sql_default(value), "{} should be accepted", value);
+        }
+        for value in [
+            "",
+            "1.2.3",
+            "abc",
+            "'open",
+            "'a' || 'b'",
+            "'it's'",
+            "0); DROP TABLE user",
+        ] {
+            assert!(!is_sql_default(value), "{} should be refused", value);
+        }
+    }
+
+    #[test]
+    fn test_is_sql_check() {
+        for expression in [
+            "a >= 0",
+            "a BETWEEN 0 AND 100",
+            "(a > 0) OR (b > 0)",
+            "name <> ')'",
+        ] {
+            assert!(
+                is_sql_check(expression),
+                "{} should be accepted",
+                expression
+            );
+        }
+        for expression in [
+            "",
+            "a > 0) OR (1",
+            "(a > 0",
+            "a > 0); DROP TABLE user",
+            "a > 0 -- comment",
+            "name <> 'open",
+        ] {
+            assert!(
+                !is_sql_check(expression),
+                "{} should be refused",
+                expression
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_struct_attributes_refuses_sql_in_table() {
+        let input: DeriveInput =
+            parse_str("#[db(table = \"course; DROP TABLE user\")] struct S { a: i32 }").unwrap();
+        let err = get_struct_attributes(&input.attrs).unwrap_err();
+        assert_eq!(err.to_string(), "table has to be a plain sql identifier");
+    }
 }
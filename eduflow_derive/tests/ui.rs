@@ -0,0 +1,9 @@
+/// compiles the structs in tests/ui and compares the errors with the .stderr files next to them
+/// update the expected errors with TRYBUILD=overwrite cargo test
+/// DBObject expands against the SQLGenerate trait of the main crate, its happy path is compiled there
+#[test]
+fn ui() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/pass/*.rs");
+    cases.compile_fail("tests/ui/fail/*.rs");
+}
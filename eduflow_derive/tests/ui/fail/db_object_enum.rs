@@ -0,0 +1,8 @@
+use eduflow_derive::DBObject;
+
+#[derive(DBObject)]
+enum CourseDB {
+    Analysis,
+}
+
+fn main() {}
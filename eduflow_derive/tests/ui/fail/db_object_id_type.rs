@@ -0,0 +1,9 @@
+use eduflow_derive::DBObject;
+
+#[derive(DBObject)]
+struct CourseDB {
+    id: Option<i32>,
+    user_id: i32,
+}
+
+fn main() {}
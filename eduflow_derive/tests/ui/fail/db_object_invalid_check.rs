@@ -0,0 +1,11 @@
+use eduflow_derive::DBObject;
+
+#[derive(DBObject)]
+struct TopicDB {
+    id: i32,
+    user_id: i32,
+    #[db(check = "progress >= 0); DROP TABLE user; --")]
+    progress: i32,
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use eduflow_derive::DBObject;
+
+#[derive(DBObject)]
+struct CourseDB {
+    id: i32,
+    user_id: i32,
+    #[db(default = "random()")]
+    position: i32,
+}
+
+fn main() {}
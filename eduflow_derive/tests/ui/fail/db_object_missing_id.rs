@@ -0,0 +1,9 @@
+use eduflow_derive::DBObject;
+
+#[derive(DBObject)]
+struct CourseDB {
+    user_id: i32,
+    id: i32,
+}
+
+fn main() {}
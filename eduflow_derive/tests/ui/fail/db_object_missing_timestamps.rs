@@ -0,0 +1,10 @@
+use eduflow_derive::DBObject;
+
+#[derive(DBObject)]
+#[db(timestamps)]
+struct CourseDB {
+    id: i32,
+    user_id: i32,
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use eduflow_derive::DBObject;
+
+#[derive(DBObject)]
+struct CourseDB {}
+
+fn main() {}
@@ -0,0 +1,14 @@
+use eduflow_derive::DBObject;
+
+struct CryptString;
+
+#[derive(DBObject)]
+#[db(order_by("course_id", "name"))]
+struct TopicDB {
+    id: i32,
+    user_id: i32,
+    course_id: i32,
+    name: CryptString,
+}
+
+fn main() {}
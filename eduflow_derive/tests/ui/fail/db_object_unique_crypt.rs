@@ -0,0 +1,13 @@
+use eduflow_derive::DBObject;
+
+struct CryptString;
+
+#[derive(DBObject)]
+#[db(unique_together("user_id", "name"))]
+struct CourseDB {
+    id: i32,
+    user_id: i32,
+    name: CryptString,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use eduflow_derive::DBObject;
+
+#[derive(DBObject)]
+struct CourseDB {
+    id: i32,
+    #[db(index)]
+    user_id: i32,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use eduflow_derive::DBObject;
+
+#[derive(DBObject)]
+struct CourseDB {
+    id: i32,
+    user_id: i32,
+    tags: Option<[u8; 4]>,
+}
+
+fn main() {}
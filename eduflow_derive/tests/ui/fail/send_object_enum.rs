@@ -0,0 +1,8 @@
+use eduflow_derive::SendObject;
+
+#[derive(SendObject)]
+enum CourseSend {
+    Analysis,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use eduflow_derive::SendObject;
+
+#[derive(SendObject)]
+struct CourseSend {
+    id: i32,
+    name: String,
+}
+
+fn main() {}
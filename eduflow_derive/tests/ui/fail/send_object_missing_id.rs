@@ -0,0 +1,9 @@
+use eduflow_derive::SendObject;
+
+#[derive(SendObject)]
+struct CourseSend {
+    name: String,
+    id: Option<i32>,
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use eduflow_derive::SendObject;
+
+#[derive(SendObject)]
+struct CourseSend(Option<i32>, String);
+
+fn main() {}
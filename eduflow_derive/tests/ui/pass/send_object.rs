@@ -0,0 +1,31 @@
+use eduflow_derive::SendObject;
+
+// stands in for the trait of the main crate
+mod data_handler {
+    pub trait Sendable {
+        fn get_id(&self) -> Option<i32>;
+        fn with_id(self, id: i32) -> Self;
+        fn strip_id(self) -> Self;
+    }
+}
+
+#[derive(SendObject)]
+struct CourseSend {
+    id: Option<i32>,
+    name: String,
+}
+
+fn main() {
+    use data_handler::Sendable;
+
+    let course = CourseSend {
+        id: Some(1),
+        name: "Analysis".to_string(),
+    };
+    assert_eq!(course.get_id(), Some(1));
+    let course = course.strip_id();
+    assert_eq!(course.get_id(), None);
+    let course = course.with_id(2);
+    assert_eq!(course.get_id(), Some(2));
+    assert_eq!(course.name, "Analysis");
+}
@@ -0,0 +1,383 @@
+use std::{
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use axum::{
+    Extension, Router,
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    routing::post,
+};
+use chrono::Utc;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    AppState,
+    api_error::{ApiError, Json},
+    db::DBInterface,
+};
+
+/// file names of backups are eduflow-<timestamp>.sqlite, only these files are pruned
+const BACKUP_PREFIX: &str = "eduflow-";
+const BACKUP_SUFFIX: &str = ".sqlite";
+
+/// where and how often the database is backed up, read from the env
+#[derive(Debug, Clone)]
+pub struct BackupConfig {
+    pub dir: PathBuf,
+    /// time between scheduled backups, None disables them (backups on demand still work)
+    pub interval: Option<Duration>,
+    /// number of backups kept, older ones are deleted after every backup
+    pub keep: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("data/backups"),
+            interval: Some(Duration::from_secs(24 * 60 * 60)),
+            keep: 7,
+        }
+    }
+}
+
+impl BackupConfig {
+    /// reads BACKUP_DIR, BACKUP_INTERVAL_SECS (0 disables scheduled backups) and BACKUP_KEEP
+    /// panics on invalid values, so misconfigurations are noticed at startup
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let number = |name: &str| {
+            env::var(name).ok().map(|value| {
+                value
+                    .parse::<u64>()
+                    .unwrap_or_else(|_| panic!("{} has to be a number", name))
+            })
+        };
+
+        let interval = match number("BACKUP_INTERVAL_SECS") {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => default.interval,
+        };
+        let keep = match number("BACKUP_KEEP") {
+            Some(0) => panic!("BACKUP_KEEP has to be a positive number"),
+            Some(keep) => keep as usize,
+            None => default.keep,
+        };
+
+        Self {
+            dir: env::var("BACKUP_DIR").map_or(default.dir, PathBuf::from),
+            interval,
+            keep,
+        }
+    }
+}
+
+/// settings of the admin routes
+#[derive(Debug, Clone, Default)]
+pub struct AdminConfig {
+    /// bearer token of the admin, the admin routes are disabled without one
+    pub token: Option<String>,
+    pub backup: BackupConfig,
+}
+
+impl AdminConfig {
+    /// reads the token from ADMIN_TOKEN, see BackupConfig::from_env for the backup settings
+    pub fn from_env() -> Self {
+        Self {
+            token: env::var("ADMIN_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+            backup: BackupConfig::from_env(),
+        }
+    }
+}
+
+/// response of an on demand backup
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BackupResponse {
+    /// file name inside of the backup directory
+    file: String,
+}
+
+/// routes for the operator of the instance, authenticated with ADMIN_TOKEN instead of a user
+pub fn admin_router<DB: DBInterface + Send + Sync + 'static>(
+    state: Arc<AppState<DB>>,
+    config: AdminConfig,
+) -> Router {
+    Router::new()
+        .route("/backup", post(handle_backup::<DB>))
+        .layer(Extension(Arc::new(config)))
+        .with_state(state)
+}
+
+/// checks the bearer token of the request against the admin token
+/// not found if no admin token is configured, so the routes do not exist then
+fn verify_admin(headers: &HeaderMap, config: &AdminConfig) -> Result<(), StatusCode> {
+    let Some(admin_token) = &config.token else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    // the hashes have a fixed length, comparing them does not leak the token through timing
+    if Sha256::digest(token) != Sha256::digest(admin_token) {
+        warn!("Admin request with a wrong token!");
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    Ok(())
+}
+
+/// handler for backups on demand
+async fn handle_backup<DB: DBInterface + Send + Sync>(
+    State(state): State<Arc<AppState<DB>>>,
+    Extension(config): Extension<Arc<AdminConfig>>,
+    headers: HeaderMap,
+) -> Result<Json<BackupResponse>, ApiError> {
+    verify_admin(&headers, &config)?;
+    info!("Backup requested by the admin.");
+
+    let path = create_backup(&state, &config.backup).await.map_err(|err| {
+        error!("Backup failed: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(BackupResponse {
+        file: path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    }))
+}
+
+/// background task, backs up the database every interval (the first backup after one interval)
+pub async fn backup_worker<DB: DBInterface + Send + Sync>(
+    state: Arc<AppState<DB>>,
+    config: BackupConfig,
+) {
+    let Some(period) = config.interval else {
+        info!("Scheduled backups are disabled.");
+        return;
+    };
+    let mut interval = tokio::time::interval(period);
+    // the first tick completes immediately, restarts should not replace the kept backups
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        if let Err(err) = create_backup(&state, &config).await {
+            error!("Scheduled backup failed: {}", err);
+        }
+    }
+}
+
+/// writes a new timestamped backup to the backup directory and prunes old ones, returns the path of the backup
+pub async fn create_backup<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    config: &BackupConfig,
+) -> Result<PathBuf, Box<dyn Error>> {
+    fs::create_dir_all(&config.dir)?;
+    let name = format!(
+        "{}{}{}",
+        BACKUP_PREFIX,
+        Utc::now().format("%Y%m%d-%H%M%S%.6f"),
+        BACKUP_SUFFIX
+    );
+    let path = config.dir.join(name);
+
+    // written under another name first, a failed backup never looks like a complete one
+    let partial = path.with_extension("partial");
+    state.db.backup_to(&partial).await?;
+    fs::rename(&partial, &path)?;
+    info!("Database backed up to {}.", path.display());
+
+    let pruned = prune_backups(&config.dir, config.keep)?;
+    if pruned > 0 {
+        info!("Deleted {} old backups.", pruned);
+    }
+
+    Ok(path)
+}
+
+/// deletes all but the newest keep backups in dir, returns the number of deleted backups
+fn prune_backups(dir: &Path, keep: usize) -> Result<usize, Box<dyn Error>> {
+    let mut backups: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| {
+                    name.starts_with(BACKUP_PREFIX) && name.ends_with(BACKUP_SUFFIX)
+                })
+        })
+        .collect();
+    // the timestamp in the name sorts like the time
+    backups.sort();
+
+    let old = backups.len().saturating_sub(keep);
+    for path in &backups[..old] {
+        fs::remove_file(path)?;
+    }
+
+    Ok(old)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        sync::Arc,
+    };
+
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use chrono::NaiveDate;
+    use rusqlite::Connection;
+    use tower::ServiceExt;
+
+    use crate::{
+        AppState, auth_handler,
+        crypt::crypt_provider::CryptProviders,
+        data_handler::{limits::PayloadLimits, objects::ToDoDB},
+        db::{DBInterface, sqlite::SqliteDatabase},
+        db_param_map,
+    };
+
+    use super::{AdminConfig, BackupConfig, admin_router, create_backup};
+
+    /// state with a file database, in memory databases do not support concurrent writers
+    fn file_state(dir: &Path) -> Arc<AppState<SqliteDatabase>> {
+        Arc::new(AppState {
+            db: Box::new(SqliteDatabase::new(dir.join("db.sqlite")).unwrap()),
+            crypt_provider: CryptProviders::SimpleCryptProv,
+            token_config: auth_handler::TokenConfig::default(),
+            lockout_config: auth_handler::LoginLockoutConfig::default(),
+            hash_config: auth_handler::HashConfig::default(),
+            payload_limits: PayloadLimits::default(),
+        })
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("eduflow_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn backups_are_consistent_and_pruned() {
+        let dir = temp_dir("backup_test");
+        let state = file_state(&dir);
+        state.db.create_table_for_type::<ToDoDB>().await.unwrap();
+        let config = BackupConfig {
+            dir: dir.join("backups"),
+            interval: None,
+            keep: 2,
+        };
+
+        // backups while another task keeps inserting
+        let writer = tokio::spawn({
+            let state = state.clone();
+            async move {
+                for _ in 0..200 {
+                    state
+                        .db
+                        .new_entry::<ToDoDB>(db_param_map! {
+                            user_id: 1,
+                            name: vec![0u8],
+                            deadline: NaiveDate::from_ymd_opt(2025, 7, 1).unwrap(),
+                            details: vec![0u8],
+                            completed: false,
+                        })
+                        .await
+                        .unwrap();
+                }
+            }
+        });
+        let mut backups: Vec<PathBuf> = Vec::new();
+        for _ in 0..3 {
+            backups.push(create_backup(&state, &config).await.unwrap());
+        }
+        writer.await.unwrap();
+
+        for backup in &backups[1..] {
+            let conn = Connection::open(backup).unwrap();
+            let check: String = conn
+                .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(check, "ok");
+            // the schema is part of the copy
+            conn.query_row("SELECT COUNT(*) FROM ToDoDB", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .unwrap();
+        }
+        // only the newest two are kept
+        assert!(!backups[0].exists());
+        assert_eq!(fs::read_dir(&config.dir).unwrap().count(), 2);
+
+        drop(state);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn backup_route_needs_the_admin_token() {
+        let dir = temp_dir("backup_route_test");
+        let state = file_state(&dir);
+        let backup = BackupConfig {
+            dir: dir.join("backups"),
+            interval: None,
+            keep: 7,
+        };
+        let request = |token: &str| {
+            Request::post("/backup")
+                .header("authorization", format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // disabled without a configured token
+        let router = admin_router(
+            state.clone(),
+            AdminConfig {
+                token: None,
+                backup: backup.clone(),
+            },
+        );
+        let response = router.oneshot(request("")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let router = admin_router(
+            state.clone(),
+            AdminConfig {
+                token: Some("admin_secret".to_string()),
+                backup: backup.clone(),
+            },
+        );
+        let response = router.clone().oneshot(request("wrong")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(!backup.dir.exists());
+
+        let response = router.oneshot(request("admin_secret")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(fs::read_dir(&backup.dir).unwrap().count(), 1);
+
+        drop(state);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
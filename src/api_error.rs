@@ -0,0 +1,387 @@
+use std::error::Error;
+
+use axum::{
+    extract::{
+        FromRequest, FromRequestParts, Request,
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+    },
+    http::{HeaderValue, StatusCode, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use log::{error, warn};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{auth_handler::AuthError, db::DbError};
+
+/// a field of a request that failed validation
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// body of every error response of the auth and data handlers
+#[derive(Serialize, Debug)]
+pub struct ApiError {
+    #[serde(skip)]
+    status: StatusCode,
+    /// snake case name of the status (e.g. not_found) or a more specific reason (e.g. token_expired)
+    code: String,
+    message: String,
+    /// the invalid fields, null if the error is not about single fields
+    field_errors: Option<Vec<FieldError>>,
+    /// sent as retry-after header
+    #[serde(skip)]
+    retry_after_secs: Option<i64>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code: code.to_string(),
+            message: message.into(),
+            field_errors: None,
+            retry_after_secs: None,
+        }
+    }
+
+    /// error about single fields of the request, e.g. failed validation
+    pub fn with_field_errors(status: StatusCode, field_errors: Vec<FieldError>) -> Self {
+        Self {
+            field_errors: Some(field_errors),
+            ..Self::new(status, &status_code(status), "invalid fields")
+        }
+    }
+
+    /// response to a request with invalid fields
+    pub fn unprocessable(field_errors: Vec<FieldError>) -> Self {
+        Self::with_field_errors(StatusCode::UNPROCESSABLE_ENTITY, field_errors)
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// tells the client to wait this many seconds before retrying
+    pub fn retry_after(mut self, secs: i64) -> Self {
+        self.retry_after_secs = Some(secs);
+        self
+    }
+}
+
+/// extension of the responses of errors about single fields, e.g. so the body limit does not replace them
+#[derive(Clone, Copy, Debug)]
+pub struct FieldErrorResponse;
+
+/// snake case name of the status, used as code if there is no more specific one
+fn status_code(status: StatusCode) -> String {
+    status
+        .canonical_reason()
+        .unwrap_or("error")
+        .to_lowercase()
+        .replace([' ', '-'], "_")
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut response = (self.status, axum::Json(&self)).into_response();
+        if self.field_errors.is_some() {
+            response.extensions_mut().insert(FieldErrorResponse);
+        }
+        if let Some(secs) = self.retry_after_secs {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
+    }
+}
+
+/// errors without a message get the lowercase reason of the status, e.g. "not found"
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        let message = status.canonical_reason().unwrap_or("error").to_lowercase();
+        Self::new(status, &status_code(status), message)
+    }
+}
+
+/// status with a message for the client, an empty message is replaced by the reason of the status
+impl From<(StatusCode, String)> for ApiError {
+    fn from((status, message): (StatusCode, String)) -> Self {
+        if message.is_empty() {
+            return status.into();
+        }
+        Self::new(status, &status_code(status), message)
+    }
+}
+
+/// status of a failed db call, used by every handler, so db errors are mapped in one place
+/// bad request if no statement could be generated from the request (e.g. nothing to update),
+/// unprocessable entity if a value is out of the range of a check constraint,
+/// service unavailable if the pool is exhausted and internal server error for anything unexpected
+impl From<DbError> for StatusCode {
+    fn from(err: DbError) -> Self {
+        match err {
+            DbError::NotFound => StatusCode::NOT_FOUND,
+            DbError::UniqueViolation(_) => StatusCode::CONFLICT,
+            DbError::CheckViolation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            DbError::Statement(_) => {
+                warn!("Invalid statement: {}", err);
+                StatusCode::BAD_REQUEST
+            }
+            DbError::Pool(_) => {
+                warn!("Database overloaded: {}", err);
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            DbError::ForeignKeyViolation(_)
+            | DbError::Serialization(_)
+            | DbError::SchemaMismatch(_)
+            | DbError::Other(_) => {
+                error!("Database error: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+impl From<DbError> for ApiError {
+    fn from(err: DbError) -> Self {
+        StatusCode::from(err).into()
+    }
+}
+
+impl From<AuthError> for StatusCode {
+    fn from(err: AuthError) -> Self {
+        match err {
+            AuthError::InvalidToken | AuthError::Expired => StatusCode::UNAUTHORIZED,
+            AuthError::NotFound | AuthError::WrongPassword => StatusCode::FORBIDDEN,
+            AuthError::Crypto(_) | AuthError::Db(_) => {
+                error!("Internal error during authentication: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            AuthError::Unavailable(_) => {
+                warn!("Overloaded during authentication: {}", err);
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+        }
+    }
+}
+
+/// token problems get their own code, internal errors only the status, they must not leak details
+impl From<AuthError> for ApiError {
+    fn from(err: AuthError) -> Self {
+        let code = match err {
+            AuthError::InvalidToken => "invalid_token",
+            AuthError::Expired => "token_expired",
+            AuthError::NotFound => "no_access",
+            AuthError::WrongPassword => "wrong_password",
+            AuthError::Crypto(_) | AuthError::Db(_) | AuthError::Unavailable(_) => {
+                return StatusCode::from(err).into();
+            }
+        };
+        let message = err.to_string().to_lowercase();
+        Self::new(StatusCode::from(err), code, message)
+    }
+}
+
+/// invalid json is unprocessable, the serde path of the invalid value is the field of the error
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        let invalid = match &rejection {
+            JsonRejection::JsonDataError(_) | JsonRejection::JsonSyntaxError(_) => {
+                find_source::<serde_path_to_error::Error<serde_json::Error>>(&rejection)
+            }
+            _ => None,
+        };
+        let Some(invalid) = invalid else {
+            // missing content type, body too large, ...
+            let status = rejection.status();
+            return Self::new(status, &status_code(status), rejection.body_text());
+        };
+
+        // the path of an error in the top level value is "."
+        let field = match invalid.path().to_string() {
+            path if path == "." => "body".to_string(),
+            path => path,
+        };
+        Self {
+            field_errors: Some(vec![FieldError {
+                field,
+                message: invalid.inner().to_string(),
+            }]),
+            ..Self::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_json",
+                "the body is not valid json for this request",
+            )
+        }
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        let status = rejection.status();
+        Self::new(status, &status_code(status), rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        let status = rejection.status();
+        Self::new(status, &status_code(status), rejection.body_text())
+    }
+}
+
+/// the error itself or the first of its sources of type T
+fn find_source<'a, T: Error + 'static>(err: &'a (dyn Error + 'static)) -> Option<&'a T> {
+    err.downcast_ref::<T>()
+        .or_else(|| find_source(err.source()?))
+}
+
+/// json extractor and response, like axum::Json but rejections are sent as ApiError
+pub struct Json<T>(pub T);
+
+impl<T: DeserializeOwned, S: Send + Sync> FromRequest<S> for Json<T> {
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::Json(value) = axum::Json::<T>::from_request(req, state).await?;
+        Ok(Self(value))
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+/// query extractor, like axum's Query but rejections are sent as ApiError
+pub struct Query<T>(pub T);
+
+impl<T: DeserializeOwned, S: Send + Sync> FromRequestParts<S> for Query<T> {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Query(value) =
+            axum::extract::Query::<T>::from_request_parts(parts, state).await?;
+        Ok(Self(value))
+    }
+}
+
+/// path extractor, like axum's Path but rejections are sent as ApiError
+pub struct Path<T>(pub T);
+
+impl<T: DeserializeOwned + Send, S: Send + Sync> FromRequestParts<S> for Path<T> {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Path(value) =
+            axum::extract::Path::<T>::from_request_parts(parts, state).await?;
+        Ok(Self(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::{Body, to_bytes},
+        http::{Method, Request, StatusCode, header},
+    };
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use crate::{
+        data_handler::data_router,
+        db::DBInterface,
+        test_util::{register_user, send_json, test_state},
+    };
+
+    #[tokio::test]
+    async fn errors_are_sent_as_json_bodies() {
+        let state = test_state().await;
+        let token = register_user(&state, "error_user").await;
+        let router = data_router(state.clone()).await;
+
+        // malformed json names the position, a wrong type the path of the value
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/course")
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{ "id": null, "name": "#))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: Value =
+            serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap())
+                .unwrap();
+        assert_eq!(body["code"], "invalid_json");
+        assert_eq!(body["field_errors"][0]["field"], "name");
+        let message = body["field_errors"][0]["message"].as_str().unwrap();
+        assert!(message.contains("EOF"), "{}", message);
+
+        let (status, body) = send_json(
+            router.clone(),
+            Method::POST,
+            "/topic",
+            &token,
+            Some(json!({ "id": null, "course_id": "one", "name": "Limits", "details": "" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["field_errors"][0]["field"], "course_id");
+        assert!(
+            body["field_errors"][0]["message"]
+                .as_str()
+                .unwrap()
+                .starts_with("invalid type")
+        );
+
+        let (status, body) =
+            send_json(router.clone(), Method::GET, "/course", "1_invalid", None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            body,
+            json!({ "code": "invalid_token", "message": "invalid token", "field_errors": null })
+        );
+
+        let (status, body) = send_json(
+            router,
+            Method::DELETE,
+            "/course",
+            &token,
+            Some(json!({ "id": 9999 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            body,
+            json!({ "code": "not_found", "message": "not found", "field_errors": null })
+        );
+    }
+
+    #[tokio::test]
+    async fn wrong_secrets_are_never_reported_as_expired() {
+        let state = test_state().await;
+        let token = register_user(&state, "expired_code_user").await;
+        let router = data_router(state.clone()).await;
+        let token_id: i32 = token.split_once('_').unwrap().0.parse().unwrap();
+        let yesterday = chrono::Utc::now().naive_utc() - chrono::Days::new(1);
+        state
+            .db
+            .update_remote_token_validity(token_id, &yesterday)
+            .await
+            .unwrap();
+
+        let guess = format!("{}_wrong", token_id);
+        let (status, body) = send_json(router.clone(), Method::GET, "/course", &guess, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], "invalid_token");
+
+        let (status, body) = send_json(router, Method::GET, "/course", &token, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], "token_expired");
+    }
+}
@@ -1,30 +1,295 @@
-use std::{error::Error, sync::Arc};
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
 
 use argon2::{
-    Argon2,
+    Algorithm, Argon2, Params, Version,
     password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, Salt, SaltString},
 };
 use axum::{
-    Json, Router,
-    extract::State,
-    http::{HeaderMap, HeaderValue, StatusCode},
-    routing::{get, post},
+    Extension, Router,
+    extract::{ConnectInfo, FromRequestParts, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header, request::Parts},
+    middleware::map_response,
+    response::{AppendHeaders, IntoResponse, Response},
+    routing::{delete, get, post},
 };
-use chrono::{Days, Utc};
+use chrono::{DateTime, Days, NaiveDateTime, TimeDelta, Utc};
 use log::{error, info, warn};
 use rand::{TryRngCore, rngs::OsRng};
 use serde::{Deserialize, Serialize};
-use token_gen::generate_token;
+use token_gen::{generate_token, generate_token_with_len};
 
 use crate::{
     AppState,
+    api_error::{ApiError, FieldError, Json, Path, Query},
     crypt::{Cryptable, crypt_types::CryptString},
-    db::{DBInterface, DBObjIdent},
+    data_handler::{delete_user_data, delete_user_data_of},
+    db::{DBInterface, DBObjIdent, DbError, LocalTokenPWCrypt, LocalTokenRTCrypt, RemoteToken},
 };
 
 mod token_gen;
 
-const TOKEN_EXPIRE: u64 = 14; // days after which a token expires
+const USERNAME_MIN_LENGTH: usize = 3;
+const USERNAME_MAX_LENGTH: usize = 32;
+const PASSWORD_MIN_LENGTH: usize = 8;
+const PASSWORD_MAX_BYTES: usize = 256;
+const EMAIL_MAX_BYTES: usize = 254;
+const AUTH_EVENTS_DEFAULT_LIMIT: u32 = 50;
+const AUTH_EVENTS_MAX_LIMIT: u32 = 500;
+/// name of the cookie holding the remote token if session cookies are enabled
+const SESSION_COOKIE_NAME: &str = "eduflow_token";
+/// minimal time between two extensions of a token with sliding expiration, limits the db writes
+const SLIDING_EXPIRATION_INTERVAL: TimeDelta = TimeDelta::hours(1);
+/// random bytes of the token handed to clients
+const REMOTE_TOKEN_BYTES: usize = 32;
+/// random bytes of the keys encrypting the users data
+const LOCAL_TOKEN_BYTES: usize = 32;
+
+/// lifetimes and transport of remote tokens, read from the env
+#[derive(Debug, Clone)]
+pub struct TokenConfig {
+    /// lifetime of a normal login (and of refreshed tokens)
+    pub valid_days: u64,
+    /// lifetime of a login with remember_me
+    pub remember_me_days: u64,
+    /// how often expired tokens are deleted
+    pub cleanup_interval: Duration,
+    /// extends tokens on use, so active users are not logged out
+    pub sliding_expiration: bool,
+    /// additionally hands out tokens as HttpOnly cookie and accepts them without auth header, off if none
+    pub session_cookie: Option<SessionCookieConfig>,
+}
+
+/// attributes of the session cookie
+#[derive(Debug, Clone)]
+pub struct SessionCookieConfig {
+    /// only send the cookie over https
+    pub secure: bool,
+    /// SameSite attribute, one of Strict, Lax or None
+    pub same_site: String,
+}
+
+impl SessionCookieConfig {
+    /// set-cookie value for a new token, the cookie expires together with the token
+    fn set_cookie(&self, issued: &IssuedToken) -> String {
+        let max_age = (issued.valid_until - Utc::now().naive_utc())
+            .num_seconds()
+            .max(0);
+        self.cookie(&issued.token, max_age)
+    }
+
+    /// set-cookie value that deletes the cookie
+    fn clear_cookie(&self) -> String {
+        self.cookie("", 0)
+    }
+
+    fn cookie(&self, value: &str, max_age: i64) -> String {
+        let mut cookie = format!(
+            "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite={}",
+            SESSION_COOKIE_NAME, value, max_age, self.same_site
+        );
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        cookie
+    }
+}
+
+impl Default for TokenConfig {
+    fn default() -> Self {
+        Self {
+            valid_days: 14,
+            remember_me_days: 30,
+            cleanup_interval: Duration::from_secs(60 * 60),
+            sliding_expiration: false,
+            session_cookie: None,
+        }
+    }
+}
+
+impl TokenConfig {
+    /// reads the config from TOKEN_EXPIRE_DAYS, TOKEN_REMEMBER_ME_DAYS, TOKEN_CLEANUP_INTERVAL_SECS, TOKEN_SLIDING_EXPIRATION
+    /// and SESSION_COOKIE (with SESSION_COOKIE_SECURE and SESSION_COOKIE_SAMESITE)
+    /// panics on invalid values, so misconfigurations are noticed at startup
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let var = positive_env_var;
+
+        let valid_days = var("TOKEN_EXPIRE_DAYS", default.valid_days);
+        // without an explicit value remember me never yields a shorter lifetime than a normal login
+        let remember_me_days = var(
+            "TOKEN_REMEMBER_ME_DAYS",
+            default.remember_me_days.max(valid_days),
+        );
+
+        let cleanup_interval = Duration::from_secs(var(
+            "TOKEN_CLEANUP_INTERVAL_SECS",
+            default.cleanup_interval.as_secs(),
+        ));
+
+        let sliding_expiration =
+            bool_env_var("TOKEN_SLIDING_EXPIRATION", default.sliding_expiration);
+
+        let session_cookie = bool_env_var("SESSION_COOKIE", false).then(|| {
+            let secure = bool_env_var("SESSION_COOKIE_SECURE", true);
+            let same_site = env::var("SESSION_COOKIE_SAMESITE").unwrap_or("Lax".to_string());
+            match same_site.as_str() {
+                "Strict" | "Lax" => (),
+                // browsers reject SameSite=None cookies without Secure
+                "None" if secure => (),
+                "None" => panic!("SESSION_COOKIE_SAMESITE=None requires SESSION_COOKIE_SECURE"),
+                _ => panic!("SESSION_COOKIE_SAMESITE has to be Strict, Lax or None"),
+            }
+            SessionCookieConfig { secure, same_site }
+        });
+
+        Self {
+            valid_days,
+            remember_me_days,
+            cleanup_interval,
+            sliding_expiration,
+            session_cookie,
+        }
+    }
+
+    /// lifetime of a new login
+    fn login_days(&self, remember_me: bool) -> u64 {
+        if remember_me {
+            self.remember_me_days
+        } else {
+            self.valid_days
+        }
+    }
+}
+
+/// per account protection against password guessing, read from the env
+#[derive(Debug, Clone)]
+pub struct LoginLockoutConfig {
+    /// consecutive wrong passwords after which the account is locked
+    pub threshold: u32,
+    /// how long logins of a locked account are refused
+    pub duration: Duration,
+}
+
+impl Default for LoginLockoutConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 10,
+            duration: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+impl LoginLockoutConfig {
+    /// reads the config from LOGIN_LOCKOUT_THRESHOLD and LOGIN_LOCKOUT_SECS
+    /// panics on invalid values, so misconfigurations are noticed at startup
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let threshold = positive_env_var("LOGIN_LOCKOUT_THRESHOLD", default.threshold.into());
+
+        Self {
+            threshold: threshold
+                .try_into()
+                .expect("LOGIN_LOCKOUT_THRESHOLD is too large"),
+            duration: Duration::from_secs(positive_env_var(
+                "LOGIN_LOCKOUT_SECS",
+                default.duration.as_secs(),
+            )),
+        }
+    }
+}
+
+/// argon2 parameters of new password (and token) hashes, read from the env
+/// existing hashes keep the parameters they were made with, password hashes are upgraded on the next login
+#[derive(Debug, Clone)]
+pub struct HashConfig {
+    /// memory cost in KiB
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for HashConfig {
+    fn default() -> Self {
+        Self {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl HashConfig {
+    /// reads the config from ARGON2_MEMORY_KIB, ARGON2_ITERATIONS and ARGON2_PARALLELISM
+    /// panics on invalid values, so misconfigurations are noticed at startup
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let var = |name: &str, default: u32| -> u32 {
+            positive_env_var(name, default.into())
+                .try_into()
+                .unwrap_or_else(|_| panic!("{} is too large", name))
+        };
+
+        let config = Self {
+            memory_kib: var("ARGON2_MEMORY_KIB", default.memory_kib),
+            iterations: var("ARGON2_ITERATIONS", default.iterations),
+            parallelism: var("ARGON2_PARALLELISM", default.parallelism),
+        };
+        if let Err(err) = Params::new(
+            config.memory_kib,
+            config.iterations,
+            config.parallelism,
+            None,
+        ) {
+            panic!("Invalid argon2 parameters: {}", err);
+        }
+        config
+    }
+
+    /// hasher with the configured parameters
+    fn argon2(&self) -> Argon2<'static> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+            .expect("Argon2 parameters are validated on startup");
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+    }
+
+    /// checks if a stored hash was made with weaker parameters than the configured ones
+    fn is_weaker(&self, hash: &str) -> bool {
+        let Ok(params) = PasswordHash::new(hash).and_then(|hash| Params::try_from(&hash)) else {
+            return false;
+        };
+        params.m_cost() < self.memory_kib
+            || params.t_cost() < self.iterations
+            || params.p_cost() < self.parallelism
+    }
+}
+
+/// reads a positive number from the env, panics on invalid values
+fn positive_env_var(name: &str, default: u64) -> u64 {
+    env::var(name)
+        .map(|value| match value.parse() {
+            Ok(number) if number > 0 => number,
+            _ => panic!("{} has to be a positive number", name),
+        })
+        .unwrap_or(default)
+}
+
+/// reads a boolean (true/false or 1/0) from the env, panics on invalid values
+fn bool_env_var(name: &str, default: bool) -> bool {
+    env::var(name)
+        .map(|value| match value.as_str() {
+            "true" | "1" => true,
+            "false" | "0" => false,
+            _ => panic!("{} has to be true or false", name),
+        })
+        .unwrap_or(default)
+}
 
 /// This function defines the authentication routes for the application.
 pub fn auth_router<DB: DBInterface + Send + Sync + 'static>(state: Arc<AppState<DB>>) -> Router {
@@ -32,7 +297,17 @@ pub fn auth_router<DB: DBInterface + Send + Sync + 'static>(state: Arc<AppState<
         .route("/register", post(handle_register))
         .route("/login", post(handle_login))
         .route("/logout", post(handle_logout)) // logout basically invalidates a existing token
+        .route("/logout-all", post(handle_logout_all)) // invalidates every token of the user
+        .route("/refresh", post(handle_refresh)) // replaces a valid token with a new one
+        .route("/change-password", post(handle_change_password))
+        .route("/reset-password", post(handle_reset_password)) // sets a new password with the recovery key
+        .route("/sessions", get(handle_get_sessions)) // lists the active tokens of the user
+        .route("/sessions/{id}", delete(handle_delete_session))
+        .route("/account", delete(handle_delete_account)) // deletes the user and all of its data
         .route("/verify-token", get(handle_verify)) // verifies that a given token is valid
+        .route("/me", get(handle_me)) // account info of the user
+        .route("/events", get(handle_get_events)) // newest auth events (logins, logouts, ...) of the user
+        .layer(map_response(retry_after_unavailable))
         .with_state(state)
 }
 
@@ -41,342 +316,3291 @@ pub fn auth_router<DB: DBInterface + Send + Sync + 'static>(state: Arc<AppState<
 struct LoginRequest {
     username: String,
     password: String,
+    /// selects the long token lifetime
+    #[serde(default)]
+    remember_me: bool,
+    /// limits the token to these object types (db identifiers), full access if missing
+    scopes: Option<Vec<String>>,
+    /// optional contact address, only used on registration
+    email: Option<String>,
 }
 
-/// struct used for logout body
+/// struct used for the change password body
 #[derive(Deserialize, Serialize, Debug)]
-struct LogoutRequest {
-    token: String,
+struct ChangePasswordRequest {
+    old_password: String,
+    new_password: String,
+}
+
+/// struct used for the reset password body
+#[derive(Deserialize, Serialize, Debug)]
+struct ResetPasswordRequest {
+    username: String,
+    recovery_key: String,
+    new_password: String,
+}
+
+/// response of a password reset
+#[derive(Deserialize, Serialize, Debug)]
+struct ResetPasswordResponse {
+    /// object types (db identifiers) whose local token had no recovery copy, their data was deleted
+    unrecoverable: Vec<String>,
+}
+
+/// body of destructive actions (logout-all, account deletion), the password is asked again so a stolen token is not enough
+#[derive(Deserialize, Serialize, Debug)]
+struct ConfirmPasswordRequest {
+    password: String,
+}
+
+/// query parameters of the logout-all request
+#[derive(Deserialize, Debug)]
+struct LogoutAllQuery {
+    /// keeps the session of the token in the auth header
+    #[serde(default)]
+    keep_current: bool,
+}
+
+/// query parameters of the auth events request
+#[derive(Deserialize, Debug)]
+struct AuthEventsQuery {
+    /// number of returned events, defaults to AUTH_EVENTS_DEFAULT_LIMIT
+    limit: Option<u32>,
+}
+
+/// struct used for the auth events response
+#[derive(Deserialize, Serialize, Debug)]
+struct AuthEventResponse {
+    event: String,
+    ip: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+/// kinds of events written to the auth audit log
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AuthEventKind {
+    Login,
+    LoginFailed,
+    Register,
+    Logout,
+    TokenExpired,
+    Locked,
+}
+
+impl AuthEventKind {
+    /// name stored in the db and returned to the user
+    fn as_str(self) -> &'static str {
+        match self {
+            AuthEventKind::Login => "login",
+            AuthEventKind::LoginFailed => "login_failed",
+            AuthEventKind::Register => "register",
+            AuthEventKind::Logout => "logout",
+            AuthEventKind::TokenExpired => "token_expired",
+            AuthEventKind::Locked => "locked",
+        }
+    }
+}
+
+/// struct used for the session list response, never contains the token hash
+#[derive(Deserialize, Serialize, Debug)]
+struct SessionResponse {
+    id: i32,
+    valid_until: NaiveDateTime,
+    /// true for the session of the token in the auth header
+    current: bool,
 }
 
 /// struct used for login / register response
 #[derive(Deserialize, Serialize, Debug)]
 struct LoginResponse {
     token: String,
+    user_id: i32,
+    valid_until: DateTime<Utc>,
+    /// only returned once on registration, needed for resetting a forgotten password
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recovery_key: Option<String>,
+}
+
+impl From<IssuedToken> for LoginResponse {
+    fn from(issued: IssuedToken) -> Self {
+        Self {
+            token: issued.token,
+            user_id: issued.user_id,
+            valid_until: issued.valid_until.and_utc(),
+            recovery_key: None,
+        }
+    }
+}
+
+/// struct used for the verify token response
+#[derive(Deserialize, Serialize, Debug)]
+struct VerifyResponse {
+    user_id: i32,
+    valid_until: DateTime<Utc>,
+    /// seconds until the token expires
+    remaining_secs: i64,
+}
+
+/// struct used for the account info response, never contains the password hash
+#[derive(Deserialize, Serialize, Debug)]
+struct MeResponse {
+    id: i32,
+    username: String,
+    email: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+/// set-cookie header of a response, empty if session cookies are disabled
+type SessionCookie = AppendHeaders<Option<(HeaderName, String)>>;
+
+/// set-cookie header with a new token
+fn set_session_cookie(config: &TokenConfig, issued: &IssuedToken) -> SessionCookie {
+    AppendHeaders(
+        config
+            .session_cookie
+            .as_ref()
+            .map(|cookie| (header::SET_COOKIE, cookie.set_cookie(issued))),
+    )
+}
+
+/// a newly created remote token
+#[derive(Debug)]
+struct IssuedToken {
+    /// the token prefixed with its id, as used in the auth header
+    token: String,
+    user_id: i32,
+    valid_until: NaiveDateTime,
 }
 
 /// handler for logout requests
 async fn handle_logout<DB: DBInterface + Send + Sync>(
-    headers: HeaderMap,
+    user: AuthenticatedUser,
     State(state): State<Arc<AppState<DB>>>,
-) -> Result<(), StatusCode> {
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+) -> Result<SessionCookie, ApiError> {
     info!("Logout request received.");
 
-    let auth_header = headers.get("authorization");
-
-    // confirm that the given token is valid, otherwise we do not need to invalidate it, or someone would just be able to invalidate any token with its id
-    let (_, token_id, _) =
-        verify_token(auth_header, state.clone()).map_err(|_| StatusCode::UNAUTHORIZED)?;
-
-    invalidate_remote_token(token_id, state).map_err(|_| {
+    // the extractor confirmed that the given token is valid, otherwise someone would just be able to invalidate any token with its id
+    let AuthenticatedUser {
+        user_id,
+        remote_token_id: token_id,
+        ..
+    } = user;
+
+    record_auth_event(
+        &state,
+        Some(user_id),
+        AuthEventKind::Logout,
+        client_ip(connect_info),
+    )
+    .await;
+
+    invalidate_remote_token(token_id, state.clone()).await.map_err(|_| {
         // well here something has really gone wrong, we could validate the token but are now unable to delete it.
         error!("Failed to invalidate token! token has been verified beforehand, meaning token is still valid!");
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
+    // the cookie is cleared as well, the browser would send the invalid token otherwise
+    Ok(AppendHeaders(
+        state
+            .token_config
+            .session_cookie
+            .as_ref()
+            .map(|cookie| (header::SET_COOKIE, cookie.clear_cookie())),
+    ))
+}
+
+/// handler for logging out every session of a user, e.g. after a token was leaked
+async fn handle_logout_all<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    Query(query): Query<LogoutAllQuery>,
+    State(state): State<Arc<AppState<DB>>>,
+    Json(request): Json<ConfirmPasswordRequest>,
+) -> Result<(), ApiError> {
+    info!("Logout of all sessions requested.");
+
+    let AuthenticatedUser {
+        user_id,
+        remote_token_id: token_id,
+        ..
+    } = user;
+
+    confirm_password(
+        user_id,
+        &request.password,
+        state.clone(),
+        "Logout of all sessions",
+    )
+    .await?;
+
+    let keep = query.keep_current.then_some(token_id);
+    state
+        .db
+        .del_remote_tokens_by_user(user_id, keep)
+        .await
+        .map_err(|_| {
+            error!("Failed to invalidate sessions! (user id: {})", user_id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    info!("Logged out all sessions. (user id: {})", user_id);
     Ok(())
 }
 
+/// handler for refresh requests, replaces a still valid token with a new one, the old token is invalidated
+async fn handle_refresh<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+) -> Result<(SessionCookie, Json<LoginResponse>), ApiError> {
+    info!("Token refresh requested.");
+
+    // expired tokens are rejected (and deleted) by the extractor, nothing gets rotated
+    let AuthenticatedUser {
+        user_id,
+        remote_token_id: token_id,
+        remote_token: token,
+    } = user;
+
+    // the local tokens are decrypted with the old remote token, no password needed
+    // the new token gets the same scope as the old one
+    let scope = state
+        .db
+        .get_local_token_ids_by_rt(token_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // loaded before, the decryption below can not wait for the db
+    let mut scoped_tokens = HashMap::new();
+    for local_token_id in scope {
+        let local_token = state
+            .db
+            .get_local_token_by_id_rtcrypt(local_token_id, token_id)
+            .await
+            .ok()
+            .flatten()
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+        scoped_tokens.insert(local_token_id, local_token.local_token_crypt);
+    }
+
+    let remote_token = create_remote_token_with(
+        user_id,
+        state.clone(),
+        state.token_config.valid_days,
+        |lt| {
+            let Some(local_token_crypt) = scoped_tokens.get(&lt.id) else {
+                return Ok(None);
+            };
+            local_token_crypt
+                .decrypt(token.as_bytes(), &state.crypt_provider)
+                .map(Some)
+                .map_err(|err| AuthError::Crypto(err.to_string()))
+        },
+    )
+    .await
+    .map_err(|_| {
+        error!("Generating refreshed remote token failed!");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    invalidate_remote_token(token_id, state.clone())
+        .await
+        .map_err(|_| {
+            // the new token works, but the old one stays valid until it expires
+            error!("Failed to invalidate refreshed token!");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    info!("Token refresh successful, returning new remote token to Client!");
+    Ok((
+        set_session_cookie(&state.token_config, &remote_token),
+        Json(remote_token.into()),
+    ))
+}
+
+/// handler for password changes, re-encrypts the local tokens with the new password
+/// every other session of the user is logged out
+async fn handle_change_password<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Json(request): Json<ChangePasswordRequest>,
+) -> Result<(), ApiError> {
+    info!("Password change requested.");
+
+    // the old password is not validated, the rules might have changed since it was set
+    if let Err(mut errors) = validate_credentials(None, &request.new_password) {
+        errors
+            .iter_mut()
+            .for_each(|error| error.field = "new_password".to_string());
+        return Err(ApiError::with_field_errors(StatusCode::BAD_REQUEST, errors));
+    }
+
+    let AuthenticatedUser {
+        user_id,
+        remote_token_id: token_id,
+        ..
+    } = user;
+
+    confirm_password(
+        user_id,
+        &request.old_password,
+        state.clone(),
+        "Password change",
+    )
+    .await?;
+
+    // decrypt everything before writing anything, a local token that is lost makes its data unreadable
+    let local_tokens = state
+        .db
+        .get_local_tokens_by_user_pwcrypt(user_id)
+        .await
+        .map_err(|_| {
+            error!("Failed to load local tokens! (user id: {})", user_id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .iter()
+        .map(|lt| {
+            let local_token = lt
+                .token_crypt
+                .decrypt(request.old_password.as_bytes(), &state.crypt_provider)?;
+            let token_crypt = CryptString::encrypt(
+                &local_token,
+                request.new_password.as_bytes(),
+                &state.crypt_provider,
+            );
+            Ok((lt.id, token_crypt))
+        })
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()
+        .map_err(|_| {
+            error!(
+                "Failed to decrypt local token with the password, nothing changed! (user id: {})",
+                user_id
+            );
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let password_hash = hash_password(&request.new_password, &state.hash_config)
+        .await
+        .map_err(|_| {
+            error!("Failed to hash password!");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    state
+        .db
+        .update_user_password_hash(user_id, &password_hash, &local_tokens)
+        .await
+        .map_err(|_| {
+            error!("Failed to store new password! (user id: {})", user_id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    // other sessions keep their own copies of the local tokens, they are logged out
+    state
+        .db
+        .del_remote_tokens_by_user(user_id, Some(token_id))
+        .await
+        .map_err(|_| {
+            error!(
+                "Failed to invalidate other sessions! (user id: {})",
+                user_id
+            );
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    info!("Password change successful. (user id: {})", user_id);
+    Ok(())
+}
+
+/// handler for resetting a forgotten password with the recovery key returned on registration
+/// every session of the user is logged out
+/// local tokens without a recovery copy (object types added after the registration) are lost with the password,
+/// they are replaced by new ones and the data of their types is deleted, the response lists these types
+async fn handle_reset_password<DB: DBInterface + Send + Sync>(
+    State(state): State<Arc<AppState<DB>>>,
+    Json(request): Json<ResetPasswordRequest>,
+) -> Result<Json<ResetPasswordResponse>, ApiError> {
+    info!("Password reset requested for user {}", request.username);
+
+    if let Err(mut errors) = validate_credentials(None, &request.new_password) {
+        errors
+            .iter_mut()
+            .for_each(|error| error.field = "new_password".to_string());
+        return Err(ApiError::with_field_errors(StatusCode::BAD_REQUEST, errors));
+    }
+
+    // unknown users, users without recovery copies and wrong keys look the same
+    let unauthorized = || {
+        warn!(
+            "Password reset with wrong recovery key for user {}!",
+            request.username
+        );
+        ApiError::from(StatusCode::UNAUTHORIZED)
+    };
+
+    let user_id = state
+        .db
+        .get_user_by_username(request.username.trim())
+        .await
+        .map_err(|_| unauthorized())?
+        .id;
+
+    // decrypt everything before writing anything, like on password changes
+    let local_tokens = state
+        .db
+        .get_local_tokens_by_user_pwcrypt(user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let recovery_tokens = state
+        .db
+        .get_local_tokens_by_user_reccrypt(user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut rekeyed = recovery_tokens
+        .iter()
+        .filter(|rt| local_tokens.iter().any(|lt| lt.id == rt.local_token_id))
+        .map(|rt| {
+            let local_token = rt
+                .local_token_crypt
+                .decrypt(request.recovery_key.as_bytes(), &state.crypt_provider)?;
+            let token_crypt = CryptString::encrypt(
+                &local_token,
+                request.new_password.as_bytes(),
+                &state.crypt_provider,
+            );
+            Ok((rt.local_token_id, token_crypt))
+        })
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()
+        .map_err(|_| unauthorized())?;
+    // users registered before recovery keys existed can not reset their password, without a copy the key can not be checked
+    if rekeyed.is_empty() {
+        return Err(unauthorized());
+    }
+
+    // the lost local tokens get new ones, with a recovery copy so the next reset recovers everything
+    let mut recovery_copies = Vec::new();
+    let mut lost = Vec::new();
+    for lt in local_tokens
+        .iter()
+        .filter(|lt| !recovery_tokens.iter().any(|rt| rt.local_token_id == lt.id))
+    {
+        let local_token = generate_token_with_len(LOCAL_TOKEN_BYTES);
+        rekeyed.push((
+            lt.id,
+            CryptString::encrypt(
+                &local_token,
+                request.new_password.as_bytes(),
+                &state.crypt_provider,
+            ),
+        ));
+        recovery_copies.push((
+            lt.id,
+            CryptString::encrypt(
+                &local_token,
+                request.recovery_key.as_bytes(),
+                &state.crypt_provider,
+            ),
+        ));
+        lost.push(lt.used_for.clone());
+    }
+
+    let password_hash = hash_password(&request.new_password, &state.hash_config)
+        .await
+        .map_err(|_| {
+            error!("Failed to hash password!");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let written_lost = lost.clone();
+    state
+        .db
+        .with_transaction(move |db| {
+            Box::pin(async move {
+                db.update_user_password_hash(user_id, &password_hash, &rekeyed)
+                    .await?;
+                for used_for in &written_lost {
+                    delete_user_data_of(db, user_id, used_for).await?;
+                }
+                for (local_token_id, token_crypt) in &recovery_copies {
+                    db.new_local_token_reccrypt(*local_token_id, token_crypt)
+                        .await?;
+                }
+                db.del_remote_tokens_by_user(user_id, None).await
+            })
+        })
+        .await
+        .map_err(|_| {
+            error!("Failed to store new password! (user id: {})", user_id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    // the owner proved itself with the recovery key, a lockout would only keep it out
+    if state.db.reset_failed_logins(user_id).await.is_err() {
+        error!("Failed to reset the failed logins of user {}!", user_id);
+    }
+
+    if !lost.is_empty() {
+        warn!(
+            "Password reset deleted the unrecoverable data of {:?}! (user id: {})",
+            lost, user_id
+        );
+    }
+    info!("Password reset successful. (user id: {})", user_id);
+    Ok(Json(ResetPasswordResponse {
+        unrecoverable: lost.into_iter().map(|ident| ident.db_identifier).collect(),
+    }))
+}
+
+/// handler for listing the active sessions of a user, expired tokens are cleaned up on the way
+async fn handle_get_sessions<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+) -> Result<Json<Vec<SessionResponse>>, ApiError> {
+    let AuthenticatedUser {
+        user_id,
+        remote_token_id: token_id,
+        ..
+    } = user;
+
+    let remote_tokens = state
+        .db
+        .get_remote_tokens_by_user(user_id)
+        .await
+        .map_err(|_| {
+            error!("Failed to load sessions! (user id: {})", user_id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let now = Utc::now().naive_utc();
+    let mut sessions = vec![];
+    for remote_token in remote_tokens {
+        if remote_token.valid_until <= now {
+            // same as in verify_token, a failed cleanup is retried on the next request
+            if invalidate_remote_token(remote_token.id, state.clone())
+                .await
+                .is_err()
+            {
+                warn!("Failed to delete expired token! (user id: {})", user_id);
+            }
+            continue;
+        }
+        sessions.push(SessionResponse {
+            id: remote_token.id,
+            valid_until: remote_token.valid_until,
+            current: remote_token.id == token_id,
+        });
+    }
+
+    Ok(Json(sessions))
+}
+
+/// handler for revoking a single session of the user
+async fn handle_delete_session<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    Path(session_id): Path<i32>,
+    State(state): State<Arc<AppState<DB>>>,
+) -> Result<(), ApiError> {
+    let AuthenticatedUser { user_id, .. } = user;
+
+    // sessions of other users are reported as missing, so their ids can not be probed
+    match state.db.get_remote_token(session_id).await {
+        Ok(Some(remote_token)) if remote_token.user_id == user_id => (),
+        _ => return Err(StatusCode::NOT_FOUND.into()),
+    }
+
+    invalidate_remote_token(session_id, state)
+        .await
+        .map_err(|_| {
+            error!("Failed to revoke session! (user id: {})", user_id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    info!("Session revoked. (user id: {})", user_id);
+    Ok(())
+}
+
+/// handler for account deletion, removes the data objects, all tokens and the user itself
+async fn handle_delete_account<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Json(request): Json<ConfirmPasswordRequest>,
+) -> Result<(), ApiError> {
+    info!("Account deletion requested.");
+
+    let AuthenticatedUser { user_id, .. } = user;
+
+    confirm_password(
+        user_id,
+        &request.password,
+        state.clone(),
+        "Account deletion",
+    )
+    .await?;
+
+    // everything or nothing, a failed deletion can be retried with the same credentials
+    state
+        .db
+        .with_transaction(move |db| {
+            Box::pin(async move {
+                delete_user_data(db, user_id).await?;
+                db.del_local_tokens_by_user(user_id).await?;
+                db.del_remote_tokens_by_user(user_id, None).await?;
+                db.del_user(user_id).await
+            })
+        })
+        .await
+        .map_err(|_| {
+            error!("Failed to delete account! (user id: {})", user_id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    info!("Account deleted. (user id: {})", user_id);
+    Ok(())
+}
+
+/// removes a partially registered user with all tokens created so far, so the username can be registered again
+async fn remove_failed_registration<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+) {
+    let result = async {
+        state.db.del_local_tokens_by_user(user_id).await?;
+        state.db.del_remote_tokens_by_user(user_id, None).await?;
+        state.db.del_user(user_id).await
+    };
+    if result.await.is_err() {
+        error!(
+            "Failed to remove partially registered user! (user id: {})",
+            user_id
+        );
+    }
+}
+
 /// handler for verifying the validity of tokens
 async fn handle_verify<DB: DBInterface + Send + Sync>(
-    headers: HeaderMap,
+    user: AuthenticatedUser,
     State(state): State<Arc<AppState<DB>>>,
-) -> Result<(), StatusCode> {
+) -> Result<Json<VerifyResponse>, ApiError> {
     info!("Token verification requested!");
 
-    let auth_header = headers.get("authorization");
+    let AuthenticatedUser {
+        user_id,
+        remote_token_id: token_id,
+        ..
+    } = user;
+
+    // the token might have been revoked in the meantime
+    let valid_until = state
+        .db
+        .get_remote_token(token_id)
+        .await?
+        .ok_or(AuthError::InvalidToken)?
+        .valid_until;
+
+    Ok(Json(VerifyResponse {
+        user_id,
+        valid_until: valid_until.and_utc(),
+        remaining_secs: (valid_until - Utc::now().naive_utc()).num_seconds(),
+    }))
+}
 
-    // confirm that the given token is valid.
-    verify_token(auth_header, state.clone()).map_err(|_| StatusCode::UNAUTHORIZED)?;
+/// handler for listing the newest auth events of the user, e.g. to spot logins from unknown ips
+async fn handle_get_events<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    Query(query): Query<AuthEventsQuery>,
+    State(state): State<Arc<AppState<DB>>>,
+) -> Result<Json<Vec<AuthEventResponse>>, ApiError> {
+    let limit = query
+        .limit
+        .unwrap_or(AUTH_EVENTS_DEFAULT_LIMIT)
+        .min(AUTH_EVENTS_MAX_LIMIT);
 
-    Ok(())
+    let events = state
+        .db
+        .get_auth_events_by_user(user.user_id, limit)
+        .await
+        .map_err(|_| {
+            error!("Failed to load auth events of user {}!", user.user_id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(
+        events
+            .into_iter()
+            .map(|event| AuthEventResponse {
+                event: event.event,
+                ip: event.ip,
+                created_at: event.created_at.and_utc(),
+            })
+            .collect(),
+    ))
+}
+
+/// handler for account info requests
+async fn handle_me<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+) -> Result<Json<MeResponse>, ApiError> {
+    let account = state.db.get_user_by_id(user.user_id).await.map_err(|_| {
+        error!("Failed to load user {} of a valid token!", user.user_id);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(MeResponse {
+        id: account.id,
+        username: account.username,
+        email: account.email,
+        created_at: account.created_at.and_utc(),
+    }))
 }
 
 /// handler for registration requests
 async fn handle_register<DB: DBInterface + Send + Sync>(
     State(state): State<Arc<AppState<DB>>>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
     Json(request): Json<LoginRequest>,
-) -> Result<Json<LoginResponse>, StatusCode> {
+) -> Result<(SessionCookie, Json<LoginResponse>), ApiError> {
     info!("Register request for new user {}", request.username);
-    // generate salt
-    let mut salt_bytes = [0u8; Salt::RECOMMENDED_LENGTH];
-    let result = OsRng.try_fill_bytes(&mut salt_bytes);
-    let salt = SaltString::encode_b64(&salt_bytes);
 
-    // salt generation error
-    if result.is_err() || salt.is_err() {
-        error!("Failed to generate salt!");
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    let username = request.username.trim();
+    // emails are compared case insensitive, an empty email counts as none
+    let email = request
+        .email
+        .as_deref()
+        .map(|email| email.trim().to_lowercase())
+        .filter(|email| !email.is_empty());
+
+    let mut errors = validate_credentials(Some(username), &request.password)
+        .err()
+        .unwrap_or_default();
+    if let Some(email) = &email
+        && !email_valid(email)
+    {
+        errors.push(FieldError {
+            field: "email".to_string(),
+            message: "is not a valid email address".to_string(),
+        });
     }
-    let salt = salt.unwrap();
-
-    let argon2 = Argon2::default();
-    let password_hash = argon2.hash_password(request.password.as_bytes(), salt.as_salt());
-
-    // hashing error
-    if password_hash.is_err() {
-        error!("Failed to hash password!");
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    if !scopes_valid(request.scopes.as_deref()) {
+        errors.push(FieldError {
+            field: "scopes".to_string(),
+            message: "unknown object type".to_string(),
+        });
+    }
+    if !errors.is_empty() {
+        return Err(ApiError::with_field_errors(StatusCode::BAD_REQUEST, errors));
     }
-    let password_hash = password_hash.unwrap();
 
-    let result = state
-        .db
-        .new_user(&request.username, password_hash.serialize().as_str());
+    let password_hash = hash_password(&request.password, &state.hash_config)
+        .await
+        .map_err(|_| {
+            error!("Failed to hash password!");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
 
-    if result.is_err() {
-        info!("User tried to register with already taken username.");
-        return Err(StatusCode::CONFLICT);
-    }
-    let user_id = result.unwrap();
+    let user_id = state
+        .db
+        .new_user(username, &password_hash, email.as_deref())
+        .await
+        .map_err(|err| {
+            if matches!(err, DbError::UniqueViolation(_)) {
+                info!("User tried to register with already taken username or email.");
+            }
+            ApiError::from(err)
+        })?;
 
     // all is right -> generate tokens so user can log in immediately
+    // an account without all of its tokens is unusable, so on any failure the user is removed again
 
     // generate local tokens for future use, every db ident element gets a local token
-    crate::data_handler::objects::get_db_idents().iter().for_each(|variant| {
-            let result = add_new_local_token(user_id, &request.password, variant, state.clone());
-            if result.is_err() {
-                error!("Failed to generate local token for variant {:?}!, user id: {}, registration partially successful!", variant, user_id);
-            }
-    });
+    // every local token is additionally encrypted with the recovery key, the key itself is never stored
+    let recovery_key = generate_token();
+    for variant in crate::data_handler::objects::get_db_idents().iter() {
+        let result = add_new_local_token(
+            user_id,
+            &request.password,
+            Some(&recovery_key),
+            variant,
+            state.clone(),
+        )
+        .await;
+        if result.is_err() {
+            error!(
+                "Failed to generate local token for variant {:?}!, user id: {}, registration aborted!",
+                variant, user_id
+            );
+            remove_failed_registration(&state, user_id).await;
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+        }
+    }
 
     // generate remote token for immediate use
-    let remote_token = create_remote_token(user_id, request.password, state, TOKEN_EXPIRE);
+    let valid_days = state.token_config.login_days(request.remember_me);
+    let remote_token = create_remote_token(
+        user_id,
+        request.password,
+        state.clone(),
+        valid_days,
+        request.scopes.as_deref(),
+    )
+    .await;
 
     if remote_token.is_err() {
         // internal decryption error or db error
         error!("Generating remote token failed!");
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        remove_failed_registration(&state, user_id).await;
+        return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
     }
     let remote_token = remote_token.unwrap();
 
+    record_auth_event(
+        &state,
+        Some(user_id),
+        AuthEventKind::Register,
+        client_ip(connect_info),
+    )
+    .await;
     info!("Registered new user {}", request.username);
 
     // build response
-    Ok(Json(LoginResponse {
-        token: remote_token,
-    }))
+    Ok((
+        set_session_cookie(&state.token_config, &remote_token),
+        Json(LoginResponse {
+            recovery_key: Some(recovery_key),
+            ..remote_token.into()
+        }),
+    ))
 }
 
 /// handler for login requests
 async fn handle_login<DB: DBInterface + Send + Sync>(
     State(state): State<Arc<AppState<DB>>>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
     Json(request): Json<LoginRequest>,
-) -> Result<Json<LoginResponse>, StatusCode> {
+) -> Result<(SessionCookie, Json<LoginResponse>), ApiError> {
     info!("Login request from user {}", request.username);
+    let ip = client_ip(connect_info);
 
-    let user = state.db.get_user_by_username(&request.username);
+    if !scopes_valid(request.scopes.as_deref()) {
+        info!("Login with unknown scope.");
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    // the error is dropped right away, it must not be held across the await below
+    let user = state
+        .db
+        .get_user_by_username(request.username.trim())
+        .await
+        .ok();
 
-    if user.is_err() {
+    if user.is_none() {
         // User has not been found or an error occurred
         // prevent timing attacks and hash the password anyways
-        // dummy salt, has no meaning
-        let mut dummy_salt_bytes = [0u8; Salt::RECOMMENDED_LENGTH];
-        OsRng
-            .try_fill_bytes(&mut dummy_salt_bytes)
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        let dummy_salt = SaltString::encode_b64(&dummy_salt_bytes)
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        let _ = Argon2::default().hash_password(request.password.as_bytes(), dummy_salt.as_salt());
+        let _ = hash_password(&request.password, &state.hash_config).await;
 
         warn!(
             "User tried to log in with non existent user {}.\nPotential brute-force attack, watch out for too many of these warnings.",
             request.username
         );
-        return Err(StatusCode::UNAUTHORIZED);
+        // unknown usernames are never counted, otherwise anyone could fill the db or probe usernames
+        record_auth_event(&state, None, AuthEventKind::LoginFailed, ip).await;
+        return Err(StatusCode::UNAUTHORIZED.into());
     }
     let user = user.unwrap();
 
+    // a locked account is refused without checking the password, so guessing is pointless until the lockout ends
+    let now = Utc::now().naive_utc();
+    if let Some(locked_until) = user.locked_until
+        && locked_until > now
+    {
+        warn!("Login attempt for locked user {}.", request.username);
+        let retry_after_secs = (locked_until - now).num_seconds().max(1);
+        return Err(ApiError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "account_locked",
+            format!("try again in {} seconds", retry_after_secs),
+        )
+        .retry_after(retry_after_secs));
+    }
+
     // check if the password matches
-    let pwd_hash = PasswordHash::new(&user.password_hash).expect("Password Hash corrupted in DB!");
-    let result = Argon2::default().verify_password(request.password.as_bytes(), &pwd_hash);
+    let matches = verify_hash(&request.password, &user.password_hash).await?;
 
-    if result.is_err() {
+    if !matches {
         warn!("User {} entered wrong password!", request.username);
-        return Err(StatusCode::UNAUTHORIZED);
+        record_auth_event(&state, Some(user.id), AuthEventKind::LoginFailed, ip).await;
+        count_failed_login(&state, user.id, ip).await;
+        return Err(StatusCode::UNAUTHORIZED.into());
+    }
+
+    // hashes of weaker parameters are replaced while the plain password is known
+    if state.hash_config.is_weaker(&user.password_hash) {
+        upgrade_password_hash(&state, user.id, &request.password).await;
     }
 
+    if (user.failed_logins > 0 || user.locked_until.is_some())
+        && state.db.reset_failed_logins(user.id).await.is_err()
+    {
+        error!("Failed to reset the failed logins of user {}!", user.id);
+    }
+
+    record_auth_event(&state, Some(user.id), AuthEventKind::Login, ip).await;
+
+    // object types added after the registration get their local tokens now, before they are encrypted for the remote token
+    heal_local_tokens(&state, user.id, &request.password).await;
+
     // password matches -> generate token
-    let remote_token = create_remote_token(user.id, request.password, state, TOKEN_EXPIRE);
+    let valid_days = state.token_config.login_days(request.remember_me);
+    let remote_token = create_remote_token(
+        user.id,
+        request.password,
+        state.clone(),
+        valid_days,
+        request.scopes.as_deref(),
+    )
+    .await;
 
     if remote_token.is_err() {
         // internal decryption error or db error
         error!("Generating remote token failed!");
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
     }
     let remote_token = remote_token.unwrap();
 
     info!("Login successful, returning new remote token to Client!");
 
     // build response
-    Ok(Json(LoginResponse {
-        token: remote_token,
-    }))
+    Ok((
+        set_session_cookie(&state.token_config, &remote_token),
+        Json(remote_token.into()),
+    ))
+}
+
+/// creates the local tokens a user is missing (object types added after the registration), failures are only logged
+/// the recovery key is unknown here, so the new tokens have no recovery copy and a password reset can not recover their data
+async fn heal_local_tokens<DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    user_id: i32,
+    password: &str,
+) {
+    let Ok(local_tokens) = state.db.get_local_tokens_by_user_pwcrypt(user_id).await else {
+        error!("Failed to load local tokens of user {}!", user_id);
+        return;
+    };
+
+    for variant in crate::data_handler::objects::get_db_idents().iter() {
+        if local_tokens
+            .iter()
+            .any(|lt| lt.used_for.db_identifier == variant.db_identifier)
+        {
+            continue;
+        }
+
+        if add_new_local_token(user_id, password, None, variant, state.clone())
+            .await
+            .is_err()
+        {
+            error!(
+                "Failed to create missing local token for variant {:?}! (user id: {})",
+                variant, user_id
+            );
+        } else {
+            info!(
+                "Created missing local token for variant {:?}. (user id: {})",
+                variant, user_id
+            );
+        }
+    }
+}
+
+/// re-hashes a verified password with the configured parameters, failures are only logged as the old hash keeps working
+async fn upgrade_password_hash<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    password: &str,
+) {
+    let Ok(password_hash) = hash_password(password, &state.hash_config).await else {
+        error!("Failed to upgrade password hash of user {}!", user_id);
+        return;
+    };
+
+    // the local tokens are encrypted with the password itself, not with the hash, they stay as they are
+    if state
+        .db
+        .update_user_password_hash(user_id, &password_hash, &[])
+        .await
+        .is_err()
+    {
+        error!(
+            "Failed to store upgraded password hash of user {}!",
+            user_id
+        );
+        return;
+    }
+    info!("Upgraded password hash of user {}.", user_id);
+}
+
+/// checks the username (if given, already trimmed) and password rules, returns every violation
+/// the password length is limited, hashing huge passwords would block the server
+pub fn validate_credentials(username: Option<&str>, password: &str) -> Result<(), Vec<FieldError>> {
+    let mut errors = vec![];
+    let mut error = |field: &str, reason: &str| {
+        errors.push(FieldError {
+            field: field.to_string(),
+            message: reason.to_string(),
+        })
+    };
+
+    if let Some(username) = username {
+        let length = username.chars().count();
+        if !(USERNAME_MIN_LENGTH..=USERNAME_MAX_LENGTH).contains(&length) {
+            error(
+                "username",
+                &format!(
+                    "has to be {} to {} characters long",
+                    USERNAME_MIN_LENGTH, USERNAME_MAX_LENGTH
+                ),
+            );
+        }
+        if !username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+        {
+            error(
+                "username",
+                "may only contain letters, digits and the characters . _ -",
+            );
+        }
+    }
+
+    if password.chars().count() < PASSWORD_MIN_LENGTH {
+        error(
+            "password",
+            &format!("has to be at least {} characters long", PASSWORD_MIN_LENGTH),
+        );
+    }
+    if password.len() > PASSWORD_MAX_BYTES {
+        error(
+            "password",
+            &format!("may not be longer than {} bytes", PASSWORD_MAX_BYTES),
+        );
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// rough check of an (already trimmed) email address, the address is never mailed to
+fn email_valid(email: &str) -> bool {
+    let Some((local, domain)) = email.split_once('@') else {
+        return false;
+    };
+    email.len() <= EMAIL_MAX_BYTES
+        && !local.is_empty()
+        && !domain.contains('@')
+        && domain.split('.').count() > 1
+        && domain.split('.').all(|part| !part.is_empty())
+        && !email.chars().any(char::is_whitespace)
+}
+
+/// checks that every scope is the db identifier of an object type
+fn scopes_valid(scopes: Option<&[String]>) -> bool {
+    let idents = crate::data_handler::objects::get_db_idents();
+    scopes
+        .unwrap_or_default()
+        .iter()
+        .all(|scope| idents.iter().any(|ident| &ident.db_identifier == scope))
+}
+
+/// hashes a password (or token) with a random salt, returns the serialized hash
+/// argon2 is slow on purpose, so it runs on the blocking thread pool instead of stalling the async workers
+async fn hash_password(password: &str, config: &HashConfig) -> Result<String, AuthError> {
+    let password = password.to_string();
+    let argon2 = config.argon2();
+    tokio::task::spawn_blocking(move || {
+        // generate salt
+        let mut salt_bytes = [0u8; Salt::RECOMMENDED_LENGTH];
+        OsRng
+            .try_fill_bytes(&mut salt_bytes)
+            .map_err(|err| AuthError::Crypto(err.to_string()))?;
+        let salt = SaltString::encode_b64(&salt_bytes)
+            .map_err(|_| AuthError::Crypto("salting failed".to_string()))?;
+
+        let password_hash = argon2
+            .hash_password(password.as_bytes(), salt.as_salt())
+            .map_err(|_| AuthError::Crypto("hashing failed".to_string()))?;
+
+        Ok(password_hash.serialize().to_string())
+    })
+    .await
+    .map_err(|err| AuthError::Crypto(err.to_string()))?
+}
+
+/// checks a password (or token) against a stored hash on the blocking thread pool, returns whether it matches
+/// the parameters are taken from the hash itself, so hashes of older configurations still verify
+async fn verify_hash(password: &str, hash: &str) -> Result<bool, AuthError> {
+    let password = password.to_string();
+    let hash = hash.to_string();
+    tokio::task::spawn_blocking(move || {
+        let hash = PasswordHash::new(&hash)
+            .map_err(|_| AuthError::Crypto("Hash corrupted in DB!".to_string()))?;
+        Ok(Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok())
+    })
+    .await
+    .map_err(|err| AuthError::Crypto(err.to_string()))?
 }
 
 /// creates a new remote token for the given user
-fn create_remote_token<DB: DBInterface + Send + Sync>(
+async fn create_remote_token<DB: DBInterface + Send + Sync>(
     user_id: i32,
     password: String,
     state: Arc<AppState<DB>>,
     valid_days: u64,
-) -> Result<String, Box<dyn Error>> {
-    let remote_token = generate_token();
+    scopes: Option<&[String]>,
+) -> Result<IssuedToken, AuthError> {
+    create_remote_token_with(user_id, state.clone(), valid_days, |lt| {
+        if scopes.is_some_and(|scopes| !scopes.contains(&lt.used_for.db_identifier)) {
+            return Ok(None);
+        }
+        lt.token_crypt
+            .decrypt(password.as_bytes(), &state.crypt_provider)
+            .map(Some)
+            .map_err(|err| AuthError::Crypto(err.to_string()))
+    })
+    .await
+}
+
+/// creates a new remote token for the given user, decrypt_local_token has to return the plain local token for a password encrypted local token
+/// or none if the new token should not have access to it (scoped token)
+/// the token is only stored together with every local token encrypted for it
+async fn create_remote_token_with<DB: DBInterface + Send + Sync>(
+    user_id: i32,
+    state: Arc<AppState<DB>>,
+    valid_days: u64,
+    decrypt_local_token: impl Fn(&LocalTokenPWCrypt) -> Result<Option<String>, AuthError> + Send,
+) -> Result<IssuedToken, AuthError> {
+    let remote_token = generate_token_with_len(REMOTE_TOKEN_BYTES);
 
     let valid_until = Utc::now().naive_utc() + Days::new(valid_days);
 
     // hash the token
-    // generate salt
-    let mut salt_bytes = [0u8; Salt::RECOMMENDED_LENGTH];
-    OsRng.try_fill_bytes(&mut salt_bytes)?;
-    let salt = SaltString::encode_b64(&salt_bytes);
-    // salting problem occurred
-    if salt.is_err() {
-        return Err("salting failed".into());
-    }
-    let salt = salt.unwrap();
-
-    let argon2 = Argon2::default();
-    let token_hashed = argon2.hash_password(remote_token.as_bytes(), salt.as_salt());
-    // hashing error
-    if token_hashed.is_err() {
-        return Err("hashing failed".into());
-    }
-    let token_hashed = token_hashed.unwrap().to_string();
-
-    // insert hashed token into db
-    let remote_token_id = state
-        .db
-        .new_remote_token(&token_hashed, user_id, &valid_until)?;
+    let token_hashed = hash_password(&remote_token, &state.hash_config).await?;
+
+    // re-encrypt every local-token the user possesses, unless the token is limited to some local-tokens to restrict permissions
+    // done before the transaction, so the slow key derivation does not hold the write lock
+    let mut local_tokens = Vec::new();
+    for lt in state.db.get_local_tokens_by_user_pwcrypt(user_id).await? {
+        let Some(local_token) = decrypt_local_token(&lt)? else {
+            continue;
+        };
+        let newcrypt_token =
+            CryptString::encrypt(&local_token, remote_token.as_bytes(), &state.crypt_provider);
+        local_tokens.push((lt.id, newcrypt_token));
+    }
 
-    // re-encrypt every local-token the user possesses, this can also be limited to only some local-tokens to restrict permissions
-    state
+    let remote_token_id: i32 = state
         .db
-        .get_local_tokens_by_user_pwcrypt(user_id)?
-        .iter()
-        .try_for_each(|lt| {
-            let local_token = lt
-                .token_crypt
-                .decrypt(password.as_bytes(), &state.crypt_provider)?;
-
-            let newcrypt_token =
-                CryptString::encrypt(&local_token, remote_token.as_bytes(), &state.crypt_provider);
-            state.db.new_local_token_rtcrypt(
-                lt.id,
-                &newcrypt_token,
-                remote_token_id
-                    .try_into()
-                    .expect("Remote token ID is too big!"),
-            )?;
-
-            Ok::<(), Box<dyn Error>>(())
-        })?;
+        .new_remote_token(&token_hashed, user_id, &valid_until, &local_tokens)
+        .await?
+        .try_into()
+        .expect("Remote token ID is too big!");
 
     // prefix the token with its token id
-    let remote_token = remote_token_id.to_string() + "_" + &remote_token;
-
-    Ok(remote_token)
+    Ok(IssuedToken {
+        token: remote_token_id.to_string() + "_" + &remote_token,
+        user_id,
+        valid_until,
+    })
 }
 
-fn invalidate_remote_token<DB: DBInterface + Send + Sync>(
+async fn invalidate_remote_token<DB: DBInterface + Send + Sync>(
     remote_token_id: i32,
     state: Arc<AppState<DB>>,
-) -> Result<(), Box<dyn Error>> {
-    state.db.del_local_token_rtcrypt_by_rt(remote_token_id)?;
-    state.db.del_remote_token(remote_token_id)?;
+) -> Result<(), AuthError> {
+    state
+        .db
+        .with_transaction(|db| {
+            Box::pin(async move {
+                db.del_local_token_rtcrypt_by_rt(remote_token_id).await?;
+                db.del_remote_token(remote_token_id).await
+            })
+        })
+        .await?;
 
     Ok(())
 }
 
+/// background task, deletes expired tokens of sessions that are never used again (first run on startup)
+pub async fn token_cleanup_worker<DB: DBInterface + Send + Sync>(state: Arc<AppState<DB>>) {
+    let mut interval = tokio::time::interval(state.token_config.cleanup_interval);
+
+    loop {
+        interval.tick().await;
+        let purged = purge_expired_tokens(state.clone(), Utc::now().naive_utc()).await;
+        info!("Token cleanup finished, purged {} expired tokens.", purged);
+    }
+}
+
+/// invalidates every remote token that expired before now, returns the number of purged tokens
+pub async fn purge_expired_tokens<DB: DBInterface + Send + Sync>(
+    state: Arc<AppState<DB>>,
+    now: NaiveDateTime,
+) -> usize {
+    let expired = match state.db.get_expired_remote_tokens(&now).await {
+        Ok(expired) => expired,
+        Err(_) => {
+            error!("Failed to load expired tokens!");
+            return 0;
+        }
+    };
+
+    let mut purged = 0;
+    for remote_token in expired {
+        if invalidate_remote_token(remote_token.id, state.clone())
+            .await
+            .inspect_err(|_| error!("Failed to delete expired token {}!", remote_token.id))
+            .is_err()
+        {
+            continue;
+        }
+        record_auth_event(
+            &state,
+            Some(remote_token.user_id),
+            AuthEventKind::TokenExpired,
+            None,
+        )
+        .await;
+        purged += 1;
+    }
+    purged
+}
+
+/// counts a wrong password of an existing user and locks the account once the threshold is reached
+/// failures are only logged, the login is refused anyways
+async fn count_failed_login<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    ip: Option<IpAddr>,
+) {
+    let Ok(failed_logins) = state.db.increment_failed_logins(user_id).await else {
+        error!("Failed to count failed login of user {}!", user_id);
+        return;
+    };
+    if i64::from(failed_logins) < i64::from(state.lockout_config.threshold) {
+        return;
+    }
+
+    let duration =
+        TimeDelta::from_std(state.lockout_config.duration).expect("Lockout duration too long");
+    let locked_until = Utc::now().naive_utc() + duration;
+    warn!(
+        "User {} locked after {} wrong passwords until {}.",
+        user_id, failed_logins, locked_until
+    );
+    if state.db.lock_user(user_id, &locked_until).await.is_err() {
+        error!("Failed to lock user {}!", user_id);
+        return;
+    }
+    record_auth_event(state, Some(user_id), AuthEventKind::Locked, ip).await;
+}
+
+/// ip of the client, requests without connection info (e.g. tests) have none
+fn client_ip(connect_info: Option<Extension<ConnectInfo<SocketAddr>>>) -> Option<IpAddr> {
+    connect_info.map(|Extension(ConnectInfo(addr))| addr.ip())
+}
+
+/// writes an event to the auth audit log, a failure is only logged and never fails the request
+async fn record_auth_event<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: Option<i32>,
+    kind: AuthEventKind,
+    ip: Option<IpAddr>,
+) {
+    let ip = ip.map(|ip| ip.to_string());
+    if state
+        .db
+        .new_auth_event(user_id, kind.as_str(), ip.as_deref())
+        .await
+        .is_err()
+    {
+        error!(
+            "Failed to store auth event {:?} of user {:?}!",
+            kind, user_id
+        );
+    }
+}
+
 /// parses and extracts the token and token id from authentication header
-fn split_auth_header(auth_header: &str) -> Result<(i32, String), Box<dyn Error>> {
+fn split_auth_header(auth_header: &str) -> Result<(i32, String), AuthError> {
     // check for Bearer token
-    let token = auth_header.strip_prefix("Bearer ").ok_or("Invalid Token")?;
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .ok_or(AuthError::InvalidToken)?;
 
-    // split the user id
-    let split: Vec<&str> = token.split_terminator("_").collect();
+    split_token(token)
+}
 
-    let token_id = split.first().ok_or("Invalid Token")?;
-    let token = split.get(1).ok_or("Invalid Token")?;
+/// returns the value of the session cookie, if the request has one
+fn session_cookie_value(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|cookies| cookies.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE_NAME)
+        .map(|(_, value)| value)
+}
+
+/// splits a token as handed out to the client into token id and token
+fn split_token(token: &str) -> Result<(i32, String), AuthError> {
+    // split the token id, the token itself may contain further underscores (url safe base64)
+    let (token_id, token) = token.split_once('_').ok_or(AuthError::InvalidToken)?;
 
     // convert user id to i32
-    Ok((token_id.parse()?, token.to_string()))
+    let token_id = token_id.parse().map_err(|_| AuthError::InvalidToken)?;
+    Ok((token_id, token.to_string()))
+}
+
+/// errors of the auth helpers, token problems are answered with unauthorized, internal errors with internal server error
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum AuthError {
+    /// malformed header, unknown token id or wrong token, intentionally not distinguished
+    #[error("Invalid Token")]
+    InvalidToken,
+    /// the token expired (and has been deleted)
+    #[error("Token expired")]
+    Expired,
+    /// the remote token has no access to a local token, e.g. because it is scoped to other object types
+    #[error("No access to local token")]
+    NotFound,
+    /// the password of the user did not match
+    #[error("Wrong password")]
+    WrongPassword,
+    /// hashing or decryption failed
+    #[error("Crypto error: {0}")]
+    Crypto(String),
+    /// the database failed, only the message is kept so the error can be sent across threads
+    #[error("Database error: {0}")]
+    Db(String),
+    /// no database connection got free in time, the request can be retried
+    #[error("Database unavailable: {0}")]
+    Unavailable(String),
+}
+
+impl From<DbError> for AuthError {
+    fn from(err: DbError) -> Self {
+        match err {
+            DbError::Pool(_) => AuthError::Unavailable(err.to_string()),
+            err => AuthError::Db(err.to_string()),
+        }
+    }
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        ApiError::from(self).into_response()
+    }
+}
+
+/// seconds clients should wait before retrying a request that failed with service unavailable
+const UNAVAILABLE_RETRY_AFTER_SECS: u64 = 1;
+
+/// response mapper, adds a retry-after header to service unavailable responses
+/// handlers only return the status code, the db does not know how long it stays overloaded
+pub async fn retry_after_unavailable(mut response: Response) -> Response {
+    if response.status() == StatusCode::SERVICE_UNAVAILABLE {
+        response
+            .headers_mut()
+            .entry(header::RETRY_AFTER)
+            .or_insert(HeaderValue::from(UNAVAILABLE_RETRY_AFTER_SECS));
+    }
+    response
+}
+
+/// extractor for handlers that need a logged in user, rejects requests with an invalid token with unauthorized
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub user_id: i32,
+    pub remote_token_id: i32,
+    pub remote_token: String,
+}
+
+impl<DB: DBInterface + Send + Sync> FromRequestParts<Arc<AppState<DB>>> for AuthenticatedUser {
+    type Rejection = AuthError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState<DB>>,
+    ) -> Result<Self, Self::Rejection> {
+        // verify that the token is valid
+        let (user_id, remote_token_id, remote_token) = verify_token(&parts.headers, state.clone())
+            .await
+            .inspect_err(|err| {
+                if matches!(err, AuthError::InvalidToken | AuthError::Expired) {
+                    warn!("Authentication failure, invalid token!");
+                }
+            })?;
+
+        Ok(Self {
+            user_id,
+            remote_token_id,
+            remote_token,
+        })
+    }
 }
 
 /// verifies if the token is valid
 /// returns user_id, token_id and the token itself on success
 /// will return err if token is invalid or expired
 /// will delete the token entry if expired
-pub fn verify_token<DB: DBInterface + Send + Sync>(
-    auth_header: Option<&HeaderValue>,
+/// every existing or missing token id takes the same time, so ids can not be enumerated
+pub async fn verify_token<DB: DBInterface + Send + Sync>(
+    headers: &HeaderMap,
     state: Arc<AppState<DB>>,
-) -> Result<(i32, i32, String), Box<dyn Error>> {
-    // auth header validation
-    let auth_header = auth_header.ok_or("Invalid Token")?.to_str()?;
-
-    // parse the auth header
-    let (token_id, token) = split_auth_header(auth_header)?;
-
-    // get the stored token hash
-    let token_db = state.db.get_remote_token(token_id)?;
+) -> Result<(i32, i32, String), AuthError> {
+    // a malformed header does not reveal anything about existing tokens
+    // the auth header wins, the session cookie is only used without it and only if enabled
+    let (token_id, token) = match headers.get(header::AUTHORIZATION) {
+        Some(auth_header) => auth_header
+            .to_str()
+            .map_err(|_| AuthError::InvalidToken)
+            .and_then(split_auth_header)?,
+        None if state.token_config.session_cookie.is_some() => session_cookie_value(headers)
+            .ok_or(AuthError::InvalidToken)
+            .and_then(split_token)?,
+        None => return Err(AuthError::InvalidToken),
+    };
+
+    // a failing db is not reported as invalid token, the client would throw away a valid token
+    let token_db = state.db.get_remote_token(token_id).await?;
+
+    let Some(token_db) = token_db else {
+        // prevent timing attacks and hash the token anyways, like on logins of unknown users
+        let _ = hash_password(&token, &state.hash_config).await;
+        return Err(AuthError::InvalidToken);
+    };
+
+    // confirm that the token matches, a wrong secret must not reveal whether the id exists or has expired
+    if !verify_hash(&token, &token_db.rt_hash).await? {
+        return Err(AuthError::InvalidToken);
+    }
 
     // Token is no longer valid:
-    if token_db.valid_until <= Utc::now().naive_utc() {
+    let now = Utc::now().naive_utc();
+    if token_db.valid_until <= now {
         info!("Remote token expired, deleting corresponding entries!");
+        record_auth_event(
+            &state,
+            Some(token_db.user_id),
+            AuthEventKind::TokenExpired,
+            None,
+        )
+        .await;
+
+        // invalidate remote token, a failed deletion is retried by the cleanup task
+        if invalidate_remote_token(token_id, state).await.is_err() {
+            error!("Failed to delete expired token {}!", token_id);
+        }
+
+        return Err(AuthError::Expired);
+    }
+
+    if state.token_config.sliding_expiration {
+        extend_remote_token(&state, &token_db, now).await;
+    }
 
-        // invalidate remote token
-        invalidate_remote_token(token_id, state)?;
+    Ok((token_db.user_id, token_db.id, token))
+}
 
-        return Err("Token expired".into());
+/// pushes the expiry of a used token to a full lifetime from now
+/// the last extension is derived from valid_until, the token is only written if it is at least SLIDING_EXPIRATION_INTERVAL old
+/// longer lived tokens (remember me) are never shortened
+async fn extend_remote_token<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    token_db: &RemoteToken,
+    now: NaiveDateTime,
+) {
+    let new_valid_until = now + Days::new(state.token_config.valid_days);
+    if new_valid_until - token_db.valid_until < SLIDING_EXPIRATION_INTERVAL {
+        return;
     }
 
-    // confirm that the token matches
-    let db_token_hash = PasswordHash::new(&token_db.rt_hash).expect("Token Hash corrupted in DB!");
-    let result = Argon2::default().verify_password(token.as_bytes(), &db_token_hash);
+    if state
+        .db
+        .update_remote_token_validity(token_db.id, &new_valid_until)
+        .await
+        .is_err()
+    {
+        // the token stays valid until its old expiry
+        error!("Failed to extend remote token {}!", token_db.id);
+    }
+}
 
-    match result {
-        Ok(_) => Ok((token_db.user_id, token_id, token)),
-        Err(_) => Err("Invalid Token".into()),
+/// checks the password of a user, returns WrongPassword if it does not match
+pub async fn verify_user_password<DB: DBInterface + Send + Sync>(
+    user_id: i32,
+    password: &str,
+    state: Arc<AppState<DB>>,
+) -> Result<(), AuthError> {
+    // the error is converted right away, the boxed db error must not be held across the await below
+    let user = state
+        .db
+        .get_user_by_id(user_id)
+        .await
+        .map_err(AuthError::from);
+    let password_hash = match user {
+        Ok(user) => user.password_hash,
+        Err(err) => {
+            // same work as a real check, like on logins of unknown users
+            let _ = hash_password(password, &state.hash_config).await;
+            return Err(err);
+        }
+    };
+
+    if !verify_hash(password, &password_hash).await? {
+        return Err(AuthError::WrongPassword);
     }
+    Ok(())
+}
+
+/// re-authentication for destructive actions, logs wrong passwords together with the action
+/// wrong passwords result in forbidden, db failures in internal server error
+pub async fn confirm_password<DB: DBInterface + Send + Sync>(
+    user_id: i32,
+    password: &str,
+    state: Arc<AppState<DB>>,
+    action: &str,
+) -> Result<(), AuthError> {
+    verify_user_password(user_id, password, state)
+        .await
+        .inspect_err(|err| {
+            if *err == AuthError::WrongPassword {
+                warn!("{} with wrong password! (user id: {})", action, user_id);
+            }
+        })
 }
+
 /// takes a remote token, the according user id and used for attribute and decrypts the corresponding local token and returns it
-pub fn decrypt_local_token_for<DB: DBInterface + Send + Sync>(
+/// returns NotFound if the remote token has no access to the local token (scoped token)
+pub async fn decrypt_local_token_for<DB: DBInterface + Send + Sync>(
     user_id: i32,
     used_for: &DBObjIdent,
     remote_token_id: i32,
     remote_token: &str,
     state: Arc<AppState<DB>>,
-) -> Result<String, Box<dyn Error>> {
+) -> Result<String, AuthError> {
     // get the necessary local token and decrypt it
-    let local_token_pwcrypt = state
-        .db
-        .get_local_token_by_used_for_pwcrypt(user_id, used_for)?;
-    // get the rt encrypted version of it:
-    let local_token_rtcrypt = state
-        .db
-        .get_local_token_by_id_rtcrypt(local_token_pwcrypt.id, remote_token_id)?;
+    let local_token_rtcrypt =
+        local_token_rtcrypt_for(user_id, used_for, remote_token_id, &state).await?;
 
     // decrypt the local token
     let local_token = local_token_rtcrypt
         .local_token_crypt
-        .decrypt(remote_token.as_bytes(), &state.crypt_provider)?;
+        .decrypt(remote_token.as_bytes(), &state.crypt_provider)
+        .map_err(|err| AuthError::Crypto(err.to_string()))?;
 
     Ok(local_token)
 }
 
+/// checks that the remote token has access to the local token of used_for, without decrypting it
+/// returns NotFound if the remote token has no access to the local token (scoped token)
+pub async fn verify_local_token_access<DB: DBInterface + Send + Sync>(
+    user_id: i32,
+    used_for: &DBObjIdent,
+    remote_token_id: i32,
+    state: &AppState<DB>,
+) -> Result<(), AuthError> {
+    local_token_rtcrypt_for(user_id, used_for, remote_token_id, state)
+        .await
+        .map(|_| ())
+}
+
+/// loads the copy of the local token of used_for which is encrypted with the remote token
+async fn local_token_rtcrypt_for<DB: DBInterface + Send + Sync>(
+    user_id: i32,
+    used_for: &DBObjIdent,
+    remote_token_id: i32,
+    state: &AppState<DB>,
+) -> Result<LocalTokenRTCrypt, AuthError> {
+    let local_token_pwcrypt = state
+        .db
+        .get_local_token_by_used_for_pwcrypt(user_id, used_for)
+        .await?;
+    // get the rt encrypted version of it:
+    state
+        .db
+        .get_local_token_by_id_rtcrypt(local_token_pwcrypt.id, remote_token_id)
+        .await?
+        .ok_or(AuthError::NotFound)
+}
+
 /// generates and adds a password encrypted local token to the Database
-pub fn add_new_local_token<DB: DBInterface + Send + Sync>(
+/// if a recovery key is given a copy encrypted with it is stored as well
+pub async fn add_new_local_token<DB: DBInterface + Send + Sync>(
     user_id: i32,
     password: &str,
+    recovery_key: Option<&str>,
     used_for: &DBObjIdent,
     state: Arc<AppState<DB>>,
-) -> Result<(), Box<dyn Error>> {
-    let local_token = generate_token();
+) -> Result<(), AuthError> {
+    let local_token = generate_token_with_len(LOCAL_TOKEN_BYTES);
     let local_token_crypt =
         CryptString::encrypt(&local_token, password.as_bytes(), &state.crypt_provider);
 
     state
         .db
-        .new_local_token_pwcrypt(user_id, &local_token_crypt, used_for)?;
+        .new_local_token_pwcrypt(user_id, &local_token_crypt, used_for)
+        .await?;
+
+    if let Some(recovery_key) = recovery_key {
+        let local_token_id = state
+            .db
+            .get_local_token_by_used_for_pwcrypt(user_id, used_for)
+            .await?
+            .id;
+        let local_token_crypt =
+            CryptString::encrypt(&local_token, recovery_key.as_bytes(), &state.crypt_provider);
+        state
+            .db
+            .new_local_token_reccrypt(local_token_id, &local_token_crypt)
+            .await?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    use argon2::{
+        Algorithm, Argon2, Params, Version,
+        password_hash::{PasswordHasher, SaltString},
+    };
+    use axum::{
+        Extension,
+        body::Body,
+        extract::ConnectInfo,
+        http::{HeaderMap, HeaderValue, Method, Request, StatusCode, header},
+        response::Response,
+    };
+    use chrono::{DateTime, Days, NaiveDateTime, TimeDelta, Utc};
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use crate::{
+        crypt::{Cryptable, crypt_types::CryptString},
+        data_handler::{
+            data_router,
+            objects::{CourseDB, NoteDB, get_db_idents},
+        },
+        db::{DBInterface, DbError, sql_helper::SQLGenerate},
+        db_param_map,
+        test_util::{mock_state, register_user, send_json, test_state},
+    };
+
+    use super::{
+        AuthError, LoginLockoutConfig, SessionCookieConfig, TokenConfig, auth_router,
+        purge_expired_tokens, split_auth_header, validate_credentials, verify_hash, verify_token,
+    };
+
+    /// headers with the token as bearer token
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", token)).unwrap(),
+        );
+        headers
+    }
+
+    #[tokio::test]
+    async fn refresh_rotates_token() {
+        let state = test_state().await;
+        let token = register_user(&state, "refresh_user").await;
+        let auth = auth_router(state.clone());
+        let data = data_router(state.clone()).await;
+
+        send_json(
+            data.clone(),
+            Method::POST,
+            "/course",
+            &token,
+            Some(json!({ "id": null, "name": "Analysis" })),
+        )
+        .await;
+
+        let (status, refreshed) =
+            send_json(auth.clone(), Method::POST, "/refresh", &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let new_token = refreshed["token"].as_str().unwrap();
+        assert_ne!(new_token, token);
+
+        // the old token is gone, the new one can decrypt the data
+        let (status, _) = send_json(auth.clone(), Method::GET, "/verify-token", &token, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, courses) = send_json(data, Method::GET, "/course", new_token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(courses[0]["name"], "Analysis");
+
+        let (status, _) = send_json(auth, Method::POST, "/refresh", &token, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn password_change_keeps_data_readable() {
+        let state = test_state().await;
+        let token = register_user(&state, "password_user").await;
+        let auth = auth_router(state.clone());
+        let data = data_router(state.clone()).await;
+
+        send_json(
+            data.clone(),
+            Method::POST,
+            "/course",
+            &token,
+            Some(json!({ "id": null, "name": "Analysis" })),
+        )
+        .await;
+        let (_, other_session) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/login",
+            "",
+            Some(json!({ "username": "password_user", "password": "password" })),
+        )
+        .await;
+
+        let (status, _) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/change-password",
+            &token,
+            Some(json!({ "old_password": "wrong", "new_password": "new password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, _) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/change-password",
+            &token,
+            Some(json!({ "old_password": "password", "new_password": "new password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        // the old password does not work anymore, other sessions are logged out
+        let (status, _) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/login",
+            "",
+            Some(json!({ "username": "password_user", "password": "password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = send_json(
+            auth.clone(),
+            Method::GET,
+            "/verify-token",
+            other_session["token"].as_str().unwrap(),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // a new login decrypts the local tokens with the new password
+        let (status, login) = send_json(
+            auth,
+            Method::POST,
+            "/login",
+            "",
+            Some(json!({ "username": "password_user", "password": "new password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, courses) = send_json(
+            data.clone(),
+            Method::GET,
+            "/course",
+            login["token"].as_str().unwrap(),
+            None,
+        )
+        .await;
+        assert_eq!(courses[0]["name"], "Analysis");
+        // the current session keeps working
+        let (status, _) = send_json(data, Method::GET, "/course", &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn failed_logout_keeps_token_valid() {
+        let state = mock_state().await;
+        let token = register_user(&state, "logout_user").await;
+        let auth = auth_router(state.clone());
+
+        // the local tokens are deleted first, the transaction has to restore them
+        state.db.fail_next(
+            "del_remote_token",
+            DbError::Other("disk I/O error".to_string()),
+        );
+        let (status, _) = send_json(auth.clone(), Method::POST, "/logout", &token, None).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        let (status, _) = send_json(auth.clone(), Method::GET, "/verify-token", &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let data = data_router(state.clone()).await;
+        let (status, _) = send_json(data, Method::GET, "/course", &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = send_json(auth.clone(), Method::POST, "/logout", &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send_json(auth, Method::GET, "/verify-token", &token, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn logout_all_invalidates_sessions() {
+        let state = test_state().await;
+        let first = register_user(&state, "leaked_user").await;
+        let auth = auth_router(state.clone());
+        let login = json!({ "username": "leaked_user", "password": "password" });
+        let (_, second) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/login",
+            "",
+            Some(login.clone()),
+        )
+        .await;
+        let second = second["token"].as_str().unwrap();
+        let (_, third) = send_json(auth.clone(), Method::POST, "/login", "", Some(login)).await;
+        let third = third["token"].as_str().unwrap();
+
+        let (status, _) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/logout-all?keep_current=true",
+            &first,
+            Some(json!({ "password": "password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send_json(auth.clone(), Method::GET, "/verify-token", &first, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send_json(auth.clone(), Method::GET, "/verify-token", second, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // a token alone is not enough
+        let (status, _) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/logout-all",
+            &first,
+            Some(json!({ "password": "wrong" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send_json(auth.clone(), Method::GET, "/verify-token", &first, None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/logout-all",
+            &first,
+            Some(json!({ "password": "password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        for token in [first.as_str(), third] {
+            let (status, _) =
+                send_json(auth.clone(), Method::GET, "/verify-token", token, None).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[tokio::test]
+    async fn sessions_can_be_listed_and_revoked() {
+        let state = test_state().await;
+        let token = register_user(&state, "session_user").await;
+        let other_token = register_user(&state, "session_other").await;
+        let auth = auth_router(state.clone());
+        let (_, second) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/login",
+            "",
+            Some(json!({ "username": "session_user", "password": "password" })),
+        )
+        .await;
+        let second = second["token"].as_str().unwrap();
+
+        let (status, sessions) =
+            send_json(auth.clone(), Method::GET, "/sessions", &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let sessions = sessions.as_array().unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0]["current"], true);
+        assert_eq!(sessions[1]["current"], false);
+        assert!(sessions[0].get("rt_hash").is_none());
+        let second_id = sessions[1]["id"].as_i64().unwrap();
+
+        // sessions of other users can not be revoked
+        let (status, _) = send_json(
+            auth.clone(),
+            Method::DELETE,
+            &format!("/sessions/{}", second_id),
+            &other_token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = send_json(
+            auth.clone(),
+            Method::DELETE,
+            &format!("/sessions/{}", second_id),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send_json(auth.clone(), Method::GET, "/verify-token", second, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (_, sessions) = send_json(auth, Method::GET, "/sessions", &token, None).await;
+        assert_eq!(sessions.as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn logins_do_not_block_the_runtime() {
+        let state = test_state().await;
+        register_user(&state, "busy_user").await;
+        let auth = auth_router(state.clone());
+        let finished = Arc::new(AtomicUsize::new(0));
+
+        let logins = (0..4)
+            .map(|_| {
+                let (auth, finished) = (auth.clone(), finished.clone());
+                tokio::spawn(async move {
+                    send_json(
+                        auth,
+                        Method::POST,
+                        "/login",
+                        "",
+                        Some(json!({ "username": "busy_user", "password": "password" })),
+                    )
+                    .await;
+                    finished.fetch_add(1, Ordering::SeqCst);
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // the test runtime has a single thread, argon2 on it would finish the logins before the timer fires
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert_eq!(finished.load(Ordering::SeqCst), 0);
+
+        for login in logins {
+            login.await.unwrap();
+        }
+        assert_eq!(finished.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn remember_me_selects_long_lifetime() {
+        let state = test_state().await;
+        let token = register_user(&state, "remember_user").await;
+        let auth = auth_router(state.clone());
+        send_json(
+            auth.clone(),
+            Method::POST,
+            "/login",
+            "",
+            Some(
+                json!({ "username": "remember_user", "password": "password", "remember_me": true }),
+            ),
+        )
+        .await;
+
+        let (_, sessions) = send_json(auth, Method::GET, "/sessions", &token, None).await;
+        let days = |session: &serde_json::Value| {
+            let valid_until = session["valid_until"]
+                .as_str()
+                .unwrap()
+                .parse::<NaiveDateTime>();
+            (valid_until.unwrap() - Utc::now().naive_utc()).num_days() + 1
+        };
+        let config = TokenConfig::default();
+        assert_eq!(days(&sessions[0]), config.valid_days as i64);
+        assert_eq!(days(&sessions[1]), config.remember_me_days as i64);
+    }
+
+    #[test]
+    fn credentials_are_validated() {
+        let fields = |username: Option<&str>, password: &str| {
+            validate_credentials(username, password)
+                .err()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|error| error.field)
+                .collect::<Vec<_>>()
+        };
+
+        assert!(fields(Some("abc"), "12345678").is_empty());
+        assert!(fields(Some("first.last-name_2"), &"ü".repeat(128)).is_empty());
+        assert!(fields(None, "12345678").is_empty());
+
+        assert_eq!(fields(Some("ab"), "12345678"), ["username"]);
+        assert_eq!(fields(Some(&"a".repeat(33)), "12345678"), ["username"]);
+        assert_eq!(fields(Some("user\nname"), "12345678"), ["username"]);
+        assert_eq!(fields(Some("user name"), "12345678"), ["username"]);
+        assert_eq!(fields(Some("abc"), "1234567"), ["password"]);
+        // the maximum is counted in bytes, multi byte characters count more than once
+        assert_eq!(fields(Some("abc"), &"ü".repeat(129)), ["password"]);
+        assert_eq!(fields(Some(""), ""), ["username", "password"]);
+    }
+
+    #[tokio::test]
+    async fn invalid_registration_lists_fields() {
+        let state = test_state().await;
+        let auth = auth_router(state.clone());
+
+        let (status, errors) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/register",
+            "",
+            Some(json!({ "username": " x ", "password": "short" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(errors["field_errors"][0]["field"], "username");
+        assert_eq!(errors["field_errors"][1]["field"], "password");
+
+        // surrounding whitespace is trimmed on registration and login
+        let (status, _) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/register",
+            "",
+            Some(json!({ "username": " trimmed ", "password": "password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, login) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/login",
+            "",
+            Some(json!({ "username": "trimmed", "password": "password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, errors) = send_json(
+            auth,
+            Method::POST,
+            "/change-password",
+            login["token"].as_str().unwrap(),
+            Some(json!({ "old_password": "password", "new_password": "short" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(errors["field_errors"][0]["field"], "new_password");
+    }
+
+    #[tokio::test]
+    async fn recovery_key_resets_password() {
+        let state = test_state().await;
+        let auth = auth_router(state.clone());
+        let data = data_router(state.clone()).await;
+        let (status, registered) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/register",
+            "",
+            Some(json!({ "username": "forgetful", "password": "password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let token = registered["token"].as_str().unwrap();
+        let recovery_key = registered["recovery_key"].as_str().unwrap();
+        send_json(
+            data.clone(),
+            Method::POST,
+            "/course",
+            token,
+            Some(json!({ "id": null, "name": "Analysis" })),
+        )
+        .await;
+
+        // a wrong key changes nothing
+        let (status, _) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/reset-password",
+            "",
+            Some(json!({ "username": "forgetful", "recovery_key": "wrong", "new_password": "new password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = send_json(auth.clone(), Method::GET, "/verify-token", token, None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/reset-password",
+            "",
+            Some(json!({ "username": "forgetful", "recovery_key": recovery_key, "new_password": "new password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        // every session is logged out, the data is readable with the new password
+        let (status, _) = send_json(auth.clone(), Method::GET, "/verify-token", token, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, login) = send_json(
+            auth,
+            Method::POST,
+            "/login",
+            "",
+            Some(json!({ "username": "forgetful", "password": "new password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(login.get("recovery_key").is_none());
+        let (_, courses) = send_json(
+            data,
+            Method::GET,
+            "/course",
+            login["token"].as_str().unwrap(),
+            None,
+        )
+        .await;
+        assert_eq!(courses[0]["name"], "Analysis");
+    }
+
+    #[tokio::test]
+    async fn reset_replaces_local_tokens_without_recovery_copy() {
+        let state = test_state().await;
+        let auth = auth_router(state.clone());
+        let data = data_router(state.clone()).await;
+        let (_, registered) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/register",
+            "",
+            Some(json!({ "username": "healed_reset", "password": "password" })),
+        )
+        .await;
+        let recovery_key = registered["recovery_key"].as_str().unwrap();
+        let user_id = state
+            .db
+            .get_user_by_username("healed_reset")
+            .await
+            .unwrap()
+            .id;
+
+        // like an object type added after the registration, the token created on login has no recovery copy
+        state.db.execute_batch(&format!(
+            "DELETE FROM pwcrypt_local_token WHERE user_id = {} AND used_for = '{}';",
+            user_id,
+            NoteDB::get_db_ident().db_identifier
+        ));
+        let login = |password: &'static str| {
+            send_json(
+                auth.clone(),
+                Method::POST,
+                "/login",
+                "",
+                Some(json!({ "username": "healed_reset", "password": password })),
+            )
+        };
+        let (status, login_body) = login("password").await;
+        assert_eq!(status, StatusCode::OK);
+        let token = login_body["token"].as_str().unwrap();
+        for (uri, body) in [
+            ("/course", json!({ "id": null, "name": "Analysis" })),
+            (
+                "/note",
+                json!({ "id": null, "title": "Lost", "content": "" }),
+            ),
+        ] {
+            let (status, _) = send_json(data.clone(), Method::POST, uri, token, Some(body)).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+
+        let reset = |new_password: &'static str| {
+            send_json(
+                auth.clone(),
+                Method::POST,
+                "/reset-password",
+                "",
+                Some(
+                    json!({ "username": "healed_reset", "recovery_key": recovery_key, "new_password": new_password }),
+                ),
+            )
+        };
+        let (status, body) = reset("new password").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            json!({ "unrecoverable": [NoteDB::get_db_ident().db_identifier] })
+        );
+
+        // the recovered data is readable, the notes are gone and new ones work
+        let (status, login_body) = login("new password").await;
+        assert_eq!(status, StatusCode::OK);
+        let token = login_body["token"].as_str().unwrap();
+        let (_, courses) = send_json(data.clone(), Method::GET, "/course", token, None).await;
+        assert_eq!(courses[0]["name"], "Analysis");
+        let (status, notes) = send_json(data.clone(), Method::GET, "/note", token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(notes, json!([]));
+        let (status, _) = send_json(
+            data.clone(),
+            Method::POST,
+            "/note",
+            token,
+            Some(json!({ "id": null, "title": "Kept", "content": "" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        // the new token got a recovery copy, the next reset recovers everything
+        let (status, body) = reset("newer password").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({ "unrecoverable": [] }));
+        let (_, login_body) = login("newer password").await;
+        let (_, notes) = send_json(
+            data,
+            Method::GET,
+            "/note",
+            login_body["token"].as_str().unwrap(),
+            None,
+        )
+        .await;
+        assert_eq!(notes[0]["title"], "Kept");
+    }
+
+    #[tokio::test]
+    async fn scoped_token_only_unlocks_its_types() {
+        let state = test_state().await;
+        register_user(&state, "scoped_user").await;
+        let auth = auth_router(state.clone());
+        let data = data_router(state.clone()).await;
+
+        let (status, _) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/login",
+            "",
+            Some(json!({ "username": "scoped_user", "password": "password", "scopes": ["NoSuchDB"] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, login) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/login",
+            "",
+            Some(
+                json!({ "username": "scoped_user", "password": "password", "scopes": ["ToDoDB"] }),
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let token = login["token"].as_str().unwrap();
+
+        let (status, _) = send_json(data.clone(), Method::GET, "/todo", token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send_json(data.clone(), Method::GET, "/course", token, None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        // counting does not decrypt anything, but is scoped as well
+        let (status, _) = send_json(data.clone(), Method::GET, "/todo/count", token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send_json(data.clone(), Method::GET, "/course/count", token, None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // a refreshed token keeps the scope
+        let (_, refreshed) = send_json(auth, Method::POST, "/refresh", token, None).await;
+        let token = refreshed["token"].as_str().unwrap();
+        let (status, _) = send_json(data.clone(), Method::GET, "/todo", token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send_json(data, Method::GET, "/course", token, None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn expired_tokens_are_purged() {
+        let state = test_state().await;
+        let token = register_user(&state, "cleanup_user").await;
+        let user_id = state
+            .db
+            .get_user_by_username("cleanup_user")
+            .await
+            .unwrap()
+            .id;
+        let expired_id = state
+            .db
+            .new_remote_token(
+                "hash",
+                user_id,
+                &(Utc::now().naive_utc() - Days::new(1)),
+                &[],
+            )
+            .await
+            .unwrap() as i32;
+        let local_token_id = state
+            .db
+            .get_local_tokens_by_user_pwcrypt(user_id)
+            .await
+            .unwrap()[0]
+            .id;
+        let crypt = CryptString::encrypt(&"token".to_string(), b"key", &state.crypt_provider);
+        state
+            .db
+            .new_local_token_rtcrypt(local_token_id, &crypt, expired_id)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            purge_expired_tokens(state.clone(), Utc::now().naive_utc()).await,
+            1
+        );
+        assert!(
+            state
+                .db
+                .get_remote_token(expired_id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            state
+                .db
+                .get_local_token_ids_by_rt(expired_id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let events = state.db.get_auth_events_by_user(user_id, 1).await.unwrap();
+        assert_eq!(events[0].event, "token_expired");
+
+        // the valid session is untouched
+        let (status, _) = send_json(
+            auth_router(state.clone()),
+            Method::GET,
+            "/verify-token",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(purge_expired_tokens(state, Utc::now().naive_utc()).await, 0);
+    }
+
+    #[tokio::test]
+    async fn login_and_verify_return_expiry() {
+        let state = test_state().await;
+        register_user(&state, "expiry_user").await;
+        let auth = auth_router(state.clone());
+
+        let (_, login) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/login",
+            "",
+            Some(json!({ "username": "expiry_user", "password": "password" })),
+        )
+        .await;
+        assert_eq!(login["user_id"], 1);
+        let valid_until = login["valid_until"].as_str().unwrap();
+        let valid_until = DateTime::parse_from_rfc3339(valid_until).unwrap();
+        let days = (valid_until.to_utc() - Utc::now()).num_days() + 1;
+        assert_eq!(days, TokenConfig::default().valid_days as i64);
+
+        let (status, verified) = send_json(
+            auth,
+            Method::GET,
+            "/verify-token",
+            login["token"].as_str().unwrap(),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(verified["user_id"], 1);
+        assert_eq!(verified["valid_until"], login["valid_until"]);
+        let remaining = verified["remaining_secs"].as_i64().unwrap();
+        assert!(remaining > 13 * 24 * 60 * 60 && remaining <= 14 * 24 * 60 * 60);
+    }
+
+    #[tokio::test]
+    async fn email_is_stored_and_returned_by_me() {
+        let state = test_state().await;
+        let auth = auth_router(state.clone());
+        let register = |username: &str, email: &str| {
+            send_json(
+                auth.clone(),
+                Method::POST,
+                "/register",
+                "",
+                Some(json!({ "username": username, "password": "password", "email": email })),
+            )
+        };
+
+        let (status, registered) = register("mail_user", " Mail@Example.org ").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, errors) = register("other_user", "no-address").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(errors["field_errors"][0]["field"], "email");
+
+        let (status, _) = register("other_user", "mail@example.org").await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        // accounts without email do not collide
+        register_user(&state, "no_mail_1").await;
+        register_user(&state, "no_mail_2").await;
+
+        let token = registered["token"].as_str().unwrap();
+        let (status, me) = send_json(auth.clone(), Method::GET, "/me", token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(me["id"], registered["user_id"]);
+        assert_eq!(me["username"], "mail_user");
+        assert_eq!(me["email"], "mail@example.org");
+        assert!(me.get("password_hash").is_none());
+        assert!(me["created_at"].is_string());
+
+        let (status, _) = send_json(auth, Method::GET, "/me", "1_invalid", None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn auth_events_are_recorded() {
+        let state = test_state().await;
+        register_user(&state, "audited_user").await;
+        let client = SocketAddr::from(([203, 0, 113, 7], 4711));
+        let auth = auth_router(state.clone()).layer(Extension(ConnectInfo(client)));
+        let login = |password: &str| {
+            send_json(
+                auth.clone(),
+                Method::POST,
+                "/login",
+                "",
+                Some(json!({ "username": "audited_user", "password": password })),
+            )
+        };
+
+        let (status, _) = login("wrong password").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (_, logged_in) = login("password").await;
+        let token = logged_in["token"].as_str().unwrap();
+        let (_, other) = login("password").await;
+        let (status, _) = send_json(auth.clone(), Method::POST, "/logout", token, None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // unknown usernames are stored without user
+        send_json(
+            auth.clone(),
+            Method::POST,
+            "/login",
+            "",
+            Some(json!({ "username": "nobody", "password": "password" })),
+        )
+        .await;
+
+        let other = other["token"].as_str().unwrap();
+        let (status, events) = send_json(auth.clone(), Method::GET, "/events", other, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let kinds: Vec<_> = events
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|event| event["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            kinds,
+            ["logout", "login", "login", "login_failed", "register"]
+        );
+        assert_eq!(events[0]["ip"], "203.0.113.7");
+        // registered through the helper without connection info
+        assert!(events[4]["ip"].is_null());
+
+        let (_, events) = send_json(auth, Method::GET, "/events?limit=2", other, None).await;
+        assert_eq!(events.as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn repeated_wrong_passwords_lock_the_account() {
+        let state = test_state().await;
+        register_user(&state, "locked_user").await;
+        let user_id = state
+            .db
+            .get_user_by_username("locked_user")
+            .await
+            .unwrap()
+            .id;
+        let auth = auth_router(state.clone());
+        let threshold = LoginLockoutConfig::default().threshold;
+        let login = |username: &str, password: &str| {
+            send_json(
+                auth.clone(),
+                Method::POST,
+                "/login",
+                "",
+                Some(json!({ "username": username, "password": password })),
+            )
+        };
+
+        // a successful login resets the counter
+        for _ in 1..threshold {
+            assert_eq!(
+                login("locked_user", "wrong").await.0,
+                StatusCode::UNAUTHORIZED
+            );
+        }
+        assert_eq!(login("locked_user", "password").await.0, StatusCode::OK);
+        assert_eq!(
+            state
+                .db
+                .get_user_by_id(user_id)
+                .await
+                .unwrap()
+                .failed_logins,
+            0
+        );
+
+        for _ in 0..threshold {
+            assert_eq!(
+                login("locked_user", "wrong").await.0,
+                StatusCode::UNAUTHORIZED
+            );
+        }
+        // even the right password is refused now, the client learns when to retry from the header
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/login")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                json!({ "username": "locked_user", "password": "password" }).to_string(),
+            ))
+            .unwrap();
+        let response = auth.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: i64 = response.headers()[header::RETRY_AFTER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let duration = LoginLockoutConfig::default().duration.as_secs() as i64;
+        assert!(retry_after > duration - 60 && retry_after <= duration);
+        let events = state.db.get_auth_events_by_user(user_id, 1).await.unwrap();
+        assert_eq!(events[0].event, "locked");
+
+        // logins work again once the lockout ended
+        let past = Utc::now().naive_utc() - Days::new(1);
+        state.db.lock_user(user_id, &past).await.unwrap();
+        assert_eq!(login("locked_user", "password").await.0, StatusCode::OK);
+        assert!(
+            state
+                .db
+                .get_user_by_id(user_id)
+                .await
+                .unwrap()
+                .locked_until
+                .is_none()
+        );
+
+        // unknown usernames are never counted
+        for _ in 0..threshold {
+            assert_eq!(
+                login("ghost_user", "wrong").await.0,
+                StatusCode::UNAUTHORIZED
+            );
+        }
+        assert!(state.db.get_user_by_username("ghost_user").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn expired_token_with_wrong_secret_is_invalid() {
+        let state = test_state().await;
+        let token = register_user(&state, "expired_guess_user").await;
+        let token_id: i32 = token.split_once('_').unwrap().0.parse().unwrap();
+        let yesterday = Utc::now().naive_utc() - Days::new(1);
+        state
+            .db
+            .update_remote_token_validity(token_id, &yesterday)
+            .await
+            .unwrap();
+
+        // only the owner of the token learns that it has expired
+        let guess = bearer(&format!("{}_wrong", token_id));
+        assert_eq!(
+            verify_token(&guess, state.clone()).await,
+            Err(AuthError::InvalidToken)
+        );
+        assert!(state.db.get_remote_token(token_id).await.unwrap().is_some());
+        let events = state.db.get_auth_events_by_user(1, 10).await.unwrap();
+        assert!(events.iter().all(|event| event.event != "token_expired"));
+
+        assert_eq!(
+            verify_token(&bearer(&token), state.clone()).await,
+            Err(AuthError::Expired)
+        );
+        assert!(state.db.get_remote_token(token_id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn sliding_expiration_extends_used_tokens() {
+        let mut state = test_state().await;
+        Arc::get_mut(&mut state)
+            .unwrap()
+            .token_config
+            .sliding_expiration = true;
+        let token = register_user(&state, "sliding_user").await;
+        let token_id: i32 = token.split_once('_').unwrap().0.parse().unwrap();
+        let header = bearer(&token);
+        let valid_days = TokenConfig::default().valid_days;
+        let set_valid_until = async |valid_until: NaiveDateTime| {
+            state
+                .db
+                .update_remote_token_validity(token_id, &valid_until)
+                .await
+                .unwrap();
+        };
+        let remaining_days = async || {
+            (state
+                .db
+                .get_remote_token(token_id)
+                .await
+                .unwrap()
+                .unwrap()
+                .valid_until
+                - Utc::now().naive_utc())
+            .num_days()
+        };
+
+        // almost expired tokens get a full lifetime again
+        set_valid_until(Utc::now().naive_utc() + Days::new(1)).await;
+        verify_token(&header, state.clone()).await.unwrap();
+        assert_eq!(remaining_days().await + 1, valid_days as i64);
+
+        // recently extended tokens are not written again
+        let recent = Utc::now().naive_utc() + Days::new(valid_days) - TimeDelta::minutes(30);
+        set_valid_until(recent).await;
+        verify_token(&header, state.clone()).await.unwrap();
+        assert_eq!(
+            state
+                .db
+                .get_remote_token(token_id)
+                .await
+                .unwrap()
+                .unwrap()
+                .valid_until,
+            recent
+        );
+
+        // longer lifetimes are kept
+        let remember_me = Utc::now().naive_utc() + Days::new(30);
+        set_valid_until(remember_me).await;
+        verify_token(&header, state.clone()).await.unwrap();
+        assert_eq!(
+            state
+                .db
+                .get_remote_token(token_id)
+                .await
+                .unwrap()
+                .unwrap()
+                .valid_until,
+            remember_me
+        );
+
+        // off by default
+        let state = test_state().await;
+        let token = register_user(&state, "fixed_user").await;
+        let token_id: i32 = token.split_once('_').unwrap().0.parse().unwrap();
+        let header = bearer(&token);
+        let tomorrow = Utc::now().naive_utc() + Days::new(1);
+        state
+            .db
+            .update_remote_token_validity(token_id, &tomorrow)
+            .await
+            .unwrap();
+        verify_token(&header, state.clone()).await.unwrap();
+        assert_eq!(
+            state
+                .db
+                .get_remote_token(token_id)
+                .await
+                .unwrap()
+                .unwrap()
+                .valid_until,
+            tomorrow
+        );
+    }
+
+    #[tokio::test]
+    async fn internal_auth_errors_are_not_reported_as_invalid_token() {
+        assert_eq!(
+            StatusCode::from(AuthError::InvalidToken),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            StatusCode::from(AuthError::Expired),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(StatusCode::from(AuthError::NotFound), StatusCode::FORBIDDEN);
+        assert_eq!(
+            StatusCode::from(AuthError::WrongPassword),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            StatusCode::from(AuthError::Db("closed".to_string())),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+
+        // a corrupted hash in the db is an internal error, not a wrong token
+        let state = test_state().await;
+        register_user(&state, "corrupt_user").await;
+        let user_id = state
+            .db
+            .get_user_by_username("corrupt_user")
+            .await
+            .unwrap()
+            .id;
+        let valid_until = Utc::now().naive_utc() + Days::new(1);
+        let token_id = state
+            .db
+            .new_remote_token("not a hash", user_id, &valid_until, &[])
+            .await
+            .unwrap();
+        let header = bearer(&format!("{}_secret", token_id));
+        assert!(matches!(
+            verify_token(&header, state.clone()).await,
+            Err(AuthError::Crypto(_))
+        ));
+        let (status, _) = send_json(
+            auth_router(state),
+            Method::GET,
+            "/verify-token",
+            &format!("{}_secret", token_id),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn session_cookie_replaces_auth_header() {
+        let mut state = test_state().await;
+        Arc::get_mut(&mut state)
+            .unwrap()
+            .token_config
+            .session_cookie = Some(SessionCookieConfig {
+            secure: true,
+            same_site: "Lax".to_string(),
+        });
+        register_user(&state, "cookie_user").await;
+        let auth = auth_router(state.clone());
+        // requests of a browser, without auth header
+        let send = |method: Method, uri: &str, cookie: &str, body: Option<Value>| {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::COOKIE, cookie)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(body.map_or(Body::empty(), |body| Body::from(body.to_string())))
+                .unwrap();
+            auth.clone().oneshot(request)
+        };
+        let set_cookie = |response: &Response| {
+            response.headers()[header::SET_COOKIE]
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+
+        let login = json!({ "username": "cookie_user", "password": "password" });
+        let response = send(Method::POST, "/login", "", Some(login)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let cookie = set_cookie(&response);
+        assert!(cookie.starts_with("eduflow_token="));
+        assert!(cookie.contains("; HttpOnly; SameSite=Lax; Secure"));
+        let value = cookie.split(';').next().unwrap();
+
+        let other_cookies = format!("theme=dark; {}", value);
+        let response = send(Method::GET, "/verify-token", &other_cookies, None)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // logout deletes the token and the cookie
+        let response = send(Method::POST, "/logout", value, None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(set_cookie(&response).contains("Max-Age=0"));
+        let response = send(Method::GET, "/verify-token", value, None)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // cookies are ignored unless enabled
+        let state = test_state().await;
+        let token = register_user(&state, "header_user").await;
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_str(&format!("eduflow_token={}", token)).unwrap(),
+        );
+        assert_eq!(
+            verify_token(&headers, state).await.err(),
+            Some(AuthError::InvalidToken)
+        );
+    }
+
+    #[test]
+    fn auth_header_keeps_underscores_of_token() {
+        let (token_id, token) = split_auth_header("Bearer 12_a-b_c").unwrap();
+        assert_eq!(token_id, 12);
+        assert_eq!(token, "a-b_c");
+        assert!(split_auth_header("Bearer 12").is_err());
+        assert!(split_auth_header("Bearer x_abc").is_err());
+        assert!(split_auth_header("12_abc").is_err());
+    }
+
+    #[tokio::test]
+    async fn token_failures_are_uniform() {
+        let state = test_state().await;
+        let token = register_user(&state, "probed_user").await;
+        let (token_id, secret) = token.split_once('_').unwrap();
+        let header = |value: &str| bearer(value);
+
+        let verify = |value: String| {
+            let state = state.clone();
+            async move {
+                verify_token(&header(&value), state)
+                    .await
+                    .map(|(user_id, _, _)| user_id)
+            }
+        };
+        assert_eq!(verify(token.clone()).await, Ok(1));
+        // wrong token, unknown id and malformed headers can not be told apart
+        assert_eq!(
+            verify(format!("{}_wrong", token_id)).await,
+            Err(AuthError::InvalidToken)
+        );
+        assert_eq!(
+            verify(format!("4242_{}", secret)).await,
+            Err(AuthError::InvalidToken)
+        );
+        assert_eq!(
+            verify("garbage".to_string()).await,
+            Err(AuthError::InvalidToken)
+        );
+        assert_eq!(
+            verify_token(&HeaderMap::new(), state.clone()).await.err(),
+            Some(AuthError::InvalidToken)
+        );
+    }
+
+    #[tokio::test]
+    async fn account_deletion_removes_everything() {
+        let state = test_state().await;
+        let token = register_user(&state, "deleted_user").await;
+        let other_token = register_user(&state, "remaining_user").await;
+        let auth = auth_router(state.clone());
+        let data = data_router(state.clone()).await;
+
+        for token in [&token, &other_token] {
+            send_json(
+                data.clone(),
+                Method::POST,
+                "/course",
+                token,
+                Some(json!({ "id": null, "name": "Analysis" })),
+            )
+            .await;
+        }
+
+        let (status, _) = send_json(
+            auth.clone(),
+            Method::DELETE,
+            "/account",
+            &token,
+            Some(json!({ "password": "wrong" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, _) = send_json(
+            auth.clone(),
+            Method::DELETE,
+            "/account",
+            &token,
+            Some(json!({ "password": "password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = send_json(
+            auth.clone(),
+            Method::POST,
+            "/login",
+            "",
+            Some(json!({ "username": "deleted_user", "password": "password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = send_json(auth, Method::GET, "/verify-token", &token, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(
+            state
+                .db
+                .select_entries::<CourseDB>(db_param_map! { user_id: 1 }, &[], None)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            state
+                .db
+                .get_local_tokens_by_user_pwcrypt(1)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        // the data of other users is untouched
+        let (_, courses) = send_json(data, Method::GET, "/course", &other_token, None).await;
+        assert_eq!(courses[0]["name"], "Analysis");
+    }
+
+    #[tokio::test]
+    async fn failed_account_deletion_keeps_everything() {
+        let state = test_state().await;
+        let token = register_user(&state, "undeleted_user").await;
+        let auth = auth_router(state.clone());
+        let data = data_router(state.clone()).await;
+        send_json(
+            data.clone(),
+            Method::POST,
+            "/course",
+            &token,
+            Some(json!({ "id": null, "name": "Analysis" })),
+        )
+        .await;
+
+        // the user row is deleted last, after the tokens and the data
+        state.db.execute_batch(
+            "CREATE TRIGGER fail_user_delete BEFORE DELETE ON user
+             BEGIN SELECT RAISE(ABORT, 'simulated failure'); END;",
+        );
+        let (status, _) = send_json(
+            auth.clone(),
+            Method::DELETE,
+            "/account",
+            &token,
+            Some(json!({ "password": "password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+        // the session and the data are still there
+        let (status, courses) = send_json(data, Method::GET, "/course", &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(courses[0]["name"], "Analysis");
+
+        state.db.execute_batch("DROP TRIGGER fail_user_delete;");
+        let (status, _) = send_json(
+            auth,
+            Method::DELETE,
+            "/account",
+            &token,
+            Some(json!({ "password": "password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn password_change_is_all_or_nothing() {
+        let state = test_state().await;
+        let user_id = state
+            .db
+            .new_user("atomic_user", "old hash", None)
+            .await
+            .unwrap();
+
+        // a local token id of another user aborts the whole change
+        let token = CryptString::encrypt(&"token".to_string(), b"new", &state.crypt_provider);
+        assert!(
+            state
+                .db
+                .update_user_password_hash(user_id, "new hash", &[(4242, token)])
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            state
+                .db
+                .get_user_by_id(user_id)
+                .await
+                .unwrap()
+                .password_hash,
+            "old hash"
+        );
+    }
+
+    #[tokio::test]
+    async fn expired_token_is_not_refreshed() {
+        let state = test_state().await;
+        register_user(&state, "refresh_expired").await;
+
+        let salt = SaltString::encode_b64(b"refresh_test_salt").unwrap();
+        let hash = Argon2::default()
+            .hash_password(b"expired", salt.as_salt())
+            .unwrap()
+            .to_string();
+        let expired_at = Utc::now().naive_utc() - Days::new(1);
+        let token_id = state
+            .db
+            .new_remote_token(&hash, 1, &expired_at, &[])
+            .await
+            .unwrap();
+
+        let (status, _) = send_json(
+            auth_router(state.clone()),
+            Method::POST,
+            "/refresh",
+            &format!("{}_expired", token_id),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // the expired token is deleted and no new token has been created
+        let token_id = token_id as i32;
+        assert!(state.db.get_remote_token(token_id).await.unwrap().is_none());
+        assert!(
+            state
+                .db
+                .get_remote_token(token_id + 1)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn weak_password_hash_is_upgraded_on_login() {
+        let state = test_state().await;
+        register_user(&state, "weak_hash").await;
+        let user_id = state.db.get_user_by_username("weak_hash").await.unwrap().id;
+
+        let salt = SaltString::encode_b64(b"weak_hash_salt").unwrap();
+        let weak_hash = Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(8, 1, 1, None).unwrap(),
+        )
+        .hash_password(b"password", salt.as_salt())
+        .unwrap()
+        .to_string();
+        state
+            .db
+            .update_user_password_hash(user_id, &weak_hash, &[])
+            .await
+            .unwrap();
+        assert!(state.hash_config.is_weaker(&weak_hash));
+
+        let (status, _) = send_json(
+            auth_router(state.clone()),
+            Method::POST,
+            "/login",
+            "",
+            Some(json!({ "username": "weak_hash", "password": "password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        // the new hash uses the configured parameters and still verifies
+        let upgraded = state
+            .db
+            .get_user_by_id(user_id)
+            .await
+            .unwrap()
+            .password_hash;
+        assert_ne!(upgraded, weak_hash);
+        assert!(!state.hash_config.is_weaker(&upgraded));
+        assert!(verify_hash("password", &upgraded).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn failed_registration_does_not_leave_the_user_behind() {
+        let state = test_state().await;
+        let idents = get_db_idents();
+        let last_ident = &idents.last().unwrap().db_identifier;
+
+        // local tokens of the other types are created before the insert fails
+        state.db.execute_batch(&format!(
+            "CREATE TRIGGER fail_local_token BEFORE INSERT ON pwcrypt_local_token
+             WHEN NEW.used_for = '{}' BEGIN SELECT RAISE(ABORT, 'simulated failure'); END;",
+            last_ident
+        ));
+        let register = || {
+            send_json(
+                auth_router(state.clone()),
+                Method::POST,
+                "/register",
+                "",
+                Some(json!({ "username": "half_registered", "password": "password" })),
+            )
+        };
+
+        assert_eq!(register().await.0, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(
+            state
+                .db
+                .get_user_by_username("half_registered")
+                .await
+                .is_err()
+        );
+        // the first user of a fresh database has id 1
+        assert!(
+            state
+                .db
+                .get_local_token_by_used_for_pwcrypt(1, &idents[0])
+                .await
+                .is_err()
+        );
+
+        // the username is free again once the database works
+        state.db.execute_batch("DROP TRIGGER fail_local_token;");
+        assert_eq!(register().await.0, StatusCode::OK);
+        let user_id = state
+            .db
+            .get_user_by_username("half_registered")
+            .await
+            .unwrap()
+            .id;
+        for ident in &idents {
+            assert!(
+                state
+                    .db
+                    .get_local_token_by_used_for_pwcrypt(user_id, ident)
+                    .await
+                    .is_ok()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_local_tokens_are_created_on_login() {
+        let state = test_state().await;
+        register_user(&state, "healed").await;
+        let user_id = state.db.get_user_by_username("healed").await.unwrap().id;
+        let idents = get_db_idents();
+        let missing = &idents.last().unwrap();
+
+        // an object type added after the registration has no local token yet
+        state.db.execute_batch(&format!(
+            "DELETE FROM pwcrypt_local_token WHERE user_id = {} AND used_for = '{}';",
+            user_id, missing.db_identifier
+        ));
+        assert!(
+            state
+                .db
+                .get_local_token_by_used_for_pwcrypt(user_id, missing)
+                .await
+                .is_err()
+        );
+
+        let (status, _) = send_json(
+            auth_router(state.clone()),
+            Method::POST,
+            "/login",
+            "",
+            Some(json!({ "username": "healed", "password": "password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        assert!(
+            state
+                .db
+                .get_local_token_by_used_for_pwcrypt(user_id, missing)
+                .await
+                .is_ok()
+        );
+        assert_eq!(
+            state
+                .db
+                .get_local_tokens_by_user_pwcrypt(user_id)
+                .await
+                .unwrap()
+                .len(),
+            idents.len()
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_token_creation_is_rolled_back() {
+        let state = test_state().await;
+        register_user(&state, "rollback_user").await;
+        let user_id = state
+            .db
+            .get_user_by_username("rollback_user")
+            .await
+            .unwrap()
+            .id;
+        let local_tokens = state
+            .db
+            .get_local_tokens_by_user_pwcrypt(user_id)
+            .await
+            .unwrap();
+        let (first, last) = (local_tokens[0].id, local_tokens.last().unwrap().id);
+
+        // the remote token and the first local tokens are inserted before this fails
+        state.db.execute_batch(&format!(
+            "CREATE TRIGGER fail_rtcrypt BEFORE INSERT ON rtcrypt_local_token
+             WHEN NEW.local_token_id = {} BEGIN SELECT RAISE(ABORT, 'simulated failure'); END;",
+            last
+        ));
+        let (status, _) = send_json(
+            auth_router(state.clone()),
+            Method::POST,
+            "/login",
+            "",
+            Some(json!({ "username": "rollback_user", "password": "password" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+        // the registration created token 1, nothing of token 2 is left
+        assert!(state.db.get_remote_token(2).await.unwrap().is_none());
+        assert!(
+            state
+                .db
+                .get_local_token_by_id_rtcrypt(first, 2)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(state.db.get_remote_token(1).await.unwrap().is_some());
+    }
+}
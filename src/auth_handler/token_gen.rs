@@ -1,23 +1,49 @@
-use chrono::Utc;
-use rand::Rng;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use rand::{TryRngCore, rngs::OsRng};
 
-const CHARSET: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz1234567890";
-const LENGTH: usize = 32;
+/// random bytes of a token generated by `generate_token`
+const TOKEN_BYTES: usize = 32;
 
-/// generates a unique token
+/// generates a random token of TOKEN_BYTES bytes
 pub fn generate_token() -> String {
-    // uses ThreadRng, which "should be secure" as the rand docs state, we will assume it is.
-    let mut rng = rand::rng();
+    generate_token_with_len(TOKEN_BYTES)
+}
+
+/// generates a random token of `len` bytes from the os rng, encoded as url safe base64 without padding
+/// the token consists only of random bytes, it does not leak anything like a creation time
+pub fn generate_token_with_len(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    // without a working os rng no secure token can be generated at all
+    OsRng
+        .try_fill_bytes(&mut bytes)
+        .expect("OS random number generator failed");
+
+    URL_SAFE_NO_PAD.encode(bytes)
+}
 
-    // add a timestamp to ensure uniqueness
-    let time_sufix = Utc::now().timestamp_millis();
+#[cfg(test)]
+mod tests {
+    use super::{TOKEN_BYTES, generate_token, generate_token_with_len};
 
-    let token: String = (0..LENGTH)
-        .map(|_| {
-            let idx = rng.random_range(0..CHARSET.len());
-            CHARSET.chars().nth(idx).unwrap()
-        })
-        .collect();
+    #[test]
+    fn tokens_have_expected_length_and_charset() {
+        let token = generate_token();
+        // 4 chars per 3 bytes, no padding
+        assert_eq!(token.len(), (TOKEN_BYTES * 4).div_ceil(3));
+        assert_eq!(generate_token_with_len(16).len(), 22);
+        assert!(
+            token
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        );
+    }
 
-    format!("{}{}", token, time_sufix)
+    #[test]
+    fn consecutive_tokens_differ() {
+        // generated well within the same millisecond
+        let tokens: Vec<String> = (0..100).map(|_| generate_token()).collect();
+        for (i, token) in tokens.iter().enumerate() {
+            assert!(!tokens[i + 1..].contains(token));
+        }
+    }
 }
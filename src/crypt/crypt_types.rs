@@ -1,6 +1,7 @@
 use std::error::Error;
 
 use rusqlite::types::FromSql;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
 
 use super::{
     Cryptable,
@@ -8,11 +9,27 @@ use super::{
 };
 
 /// Encrypted type of String
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CryptString {
     pub data_crypt: Vec<u8>,
 }
 
+/// the ciphertext is serialized as hex string, it is never decrypted for this
+impl Serialize for CryptString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(&self.data_crypt))
+    }
+}
+
+impl<'de> Deserialize<'de> for CryptString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data_hex = String::deserialize(deserializer)?;
+        Ok(CryptString {
+            data_crypt: hex::decode(data_hex).map_err(de::Error::custom)?,
+        })
+    }
+}
+
 impl Cryptable<String> for CryptString {
     fn encrypt(data: &String, key: &[u8], provider: &CryptProviders) -> CryptString {
         Self {
@@ -37,6 +54,7 @@ impl FromSql for CryptString {
 }
 
 /// Encrypted type of i32
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct CryptI32 {
     pub data_crypt: Vec<u8>,
@@ -1,52 +1,291 @@
-use std::{any::type_name, collections::HashMap, error::Error, sync::Arc};
+use std::{any::type_name, collections::HashMap, error::Error, marker::PhantomData, sync::Arc};
 
+use async_trait::async_trait;
 use axum::{
-    Json, Router,
-    extract::{Query, State},
-    http::{HeaderMap, StatusCode},
-    routing::{delete, get, post},
+    Router,
+    extract::{FromRequestParts, State},
+    http::{HeaderMap, HeaderValue, StatusCode, request::Parts},
+    middleware::map_response,
+    routing::{delete, get, patch, post, put},
 };
+use backup::{handle_backup, handle_restore};
+use chrono::Utc;
+use csv::{handle_export_csv, handle_import_csv};
+use export::{handle_export, handle_import};
+use history::{HistoryAction, handle_get_history, handle_revert, load_object, record_history};
+use ics_import::{handle_import_ics, handle_import_ics_confirm};
+use limits::payload_too_large;
 use log::{error, info, warn};
 use objects::{
-    CourseDB, CourseSend, ExamDB, ExamSend, StudyGoalDB, StudyGoalSend, ToDoDB, ToDoSend, TopicDB,
-    TopicSend,
+    CourseDB, CoursePatch, CourseSend, ExamDB, ExamPatch, ExamSend, FlashcardDB, FlashcardSend,
+    GradeDB, GradeSend, NoteDB, NoteSend, SemesterDB, SemesterSend, StudyGoalDB, StudyGoalPatch,
+    StudyGoalSend, StudySessionDB, StudySessionSend, ToDoDB, ToDoSend, TopicDB, TopicPatch,
+    TopicSend, UserSettingsDB, WebhookSubscriptionDB, WebhookSubscriptionSend,
 };
+use retention::{handle_get_retention_log, soft_delete_cutoff};
+use search::handle_search;
 use serde::{Deserialize, Serialize};
+use settings::{handle_get_settings, handle_put_settings};
+use share::{handle_delete_share_link, handle_get_share_links, handle_new_share_link};
+use study_goal::{handle_get_study_goals, handle_set_study_goal_topics, remove_links_for};
+use sync::handle_sync;
+use webhook::{WebhookAction, enqueue_event, handle_get_deliveries};
 
 use crate::{
     AppState,
-    auth_handler::{decrypt_local_token_for, verify_token},
+    api_error::{ApiError, FieldError, Json, Query},
+    auth_handler::{
+        AuthError, AuthenticatedUser, decrypt_local_token_for, retry_after_unavailable,
+        verify_local_token_access,
+    },
     crypt::crypt_provider::CryptProviders,
     db::{
-        DBInterface,
-        sql_helper::{SQLGenerate, SQLValue},
+        DBInterface, DBObjIdent, DbError, Pagination,
+        sql_helper::{SQLGenerate, SQLValue, SortDirection, WhereOp, WhereParam},
     },
     db_param_map,
 };
 
+mod backup;
+pub mod course;
+mod csv;
+mod export;
+mod flashcard;
+mod grade;
+mod history;
+mod ics_import;
+pub mod limits;
 // allow dead code but only in objects
 #[allow(dead_code)]
 pub mod objects;
+pub mod retention;
+mod search;
+mod settings;
+pub mod share;
+mod stats;
+pub mod study_goal;
+mod study_session;
+mod sync;
+pub mod todo;
+mod topic;
+mod upcoming;
+pub mod webhook;
 
 /// This function defines the authentication routes for the application.
-pub fn data_router<DB: DBInterface + Send + Sync + 'static>(state: Arc<AppState<DB>>) -> Router {
+pub async fn data_router<DB: DBInterface + Send + Sync + 'static>(
+    state: Arc<AppState<DB>>,
+) -> Router {
     // create the db tables
-    state.db.create_table_for_type::<CourseDB>().unwrap();
-    state.db.create_table_for_type::<TopicDB>().unwrap();
-    state.db.create_table_for_type::<StudyGoalDB>().unwrap();
-    state.db.create_table_for_type::<ExamDB>().unwrap();
-    state.db.create_table_for_type::<ToDoDB>().unwrap();
+    state.db.create_table_for_type::<CourseDB>().await.unwrap();
+    state.db.create_table_for_type::<TopicDB>().await.unwrap();
+    state
+        .db
+        .create_table_for_type::<StudyGoalDB>()
+        .await
+        .unwrap();
+    state.db.create_table_for_type::<ExamDB>().await.unwrap();
+    state.db.create_table_for_type::<ToDoDB>().await.unwrap();
+    state
+        .db
+        .create_table_for_type::<WebhookSubscriptionDB>()
+        .await
+        .unwrap();
+    state.db.create_table_for_type::<NoteDB>().await.unwrap();
+    state
+        .db
+        .create_table_for_type::<StudySessionDB>()
+        .await
+        .unwrap();
+    state.db.create_table_for_type::<GradeDB>().await.unwrap();
+    state
+        .db
+        .create_table_for_type::<FlashcardDB>()
+        .await
+        .unwrap();
+    state
+        .db
+        .create_table_for_type::<SemesterDB>()
+        .await
+        .unwrap();
+    // data tables created before the objects had timestamps, sqlite only allows constant defaults for new columns
+    let created_at = format!(
+        "DATETIME NOT NULL DEFAULT '{}'",
+        Utc::now().naive_utc().format("%Y-%m-%d %H:%M:%S")
+    );
+    state
+        .db
+        .add_column_for_type::<CourseDB>("created_at", &created_at)
+        .await
+        .unwrap();
+    state
+        .db
+        .add_column_for_type::<TopicDB>("created_at", &created_at)
+        .await
+        .unwrap();
+    state
+        .db
+        .add_column_for_type::<StudyGoalDB>("created_at", &created_at)
+        .await
+        .unwrap();
+    state
+        .db
+        .add_column_for_type::<ExamDB>("created_at", &created_at)
+        .await
+        .unwrap();
+    state
+        .db
+        .add_column_for_type::<ToDoDB>("created_at", &created_at)
+        .await
+        .unwrap();
+    state
+        .db
+        .create_table_for_type::<UserSettingsDB>()
+        .await
+        .unwrap();
+
+    // adds the remaining nullable columns of older tables, NOT NULL columns without a default need a migration above
+    // an incomplete table would fail every insert, so the service does not start with one
+    state.db.verify_schema_for_type::<CourseDB>().await.unwrap();
+    state.db.verify_schema_for_type::<TopicDB>().await.unwrap();
+    state
+        .db
+        .verify_schema_for_type::<StudyGoalDB>()
+        .await
+        .unwrap();
+    state.db.verify_schema_for_type::<ExamDB>().await.unwrap();
+    state.db.verify_schema_for_type::<ToDoDB>().await.unwrap();
+    state
+        .db
+        .verify_schema_for_type::<WebhookSubscriptionDB>()
+        .await
+        .unwrap();
+    state.db.verify_schema_for_type::<NoteDB>().await.unwrap();
+    state
+        .db
+        .verify_schema_for_type::<StudySessionDB>()
+        .await
+        .unwrap();
+    state.db.verify_schema_for_type::<GradeDB>().await.unwrap();
+    state
+        .db
+        .verify_schema_for_type::<FlashcardDB>()
+        .await
+        .unwrap();
+    state
+        .db
+        .verify_schema_for_type::<SemesterDB>()
+        .await
+        .unwrap();
+    state
+        .db
+        .verify_schema_for_type::<UserSettingsDB>()
+        .await
+        .unwrap();
 
     // handles returning data
     let get_routes = Router::new()
-        .route("/course", get(handle_get::<CourseDB, CourseSend, DB>))
+        .route("/course", get(handle_get_courses::<DB>))
         .route("/topic", get(handle_get::<TopicDB, TopicSend, DB>))
+        .route("/study_goal", get(handle_get_study_goals::<DB>))
         .route(
-            "/study_goal",
-            get(handle_get::<StudyGoalDB, StudyGoalSend, DB>),
+            "/study_goal/overdue",
+            get(study_goal::handle_overdue_study_goals::<DB>),
         )
         .route("/exam", get(handle_get::<ExamDB, ExamSend, DB>))
-        .route("/todo", get(handle_get::<ToDoDB, ToDoSend, DB>));
+        .route("/todo", get(handle_get_todos::<DB>))
+        .route(
+            "/webhook",
+            get(handle_get::<WebhookSubscriptionDB, WebhookSubscriptionSend, DB>),
+        )
+        .route("/webhook/deliveries", get(handle_get_deliveries::<DB>))
+        .route("/note", get(handle_get::<NoteDB, NoteSend, DB>))
+        .route(
+            "/study_session",
+            get(handle_get::<StudySessionDB, StudySessionSend, DB>),
+        )
+        .route(
+            "/study_session/summary",
+            get(study_session::handle_summary::<DB>),
+        )
+        .route("/grade", get(handle_get::<GradeDB, GradeSend, DB>))
+        .route(
+            "/flashcard",
+            get(handle_get::<FlashcardDB, FlashcardSend, DB>),
+        )
+        .route("/flashcard/due", get(flashcard::handle_due::<DB>))
+        .route("/semester", get(handle_get::<SemesterDB, SemesterSend, DB>))
+        .route(
+            "/course/{id}/average",
+            get(grade::handle_course_average::<DB>),
+        )
+        .route("/course/count", get(handle_count_courses::<DB>))
+        .route("/course/progress", get(stats::handle_course_progress::<DB>)) // counts only
+        .route("/course/full", get(course::handle_get_course_tree::<DB>)) // courses with topics and exams
+        .route("/topic/count", get(handle_count::<TopicDB, DB>))
+        .route("/study_goal/count", get(handle_count::<StudyGoalDB, DB>))
+        .route("/exam/count", get(handle_count::<ExamDB, DB>))
+        .route("/todo/count", get(handle_count_todos::<DB>))
+        .route(
+            "/todo/completed-per-day",
+            get(todo::handle_completed_per_day::<DB>),
+        )
+        .route("/note/count", get(handle_count::<NoteDB, DB>))
+        .route(
+            "/study_session/count",
+            get(handle_count::<StudySessionDB, DB>),
+        )
+        .route("/grade/count", get(handle_count::<GradeDB, DB>))
+        .route("/flashcard/count", get(handle_count::<FlashcardDB, DB>))
+        .route("/semester/count", get(handle_count::<SemesterDB, DB>))
+        .route("/sync", get(handle_sync::<DB>))
+        .route("/backup", get(handle_backup::<DB>))
+        .route("/stats", get(stats::handle_stats::<DB>)) // counts only, nothing is decrypted
+        .route("/upcoming", get(upcoming::handle_upcoming::<DB>)) // exams, study goals and todos due soon
+        .route(
+            "/course/export.csv",
+            get(handle_export_csv::<CourseDB, CourseSend, DB>),
+        )
+        .route(
+            "/topic/export.csv",
+            get(handle_export_csv::<TopicDB, TopicSend, DB>),
+        )
+        .route(
+            "/todo/export.csv",
+            get(handle_export_csv::<ToDoDB, ToDoSend, DB>),
+        )
+        .route("/export", get(handle_export::<DB>)) // every object of the user decrypted, for downloads
+        .route("/export.json", get(handle_export::<DB>))
+        .route("/share-link", get(handle_get_share_links::<DB>))
+        .route("/search", get(handle_search::<DB>))
+        .route("/search/all", get(handle_search::<DB>)) // older path of the search
+        .route(
+            "/course/{id}/history",
+            get(handle_get_history::<CourseDB, CourseSend, DB>),
+        )
+        .route(
+            "/topic/{id}/history",
+            get(handle_get_history::<TopicDB, TopicSend, DB>),
+        )
+        .route(
+            "/study_goal/{id}/history",
+            get(handle_get_history::<StudyGoalDB, StudyGoalSend, DB>),
+        )
+        .route(
+            "/exam/{id}/history",
+            get(handle_get_history::<ExamDB, ExamSend, DB>),
+        )
+        .route(
+            "/todo/{id}/history",
+            get(handle_get_history::<ToDoDB, ToDoSend, DB>),
+        )
+        .route(
+            "/settings",
+            get(handle_get_settings::<DB>).put(handle_put_settings::<DB>),
+        )
+        .route(
+            "/settings/retention-log",
+            get(handle_get_retention_log::<DB>),
+        );
 
     // handles creating / editing data
     let new_routes = Router::new()
@@ -57,20 +296,129 @@ pub fn data_router<DB: DBInterface + Send + Sync + 'static>(state: Arc<AppState<
             post(handle_new::<StudyGoalDB, StudyGoalSend, DB>),
         )
         .route("/exam", post(handle_new::<ExamDB, ExamSend, DB>))
-        .route("/todo", post(handle_new::<ToDoDB, ToDoSend, DB>));
+        .route("/todo", post(todo::handle_save_todo::<DB>))
+        .route("/note", post(handle_new::<NoteDB, NoteSend, DB>))
+        .route(
+            "/study_session",
+            post(handle_new::<StudySessionDB, StudySessionSend, DB>),
+        )
+        .route("/grade", post(handle_new::<GradeDB, GradeSend, DB>))
+        .route(
+            "/flashcard",
+            post(handle_new::<FlashcardDB, FlashcardSend, DB>),
+        )
+        .route(
+            "/flashcard/{id}/review",
+            post(flashcard::handle_review::<DB>),
+        )
+        .route(
+            "/semester",
+            post(handle_new::<SemesterDB, SemesterSend, DB>),
+        )
+        .route(
+            "/course/bulk",
+            post(handle_new_bulk::<CourseDB, CourseSend, DB>),
+        )
+        .route(
+            "/topic/bulk",
+            post(handle_new_bulk::<TopicDB, TopicSend, DB>),
+        )
+        .route("/topic/reorder", post(topic::handle_reorder_topics::<DB>))
+        .route(
+            "/study_goal/bulk",
+            post(handle_new_bulk::<StudyGoalDB, StudyGoalSend, DB>),
+        )
+        .route("/exam/bulk", post(handle_new_bulk::<ExamDB, ExamSend, DB>))
+        .route("/todo/bulk", post(handle_new_bulk::<ToDoDB, ToDoSend, DB>))
+        .route("/todo/restore", post(todo::handle_restore_todo::<DB>))
+        .route(
+            "/webhook",
+            post(handle_new::<WebhookSubscriptionDB, WebhookSubscriptionSend, DB>),
+        )
+        .route("/course/{id}/share-link", post(handle_new_share_link::<DB>))
+        .route(
+            "/study_goal/{id}/topics",
+            put(handle_set_study_goal_topics::<DB>),
+        )
+        .route(
+            "/course/{id}/revert/{change_id}",
+            post(handle_revert::<CourseDB, DB>),
+        )
+        .route(
+            "/topic/{id}/revert/{change_id}",
+            post(handle_revert::<TopicDB, DB>),
+        )
+        .route(
+            "/study_goal/{id}/revert/{change_id}",
+            post(handle_revert::<StudyGoalDB, DB>),
+        )
+        .route(
+            "/exam/{id}/revert/{change_id}",
+            post(handle_revert::<ExamDB, DB>),
+        )
+        .route(
+            "/todo/{id}/revert/{change_id}",
+            post(handle_revert::<ToDoDB, DB>),
+        );
+
+    // handles partial edits
+    let patch_routes = Router::new()
+        .route("/course", patch(handle_patch::<CourseDB, CoursePatch, DB>))
+        .route("/topic", patch(handle_patch::<TopicDB, TopicPatch, DB>))
+        .route(
+            "/study_goal",
+            patch(handle_patch::<StudyGoalDB, StudyGoalPatch, DB>),
+        )
+        .route("/exam", patch(handle_patch::<ExamDB, ExamPatch, DB>))
+        .route("/todo", patch(todo::handle_patch_todo::<DB>));
 
     // handles deleting data
     let delete_routes = Router::new()
-        .route("/course", delete(handle_delete::<CourseDB, DB>))
+        .route("/course", delete(course::handle_delete_course::<DB>))
         .route("/topic", delete(handle_delete::<TopicDB, DB>))
         .route("/study_goal", delete(handle_delete::<StudyGoalDB, DB>))
         .route("/exam", delete(handle_delete::<ExamDB, DB>))
-        .route("/todo", delete(handle_delete::<ToDoDB, DB>));
+        .route("/todo", delete(todo::handle_delete_todo::<DB>))
+        .route("/note", delete(handle_delete::<NoteDB, DB>))
+        .route(
+            "/study_session",
+            delete(handle_delete::<StudySessionDB, DB>),
+        )
+        .route("/grade", delete(handle_delete::<GradeDB, DB>))
+        .route("/flashcard", delete(handle_delete::<FlashcardDB, DB>))
+        .route("/semester", delete(handle_delete::<SemesterDB, DB>))
+        .route(
+            "/webhook",
+            delete(handle_delete::<WebhookSubscriptionDB, DB>),
+        )
+        .route("/share-link", delete(handle_delete_share_link::<DB>));
+
+    // handles importing data from other sources
+    let import_routes = Router::new()
+        .route("/import/ics", post(handle_import_ics::<DB>))
+        .route("/import/ics/confirm", post(handle_import_ics_confirm::<DB>))
+        .route(
+            "/course/import",
+            post(handle_import_csv::<CourseDB, CourseSend, DB>),
+        )
+        .route(
+            "/topic/import",
+            post(handle_import_csv::<TopicDB, TopicSend, DB>),
+        )
+        .route(
+            "/todo/import",
+            post(handle_import_csv::<ToDoDB, ToDoSend, DB>),
+        )
+        .route("/restore", post(handle_restore::<DB>))
+        .route("/import.json", post(handle_import::<DB>)); // export of this or another account
 
     Router::new()
         .merge(get_routes)
         .merge(new_routes)
+        .merge(patch_routes)
         .merge(delete_routes)
+        .merge(import_routes)
+        .layer(map_response(retry_after_unavailable))
         .with_state(state)
 }
 // general structs
@@ -87,6 +435,95 @@ struct IDBody {
 pub trait Sendable {
     /// gets the id for the send Object
     fn get_id(&self) -> Option<i32>;
+    /// the object with the id set, e.g. the id of an edited entry
+    #[allow(dead_code)] // no route takes the id from the path yet
+    fn with_id(self, id: i32) -> Self;
+    /// the object without id, e.g. to create it as a new entry
+    fn strip_id(self) -> Self;
+}
+
+/// needs to be implemented for the send and patch types, checks that the ids they reference (e.g. course_id) belong to the user
+/// types without references keep the default, which references nothing
+#[async_trait]
+pub trait CheckReferences: Sync {
+    /// names of the fields whose id is no entry of the user, see missing_reference
+    async fn missing_references<DB: DBInterface + Send + Sync>(
+        &self,
+        _db: &DB,
+        _user_id: i32,
+    ) -> Result<Vec<&'static str>, DbError> {
+        Ok(Vec::new())
+    }
+
+    /// fields with values the database accepts but the server does not, e.g. grades outside of the configured scale
+    fn invalid_values(&self) -> Vec<FieldError> {
+        Vec::new()
+    }
+
+    /// fields referencing existing entries of the user that can not be referenced, e.g. a subtask as parent of a todo
+    async fn invalid_references<DB: DBInterface + Send + Sync>(
+        &self,
+        _db: &DB,
+        _user_id: i32,
+    ) -> Result<Vec<FieldError>, DbError> {
+        Ok(Vec::new())
+    }
+}
+
+/// field if the user has no entry of T with the id (unknown or of another user), unset ids are not checked
+pub async fn missing_reference<T: SQLGenerate, DB: DBInterface + Send + Sync>(
+    db: &DB,
+    user_id: i32,
+    field: &'static str,
+    id: Option<i32>,
+) -> Result<Option<&'static str>, DbError> {
+    let Some(id) = id else {
+        return Ok(None);
+    };
+    let count = db
+        .count_entries::<T>(db_param_map! { id: id, user_id: user_id })
+        .await?;
+    Ok((count == 0).then_some(field))
+}
+
+/// field errors of the references of the request that are no entries of the user and of its invalid values
+async fn reference_errors<R: CheckReferences, DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    request: &R,
+) -> Result<Vec<FieldError>, ApiError> {
+    let check_failed = |err: DbError| {
+        error!("Failed to check references! (user id: {})", user_id);
+        ApiError::from(err)
+    };
+    let fields = request
+        .missing_references(state.db.as_ref(), user_id)
+        .await
+        .map_err(check_failed)?;
+    // references to unknown entries are not checked any further
+    let invalid = if fields.is_empty() {
+        request
+            .invalid_references(state.db.as_ref(), user_id)
+            .await
+            .map_err(check_failed)?
+    } else {
+        Vec::new()
+    };
+    if !fields.is_empty() {
+        warn!(
+            "Request with unknown references {:?} rejected! (user id: {})",
+            fields, user_id
+        );
+    }
+    Ok(fields
+        .into_iter()
+        .map(|field| FieldError {
+            field: field.to_string(),
+            message: "does not exist".to_string(),
+        })
+        .chain(invalid)
+        .chain(request.invalid_values())
+        .collect())
 }
 
 /// needs to be implemented for every Send datatype, helps converting the send datatype into a parameter map, encrypts values
@@ -95,6 +532,18 @@ pub trait ToDB {
     fn to_param_vec(&self, key: &[u8], provider: &CryptProviders) -> Vec<(String, SQLValue)>;
 }
 
+/// needs to be implemented for the patch types, every field besides the id is optional
+pub trait ToPartialDB {
+    /// id of the entry to edit
+    fn get_id(&self) -> i32;
+    /// like to_param_vec, but only containing the fields that were sent, encrypts values
+    fn to_partial_param_vec(
+        &self,
+        key: &[u8],
+        provider: &CryptProviders,
+    ) -> Vec<(String, SQLValue)>;
+}
+
 /// needs to be implemented for send types
 pub trait FromDB<DBT: SQLGenerate> {
     /// should convert a dbt to a Send type, decrypting the crypt values
@@ -103,61 +552,367 @@ pub trait FromDB<DBT: SQLGenerate> {
         Self: Sized;
 }
 
+/// response to a failed insert or edit, a violated check names the field that is out of range
+fn write_failed<DBT: SQLGenerate>(err: DbError) -> ApiError {
+    let DbError::CheckViolation(constraint) = err else {
+        return err.into();
+    };
+    warn!("Check of {} violated: {}", type_name::<DBT>(), constraint);
+    // checks of fields are named after their column, table checks are reported by their expression
+    let error = if DBT::field_names().contains(&constraint.as_str()) {
+        FieldError {
+            field: constraint,
+            message: "is out of range".to_string(),
+        }
+    } else {
+        FieldError {
+            field: "body".to_string(),
+            message: format!("violates {}", constraint),
+        }
+    };
+    ApiError::unprocessable(vec![error])
+}
+
 /// handler for get requests, retrieving objects from the db
 pub async fn handle_get<DBT: SQLGenerate, ST: FromDB<DBT>, DB: DBInterface + Send + Sync>(
-    headers: HeaderMap,
+    key: LocalKey<DBT>,
     State(state): State<Arc<AppState<DB>>>,
-    Query(params_query): Query<HashMap<String, String>>,
+    Query(mut params_query): Query<HashMap<String, String>>,
     //Json(request): Json<RT>,
-) -> Result<Json<Vec<ST>>, StatusCode> {
+) -> Result<(HeaderMap, Json<Vec<ST>>), ApiError> {
     info!("{} read requested!", type_name::<DBT>());
 
-    let auth_header = headers.get("authorization");
-    // verify that the token is valid
-    let verified_token = verify_token(auth_header, state.clone());
-    if verified_token.is_err() {
-        warn!("Authentication failure, invalid token!");
-        // invalid token, authentication failure
-        return Err(StatusCode::UNAUTHORIZED);
+    let pagination = take_pagination(&mut params_query)?;
+    let order = take_order::<DBT>(&mut params_query)?;
+    // add remaining parameters from query to select statement
+    let params = filter_params::<DBT>(params_query)?;
+
+    // the total is only needed to render pagers
+    let mut headers = HeaderMap::new();
+    if pagination.is_some() {
+        let total = state
+            .db
+            .count_entries::<DBT>(user_where_params(key.user.user_id, params.clone()))
+            .await
+            .map_err(|err| {
+                error!("Error while counting {} entries in DB!", type_name::<DBT>());
+                StatusCode::from(err)
+            })?;
+        headers.insert(TOTAL_COUNT_HEADER, HeaderValue::from(total));
+    }
+
+    let entries_send = get_entries::<DBT, ST, DB>(&state, &key, params, &order, pagination).await?;
+
+    info!("{} read successful, building response!", type_name::<DBT>());
+    Ok((headers, Json(entries_send)))
+}
+
+/// handler for reading courses, like handle_get but archived courses are left out by default
+async fn handle_get_courses<DB: DBInterface + Send + Sync>(
+    key: LocalKey<CourseDB>,
+    state: State<Arc<AppState<DB>>>,
+    Query(params_query): Query<HashMap<String, String>>,
+) -> Result<(HeaderMap, Json<Vec<CourseSend>>), ApiError> {
+    let params_query = course::archived_filter(params_query)?;
+    handle_get::<CourseDB, CourseSend, DB>(key, state, Query(params_query)).await
+}
+
+/// handler for counting courses, takes the same filters as handle_get_courses
+async fn handle_count_courses<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    state: State<Arc<AppState<DB>>>,
+    Query(params_query): Query<HashMap<String, String>>,
+) -> Result<Json<CountResponse>, ApiError> {
+    let params_query = course::archived_filter(params_query)?;
+    handle_count::<CourseDB, DB>(user, state, Query(params_query)).await
+}
+
+/// handler for reading todos, like handle_get with due_before and due_after as names for the deadline range
+async fn handle_get_todos<DB: DBInterface + Send + Sync>(
+    key: LocalKey<ToDoDB>,
+    state: State<Arc<AppState<DB>>>,
+    Query(params_query): Query<HashMap<String, String>>,
+) -> Result<(HeaderMap, Json<Vec<ToDoSend>>), ApiError> {
+    handle_get::<ToDoDB, ToDoSend, DB>(key, state, Query(due_as_deadline(params_query))).await
+}
+
+/// handler for counting todos, takes the same filters as handle_get_todos
+async fn handle_count_todos<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    state: State<Arc<AppState<DB>>>,
+    Query(params_query): Query<HashMap<String, String>>,
+) -> Result<Json<CountResponse>, ApiError> {
+    handle_count::<ToDoDB, DB>(user, state, Query(due_as_deadline(params_query))).await
+}
+
+/// renames due_before and due_after of the todo list to the deadline filters
+fn due_as_deadline(query: HashMap<String, String>) -> HashMap<String, String> {
+    query
+        .into_iter()
+        .map(|(param, value)| match param.as_str() {
+            "due_before" => ("deadline_before".to_string(), value),
+            "due_after" => ("deadline_after".to_string(), value),
+            _ => (param, value),
+        })
+        .collect()
+}
+
+/// response of a count request
+#[derive(Deserialize, Serialize, Debug)]
+struct CountResponse {
+    count: i64,
+}
+
+/// handler for counting objects, takes the same filters as handle_get
+/// nothing is decrypted, so the local token is only checked for access (scoped tokens) but not decrypted
+async fn handle_count<DBT: SQLGenerate, DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Query(params_query): Query<HashMap<String, String>>,
+) -> Result<Json<CountResponse>, ApiError> {
+    info!("{} count requested!", type_name::<DBT>());
+
+    verify_local_token_access(
+        user.user_id,
+        &DBT::get_db_ident(),
+        user.remote_token_id,
+        &state,
+    )
+    .await
+    .map_err(|err| decrypt_failed_status(err, &DBT::get_db_ident(), user.remote_token_id))?;
+
+    let params = filter_params::<DBT>(params_query)?;
+    let count = state
+        .db
+        .count_entries::<DBT>(user_where_params(user.user_id, params))
+        .await
+        .map_err(|err| {
+            error!("Error while counting {} entries in DB!", type_name::<DBT>());
+            StatusCode::from(err)
+        })?;
+
+    Ok(Json(CountResponse { count }))
+}
+
+/// upper bound of the page size of GET requests
+const MAX_PAGE_SIZE: u32 = 500;
+/// number of entries matching a paginated GET request (without limit and offset)
+const TOTAL_COUNT_HEADER: &str = "x-total-count";
+
+/// removes limit and offset from the query parameters, a missing limit defaults to MAX_PAGE_SIZE
+/// bad request if they are no numbers or the limit is not between 1 and MAX_PAGE_SIZE
+fn take_pagination(query: &mut HashMap<String, String>) -> Result<Option<Pagination>, StatusCode> {
+    let mut take = |name: &str| {
+        query
+            .remove(name)
+            .map(|value| value.parse::<u32>().map_err(|_| StatusCode::BAD_REQUEST))
+            .transpose()
+    };
+    let (limit, offset) = (take("limit")?, take("offset")?);
+    if limit.is_none() && offset.is_none() {
+        return Ok(None);
+    }
+
+    let limit = limit.unwrap_or(MAX_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(Some(Pagination {
+        limit,
+        offset: offset.unwrap_or(0),
+    }))
+}
+
+/// converts the query parameters into where params with the type of their column
+/// field_before and field_after compare with less and greater than, every other parameter is checked on equality
+/// or, with comma separated values, on being one of them
+/// nullable fields can be checked with null and !null
+/// the names end up in the sql, so only columns of the type are accepted, bad request for others and invalid values
+pub fn filter_params<DBT: SQLGenerate>(
+    query: HashMap<String, String>,
+) -> Result<Vec<WhereParam>, StatusCode> {
+    let mut params = Vec::new();
+    for (param, value) in query {
+        let (field, op) = if DBT::field_names().contains(&param.as_str()) {
+            (param.as_str(), WhereOp::Eq)
+        } else if let Some(field) = param.strip_suffix("_before") {
+            (field, WhereOp::Lt)
+        } else if let Some(field) = param.strip_suffix("_after") {
+            (field, WhereOp::Gt)
+        } else {
+            (param.as_str(), WhereOp::Eq)
+        };
+
+        // null and !null select objects where a nullable field is (not) set
+        let (op, value) = match value.as_str() {
+            "!null" if op == WhereOp::Eq => (WhereOp::Ne, "null".to_string()),
+            _ => (op, value),
+        };
+
+        // comma separated values select every object matching one of them
+        let where_param = if op == WhereOp::Eq && value.contains(',') {
+            value
+                .split(',')
+                .map(|value| DBT::parse_filter_value(field, value))
+                .collect::<Option<Vec<_>>>()
+                .map(|values| WhereParam::is_in(field, values))
+        } else {
+            DBT::parse_filter_value(field, &value).map(|value| WhereParam::new(field, op, value))
+        };
+        let Some(where_param) = where_param else {
+            info!(
+                "{} read with unknown filter field or invalid value for {}.",
+                type_name::<DBT>(),
+                param
+            );
+            return Err(StatusCode::BAD_REQUEST);
+        };
+        params.push(where_param);
+    }
+
+    Ok(params)
+}
+
+/// removes sort and dir (asc or desc, default asc) from the query parameters
+/// bad request for unknown or encrypted fields and other directions
+fn take_order<DBT: SQLGenerate>(
+    query: &mut HashMap<String, String>,
+) -> Result<Vec<(String, SortDirection)>, StatusCode> {
+    let direction = match query.remove("dir").as_deref() {
+        None | Some("asc") => SortDirection::Asc,
+        Some("desc") => SortDirection::Desc,
+        Some(_) => return Err(StatusCode::BAD_REQUEST),
+    };
+    let Some(field) = query.remove("sort") else {
+        return Ok(Vec::new());
+    };
+
+    if !DBT::sortable_fields().contains(&field.as_str()) {
+        info!(
+            "{} read sorted by unknown field {}.",
+            type_name::<DBT>(),
+            field
+        );
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(vec![(field, direction)])
+}
+
+/// where params of a select, always restricted to the user
+fn user_where_params(user_id: i32, params: Vec<WhereParam>) -> Vec<WhereParam> {
+    let mut where_params = db_param_map! { user_id: user_id };
+    where_params.extend(params);
+    where_params
+}
+
+/// extractor for handlers working on objects of the type DBT, verifies the token and decrypts the local token of the type
+/// rejects with unauthorized for invalid tokens, forbidden if the token is scoped to other types and internal server error if decryption fails
+pub struct LocalKey<DBT> {
+    pub user: AuthenticatedUser,
+    pub local_token: String,
+    // fn pointer, so the key is Send and Sync independent of DBT
+    object_type: PhantomData<fn() -> DBT>,
+}
+
+impl<DBT: SQLGenerate> LocalKey<DBT> {
+    /// decrypts the local token of the type for an already verified user
+    pub async fn new<DB: DBInterface + Send + Sync>(
+        state: &Arc<AppState<DB>>,
+        user: AuthenticatedUser,
+    ) -> Result<Self, StatusCode> {
+        let local_token = local_token_for::<DBT, DB>(
+            state,
+            user.user_id,
+            user.remote_token_id,
+            &user.remote_token,
+        )
+        .await?;
+
+        Ok(Self {
+            user,
+            local_token,
+            object_type: PhantomData,
+        })
+    }
+}
+
+impl<DBT: SQLGenerate, DB: DBInterface + Send + Sync> FromRequestParts<Arc<AppState<DB>>>
+    for LocalKey<DBT>
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState<DB>>,
+    ) -> Result<Self, Self::Rejection> {
+        let user = AuthenticatedUser::from_request_parts(parts, state).await?;
+        Ok(Self::new(state, user).await?)
     }
-    let (user_id, remote_token_id, remote_token) = verified_token.unwrap();
+}
 
-    // decrypt the corresponding local token
-    let local_token = decrypt_local_token_for(
+/// decrypts the local token of the type DBT with the remote token
+async fn local_token_for<DBT: SQLGenerate, DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    user_id: i32,
+    remote_token_id: i32,
+    remote_token: &str,
+) -> Result<String, StatusCode> {
+    decrypt_local_token_for(
         user_id,
         &DBT::get_db_ident(),
         remote_token_id,
-        &remote_token,
+        remote_token,
         state.clone(),
-    );
-    if local_token.is_err() {
+    )
+    .await
+    .map_err(|err| decrypt_failed_status(err, &DBT::get_db_ident(), remote_token_id))
+}
+
+/// status for a local token that could not be decrypted, forbidden if the remote token is scoped to other object types
+pub fn decrypt_failed_status(
+    err: AuthError,
+    used_for: &DBObjIdent,
+    remote_token_id: i32,
+) -> StatusCode {
+    if err == AuthError::NotFound {
+        warn!(
+            "Remote token (id: {}) has no access to {}!",
+            remote_token_id, used_for.db_identifier
+        );
+    } else {
         error!(
             "Failed to decrypt local token with remote token (id: {})",
             remote_token_id
         );
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
     }
-    let local_token = local_token.unwrap();
-
-    // retrieve db data
-    let mut params: Vec<(String, String)> = vec![("user_id".to_string(), user_id.to_string())];
+    err.into()
+}
 
-    // add parameters from query to select statement
-    params_query.iter().for_each(|e| {
-        params.push((e.0.clone(), e.1.clone()));
-    });
+/// loads all entries of the user matching the params (checked on equality) and decrypts them into the send type
+pub async fn get_entries<DBT: SQLGenerate, ST: FromDB<DBT>, DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    key: &LocalKey<DBT>,
+    params: Vec<WhereParam>,
+    order: &[(String, SortDirection)],
+    pagination: Option<Pagination>,
+) -> Result<Vec<ST>, StatusCode> {
+    let (user_id, local_token) = (key.user.user_id, &key.local_token);
 
-    let entries = state.db.select_entries::<DBT>(params);
-    if entries.is_err() {
-        error!(
-            "Error while querying DB! Tried to get {} information.",
-            type_name::<DBT>()
-        );
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
-    }
+    // retrieve db data, always restricted to the user
+    let where_params = user_where_params(user_id, params);
+    let entries = state
+        .db
+        .select_entries::<DBT>(where_params, order, pagination)
+        .await
+        .map_err(|err| {
+            error!(
+                "Error while querying DB! Tried to get {} information.",
+                type_name::<DBT>()
+            );
+            StatusCode::from(err)
+        })?;
 
-    let entries_send: Result<Vec<ST>, StatusCode> = entries
-        .unwrap()
+    entries
         .iter()
         .map(|entry| {
             ST::from_dbt(entry, local_token.as_bytes(), &state.crypt_provider).map_err(|_| {
@@ -165,134 +920,1680 @@ pub async fn handle_get<DBT: SQLGenerate, ST: FromDB<DBT>, DB: DBInterface + Sen
                 StatusCode::INTERNAL_SERVER_ERROR
             })
         })
-        .collect();
-    let entries_send = entries_send?;
-
-    info!("{} read successful, building response!", type_name::<DBT>());
-    Ok(Json(entries_send))
+        .collect()
 }
 
 /// handler for creating new objects
-async fn handle_new<DBT: SQLGenerate, ST: Sendable + ToDB, DB: DBInterface + Send + Sync>(
-    headers: HeaderMap,
+async fn handle_new<
+    DBT: SQLGenerate + Serialize,
+    ST: Sendable + ToDB + CheckReferences + Serialize,
+    DB: DBInterface + Send + Sync,
+>(
+    key: LocalKey<DBT>,
     State(state): State<Arc<AppState<DB>>>,
     Json(request): Json<ST>,
-) -> Result<Json<IDBody>, StatusCode> {
+) -> Result<Json<IDBody>, ApiError> {
     info!("{} creation / edit requested!", type_name::<DBT>());
 
-    let auth_header = headers.get("authorization");
+    state
+        .payload_limits
+        .check(&request)
+        .map_err(payload_too_large)?;
+    let id = save_entry::<DBT, ST, DB>(&state, &key, request).await?;
 
-    // verify that the token is valid
-    let verified_token = verify_token(auth_header, state.clone());
-    if verified_token.is_err() {
-        warn!("Authentication failure, invalid token!");
-        // invalid token, authentication failure
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    let (user_id, remote_token_id, remote_token) = verified_token.unwrap();
+    Ok(Json(IDBody { id }))
+}
 
-    // decrypt the corresponding local token
-    let local_token = decrypt_local_token_for(
-        user_id,
-        &DBT::get_db_ident(),
-        remote_token_id,
-        &remote_token,
-        state.clone(),
+/// upper bound of the number of objects created by one bulk request
+const MAX_BULK_SIZE: usize = 500;
+
+/// handler for creating many objects at once, either all of them are created or none
+/// returns the ids in the order of the request
+async fn handle_new_bulk<
+    DBT: SQLGenerate + Serialize,
+    ST: Sendable + ToDB + CheckReferences + Serialize,
+    DB: DBInterface + Send + Sync,
+>(
+    key: LocalKey<DBT>,
+    State(state): State<Arc<AppState<DB>>>,
+    Json(request): Json<Vec<ST>>,
+) -> Result<Json<Vec<i32>>, ApiError> {
+    info!(
+        "Bulk creation of {} {} requested!",
+        request.len(),
+        type_name::<DBT>()
     );
-    if local_token.is_err() {
+    let user_id = key.user.user_id;
+
+    // bulk requests only create, edits have to be sent one by one
+    if request.len() > MAX_BULK_SIZE || request.iter().any(|entry| entry.get_id().is_some()) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+    state
+        .payload_limits
+        .check_all(&request)
+        .map_err(payload_too_large)?;
+    // like the limits, errors are named with the index of their object
+    let mut errors = Vec::new();
+    for (i, entry) in request.iter().enumerate() {
+        errors.extend(
+            reference_errors(&state, user_id, entry)
+                .await?
+                .into_iter()
+                .map(|error| FieldError {
+                    field: format!("{}.{}", i, error.field),
+                    message: error.message,
+                }),
+        );
+    }
+    if !errors.is_empty() {
+        return Err(ApiError::unprocessable(errors));
+    }
+
+    let ids = insert_entries::<DBT, ST, DB>(&state, &key, &request).await?;
+    info!("Bulk creation of {} successful.", type_name::<DBT>());
+
+    Ok(Json(ids))
+}
+
+/// inserts the entries for the user in one transaction, returns their ids in order
+/// the entries have to be checked (references, limits) before
+async fn insert_entries<DBT: SQLGenerate, ST: ToDB, DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    key: &LocalKey<DBT>,
+    entries: &[ST],
+) -> Result<Vec<i32>, ApiError> {
+    let (user_id, local_token) = (key.user.user_id, &key.local_token);
+    let rows = entries
+        .iter()
+        .map(|entry| {
+            let mut params = db_param_map! { user_id: user_id };
+            params.extend(entry.to_param_vec(local_token.as_bytes(), &state.crypt_provider));
+            params
+        })
+        .collect();
+
+    let ids = state.db.new_entries::<DBT>(rows).await.map_err(|err| {
         error!(
-            "Failed to decrypt local token with remote token (id: {})",
-            remote_token_id
+            "Failed to insert new {} into db! (user id: {})",
+            type_name::<DBT>(),
+            user_id
         );
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        write_failed::<DBT>(err)
+    })?;
+
+    for id in &ids {
+        record_change(
+            state,
+            user_id,
+            &DBT::get_db_ident(),
+            WebhookAction::Create,
+            *id,
+        )
+        .await;
+    }
+    Ok(ids)
+}
+
+/// creates (id is None) or edits (id is Some) an entry of the user, returns the id of the entry
+/// fails with the response of handle_new, e.g. the fields violating a check or referencing ids of other users
+pub async fn save_entry<
+    DBT: SQLGenerate + Serialize,
+    ST: Sendable + ToDB + CheckReferences,
+    DB: DBInterface + Send + Sync,
+>(
+    state: &Arc<AppState<DB>>,
+    key: &LocalKey<DBT>,
+    request: ST,
+) -> Result<i32, ApiError> {
+    let errors = reference_errors(state, key.user.user_id, &request).await?;
+    if !errors.is_empty() {
+        return Err(ApiError::unprocessable(errors));
     }
-    let local_token = local_token.unwrap();
 
     // id is null => means we want to create
     // not null   => means we want to edit
-    if request.get_id().is_none() {
-        info!("Authentication successful, creation requested.");
+    match request.get_id() {
+        None => create_entry::<DBT, ST, DB>(state, key, request).await,
+        Some(entry_id) => edit_entry::<DBT, ST, DB>(state, key, entry_id, request.strip_id()).await,
+    }
+}
 
-        // insert user id, as this is not included in the send data type
-        let mut params = db_param_map! { user_id: user_id };
-        // extend it with the parameters from the send type (except for user_id)
-        params.extend(request.to_param_vec(local_token.as_bytes(), &state.crypt_provider));
+/// inserts the request as a new entry of the user, returns its id
+async fn create_entry<
+    DBT: SQLGenerate + Serialize,
+    ST: Sendable + ToDB,
+    DB: DBInterface + Send + Sync,
+>(
+    state: &Arc<AppState<DB>>,
+    key: &LocalKey<DBT>,
+    request: ST,
+) -> Result<i32, ApiError> {
+    let (user_id, local_token) = (key.user.user_id, &key.local_token);
+    info!("Authentication successful, creation requested.");
 
-        let id = state.db.new_entry::<DBT>(params);
-        if id.is_err() {
-            error!(
-                "Failed to insert new {} into db! (user id: {})",
-                type_name::<DBT>(),
-                user_id
-            );
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-        info!("{} creation successful.", type_name::<DBT>());
+    // insert user id, as this is not included in the send data type
+    let mut params = db_param_map! { user_id: user_id };
+    // extend it with the parameters from the send type (except for user_id)
+    params.extend(request.to_param_vec(local_token.as_bytes(), &state.crypt_provider));
 
-        Ok(Json(IDBody { id: id.unwrap() }))
-    } else {
-        info!("Authentication successful, edit requested.");
-        // id is not none
-        let entry_id = request.get_id().unwrap();
-
-        // prepare where params (same for every type)
-        let where_params = db_param_map! {
-            id: entry_id,
-            user_id: user_id,
-        };
+    let id = state.db.new_entry::<DBT>(params).await.map_err(|err| {
+        error!(
+            "Failed to insert new {} into db! (user id: {})",
+            type_name::<DBT>(),
+            user_id
+        );
+        write_failed::<DBT>(err)
+    })?;
+    info!("{} creation successful.", type_name::<DBT>());
+    record_change(
+        state,
+        user_id,
+        &DBT::get_db_ident(),
+        WebhookAction::Create,
+        id,
+    )
+    .await;
 
-        // always update every field, retrieved from the request type
-        let params = request.to_param_vec(local_token.as_bytes(), &state.crypt_provider);
+    Ok(id)
+}
 
-        let result = state.db.update_entry::<DBT>(params, where_params);
-        if result.is_err() {
+/// overwrites every field of the entry with the id by the request, the id of the request is not used
+async fn edit_entry<
+    DBT: SQLGenerate + Serialize,
+    ST: Sendable + ToDB,
+    DB: DBInterface + Send + Sync,
+>(
+    state: &Arc<AppState<DB>>,
+    key: &LocalKey<DBT>,
+    entry_id: i32,
+    request: ST,
+) -> Result<i32, ApiError> {
+    info!("Authentication successful, edit requested.");
+
+    // always update every field, retrieved from the request type
+    let params = request.to_param_vec(key.local_token.as_bytes(), &state.crypt_provider);
+    update_fields::<DBT, DB>(state, key, entry_id, params).await
+}
+
+/// updates the fields of params of the entry with the id, if it belongs to the user
+/// the previous version is kept in the history, returns the id
+async fn update_fields<DBT: SQLGenerate + Serialize, DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    key: &LocalKey<DBT>,
+    entry_id: i32,
+    params: Vec<(String, SQLValue)>,
+) -> Result<i32, ApiError> {
+    let (user_id, local_token) = (key.user.user_id, &key.local_token);
+
+    // prepare where params (same for every type)
+    let where_params = db_param_map! {
+        id: entry_id,
+        user_id: user_id,
+    };
+
+    // keep the previous version, so the edit can be undone
+    let previous = load_object::<DBT, DB>(state, user_id, entry_id).await?;
+
+    match state.db.update_entry::<DBT>(params, where_params).await {
+        Ok(0) => {
+            // the entry does not exist or belongs to someone else
+            warn!(
+                "Edit of unknown {}! (user id: {}, id: {})",
+                type_name::<DBT>(),
+                user_id,
+                entry_id
+            );
+            return Err(StatusCode::NOT_FOUND.into());
+        }
+        Ok(_) => {}
+        Err(err) => {
             error!(
                 "Failed to edit {} in DB! {} id: {}",
                 type_name::<DBT>(),
                 type_name::<DBT>(),
                 entry_id
             );
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(write_failed::<DBT>(err));
         }
+    }
 
-        info!("{} edit successful.", type_name::<DBT>());
-        // respond with the id that we already got from client, but hey we need to send something
-        Ok(Json(IDBody { id: entry_id }))
+    // only edits which happened are kept, a restore would replay them otherwise
+    if let Some(previous) = previous {
+        record_history(
+            state,
+            user_id,
+            local_token,
+            HistoryAction::Edit,
+            entry_id,
+            &previous,
+        )
+        .await?;
     }
+
+    info!("{} edit successful.", type_name::<DBT>());
+    record_change(
+        state,
+        user_id,
+        &DBT::get_db_ident(),
+        WebhookAction::Edit,
+        entry_id,
+    )
+    .await;
+    // respond with the id that we already got from client, but hey we need to send something
+    Ok(entry_id)
+}
+
+/// handler for partial edits, only the fields that are sent are changed
+/// fails with bad request if no field besides the id is sent
+async fn handle_patch<
+    DBT: SQLGenerate + Serialize,
+    PT: ToPartialDB + CheckReferences + Serialize,
+    DB: DBInterface + Send + Sync,
+>(
+    key: LocalKey<DBT>,
+    State(state): State<Arc<AppState<DB>>>,
+    Json(request): Json<PT>,
+) -> Result<Json<IDBody>, ApiError> {
+    info!("{} partial edit requested!", type_name::<DBT>());
+
+    let id = patch_entry::<DBT, PT, DB>(&state, &key, request).await?;
+    Ok(Json(IDBody { id }))
+}
+
+/// changes the fields sent in the request of the entry of the user, returns its id
+async fn patch_entry<
+    DBT: SQLGenerate + Serialize,
+    PT: ToPartialDB + CheckReferences + Serialize,
+    DB: DBInterface + Send + Sync,
+>(
+    state: &Arc<AppState<DB>>,
+    key: &LocalKey<DBT>,
+    request: PT,
+) -> Result<i32, ApiError> {
+    state
+        .payload_limits
+        .check(&request)
+        .map_err(payload_too_large)?;
+    let params = request.to_partial_param_vec(key.local_token.as_bytes(), &state.crypt_provider);
+    if params.is_empty() {
+        warn!("Partial edit of {} without fields!", type_name::<DBT>());
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+    let errors = reference_errors(state, key.user.user_id, &request).await?;
+    if !errors.is_empty() {
+        return Err(ApiError::unprocessable(errors));
+    }
+
+    update_fields::<DBT, DB>(state, key, request.get_id(), params).await
 }
 
 /// handles delete request for a type T which has to implement SQLGenerate
 /// T also has to have the id and user_id field for this to work, as those two are used to strictly identify an element in the DB
-async fn handle_delete<DBT: SQLGenerate, DB: DBInterface + Send + Sync>(
-    headers: HeaderMap,
+async fn handle_delete<DBT: SQLGenerate + Serialize, DB: DBInterface + Send + Sync>(
+    key: LocalKey<DBT>,
     State(state): State<Arc<AppState<DB>>>,
     Json(request): Json<IDBody>,
-) -> Result<Json<IDBody>, StatusCode> {
+) -> Result<Json<IDBody>, ApiError> {
     info!("{} deletion requested!", type_name::<DBT>());
 
-    let auth_header = headers.get("authorization");
+    remove_entry::<DBT, DB>(&state, &key, request.id).await?;
+
+    Ok(Json(IDBody { id: request.id }))
+}
+
+/// deletes the entry with the id, if it belongs to the user
+pub async fn remove_entry<DBT: SQLGenerate + Serialize, DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    key: &LocalKey<DBT>,
+    id: i32,
+) -> Result<(), StatusCode> {
+    // the local token is only needed to encrypt the history entry
+    let (user_id, local_token) = (key.user.user_id, &key.local_token);
+    let Some(previous) = load_object::<DBT, DB>(state, user_id, id).await? else {
+        // the entry does not exist or belongs to someone else
+        warn!(
+            "Deletion of unknown {}! (user id: {}, id: {})",
+            type_name::<DBT>(),
+            user_id,
+            id
+        );
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    // all is good, delete the provided entry (types with soft delete are moved into the trash)
+    let where_params = db_param_map! { id: id, user_id: user_id };
+    let result = if DBT::soft_delete() {
+        state.db.soft_delete_entry::<DBT>(where_params).await
+    } else {
+        state.db.delete_entry::<DBT>(where_params).await
+    };
 
-    // verify that the token is valid
-    let verified_token = verify_token(auth_header, state.clone());
-    if verified_token.is_err() {
-        warn!("Authentication failure, invalid token!");
-        // invalid token, authentication failure
-        return Err(StatusCode::UNAUTHORIZED);
+    match result {
+        // deleted concurrently since it was loaded
+        Ok(0) => return Err(StatusCode::NOT_FOUND),
+        Ok(_) => {}
+        Err(err) => {
+            // this happens if the sql query is formatted wrong (which should never happen) or the db is overloaded
+            error!("Failed to delete entry in DB!");
+            return Err(StatusCode::from(err));
+        }
     }
-    let (user_id, _, _) = verified_token.unwrap();
-    // we do not need a local token, because we do not need to decrypt or encrypt anything
+    record_history(
+        state,
+        user_id,
+        local_token,
+        HistoryAction::Delete,
+        id,
+        &previous,
+    )
+    .await?;
+
+    info!("{} deletion successful.", type_name::<DBT>());
+    record_change(
+        state,
+        user_id,
+        &DBT::get_db_ident(),
+        WebhookAction::Delete,
+        id,
+    )
+    .await;
+    // links between study goals and topics are not part of the object tables
+    remove_links_for::<DBT, DB>(state, user_id, id).await?;
+
+    Ok(())
+}
 
-    // all is good, delete the provided entry
+/// restores the soft deleted entry with the id, if it belongs to the user and was deleted within the grace period
+async fn restore_deleted<DBT: SQLGenerate, DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    id: i32,
+) -> Result<(), StatusCode> {
+    let cutoff = soft_delete_cutoff(Utc::now().naive_utc()).ok_or(StatusCode::NOT_FOUND)?;
     let result = state
         .db
-        .delete_entry::<DBT>(db_param_map! { id: request.id, user_id: user_id});
+        .restore_entry::<DBT>(db_param_map! { id: id, user_id: user_id }, &cutoff)
+        .await;
 
-    if result.is_err() {
-        // this happens if the sql query is formatted wrong (which should never happen)
-        error!("Failed to delete entry in DB!");
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    match result {
+        Ok(0) => {
+            // not deleted, purged already or belongs to someone else
+            warn!(
+                "Restore of unknown {}! (user id: {}, id: {})",
+                type_name::<DBT>(),
+                user_id,
+                id
+            );
+            return Err(StatusCode::NOT_FOUND);
+        }
+        Ok(_) => {}
+        Err(err) => {
+            error!("Failed to restore entry in DB!");
+            return Err(StatusCode::from(err));
+        }
     }
 
-    info!("{} deletion successful.", type_name::<DBT>());
-    Ok(Json(IDBody { id: request.id }))
+    info!("{} restore successful.", type_name::<DBT>());
+    // for clients the entry appears again
+    record_change(
+        state,
+        user_id,
+        &DBT::get_db_ident(),
+        WebhookAction::Create,
+        id,
+    )
+    .await;
+
+    Ok(())
+}
+
+/// deletes every data object of the user, used when the account is deleted (in its transaction)
+/// no history, sync changes or webhook events are recorded, they are deleted together with the user
+pub async fn delete_user_data<DB: DBInterface + Send + Sync>(
+    db: &DB,
+    user_id: i32,
+) -> Result<(), DbError> {
+    // deliveries are not bound to the user directly, only through the subscription
+    for subscription in db
+        .select_entries::<WebhookSubscriptionDB>(db_param_map! { user_id: user_id }, &[], None)
+        .await?
+    {
+        db.del_webhook_deliveries_by_subscription(subscription.id)
+            .await?;
+    }
+
+    db.delete_entry::<WebhookSubscriptionDB>(db_param_map! { user_id: user_id })
+        .await?;
+    db.delete_entry::<ToDoDB>(db_param_map! { user_id: user_id })
+        .await?;
+    // notes reference courses and topics, study sessions and flashcards topics and grades exams
+    db.delete_entry::<NoteDB>(db_param_map! { user_id: user_id })
+        .await?;
+    db.delete_entry::<StudySessionDB>(db_param_map! { user_id: user_id })
+        .await?;
+    db.delete_entry::<GradeDB>(db_param_map! { user_id: user_id })
+        .await?;
+    db.delete_entry::<FlashcardDB>(db_param_map! { user_id: user_id })
+        .await?;
+    db.delete_entry::<ExamDB>(db_param_map! { user_id: user_id })
+        .await?;
+    db.delete_entry::<StudyGoalDB>(db_param_map! { user_id: user_id })
+        .await?;
+    db.delete_entry::<TopicDB>(db_param_map! { user_id: user_id })
+        .await?;
+    db.delete_entry::<CourseDB>(db_param_map! { user_id: user_id })
+        .await?;
+    db.delete_entry::<SemesterDB>(db_param_map! { user_id: user_id })
+        .await?;
+    db.delete_entry::<UserSettingsDB>(db_param_map! { user_id: user_id })
+        .await?;
+
+    Ok(())
+}
+
+/// deletes every object of the type with the ident of the user, used when its local token is lost
+/// references to the objects are handled like on single deletes, nothing is recorded
+pub async fn delete_user_data_of<DB: DBInterface + Send + Sync>(
+    db: &DB,
+    user_id: i32,
+    ident: &DBObjIdent,
+) -> Result<(), DbError> {
+    let ident = ident.db_identifier.as_str();
+    let params = db_param_map! { user_id: user_id };
+    if ident == WebhookSubscriptionDB::get_db_ident().db_identifier {
+        for subscription in db
+            .select_entries::<WebhookSubscriptionDB>(params.clone(), &[], None)
+            .await?
+        {
+            db.del_webhook_deliveries_by_subscription(subscription.id)
+                .await?;
+        }
+        db.delete_entry::<WebhookSubscriptionDB>(params).await
+    } else if ident == CourseDB::get_db_ident().db_identifier {
+        db.delete_entry::<CourseDB>(params).await
+    } else if ident == TopicDB::get_db_ident().db_identifier {
+        db.delete_entry::<TopicDB>(params).await
+    } else if ident == StudyGoalDB::get_db_ident().db_identifier {
+        db.delete_entry::<StudyGoalDB>(params).await
+    } else if ident == ExamDB::get_db_ident().db_identifier {
+        db.delete_entry::<ExamDB>(params).await
+    } else if ident == ToDoDB::get_db_ident().db_identifier {
+        db.delete_entry::<ToDoDB>(params).await
+    } else if ident == NoteDB::get_db_ident().db_identifier {
+        db.delete_entry::<NoteDB>(params).await
+    } else if ident == StudySessionDB::get_db_ident().db_identifier {
+        db.delete_entry::<StudySessionDB>(params).await
+    } else if ident == GradeDB::get_db_ident().db_identifier {
+        db.delete_entry::<GradeDB>(params).await
+    } else if ident == FlashcardDB::get_db_ident().db_identifier {
+        db.delete_entry::<FlashcardDB>(params).await
+    } else if ident == SemesterDB::get_db_ident().db_identifier {
+        db.delete_entry::<SemesterDB>(params).await
+    } else {
+        warn!("No data type for local token {}!", ident);
+        Ok(0)
+    }
+    .map(|_| ())
+}
+
+/// records a change of an object in the sync journal and notifies the webhooks of the user
+pub async fn record_change<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    ident: &DBObjIdent,
+    action: WebhookAction,
+    object_id: i32,
+) {
+    let deleted = matches!(action, WebhookAction::Delete);
+    if let Err(err) = state
+        .db
+        .new_sync_change(user_id, ident, object_id, deleted)
+        .await
+    {
+        // the change itself already happened, clients will only pick it up with a full sync
+        error!(
+            "Failed to record change in sync journal! (user id: {}): {}",
+            user_id, err
+        );
+    }
+
+    enqueue_event(state, user_id, ident, action, object_id).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::{
+        body::Body,
+        http::{Method, Request, StatusCode},
+    };
+    use eduflow_derive::{DBObject, SendObject};
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use chrono::{NaiveDate, Utc};
+
+    use super::*;
+    use crate::{
+        data_handler::{
+            data_router,
+            objects::{NoteDB, ToDoDB},
+            retention::purge_soft_deleted,
+        },
+        db::{
+            DBInterface, DbError,
+            sqlite::{SqliteConfig, SqliteDatabase},
+        },
+        db_param_map,
+        test_util::{
+            create_course, mock_state, register_user, send_json, test_state, test_state_with_db,
+        },
+    };
+
+    #[tokio::test]
+    async fn entries_round_trip_through_the_mock_database() {
+        let state = mock_state().await;
+        let token = register_user(&state, "mock_user").await;
+        let router = data_router(state.clone()).await;
+        let course_id = create_course(router.clone(), &token).await;
+
+        let (status, topic) = send_json(
+            router.clone(),
+            Method::POST,
+            "/topic",
+            &token,
+            Some(json!({ "id": null, "course_id": course_id, "name": "Limits", "details": "" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send_json(
+            router.clone(),
+            Method::POST,
+            "/topic",
+            &token,
+            Some(json!({ "id": topic["id"], "course_id": course_id, "name": "Series", "details": "" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, topics) = send_json(router.clone(), Method::GET, "/topic", &token, None).await;
+        assert_eq!(topics.as_array().unwrap().len(), 1);
+        assert_eq!(topics[0]["name"], "Series");
+
+        // an overloaded database is reported as such, the next request works again
+        state
+            .db
+            .fail_next("select_entries", DbError::Pool("timed out".to_string()));
+        let (status, _) = send_json(router.clone(), Method::GET, "/topic", &token, None).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+        let (status, _) = send_json(
+            router.clone(),
+            Method::DELETE,
+            "/topic",
+            &token,
+            Some(json!({ "id": topic["id"] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, topics) = send_json(router, Method::GET, "/topic", &token, None).await;
+        assert_eq!(topics, json!([]));
+    }
+
+    #[tokio::test]
+    async fn todos_are_open_unless_completed_is_sent() {
+        let state = mock_state().await;
+        let token = register_user(&state, "todo_default").await;
+        let router = data_router(state.clone()).await;
+
+        let (status, todo) = send_json(
+            router.clone(),
+            Method::POST,
+            "/todo",
+            &token,
+            Some(json!({ "id": null, "name": "Laundry", "deadline": "2025-07-01", "details": "" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, todos) =
+            send_json(router, Method::GET, "/todo?completed=false", &token, None).await;
+        assert_eq!(todos[0]["id"], todo["id"]);
+
+        // inserts without the field get the column default
+        state
+            .db
+            .new_entry::<ToDoDB>(db_param_map! {
+                user_id: 1,
+                name: vec![0u8],
+                deadline: NaiveDate::from_ymd_opt(2025, 7, 1).unwrap(),
+                details: vec![0u8],
+            })
+            .await
+            .unwrap();
+        let todos = state
+            .db
+            .select_entries::<ToDoDB>(db_param_map! { completed: false }, &[], None)
+            .await
+            .unwrap();
+        assert_eq!(todos.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn todo_list_is_filtered_by_state_and_due_date() {
+        let state = test_state().await;
+        let token = register_user(&state, "todo_list").await;
+        let router = data_router(state.clone()).await;
+        for (name, deadline, completed) in [
+            ("Taxes", "2025-06-20", false),
+            ("Laundry", "2025-05-01", false),
+            ("Dishes", "2025-05-02", true),
+            ("Holiday", "2025-07-01", false),
+            ("Groceries", "2025-06-01", false),
+        ] {
+            let (status, _) = send_json(
+                router.clone(),
+                Method::POST,
+                "/todo",
+                &token,
+                Some(json!({ "id": null, "name": name, "deadline": deadline, "details": "", "completed": completed })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+        }
+
+        let (status, todos) = send_json(
+            router.clone(),
+            Method::GET,
+            "/todo?completed=false&due_before=2025-07-01&sort=deadline&dir=asc&limit=50",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let names: Vec<&str> = todos
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|todo| todo["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["Laundry", "Groceries", "Taxes"]);
+
+        let (_, todos) = send_json(
+            router.clone(),
+            Method::GET,
+            "/todo?completed=true&due_after=2025-05-01",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(todos.as_array().unwrap().len(), 1);
+        assert_eq!(todos[0]["name"], "Dishes");
+        let (_, count) = send_json(
+            router.clone(),
+            Method::GET,
+            "/todo/count?due_after=2025-06-01",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(count["count"], 2);
+
+        for query in ["due_before=next-week", "completed=maybe"] {
+            let (status, _) = send_json(
+                router.clone(),
+                Method::GET,
+                &format!("/todo?{}", query),
+                &token,
+                None,
+            )
+            .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn notes_can_be_created_filtered_edited_and_deleted() {
+        let state = test_state().await;
+        let token = register_user(&state, "note_user").await;
+        let other = register_user(&state, "note_other").await;
+        let router = data_router(state.clone()).await;
+        let course_id = create_course(router.clone(), &token).await;
+        let (_, topic) = send_json(
+            router.clone(),
+            Method::POST,
+            "/topic",
+            &token,
+            Some(json!({ "id": null, "course_id": course_id, "name": "Limits", "details": "" })),
+        )
+        .await;
+
+        let mut ids = Vec::new();
+        for body in [
+            json!({ "id": null, "course_id": course_id, "title": "Course", "content": "about the course" }),
+            json!({ "id": null, "topic_id": topic["id"], "title": "Topic", "content": "about the topic" }),
+            json!({ "id": null, "title": "Loose", "content": "about nothing" }),
+        ] {
+            let (status, note) =
+                send_json(router.clone(), Method::POST, "/note", &token, Some(body)).await;
+            assert_eq!(status, StatusCode::OK);
+            ids.push(note["id"].clone());
+        }
+
+        // title and content are stored encrypted
+        let stored = state
+            .db
+            .select_entries::<NoteDB>(vec![], &[], None)
+            .await
+            .unwrap();
+        assert!(stored.iter().all(|note| note.title.data_crypt != b"Course"
+            && note.content.data_crypt != b"about the course"));
+
+        let (status, notes) = send_json(router.clone(), Method::GET, "/note", &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(notes.as_array().unwrap().len(), 3);
+        let (_, notes) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!("/note?course_id={}", course_id),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(notes.as_array().unwrap().len(), 1);
+        assert_eq!(notes[0]["content"], "about the course");
+        let (_, notes) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!("/note?topic_id={}", topic["id"]),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(notes[0]["title"], "Topic");
+        assert_eq!(notes[0]["course_id"], Value::Null);
+        let (_, count) = send_json(router.clone(), Method::GET, "/note/count", &token, None).await;
+        assert_eq!(count["count"], 3);
+        let (_, notes) = send_json(router.clone(), Method::GET, "/note", &other, None).await;
+        assert_eq!(notes, json!([]));
+
+        let (status, note) = send_json(
+            router.clone(),
+            Method::POST,
+            "/note",
+            &token,
+            Some(json!({ "id": ids[2], "topic_id": topic["id"], "title": "Moved", "content": "now with a topic" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(note["id"], ids[2]);
+        let (_, notes) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!("/note?id={}", ids[2]),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(notes[0]["title"], "Moved");
+        assert_eq!(notes[0]["topic_id"], topic["id"]);
+        assert!(notes[0]["created_at"].is_string());
+        assert!(notes[0]["updated_at"].is_string());
+
+        // courses and topics of other users can not be referenced
+        let (status, _) = send_json(
+            router.clone(),
+            Method::POST,
+            "/note",
+            &other,
+            Some(json!({ "id": null, "course_id": course_id, "title": "Foreign", "content": "" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let (status, _) = send_json(
+            router.clone(),
+            Method::DELETE,
+            "/note",
+            &token,
+            Some(json!({ "id": ids[0] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send_json(
+            router.clone(),
+            Method::DELETE,
+            "/note",
+            &token,
+            Some(json!({ "id": ids[0] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // the remaining notes are deleted with their topic
+        send_json(
+            router.clone(),
+            Method::DELETE,
+            "/topic",
+            &token,
+            Some(json!({ "id": topic["id"] })),
+        )
+        .await;
+        let (_, count) = send_json(router, Method::GET, "/note/count", &token, None).await;
+        assert_eq!(count["count"], 0);
+    }
+
+    #[allow(dead_code)] // only written, the rows are never read
+    #[derive(DBObject, Serialize)]
+    #[db(table = "rating", ident = "CourseDB", check = "stars <= best")]
+    struct RatingDB {
+        id: i32,
+        user_id: i32,
+        #[db(check = "stars BETWEEN 1 AND 5")]
+        stars: i32,
+        best: i32,
+    }
+
+    #[derive(SendObject, Serialize, Deserialize)]
+    struct RatingSend {
+        id: Option<i32>,
+        stars: i32,
+        best: i32,
+    }
+
+    impl CheckReferences for RatingSend {}
+
+    impl ToDB for RatingSend {
+        fn to_param_vec(&self, _: &[u8], _: &CryptProviders) -> Vec<(String, SQLValue)> {
+            db_param_map! { stars: self.stars, best: self.best }
+        }
+    }
+
+    #[tokio::test]
+    async fn check_violations_name_the_field() {
+        let state = test_state().await;
+        let token = register_user(&state, "check_user").await;
+        state.db.create_table_for_type::<RatingDB>().await.unwrap();
+        // the rating shares the local token of courses, so no token has to be created for it
+        let router = Router::new()
+            .route("/rating", post(handle_new::<RatingDB, RatingSend, _>))
+            .with_state(state.clone());
+
+        let rating =
+            |stars: i32, best: i32| Some(json!({ "id": null, "stars": stars, "best": best }));
+        let (status, _) = send_json(
+            router.clone(),
+            Method::POST,
+            "/rating",
+            &token,
+            rating(5, 5),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = send_json(
+            router.clone(),
+            Method::POST,
+            "/rating",
+            &token,
+            rating(6, 6),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            body["field_errors"],
+            json!([{ "field": "stars", "message": "is out of range" }])
+        );
+        let (status, body) = send_json(router, Method::POST, "/rating", &token, rating(4, 3)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            body["field_errors"],
+            json!([{ "field": "body", "message": "violates stars <= best" }])
+        );
+    }
+
+    #[tokio::test]
+    async fn patch_changes_only_the_sent_fields() {
+        let state = test_state().await;
+        let token = register_user(&state, "patch_user").await;
+        let other = register_user(&state, "patch_other").await;
+        let router = data_router(state.clone()).await;
+
+        let (_, todo) = send_json(
+            router.clone(),
+            Method::POST,
+            "/todo",
+            &token,
+            Some(json!({ "id": null, "name": "Laundry", "deadline": "2025-07-01", "details": "whites" })),
+        )
+        .await;
+        let (status, body) = send_json(
+            router.clone(),
+            Method::PATCH,
+            "/todo",
+            &token,
+            Some(json!({ "id": todo["id"], "completed": true })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["id"], todo["id"]);
+        let (_, todos) = send_json(router.clone(), Method::GET, "/todo", &token, None).await;
+        assert_eq!(todos[0]["name"], "Laundry");
+        assert_eq!(todos[0]["details"], "whites");
+        assert_eq!(todos[0]["completed"], true);
+
+        // nothing to change, or not the entry of the user
+        let (status, _) = send_json(
+            router.clone(),
+            Method::PATCH,
+            "/todo",
+            &token,
+            Some(json!({ "id": todo["id"] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = send_json(
+            router.clone(),
+            Method::PATCH,
+            "/todo",
+            &other,
+            Some(json!({ "id": todo["id"], "name": "Mine" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // a missing nullable field is kept, null unsets it
+        let course_id = create_course(router.clone(), &token).await;
+        let (_, exam) = send_json(
+            router.clone(),
+            Method::POST,
+            "/exam",
+            &token,
+            Some(json!({ "id": null, "course_id": course_id, "name": "Analysis", "date": "2025-07-01", "prep_notes": "chapter 1" })),
+        )
+        .await;
+        let patch = |fields: serde_json::Value| {
+            let mut body = json!({ "id": exam["id"] });
+            body.as_object_mut()
+                .unwrap()
+                .extend(fields.as_object().unwrap().clone());
+            Some(body)
+        };
+        send_json(
+            router.clone(),
+            Method::PATCH,
+            "/exam",
+            &token,
+            patch(json!({ "prep_status": "confident" })),
+        )
+        .await;
+        let (_, exams) = send_json(router.clone(), Method::GET, "/exam", &token, None).await;
+        assert_eq!(exams[0]["prep_status"], "confident");
+        assert_eq!(exams[0]["prep_notes"], "chapter 1");
+        send_json(
+            router.clone(),
+            Method::PATCH,
+            "/exam",
+            &token,
+            patch(json!({ "prep_notes": null })),
+        )
+        .await;
+        let (_, exams) = send_json(router, Method::GET, "/exam", &token, None).await;
+        assert_eq!(exams[0]["prep_notes"], serde_json::Value::Null);
+        assert_eq!(exams[0]["name"], "Analysis");
+    }
+
+    #[tokio::test]
+    async fn references_have_to_belong_to_the_user() {
+        let state = test_state().await;
+        let token = register_user(&state, "reference_user").await;
+        let other = register_user(&state, "reference_other").await;
+        let router = data_router(state.clone()).await;
+        let course_id = create_course(router.clone(), &token).await;
+        let foreign_course_id = create_course(router.clone(), &other).await;
+        let topic = |course_id: i64| {
+            Some(json!({ "id": null, "course_id": course_id, "name": "Limits", "details": "" }))
+        };
+
+        let (status, created) = send_json(
+            router.clone(),
+            Method::POST,
+            "/topic",
+            &token,
+            topic(course_id),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        for course_id in [9999, foreign_course_id] {
+            let (status, body) = send_json(
+                router.clone(),
+                Method::POST,
+                "/topic",
+                &token,
+                topic(course_id),
+            )
+            .await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+            assert_eq!(
+                body["field_errors"],
+                json!([{ "field": "course_id", "message": "does not exist" }])
+            );
+        }
+
+        // edits and partial edits can not move the topic either
+        let mut edit = topic(foreign_course_id).unwrap();
+        edit["id"] = created["id"].clone();
+        let (status, _) =
+            send_json(router.clone(), Method::POST, "/topic", &token, Some(edit)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let (status, _) = send_json(
+            router.clone(),
+            Method::PATCH,
+            "/topic",
+            &token,
+            Some(json!({ "id": created["id"], "course_id": foreign_course_id })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        // every reference of a study goal is named
+        let (status, body) = send_json(
+            router.clone(),
+            Method::POST,
+            "/study_goal",
+            &token,
+            Some(
+                json!({ "id": null, "topic_id": 9999, "deadline": "2025-07-01", "exam_id": 9999 }),
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["field_errors"][0]["field"], "topic_id");
+        assert_eq!(body["field_errors"][1]["field"], "exam_id");
+
+        let (_, topics) = send_json(router, Method::GET, "/topic", &token, None).await;
+        assert_eq!(topics.as_array().unwrap().len(), 1);
+        assert_eq!(topics[0]["course_id"], course_id);
+    }
+
+    #[tokio::test]
+    async fn foreign_entries_can_not_be_edited_or_deleted() {
+        let state = test_state().await;
+        let owner = register_user(&state, "entry_owner").await;
+        let other = register_user(&state, "entry_other").await;
+        let router = data_router(state.clone()).await;
+
+        let (_, course) = send_json(
+            router.clone(),
+            Method::POST,
+            "/course",
+            &owner,
+            Some(json!({ "id": null, "name": "Analysis" })),
+        )
+        .await;
+        let course_id = course["id"].as_i64().unwrap();
+
+        let (status, _) = send_json(
+            router.clone(),
+            Method::POST,
+            "/course",
+            &other,
+            Some(json!({ "id": course_id, "name": "Taken over" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = send_json(
+            router.clone(),
+            Method::DELETE,
+            "/course",
+            &other,
+            Some(json!({ "id": course_id })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // the course of the owner is untouched
+        let (_, courses) = send_json(router.clone(), Method::GET, "/course", &owner, None).await;
+        assert_eq!(courses[0]["name"], "Analysis");
+        assert!(courses[0]["created_at"].is_string());
+        assert!(courses[0]["updated_at"].is_null());
+
+        let (status, _) = send_json(
+            router.clone(),
+            Method::DELETE,
+            "/course",
+            &owner,
+            Some(json!({ "id": course_id })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        // deleting twice finds nothing
+        let (status, _) = send_json(
+            router.clone(),
+            Method::DELETE,
+            "/course",
+            &owner,
+            Some(json!({ "id": course_id })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn entries_can_be_paginated() {
+        let state = test_state().await;
+        let token = register_user(&state, "pager").await;
+        let router = data_router(state.clone()).await;
+        for name in ["first", "second", "third"] {
+            send_json(
+                router.clone(),
+                Method::POST,
+                "/course",
+                &token,
+                Some(json!({ "id": null, "name": name })),
+            )
+            .await;
+        }
+
+        let request = Request::get("/course?limit=2")
+            .header("authorization", format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-total-count"], "3");
+
+        let (_, page) =
+            send_json(router.clone(), Method::GET, "/course?limit=2", &token, None).await;
+        let names: Vec<_> = page
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| &c["name"])
+            .collect();
+        assert_eq!(names, ["first", "second"]);
+        let (_, page) = send_json(
+            router.clone(),
+            Method::GET,
+            "/course?limit=2&offset=2",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(page.as_array().unwrap().len(), 1);
+        assert_eq!(page[0]["name"], "third");
+
+        for query in ["limit=0", "limit=501", "limit=abc", "offset=-1"] {
+            let (status, _) = send_json(
+                router.clone(),
+                Method::GET,
+                &format!("/course?{}", query),
+                &token,
+                None,
+            )
+            .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", query);
+        }
+    }
+
+    #[tokio::test]
+    async fn entries_can_be_sorted() {
+        let state = test_state().await;
+        let token = register_user(&state, "sorter").await;
+        let router = data_router(state.clone()).await;
+        for (name, deadline) in [
+            ("b", "2025-07-02"),
+            ("a", "2025-07-03"),
+            ("c", "2025-07-01"),
+        ] {
+            send_json(
+                router.clone(),
+                Method::POST,
+                "/todo",
+                &token,
+                Some(json!({ "id": null, "name": name, "deadline": deadline, "details": "", "completed": false })),
+            )
+            .await;
+        }
+
+        let (status, todos) = send_json(
+            router.clone(),
+            Method::GET,
+            "/todo?sort=deadline&dir=desc",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let names: Vec<_> = todos
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| &t["name"])
+            .collect();
+        assert_eq!(names, ["a", "b", "c"]);
+
+        // encrypted and unknown fields, unknown directions and filters are refused
+        for query in [
+            "sort=name",
+            "sort=id;DROP TABLE ToDoDB",
+            "sort=deadline&dir=up",
+            "1=1 OR 1",
+        ] {
+            let (status, _) = send_json(
+                router.clone(),
+                Method::GET,
+                &format!("/todo?{}", query.replace(' ', "%20")),
+                &token,
+                None,
+            )
+            .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", query);
+        }
+    }
+
+    #[tokio::test]
+    async fn filters_are_read_from_the_query_without_a_body() {
+        let state = test_state().await;
+        let token = register_user(&state, "query_user").await;
+        let router = data_router(state.clone()).await;
+        let mut course_ids = Vec::new();
+        for name in ["Algebra", "Statistics"] {
+            let course_id = create_course(router.clone(), &token).await;
+            send_json(
+                router.clone(),
+                Method::POST,
+                "/topic",
+                &token,
+                Some(json!({ "id": null, "course_id": course_id, "name": name, "details": "" })),
+            )
+            .await;
+            course_ids.push(course_id);
+        }
+
+        // like fetch in a browser, neither a body nor a content type
+        let request = Request::get(format!("/topic?course_id={}", course_ids[1]))
+            .header("authorization", format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let topics: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(topics.as_array().unwrap().len(), 1);
+        assert_eq!(topics[0]["name"], "Statistics");
+    }
+
+    #[tokio::test]
+    async fn entries_can_be_filtered_by_range_and_ids() {
+        let state = test_state().await;
+        let token = register_user(&state, "planner").await;
+        let router = data_router(state.clone()).await;
+        let course_id = create_course(router.clone(), &token).await;
+        for (name, date) in [
+            ("june", "2025-06-30"),
+            ("july", "2025-07-15"),
+            ("august", "2025-08-01"),
+        ] {
+            send_json(
+                router.clone(),
+                Method::POST,
+                "/exam",
+                &token,
+                Some(json!({ "id": null, "course_id": course_id, "name": name, "date": date })),
+            )
+            .await;
+        }
+
+        let (status, exams) = send_json(
+            router.clone(),
+            Method::GET,
+            "/exam?date_after=2025-06-30&date_before=2025-08-01",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let names: Vec<_> = exams
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| &e["name"])
+            .collect();
+        assert_eq!(names, ["july"]);
+
+        let (status, exams) = send_json(
+            router.clone(),
+            Method::GET,
+            "/exam?date_before=2025-07-15",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(exams.as_array().unwrap().len(), 1);
+
+        // several ids at once
+        let ids: Vec<_> = exams
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["id"].clone())
+            .collect();
+        let (status, exams) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!("/exam?id={},{}", ids[0], 9999),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(exams[0]["name"], "june");
+        assert_eq!(exams.as_array().unwrap().len(), 1);
+
+        // counting takes the same filters
+        let (status, count) = send_json(
+            router.clone(),
+            Method::GET,
+            "/exam/count?date_after=2025-06-30",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(count["count"], 2);
+
+        // the compared value has to match the type of the field
+        let (status, _) = send_json(
+            router,
+            Method::GET,
+            "/exam?date_before=tomorrow",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn entries_can_be_filtered_by_unset_fields() {
+        let state = test_state().await;
+        let token = register_user(&state, "note_taker").await;
+        let router = data_router(state.clone()).await;
+        let course_id = create_course(router.clone(), &token).await;
+        for (name, notes) in [("with", Some("chapter 3")), ("without", None)] {
+            send_json(
+                router.clone(),
+                Method::POST,
+                "/exam",
+                &token,
+                Some(json!({ "id": null, "course_id": course_id, "name": name, "date": "2025-07-01", "prep_notes": notes })),
+            )
+            .await;
+        }
+
+        for (query, expected) in [("prep_notes=null", "without"), ("prep_notes=!null", "with")] {
+            let (status, exams) = send_json(
+                router.clone(),
+                Method::GET,
+                &format!("/exam?{}", query),
+                &token,
+                None,
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(exams.as_array().unwrap().len(), 1, "{}", query);
+            assert_eq!(exams[0]["name"], expected);
+        }
+
+        // fields that are always set can not be checked
+        let (status, _) =
+            send_json(router, Method::GET, "/exam?course_id=null", &token, None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn deleting_a_course_removes_its_topics_and_exams() {
+        let state = test_state().await;
+        let token = register_user(&state, "dropout").await;
+        let router = data_router(state.clone()).await;
+        let (course_id, other_course_id) = (
+            create_course(router.clone(), &token).await,
+            create_course(router.clone(), &token).await,
+        );
+        for course_id in [course_id, other_course_id] {
+            send_json(
+                router.clone(),
+                Method::POST,
+                "/topic",
+                &token,
+                Some(json!({ "id": null, "course_id": course_id, "name": "topic", "details": "" })),
+            )
+            .await;
+        }
+        send_json(
+            router.clone(),
+            Method::POST,
+            "/exam",
+            &token,
+            Some(
+                json!({ "id": null, "course_id": course_id, "name": "exam", "date": "2025-07-01" }),
+            ),
+        )
+        .await;
+
+        let (status, _) = send_json(
+            router.clone(),
+            Method::DELETE,
+            "/course",
+            &token,
+            Some(json!({ "id": course_id })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (_, topics) = send_json(router.clone(), Method::GET, "/topic", &token, None).await;
+        let topics = topics.as_array().unwrap();
+        assert_eq!(topics.len(), 1);
+        assert_eq!(topics[0]["course_id"], other_course_id);
+        let (_, exams) = send_json(router, Method::GET, "/exam", &token, None).await;
+        assert!(exams.as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn deleted_todos_can_be_restored_until_purged() {
+        let state = test_state().await;
+        let token = register_user(&state, "undo_user").await;
+        let router = data_router(state.clone()).await;
+
+        let (_, todo) = send_json(
+            router.clone(),
+            Method::POST,
+            "/todo",
+            &token,
+            Some(json!({ "id": null, "name": "Laundry", "deadline": "2025-07-01", "details": "", "completed": false })),
+        )
+        .await;
+        let todo_id = todo["id"].as_i64().unwrap();
+        let (status, _) = send_json(
+            router.clone(),
+            Method::DELETE,
+            "/todo",
+            &token,
+            Some(json!({ "id": todo_id })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        // the deleted todo is invisible and can not be edited
+        let (_, todos) = send_json(router.clone(), Method::GET, "/todo", &token, None).await;
+        assert!(todos.as_array().unwrap().is_empty());
+        let (_, count) = send_json(router.clone(), Method::GET, "/todo/count", &token, None).await;
+        assert_eq!(count["count"], 0);
+        let (status, _) = send_json(
+            router.clone(),
+            Method::POST,
+            "/todo",
+            &token,
+            Some(json!({ "id": todo_id, "name": "Edited", "deadline": "2025-07-01", "details": "", "completed": false })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let restore = || {
+            send_json(
+                router.clone(),
+                Method::POST,
+                "/todo/restore",
+                &token,
+                Some(json!({ "id": todo_id })),
+            )
+        };
+        assert_eq!(restore().await.0, StatusCode::OK);
+        let (_, todos) = send_json(router.clone(), Method::GET, "/todo", &token, None).await;
+        assert_eq!(todos[0]["name"], "Laundry");
+        // only deleted todos can be restored
+        assert_eq!(restore().await.0, StatusCode::NOT_FOUND);
+
+        // after the grace period the todo is gone for good
+        send_json(
+            router.clone(),
+            Method::DELETE,
+            "/todo",
+            &token,
+            Some(json!({ "id": todo_id })),
+        )
+        .await;
+        let later = Utc::now().naive_utc() + chrono::Days::new(31);
+        assert_eq!(purge_soft_deleted::<ToDoDB, _>(&state, later).await, 1);
+        assert_eq!(restore().await.0, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn exhausted_pool_returns_service_unavailable() {
+        let state = test_state_with_db(SqliteDatabase::new_in_memory_with_config(SqliteConfig {
+            max_connections: 1,
+            acquire_timeout: Duration::from_millis(100),
+            ..SqliteConfig::default()
+        }))
+        .await;
+        let token = register_user(&state, "pool_user").await;
+        let router = data_router(state.clone()).await;
+        let request = || {
+            Request::get("/todo")
+                .header("authorization", format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // the only connection is taken, the request can not wait for it forever
+        let conn = state.db.hold_connection();
+        let response = router.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()["retry-after"], "1");
+
+        drop(conn);
+        let response = router.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn waiting_writes_do_not_block_other_requests() {
+        let path =
+            std::env::temp_dir().join(format!("eduflow_load_test_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        // the test runtime has a single thread, a blocking db call would stall every request
+        let state = test_state_with_db(SqliteDatabase::new(&path).unwrap()).await;
+        let token = register_user(&state, "load_user").await;
+        let router = data_router(state.clone()).await;
+
+        // another connection holds the write lock, writers wait for the busy timeout
+        let conn = state.db.hold_connection();
+        conn.execute_batch("BEGIN IMMEDIATE").unwrap();
+        let writer = tokio::spawn({
+            let (router, token) = (router.clone(), token.clone());
+            async move {
+                let course = json!({ "id": null, "name": "Analysis" });
+                send_json(router, Method::POST, "/course", &token, Some(course)).await
+            }
+        });
+
+        // meanwhile reads are answered
+        for _ in 0..50 {
+            let (status, _) = send_json(router.clone(), Method::GET, "/course", &token, None).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+        assert!(!writer.is_finished());
+
+        conn.execute_batch("ROLLBACK").unwrap();
+        drop(conn);
+        assert_eq!(writer.await.unwrap().0, StatusCode::OK);
+
+        drop(state);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    #[tokio::test]
+    async fn bulk_creation_is_all_or_nothing() {
+        let state = test_state().await;
+        let token = register_user(&state, "semester_planner").await;
+        let router = data_router(state.clone()).await;
+        let course_id = create_course(router.clone(), &token).await;
+        let topic = |course_id: i64, name: &str| json!({ "id": null, "course_id": course_id, "name": name, "details": "" });
+
+        let (status, ids) = send_json(
+            router.clone(),
+            Method::POST,
+            "/topic/bulk",
+            &token,
+            Some(json!([
+                topic(course_id, "first"),
+                topic(course_id, "second"),
+                topic(course_id, "third")
+            ])),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, topics) = send_json(router.clone(), Method::GET, "/topic", &token, None).await;
+        let topics = topics.as_array().unwrap();
+        assert_eq!(ids.as_array().unwrap().len(), 3);
+        for (topic, id) in topics.iter().zip(ids.as_array().unwrap()) {
+            assert_eq!(&topic["id"], id);
+        }
+        assert_eq!(topics[1]["name"], "second");
+
+        // the unknown course is named with the index of its topic, the valid topic before it is not created either
+        let (status, body) = send_json(
+            router.clone(),
+            Method::POST,
+            "/topic/bulk",
+            &token,
+            Some(json!([topic(course_id, "valid"), topic(9999, "orphan")])),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            body["field_errors"],
+            json!([{ "field": "1.course_id", "message": "does not exist" }])
+        );
+        // only creation is allowed
+        let mut edit = topic(course_id, "edit");
+        edit["id"] = ids[0].clone();
+        let (status, _) = send_json(
+            router.clone(),
+            Method::POST,
+            "/topic/bulk",
+            &token,
+            Some(json!([edit])),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (_, count) = send_json(router, Method::GET, "/topic/count", &token, None).await;
+        assert_eq!(count["count"], 3);
+    }
 }
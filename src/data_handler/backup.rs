@@ -0,0 +1,871 @@
+use std::{any::type_name, collections::BTreeMap, collections::HashMap, sync::Arc};
+
+use axum::{extract::State, http::StatusCode};
+use chrono::{NaiveDateTime, Utc};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    AppState,
+    api_error::{ApiError, FieldError, Json},
+    auth_handler::{AuthenticatedUser, confirm_password, decrypt_local_token_for},
+    crypt::{Cryptable, crypt_types::CryptString},
+    db::{DBInterface, DBObjIdent, DbError, StudyGoalTopic, sql_helper::SQLGenerate},
+    db_param_map,
+};
+
+use super::{
+    decrypt_failed_status,
+    objects::{CourseDB, ExamDB, StudyGoalDB, ToDoDB, TopicDB},
+    record_change,
+    webhook::WebhookAction,
+};
+
+/// version of the backup format, increased on incompatible changes of the archive itself
+const BACKUP_FORMAT_VERSION: u32 = 1;
+
+/// archive of the raw rows of a user, nothing in here is decrypted
+#[derive(Serialize, Deserialize)]
+pub struct Backup {
+    manifest: BackupManifest,
+    /// local tokens of the user, still encrypted with the users password
+    local_tokens: Vec<BackupLocalToken>,
+    course: Vec<CourseDB>,
+    topic: Vec<TopicDB>,
+    study_goal: Vec<StudyGoalDB>,
+    /// links between study goals and their topics, missing in backups of older versions
+    #[serde(default)]
+    study_goal_topic: Vec<StudyGoalTopic>,
+    exam: Vec<ExamDB>,
+    todo: Vec<ToDoDB>,
+}
+
+/// describes the backup, used to reject backups of incompatible formats
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct BackupManifest {
+    format_version: u32,
+    created_at: NaiveDateTime,
+    /// schema version (hash of the table definition) per table, only informational
+    /// rows of older schemas are restored as long as they have every column without a default
+    #[serde(default)]
+    schemas: BTreeMap<String, String>,
+}
+
+/// password encrypted local token
+#[derive(Serialize, Deserialize)]
+pub struct BackupLocalToken {
+    used_for: String,
+    token_crypt: CryptString,
+}
+
+/// body of the restore request
+#[derive(Deserialize)]
+pub struct RestoreRequest {
+    /// password the local tokens of the backup are encrypted with
+    backup_password: String,
+    /// current password of the account the backup is restored into
+    password: String,
+    /// recovery key of the account, without it the recovery key no longer covers the replaced local tokens
+    #[serde(default)]
+    recovery_key: Option<String>,
+    backup: Backup,
+}
+
+/// number of restored objects per type, also the response of the json import
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RestoreResult {
+    pub course: usize,
+    pub topic: usize,
+    pub study_goal: usize,
+    pub exam: usize,
+    pub todo: usize,
+    /// object types (db identifiers) whose data a password reset can no longer recover, only on restores without recovery key
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unrecoverable: Vec<String>,
+}
+
+/// local token of a backup encrypted for the account it is restored into
+struct RewrappedToken {
+    local_token_id: i32,
+    pwcrypt: CryptString,
+    rtcrypt: CryptString,
+    /// copy for the recovery key, none if the recovery key is unknown
+    reccrypt: Option<CryptString>,
+}
+
+/// schema version of a table, changes whenever the table definition changes
+fn schema_version<DBT: SQLGenerate>() -> (String, String) {
+    let hash = Sha256::digest(DBT::get_db_table_create().as_bytes());
+    (DBT::get_db_ident().db_identifier, hex::encode(&hash[..8]))
+}
+
+/// schema versions of every table contained in a backup
+fn current_schemas() -> BTreeMap<String, String> {
+    BTreeMap::from([
+        schema_version::<CourseDB>(),
+        schema_version::<TopicDB>(),
+        schema_version::<StudyGoalDB>(),
+        schema_version::<ExamDB>(),
+        schema_version::<ToDoDB>(),
+    ])
+}
+
+/// idents of every table contained in a backup
+fn backup_idents() -> [DBObjIdent; 5] {
+    [
+        CourseDB::get_db_ident(),
+        TopicDB::get_db_ident(),
+        StudyGoalDB::get_db_ident(),
+        ExamDB::get_db_ident(),
+        ToDoDB::get_db_ident(),
+    ]
+}
+
+/// loads every row of the user, without decrypting it
+async fn load_rows<DBT: SQLGenerate, DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    user_id: i32,
+) -> Result<Vec<DBT>, StatusCode> {
+    state
+        .db
+        .select_entries::<DBT>(db_param_map! { user_id: user_id }, &[], None)
+        .await
+        .map_err(|_| {
+            error!(
+                "Error while querying DB! Tried to back up {} information.",
+                type_name::<DBT>()
+            );
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// handler for backup requests, returns the raw rows of the user
+pub async fn handle_backup<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+) -> Result<Json<Backup>, ApiError> {
+    info!("Backup requested!");
+
+    let AuthenticatedUser { user_id, .. } = user;
+    // no local token needed, the rows are exported encrypted
+
+    let idents = backup_idents();
+    let local_tokens = state
+        .db
+        .get_local_tokens_by_user_pwcrypt(user_id)
+        .await
+        .map_err(|_| {
+            error!("Failed to load local tokens! (user id: {})", user_id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .into_iter()
+        .filter(|lt| {
+            idents
+                .iter()
+                .any(|ident| ident.db_identifier == lt.used_for.db_identifier)
+        })
+        .map(|lt| BackupLocalToken {
+            used_for: lt.used_for.db_identifier,
+            token_crypt: lt.token_crypt,
+        })
+        .collect();
+
+    let backup = Backup {
+        manifest: BackupManifest {
+            format_version: BACKUP_FORMAT_VERSION,
+            created_at: Utc::now().naive_utc(),
+            schemas: current_schemas(),
+        },
+        local_tokens,
+        course: load_rows(&state, user_id).await?,
+        topic: load_rows(&state, user_id).await?,
+        study_goal: load_rows(&state, user_id).await?,
+        study_goal_topic: state
+            .db
+            .get_study_goal_topics_by_user(user_id)
+            .await
+            .map_err(|_| {
+                error!("Failed to load study goal topics! (user id: {})", user_id);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?,
+        exam: load_rows(&state, user_id).await?,
+        todo: load_rows(&state, user_id).await?,
+    };
+
+    info!("Backup successful, building response!");
+    Ok(Json(backup))
+}
+
+/// handler for restore requests, imports a backup into the account of the token
+pub async fn handle_restore<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Json(request): Json<RestoreRequest>,
+) -> Result<Json<RestoreResult>, ApiError> {
+    info!("Restore requested!");
+
+    let AuthenticatedUser {
+        user_id,
+        remote_token_id,
+        remote_token,
+    } = user;
+
+    // the local tokens get re-wrapped with this password, so it has to be the right one
+    confirm_password(user_id, &request.password, state.clone(), "Restore").await?;
+
+    // rows of other schema versions have been checked while parsing, columns added since then get their defaults
+    let backup = request.backup;
+    if backup.manifest.format_version != BACKUP_FORMAT_VERSION {
+        warn!("Restore of backup with incompatible format requested!");
+        return Err(StatusCode::UNPROCESSABLE_ENTITY.into());
+    }
+
+    // decrypt the local tokens of the backup and find out which ones differ from the current ones
+    let mut rewrap = Vec::new();
+    for ident in backup_idents() {
+        let backup_token = backup
+            .local_tokens
+            .iter()
+            .find(|lt| lt.used_for == ident.db_identifier)
+            .ok_or_else(|| {
+                warn!("Backup is missing the local token for {:?}", ident);
+                StatusCode::UNPROCESSABLE_ENTITY
+            })?
+            .token_crypt
+            .decrypt(request.backup_password.as_bytes(), &state.crypt_provider)
+            .map_err(|_| {
+                warn!("Restore with wrong backup password! (user id: {})", user_id);
+                StatusCode::FORBIDDEN
+            })?;
+
+        let current_token = decrypt_local_token_for(
+            user_id,
+            &ident,
+            remote_token_id,
+            &remote_token,
+            state.clone(),
+        )
+        .await
+        .map_err(|err| decrypt_failed_status(err, &ident, remote_token_id))?;
+
+        if current_token != backup_token {
+            rewrap.push((ident, backup_token));
+        }
+    }
+
+    // replacing a local token makes existing rows of that type unreadable
+    for (ident, _) in &rewrap {
+        let has_rows = has_rows_for(&state, user_id, ident).await?;
+        if has_rows {
+            warn!(
+                "Restore into account with existing {} data rejected! (user id: {})",
+                ident.db_identifier, user_id
+            );
+            return Err(StatusCode::CONFLICT.into());
+        }
+    }
+
+    let recovery_key = verified_recovery_key(&state, user_id, request.recovery_key).await?;
+    let mut rewrapped = Vec::new();
+    let mut unrecoverable = Vec::new();
+    for (ident, backup_token) in &rewrap {
+        let local_token_id = state
+            .db
+            .get_local_token_by_used_for_pwcrypt(user_id, ident)
+            .await
+            .map_err(|_| {
+                error!(
+                    "Failed to load local token for {:?}! (user id: {})",
+                    ident, user_id
+                );
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .id;
+        let encrypt =
+            |key: &str| CryptString::encrypt(backup_token, key.as_bytes(), &state.crypt_provider);
+        let reccrypt = recovery_key.as_deref().map(encrypt);
+        if reccrypt.is_none() {
+            unrecoverable.push(ident.db_identifier.clone());
+        }
+        rewrapped.push(RewrappedToken {
+            local_token_id,
+            pwcrypt: encrypt(&request.password),
+            rtcrypt: encrypt(&remote_token),
+            reccrypt,
+        });
+    }
+    if !rewrapped.is_empty() {
+        // the copies of a local token are replaced together, a stale recovery copy would bring back the old token on a reset
+        state
+            .db
+            .with_transaction(move |db| {
+                Box::pin(async move {
+                    for token in &rewrapped {
+                        rewrap_local_token(db, token, remote_token_id).await?;
+                    }
+                    // other sessions still hold the replaced local tokens, they have to log in again
+                    db.del_remote_tokens_by_user(user_id, Some(remote_token_id))
+                        .await
+                })
+            })
+            .await
+            .map_err(|_| {
+                error!("Failed to replace local tokens! (user id: {})", user_id);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    }
+
+    let mut result = restore_rows(&state, user_id, backup).await?;
+    result.unrecoverable = unrecoverable;
+
+    info!("Restore successful! (user id: {})", user_id);
+    Ok(Json(result))
+}
+
+/// checks if the user has at least one row of the type with the ident
+async fn has_rows_for<DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    user_id: i32,
+    ident: &DBObjIdent,
+) -> Result<bool, StatusCode> {
+    let ident = ident.db_identifier.as_str();
+    let has_rows = if ident == CourseDB::get_db_ident().db_identifier {
+        !load_rows::<CourseDB, DB>(state, user_id).await?.is_empty()
+    } else if ident == TopicDB::get_db_ident().db_identifier {
+        !load_rows::<TopicDB, DB>(state, user_id).await?.is_empty()
+    } else if ident == StudyGoalDB::get_db_ident().db_identifier {
+        !load_rows::<StudyGoalDB, DB>(state, user_id)
+            .await?
+            .is_empty()
+    } else if ident == ExamDB::get_db_ident().db_identifier {
+        !load_rows::<ExamDB, DB>(state, user_id).await?.is_empty()
+    } else {
+        !load_rows::<ToDoDB, DB>(state, user_id).await?.is_empty()
+    };
+
+    Ok(has_rows)
+}
+
+/// checks the recovery key of a restore against a recovery copy of the user, none if no key is given
+/// the key can not be checked for users without recovery copies, it is left out then
+async fn verified_recovery_key<DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    user_id: i32,
+    recovery_key: Option<String>,
+) -> Result<Option<String>, ApiError> {
+    let Some(recovery_key) = recovery_key else {
+        return Ok(None);
+    };
+    let recovery_tokens = state
+        .db
+        .get_local_tokens_by_user_reccrypt(user_id)
+        .await
+        .map_err(|_| {
+            error!("Failed to load recovery copies! (user id: {})", user_id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let Some(recovery_token) = recovery_tokens.first() else {
+        return Ok(None);
+    };
+    if recovery_token
+        .local_token_crypt
+        .decrypt(recovery_key.as_bytes(), &state.crypt_provider)
+        .is_err()
+    {
+        warn!("Restore with wrong recovery key! (user id: {})", user_id);
+        return Err(ApiError::with_field_errors(
+            StatusCode::FORBIDDEN,
+            vec![FieldError {
+                field: "recovery_key".to_string(),
+                message: "is wrong".to_string(),
+            }],
+        ));
+    }
+    Ok(Some(recovery_key))
+}
+
+/// replaces every copy of a local token with the one from the backup, the current remote token is the only one with access
+async fn rewrap_local_token<DB: DBInterface + Send + Sync>(
+    db: &DB,
+    token: &RewrappedToken,
+    remote_token_id: i32,
+) -> Result<(), DbError> {
+    db.update_local_token_pwcrypt(token.local_token_id, &token.pwcrypt)
+        .await?;
+
+    db.del_local_token_rtcrypt_by_lt(token.local_token_id)
+        .await?;
+    db.new_local_token_rtcrypt(token.local_token_id, &token.rtcrypt, remote_token_id)
+        .await?;
+
+    db.del_local_token_reccrypt(token.local_token_id).await?;
+    if let Some(reccrypt) = &token.reccrypt {
+        db.new_local_token_reccrypt(token.local_token_id, reccrypt)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// inserts the rows of the backup for the user, references between objects are remapped to the new ids
+async fn restore_rows<DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    user_id: i32,
+    backup: Backup,
+) -> Result<RestoreResult, StatusCode> {
+    let mut course_ids = HashMap::new();
+    for mut course in backup.course {
+        // semesters are not part of a backup
+        course.semester_id = None;
+        course_ids.insert(course.id, insert_row(state, user_id, &course).await?);
+    }
+
+    let mut topic_ids = HashMap::new();
+    for mut topic in backup.topic {
+        let Some(course_id) = course_ids.get(&topic.course_id) else {
+            warn!("Skipping topic {} with unknown course in backup", topic.id);
+            continue;
+        };
+        topic.course_id = *course_id;
+        topic_ids.insert(topic.id, insert_row(state, user_id, &topic).await?);
+    }
+
+    let mut study_goal_ids = HashMap::new();
+    for mut study_goal in backup.study_goal {
+        match topic_ids.get(&study_goal.topic_id) {
+            Some(topic_id) => study_goal.topic_id = *topic_id,
+            // the topic of a flagged goal is gone already, the goal is kept
+            None if study_goal.topics_missing => {}
+            None => {
+                warn!(
+                    "Skipping study goal {} with unknown topic in backup",
+                    study_goal.id
+                );
+                continue;
+            }
+        }
+        study_goal_ids.insert(
+            study_goal.id,
+            insert_row(state, user_id, &study_goal).await?,
+        );
+    }
+
+    // links are grouped by goal, the order of the backup is kept
+    let mut goal_topics: Vec<(i32, Vec<i32>)> = Vec::new();
+    for link in &backup.study_goal_topic {
+        let (Some(goal_id), Some(topic_id)) = (
+            study_goal_ids.get(&link.goal_id),
+            topic_ids.get(&link.topic_id),
+        ) else {
+            continue;
+        };
+        match goal_topics.iter_mut().find(|(id, _)| id == goal_id) {
+            Some((_, topics)) => topics.push(*topic_id),
+            None => goal_topics.push((*goal_id, vec![*topic_id])),
+        }
+    }
+    for (goal_id, topics) in goal_topics {
+        state
+            .db
+            .set_study_goal_topics(user_id, goal_id, &topics)
+            .await
+            .map_err(|_| {
+                error!(
+                    "Failed to restore study goal topics! (user id: {})",
+                    user_id
+                );
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    }
+
+    let mut exams = 0;
+    for mut exam in backup.exam {
+        let Some(course_id) = course_ids.get(&exam.course_id) else {
+            warn!("Skipping exam {} with unknown course in backup", exam.id);
+            continue;
+        };
+        exam.course_id = *course_id;
+        insert_row(state, user_id, &exam).await?;
+        exams += 1;
+    }
+
+    // parents before their subtasks
+    let (todos, subtasks): (Vec<_>, Vec<_>) = backup
+        .todo
+        .into_iter()
+        .partition(|todo| todo.parent_todo_id.is_none());
+    let mut todo_ids = HashMap::new();
+    for todo in todos {
+        todo_ids.insert(todo.id, insert_row(state, user_id, &todo).await?);
+    }
+    let mut restored_subtasks = 0;
+    for mut subtask in subtasks {
+        let Some(parent_id) = subtask.parent_todo_id.and_then(|id| todo_ids.get(&id)) else {
+            warn!(
+                "Skipping subtask {} with unknown todo in backup",
+                subtask.id
+            );
+            continue;
+        };
+        subtask.parent_todo_id = Some(*parent_id);
+        insert_row(state, user_id, &subtask).await?;
+        restored_subtasks += 1;
+    }
+
+    Ok(RestoreResult {
+        course: course_ids.len(),
+        topic: topic_ids.len(),
+        study_goal: study_goal_ids.len(),
+        exam: exams,
+        todo: todo_ids.len() + restored_subtasks,
+        unrecoverable: Vec::new(),
+    })
+}
+
+/// inserts a single raw row for the user, returns the new id
+async fn insert_row<DBT: SQLGenerate, DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    user_id: i32,
+    row: &DBT,
+) -> Result<i32, StatusCode> {
+    let mut params = db_param_map! { user_id: user_id };
+    params.extend(row.to_params());
+
+    let id = state.db.new_entry::<DBT>(params).await.map_err(|err| {
+        error!(
+            "Failed to insert restored {} into db! (user id: {}): {}",
+            type_name::<DBT>(),
+            user_id,
+            err
+        );
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    record_change(
+        state,
+        user_id,
+        &DBT::get_db_ident(),
+        WebhookAction::Create,
+        id,
+    )
+    .await;
+
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    use crate::{
+        auth_handler::auth_router,
+        data_handler::data_router,
+        db::DBInterface,
+        test_util::{create_course, register_user, send_json, test_state},
+    };
+
+    #[tokio::test]
+    async fn backup_round_trips_into_fresh_account() {
+        let state = test_state().await;
+        let token = register_user(&state, "backup_source").await;
+        let router = data_router(state.clone()).await;
+
+        let (_, course) = send_json(
+            router.clone(),
+            Method::POST,
+            "/course",
+            &token,
+            Some(json!({ "id": null, "name": "Analysis" })),
+        )
+        .await;
+        let course_id = course["id"].as_i64().unwrap();
+        let (_, topic) = send_json(
+            router.clone(),
+            Method::POST,
+            "/topic",
+            &token,
+            Some(json!({ "id": null, "course_id": course_id, "name": "Integrals", "details": "by parts" })),
+        )
+        .await;
+        let (_, goal) = send_json(
+            router.clone(),
+            Method::POST,
+            "/study_goal",
+            &token,
+            Some(json!({ "id": null, "topic_id": topic["id"], "deadline": "2025-07-01" })),
+        )
+        .await;
+        send_json(
+            router.clone(),
+            Method::PUT,
+            &format!("/study_goal/{}/topics", goal["id"]),
+            &token,
+            Some(json!({ "topic_ids": [topic["id"]] })),
+        )
+        .await;
+
+        let (status, backup) =
+            send_json(router.clone(), Method::GET, "/backup", &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        // the backup only contains ciphertexts
+        assert!(!backup.to_string().contains("Analysis"));
+        assert!(!backup.to_string().contains("Integrals"));
+
+        let new_token = register_user(&state, "backup_target").await;
+        let (status, result) = send_json(
+            router.clone(),
+            Method::POST,
+            "/restore",
+            &new_token,
+            Some(
+                json!({ "backup_password": "password", "password": "password", "backup": backup }),
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(result["course"], 1);
+        assert_eq!(result["topic"], 1);
+
+        let (_, courses) =
+            send_json(router.clone(), Method::GET, "/course", &new_token, None).await;
+        assert_eq!(courses.as_array().unwrap().len(), 1);
+        assert_eq!(courses[0]["name"], "Analysis");
+        let (_, topics) = send_json(router.clone(), Method::GET, "/topic", &new_token, None).await;
+        assert_eq!(topics[0]["name"], "Integrals");
+        assert_eq!(topics[0]["course_id"], courses[0]["id"]);
+        // links are remapped to the new ids
+        let (_, goals) = send_json(router, Method::GET, "/study_goal", &new_token, None).await;
+        assert_eq!(goals[0]["topic_ids"], json!([topics[0]["id"]]));
+        assert_eq!(
+            state
+                .db
+                .get_study_goal_topics_by_user(2)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn restored_local_tokens_replace_the_recovery_copies() {
+        let state = test_state().await;
+        let token = register_user(&state, "backup_reset_source").await;
+        let router = data_router(state.clone()).await;
+        let auth = auth_router(state.clone());
+        send_json(
+            router.clone(),
+            Method::POST,
+            "/course",
+            &token,
+            Some(json!({ "id": null, "name": "Analysis" })),
+        )
+        .await;
+        let (_, backup) = send_json(router.clone(), Method::GET, "/backup", &token, None).await;
+
+        // restores into a new account with the recovery key, then resets its password
+        let restore_and_reset = |username: &'static str, recovery_key: Option<&'static str>| {
+            let (router, auth, backup) = (router.clone(), auth.clone(), backup.clone());
+            async move {
+                let (_, registered) = send_json(
+                    auth.clone(),
+                    Method::POST,
+                    "/register",
+                    "",
+                    Some(json!({ "username": username, "password": "password" })),
+                )
+                .await;
+                let token = registered["token"].as_str().unwrap();
+                let key = registered["recovery_key"].as_str().unwrap();
+
+                let mut request = json!({ "backup_password": "password", "password": "password", "backup": backup });
+                if let Some(recovery_key) = recovery_key {
+                    let (status, _) = send_json(
+                        router.clone(),
+                        Method::POST,
+                        "/restore",
+                        token,
+                        Some(json!({ "recovery_key": recovery_key, "backup_password": "password", "password": "password", "backup": backup })),
+                    )
+                    .await;
+                    assert_eq!(status, StatusCode::FORBIDDEN);
+                    request["recovery_key"] = json!(key);
+                }
+                let (status, restored) = send_json(
+                    router.clone(),
+                    Method::POST,
+                    "/restore",
+                    token,
+                    Some(request),
+                )
+                .await;
+                assert_eq!(status, StatusCode::OK);
+
+                let (status, reset) = send_json(
+                    auth.clone(),
+                    Method::POST,
+                    "/reset-password",
+                    "",
+                    Some(json!({ "username": username, "recovery_key": key, "new_password": "new password" })),
+                )
+                .await;
+                assert_eq!(status, StatusCode::OK);
+                let (_, login) = send_json(
+                    auth,
+                    Method::POST,
+                    "/login",
+                    "",
+                    Some(json!({ "username": username, "password": "new password" })),
+                )
+                .await;
+                let (status, courses) = send_json(
+                    router,
+                    Method::GET,
+                    "/course",
+                    login["token"].as_str().unwrap(),
+                    None,
+                )
+                .await;
+                assert_eq!(status, StatusCode::OK);
+                (restored, reset, courses)
+            }
+        };
+
+        // a wrong key is rejected, the right one keeps the restored data recoverable
+        let (restored, reset, courses) = restore_and_reset("backup_reset_key", Some("wrong")).await;
+        assert!(restored.get("unrecoverable").is_none());
+        assert_eq!(reset["unrecoverable"], json!([]));
+        assert_eq!(courses[0]["name"], "Analysis");
+
+        // without the key the reset can not recover the restored types
+        let (restored, reset, courses) = restore_and_reset("backup_reset_no_key", None).await;
+        assert_eq!(restored["unrecoverable"].as_array().unwrap().len(), 5);
+        assert_eq!(reset["unrecoverable"], restored["unrecoverable"]);
+        assert_eq!(courses, json!([]));
+    }
+
+    #[tokio::test]
+    async fn backups_of_older_schemas_are_restored() {
+        let state = test_state().await;
+        let token = register_user(&state, "backup_old_source").await;
+        let router = data_router(state.clone()).await;
+
+        let course_id = create_course(router.clone(), &token).await;
+        let (_, topic) = send_json(
+            router.clone(),
+            Method::POST,
+            "/topic",
+            &token,
+            Some(json!({ "id": null, "course_id": course_id, "name": "Integrals", "details": "" })),
+        )
+        .await;
+        for (uri, body) in [
+            (
+                "/study_goal",
+                json!({ "id": null, "topic_id": topic["id"], "deadline": "2025-07-01" }),
+            ),
+            (
+                "/exam",
+                json!({ "id": null, "course_id": course_id, "name": "Analysis", "date": "2025-07-14" }),
+            ),
+            (
+                "/todo",
+                json!({ "id": null, "name": "Revise", "deadline": "2025-07-10", "details": "", "completed": false }),
+            ),
+        ] {
+            let (status, _) =
+                send_json(router.clone(), Method::POST, uri, &token, Some(body)).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+        let (_, mut backup) = send_json(router.clone(), Method::GET, "/backup", &token, None).await;
+
+        // rows as written by the first version of the backup, every later column is missing
+        for (table, columns) in [
+            ("course", &["id", "user_id", "name"][..]),
+            ("topic", &["id", "user_id", "course_id", "name", "details"]),
+            ("study_goal", &["id", "user_id", "topic_id", "deadline"]),
+            ("exam", &["id", "user_id", "course_id", "name", "date"]),
+            (
+                "todo",
+                &["id", "user_id", "name", "deadline", "details", "completed"],
+            ),
+        ] {
+            for row in backup[table].as_array_mut().unwrap() {
+                row.as_object_mut()
+                    .unwrap()
+                    .retain(|column, _| columns.contains(&column.as_str()));
+            }
+        }
+        backup.as_object_mut().unwrap().remove("study_goal_topic");
+        backup["manifest"]["schemas"] = json!({ "CourseDB": "0123456789abcdef" });
+
+        let new_token = register_user(&state, "backup_old_target").await;
+        let restore = |backup| {
+            send_json(
+                router.clone(),
+                Method::POST,
+                "/restore",
+                &new_token,
+                Some(
+                    json!({ "backup_password": "password", "password": "password", "backup": backup }),
+                ),
+            )
+        };
+
+        // columns without a default can not be filled in
+        let mut incomplete = backup.clone();
+        incomplete["exam"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("date");
+        let (status, body) = restore(incomplete).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["field_errors"][0]["field"], "backup.exam[0]");
+
+        let (status, result) = restore(backup).await;
+        assert_eq!(status, StatusCode::OK, "{}", result);
+        for table in ["course", "topic", "study_goal", "exam", "todo"] {
+            assert_eq!(result[table], 1, "{}", table);
+        }
+        let (_, courses) =
+            send_json(router.clone(), Method::GET, "/course", &new_token, None).await;
+        assert_eq!(courses[0]["name"], "Course");
+        assert_eq!(courses[0]["archived"], false);
+        let (_, exams) = send_json(router, Method::GET, "/exam", &new_token, None).await;
+        assert_eq!(exams[0]["prep_status"], "not_started");
+    }
+
+    #[tokio::test]
+    async fn restore_with_wrong_password_fails() {
+        let state = test_state().await;
+        let token = register_user(&state, "backup_wrong_source").await;
+        let router = data_router(state.clone()).await;
+
+        send_json(
+            router.clone(),
+            Method::POST,
+            "/course",
+            &token,
+            Some(json!({ "id": null, "name": "Analysis" })),
+        )
+        .await;
+        let (_, backup) = send_json(router.clone(), Method::GET, "/backup", &token, None).await;
+
+        let new_token = register_user(&state, "backup_wrong_target").await;
+        let (status, _) = send_json(
+            router.clone(),
+            Method::POST,
+            "/restore",
+            &new_token,
+            Some(json!({ "backup_password": "wrong", "password": "password", "backup": backup })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // nothing has been restored and the account is still usable
+        let (status, courses) = send_json(router, Method::GET, "/course", &new_token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(courses, json!([]));
+    }
+}
@@ -0,0 +1,635 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{extract::State, http::StatusCode};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState,
+    api_error::{ApiError, Json, Query},
+    auth_handler::AuthenticatedUser,
+    db::{
+        DBInterface, DbError,
+        sql_helper::{SQLGenerate, WhereParam},
+    },
+    db_param_map,
+};
+
+use super::{
+    IDBody, LocalKey, get_entries,
+    history::{HistoryAction, load_object, record_history},
+    objects::{CourseDB, CourseSend, ExamDB, ExamSend, StudyGoalDB, TopicDB, TopicSend},
+    record_change,
+    study_goal::{group_links, remove_links_for},
+    webhook::WebhookAction,
+};
+
+/// query parameters selecting archived courses, without them archived courses are left out
+#[derive(Deserialize, Debug, Default)]
+pub struct ArchivedQuery {
+    #[serde(default)]
+    include_archived: bool,
+    archived: Option<bool>,
+}
+impl ArchivedQuery {
+    /// filter on the courses, an explicit archived filter wins over include_archived
+    pub fn where_params(&self) -> Vec<WhereParam> {
+        match (self.archived, self.include_archived) {
+            (Some(archived), _) => db_param_map! { archived: archived },
+            (None, true) => vec![],
+            (None, false) => db_param_map! { archived: false },
+        }
+    }
+}
+
+/// applies the semantics of ArchivedQuery to the filters of the course list
+/// include_archived is removed, archived=false is added if neither is given
+pub(super) fn archived_filter(
+    mut query: HashMap<String, String>,
+) -> Result<HashMap<String, String>, StatusCode> {
+    let include_archived = match query.remove("include_archived") {
+        Some(value) => value.parse().map_err(|_| {
+            warn!("Invalid include_archived value: {}", value);
+            StatusCode::BAD_REQUEST
+        })?,
+        None => false,
+    };
+    if !include_archived && !query.contains_key("archived") {
+        query.insert("archived".to_string(), false.to_string());
+    }
+    Ok(query)
+}
+
+/// a course with its topics and exams, element of the response of GET /course/full
+#[derive(Deserialize, Serialize)]
+pub struct CourseTree {
+    pub course: CourseSend,
+    pub topics: Vec<TopicSend>,
+    pub exams: Vec<ExamSend>,
+}
+
+/// handler returning the courses of the user with their topics and exams embedded
+/// archived courses are selected like in the course list, see ArchivedQuery
+/// the token is verified once, the local token of each type is decrypted once
+pub async fn handle_get_course_tree<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Query(query): Query<ArchivedQuery>,
+) -> Result<Json<Vec<CourseTree>>, ApiError> {
+    info!("Courses with topics and exams requested!");
+
+    let courses: Vec<CourseSend> = get_entries(
+        &state,
+        &LocalKey::<CourseDB>::new(&state, user.clone()).await?,
+        query.where_params(),
+        &[],
+        None,
+    )
+    .await?;
+    let mut topics = group_by_course(
+        get_entries::<TopicDB, TopicSend, DB>(
+            &state,
+            &LocalKey::new(&state, user.clone()).await?,
+            vec![],
+            &[],
+            None,
+        )
+        .await?,
+        |topic| topic.course_id,
+    );
+    let mut exams = group_by_course(
+        get_entries::<ExamDB, ExamSend, DB>(
+            &state,
+            &LocalKey::new(&state, user).await?,
+            vec![],
+            &[],
+            None,
+        )
+        .await?,
+        |exam| exam.course_id,
+    );
+
+    Ok(Json(
+        courses
+            .into_iter()
+            .map(|course| {
+                let id = course.id.unwrap_or_default();
+                CourseTree {
+                    course,
+                    topics: topics.remove(&id).unwrap_or_default(),
+                    exams: exams.remove(&id).unwrap_or_default(),
+                }
+            })
+            .collect(),
+    ))
+}
+
+/// groups the children by the id of their course
+fn group_by_course<T>(children: Vec<T>, course_id: fn(&T) -> i32) -> HashMap<i32, Vec<T>> {
+    let mut grouped: HashMap<i32, Vec<T>> = HashMap::new();
+    for child in children {
+        grouped.entry(course_id(&child)).or_default().push(child);
+    }
+    grouped
+}
+
+/// response of a course deletion, the number of children deleted with the course
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct CourseDeletion {
+    pub id: i32,
+    pub topics: usize,
+    pub exams: usize,
+    pub study_goals: usize,
+}
+
+/// ids of the rows deleted with a course
+struct DeletedChildren {
+    topic_ids: Vec<i32>,
+    exam_ids: Vec<i32>,
+    goal_ids: Vec<i32>,
+}
+
+/// handler for deleting a course together with its topics, exams and the study goals of its topics
+pub(super) async fn handle_delete_course<DB: DBInterface + Send + Sync>(
+    key: LocalKey<CourseDB>,
+    State(state): State<Arc<AppState<DB>>>,
+    Json(request): Json<IDBody>,
+) -> Result<Json<CourseDeletion>, ApiError> {
+    info!("Course deletion requested!");
+
+    Ok(Json(remove_course(&state, &key, request.id).await?))
+}
+
+/// deletes the course with the id and its children in one transaction, if it belongs to the user
+/// like remove_entry the course is kept in the history, its children are not
+pub async fn remove_course<DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    key: &LocalKey<CourseDB>,
+    id: i32,
+) -> Result<CourseDeletion, StatusCode> {
+    let user_id = key.user.user_id;
+    let Some(previous) = load_object::<CourseDB, DB>(state, user_id, id).await? else {
+        // the course does not exist or belongs to someone else
+        warn!(
+            "Deletion of unknown course! (user id: {}, id: {})",
+            user_id, id
+        );
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let children = state
+        .db
+        .with_transaction(|db| Box::pin(delete_course_tree(db, user_id, id)))
+        .await
+        .map_err(|err| {
+            error!("Failed to delete course {}! (user id: {})", id, user_id);
+            StatusCode::from(err)
+        })?;
+    record_history(
+        state,
+        user_id,
+        &key.local_token,
+        HistoryAction::Delete,
+        id,
+        &previous,
+    )
+    .await?;
+    info!(
+        "Course deletion successful, {} topics, {} exams and {} study goals deleted with it.",
+        children.topic_ids.len(),
+        children.exam_ids.len(),
+        children.goal_ids.len()
+    );
+
+    for (ident, ids) in [
+        (StudyGoalDB::get_db_ident(), &children.goal_ids),
+        (TopicDB::get_db_ident(), &children.topic_ids),
+        (ExamDB::get_db_ident(), &children.exam_ids),
+        (CourseDB::get_db_ident(), &vec![id]),
+    ] {
+        for id in ids {
+            record_change(state, user_id, &ident, WebhookAction::Delete, *id).await;
+        }
+    }
+    // goals with topics of other courses keep those
+    for topic_id in &children.topic_ids {
+        remove_links_for::<TopicDB, DB>(state, user_id, *topic_id).await?;
+    }
+
+    Ok(CourseDeletion {
+        id,
+        topics: children.topic_ids.len(),
+        exams: children.exam_ids.len(),
+        study_goals: children.goal_ids.len(),
+    })
+}
+
+/// deletes the topics and exams of the course, the study goals of only these topics and the course itself
+/// fails with not found if the course has been deleted concurrently
+async fn delete_course_tree<DB: DBInterface>(
+    db: &DB,
+    user_id: i32,
+    course_id: i32,
+) -> Result<DeletedChildren, DbError> {
+    let topic_ids: Vec<i32> = db
+        .select_entries::<TopicDB>(
+            db_param_map! { course_id: course_id, user_id: user_id },
+            &[],
+            None,
+        )
+        .await?
+        .iter()
+        .map(|topic| topic.id)
+        .collect();
+    let exam_ids: Vec<i32> = db
+        .select_entries::<ExamDB>(
+            db_param_map! { course_id: course_id, user_id: user_id },
+            &[],
+            None,
+        )
+        .await?
+        .iter()
+        .map(|exam| exam.id)
+        .collect();
+
+    // goals without links only have the legacy topic, like in with_topic_ids
+    let links = group_links(db.get_study_goal_topics_by_user(user_id).await?);
+    let goal_ids: Vec<i32> = db
+        .select_entries::<StudyGoalDB>(db_param_map! { user_id: user_id }, &[], None)
+        .await?
+        .iter()
+        .filter(|goal| {
+            let topics = match links.get(&goal.id) {
+                Some(topics) => topics.clone(),
+                None if goal.topics_missing => Vec::new(),
+                None => vec![goal.topic_id],
+            };
+            !topics.is_empty() && topics.iter().all(|topic| topic_ids.contains(topic))
+        })
+        .map(|goal| goal.id)
+        .collect();
+
+    for goal_id in &goal_ids {
+        db.del_study_goal_topics_by_goal(user_id, *goal_id).await?;
+        db.delete_entry::<StudyGoalDB>(db_param_map! { id: *goal_id, user_id: user_id })
+            .await?;
+    }
+    db.delete_entry::<TopicDB>(db_param_map! { course_id: course_id, user_id: user_id })
+        .await?;
+    db.delete_entry::<ExamDB>(db_param_map! { course_id: course_id, user_id: user_id })
+        .await?;
+    if db
+        .delete_entry::<CourseDB>(db_param_map! { id: course_id, user_id: user_id })
+        .await?
+        == 0
+    {
+        return Err(DbError::NotFound);
+    }
+
+    Ok(DeletedChildren {
+        topic_ids,
+        exam_ids,
+        goal_ids,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        http::{Method, StatusCode},
+    };
+    use serde_json::{Value, json};
+
+    use crate::{
+        data_handler::data_router,
+        test_util::{create_course, register_user, send_json, test_state},
+    };
+
+    async fn create(router: Router, token: &str, uri: &str, body: Value) -> i64 {
+        let (status, body) = send_json(router, Method::POST, uri, token, Some(body)).await;
+        assert_eq!(status, StatusCode::OK);
+        body["id"].as_i64().unwrap()
+    }
+
+    #[tokio::test]
+    async fn deleting_a_course_deletes_its_children() {
+        let state = test_state().await;
+        let token = register_user(&state, "cascade_user").await;
+        let other = register_user(&state, "cascade_other").await;
+        let router = data_router(state.clone()).await;
+
+        let course_id = create_course(router.clone(), &token).await;
+        let kept_course_id = create_course(router.clone(), &token).await;
+        let foreign_course_id = create_course(router.clone(), &other).await;
+        let mut topics = Vec::new();
+        for course_id in [course_id, course_id, kept_course_id] {
+            let topic =
+                json!({ "id": null, "course_id": course_id, "name": "Topic", "details": "" });
+            topics.push(create(router.clone(), &token, "/topic", topic).await);
+        }
+        let exam =
+            json!({ "id": null, "course_id": course_id, "name": "Exam", "date": "2025-07-01" });
+        create(router.clone(), &token, "/exam", exam).await;
+        let goal = json!({ "id": null, "topic_id": topics[0], "deadline": "2025-06-01" });
+        create(router.clone(), &token, "/study_goal", goal).await;
+        // spans both courses, only loses the topic of the deleted one
+        let goal = json!({ "id": null, "topic_id": topics[1], "deadline": "2025-06-01" });
+        let mixed_goal_id = create(router.clone(), &token, "/study_goal", goal).await;
+        let (status, _) = send_json(
+            router.clone(),
+            Method::PUT,
+            &format!("/study_goal/{}/topics", mixed_goal_id),
+            &token,
+            Some(json!({ "topic_ids": [topics[1], topics[2]] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        // courses of other users can not be deleted
+        let (status, _) = send_json(
+            router.clone(),
+            Method::DELETE,
+            "/course",
+            &token,
+            Some(json!({ "id": foreign_course_id })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, body) = send_json(
+            router.clone(),
+            Method::DELETE,
+            "/course",
+            &token,
+            Some(json!({ "id": course_id })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            json!({ "id": course_id, "topics": 2, "exams": 1, "study_goals": 1 })
+        );
+
+        let (_, courses) = send_json(router.clone(), Method::GET, "/course", &token, None).await;
+        assert_eq!(courses.as_array().unwrap().len(), 1);
+        let (_, topics_left) = send_json(router.clone(), Method::GET, "/topic", &token, None).await;
+        assert_eq!(topics_left.as_array().unwrap().len(), 1);
+        let (_, exams) = send_json(router.clone(), Method::GET, "/exam", &token, None).await;
+        assert_eq!(exams, json!([]));
+        let (_, goals) = send_json(router.clone(), Method::GET, "/study_goal", &token, None).await;
+        assert_eq!(goals.as_array().unwrap().len(), 1);
+        assert_eq!(goals[0]["id"], mixed_goal_id);
+        assert_eq!(goals[0]["topic_ids"], json!([topics[2]]));
+
+        // the other user keeps everything
+        let (_, courses) = send_json(router, Method::GET, "/course", &other, None).await;
+        assert_eq!(courses.as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_course_deletion_is_not_recorded() {
+        let state = test_state().await;
+        let token = register_user(&state, "cascade_failed").await;
+        let router = data_router(state.clone()).await;
+        let course_id = create_course(router.clone(), &token).await;
+
+        state.db.execute_batch(
+            "CREATE TRIGGER fail_course_delete BEFORE DELETE ON CourseDB
+             BEGIN SELECT RAISE(ABORT, 'simulated failure'); END;",
+        );
+        let (status, _) = send_json(
+            router.clone(),
+            Method::DELETE,
+            "/course",
+            &token,
+            Some(json!({ "id": course_id })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+        let history_uri = format!("/course/{}/history", course_id);
+        let (_, history) = send_json(router.clone(), Method::GET, &history_uri, &token, None).await;
+        assert_eq!(history, json!([]));
+
+        state.db.execute_batch("DROP TRIGGER fail_course_delete;");
+        let (status, _) = send_json(
+            router.clone(),
+            Method::DELETE,
+            "/course",
+            &token,
+            Some(json!({ "id": course_id })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, history) = send_json(router, Method::GET, &history_uri, &token, None).await;
+        assert_eq!(history[0]["action"], "delete");
+    }
+
+    #[tokio::test]
+    async fn full_courses_embed_their_topics_and_exams() {
+        let state = test_state().await;
+        let token = register_user(&state, "tree_user").await;
+        let other = register_user(&state, "tree_other").await;
+        let router = data_router(state.clone()).await;
+
+        let course_id = create_course(router.clone(), &token).await;
+        let empty_course_id = create_course(router.clone(), &token).await;
+        let foreign_course_id = create_course(router.clone(), &other).await;
+        for name in ["Limits", "Integrals"] {
+            let topic = json!({ "id": null, "course_id": course_id, "name": name, "details": "" });
+            create(router.clone(), &token, "/topic", topic).await;
+        }
+        let exam =
+            json!({ "id": null, "course_id": course_id, "name": "Exam", "date": "2025-07-01" });
+        create(router.clone(), &token, "/exam", exam).await;
+        let topic =
+            json!({ "id": null, "course_id": foreign_course_id, "name": "Foreign", "details": "" });
+        create(router.clone(), &other, "/topic", topic).await;
+
+        let (status, body) = send_json(router, Method::GET, "/course/full", &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let courses = body.as_array().unwrap();
+        assert_eq!(courses.len(), 2);
+        let full = courses
+            .iter()
+            .find(|tree| tree["course"]["id"] == course_id)
+            .unwrap();
+        assert_eq!(full["course"]["name"], "Course");
+        let names: Vec<&Value> = full["topics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|topic| &topic["name"])
+            .collect();
+        assert_eq!(names, [&json!("Limits"), &json!("Integrals")]);
+        assert_eq!(full["exams"][0]["name"], "Exam");
+        let empty = courses
+            .iter()
+            .find(|tree| tree["course"]["id"] == empty_course_id)
+            .unwrap();
+        assert_eq!(empty["topics"], json!([]));
+        assert_eq!(empty["exams"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn courses_are_filtered_by_semester_and_kept_when_it_is_deleted() {
+        let state = test_state().await;
+        let token = register_user(&state, "semester_user").await;
+        let other = register_user(&state, "semester_other").await;
+        let router = data_router(state.clone()).await;
+
+        let semester = |name: &str| json!({ "id": null, "name": name, "start_date": "2025-10-01", "end_date": "2026-03-31" });
+        let winter = create(router.clone(), &token, "/semester", semester("Winter")).await;
+        let summer = create(router.clone(), &token, "/semester", semester("Summer")).await;
+        let foreign = create(router.clone(), &other, "/semester", semester("Other")).await;
+        let (status, _) = send_json(
+            router.clone(),
+            Method::POST,
+            "/semester",
+            &token,
+            Some(json!({ "id": null, "name": "Backwards", "start_date": "2026-03-31", "end_date": "2025-10-01" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let analysis = create(
+            router.clone(),
+            &token,
+            "/course",
+            json!({ "id": null, "name": "Analysis", "semester_id": winter }),
+        )
+        .await;
+        create(
+            router.clone(),
+            &token,
+            "/course",
+            json!({ "id": null, "name": "Algebra", "semester_id": summer }),
+        )
+        .await;
+        let loose = create_course(router.clone(), &token).await;
+        let (status, errors) = send_json(
+            router.clone(),
+            Method::POST,
+            "/course",
+            &token,
+            Some(json!({ "id": null, "name": "Foreign", "semester_id": foreign })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(errors["field_errors"][0]["field"], "semester_id");
+
+        let (status, courses) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!("/course?semester_id={}", winter),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(courses.as_array().unwrap().len(), 1);
+        assert_eq!(courses[0]["name"], "Analysis");
+        let (_, courses) = send_json(
+            router.clone(),
+            Method::GET,
+            "/course?semester_id=null",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(courses.as_array().unwrap().len(), 1);
+        assert_eq!(courses[0]["id"], loose);
+
+        // the courses of a deleted semester are kept without semester
+        let (status, _) = send_json(
+            router.clone(),
+            Method::DELETE,
+            "/semester",
+            &token,
+            Some(json!({ "id": winter })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, courses) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!("/course?id={}", analysis),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(courses[0]["name"], "Analysis");
+        assert_eq!(courses[0]["semester_id"], Value::Null);
+        let (_, semesters) = send_json(router, Method::GET, "/semester", &token, None).await;
+        assert_eq!(semesters.as_array().unwrap().len(), 1);
+        assert_eq!(semesters[0]["name"], "Summer");
+    }
+
+    #[tokio::test]
+    async fn archived_courses_are_left_out_unless_requested() {
+        let state = test_state().await;
+        let token = register_user(&state, "archive_user").await;
+        let router = data_router(state.clone()).await;
+
+        let archived_id = create_course(router.clone(), &token).await;
+        let active_id = create_course(router.clone(), &token).await;
+        let topic =
+            json!({ "id": null, "course_id": archived_id, "name": "Limits", "details": "" });
+        create(router.clone(), &token, "/topic", topic).await;
+        let (status, _) = send_json(
+            router.clone(),
+            Method::PATCH,
+            "/course",
+            &token,
+            Some(json!({ "id": archived_id, "archived": true })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let get = |uri: &'static str| send_json(router.clone(), Method::GET, uri, &token, None);
+        let course_ids = |body: Value| -> Vec<i64> {
+            body.as_array()
+                .unwrap()
+                .iter()
+                .map(|course| course["id"].as_i64().unwrap())
+                .collect()
+        };
+        let (_, courses) = get("/course").await;
+        assert_eq!(course_ids(courses), [active_id]);
+        let (_, courses) = get("/course?include_archived=true").await;
+        assert_eq!(course_ids(courses), [archived_id, active_id]);
+        let (_, courses) = get("/course?archived=true").await;
+        assert_eq!(courses[0]["archived"], true);
+        assert_eq!(course_ids(courses), [archived_id]);
+        let (_, count) = get("/course/count").await;
+        assert_eq!(count["count"], 1);
+        let (status, _) = get("/course?include_archived=maybe").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (_, trees) = get("/course/full").await;
+        assert_eq!(trees.as_array().unwrap().len(), 1);
+        assert_eq!(trees[0]["course"]["id"], active_id);
+        let (_, trees) = get("/course/full?archived=true").await;
+        assert_eq!(trees[0]["topics"][0]["name"], "Limits");
+        let (_, stats) = get("/stats").await;
+        assert_eq!(stats["courses"], 1);
+        let (_, stats) = get("/stats?include_archived=true").await;
+        assert_eq!(stats["courses"], 2);
+
+        // the topics stay untouched and the course comes back when it is unarchived
+        let (_, topics) = get("/topic").await;
+        assert_eq!(topics.as_array().unwrap().len(), 1);
+        let (status, _) = send_json(
+            router.clone(),
+            Method::POST,
+            "/course",
+            &token,
+            Some(json!({ "id": archived_id, "name": "Course", "archived": false })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, courses) = get("/course").await;
+        assert_eq!(course_ids(courses), [archived_id, active_id]);
+    }
+}
@@ -0,0 +1,675 @@
+use std::{any::type_name, collections::HashMap, str::FromStr, sync::Arc};
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::NaiveDate;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState,
+    api_error::{ApiError, FieldError, Json, Query},
+    db::{DBInterface, sql_helper::SQLGenerate},
+};
+
+use super::{
+    CheckReferences, FromDB, LocalKey, MAX_BULK_SIZE, ToDB, get_entries, insert_entries,
+    objects::{CourseSend, ToDoSend, TopicSend},
+    reference_errors,
+};
+
+/// query parameters of the csv import
+#[derive(Deserialize, Debug)]
+pub struct CsvImportQuery {
+    /// import the valid rows and skip the others, instead of rejecting the whole file
+    partial: Option<bool>,
+}
+
+/// response of a csv import
+#[derive(Deserialize, Serialize, Debug)]
+pub struct CsvImportResult {
+    /// ids of the created objects, in the order of the rows
+    pub ids: Vec<i32>,
+    /// errors of the rows that were not imported, only with partial=true
+    pub skipped: Vec<FieldError>,
+}
+
+/// a parsed row of a csv file, values by the column names of the header
+/// conversion errors are collected instead of returned, so every invalid value of a row is reported
+pub struct CsvRow<'a> {
+    line: usize,
+    values: HashMap<&'a str, &'a str>,
+    errors: Vec<FieldError>,
+}
+
+impl CsvRow<'_> {
+    fn error(&mut self, column: &str, reason: &str) {
+        self.errors.push(FieldError {
+            field: format!("{}.{}", self.line, column),
+            message: reason.to_string(),
+        });
+    }
+
+    /// text of a required column
+    fn text(&mut self, column: &str) -> String {
+        match self.values.get(column) {
+            Some(value) => value.to_string(),
+            None => {
+                self.error(column, "is missing");
+                String::new()
+            }
+        }
+    }
+
+    /// text of an optional column, empty if the column is missing
+    fn optional_text(&self, column: &str) -> String {
+        self.values.get(column).unwrap_or(&"").to_string()
+    }
+
+    /// date of a required column, ISO 8601 (yyyy-mm-dd)
+    fn date(&mut self, column: &str) -> NaiveDate {
+        let value = self.text(column);
+        NaiveDate::parse_from_str(&value, "%Y-%m-%d").unwrap_or_else(|_| {
+            self.error(column, "is not a date (yyyy-mm-dd)");
+            NaiveDate::default()
+        })
+    }
+
+    /// integer of a required column
+    fn int(&mut self, column: &str) -> i32 {
+        let value = self.text(column);
+        value.trim().parse().unwrap_or_else(|_| {
+            self.error(column, "is not a number");
+            0
+        })
+    }
+
+    /// parsed value of an optional column, none if the column is missing or empty
+    fn optional<T: FromStr>(&mut self, column: &str, reason: &str) -> Option<T> {
+        let value = self.optional_text(column);
+        let value = value.trim();
+        if value.is_empty() {
+            return None;
+        }
+        value.parse().map_or_else(
+            |_| {
+                self.error(column, reason);
+                None
+            },
+            Some,
+        )
+    }
+
+    /// true or false of an optional column, false if the column is missing or empty
+    fn flag(&mut self, column: &str) -> bool {
+        match self.optional_text(column).trim().to_lowercase().as_str() {
+            "" | "false" => false,
+            "true" => true,
+            _ => {
+                self.error(column, "is not true or false");
+                false
+            }
+        }
+    }
+}
+
+/// implemented by send types that can be exported to and imported from csv
+/// the id is exported, but ignored on import, server set fields (timestamps) are left out
+pub trait CsvRecord: Sized {
+    /// columns of the header, in order
+    fn csv_columns() -> &'static [&'static str];
+    /// values of the columns, in the order of csv_columns
+    fn to_csv_record(&self) -> Vec<String>;
+    /// builds a new object (without id) from a row, invalid values are collected in the row
+    fn from_csv_record(row: &mut CsvRow) -> Self;
+}
+impl CsvRecord for CourseSend {
+    fn csv_columns() -> &'static [&'static str] {
+        &["id", "name"]
+    }
+
+    fn to_csv_record(&self) -> Vec<String> {
+        vec![id_text(self.id), self.name.clone()]
+    }
+
+    fn from_csv_record(row: &mut CsvRow) -> Self {
+        Self {
+            id: None,
+            name: row.text("name"),
+            semester_id: None,
+            archived: false,
+            created_at: Default::default(),
+            updated_at: None,
+        }
+    }
+}
+impl CsvRecord for TopicSend {
+    fn csv_columns() -> &'static [&'static str] {
+        &["id", "course_id", "name", "details", "studied"]
+    }
+
+    fn to_csv_record(&self) -> Vec<String> {
+        vec![
+            id_text(self.id),
+            self.course_id.to_string(),
+            self.name.clone(),
+            self.details.clone(),
+            self.studied.to_string(),
+        ]
+    }
+
+    fn from_csv_record(row: &mut CsvRow) -> Self {
+        Self {
+            id: None,
+            course_id: row.int("course_id"),
+            name: row.text("name"),
+            details: row.optional_text("details"),
+            studied: row.flag("studied"),
+            // appended to the course in the order of the rows
+            position: 0,
+            created_at: Default::default(),
+            updated_at: None,
+        }
+    }
+}
+impl CsvRecord for ToDoSend {
+    fn csv_columns() -> &'static [&'static str] {
+        &[
+            "id",
+            "name",
+            "deadline",
+            "details",
+            "completed",
+            "priority",
+            "parent_todo_id",
+            "recurrence",
+            "recurrence_until",
+        ]
+    }
+
+    fn to_csv_record(&self) -> Vec<String> {
+        vec![
+            id_text(self.id),
+            self.name.clone(),
+            self.deadline.to_string(),
+            self.details.clone(),
+            self.completed.to_string(),
+            self.priority.to_string(),
+            id_text(self.parent_todo_id),
+            self.recurrence
+                .map(|recurrence| recurrence.to_string())
+                .unwrap_or_default(),
+            self.recurrence_until
+                .map(|date| date.to_string())
+                .unwrap_or_default(),
+        ]
+    }
+
+    fn from_csv_record(row: &mut CsvRow) -> Self {
+        Self {
+            id: None,
+            name: row.text("name"),
+            deadline: row.date("deadline"),
+            details: row.optional_text("details"),
+            completed: row.flag("completed"),
+            completed_at: None,
+            priority: row
+                .optional("priority", "is not a number")
+                .unwrap_or_default(),
+            parent_todo_id: row.optional("parent_todo_id", "is not a number"),
+            recurrence: row.optional("recurrence", "is not daily, weekly or monthly"),
+            recurrence_until: row.optional("recurrence_until", "is not a date (yyyy-mm-dd)"),
+            created_at: Default::default(),
+            updated_at: None,
+        }
+    }
+}
+
+fn id_text(id: Option<i32>) -> String {
+    id.map(|id| id.to_string()).unwrap_or_default()
+}
+
+/// quotes a value if it contains a separator, a quote or a line break (RFC 4180)
+fn quote(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// writes the records as csv, lines end with CRLF
+fn write_csv<'a>(records: impl Iterator<Item = Vec<&'a str>>) -> String {
+    let mut csv = String::new();
+    for record in records {
+        let line: Vec<String> = record.into_iter().map(quote).collect();
+        csv.push_str(&line.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+/// reads the records of a csv file (RFC 4180), together with the line each record starts on
+/// quoted values can contain separators, line breaks and doubled quotes, lines can end with LF or CRLF
+/// empty lines are skipped, fails for unterminated quotes and text after a closing quote
+fn parse_csv(text: &str) -> Result<Vec<(usize, Vec<String>)>, FieldError> {
+    // spreadsheet programs like to start their files with a byte order mark
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+
+    let mut records = Vec::new();
+    let (mut record, mut value) = (Vec::new(), String::new());
+    let (mut line, mut record_line) = (1, 1);
+    let mut chars = text.chars().peekable();
+    let mut quoted = false;
+    let mut was_quoted = false;
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    value.push('"');
+                }
+                '"' => quoted = false,
+                '\n' => {
+                    line += 1;
+                    value.push(c);
+                }
+                _ => value.push(c),
+            }
+            continue;
+        }
+
+        match c {
+            '"' if value.is_empty() && !was_quoted => {
+                quoted = true;
+                was_quoted = true;
+            }
+            ',' => {
+                record.push(std::mem::take(&mut value));
+                was_quoted = false;
+            }
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                if was_quoted || !value.is_empty() || !record.is_empty() {
+                    record.push(std::mem::take(&mut value));
+                    records.push((record_line, std::mem::take(&mut record)));
+                }
+                was_quoted = false;
+                line += 1;
+                record_line = line;
+            }
+            _ if was_quoted => {
+                return Err(FieldError {
+                    field: line.to_string(),
+                    message: "has text after a closing quote".to_string(),
+                });
+            }
+            _ => value.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(FieldError {
+            field: record_line.to_string(),
+            message: "has an unterminated quote".to_string(),
+        });
+    }
+    // the last line does not need a line break
+    if was_quoted || !value.is_empty() || !record.is_empty() {
+        record.push(value);
+        records.push((record_line, record));
+    }
+    Ok(records)
+}
+
+/// handler for csv exports, returns the objects of the type decrypted as a csv download with a header row
+pub async fn handle_export_csv<
+    DBT: SQLGenerate,
+    ST: FromDB<DBT> + CsvRecord,
+    DB: DBInterface + Send + Sync,
+>(
+    key: LocalKey<DBT>,
+    State(state): State<Arc<AppState<DB>>>,
+) -> Result<Response, ApiError> {
+    info!("CSV export of {} requested!", type_name::<DBT>());
+
+    let entries: Vec<ST> = get_entries(&state, &key, vec![], &[], None).await?;
+    let records: Vec<Vec<String>> = entries.iter().map(CsvRecord::to_csv_record).collect();
+    let csv = write_csv(
+        std::iter::once(ST::csv_columns().to_vec()).chain(
+            records
+                .iter()
+                .map(|record| record.iter().map(String::as_str).collect()),
+        ),
+    );
+
+    info!(
+        "CSV export of {} successful, building response!",
+        type_name::<DBT>()
+    );
+    let disposition = HeaderValue::from_str(&format!(
+        "attachment; filename=\"{}.csv\"",
+        DBT::get_db_table()
+    ))
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("text/csv; charset=utf-8"),
+            ),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        csv,
+    )
+        .into_response())
+}
+
+/// handler for csv imports, the body is a csv file with a header row naming the columns
+/// every row is validated, errors are named by line and column (e.g. 3.deadline)
+/// invalid rows reject the whole file, unless partial=true is passed, then they are skipped
+/// the valid rows are created in one transaction
+pub async fn handle_import_csv<
+    DBT: SQLGenerate,
+    ST: ToDB + CheckReferences + CsvRecord + Serialize,
+    DB: DBInterface + Send + Sync,
+>(
+    key: LocalKey<DBT>,
+    State(state): State<Arc<AppState<DB>>>,
+    Query(query): Query<CsvImportQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Json<CsvImportResult>, ApiError> {
+    info!("CSV import of {} requested!", type_name::<DBT>());
+
+    let is_csv = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/csv"));
+    if !is_csv {
+        return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE.into());
+    }
+
+    let mut records = parse_csv(&body)
+        .map_err(|error| ApiError::unprocessable(vec![error]))?
+        .into_iter();
+    let Some((_, header)) = records.next() else {
+        return Err(ApiError::unprocessable(vec![FieldError {
+            field: "body".to_string(),
+            message: "has no header row".to_string(),
+        }]));
+    };
+    let records: Vec<(usize, Vec<String>)> = records.collect();
+    if records.len() > MAX_BULK_SIZE {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let user_id = key.user.user_id;
+    let (mut entries, mut errors) = (Vec::new(), Vec::new());
+    for (line, record) in &records {
+        if record.len() > header.len() {
+            errors.push(FieldError {
+                field: line.to_string(),
+                message: "has more values than the header".to_string(),
+            });
+            continue;
+        }
+        // missing values at the end of a row are left out, like spreadsheets do
+        let mut row = CsvRow {
+            line: *line,
+            values: header
+                .iter()
+                .map(|column| column.trim())
+                .zip(record.iter().map(String::as_str))
+                .collect(),
+            errors: Vec::new(),
+        };
+        let entry = ST::from_csv_record(&mut row);
+
+        if let Err(limit_errors) = state.payload_limits.check(&entry) {
+            row.errors
+                .extend(limit_errors.into_iter().map(|error| FieldError {
+                    field: format!("{}.{}", line, error.field),
+                    message: error.message,
+                }));
+        }
+        // references are only checked for otherwise valid rows
+        if row.errors.is_empty() {
+            for error in reference_errors(&state, user_id, &entry).await? {
+                row.error(&error.field, &error.message);
+            }
+        }
+
+        if row.errors.is_empty() {
+            entries.push(entry);
+        } else {
+            errors.extend(row.errors);
+        }
+    }
+
+    if !errors.is_empty() && !query.partial.unwrap_or(false) {
+        info!(
+            "CSV import of {} rejected, {} invalid values.",
+            type_name::<DBT>(),
+            errors.len()
+        );
+        return Err(ApiError::unprocessable(errors));
+    }
+
+    let ids = if entries.is_empty() {
+        Vec::new()
+    } else {
+        insert_entries::<DBT, ST, DB>(&state, &key, &entries).await?
+    };
+    info!(
+        "CSV import of {} successful, {} rows created.",
+        type_name::<DBT>(),
+        ids.len()
+    );
+
+    Ok(Json(CsvImportResult {
+        ids,
+        skipped: errors,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        body::{Body, to_bytes},
+        http::{Method, Request},
+    };
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        data_handler::data_router,
+        test_util::{create_course, register_user, send_json, test_state},
+    };
+
+    /// sends a request with a raw body, returns the status, the content type and the body as text
+    async fn send_text(
+        router: Router,
+        method: Method,
+        uri: &str,
+        token: &str,
+        body: &str,
+    ) -> (StatusCode, String, String) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("authorization", format!("Bearer {}", token))
+            .header("content-type", "text/csv")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .map(|value| value.to_str().unwrap().to_string())
+            .unwrap_or_default();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (
+            status,
+            content_type,
+            String::from_utf8(bytes.to_vec()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn csv_is_read_and_written_like_rfc_4180() {
+        let csv = write_csv(
+            [
+                vec!["name", "details"],
+                vec!["Limits, Series", "say \"hi\"\nnext line"],
+            ]
+            .into_iter(),
+        );
+        assert_eq!(
+            csv,
+            "name,details\r\n\"Limits, Series\",\"say \"\"hi\"\"\nnext line\"\r\n"
+        );
+        assert_eq!(
+            parse_csv(&csv).unwrap(),
+            [
+                (1, vec!["name".to_string(), "details".to_string()]),
+                (
+                    2,
+                    vec![
+                        "Limits, Series".to_string(),
+                        "say \"hi\"\nnext line".to_string()
+                    ]
+                ),
+            ]
+        );
+
+        // line numbers count the line breaks inside of quotes, empty lines are skipped
+        let records = parse_csv("\u{feff}a,b\n\"x\ny\",1\n\nlast,\"\"").unwrap();
+        assert_eq!(records[1].0, 2);
+        assert_eq!(records[2], (5, vec!["last".to_string(), String::new()]));
+
+        assert_eq!(parse_csv("a\n\"open").unwrap_err().field, "2");
+        assert_eq!(parse_csv("\"a\"b").unwrap_err().field, "1");
+    }
+
+    #[tokio::test]
+    async fn todos_are_exported_and_imported_as_csv() {
+        let state = test_state().await;
+        let token = register_user(&state, "csv_user").await;
+        let router = data_router(state.clone()).await;
+
+        let (status, _, body) = send_text(
+            router.clone(),
+            Method::POST,
+            "/todo/import",
+            &token,
+            "id,name,deadline,details,completed,priority,recurrence\r\n\
+             99,\"Laundry, whites\",2025-07-01,\"two \"\"loads\"\"\",false,2,weekly\r\n\
+             ,Taxes,2025-05-31,,TRUE,,\r\n",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["ids"].as_array().unwrap().len(), 2);
+        assert_eq!(result["skipped"], json!([]));
+        // the id column is ignored, new todos are created
+        assert_ne!(result["ids"][0], 99);
+
+        let (status, content_type, csv) =
+            send_text(router.clone(), Method::GET, "/todo/export.csv", &token, "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "text/csv; charset=utf-8");
+        assert_eq!(
+            csv,
+            format!(
+                "id,name,deadline,details,completed,priority,parent_todo_id,recurrence,recurrence_until\r\n\
+                 {},\"Laundry, whites\",2025-07-01,\"two \"\"loads\"\"\",false,2,,weekly,\r\n\
+                 {},Taxes,2025-05-31,,true,0,,,\r\n",
+                result["ids"][0], result["ids"][1]
+            )
+        );
+
+        // invalid rows reject the whole file, errors are named by line and column
+        let file = "name,deadline,completed,recurrence\n\
+                    Dishes,2025-07-02,false,\n\
+                    Groceries,tomorrow,maybe,sometimes\n\
+                    ,2025-07-03\n";
+        let (status, _, body) =
+            send_text(router.clone(), Method::POST, "/todo/import", &token, file).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            serde_json::from_str::<Value>(&body).unwrap()["field_errors"],
+            json!([
+                { "field": "3.deadline", "message": "is not a date (yyyy-mm-dd)" },
+                { "field": "3.completed", "message": "is not true or false" },
+                { "field": "3.recurrence", "message": "is not daily, weekly or monthly" },
+            ])
+        );
+        let (_, todos) = send_json(router.clone(), Method::GET, "/todo", &token, None).await;
+        assert_eq!(todos.as_array().unwrap().len(), 2);
+
+        // with partial the valid rows are imported (an empty name is valid)
+        let (status, _, body) = send_text(
+            router.clone(),
+            Method::POST,
+            "/todo/import?partial=true",
+            &token,
+            file,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["ids"].as_array().unwrap().len(), 2);
+        assert_eq!(result["skipped"][0]["field"], "3.deadline");
+        let (_, todos) = send_json(router.clone(), Method::GET, "/todo", &token, None).await;
+        assert_eq!(todos.as_array().unwrap().len(), 4);
+
+        // json bodies are not mistaken for csv
+        let (status, _) = send_json(
+            router,
+            Method::POST,
+            "/todo/import",
+            &token,
+            Some(json!({ "name": "Laundry" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn imported_topics_need_a_course_of_the_user() {
+        let state = test_state().await;
+        let token = register_user(&state, "csv_topics").await;
+        let other = register_user(&state, "csv_other").await;
+        let router = data_router(state.clone()).await;
+        let course_id = create_course(router.clone(), &token).await;
+        let foreign_course_id = create_course(router.clone(), &other).await;
+
+        let file = format!(
+            "course_id,name\n{},Limits\n{},Foreign\nabc,Broken\n",
+            course_id, foreign_course_id
+        );
+        let (status, _, body) =
+            send_text(router.clone(), Method::POST, "/topic/import", &token, &file).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            serde_json::from_str::<Value>(&body).unwrap()["field_errors"],
+            json!([
+                { "field": "3.course_id", "message": "does not exist" },
+                { "field": "4.course_id", "message": "is not a number" },
+            ])
+        );
+
+        let (status, _, csv) =
+            send_text(router, Method::GET, "/topic/export.csv", &token, "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(csv, "id,course_id,name,details,studied\r\n");
+    }
+}
@@ -0,0 +1,695 @@
+use std::{
+    any::type_name,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use axum::{
+    extract::State,
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::{NaiveDateTime, Utc};
+use log::{error, info};
+use rusqlite::types::Value;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState,
+    api_error::{ApiError, FieldError, Json},
+    auth_handler::{AuthenticatedUser, decrypt_local_token_for},
+    crypt::crypt_provider::CryptProviders,
+    db::{DBInterface, DbError, sql_helper::SQLGenerate},
+    db_param_map,
+};
+
+use super::{
+    FromDB, LocalKey, ToDB,
+    backup::RestoreResult,
+    decrypt_failed_status,
+    objects::{
+        CourseDB, CourseSend, ExamDB, ExamSend, StudyGoalDB, StudyGoalSend, ToDoDB, ToDoSend,
+        TopicDB, TopicSend, WebhookSubscriptionDB, WebhookSubscriptionSend,
+    },
+    record_change,
+    study_goal::group_links,
+    webhook::WebhookAction,
+};
+
+/// everything a user owns, decrypted and grouped by type
+/// auth data (tokens, password hash) is not part of it
+/// can be imported into another account, see handle_import
+#[derive(Serialize, Deserialize)]
+pub struct UserExport {
+    #[serde(default)]
+    exported_at: NaiveDateTime,
+    course: Vec<CourseSend>,
+    topic: Vec<TopicSend>,
+    study_goal: Vec<StudyGoalSend>,
+    exam: Vec<ExamSend>,
+    todo: Vec<ToDoSend>,
+    /// todos in the trash, purged after the grace period
+    #[serde(default)]
+    todo_trash: Vec<ToDoSend>,
+    #[serde(default)]
+    webhook_subscription: Vec<WebhookSubscriptionSend>,
+}
+
+/// ids of the objects created by an import, per type
+#[derive(Default)]
+struct ImportedIds {
+    course: Vec<i32>,
+    topic: Vec<i32>,
+    study_goal: Vec<i32>,
+    exam: Vec<i32>,
+    todo: Vec<i32>,
+}
+
+/// decrypted local tokens of the imported types
+struct ImportTokens {
+    course: String,
+    topic: String,
+    study_goal: String,
+    exam: String,
+    todo: String,
+}
+
+/// tables of every type contained in an export
+fn export_tables() -> [&'static str; 6] {
+    [
+        CourseDB::get_db_table(),
+        TopicDB::get_db_table(),
+        StudyGoalDB::get_db_table(),
+        ExamDB::get_db_table(),
+        ToDoDB::get_db_table(),
+        WebhookSubscriptionDB::get_db_table(),
+    ]
+}
+
+/// decrypts the exported rows of DBT into the send type, split into rows outside and inside of the trash
+async fn export_rows<DBT: SQLGenerate, ST: FromDB<DBT>, DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    user: &AuthenticatedUser,
+    tables: &mut HashMap<String, Vec<HashMap<String, Value>>>,
+) -> Result<(Vec<ST>, Vec<ST>), StatusCode> {
+    let ident = DBT::get_db_ident();
+    let local_token = decrypt_local_token_for(
+        user.user_id,
+        &ident,
+        user.remote_token_id,
+        &user.remote_token,
+        state.clone(),
+    )
+    .await
+    .map_err(|err| decrypt_failed_status(err, &ident, user.remote_token_id))?;
+
+    let mut entries = (Vec::new(), Vec::new());
+    for row in tables.remove(DBT::get_db_table()).unwrap_or_default() {
+        let entry = DBT::values_to_struct(&row)
+            .map_err(|err| err.to_string())
+            .and_then(|dbt| {
+                ST::from_dbt(&dbt, local_token.as_bytes(), &state.crypt_provider)
+                    .map_err(|err| err.to_string())
+            })
+            .map_err(|err| {
+                error!(
+                    "Failed to export {} entry! (user id: {}): {}",
+                    type_name::<DBT>(),
+                    user.user_id,
+                    err
+                );
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        match row.get("deleted_at") {
+            Some(Value::Null) | None => entries.0.push(entry),
+            Some(_) => entries.1.push(entry),
+        }
+    }
+    Ok(entries)
+}
+
+/// handler for export requests, returns every object of the user decrypted as a json download
+pub async fn handle_export<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+) -> Result<Response, ApiError> {
+    info!("Export requested!");
+
+    let data = state
+        .db
+        .export_user_data(user.user_id, &export_tables())
+        .await
+        .map_err(|err| {
+            error!(
+                "Failed to load the data of user {} for export!",
+                user.user_id
+            );
+            StatusCode::from(err)
+        })?;
+    let mut tables = data.tables;
+
+    let (course, _) = export_rows::<CourseDB, CourseSend, DB>(&state, &user, &mut tables).await?;
+    let (topic, _) = export_rows::<TopicDB, TopicSend, DB>(&state, &user, &mut tables).await?;
+    let (mut study_goal, _) =
+        export_rows::<StudyGoalDB, StudyGoalSend, DB>(&state, &user, &mut tables).await?;
+    let (exam, _) = export_rows::<ExamDB, ExamSend, DB>(&state, &user, &mut tables).await?;
+    let (todo, todo_trash) =
+        export_rows::<ToDoDB, ToDoSend, DB>(&state, &user, &mut tables).await?;
+    let (webhook_subscription, _) =
+        export_rows::<WebhookSubscriptionDB, WebhookSubscriptionSend, DB>(
+            &state,
+            &user,
+            &mut tables,
+        )
+        .await?;
+
+    // goals without links keep their legacy topic, like in handle_get_study_goals
+    let mut links = group_links(data.study_goal_topics);
+    for goal in study_goal.iter_mut() {
+        if let Some(topic_ids) = goal.id.and_then(|id| links.remove(&id)) {
+            goal.topic_ids = topic_ids;
+        }
+    }
+
+    let export = UserExport {
+        exported_at: Utc::now().naive_utc(),
+        course,
+        topic,
+        study_goal,
+        exam,
+        todo,
+        todo_trash,
+        webhook_subscription,
+    };
+
+    info!("Export successful, building response!");
+    // browsers save the document instead of displaying it
+    let disposition = HeaderValue::from_static("attachment; filename=\"eduflow-export.json\"");
+    Ok(([(header::CONTENT_DISPOSITION, disposition)], Json(export)).into_response())
+}
+
+/// checks that the ids of each type are unique and that every reference points to an object of the document
+/// errors are named by type, index and field, e.g. topic.0.course_id
+fn reference_errors(document: &UserExport) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    let mut error = |field: String, reason: &str| {
+        errors.push(FieldError {
+            field,
+            message: reason.to_string(),
+        })
+    };
+
+    let mut unique_ids = |group: &str, ids: Vec<Option<i32>>| {
+        let mut seen = HashSet::new();
+        for (i, id) in ids.into_iter().enumerate() {
+            if id.is_some_and(|id| !seen.insert(id)) {
+                error(format!("{}.{}.id", group, i), "is used twice");
+            }
+        }
+        seen
+    };
+    let courses = unique_ids("course", document.course.iter().map(|c| c.id).collect());
+    let topics = unique_ids("topic", document.topic.iter().map(|t| t.id).collect());
+    let exams = unique_ids("exam", document.exam.iter().map(|e| e.id).collect());
+    let todos = unique_ids("todo", document.todo.iter().map(|t| t.id).collect());
+
+    for (i, topic) in document.topic.iter().enumerate() {
+        if !courses.contains(&topic.course_id) {
+            error(format!("topic.{}.course_id", i), "does not exist");
+        }
+    }
+    for (i, exam) in document.exam.iter().enumerate() {
+        if !courses.contains(&exam.course_id) {
+            error(format!("exam.{}.course_id", i), "does not exist");
+        }
+    }
+    for (i, goal) in document.study_goal.iter().enumerate() {
+        if !goal.topic_ids.iter().all(|id| topics.contains(id)) {
+            error(format!("study_goal.{}.topic_ids", i), "does not exist");
+        }
+        // goals without links only have the legacy topic
+        if goal.topic_ids.is_empty() && !goal.topics_missing && !topics.contains(&goal.topic_id) {
+            error(format!("study_goal.{}.topic_id", i), "does not exist");
+        }
+        if goal.exam_id.is_some_and(|id| !exams.contains(&id)) {
+            error(format!("study_goal.{}.exam_id", i), "does not exist");
+        }
+    }
+    // subtasks are one level deep, their parents are no subtasks
+    let subtasks: HashSet<i32> = document
+        .todo
+        .iter()
+        .filter(|todo| todo.parent_todo_id.is_some())
+        .filter_map(|todo| todo.id)
+        .collect();
+    for (i, todo) in document.todo.iter().enumerate() {
+        match todo.parent_todo_id {
+            Some(id) if !todos.contains(&id) => {
+                error(format!("todo.{}.parent_todo_id", i), "does not exist")
+            }
+            Some(id) if subtasks.contains(&id) => {
+                error(format!("todo.{}.parent_todo_id", i), "is a subtask")
+            }
+            _ => {}
+        }
+    }
+    errors
+}
+
+/// inserts a new object for the user, returns its id
+async fn insert<DBT: SQLGenerate, ST: ToDB, DB: DBInterface>(
+    db: &DB,
+    user_id: i32,
+    entry: &ST,
+    local_token: &str,
+    provider: &CryptProviders,
+) -> Result<i32, DbError> {
+    let mut params = db_param_map! { user_id: user_id };
+    params.extend(entry.to_param_vec(local_token.as_bytes(), provider));
+    db.new_entry::<DBT>(params).await
+}
+
+/// inserts the objects of the document for the user, references are rewritten to the new ids
+/// courses first, then topics and exams of the courses, then study goals of the topics and exams, todos before their subtasks
+async fn import_document<DB: DBInterface + Send + Sync>(
+    db: &DB,
+    state: Arc<AppState<DB>>,
+    user_id: i32,
+    tokens: ImportTokens,
+    document: UserExport,
+) -> Result<ImportedIds, DbError> {
+    let provider = &state.crypt_provider;
+    let mut created = ImportedIds::default();
+
+    let mut course_ids = HashMap::new();
+    for mut course in document.course {
+        // semesters are not part of an export
+        course.semester_id = None;
+        let id = insert::<CourseDB, _, DB>(db, user_id, &course, &tokens.course, provider).await?;
+        course_ids.extend(course.id.map(|old| (old, id)));
+        created.course.push(id);
+    }
+
+    // references are checked before, every old id has a new one
+    let mut topic_ids = HashMap::new();
+    // topics are appended to their course, inserting them by position keeps the order of the export
+    let mut topics = document.topic;
+    topics.sort_by_key(|topic| topic.position);
+    for mut topic in topics {
+        topic.course_id = course_ids[&topic.course_id];
+        let id = insert::<TopicDB, _, DB>(db, user_id, &topic, &tokens.topic, provider).await?;
+        topic_ids.extend(topic.id.map(|old| (old, id)));
+        created.topic.push(id);
+    }
+
+    let mut exam_ids = HashMap::new();
+    for mut exam in document.exam {
+        exam.course_id = course_ids[&exam.course_id];
+        let id = insert::<ExamDB, _, DB>(db, user_id, &exam, &tokens.exam, provider).await?;
+        exam_ids.extend(exam.id.map(|old| (old, id)));
+        created.exam.push(id);
+    }
+
+    for mut goal in document.study_goal {
+        let topics: Vec<i32> = if goal.topic_ids.is_empty() && !goal.topics_missing {
+            vec![topic_ids[&goal.topic_id]]
+        } else {
+            goal.topic_ids.iter().map(|id| topic_ids[id]).collect()
+        };
+        // the legacy topic of a flagged goal is gone already, it is kept like in restore_rows
+        if let Some(first) = topics.first() {
+            goal.topic_id = *first;
+        }
+        goal.exam_id = goal.exam_id.map(|id| exam_ids[&id]);
+
+        let id =
+            insert::<StudyGoalDB, _, DB>(db, user_id, &goal, &tokens.study_goal, provider).await?;
+        if !topics.is_empty() {
+            db.set_study_goal_topics(user_id, id, &topics).await?;
+        }
+        created.study_goal.push(id);
+    }
+
+    // parents before their subtasks
+    let (todos, subtasks): (Vec<_>, Vec<_>) = document
+        .todo
+        .into_iter()
+        .partition(|todo| todo.parent_todo_id.is_none());
+    let mut todo_ids = HashMap::new();
+    for todo in todos {
+        let id = insert::<ToDoDB, _, DB>(db, user_id, &todo, &tokens.todo, provider).await?;
+        todo_ids.extend(todo.id.map(|old| (old, id)));
+        created.todo.push(id);
+    }
+    for mut subtask in subtasks {
+        subtask.parent_todo_id = subtask.parent_todo_id.map(|id| todo_ids[&id]);
+        let id = insert::<ToDoDB, _, DB>(db, user_id, &subtask, &tokens.todo, provider).await?;
+        created.todo.push(id);
+    }
+
+    Ok(created)
+}
+
+/// handler for json imports, creates the objects of an export (of this or another account) for the user
+/// ids are remapped, either everything is imported or nothing
+/// todos in the trash and webhook subscriptions are not imported
+pub async fn handle_import<DB: DBInterface + Send + Sync + 'static>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Json(document): Json<UserExport>,
+) -> Result<Json<RestoreResult>, ApiError> {
+    info!("Import requested!");
+    let user_id = user.user_id;
+
+    let mut errors = reference_errors(&document);
+    for (group, result) in [
+        ("course", state.payload_limits.check_all(&document.course)),
+        ("topic", state.payload_limits.check_all(&document.topic)),
+        ("exam", state.payload_limits.check_all(&document.exam)),
+        ("todo", state.payload_limits.check_all(&document.todo)),
+    ] {
+        errors.extend(result.err().into_iter().flatten().map(|error| FieldError {
+            field: format!("{}.{}", group, error.field),
+            message: error.message,
+        }));
+    }
+    if !errors.is_empty() {
+        info!("Import rejected, {} invalid values.", errors.len());
+        return Err(ApiError::unprocessable(errors));
+    }
+
+    let tokens = ImportTokens {
+        course: LocalKey::<CourseDB>::new(&state, user.clone())
+            .await?
+            .local_token,
+        topic: LocalKey::<TopicDB>::new(&state, user.clone())
+            .await?
+            .local_token,
+        study_goal: LocalKey::<StudyGoalDB>::new(&state, user.clone())
+            .await?
+            .local_token,
+        exam: LocalKey::<ExamDB>::new(&state, user.clone())
+            .await?
+            .local_token,
+        todo: LocalKey::<ToDoDB>::new(&state, user).await?.local_token,
+    };
+
+    let transaction_state = state.clone();
+    let created = state
+        .db
+        .with_transaction(move |db| {
+            Box::pin(import_document(
+                db,
+                transaction_state,
+                user_id,
+                tokens,
+                document,
+            ))
+        })
+        .await
+        .map_err(|err| {
+            error!("Failed to import objects! (user id: {}): {}", user_id, err);
+            ApiError::from(err)
+        })?;
+    info!("Import successful! (user id: {})", user_id);
+
+    for (ident, ids) in [
+        (CourseDB::get_db_ident(), &created.course),
+        (TopicDB::get_db_ident(), &created.topic),
+        (ExamDB::get_db_ident(), &created.exam),
+        (StudyGoalDB::get_db_ident(), &created.study_goal),
+        (ToDoDB::get_db_ident(), &created.todo),
+    ] {
+        for id in ids {
+            record_change(&state, user_id, &ident, WebhookAction::Create, *id).await;
+        }
+    }
+
+    Ok(Json(RestoreResult {
+        course: created.course.len(),
+        topic: created.topic.len(),
+        study_goal: created.study_goal.len(),
+        exam: created.exam.len(),
+        todo: created.todo.len(),
+        // no local token is replaced by an import
+        unrecoverable: Vec::new(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum::http::{Method, StatusCode};
+    use serde_json::{Value, json};
+
+    use crate::{
+        data_handler::data_router,
+        test_util::{create_course, register_user, send_json, test_state},
+    };
+
+    /// replaces ids and references by the index of the object in its group and drops the timestamps
+    fn without_ids(mut export: Value) -> Value {
+        let index = |group: &str| -> HashMap<i64, usize> {
+            export[group]
+                .as_array()
+                .unwrap()
+                .iter()
+                .enumerate()
+                .map(|(i, object)| (object["id"].as_i64().unwrap(), i))
+                .collect()
+        };
+        let (courses, topics, exams) = (index("course"), index("topic"), index("exam"));
+        let todos = index("todo");
+        let export_object = export.as_object_mut().unwrap();
+        export_object.remove("exported_at");
+
+        for (group, objects) in export_object.iter_mut() {
+            for object in objects.as_array_mut().unwrap() {
+                let object = object.as_object_mut().unwrap();
+                for field in ["id", "created_at", "updated_at", "completed_at"] {
+                    object.remove(field);
+                }
+                let mut replace = |field: &str, ids: &HashMap<i64, usize>| {
+                    if let Some(id) = object.get(field).and_then(Value::as_i64) {
+                        object.insert(field.to_string(), json!(ids[&id]));
+                    }
+                };
+                replace("course_id", &courses);
+                replace("exam_id", &exams);
+                replace("parent_todo_id", &todos);
+                if group == "study_goal" {
+                    replace("topic_id", &topics);
+                    let topic_ids: Vec<usize> = object["topic_ids"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|id| topics[&id.as_i64().unwrap()])
+                        .collect();
+                    object.insert("topic_ids".to_string(), json!(topic_ids));
+                }
+            }
+        }
+        export
+    }
+
+    #[tokio::test]
+    async fn export_contains_decrypted_objects_of_the_user_only() {
+        let state = test_state().await;
+        let token = register_user(&state, "export_user").await;
+        let other = register_user(&state, "export_other").await;
+        let router = data_router(state.clone()).await;
+
+        // a new account exports empty groups
+        let (status, export) =
+            send_json(router.clone(), Method::GET, "/export", &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(export["course"], json!([]));
+        assert_eq!(export["webhook_subscription"], json!([]));
+
+        let course_id = create_course(router.clone(), &token).await;
+        create_course(router.clone(), &other).await;
+        let (_, topic) = send_json(
+            router.clone(),
+            Method::POST,
+            "/topic",
+            &token,
+            Some(json!({ "id": null, "course_id": course_id, "name": "Integrals", "details": "by parts" })),
+        )
+        .await;
+        let (_, goal) = send_json(
+            router.clone(),
+            Method::POST,
+            "/study_goal",
+            &token,
+            Some(json!({ "id": null, "topic_id": topic["id"], "deadline": "2025-07-01" })),
+        )
+        .await;
+        for name in ["Laundry", "Dishes"] {
+            send_json(
+                router.clone(),
+                Method::POST,
+                "/todo",
+                &token,
+                Some(json!({ "id": null, "name": name, "deadline": "2025-07-01", "details": "", "completed": false })),
+            )
+            .await;
+        }
+        let (_, todos) = send_json(router.clone(), Method::GET, "/todo", &token, None).await;
+        send_json(
+            router.clone(),
+            Method::DELETE,
+            "/todo",
+            &token,
+            Some(json!({ "id": todos[1]["id"] })),
+        )
+        .await;
+
+        let (status, export) = send_json(router, Method::GET, "/export", &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(export["course"].as_array().unwrap().len(), 1);
+        assert_eq!(export["course"][0]["name"], "Course");
+        assert_eq!(export["topic"][0]["details"], "by parts");
+        assert_eq!(export["study_goal"][0]["id"], goal["id"]);
+        assert_eq!(export["study_goal"][0]["topic_ids"], json!([topic["id"]]));
+        assert_eq!(export["exam"], json!([]));
+        assert_eq!(export["todo"].as_array().unwrap().len(), 1);
+        assert_eq!(export["todo"][0]["name"], "Laundry");
+        assert_eq!(export["todo_trash"][0]["name"], "Dishes");
+        // nothing of the auth data
+        let text = export.to_string();
+        assert!(!text.contains("password"));
+        assert!(!text.contains("token"));
+    }
+
+    #[tokio::test]
+    async fn export_can_be_imported_into_another_account() {
+        let state = test_state().await;
+        let token = register_user(&state, "import_source").await;
+        let target = register_user(&state, "import_target").await;
+        let router = data_router(state.clone()).await;
+
+        // the target already has objects, their ids differ from the source
+        create_course(router.clone(), &target).await;
+        let empty_course_id = create_course(router.clone(), &token).await;
+        let course_id = create_course(router.clone(), &token).await;
+        let mut topic_ids = Vec::new();
+        for name in ["Limits", "Series"] {
+            let (_, topic) = send_json(
+                router.clone(),
+                Method::POST,
+                "/topic",
+                &token,
+                Some(json!({ "id": null, "course_id": course_id, "name": name, "details": "" })),
+            )
+            .await;
+            topic_ids.push(topic["id"].clone());
+        }
+        let (_, exam) = send_json(
+            router.clone(),
+            Method::POST,
+            "/exam",
+            &token,
+            Some(json!({ "id": null, "course_id": course_id, "name": "Analysis", "date": "2025-07-01", "prep_notes": "chapter 3" })),
+        )
+        .await;
+        let (_, goal) = send_json(
+            router.clone(),
+            Method::POST,
+            "/study_goal",
+            &token,
+            Some(json!({ "id": null, "topic_id": topic_ids[1], "deadline": "2025-06-01", "exam_id": exam["id"], "title": "Series first" })),
+        )
+        .await;
+        send_json(
+            router.clone(),
+            Method::PUT,
+            &format!("/study_goal/{}/topics", goal["id"]),
+            &token,
+            Some(json!({ "topic_ids": [topic_ids[1], topic_ids[0]] })),
+        )
+        .await;
+        let (_, todo) = send_json(
+            router.clone(),
+            Method::POST,
+            "/todo",
+            &token,
+            Some(json!({ "id": null, "name": "Laundry", "deadline": "2025-07-01", "details": "whites", "completed": true })),
+        )
+        .await;
+        send_json(
+            router.clone(),
+            Method::POST,
+            "/todo",
+            &token,
+            Some(json!({ "id": null, "name": "Iron", "deadline": "2025-07-01", "details": "", "parent_todo_id": todo["id"] })),
+        )
+        .await;
+        let (_, source) =
+            send_json(router.clone(), Method::GET, "/export.json", &token, None).await;
+        assert_eq!(source["course"][0]["id"], empty_course_id);
+
+        let (status, result) = send_json(
+            router.clone(),
+            Method::POST,
+            "/import.json",
+            &target,
+            Some(source.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            result,
+            json!({ "course": 2, "topic": 2, "study_goal": 1, "exam": 1, "todo": 2 })
+        );
+
+        let (_, mut imported) = send_json(router, Method::GET, "/export.json", &target, None).await;
+        // the course the target had before the import
+        imported["course"].as_array_mut().unwrap().remove(0);
+        assert_eq!(without_ids(imported), without_ids(source));
+    }
+
+    #[tokio::test]
+    async fn imports_with_unknown_references_are_rejected() {
+        let state = test_state().await;
+        let token = register_user(&state, "import_broken").await;
+        let router = data_router(state.clone()).await;
+        let course_id = create_course(router.clone(), &token).await;
+
+        let document = json!({
+            "course": [{ "id": 1, "name": "Analysis" }, { "id": 1, "name": "Twice" }],
+            "topic": [{ "id": 5, "course_id": course_id + 100, "name": "Limits", "details": "" }],
+            "study_goal": [{ "id": 1, "topic_id": 6, "deadline": "2025-06-01", "exam_id": 3 }],
+            "exam": [],
+            "todo": [
+                { "id": null, "name": "Laundry", "deadline": "2025-07-01", "details": "" },
+                { "id": 2, "name": "Iron", "deadline": "2025-07-01", "details": "", "parent_todo_id": 1 },
+            ],
+        });
+        let (status, errors) = send_json(
+            router.clone(),
+            Method::POST,
+            "/import.json",
+            &token,
+            Some(document),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            errors["field_errors"],
+            json!([
+                { "field": "course.1.id", "message": "is used twice" },
+                { "field": "topic.0.course_id", "message": "does not exist" },
+                { "field": "study_goal.0.topic_id", "message": "does not exist" },
+                { "field": "study_goal.0.exam_id", "message": "does not exist" },
+                { "field": "todo.1.parent_todo_id", "message": "does not exist" },
+            ])
+        );
+
+        // nothing of it is created
+        let (_, export) = send_json(router, Method::GET, "/export", &token, None).await;
+        assert_eq!(export["course"].as_array().unwrap().len(), 1);
+        assert_eq!(export["todo"], json!([]));
+    }
+}
@@ -0,0 +1,353 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode};
+use chrono::{Days, NaiveDate, Utc};
+use log::{error, info};
+use serde::Deserialize;
+
+use crate::{
+    AppState,
+    api_error::{ApiError, FieldError, Json, Path, Query},
+    db::{
+        DBInterface,
+        sql_helper::{SortDirection, WhereOp, WhereParam},
+    },
+    db_param_map,
+};
+
+use super::{
+    FromDB, LocalKey, get_entries,
+    history::load_object,
+    objects::{FlashcardDB, FlashcardSend},
+    update_fields,
+};
+
+/// ease of new cards
+pub const INITIAL_EASE: f64 = 2.5;
+/// the ease never drops below this, so hard cards still get longer intervals
+pub const MIN_EASE: f64 = 1.3;
+/// the ease never grows above this, it only matters for cards that are always answered well
+pub const MAX_EASE: f64 = 5.0;
+/// longest interval between two reviews (about 100 years), keeps due dates in range
+pub const MAX_INTERVAL_DAYS: i32 = 36500;
+/// best answer of a review, 0 is a complete blackout
+const MAX_QUALITY: u8 = 5;
+
+/// scheduling state of a card
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Schedule {
+    pub ease: f64,
+    pub interval_days: i32,
+    pub due_date: NaiveDate,
+}
+
+/// SM-2 update of the schedule after a review with the quality (0 to 5) on the day today
+/// answers below 3 start the card over with an interval of one day
+/// ease and interval are capped at MAX_EASE and MAX_INTERVAL_DAYS
+pub fn next_schedule(current: Schedule, quality: u8, today: NaiveDate) -> Schedule {
+    let quality = quality.min(MAX_QUALITY);
+    let miss = (MAX_QUALITY - quality) as f64;
+    let ease = (current.ease + 0.1 - miss * (0.08 + miss * 0.02)).clamp(MIN_EASE, MAX_EASE);
+
+    let interval_days = match current.interval_days {
+        _ if quality < 3 => 1,
+        0 => 1,
+        1 => 6,
+        interval => (interval as f64 * ease)
+            .round()
+            .min(MAX_INTERVAL_DAYS as f64) as i32,
+    };
+
+    Schedule {
+        ease,
+        interval_days,
+        due_date: today
+            .checked_add_days(Days::new(interval_days as u64))
+            .unwrap_or(NaiveDate::MAX),
+    }
+}
+
+/// query parameters of the due request
+#[derive(Deserialize, Debug)]
+pub struct DueQuery {
+    topic_id: Option<i32>,
+}
+
+/// handler returning the cards due today or earlier, optionally of one topic, the longest due first
+pub async fn handle_due<DB: DBInterface + Send + Sync>(
+    key: LocalKey<FlashcardDB>,
+    State(state): State<Arc<AppState<DB>>>,
+    Query(query): Query<DueQuery>,
+) -> Result<Json<Vec<FlashcardSend>>, ApiError> {
+    info!("Due flashcards requested!");
+
+    let mut params = vec![WhereParam::new(
+        "due_date",
+        WhereOp::Le,
+        Utc::now().date_naive(),
+    )];
+    if let Some(topic_id) = query.topic_id {
+        params.extend(db_param_map! { topic_id: topic_id });
+    }
+    let order = [("due_date".to_string(), SortDirection::Asc)];
+    let cards = get_entries(&state, &key, params, &order, None).await?;
+
+    info!("Due flashcards successful, building response!");
+    Ok(Json(cards))
+}
+
+/// body of a review
+#[derive(Deserialize, Debug)]
+pub struct ReviewRequest {
+    quality: u8,
+}
+
+/// handler for reviewing a card, reschedules it and returns the updated card
+pub async fn handle_review<DB: DBInterface + Send + Sync>(
+    key: LocalKey<FlashcardDB>,
+    State(state): State<Arc<AppState<DB>>>,
+    Path(card_id): Path<i32>,
+    Json(review): Json<ReviewRequest>,
+) -> Result<Json<FlashcardSend>, ApiError> {
+    info!("Flashcard review requested!");
+
+    if review.quality > MAX_QUALITY {
+        return Err(ApiError::unprocessable(vec![FieldError {
+            field: "quality".to_string(),
+            message: format!("is not between 0 and {}", MAX_QUALITY),
+        }]));
+    }
+    let card = load_object::<FlashcardDB, DB>(&state, key.user.user_id, card_id)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let schedule = next_schedule(
+        Schedule {
+            ease: card.ease,
+            interval_days: card.interval_days,
+            due_date: card.due_date,
+        },
+        review.quality,
+        Utc::now().date_naive(),
+    );
+    let params = db_param_map! {
+        ease: schedule.ease,
+        interval_days: schedule.interval_days,
+        due_date: schedule.due_date,
+    };
+    update_fields::<FlashcardDB, DB>(&state, &key, card_id, params).await?;
+
+    let card = FlashcardSend::from_dbt(&card, key.local_token.as_bytes(), &state.crypt_provider)
+        .map_err(|_| {
+            error!("Failed to convert database type to send type");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    info!("Flashcard review successful, building response!");
+    Ok(Json(FlashcardSend {
+        ease: schedule.ease,
+        interval_days: schedule.interval_days,
+        due_date: schedule.due_date,
+        ..card
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use chrono::{Days, NaiveDate, Utc};
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        data_handler::data_router,
+        test_util::{create_course, register_user, send_json, test_state},
+    };
+
+    fn day(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 6, day).unwrap()
+    }
+
+    fn new_card() -> Schedule {
+        Schedule {
+            ease: INITIAL_EASE,
+            interval_days: 0,
+            due_date: day(1),
+        }
+    }
+
+    #[test]
+    fn good_answers_grow_the_interval() {
+        let first = next_schedule(new_card(), 5, day(1));
+        assert_eq!(first.interval_days, 1);
+        assert_eq!(first.due_date, day(2));
+        assert!((first.ease - 2.6).abs() < 1e-9);
+
+        let second = next_schedule(first, 4, day(2));
+        assert_eq!(second.interval_days, 6);
+        assert_eq!(second.due_date, day(8));
+        assert!((second.ease - 2.6).abs() < 1e-9);
+
+        let third = next_schedule(second, 3, day(8));
+        assert!((third.ease - 2.46).abs() < 1e-9);
+        // 6 * 2.46 = 14.76
+        assert_eq!(third.interval_days, 15);
+        assert_eq!(third.due_date, day(23));
+    }
+
+    #[test]
+    fn failed_answers_start_over_and_lower_the_ease() {
+        let card = Schedule {
+            ease: 2.5,
+            interval_days: 20,
+            due_date: day(1),
+        };
+        let failed = next_schedule(card, 2, day(1));
+        assert_eq!(failed.interval_days, 1);
+        assert_eq!(failed.due_date, day(2));
+        assert!((failed.ease - 2.18).abs() < 1e-9);
+
+        let mut hard = card;
+        for _ in 0..10 {
+            hard = next_schedule(hard, 0, day(1));
+        }
+        assert_eq!(hard.ease, MIN_EASE);
+    }
+
+    #[test]
+    fn intervals_are_capped() {
+        let mut card = Schedule {
+            ease: MAX_EASE,
+            interval_days: i32::MAX,
+            due_date: day(1),
+        };
+        for _ in 0..3 {
+            card = next_schedule(card, 5, day(1));
+            assert_eq!(card.ease, MAX_EASE);
+            assert_eq!(card.interval_days, MAX_INTERVAL_DAYS);
+        }
+        assert_eq!(card.due_date, day(1) + Days::new(MAX_INTERVAL_DAYS as u64));
+        let late = next_schedule(card, 5, NaiveDate::MAX);
+        assert_eq!(late.due_date, NaiveDate::MAX);
+    }
+
+    #[tokio::test]
+    async fn reviews_reschedule_due_cards() {
+        let state = test_state().await;
+        let token = register_user(&state, "flashcard_user").await;
+        let other = register_user(&state, "flashcard_other").await;
+        let router = data_router(state.clone()).await;
+        let today = Utc::now().date_naive();
+        let course_id = create_course(router.clone(), &token).await;
+        let mut topic_ids = Vec::new();
+        for name in ["Limits", "Series"] {
+            let (_, topic) = send_json(
+                router.clone(),
+                Method::POST,
+                "/topic",
+                &token,
+                Some(json!({ "id": null, "course_id": course_id, "name": name, "details": "" })),
+            )
+            .await;
+            topic_ids.push(topic["id"].clone());
+        }
+
+        let mut card_ids = Vec::new();
+        for body in [
+            json!({ "id": null, "topic_id": topic_ids[0], "front": "lim 1/n", "back": "0" }),
+            json!({ "id": null, "topic_id": topic_ids[1], "front": "sum 1/2^n", "back": "2" }),
+            json!({ "id": null, "topic_id": topic_ids[1], "front": "later", "back": "",
+                "ease": 2.5, "interval_days": 3, "due_date": (today + Days::new(3)).to_string() }),
+        ] {
+            let (status, card) = send_json(
+                router.clone(),
+                Method::POST,
+                "/flashcard",
+                &token,
+                Some(body),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            card_ids.push(card["id"].clone());
+        }
+
+        let (status, due) =
+            send_json(router.clone(), Method::GET, "/flashcard/due", &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(due.as_array().unwrap().len(), 2);
+        assert_eq!(due[0]["ease"], INITIAL_EASE);
+        assert_eq!(due[0]["due_date"], today.to_string());
+        let (_, due) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!("/flashcard/due?topic_id={}", topic_ids[1]),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(due.as_array().unwrap().len(), 1);
+        assert_eq!(due[0]["front"], "sum 1/2^n");
+
+        let (status, card) = send_json(
+            router.clone(),
+            Method::POST,
+            &format!("/flashcard/{}/review", card_ids[0]),
+            &token,
+            Some(json!({ "quality": 4 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(card["interval_days"], 1);
+        assert_eq!(card["due_date"], (today + Days::new(1)).to_string());
+        assert_eq!(card["back"], "0");
+        let (_, cards) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!("/flashcard?id={}", card_ids[0]),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(cards[0], card);
+        let (_, due) = send_json(router.clone(), Method::GET, "/flashcard/due", &token, None).await;
+        assert_eq!(due.as_array().unwrap().len(), 1);
+
+        let (status, _) = send_json(
+            router.clone(),
+            Method::POST,
+            &format!("/flashcard/{}/review", card_ids[1]),
+            &token,
+            Some(json!({ "quality": 6 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        // schedules out of range are rejected on creates and edits
+        for (id, ease, interval_days, field) in [
+            (json!(null), 2.5, i32::MAX, "interval_days"),
+            (card_ids[1].clone(), 2.5, -1, "interval_days"),
+            (card_ids[1].clone(), 100.0, 1, "ease"),
+        ] {
+            let (status, body) = send_json(
+                router.clone(),
+                Method::POST,
+                "/flashcard",
+                &token,
+                Some(
+                    json!({ "id": id, "topic_id": topic_ids[0], "front": "far", "back": "",
+                    "ease": ease, "interval_days": interval_days }),
+                ),
+            )
+            .await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+            assert_eq!(body["field_errors"][0]["field"], field);
+        }
+        let (status, _) = send_json(
+            router,
+            Method::POST,
+            &format!("/flashcard/{}/review", card_ids[1]),
+            &other,
+            Some(json!({ "quality": 3 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,229 @@
+use std::{collections::HashSet, env, sync::Arc};
+
+use axum::{extract::State, http::StatusCode};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState,
+    api_error::{ApiError, Json, Path},
+    auth_handler::AuthenticatedUser,
+    db::{DBInterface, DbError},
+    db_param_map,
+};
+
+use super::{
+    objects::{CourseDB, ExamDB, GradeDB},
+    stats::check_access,
+};
+
+/// best and worst grade if GRADE_MIN and GRADE_MAX are not set, the german scale
+const DEFAULT_GRADE_SCALE: (f64, f64) = (1.0, 5.0);
+
+/// lowest and highest accepted grade, configurable via GRADE_MIN and GRADE_MAX
+pub fn grade_scale() -> (f64, f64) {
+    let bound = |name: &str, default: f64| {
+        env::var(name)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    };
+    (
+        bound("GRADE_MIN", DEFAULT_GRADE_SCALE.0),
+        bound("GRADE_MAX", DEFAULT_GRADE_SCALE.1),
+    )
+}
+
+/// response of the average request
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct CourseAverage {
+    pub course_id: i32,
+    /// none if no exam of the course has a grade
+    pub average: Option<f64>,
+}
+
+/// weighted average of the grades of the exams of the course, none if the course does not exist
+async fn course_average<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    course_id: i32,
+) -> Result<Option<CourseAverage>, DbError> {
+    if state
+        .db
+        .count_entries::<CourseDB>(db_param_map! { id: course_id, user_id: user_id })
+        .await?
+        == 0
+    {
+        return Ok(None);
+    }
+
+    let exam_ids: HashSet<i32> = state
+        .db
+        .select_entries::<ExamDB>(
+            db_param_map! { course_id: course_id, user_id: user_id },
+            &[],
+            None,
+        )
+        .await?
+        .iter()
+        .map(|exam| exam.id)
+        .collect();
+    let (weighted, weights) = state
+        .db
+        .select_entries::<GradeDB>(db_param_map! { user_id: user_id }, &[], None)
+        .await?
+        .iter()
+        .filter(|grade| exam_ids.contains(&grade.exam_id))
+        .fold((0.0, 0.0), |(weighted, weights), grade| {
+            (
+                weighted + grade.grade * grade.weight,
+                weights + grade.weight,
+            )
+        });
+
+    Ok(Some(CourseAverage {
+        course_id,
+        average: (weights > 0.0).then(|| weighted / weights),
+    }))
+}
+
+/// handler returning the weighted average of the grades of a course
+/// only ids and numbers are needed, so no local token is decrypted
+pub async fn handle_course_average<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Path(course_id): Path<i32>,
+) -> Result<Json<CourseAverage>, ApiError> {
+    info!("Course average requested!");
+
+    check_access::<CourseDB, DB>(&state, &user).await?;
+    check_access::<ExamDB, DB>(&state, &user).await?;
+    check_access::<GradeDB, DB>(&state, &user).await?;
+
+    let average = course_average(&state, user.user_id, course_id)
+        .await
+        .map_err(|err| {
+            error!("Error while averaging the grades of user {}!", user.user_id);
+            StatusCode::from(err)
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    info!("Course average successful, building response!");
+    Ok(Json(average))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    use crate::{
+        data_handler::data_router,
+        test_util::{create_course, register_user, send_json, test_state},
+    };
+
+    #[tokio::test]
+    async fn course_average_weights_the_grades_of_its_exams() {
+        let state = test_state().await;
+        let token = register_user(&state, "grade_user").await;
+        let other = register_user(&state, "grade_other").await;
+        let router = data_router(state.clone()).await;
+        let course_id = create_course(router.clone(), &token).await;
+        let other_course_id = create_course(router.clone(), &token).await;
+
+        let mut exam_ids = Vec::new();
+        for course_id in [course_id, course_id, other_course_id] {
+            let (_, exam) = send_json(
+                router.clone(),
+                Method::POST,
+                "/exam",
+                &token,
+                Some(json!({ "id": null, "course_id": course_id, "name": "Exam", "date": "2025-07-01" })),
+            )
+            .await;
+            exam_ids.push(exam["id"].clone());
+        }
+
+        let (status, average) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!("/course/{}/average", course_id),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(average, json!({ "course_id": course_id, "average": null }));
+
+        for (exam_id, grade, weight) in [
+            (&exam_ids[0], 1.3, 2.0),
+            (&exam_ids[1], 2.7, 1.0),
+            (&exam_ids[2], 5.0, 1.0),
+        ] {
+            let (status, _) = send_json(
+                router.clone(),
+                Method::POST,
+                "/grade",
+                &token,
+                Some(json!({ "id": null, "exam_id": exam_id, "grade": grade, "weight": weight, "note": "oral" })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+        }
+
+        let (_, grades) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!("/grade?exam_id={}", exam_ids[1]),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(
+            grades,
+            json!([{ "id": grades[0]["id"], "exam_id": exam_ids[1], "grade": 2.7, "weight": 1.0, "note": "oral" }])
+        );
+        let (_, average) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!("/course/{}/average", course_id),
+            &token,
+            None,
+        )
+        .await;
+        assert!((average["average"].as_f64().unwrap() - 5.3 / 3.0).abs() < 1e-9);
+
+        for (token, body, field) in [
+            (
+                &token,
+                json!({ "id": null, "exam_id": exam_ids[0], "grade": 0.7 }),
+                "grade",
+            ),
+            (
+                &token,
+                json!({ "id": null, "exam_id": exam_ids[0], "grade": 2.0, "weight": 0.0 }),
+                "weight",
+            ),
+            (
+                &other,
+                json!({ "id": null, "exam_id": exam_ids[0], "grade": 2.0 }),
+                "exam_id",
+            ),
+        ] {
+            let (status, errors) =
+                send_json(router.clone(), Method::POST, "/grade", token, Some(body)).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+            assert_eq!(errors["field_errors"][0]["field"], field);
+        }
+
+        let (status, _) = send_json(
+            router,
+            Method::GET,
+            &format!("/course/{}/average", course_id),
+            &other,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
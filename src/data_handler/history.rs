@@ -0,0 +1,474 @@
+use std::{any::type_name, sync::Arc};
+
+use axum::{extract::State, http::StatusCode};
+use chrono::NaiveDateTime;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{
+    AppState,
+    api_error::{ApiError, Json, Path},
+    auth_handler::AuthenticatedUser,
+    crypt::{Cryptable, crypt_types::CryptString},
+    db::{DBInterface, sql_helper::SQLGenerate},
+    db_param_map,
+};
+
+use super::{FromDB, IDBody, local_token_for, record_change, webhook::WebhookAction};
+
+/// why the previous state of an object was stored
+#[derive(Debug, Clone, Copy)]
+pub enum HistoryAction {
+    Edit,
+    Delete,
+    Revert,
+}
+
+impl HistoryAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            HistoryAction::Edit => "edit",
+            HistoryAction::Delete => "delete",
+            HistoryAction::Revert => "revert",
+        }
+    }
+}
+
+/// single history entry, previous is the object as it was before the change
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HistoryEntrySend<ST> {
+    change_id: i32,
+    action: String,
+    changed_at: NaiveDateTime,
+    previous: ST,
+}
+
+/// loads an object of the user, None if it does not exist
+pub async fn load_object<DBT: SQLGenerate, DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    id: i32,
+) -> Result<Option<DBT>, StatusCode> {
+    let entries = state
+        .db
+        .select_entries::<DBT>(db_param_map! { id: id, user_id: user_id }, &[], None)
+        .await
+        .map_err(|err| {
+            error!(
+                "Error while querying DB! Tried to get {} information.",
+                type_name::<DBT>()
+            );
+            StatusCode::from(err)
+        })?;
+
+    Ok(entries.into_iter().next())
+}
+
+/// logs a failed history write
+fn history_failed<DBT>(user_id: i32) -> StatusCode {
+    error!(
+        "Failed to record history of {}! (user id: {})",
+        type_name::<DBT>(),
+        user_id
+    );
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// stores the state of an object before it gets changed, encrypted with the local token of its type
+/// previous has to be loaded before the change, it is recorded once the change succeeded
+pub async fn record_history<DBT: SQLGenerate + Serialize, DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    local_token: &str,
+    action: HistoryAction,
+    object_id: i32,
+    previous: &DBT,
+) -> Result<(), StatusCode> {
+    let snapshot = serde_json::to_string(previous).map_err(|_| history_failed::<DBT>(user_id))?;
+    let snapshot = CryptString::encrypt(&snapshot, local_token.as_bytes(), &state.crypt_provider);
+    state
+        .db
+        .new_change_log(
+            user_id,
+            &DBT::get_db_ident(),
+            object_id,
+            action.as_str(),
+            &snapshot,
+        )
+        .await
+        .map_err(|_| history_failed::<DBT>(user_id))?;
+
+    Ok(())
+}
+
+/// decrypts the snapshot of a history entry
+fn decrypt_snapshot<DBT: DeserializeOwned, DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    snapshot: &CryptString,
+    local_token: &str,
+) -> Result<DBT, StatusCode> {
+    let snapshot = snapshot
+        .decrypt(local_token.as_bytes(), &state.crypt_provider)
+        .map_err(|_| {
+            error!("Failed to decrypt history snapshot!");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    serde_json::from_str(&snapshot).map_err(|_| {
+        // happens if the table changed since the snapshot was taken
+        warn!(
+            "History snapshot does not match {} anymore!",
+            type_name::<DBT>()
+        );
+        StatusCode::UNPROCESSABLE_ENTITY
+    })
+}
+
+/// handler for reading the history of an object, newest first
+pub async fn handle_get_history<
+    DBT: SQLGenerate + DeserializeOwned,
+    ST: FromDB<DBT>,
+    DB: DBInterface + Send + Sync,
+>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<HistoryEntrySend<ST>>>, ApiError> {
+    info!("{} history requested!", type_name::<DBT>());
+
+    let AuthenticatedUser {
+        user_id,
+        remote_token_id,
+        remote_token,
+    } = user;
+
+    let local_token =
+        local_token_for::<DBT, DB>(&state, user_id, remote_token_id, &remote_token).await?;
+
+    let changes = state
+        .db
+        .get_change_logs_by_object(user_id, &DBT::get_db_ident(), id)
+        .await
+        .map_err(|_| {
+            error!("Failed to load history! (user id: {})", user_id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let history = changes
+        .iter()
+        .map(|change| {
+            let previous: DBT = decrypt_snapshot(&state, &change.snapshot, &local_token)?;
+            let previous = ST::from_dbt(&previous, local_token.as_bytes(), &state.crypt_provider)
+                .map_err(|_| {
+                error!("Failed to convert database type to send type");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            Ok(HistoryEntrySend {
+                change_id: change.id,
+                action: change.action.clone(),
+                changed_at: change.changed_at,
+                previous,
+            })
+        })
+        .collect::<Result<Vec<_>, StatusCode>>()?;
+
+    info!("{} history read successful.", type_name::<DBT>());
+    Ok(Json(history))
+}
+
+/// handler for reverting an object to the state before a change
+/// deleted objects are created again, the response contains the (new) id of the object
+pub(super) async fn handle_revert<
+    DBT: SQLGenerate + Serialize + DeserializeOwned,
+    DB: DBInterface + Send + Sync,
+>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Path((id, change_id)): Path<(i32, i32)>,
+) -> Result<Json<IDBody>, ApiError> {
+    info!("{} revert requested!", type_name::<DBT>());
+
+    let AuthenticatedUser {
+        user_id,
+        remote_token_id,
+        remote_token,
+    } = user;
+
+    // the change has to belong to the user and to the object of the url
+    let change = state
+        .db
+        .get_change_log(user_id, change_id)
+        .await
+        .ok()
+        .filter(|change| {
+            change.object_id == id
+                && change.used_for.db_identifier == DBT::get_db_ident().db_identifier
+        })
+        .ok_or_else(|| {
+            warn!(
+                "History entry {} not found! (user id: {})",
+                change_id, user_id
+            );
+            StatusCode::NOT_FOUND
+        })?;
+
+    let local_token =
+        local_token_for::<DBT, DB>(&state, user_id, remote_token_id, &remote_token).await?;
+    let previous: DBT = decrypt_snapshot(&state, &change.snapshot, &local_token)?;
+    // the snapshot is still encrypted with the same local token, so the raw values can be written back
+    let params = previous.to_params();
+
+    let query_failed = |_| {
+        error!(
+            "Failed to revert {}! (user id: {})",
+            type_name::<DBT>(),
+            user_id
+        );
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let id = match load_object::<DBT, DB>(&state, user_id, id).await? {
+        Some(current) => {
+            // the revert is a change as well and can be reverted again
+            record_history(
+                &state,
+                user_id,
+                &local_token,
+                HistoryAction::Revert,
+                id,
+                &current,
+            )
+            .await?;
+            state
+                .db
+                .update_entry::<DBT>(params, db_param_map! { id: id, user_id: user_id })
+                .await
+                .map_err(query_failed)?;
+            record_change(
+                &state,
+                user_id,
+                &DBT::get_db_ident(),
+                WebhookAction::Edit,
+                id,
+            )
+            .await;
+            id
+        }
+        None => {
+            let mut insert_params = db_param_map! { user_id: user_id };
+            insert_params.extend(params);
+            let new_id = state
+                .db
+                .new_entry::<DBT>(insert_params)
+                .await
+                .map_err(query_failed)?;
+            record_change(
+                &state,
+                user_id,
+                &DBT::get_db_ident(),
+                WebhookAction::Create,
+                new_id,
+            )
+            .await;
+            new_id
+        }
+    };
+
+    info!("{} revert successful.", type_name::<DBT>());
+    Ok(Json(IDBody { id }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    use crate::{
+        data_handler::data_router,
+        test_util::{create_course, register_user, send_json, test_state},
+    };
+
+    #[tokio::test]
+    async fn edits_can_be_listed_and_reverted() {
+        let state = test_state().await;
+        let token = register_user(&state, "history_user").await;
+        let router = data_router(state.clone()).await;
+
+        let (_, course) = send_json(
+            router.clone(),
+            Method::POST,
+            "/course",
+            &token,
+            Some(json!({ "id": null, "name": "Analysis" })),
+        )
+        .await;
+        let course_id = course["id"].as_i64().unwrap();
+        let (_, topic) = send_json(
+            router.clone(),
+            Method::POST,
+            "/topic",
+            &token,
+            Some(
+                json!({ "id": null, "course_id": course_id, "name": "Integrals", "details": "v1" }),
+            ),
+        )
+        .await;
+        let topic_id = topic["id"].as_i64().unwrap();
+
+        for details in ["v2", "v3"] {
+            send_json(
+                router.clone(),
+                Method::POST,
+                "/topic",
+                &token,
+                Some(json!({ "id": topic_id, "course_id": course_id, "name": "Integrals", "details": details })),
+            )
+            .await;
+        }
+
+        let history_uri = format!("/topic/{}/history", topic_id);
+        let (status, history) =
+            send_json(router.clone(), Method::GET, &history_uri, &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let history = history.as_array().unwrap().clone();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0]["action"], "edit");
+        assert_eq!(history[0]["previous"]["details"], "v2");
+        assert_eq!(history[1]["previous"]["details"], "v1");
+
+        // revert to the first version
+        let (status, reverted) = send_json(
+            router.clone(),
+            Method::POST,
+            &format!("/topic/{}/revert/{}", topic_id, history[1]["change_id"]),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(reverted["id"], topic_id);
+
+        let (_, topics) = send_json(router.clone(), Method::GET, "/topic", &token, None).await;
+        assert_eq!(topics[0]["details"], "v1");
+        assert_eq!(topics[0]["name"], "Integrals");
+
+        // the revert itself is part of the history
+        let (_, history) = send_json(router.clone(), Method::GET, &history_uri, &token, None).await;
+        assert_eq!(history.as_array().unwrap().len(), 3);
+        assert_eq!(history[0]["action"], "revert");
+        assert_eq!(history[0]["previous"]["details"], "v3");
+
+        // changes of other users and other objects can not be used
+        let other_token = register_user(&state, "history_other").await;
+        let (status, _) = send_json(
+            router.clone(),
+            Method::POST,
+            &format!("/topic/{}/revert/{}", topic_id, history[0]["change_id"]),
+            &other_token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = send_json(
+            router,
+            Method::POST,
+            &format!("/course/{}/revert/{}", course_id, history[0]["change_id"]),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn failed_edits_are_not_recorded() {
+        let state = test_state().await;
+        let token = register_user(&state, "history_failed").await;
+        let router = data_router(state.clone()).await;
+
+        let course_id = create_course(router.clone(), &token).await;
+        let (_, topic) = send_json(
+            router.clone(),
+            Method::POST,
+            "/topic",
+            &token,
+            Some(json!({ "id": null, "course_id": course_id, "name": "Integrals", "details": "" })),
+        )
+        .await;
+
+        state.db.execute_batch(
+            "CREATE TRIGGER fail_topic_update BEFORE UPDATE ON TopicDB
+             BEGIN SELECT RAISE(ABORT, 'simulated failure'); END;",
+        );
+        let (status, _) = send_json(
+            router.clone(),
+            Method::PATCH,
+            "/topic",
+            &token,
+            Some(json!({ "id": topic["id"], "details": "by parts" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+        let (status, history) = send_json(
+            router,
+            Method::GET,
+            &format!("/topic/{}/history", topic["id"]),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(history, json!([]));
+    }
+
+    #[tokio::test]
+    async fn deleted_objects_can_be_restored() {
+        let state = test_state().await;
+        let token = register_user(&state, "history_delete").await;
+        let router = data_router(state.clone()).await;
+
+        let (_, todo) = send_json(
+            router.clone(),
+            Method::POST,
+            "/todo",
+            &token,
+            Some(json!({ "id": null, "name": "Übungsblatt", "deadline": "2025-07-01", "details": "", "completed": false })),
+        )
+        .await;
+        let todo_id = todo["id"].as_i64().unwrap();
+        send_json(
+            router.clone(),
+            Method::DELETE,
+            "/todo",
+            &token,
+            Some(json!({ "id": todo_id })),
+        )
+        .await;
+
+        let (_, history) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!("/todo/{}/history", todo_id),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(history[0]["action"], "delete");
+
+        let (status, restored) = send_json(
+            router.clone(),
+            Method::POST,
+            &format!("/todo/{}/revert/{}", todo_id, history[0]["change_id"]),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (_, todos) = send_json(router, Method::GET, "/todo", &token, None).await;
+        assert_eq!(todos.as_array().unwrap().len(), 1);
+        assert_eq!(todos[0]["id"], restored["id"]);
+        assert_eq!(todos[0]["name"], "Übungsblatt");
+    }
+}
@@ -0,0 +1,749 @@
+use std::{collections::BTreeMap, env, error::Error, io::BufReader, sync::Arc};
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::{Datelike, Days, Months, NaiveDate, Weekday};
+use ical::{IcalParser, parser::ical::component::IcalEvent};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState,
+    api_error::{ApiError, FieldError, Json, Query},
+    auth_handler::{AuthenticatedUser, decrypt_local_token_for},
+    db::{DBInterface, DbError, sql_helper::SQLGenerate},
+    db_param_map,
+};
+
+use super::{
+    MAX_BULK_SIZE, ToDB, decrypt_failed_status,
+    objects::{CourseDB, CourseSend, ExamDB, ExamSend, PrepStatus},
+    payload_too_large, record_change,
+    webhook::WebhookAction,
+};
+
+/// keywords used to detect exams if ICS_EXAM_KEYWORDS is not set
+const DEFAULT_EXAM_KEYWORDS: &str = "klausur,prüfung,exam";
+/// recurring events are never expanded further than this many days after their first occurrence
+const MAX_EXPANSION_DAYS: u64 = 366;
+
+/// query parameters of the ics import
+#[derive(Deserialize, Debug)]
+pub struct IcsImportQuery {
+    /// create the proposed rows right away instead of returning a preview
+    confirm: Option<bool>,
+    /// start of the semester, occurrences before this date are ignored
+    from: Option<NaiveDate>,
+    /// end of the semester, recurring events are expanded until this date
+    to: Option<NaiveDate>,
+}
+
+/// proposal of courses and exams found in a calendar, can be edited by the client and sent back to confirm
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct IcsPreview {
+    pub courses: Vec<CoursePreview>,
+}
+
+/// a course proposed from all events sharing the same SUMMARY
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct CoursePreview {
+    pub name: String,
+    /// number of (expanded) non exam events
+    #[serde(default)]
+    pub lectures: usize,
+    #[serde(default)]
+    pub first_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub last_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub exams: Vec<ExamPreview>,
+}
+
+/// an exam proposed from an event matching one of the exam keywords
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct ExamPreview {
+    pub name: String,
+    pub date: NaiveDate,
+}
+
+/// ids of the rows created by a confirmed import
+#[derive(Deserialize, Serialize, Debug)]
+pub struct IcsImportResult {
+    course_ids: Vec<i32>,
+    exam_ids: Vec<i32>,
+}
+
+/// the parts of a VEVENT the import cares about
+#[derive(Debug)]
+struct IcsEvent {
+    summary: String,
+    /// lowercase SUMMARY, DESCRIPTION and CATEGORIES, used for keyword detection
+    search_text: String,
+    dates: Vec<NaiveDate>,
+}
+
+/// handler for ics uploads, the body is the raw calendar file
+/// returns a preview, or creates the proposed rows if confirm=true is passed
+pub async fn handle_import_ics<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Query(query): Query<IcsImportQuery>,
+    body: String,
+) -> Result<Response, ApiError> {
+    info!("ICS import requested!");
+
+    let AuthenticatedUser {
+        user_id,
+        remote_token_id,
+        remote_token,
+    } = user;
+
+    let events = parse_ics(&body, query.from, query.to).map_err(|err| {
+        info!("Uploaded calendar could not be parsed: {}", err);
+        (StatusCode::UNPROCESSABLE_ENTITY, err.to_string())
+    })?;
+    let preview = build_preview(events, &exam_keywords());
+
+    if !query.confirm.unwrap_or(false) {
+        info!(
+            "ICS preview built, {} courses proposed.",
+            preview.courses.len()
+        );
+        return Ok(Json(preview).into_response());
+    }
+
+    let result = import_preview(user_id, remote_token_id, &remote_token, preview, state).await?;
+    Ok(Json(result).into_response())
+}
+
+/// handler for confirming a (possibly edited) preview returned by handle_import_ics
+pub async fn handle_import_ics_confirm<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Json(preview): Json<IcsPreview>,
+) -> Result<Json<IcsImportResult>, ApiError> {
+    info!("ICS import confirmation requested!");
+
+    let AuthenticatedUser {
+        user_id,
+        remote_token_id,
+        remote_token,
+    } = user;
+
+    let result = import_preview(user_id, remote_token_id, &remote_token, preview, state).await?;
+    Ok(Json(result))
+}
+
+/// creates courses and exams of a preview through the usual encrypted path
+/// like bulk requests at most MAX_BULK_SIZE objects are created and the payload limits apply to them
+async fn import_preview<DB: DBInterface + Send + Sync>(
+    user_id: i32,
+    remote_token_id: i32,
+    remote_token: &str,
+    preview: IcsPreview,
+    state: Arc<AppState<DB>>,
+) -> Result<IcsImportResult, ApiError> {
+    // the course id of the exams is set once their course is inserted
+    let courses: Vec<(CourseSend, Vec<ExamSend>)> = preview
+        .courses
+        .into_iter()
+        .map(|course| {
+            let exams = course
+                .exams
+                .into_iter()
+                .map(|exam| ExamSend {
+                    id: None,
+                    course_id: 0,
+                    name: exam.name,
+                    date: exam.date,
+                    prep_status: PrepStatus::default(),
+                    prep_notes: None,
+                    created_at: Default::default(),
+                    updated_at: None,
+                })
+                .collect();
+            let course = CourseSend {
+                id: None,
+                name: course.name,
+                semester_id: None,
+                archived: false,
+                created_at: Default::default(),
+                updated_at: None,
+            };
+            (course, exams)
+        })
+        .collect();
+
+    let objects: usize = courses.iter().map(|(_, exams)| 1 + exams.len()).sum();
+    if objects > MAX_BULK_SIZE {
+        info!("ICS import of {} objects rejected!", objects);
+        return Err(payload_too_large(vec![FieldError {
+            field: "courses".to_string(),
+            message: format!("has more than {} courses and exams", MAX_BULK_SIZE),
+        }]));
+    }
+    // errors are named by the path of the object in the preview
+    let mut errors = Vec::new();
+    for (i, (course, exams)) in courses.iter().enumerate() {
+        let exam_errors = exams.iter().enumerate().flat_map(|(j, exam)| {
+            let errors = state.payload_limits.check(exam).err().unwrap_or_default();
+            errors.into_iter().map(move |error| FieldError {
+                field: format!("courses.{}.exams.{}.{}", i, j, error.field),
+                message: error.message,
+            })
+        });
+        let course_errors = state.payload_limits.check(course).err().unwrap_or_default();
+        errors.extend(
+            course_errors
+                .into_iter()
+                .map(|error| FieldError {
+                    field: format!("courses.{}.{}", i, error.field),
+                    message: error.message,
+                })
+                .chain(exam_errors),
+        );
+    }
+    if !errors.is_empty() {
+        return Err(payload_too_large(errors));
+    }
+
+    // both types are encrypted with their own local token
+    let local_token_for = async |ident| {
+        decrypt_local_token_for(
+            user_id,
+            &ident,
+            remote_token_id,
+            remote_token,
+            state.clone(),
+        )
+        .await
+        .map_err(|err| decrypt_failed_status(err, &ident, remote_token_id))
+    };
+    let course_token = local_token_for(CourseDB::get_db_ident()).await?;
+    let exam_token = local_token_for(ExamDB::get_db_ident()).await?;
+
+    // either the whole timetable is created or nothing, webhooks are only sent after the commit
+    let transaction_state = state.clone();
+    let result = state
+        .db
+        .with_transaction(move |db| {
+            Box::pin(async move {
+                let provider = &transaction_state.crypt_provider;
+                let mut result = IcsImportResult {
+                    course_ids: Vec::new(),
+                    exam_ids: Vec::new(),
+                };
+                for (course, exams) in courses {
+                    let mut params = db_param_map! { user_id: user_id };
+                    params.extend(course.to_param_vec(course_token.as_bytes(), provider));
+                    let course_id = db.new_entry::<CourseDB>(params).await?;
+                    result.course_ids.push(course_id);
+
+                    for exam in exams {
+                        let exam = ExamSend { course_id, ..exam };
+                        let mut params = db_param_map! { user_id: user_id };
+                        params.extend(exam.to_param_vec(exam_token.as_bytes(), provider));
+                        result.exam_ids.push(db.new_entry::<ExamDB>(params).await?);
+                    }
+                }
+                Ok(result)
+            })
+        })
+        .await
+        .map_err(|err: DbError| {
+            error!(
+                "Failed to insert imported entries into db! (user id: {}): {}",
+                user_id, err
+            );
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    for (ident, ids) in [
+        (CourseDB::get_db_ident(), &result.course_ids),
+        (ExamDB::get_db_ident(), &result.exam_ids),
+    ] {
+        for id in ids {
+            record_change(&state, user_id, &ident, WebhookAction::Create, *id).await;
+        }
+    }
+
+    info!(
+        "ICS import successful, created {} courses and {} exams.",
+        result.course_ids.len(),
+        result.exam_ids.len()
+    );
+    Ok(result)
+}
+
+/// returns the configured exam keywords (lowercase), configurable via ICS_EXAM_KEYWORDS as a comma separated list
+fn exam_keywords() -> Vec<String> {
+    env::var("ICS_EXAM_KEYWORDS")
+        .unwrap_or(DEFAULT_EXAM_KEYWORDS.to_string())
+        .split(',')
+        .map(|keyword| keyword.trim().to_lowercase())
+        .filter(|keyword| !keyword.is_empty())
+        .collect()
+}
+
+/// parses a calendar file and expands recurring events between from and to
+fn parse_ics(
+    data: &str,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Result<Vec<IcsEvent>, Box<dyn Error>> {
+    let mut events = Vec::new();
+    let mut calendars = 0;
+
+    for calendar in IcalParser::new(BufReader::new(data.as_bytes())) {
+        calendars += 1;
+        for event in calendar?.events {
+            events.push(parse_event(&event, from, to)?);
+        }
+    }
+
+    if calendars == 0 {
+        return Err("no VCALENDAR found".into());
+    }
+
+    Ok(events)
+}
+
+/// converts a single VEVENT, including the expansion of its RRULE
+fn parse_event(
+    event: &IcalEvent,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Result<IcsEvent, Box<dyn Error>> {
+    let property = |name: &str| {
+        event
+            .properties
+            .iter()
+            .find(|p| p.name == name)
+            .and_then(|p| p.value.as_deref())
+    };
+
+    let summary = unescape_text(property("SUMMARY").ok_or("VEVENT without SUMMARY")?);
+    let start = parse_ics_date(property("DTSTART").ok_or("VEVENT without DTSTART")?)?;
+
+    // EXDATE may occur multiple times and may contain a list of dates
+    let exdates = event
+        .properties
+        .iter()
+        .filter(|p| p.name == "EXDATE")
+        .filter_map(|p| p.value.as_deref())
+        .flat_map(|value| value.split(','))
+        .map(parse_ics_date)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut dates = match property("RRULE") {
+        Some(rrule) => expand_rrule(start, rrule, to)?,
+        None => vec![start],
+    };
+    dates.retain(|date| {
+        !exdates.contains(date)
+            && from.is_none_or(|from| *date >= from)
+            && to.is_none_or(|to| *date <= to)
+    });
+
+    let search_text = [
+        property("SUMMARY"),
+        property("DESCRIPTION"),
+        property("CATEGORIES"),
+    ]
+    .iter()
+    .flatten()
+    .map(|value| unescape_text(value).to_lowercase())
+    .collect::<Vec<_>>()
+    .join("\n");
+
+    Ok(IcsEvent {
+        summary,
+        search_text,
+        dates,
+    })
+}
+
+/// parses the date part of DATE and DATE-TIME values (e.g. 20250303 or 20250303T090000Z)
+fn parse_ics_date(value: &str) -> Result<NaiveDate, Box<dyn Error>> {
+    let date = value.get(..8).ok_or(format!("invalid date {}", value))?;
+    Ok(NaiveDate::parse_from_str(date, "%Y%m%d")?)
+}
+
+/// removes the TEXT escaping of RFC 5545
+fn unescape_text(value: &str) -> String {
+    value
+        .replace("\\n", "\n")
+        .replace("\\N", "\n")
+        .replace("\\,", ",")
+        .replace("\\;", ";")
+        .replace("\\\\", "\\")
+        .trim()
+        .to_string()
+}
+
+/// expands a RRULE into the dates of its occurrences
+/// supports FREQ DAILY, WEEKLY (with BYDAY) and MONTHLY together with INTERVAL, COUNT and UNTIL
+/// the expansion stops at UNTIL, after COUNT occurrences, at the end of the semester or MAX_EXPANSION_DAYS after start
+fn expand_rrule(
+    start: NaiveDate,
+    rrule: &str,
+    semester_end: Option<NaiveDate>,
+) -> Result<Vec<NaiveDate>, Box<dyn Error>> {
+    let rule: BTreeMap<&str, &str> = rrule
+        .split(';')
+        .filter_map(|part| part.split_once('='))
+        .collect();
+
+    let interval: u32 = rule.get("INTERVAL").map_or(Ok(1), |i| i.parse())?;
+    let count: Option<usize> = rule.get("COUNT").map(|c| c.parse()).transpose()?;
+    let until = rule.get("UNTIL").map(|u| parse_ics_date(u)).transpose()?;
+    if interval == 0 {
+        return Err("RRULE INTERVAL must not be 0".into());
+    }
+
+    let mut end = start + Days::new(MAX_EXPANSION_DAYS);
+    for limit in [until, semester_end].into_iter().flatten() {
+        end = end.min(limit);
+    }
+
+    // candidate dates, generated in ascending order
+    let candidates: Box<dyn Iterator<Item = NaiveDate>> = match rule.get("FREQ") {
+        Some(&"DAILY") => Box::new(
+            (0..).map_while(move |i| start.checked_add_days(Days::new(i * interval as u64))),
+        ),
+        Some(&"WEEKLY") => {
+            let mut weekdays = match rule.get("BYDAY") {
+                Some(days) => days
+                    .split(',')
+                    .map(parse_weekday)
+                    .collect::<Result<Vec<_>, _>>()?,
+                None => vec![start.weekday()],
+            };
+            weekdays.sort_by_key(|day| day.num_days_from_monday());
+
+            let week_start = start - Days::new(start.weekday().num_days_from_monday() as u64);
+            Box::new(
+                (0..)
+                    .map_while(move |i| {
+                        week_start.checked_add_days(Days::new(i * 7 * interval as u64))
+                    })
+                    .flat_map(move |week| {
+                        weekdays
+                            .clone()
+                            .into_iter()
+                            .map(move |day| week + Days::new(day.num_days_from_monday() as u64))
+                    })
+                    .filter(move |date| *date >= start),
+            )
+        }
+        Some(&"MONTHLY") => {
+            Box::new((0..).map_while(move |i| start.checked_add_months(Months::new(i * interval))))
+        }
+        freq => {
+            warn!(
+                "Unsupported RRULE frequency {:?}, using first occurrence only.",
+                freq
+            );
+            Box::new(std::iter::once(start))
+        }
+    };
+
+    Ok(candidates
+        .take_while(|date| *date <= end)
+        .take(count.unwrap_or(usize::MAX))
+        .collect())
+}
+
+/// parses a BYDAY entry like MO or TU, ordinal prefixes (e.g. 1MO) are ignored
+fn parse_weekday(day: &str) -> Result<Weekday, Box<dyn Error>> {
+    let day = day.trim_start_matches(|c: char| c == '+' || c == '-' || c.is_ascii_digit());
+    match day {
+        "MO" => Ok(Weekday::Mon),
+        "TU" => Ok(Weekday::Tue),
+        "WE" => Ok(Weekday::Wed),
+        "TH" => Ok(Weekday::Thu),
+        "FR" => Ok(Weekday::Fri),
+        "SA" => Ok(Weekday::Sat),
+        "SU" => Ok(Weekday::Sun),
+        _ => Err(format!("invalid BYDAY value {}", day).into()),
+    }
+}
+
+/// groups events by SUMMARY into courses and attaches the events matching an exam keyword to them
+fn build_preview(events: Vec<IcsEvent>, keywords: &[String]) -> IcsPreview {
+    let is_exam = |event: &IcsEvent| keywords.iter().any(|k| event.search_text.contains(k));
+    let (exams, lectures): (Vec<_>, Vec<_>) = events.into_iter().partition(is_exam);
+
+    // BTreeMap keeps the courses sorted by name
+    let mut courses: BTreeMap<String, CoursePreview> = BTreeMap::new();
+    let new_course = |name: &str| CoursePreview {
+        name: name.to_string(),
+        lectures: 0,
+        first_date: None,
+        last_date: None,
+        exams: Vec::new(),
+    };
+
+    for lecture in lectures {
+        let course = courses
+            .entry(lecture.summary.clone())
+            .or_insert_with(|| new_course(&lecture.summary));
+        course.lectures += lecture.dates.len();
+        course.first_date = course
+            .first_date
+            .into_iter()
+            .chain(lecture.dates.first().copied())
+            .min();
+        course.last_date = course
+            .last_date
+            .into_iter()
+            .chain(lecture.dates.last().copied())
+            .max();
+    }
+
+    for exam in exams {
+        // prefer the longest lecture course mentioned in the exam summary, otherwise strip the keywords
+        let summary_lower = exam.summary.to_lowercase();
+        let course_name = courses
+            .keys()
+            .filter(|name| summary_lower.contains(&name.to_lowercase()))
+            .max_by_key(|name| name.len())
+            .cloned()
+            .unwrap_or_else(|| strip_keywords(&exam.summary, keywords));
+
+        let course = courses
+            .entry(course_name.clone())
+            .or_insert_with(|| new_course(&course_name));
+        course
+            .exams
+            .extend(exam.dates.iter().map(|date| ExamPreview {
+                name: exam.summary.clone(),
+                date: *date,
+            }));
+    }
+
+    IcsPreview {
+        courses: courses.into_values().collect(),
+    }
+}
+
+/// removes exam keywords and leftover separators from a summary, e.g. "Klausur: Statistik" => "Statistik"
+fn strip_keywords(summary: &str, keywords: &[String]) -> String {
+    let stripped = summary
+        .split_whitespace()
+        .filter(|word| {
+            let word = word
+                .trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase();
+            !keywords.contains(&word)
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    let stripped = stripped.trim_matches(|c: char| c.is_whitespace() || ":-–".contains(c));
+
+    if stripped.is_empty() {
+        summary.to_string()
+    } else {
+        stripped.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        data_handler::data_router,
+        test_util::{register_user, send_json, test_state},
+    };
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn keywords() -> Vec<String> {
+        DEFAULT_EXAM_KEYWORDS.split(',').map(String::from).collect()
+    }
+
+    #[test]
+    fn test_rapla_preview() {
+        let events = parse_ics(include_str!("../../test/ics/rapla.ics"), None, None).unwrap();
+        let preview = build_preview(events, &keywords());
+
+        assert_eq!(preview.courses.len(), 2);
+
+        let db = &preview.courses[0];
+        assert_eq!(db.name, "Datenbanken, Teil 2");
+        // COUNT=3 with two days a week
+        assert_eq!(db.lectures, 3);
+        assert_eq!(db.first_date, Some(date(2025, 3, 4)));
+        assert_eq!(db.last_date, Some(date(2025, 3, 11)));
+        assert!(db.exams.is_empty());
+
+        let math = &preview.courses[1];
+        assert_eq!(math.name, "Mathematik II");
+        // weekly until end of march, one EXDATE
+        assert_eq!(math.lectures, 4);
+        assert_eq!(math.last_date, Some(date(2025, 3, 31)));
+        assert_eq!(
+            math.exams,
+            vec![ExamPreview {
+                name: "Mathematik II".to_string(),
+                date: date(2025, 4, 7)
+            }]
+        );
+    }
+
+    #[test]
+    fn test_hisinone_preview() {
+        let events = parse_ics(include_str!("../../test/ics/hisinone.ics"), None, None).unwrap();
+        let preview = build_preview(events, &keywords());
+
+        let names: Vec<&str> = preview.courses.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["Rechnernetze", "Software Engineering", "Statistik"]
+        );
+
+        let se = &preview.courses[1];
+        assert_eq!(se.lectures, 2);
+        assert_eq!(se.exams.len(), 1);
+        assert_eq!(se.exams[0].name, "Klausur: Software Engineering");
+        assert_eq!(se.exams[0].date, date(2025, 7, 15));
+
+        let statistics = &preview.courses[2];
+        assert_eq!(statistics.lectures, 0);
+        assert_eq!(statistics.exams[0].date, date(2025, 7, 18));
+    }
+
+    #[test]
+    fn test_semester_range() {
+        let events = parse_ics(
+            include_str!("../../test/ics/rapla.ics"),
+            Some(date(2025, 3, 10)),
+            Some(date(2025, 3, 24)),
+        )
+        .unwrap();
+        let preview = build_preview(events, &keywords());
+
+        let math = &preview.courses[1];
+        // 10th and 24th, 17th is excluded
+        assert_eq!(math.lectures, 2);
+        // exams outside of the range are dropped as well
+        assert!(math.exams.is_empty());
+    }
+
+    #[test]
+    fn test_expand_rrule() {
+        let start = date(2025, 1, 31);
+        assert_eq!(
+            expand_rrule(start, "FREQ=MONTHLY;COUNT=3", None).unwrap(),
+            vec![start, date(2025, 2, 28), date(2025, 3, 31)]
+        );
+        assert_eq!(
+            expand_rrule(start, "FREQ=DAILY;INTERVAL=2;UNTIL=20250206", None).unwrap(),
+            vec![start, date(2025, 2, 2), date(2025, 2, 4), date(2025, 2, 6)]
+        );
+        // without limits, the expansion is capped
+        assert_eq!(expand_rrule(start, "FREQ=WEEKLY", None).unwrap().len(), 53);
+        assert!(expand_rrule(start, "FREQ=WEEKLY;BYDAY=XY", None).is_err());
+    }
+
+    #[test]
+    fn test_malformed_calendar() {
+        assert!(parse_ics("this is not a calendar", None, None).is_err());
+        assert!(parse_ics("", None, None).is_err());
+        assert!(parse_ics("BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:x\n", None, None).is_err());
+        assert!(
+            parse_ics(
+                "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:x\nDTSTART:2025\nEND:VEVENT\nEND:VCALENDAR\n",
+                None,
+                None
+            )
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_confirmation_creates_nothing() {
+        let state = test_state().await;
+        let token = register_user(&state, "ics_user").await;
+        let router = data_router(state.clone()).await;
+        let preview = json!({ "courses": [
+            { "name": "Mathematik II", "exams": [] },
+            { "name": "Datenbanken", "exams": [{ "name": "Datenbanken", "date": "2025-04-07" }] },
+        ] });
+
+        // the first course is inserted before the exam fails
+        state.db.execute_batch(
+            "CREATE TRIGGER fail_exam BEFORE INSERT ON ExamDB
+             BEGIN SELECT RAISE(ABORT, 'simulated failure'); END;",
+        );
+        let (status, _) = send_json(
+            router.clone(),
+            Method::POST,
+            "/import/ics/confirm",
+            &token,
+            Some(preview.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        let (status, courses) =
+            send_json(router.clone(), Method::GET, "/course", &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(courses, json!([]));
+
+        state.db.execute_batch("DROP TRIGGER fail_exam;");
+        let (status, result) = send_json(
+            router.clone(),
+            Method::POST,
+            "/import/ics/confirm",
+            &token,
+            Some(preview),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(result["course_ids"].as_array().unwrap().len(), 2);
+        assert_eq!(result["exam_ids"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn large_confirmations_are_rejected() {
+        let state = test_state().await;
+        let token = register_user(&state, "ics_large_user").await;
+        let router = data_router(state.clone()).await;
+        let confirm = |preview| {
+            send_json(
+                router.clone(),
+                Method::POST,
+                "/import/ics/confirm",
+                &token,
+                Some(preview),
+            )
+        };
+
+        let exams: Vec<_> = (0..MAX_BULK_SIZE)
+            .map(|_| json!({ "name": "Klausur", "date": "2025-04-07" }))
+            .collect();
+        let (status, body) =
+            confirm(json!({ "courses": [{ "name": "Mathematik II", "exams": exams }] })).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["field_errors"][0]["field"], "courses");
+
+        let name = "x".repeat(5000);
+        let (status, body) = confirm(json!({ "courses": [
+            { "name": "Mathematik II", "exams": [{ "name": name, "date": "2025-04-07" }] },
+        ] }))
+        .await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["field_errors"][0]["field"], "courses.0.exams.0.name");
+
+        let (_, courses) = send_json(router.clone(), Method::GET, "/course", &token, None).await;
+        assert_eq!(courses, json!([]));
+    }
+}
@@ -0,0 +1,263 @@
+use std::{collections::HashMap, env};
+
+use axum::{
+    Router,
+    extract::DefaultBodyLimit,
+    http::StatusCode,
+    middleware::map_response,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use serde_json::Value;
+use tower_http::limit::RequestBodyLimitLayer;
+
+use crate::api_error::{ApiError, FieldError, FieldErrorResponse};
+
+/// size of a request body if MAX_BODY_BYTES is not set
+const DEFAULT_MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+/// size of a text field if neither MAX_FIELD_BYTES nor a limit of the field is set
+const DEFAULT_MAX_FIELD_BYTES: usize = 4 * 1024;
+/// env prefix of the limits of single fields, e.g. MAX_FIELD_BYTES_DETAILS
+const FIELD_LIMIT_PREFIX: &str = "MAX_FIELD_BYTES_";
+
+/// upper bounds of the size of requests to the data routes, read from the env
+/// text fields are checked before they get encrypted, so the error can name the field
+#[derive(Debug, Clone)]
+pub struct PayloadLimits {
+    /// size of a whole request body
+    pub max_body_bytes: usize,
+    /// size of every text field without its own limit
+    pub max_field_bytes: usize,
+    /// size of single text fields by field name
+    pub field_bytes: HashMap<String, usize>,
+}
+
+impl Default for PayloadLimits {
+    fn default() -> Self {
+        Self {
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            max_field_bytes: DEFAULT_MAX_FIELD_BYTES,
+            // details and the content of notes are the only long texts
+            field_bytes: HashMap::from([
+                ("details".to_string(), 64 * 1024),
+                ("content".to_string(), 64 * 1024),
+            ]),
+        }
+    }
+}
+
+impl PayloadLimits {
+    /// reads the limits from MAX_BODY_BYTES, MAX_FIELD_BYTES and MAX_FIELD_BYTES_(FIELD NAME), e.g. MAX_FIELD_BYTES_DETAILS
+    /// panics on invalid values, so misconfigurations are noticed at startup
+    pub fn from_env() -> Self {
+        let parse = |name: &str, value: String| -> usize {
+            match value.parse() {
+                Ok(bytes) if bytes > 0 => bytes,
+                _ => panic!("{} has to be a positive number", name),
+            }
+        };
+
+        let mut limits = Self::default();
+        if let Ok(value) = env::var("MAX_BODY_BYTES") {
+            limits.max_body_bytes = parse("MAX_BODY_BYTES", value);
+        }
+        if let Ok(value) = env::var("MAX_FIELD_BYTES") {
+            limits.max_field_bytes = parse("MAX_FIELD_BYTES", value);
+        }
+        for (name, value) in env::vars() {
+            if let Some(field) = name.strip_prefix(FIELD_LIMIT_PREFIX) {
+                let bytes = parse(&name, value);
+                limits.field_bytes.insert(field.to_lowercase(), bytes);
+            }
+        }
+        limits
+    }
+
+    /// maximum size of a text field
+    fn field_limit(&self, field: &str) -> usize {
+        self.field_bytes
+            .get(field)
+            .copied()
+            .unwrap_or(self.max_field_bytes)
+    }
+
+    /// checks the size of every text field of an object (strings in lists count separately)
+    /// returns the fields that are too large
+    pub fn check<ST: Serialize>(&self, entry: &ST) -> Result<(), Vec<FieldError>> {
+        let Ok(Value::Object(fields)) = serde_json::to_value(entry) else {
+            return Ok(());
+        };
+
+        let errors: Vec<FieldError> = fields
+            .iter()
+            .filter(|(field, value)| {
+                let limit = self.field_limit(field);
+                match value {
+                    Value::String(text) => text.len() > limit,
+                    Value::Array(values) => values
+                        .iter()
+                        .any(|value| value.as_str().is_some_and(|text| text.len() > limit)),
+                    _ => false,
+                }
+            })
+            .map(|(field, _)| FieldError {
+                field: field.clone(),
+                message: format!("is larger than {} bytes", self.field_limit(field)),
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// like check, for the objects of a bulk request, the fields are prefixed with the index of their object
+    pub fn check_all<ST: Serialize>(&self, entries: &[ST]) -> Result<(), Vec<FieldError>> {
+        let errors: Vec<FieldError> = entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| self.check(entry).err().map(|errors| (i, errors)))
+            .flat_map(|(i, errors)| {
+                errors.into_iter().map(move |error| FieldError {
+                    field: format!("{}.{}", i, error.field),
+                    message: error.message,
+                })
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// response to an object with too large fields
+pub fn payload_too_large(errors: Vec<FieldError>) -> ApiError {
+    ApiError::with_field_errors(StatusCode::PAYLOAD_TOO_LARGE, errors)
+}
+
+/// limits the size of the request bodies of the router to max_body_bytes
+pub fn with_body_limit(router: Router, max_body_bytes: usize) -> Router {
+    router
+        // replaces the limit of the json extractor, otherwise the smaller of both would apply
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(max_body_bytes))
+        .layer(map_response(move |response: Response| async move {
+            body_too_large(response, max_body_bytes)
+        }))
+}
+
+/// response mapper, replaces the body of rejected large requests with a field error
+/// responses naming the too large fields are kept
+fn body_too_large(response: Response, max_body_bytes: usize) -> Response {
+    let has_field_errors = response.extensions().get::<FieldErrorResponse>().is_some();
+    if response.status() != StatusCode::PAYLOAD_TOO_LARGE || has_field_errors {
+        return response;
+    }
+
+    payload_too_large(vec![FieldError {
+        field: "body".to_string(),
+        message: format!("is larger than {} bytes", max_body_bytes),
+    }])
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    use crate::{
+        data_handler::data_router,
+        test_util::{create_course, register_user, send_json, test_state},
+    };
+
+    use super::with_body_limit;
+
+    #[tokio::test]
+    async fn oversized_fields_are_rejected_with_their_name() {
+        let state = test_state().await;
+        let token = register_user(&state, "limit_user").await;
+        let router = data_router(state.clone()).await;
+        let course_id = create_course(router.clone(), &token).await;
+        let limit = state.payload_limits.field_limit("details");
+
+        let topic = |details: String| {
+            Some(json!({ "id": null, "course_id": course_id, "name": "Notes", "details": details }))
+        };
+        let (status, _) = send_json(
+            router.clone(),
+            Method::POST,
+            "/topic",
+            &token,
+            topic("a".repeat(limit)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = send_json(
+            router.clone(),
+            Method::POST,
+            "/topic",
+            &token,
+            topic("a".repeat(limit + 1)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["field_errors"][0]["field"], "details");
+
+        // names have the default limit, bulk errors name the object
+        let name_limit = state.payload_limits.max_field_bytes;
+        let (status, body) = send_json(
+            router.clone(),
+            Method::POST,
+            "/course/bulk",
+            &token,
+            Some(json!([
+                { "id": null, "name": "a".repeat(name_limit) },
+                { "id": null, "name": "a".repeat(name_limit + 1) },
+            ])),
+        )
+        .await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            body["field_errors"],
+            json!([{ "field": "1.name", "message": format!("is larger than {} bytes", name_limit) }])
+        );
+        let (_, topics) = send_json(router, Method::GET, "/topic", &token, None).await;
+        assert_eq!(topics.as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_are_rejected() {
+        let state = test_state().await;
+        let token = register_user(&state, "body_limit_user").await;
+        let router = with_body_limit(data_router(state.clone()).await, 1024);
+
+        // the body is the name plus the json around it
+        let course = |name: String| Some(json!({ "id": null, "name": name }));
+        let overhead = json!({ "id": null, "name": "" }).to_string().len();
+        let (status, _) = send_json(
+            router.clone(),
+            Method::POST,
+            "/course",
+            &token,
+            course("a".repeat(1024 - overhead)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = send_json(
+            router,
+            Method::POST,
+            "/course",
+            &token,
+            course("a".repeat(1024 - overhead + 1)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["field_errors"][0]["field"], "body");
+    }
+}
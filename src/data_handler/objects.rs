@@ -1,28 +1,44 @@
-use std::error::Error;
+use std::{error::Error, fmt, str::FromStr};
 
-use chrono::NaiveDate;
+use async_graphql::{Enum, InputObject, SimpleObject};
+use async_trait::async_trait;
+use chrono::{Days, Months, NaiveDate, NaiveDateTime, Utc};
 use eduflow_derive::{DBObject, SendObject};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::{
+    api_error::FieldError,
     crypt::{Cryptable, crypt_provider::CryptProviders, crypt_types::CryptString},
     db::{
-        DBObjIdent,
+        DBInterface, DBObjIdent, DbError,
         sql_helper::{SQLGenerate, SQLValue},
     },
-    db_param_map,
+    db_param_map, db_partial_param_map, sql_value_from_text,
 };
 
-use super::{FromDB, ToDB};
+use super::{
+    CheckReferences, FromDB, ToDB, ToPartialDB,
+    flashcard::{INITIAL_EASE, MAX_EASE, MAX_INTERVAL_DAYS, MIN_EASE},
+    grade::grade_scale,
+    missing_reference,
+    todo::parent_errors,
+    webhook::{allowed_hosts, check_webhook_url},
+};
 
 /// create a list of all db object idents here
-pub fn get_db_idents() -> [DBObjIdent; 5] {
+pub fn get_db_idents() -> [DBObjIdent; 11] {
     [
         CourseDB::get_db_ident(),
         TopicDB::get_db_ident(),
         StudyGoalDB::get_db_ident(),
         ExamDB::get_db_ident(),
         ToDoDB::get_db_ident(),
+        WebhookSubscriptionDB::get_db_ident(),
+        NoteDB::get_db_ident(),
+        StudySessionDB::get_db_ident(),
+        GradeDB::get_db_ident(),
+        FlashcardDB::get_db_ident(),
+        SemesterDB::get_db_ident(),
     ]
 }
 
@@ -39,28 +55,77 @@ pub fn get_db_idents() -> [DBObjIdent; 5] {
 // send types need an id field at first position (Option<i32>)
 // send types are used for creating new objects in the db and returning objects to the client, they have to impl CourseSend and FromDB<DBT> with corresponding DB Type
 // send types derive Deserialize, Serialize, SendObject
+// send and patch types impl CheckReferences, the ids of other objects they contain have to belong to the user
+
+// patch types are used for partial edits, every field besides the id is optional, they impl ToPartialDB
+// nullable fields are Option<Option<T>> with deserialize_with = "present", so null unsets them and a missing field keeps them
+
+/// deserializes a field that is present (even if null) as Some, missing fields are None by serde(default)
+fn present<'de, T: Deserialize<'de>, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<T>, D::Error> {
+    T::deserialize(deserializer).map(Some)
+}
 
 // Course
-#[derive(DBObject)]
+#[derive(DBObject, Serialize, Deserialize)]
+#[db(timestamps)]
 pub struct CourseDB {
     pub id: i32,
     pub user_id: i32,
 
     pub name: CryptString,
+    #[db(references = "SemesterDB", on_delete = "set_null")]
+    #[serde(default)] // history entries from before semesters existed
+    pub semester_id: Option<i32>, // unset if the semester is deleted
+    #[db(default = "FALSE")]
+    #[serde(default)]
+    pub archived: bool,
+    // set by the database, see the timestamps attribute
+    #[serde(default)] // history entries from before timestamps existed
+    pub created_at: NaiveDateTime,
+    #[serde(default)]
+    pub updated_at: Option<NaiveDateTime>,
 }
-#[derive(Deserialize, Serialize, SendObject)]
+#[derive(Deserialize, Serialize, SendObject, Clone, SimpleObject, InputObject)]
+#[graphql(complex, input_name = "CourseInput")] // topics and exams are resolved in graphql.rs
 pub struct CourseSend {
-    id: Option<i32>,
-    name: String,
+    pub id: Option<i32>,
+    pub name: String,
+    #[serde(default)]
+    pub semester_id: Option<i32>,
+    #[serde(default)] // archived courses are left out of the course list by default
+    #[graphql(default)]
+    pub archived: bool,
+    // read only, set by the server
+    #[serde(default)]
+    #[graphql(default)]
+    pub created_at: NaiveDateTime,
+    #[serde(default)]
+    pub updated_at: Option<NaiveDateTime>,
 }
 impl ToDB for CourseSend {
     fn to_param_vec(&self, key: &[u8], provider: &CryptProviders) -> Vec<(String, SQLValue)> {
         let name_crypt = CryptString::encrypt(&self.name, key, provider);
         db_param_map! {
-            name: SQLValue::Blob(name_crypt.data_crypt)
+            name: SQLValue::Blob(name_crypt.data_crypt),
+            semester_id: self.semester_id,
+            archived: self.archived,
         }
     }
 }
+#[async_trait]
+impl CheckReferences for CourseSend {
+    async fn missing_references<DB: DBInterface + Send + Sync>(
+        &self,
+        db: &DB,
+        user_id: i32,
+    ) -> Result<Vec<&'static str>, DbError> {
+        let semester =
+            missing_reference::<SemesterDB, DB>(db, user_id, "semester_id", self.semester_id);
+        Ok(semester.await?.into_iter().collect())
+    }
+}
 impl FromDB<CourseDB> for CourseSend {
     fn from_dbt(
         dbt: &CourseDB,
@@ -71,27 +136,98 @@ impl FromDB<CourseDB> for CourseSend {
         Ok(Self {
             id: Some(dbt.id),
             name: name?,
+            semester_id: dbt.semester_id,
+            archived: dbt.archived,
+            created_at: dbt.created_at,
+            updated_at: dbt.updated_at,
         })
     }
 }
+#[derive(Deserialize, Serialize)]
+pub struct CoursePatch {
+    pub id: i32,
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    pub semester_id: Option<Option<i32>>,
+    pub archived: Option<bool>,
+}
+#[async_trait]
+impl CheckReferences for CoursePatch {
+    async fn missing_references<DB: DBInterface + Send + Sync>(
+        &self,
+        db: &DB,
+        user_id: i32,
+    ) -> Result<Vec<&'static str>, DbError> {
+        let semester_id = self.semester_id.flatten();
+        let semester = missing_reference::<SemesterDB, DB>(db, user_id, "semester_id", semester_id);
+        Ok(semester.await?.into_iter().collect())
+    }
+}
+impl ToPartialDB for CoursePatch {
+    fn get_id(&self) -> i32 {
+        self.id
+    }
+
+    fn to_partial_param_vec(
+        &self,
+        key: &[u8],
+        provider: &CryptProviders,
+    ) -> Vec<(String, SQLValue)> {
+        let name_crypt = self
+            .name
+            .as_ref()
+            .map(|name| CryptString::encrypt(name, key, provider).data_crypt);
+        db_partial_param_map! {
+            name: name_crypt,
+            semester_id: self.semester_id,
+            archived: self.archived,
+        }
+    }
+}
 
 // Topic
-#[derive(DBObject)]
+#[derive(DBObject, Serialize, Deserialize)]
+#[db(timestamps, order_by("course_id", "position"))]
 pub struct TopicDB {
     pub id: i32,
     pub user_id: i32,
 
+    #[db(references = "CourseDB", on_delete = "cascade")]
     pub course_id: i32,
     pub name: CryptString,
     pub details: CryptString,
+    #[db(default = "FALSE")]
+    #[serde(default)]
+    pub studied: bool,
+    // new topics are placed last in their course, existing ones start at 0 and keep their id order
+    #[db(default = "0", append_within = "course_id")]
+    #[serde(default)]
+    pub position: i32,
+    #[serde(default)]
+    pub created_at: NaiveDateTime,
+    #[serde(default)]
+    pub updated_at: Option<NaiveDateTime>,
 }
-#[derive(Deserialize, Serialize, SendObject)]
+#[derive(Deserialize, Serialize, SendObject, Clone, SimpleObject, InputObject)]
+#[graphql(input_name = "TopicInput")]
 pub struct TopicSend {
-    id: Option<i32>,
+    pub id: Option<i32>,
 
-    course_id: i32,
-    name: String,
-    details: String,
+    pub course_id: i32,
+    pub name: String,
+    pub details: String,
+    #[serde(default)] // new topics are not studied yet
+    #[graphql(default)]
+    pub studied: bool,
+    // read only, set by POST /topic/reorder
+    #[serde(default)]
+    #[graphql(skip_input)]
+    pub position: i32,
+    #[serde(default)]
+    #[graphql(default)]
+    pub created_at: NaiveDateTime,
+    #[serde(default)]
+    pub updated_at: Option<NaiveDateTime>,
 }
 impl ToDB for TopicSend {
     fn to_param_vec(&self, key: &[u8], provider: &CryptProviders) -> Vec<(String, SQLValue)> {
@@ -101,9 +237,22 @@ impl ToDB for TopicSend {
             course_id: self.course_id,
             name: name_crypt.data_crypt,
             details: details_crypt.data_crypt,
+            studied: self.studied,
         }
     }
 }
+#[async_trait]
+impl CheckReferences for TopicSend {
+    async fn missing_references<DB: DBInterface + Send + Sync>(
+        &self,
+        db: &DB,
+        user_id: i32,
+    ) -> Result<Vec<&'static str>, DbError> {
+        let course =
+            missing_reference::<CourseDB, DB>(db, user_id, "course_id", Some(self.course_id));
+        Ok(course.await?.into_iter().collect())
+    }
+}
 impl FromDB<TopicDB> for TopicSend {
     fn from_dbt(
         dbt: &TopicDB,
@@ -117,72 +266,363 @@ impl FromDB<TopicDB> for TopicSend {
             course_id: dbt.course_id,
             name: name?,
             details: details?,
+            studied: dbt.studied,
+            position: dbt.position,
+            created_at: dbt.created_at,
+            updated_at: dbt.updated_at,
         })
     }
 }
+#[derive(Deserialize, Serialize)]
+pub struct TopicPatch {
+    pub id: i32,
+
+    pub course_id: Option<i32>,
+    pub name: Option<String>,
+    pub details: Option<String>,
+    pub studied: Option<bool>,
+}
+#[async_trait]
+impl CheckReferences for TopicPatch {
+    async fn missing_references<DB: DBInterface + Send + Sync>(
+        &self,
+        db: &DB,
+        user_id: i32,
+    ) -> Result<Vec<&'static str>, DbError> {
+        let course = missing_reference::<CourseDB, DB>(db, user_id, "course_id", self.course_id);
+        Ok(course.await?.into_iter().collect())
+    }
+}
+impl ToPartialDB for TopicPatch {
+    fn get_id(&self) -> i32 {
+        self.id
+    }
+
+    fn to_partial_param_vec(
+        &self,
+        key: &[u8],
+        provider: &CryptProviders,
+    ) -> Vec<(String, SQLValue)> {
+        let encrypt = |text: &String| CryptString::encrypt(text, key, provider).data_crypt;
+        db_partial_param_map! {
+            course_id: self.course_id,
+            name: self.name.as_ref().map(encrypt),
+            details: self.details.as_ref().map(encrypt),
+            studied: self.studied,
+        }
+    }
+}
 
 // Study Goal
-#[derive(DBObject)]
+#[derive(DBObject, Serialize, Deserialize)]
+#[db(timestamps)]
 pub struct StudyGoalDB {
     pub id: i32,
     pub user_id: i32,
 
-    pub topic_id: i32,
+    // no foreign key, goals are kept when their topics are deleted (see topics_missing)
+    pub topic_id: i32, // deprecated, first topic of the goal, see study_goal_topic
     pub deadline: NaiveDate, // FIXME: encrypt this?
+    #[db(default = "FALSE")]
+    #[serde(default)]
+    pub topics_missing: bool, // every topic of the goal has been deleted
+    #[db(references = "ExamDB", on_delete = "set_null")]
+    pub exam_id: Option<i32>, // exam the goal prepares for, unset if the exam is deleted
+    pub title: Option<CryptString>, // none for goals created before goals had titles
+    #[db(check = "target_minutes > 0")]
+    pub target_minutes: Option<i32>,
+    #[db(enum_text, default = "'open'")]
+    #[serde(default)]
+    pub status: GoalStatus,
+    #[serde(default)]
+    pub created_at: NaiveDateTime,
+    #[serde(default)]
+    pub updated_at: Option<NaiveDateTime>,
 }
-#[derive(Deserialize, Serialize, SendObject)]
+
+/// state of a study goal, set by the user
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy, Default, Enum)]
+#[serde(rename_all = "snake_case")]
+pub enum GoalStatus {
+    #[default]
+    Open,
+    Achieved,
+    Missed,
+}
+impl GoalStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GoalStatus::Open => "open",
+            GoalStatus::Achieved => "achieved",
+            GoalStatus::Missed => "missed",
+        }
+    }
+}
+impl fmt::Display for GoalStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+impl FromStr for GoalStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(GoalStatus::Open),
+            "achieved" => Ok(GoalStatus::Achieved),
+            "missed" => Ok(GoalStatus::Missed),
+            _ => Err(format!("unknown goal status {}", s)),
+        }
+    }
+}
+sql_value_from_text!(GoalStatus);
+
+#[derive(Deserialize, Serialize, SendObject, Clone, SimpleObject, InputObject)]
+#[graphql(input_name = "StudyGoalInput")]
 pub struct StudyGoalSend {
-    id: Option<i32>,
+    pub id: Option<i32>,
 
-    topic_id: i32,
-    deadline: NaiveDate,
+    pub topic_id: i32, // deprecated, use topic_ids
+    pub deadline: NaiveDate,
+    // read only, topics are set with PUT /study_goal/{id}/topics
+    #[serde(default)]
+    #[graphql(default)]
+    pub topic_ids: Vec<i32>,
+    // sent back unchanged by clients, cleared when new topics are set
+    #[serde(default)]
+    #[graphql(default)]
+    pub topics_missing: bool,
+    pub exam_id: Option<i32>,
+    #[serde(default)] // older clients do not send the title, target and status
+    #[graphql(default)]
+    pub title: String,
+    #[serde(default)]
+    pub target_minutes: Option<i32>,
+    #[serde(default)]
+    #[graphql(default)]
+    pub status: GoalStatus,
+    // read only, minutes of the study sessions of the topics until the deadline
+    #[serde(default)]
+    #[graphql(skip_input)]
+    pub progress_minutes: i64,
+    #[serde(default)]
+    #[graphql(default)]
+    pub created_at: NaiveDateTime,
+    #[serde(default)]
+    pub updated_at: Option<NaiveDateTime>,
 }
 impl ToDB for StudyGoalSend {
-    fn to_param_vec(&self, _: &[u8], _: &CryptProviders) -> Vec<(String, SQLValue)> {
+    fn to_param_vec(&self, key: &[u8], provider: &CryptProviders) -> Vec<(String, SQLValue)> {
+        let title_crypt = CryptString::encrypt(&self.title, key, provider);
         db_param_map! {
             topic_id: self.topic_id,
             deadline: self.deadline,
+            topics_missing: self.topics_missing,
+            exam_id: self.exam_id,
+            title: title_crypt.data_crypt,
+            target_minutes: self.target_minutes,
+            status: self.status,
         }
     }
 }
+#[async_trait]
+impl CheckReferences for StudyGoalSend {
+    async fn missing_references<DB: DBInterface + Send + Sync>(
+        &self,
+        db: &DB,
+        user_id: i32,
+    ) -> Result<Vec<&'static str>, DbError> {
+        // the topic of a goal whose topics were deleted is gone, it is sent back unchanged
+        let topic_id = (!self.topics_missing).then_some(self.topic_id);
+        let topic = missing_reference::<TopicDB, DB>(db, user_id, "topic_id", topic_id).await?;
+        let exam = missing_reference::<ExamDB, DB>(db, user_id, "exam_id", self.exam_id).await?;
+        Ok(topic.into_iter().chain(exam).collect())
+    }
+}
 impl FromDB<StudyGoalDB> for StudyGoalSend {
-    fn from_dbt(dbt: &StudyGoalDB, _: &[u8], _: &CryptProviders) -> Result<Self, Box<dyn Error>> {
+    fn from_dbt(
+        dbt: &StudyGoalDB,
+        key: &[u8],
+        provider: &CryptProviders,
+    ) -> Result<Self, Box<dyn Error>> {
+        let title = dbt
+            .title
+            .as_ref()
+            .map(|title| title.decrypt(key, provider))
+            .transpose();
         Ok(Self {
             id: Some(dbt.id),
             topic_id: dbt.topic_id,
             deadline: dbt.deadline,
+            // goals without links only have the legacy topic, the links are added by with_topic_ids
+            topic_ids: if dbt.topics_missing {
+                Vec::new()
+            } else {
+                vec![dbt.topic_id]
+            },
+            topics_missing: dbt.topics_missing,
+            exam_id: dbt.exam_id,
+            title: title?.unwrap_or_default(),
+            target_minutes: dbt.target_minutes,
+            status: dbt.status,
+            // the sessions are summed by with_progress
+            progress_minutes: 0,
+            created_at: dbt.created_at,
+            updated_at: dbt.updated_at,
         })
     }
 }
+// topics are set with PUT /study_goal/{id}/topics
+#[derive(Deserialize, Serialize)]
+pub struct StudyGoalPatch {
+    pub id: i32,
+
+    pub deadline: Option<NaiveDate>,
+    #[serde(default, deserialize_with = "present")]
+    pub exam_id: Option<Option<i32>>,
+    pub title: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    pub target_minutes: Option<Option<i32>>,
+    pub status: Option<GoalStatus>,
+}
+#[async_trait]
+impl CheckReferences for StudyGoalPatch {
+    async fn missing_references<DB: DBInterface + Send + Sync>(
+        &self,
+        db: &DB,
+        user_id: i32,
+    ) -> Result<Vec<&'static str>, DbError> {
+        let exam_id = self.exam_id.flatten();
+        let exam = missing_reference::<ExamDB, DB>(db, user_id, "exam_id", exam_id);
+        Ok(exam.await?.into_iter().collect())
+    }
+}
+impl ToPartialDB for StudyGoalPatch {
+    fn get_id(&self) -> i32 {
+        self.id
+    }
+
+    fn to_partial_param_vec(
+        &self,
+        key: &[u8],
+        provider: &CryptProviders,
+    ) -> Vec<(String, SQLValue)> {
+        let encrypt = |text: &String| CryptString::encrypt(text, key, provider).data_crypt;
+        db_partial_param_map! {
+            deadline: self.deadline,
+            exam_id: self.exam_id,
+            title: self.title.as_ref().map(encrypt),
+            target_minutes: self.target_minutes,
+            status: self.status,
+        }
+    }
+}
 
 // Exam
-#[derive(DBObject)]
+#[derive(DBObject, Serialize, Deserialize)]
+#[db(timestamps)]
 pub struct ExamDB {
     pub id: i32,
     pub user_id: i32,
 
+    #[db(references = "CourseDB", on_delete = "cascade")]
     pub course_id: i32,
     pub name: CryptString,
     pub date: NaiveDate, // FIXME: crypt?
+    #[db(enum_text, default = "'not_started'")]
+    #[serde(default)]
+    pub prep_status: PrepStatus,
+    pub prep_notes: Option<CryptString>,
+    #[serde(default)]
+    pub created_at: NaiveDateTime,
+    #[serde(default)]
+    pub updated_at: Option<NaiveDateTime>,
 }
-#[derive(Deserialize, Serialize, SendObject)]
+
+/// how well prepared the user is for an exam
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy, Default, Enum)]
+#[serde(rename_all = "snake_case")]
+pub enum PrepStatus {
+    #[default]
+    NotStarted,
+    InProgress,
+    Confident,
+}
+impl PrepStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PrepStatus::NotStarted => "not_started",
+            PrepStatus::InProgress => "in_progress",
+            PrepStatus::Confident => "confident",
+        }
+    }
+}
+impl fmt::Display for PrepStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+impl FromStr for PrepStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "not_started" => Ok(PrepStatus::NotStarted),
+            "in_progress" => Ok(PrepStatus::InProgress),
+            "confident" => Ok(PrepStatus::Confident),
+            _ => Err(format!("unknown prep status {}", s)),
+        }
+    }
+}
+sql_value_from_text!(PrepStatus);
+
+#[derive(Deserialize, Serialize, SendObject, Clone, SimpleObject, InputObject)]
+#[graphql(input_name = "ExamInput")]
 pub struct ExamSend {
-    id: Option<i32>,
+    pub id: Option<i32>,
 
-    course_id: i32,
-    name: String,
-    date: NaiveDate,
+    pub course_id: i32,
+    pub name: String,
+    pub date: NaiveDate,
+    #[serde(default)] // older clients do not send the preparation fields
+    #[graphql(default)]
+    pub prep_status: PrepStatus,
+    #[serde(default)]
+    pub prep_notes: Option<String>,
+    #[serde(default)]
+    #[graphql(default)]
+    pub created_at: NaiveDateTime,
+    #[serde(default)]
+    pub updated_at: Option<NaiveDateTime>,
 }
 impl ToDB for ExamSend {
     fn to_param_vec(&self, key: &[u8], provider: &CryptProviders) -> Vec<(String, SQLValue)> {
         let name_crypt = CryptString::encrypt(&self.name, key, provider);
+        let prep_notes_crypt = self
+            .prep_notes
+            .as_ref()
+            .map(|notes| CryptString::encrypt(notes, key, provider).data_crypt);
         db_param_map! {
             course_id: self.course_id,
             name: name_crypt.data_crypt,
             date: self.date,
+            prep_status: self.prep_status,
+            prep_notes: prep_notes_crypt,
         }
     }
 }
+#[async_trait]
+impl CheckReferences for ExamSend {
+    async fn missing_references<DB: DBInterface + Send + Sync>(
+        &self,
+        db: &DB,
+        user_id: i32,
+    ) -> Result<Vec<&'static str>, DbError> {
+        let course =
+            missing_reference::<CourseDB, DB>(db, user_id, "course_id", Some(self.course_id));
+        Ok(course.await?.into_iter().collect())
+    }
+}
 impl FromDB<ExamDB> for ExamSend {
     fn from_dbt(
         dbt: &ExamDB,
@@ -190,17 +630,69 @@ impl FromDB<ExamDB> for ExamSend {
         provider: &CryptProviders,
     ) -> Result<Self, Box<dyn Error>> {
         let name = dbt.name.decrypt(key, provider);
+        let prep_notes = dbt
+            .prep_notes
+            .as_ref()
+            .map(|notes| notes.decrypt(key, provider))
+            .transpose();
         Ok(Self {
             id: Some(dbt.id),
             course_id: dbt.course_id,
             name: name?,
             date: dbt.date,
+            prep_status: dbt.prep_status,
+            prep_notes: prep_notes?,
+            created_at: dbt.created_at,
+            updated_at: dbt.updated_at,
         })
     }
 }
+#[derive(Deserialize, Serialize)]
+pub struct ExamPatch {
+    pub id: i32,
+
+    pub course_id: Option<i32>,
+    pub name: Option<String>,
+    pub date: Option<NaiveDate>,
+    pub prep_status: Option<PrepStatus>,
+    #[serde(default, deserialize_with = "present")]
+    pub prep_notes: Option<Option<String>>,
+}
+#[async_trait]
+impl CheckReferences for ExamPatch {
+    async fn missing_references<DB: DBInterface + Send + Sync>(
+        &self,
+        db: &DB,
+        user_id: i32,
+    ) -> Result<Vec<&'static str>, DbError> {
+        let course = missing_reference::<CourseDB, DB>(db, user_id, "course_id", self.course_id);
+        Ok(course.await?.into_iter().collect())
+    }
+}
+impl ToPartialDB for ExamPatch {
+    fn get_id(&self) -> i32 {
+        self.id
+    }
+
+    fn to_partial_param_vec(
+        &self,
+        key: &[u8],
+        provider: &CryptProviders,
+    ) -> Vec<(String, SQLValue)> {
+        let encrypt = |text: &String| CryptString::encrypt(text, key, provider).data_crypt;
+        db_partial_param_map! {
+            course_id: self.course_id,
+            name: self.name.as_ref().map(encrypt),
+            date: self.date,
+            prep_status: self.prep_status,
+            prep_notes: self.prep_notes.as_ref().map(|notes| notes.as_ref().map(encrypt)),
+        }
+    }
+}
 
 // To Do
-#[derive(DBObject)]
+#[derive(DBObject, Serialize, Deserialize)]
+#[db(soft_delete, timestamps)] // deleted todos can be restored, see handle_restore_todo
 pub struct ToDoDB {
     pub id: i32,
     pub user_id: i32,
@@ -208,16 +700,102 @@ pub struct ToDoDB {
     pub name: CryptString,
     pub deadline: NaiveDate,
     pub details: CryptString,
+    #[db(default = "FALSE")]
+    #[serde(default)]
     pub completed: bool,
+    #[serde(default)]
+    pub completed_at: Option<NaiveDateTime>, // set by the server when the todo is completed, unset when it is opened again
+    #[db(default = "0", check = "priority BETWEEN 0 AND 3")]
+    #[serde(default)]
+    pub priority: i32, // 0 none, 1 low, 2 medium, 3 high
+    #[db(references = "ToDoDB", on_delete = "cascade")]
+    #[serde(default)]
+    pub parent_todo_id: Option<i32>, // subtasks have a parent, which is no subtask itself
+    #[db(enum_text)]
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>, // completing the todo creates the next occurrence
+    #[serde(default)]
+    pub recurrence_until: Option<NaiveDate>, // last possible deadline of an occurrence
+    #[serde(default)]
+    pub created_at: NaiveDateTime,
+    #[serde(default)]
+    pub updated_at: Option<NaiveDateTime>,
 }
-#[derive(Deserialize, Serialize, SendObject)]
+
+/// how often a todo repeats
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy, Enum)]
+#[serde(rename_all = "snake_case")]
+pub enum Recurrence {
+    Daily,
+    Weekly,
+    Monthly,
+}
+impl Recurrence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Recurrence::Daily => "daily",
+            Recurrence::Weekly => "weekly",
+            Recurrence::Monthly => "monthly",
+        }
+    }
+
+    /// deadline of the occurrence after the one due on date
+    /// months without the day end on their last day, e.g. the occurrence after Jan 31 is due on Feb 28 (or 29)
+    pub fn next(&self, date: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Recurrence::Daily => date.checked_add_days(Days::new(1)),
+            Recurrence::Weekly => date.checked_add_days(Days::new(7)),
+            Recurrence::Monthly => date.checked_add_months(Months::new(1)),
+        }
+    }
+}
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+impl FromStr for Recurrence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "daily" => Ok(Recurrence::Daily),
+            "weekly" => Ok(Recurrence::Weekly),
+            "monthly" => Ok(Recurrence::Monthly),
+            _ => Err(format!("unknown recurrence {}", s)),
+        }
+    }
+}
+sql_value_from_text!(Recurrence);
+
+#[derive(Deserialize, Serialize, SendObject, Clone, SimpleObject, InputObject)]
+#[graphql(input_name = "ToDoInput")]
 pub struct ToDoSend {
-    id: Option<i32>,
+    pub id: Option<i32>,
 
-    name: String,
-    deadline: NaiveDate, // FIXME: crypt
-    details: String,
-    completed: bool,
+    pub name: String,
+    pub deadline: NaiveDate, // FIXME: crypt
+    pub details: String,
+    #[serde(default)] // new todos are open
+    #[graphql(default)]
+    pub completed: bool,
+    #[serde(default, skip_deserializing)] // read only, set by the server
+    #[graphql(skip_input)]
+    pub completed_at: Option<NaiveDateTime>,
+    #[serde(default)] // no priority
+    #[graphql(default)]
+    pub priority: i32,
+    #[serde(default)]
+    pub parent_todo_id: Option<i32>,
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+    #[serde(default)]
+    pub recurrence_until: Option<NaiveDate>,
+    #[serde(default)]
+    #[graphql(default)]
+    pub created_at: NaiveDateTime,
+    #[serde(default)]
+    pub updated_at: Option<NaiveDateTime>,
 }
 impl ToDB for ToDoSend {
     fn to_param_vec(&self, key: &[u8], provider: &CryptProviders) -> Vec<(String, SQLValue)> {
@@ -228,9 +806,37 @@ impl ToDB for ToDoSend {
             deadline: self.deadline,
             details: details_crypt.data_crypt,
             completed: self.completed,
+            // the time of an earlier completion is kept, see handle_save_todo
+            completed_at: self
+                .completed
+                .then(|| self.completed_at.unwrap_or_else(|| Utc::now().naive_utc())),
+            priority: self.priority,
+            parent_todo_id: self.parent_todo_id,
+            recurrence: self.recurrence,
+            recurrence_until: self.recurrence_until,
         }
     }
 }
+#[async_trait]
+impl CheckReferences for ToDoSend {
+    async fn missing_references<DB: DBInterface + Send + Sync>(
+        &self,
+        db: &DB,
+        user_id: i32,
+    ) -> Result<Vec<&'static str>, DbError> {
+        let parent =
+            missing_reference::<ToDoDB, DB>(db, user_id, "parent_todo_id", self.parent_todo_id);
+        Ok(parent.await?.into_iter().collect())
+    }
+
+    async fn invalid_references<DB: DBInterface + Send + Sync>(
+        &self,
+        db: &DB,
+        user_id: i32,
+    ) -> Result<Vec<FieldError>, DbError> {
+        parent_errors(db, user_id, self.id, self.parent_todo_id).await
+    }
+}
 impl FromDB<ToDoDB> for ToDoSend {
     fn from_dbt(
         dbt: &ToDoDB,
@@ -245,6 +851,706 @@ impl FromDB<ToDoDB> for ToDoSend {
             deadline: dbt.deadline,
             details: details?,
             completed: dbt.completed,
+            completed_at: dbt.completed_at,
+            priority: dbt.priority,
+            parent_todo_id: dbt.parent_todo_id,
+            recurrence: dbt.recurrence,
+            recurrence_until: dbt.recurrence_until,
+            created_at: dbt.created_at,
+            updated_at: dbt.updated_at,
         })
     }
 }
+#[derive(Deserialize, Serialize)]
+pub struct ToDoPatch {
+    pub id: i32,
+
+    pub name: Option<String>,
+    pub deadline: Option<NaiveDate>,
+    pub details: Option<String>,
+    pub completed: Option<bool>,
+    /// follows completed, set by handle_patch_todo
+    #[serde(skip)]
+    pub completed_at: Option<Option<NaiveDateTime>>,
+    pub priority: Option<i32>,
+    #[serde(default, deserialize_with = "present")]
+    pub parent_todo_id: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "present")]
+    pub recurrence: Option<Option<Recurrence>>,
+    #[serde(default, deserialize_with = "present")]
+    pub recurrence_until: Option<Option<NaiveDate>>,
+    /// completing the last open subtask completes its parent as well, no field of the todo
+    #[serde(default)]
+    pub auto_complete_parent: bool,
+}
+#[async_trait]
+impl CheckReferences for ToDoPatch {
+    async fn missing_references<DB: DBInterface + Send + Sync>(
+        &self,
+        db: &DB,
+        user_id: i32,
+    ) -> Result<Vec<&'static str>, DbError> {
+        let parent_todo_id = self.parent_todo_id.flatten();
+        let parent = missing_reference::<ToDoDB, DB>(db, user_id, "parent_todo_id", parent_todo_id);
+        Ok(parent.await?.into_iter().collect())
+    }
+
+    async fn invalid_references<DB: DBInterface + Send + Sync>(
+        &self,
+        db: &DB,
+        user_id: i32,
+    ) -> Result<Vec<FieldError>, DbError> {
+        parent_errors(db, user_id, Some(self.id), self.parent_todo_id.flatten()).await
+    }
+}
+impl ToPartialDB for ToDoPatch {
+    fn get_id(&self) -> i32 {
+        self.id
+    }
+
+    fn to_partial_param_vec(
+        &self,
+        key: &[u8],
+        provider: &CryptProviders,
+    ) -> Vec<(String, SQLValue)> {
+        let encrypt = |text: &String| CryptString::encrypt(text, key, provider).data_crypt;
+        db_partial_param_map! {
+            name: self.name.as_ref().map(encrypt),
+            deadline: self.deadline,
+            details: self.details.as_ref().map(encrypt),
+            completed: self.completed,
+            completed_at: self.completed_at,
+            priority: self.priority,
+            parent_todo_id: self.parent_todo_id,
+            recurrence: self.recurrence,
+            recurrence_until: self.recurrence_until,
+        }
+    }
+}
+
+// Note
+// attached to a course, a topic of a course or nothing
+#[derive(DBObject, Serialize, Deserialize)]
+#[db(timestamps)]
+pub struct NoteDB {
+    pub id: i32,
+    pub user_id: i32,
+
+    #[db(references = "CourseDB", on_delete = "cascade")]
+    pub course_id: Option<i32>,
+    #[db(references = "TopicDB", on_delete = "cascade")]
+    pub topic_id: Option<i32>,
+    pub title: CryptString,
+    pub content: CryptString,
+    #[serde(default)]
+    pub created_at: NaiveDateTime,
+    #[serde(default)]
+    pub updated_at: Option<NaiveDateTime>,
+}
+#[derive(Deserialize, Serialize, SendObject, Clone)]
+pub struct NoteSend {
+    pub id: Option<i32>,
+
+    #[serde(default)]
+    pub course_id: Option<i32>,
+    #[serde(default)]
+    pub topic_id: Option<i32>,
+    pub title: String,
+    pub content: String,
+    // read only, set by the server
+    #[serde(default)]
+    pub created_at: NaiveDateTime,
+    #[serde(default)]
+    pub updated_at: Option<NaiveDateTime>,
+}
+impl ToDB for NoteSend {
+    fn to_param_vec(&self, key: &[u8], provider: &CryptProviders) -> Vec<(String, SQLValue)> {
+        let title_crypt = CryptString::encrypt(&self.title, key, provider);
+        let content_crypt = CryptString::encrypt(&self.content, key, provider);
+        db_param_map! {
+            course_id: self.course_id,
+            topic_id: self.topic_id,
+            title: title_crypt.data_crypt,
+            content: content_crypt.data_crypt,
+        }
+    }
+}
+#[async_trait]
+impl CheckReferences for NoteSend {
+    async fn missing_references<DB: DBInterface + Send + Sync>(
+        &self,
+        db: &DB,
+        user_id: i32,
+    ) -> Result<Vec<&'static str>, DbError> {
+        let course = missing_reference::<CourseDB, DB>(db, user_id, "course_id", self.course_id);
+        let topic = missing_reference::<TopicDB, DB>(db, user_id, "topic_id", self.topic_id);
+        Ok(course.await?.into_iter().chain(topic.await?).collect())
+    }
+}
+impl FromDB<NoteDB> for NoteSend {
+    fn from_dbt(
+        dbt: &NoteDB,
+        key: &[u8],
+        provider: &CryptProviders,
+    ) -> Result<Self, Box<dyn Error>> {
+        let title = dbt.title.decrypt(key, provider);
+        let content = dbt.content.decrypt(key, provider);
+        Ok(Self {
+            id: Some(dbt.id),
+            course_id: dbt.course_id,
+            topic_id: dbt.topic_id,
+            title: title?,
+            content: content?,
+            created_at: dbt.created_at,
+            updated_at: dbt.updated_at,
+        })
+    }
+}
+
+// Study session
+// time spent studying a topic
+#[derive(DBObject, Serialize, Deserialize)]
+pub struct StudySessionDB {
+    pub id: i32,
+    pub user_id: i32,
+
+    #[db(references = "TopicDB", on_delete = "cascade")]
+    pub topic_id: i32,
+    pub started_at: NaiveDateTime,
+    #[db(check = "duration_minutes > 0")]
+    pub duration_minutes: i32,
+    pub notes: CryptString,
+}
+#[derive(Deserialize, Serialize, SendObject, Clone)]
+pub struct StudySessionSend {
+    pub id: Option<i32>,
+
+    pub topic_id: i32,
+    pub started_at: NaiveDateTime,
+    pub duration_minutes: i32,
+    #[serde(default)]
+    pub notes: String,
+}
+impl ToDB for StudySessionSend {
+    fn to_param_vec(&self, key: &[u8], provider: &CryptProviders) -> Vec<(String, SQLValue)> {
+        let notes_crypt = CryptString::encrypt(&self.notes, key, provider);
+        db_param_map! {
+            topic_id: self.topic_id,
+            started_at: self.started_at,
+            duration_minutes: self.duration_minutes,
+            notes: notes_crypt.data_crypt,
+        }
+    }
+}
+#[async_trait]
+impl CheckReferences for StudySessionSend {
+    async fn missing_references<DB: DBInterface + Send + Sync>(
+        &self,
+        db: &DB,
+        user_id: i32,
+    ) -> Result<Vec<&'static str>, DbError> {
+        let topic = missing_reference::<TopicDB, DB>(db, user_id, "topic_id", Some(self.topic_id));
+        Ok(topic.await?.into_iter().collect())
+    }
+}
+impl FromDB<StudySessionDB> for StudySessionSend {
+    fn from_dbt(
+        dbt: &StudySessionDB,
+        key: &[u8],
+        provider: &CryptProviders,
+    ) -> Result<Self, Box<dyn Error>> {
+        let notes = dbt.notes.decrypt(key, provider);
+        Ok(Self {
+            id: Some(dbt.id),
+            topic_id: dbt.topic_id,
+            started_at: dbt.started_at,
+            duration_minutes: dbt.duration_minutes,
+            notes: notes?,
+        })
+    }
+}
+
+// Grade
+// result of an exam, weighted for the average of the course
+#[derive(DBObject, Serialize, Deserialize)]
+pub struct GradeDB {
+    pub id: i32,
+    pub user_id: i32,
+
+    #[db(references = "ExamDB", on_delete = "cascade")]
+    pub exam_id: i32,
+    pub grade: f64,
+    #[db(check = "weight > 0")]
+    pub weight: f64,
+    pub note: CryptString,
+}
+#[derive(Deserialize, Serialize, SendObject, Clone)]
+pub struct GradeSend {
+    pub id: Option<i32>,
+
+    pub exam_id: i32,
+    pub grade: f64,
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    #[serde(default)]
+    pub note: String,
+}
+/// grades without a weight count once
+fn default_weight() -> f64 {
+    1.0
+}
+impl ToDB for GradeSend {
+    fn to_param_vec(&self, key: &[u8], provider: &CryptProviders) -> Vec<(String, SQLValue)> {
+        let note_crypt = CryptString::encrypt(&self.note, key, provider);
+        db_param_map! {
+            exam_id: self.exam_id,
+            grade: self.grade,
+            weight: self.weight,
+            note: note_crypt.data_crypt,
+        }
+    }
+}
+#[async_trait]
+impl CheckReferences for GradeSend {
+    async fn missing_references<DB: DBInterface + Send + Sync>(
+        &self,
+        db: &DB,
+        user_id: i32,
+    ) -> Result<Vec<&'static str>, DbError> {
+        let exam = missing_reference::<ExamDB, DB>(db, user_id, "exam_id", Some(self.exam_id));
+        Ok(exam.await?.into_iter().collect())
+    }
+
+    fn invalid_values(&self) -> Vec<FieldError> {
+        let (min, max) = grade_scale();
+        if (min..=max).contains(&self.grade) {
+            return Vec::new();
+        }
+        vec![FieldError {
+            field: "grade".to_string(),
+            message: format!("is not between {} and {}", min, max),
+        }]
+    }
+}
+impl FromDB<GradeDB> for GradeSend {
+    fn from_dbt(
+        dbt: &GradeDB,
+        key: &[u8],
+        provider: &CryptProviders,
+    ) -> Result<Self, Box<dyn Error>> {
+        let note = dbt.note.decrypt(key, provider);
+        Ok(Self {
+            id: Some(dbt.id),
+            exam_id: dbt.exam_id,
+            grade: dbt.grade,
+            weight: dbt.weight,
+            note: note?,
+        })
+    }
+}
+
+// Flashcard
+// front and back of a card of a topic, scheduled for review with SM-2
+#[derive(DBObject, Serialize, Deserialize)]
+pub struct FlashcardDB {
+    pub id: i32,
+    pub user_id: i32,
+
+    #[db(references = "TopicDB", on_delete = "cascade")]
+    pub topic_id: i32,
+    pub front: CryptString,
+    pub back: CryptString,
+    #[db(check = "ease >= 1.3")]
+    pub ease: f64,
+    #[db(check = "interval_days >= 0")]
+    pub interval_days: i32,
+    pub due_date: NaiveDate,
+}
+#[derive(Deserialize, Serialize, SendObject, Clone)]
+pub struct FlashcardSend {
+    pub id: Option<i32>,
+
+    pub topic_id: i32,
+    pub front: String,
+    pub back: String,
+    // scheduling, new cards are due today, updated by reviews
+    #[serde(default = "initial_ease")]
+    pub ease: f64,
+    #[serde(default)]
+    pub interval_days: i32,
+    #[serde(default = "today")]
+    pub due_date: NaiveDate,
+}
+/// ease of new cards
+fn initial_ease() -> f64 {
+    INITIAL_EASE
+}
+/// due date of new cards
+fn today() -> NaiveDate {
+    Utc::now().date_naive()
+}
+impl ToDB for FlashcardSend {
+    fn to_param_vec(&self, key: &[u8], provider: &CryptProviders) -> Vec<(String, SQLValue)> {
+        let front_crypt = CryptString::encrypt(&self.front, key, provider);
+        let back_crypt = CryptString::encrypt(&self.back, key, provider);
+        db_param_map! {
+            topic_id: self.topic_id,
+            front: front_crypt.data_crypt,
+            back: back_crypt.data_crypt,
+            ease: self.ease,
+            interval_days: self.interval_days,
+            due_date: self.due_date,
+        }
+    }
+}
+#[async_trait]
+impl CheckReferences for FlashcardSend {
+    async fn missing_references<DB: DBInterface + Send + Sync>(
+        &self,
+        db: &DB,
+        user_id: i32,
+    ) -> Result<Vec<&'static str>, DbError> {
+        let topic = missing_reference::<TopicDB, DB>(db, user_id, "topic_id", Some(self.topic_id));
+        Ok(topic.await?.into_iter().collect())
+    }
+
+    fn invalid_values(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if !(MIN_EASE..=MAX_EASE).contains(&self.ease) {
+            errors.push(FieldError {
+                field: "ease".to_string(),
+                message: format!("is not between {} and {}", MIN_EASE, MAX_EASE),
+            });
+        }
+        if !(0..=MAX_INTERVAL_DAYS).contains(&self.interval_days) {
+            errors.push(FieldError {
+                field: "interval_days".to_string(),
+                message: format!("is not between 0 and {}", MAX_INTERVAL_DAYS),
+            });
+        }
+        errors
+    }
+}
+impl FromDB<FlashcardDB> for FlashcardSend {
+    fn from_dbt(
+        dbt: &FlashcardDB,
+        key: &[u8],
+        provider: &CryptProviders,
+    ) -> Result<Self, Box<dyn Error>> {
+        let front = dbt.front.decrypt(key, provider);
+        let back = dbt.back.decrypt(key, provider);
+        Ok(Self {
+            id: Some(dbt.id),
+            topic_id: dbt.topic_id,
+            front: front?,
+            back: back?,
+            ease: dbt.ease,
+            interval_days: dbt.interval_days,
+            due_date: dbt.due_date,
+        })
+    }
+}
+
+// Semester
+// groups courses, deleting it keeps the courses
+#[derive(DBObject, Serialize, Deserialize)]
+#[db(check = "start_date <= end_date")]
+pub struct SemesterDB {
+    pub id: i32,
+    pub user_id: i32,
+
+    pub name: CryptString,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+}
+#[derive(Deserialize, Serialize, SendObject, Clone)]
+pub struct SemesterSend {
+    pub id: Option<i32>,
+
+    pub name: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+}
+impl ToDB for SemesterSend {
+    fn to_param_vec(&self, key: &[u8], provider: &CryptProviders) -> Vec<(String, SQLValue)> {
+        let name_crypt = CryptString::encrypt(&self.name, key, provider);
+        db_param_map! {
+            name: name_crypt.data_crypt,
+            start_date: self.start_date,
+            end_date: self.end_date,
+        }
+    }
+}
+impl CheckReferences for SemesterSend {}
+impl FromDB<SemesterDB> for SemesterSend {
+    fn from_dbt(
+        dbt: &SemesterDB,
+        key: &[u8],
+        provider: &CryptProviders,
+    ) -> Result<Self, Box<dyn Error>> {
+        let name = dbt.name.decrypt(key, provider);
+        Ok(Self {
+            id: Some(dbt.id),
+            name: name?,
+            start_date: dbt.start_date,
+            end_date: dbt.end_date,
+        })
+    }
+}
+
+// Webhook subscription
+// url and secret are stored in plain text, the delivery worker has to be able to use them without a user session
+#[derive(DBObject, Serialize, Deserialize)]
+pub struct WebhookSubscriptionDB {
+    pub id: i32,
+    pub user_id: i32,
+
+    pub url: String,
+    pub secret: String,
+    pub event_filter: String, // comma separated list of ident:action, e.g. "ToDoDB:create,CourseDB:*"
+    pub active: bool,
+    pub failure_count: i32, // consecutive failed delivery attempts
+}
+#[derive(Deserialize, Serialize, SendObject)]
+pub struct WebhookSubscriptionSend {
+    pub id: Option<i32>,
+
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<String>, // empty => every event
+    pub active: bool,
+}
+impl ToDB for WebhookSubscriptionSend {
+    fn to_param_vec(&self, _: &[u8], _: &CryptProviders) -> Vec<(String, SQLValue)> {
+        // saving a subscription resets its failure counter, so disabled subscriptions can be re-enabled
+        db_param_map! {
+            url: self.url.as_str(),
+            secret: self.secret.as_str(),
+            event_filter: self.events.join(","),
+            active: self.active,
+            failure_count: 0,
+        }
+    }
+}
+impl CheckReferences for WebhookSubscriptionSend {
+    fn invalid_values(&self) -> Vec<FieldError> {
+        match check_webhook_url(&self.url, &allowed_hosts()) {
+            Ok(_) => Vec::new(),
+            Err(reason) => vec![FieldError {
+                field: "url".to_string(),
+                message: reason.to_string(),
+            }],
+        }
+    }
+}
+impl FromDB<WebhookSubscriptionDB> for WebhookSubscriptionSend {
+    fn from_dbt(
+        dbt: &WebhookSubscriptionDB,
+        _: &[u8],
+        _: &CryptProviders,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            id: Some(dbt.id),
+            url: dbt.url.clone(),
+            secret: dbt.secret.clone(),
+            events: dbt
+                .event_filter
+                .split(',')
+                .filter(|event| !event.is_empty())
+                .map(String::from)
+                .collect(),
+            active: dbt.active,
+        })
+    }
+}
+
+// User settings
+// exactly one row per user, stored in plain text as background tasks (e.g. retention) need them without a user session
+#[derive(DBObject)]
+pub struct UserSettingsDB {
+    pub id: i32,
+    #[db(unique)]
+    pub user_id: i32,
+
+    pub timezone: String, // IANA name, e.g. "Europe/Berlin"
+    pub week_start: String,
+    pub reminder_offsets: String, // comma separated minutes before a deadline
+    pub completed_todo_retention_days: Option<i32>, // None => completed todos are kept forever
+    pub email_digest: bool,
+    pub extras: String, // json object, settings unknown to this version of the server
+}
+
+/// first day of the week
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WeekStart {
+    #[default]
+    Monday,
+    Sunday,
+}
+impl WeekStart {
+    fn as_str(&self) -> &'static str {
+        match self {
+            WeekStart::Monday => "monday",
+            WeekStart::Sunday => "sunday",
+        }
+    }
+}
+
+/// missing fields are set to their default value
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[serde(default)]
+pub struct UserSettingsSend {
+    pub timezone: String,
+    pub week_start: WeekStart,
+    pub reminder_offsets: Vec<i32>,
+    pub completed_todo_retention_days: Option<i32>,
+    pub email_digest: bool,
+    pub extras: serde_json::Value,
+}
+impl Default for UserSettingsSend {
+    fn default() -> Self {
+        Self {
+            timezone: "UTC".to_string(),
+            week_start: WeekStart::default(),
+            reminder_offsets: vec![24 * 60], // one day before
+            completed_todo_retention_days: None,
+            email_digest: false,
+            extras: serde_json::Value::Object(serde_json::Map::new()),
+        }
+    }
+}
+impl From<&UserSettingsDB> for UserSettingsSend {
+    fn from(dbt: &UserSettingsDB) -> Self {
+        let defaults = Self::default();
+        Self {
+            timezone: dbt.timezone.clone(),
+            week_start: serde_json::from_value(serde_json::Value::String(dbt.week_start.clone()))
+                .unwrap_or(defaults.week_start),
+            reminder_offsets: dbt
+                .reminder_offsets
+                .split(',')
+                .filter_map(|offset| offset.parse().ok())
+                .collect(),
+            completed_todo_retention_days: dbt.completed_todo_retention_days,
+            email_digest: dbt.email_digest,
+            extras: serde_json::from_str(&dbt.extras).unwrap_or(defaults.extras),
+        }
+    }
+}
+impl UserSettingsSend {
+    /// parameter map of every setting
+    pub fn to_param_vec(&self) -> Vec<(String, SQLValue)> {
+        let reminder_offsets: Vec<String> = self
+            .reminder_offsets
+            .iter()
+            .map(|offset| offset.to_string())
+            .collect();
+        db_param_map! {
+            timezone: self.timezone.as_str(),
+            week_start: self.week_start.as_str(),
+            reminder_offsets: reminder_offsets.join(","),
+            completed_todo_retention_days: self.completed_todo_retention_days,
+            email_digest: self.email_digest,
+            extras: self.extras.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    use crate::{
+        data_handler::data_router,
+        test_util::{create_course, register_user, send_json, test_state},
+    };
+
+    use chrono::NaiveDate;
+
+    use super::{ExamSend, PrepStatus, Recurrence};
+
+    #[test]
+    fn monthly_recurrence_ends_on_the_last_day_of_shorter_months() {
+        let date = |month, day| NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+        assert_eq!(Recurrence::Daily.next(date(2, 28)), Some(date(2, 29)));
+        assert_eq!(Recurrence::Weekly.next(date(2, 26)), Some(date(3, 4)));
+        assert_eq!(Recurrence::Monthly.next(date(1, 15)), Some(date(2, 15)));
+        assert_eq!(Recurrence::Monthly.next(date(1, 31)), Some(date(2, 29)));
+        assert_eq!(Recurrence::Monthly.next(date(3, 31)), Some(date(4, 30)));
+        assert_eq!(
+            Recurrence::Monthly.next(date(12, 31)),
+            NaiveDate::from_ymd_opt(2025, 1, 31)
+        );
+    }
+
+    #[test]
+    fn exam_prep_fields_round_trip() {
+        let exam: ExamSend = serde_json::from_value(json!({
+            "id": null,
+            "course_id": 1,
+            "name": "Analysis",
+            "date": "2025-07-01",
+            "prep_status": "in_progress",
+            "prep_notes": "chapter 3 missing"
+        }))
+        .unwrap();
+        assert_eq!(exam.prep_status, PrepStatus::InProgress);
+
+        let value = serde_json::to_value(&exam).unwrap();
+        assert_eq!(value["prep_status"], "in_progress");
+        assert_eq!(value["prep_notes"], "chapter 3 missing");
+
+        // older clients do not send the preparation fields
+        let exam: ExamSend = serde_json::from_value(
+            json!({ "id": null, "course_id": 1, "name": "Analysis", "date": "2025-07-01" }),
+        )
+        .unwrap();
+        assert_eq!(exam.prep_status, PrepStatus::NotStarted);
+        assert_eq!(exam.prep_notes, None);
+
+        for status in [
+            PrepStatus::NotStarted,
+            PrepStatus::InProgress,
+            PrepStatus::Confident,
+        ] {
+            assert_eq!(status.as_str().parse::<PrepStatus>(), Ok(status));
+        }
+        assert!(serde_json::from_value::<PrepStatus>(json!("panicking")).is_err());
+    }
+
+    #[tokio::test]
+    async fn exams_can_be_filtered_by_prep_status() {
+        let state = test_state().await;
+        let token = register_user(&state, "exam_prep").await;
+        let router = data_router(state.clone()).await;
+        let course_id = create_course(router.clone(), &token).await;
+
+        for (name, status) in [("Analysis", "confident"), ("Algebra", "not_started")] {
+            let (status, _) = send_json(
+                router.clone(),
+                Method::POST,
+                "/exam",
+                &token,
+                Some(json!({ "id": null, "course_id": course_id, "name": name, "date": "2025-07-01", "prep_status": status, "prep_notes": null })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+        }
+
+        let (status, exams) = send_json(
+            router,
+            Method::GET,
+            "/exam?prep_status=confident",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(exams.as_array().unwrap().len(), 1);
+        assert_eq!(exams[0]["name"], "Analysis");
+        assert_eq!(exams[0]["prep_status"], "confident");
+        assert_eq!(exams[0]["prep_notes"], json!(null));
+    }
+}
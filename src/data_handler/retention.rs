@@ -0,0 +1,397 @@
+use std::{collections::HashSet, env, iter, sync::Arc, time::Duration};
+
+use axum::{extract::State, http::StatusCode};
+use chrono::{Days, NaiveDate, NaiveDateTime, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState,
+    api_error::{ApiError, Json},
+    auth_handler::AuthenticatedUser,
+    db::{
+        DBInterface,
+        sql_helper::{SQLGenerate, SQLValue, WhereOp, WhereParam},
+    },
+    db_param_map,
+};
+
+use super::{
+    objects::{ToDoDB, UserSettingsDB},
+    record_change,
+    webhook::WebhookAction,
+};
+
+/// the retention task runs once a day
+const RETENTION_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+/// history entries are kept this many days, if HISTORY_RETENTION_DAYS is not set
+const DEFAULT_HISTORY_RETENTION_DAYS: u64 = 90;
+/// soft deleted entries can be restored this many days, if SOFT_DELETE_GRACE_DAYS is not set
+const DEFAULT_SOFT_DELETE_GRACE_DAYS: u64 = 30;
+
+/// single retention run, returned to the user
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RetentionLogSend {
+    pub purged_count: i32,
+    pub ran_at: NaiveDateTime,
+}
+
+/// background task, purges old completed todos once a day (first run on startup)
+pub async fn retention_worker<DB: DBInterface + Send + Sync>(state: Arc<AppState<DB>>) {
+    let mut interval = tokio::time::interval(RETENTION_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        let purged = purge_completed_todos(&state, Utc::now().date_naive()).await;
+        let history = purge_history(&state, Utc::now().naive_utc()).await;
+        let deleted = purge_soft_deleted::<ToDoDB, DB>(&state, Utc::now().naive_utc()).await;
+        info!(
+            "Retention run finished, purged {} completed todos, {} history entries and {} deleted todos.",
+            purged, history, deleted
+        );
+    }
+}
+
+/// deletes the completed todos of every user with a retention setting, returns the number of purged todos
+/// a todo is purged if its deadline is more than completed_todo_retention_days days before today,
+/// todos with a deadline exactly that many days ago are kept
+pub async fn purge_completed_todos<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    today: NaiveDate,
+) -> usize {
+    // only users with a retention setting
+    let settings = match state
+        .db
+        .select_entries::<UserSettingsDB>(
+            vec![WhereParam::new(
+                "completed_todo_retention_days",
+                WhereOp::Ne,
+                SQLValue::Null,
+            )],
+            &[],
+            None,
+        )
+        .await
+    {
+        Ok(settings) => settings,
+        Err(_) => {
+            error!("Failed to load user settings for retention!");
+            return 0;
+        }
+    };
+
+    let mut purged = 0;
+    for settings in settings {
+        let Some(cutoff) = settings
+            .completed_todo_retention_days
+            .and_then(|days| days.try_into().ok())
+            .and_then(|days| today.checked_sub_days(Days::new(days)))
+        else {
+            continue;
+        };
+        purged += purge_for_user(state, settings.user_id, cutoff).await;
+    }
+    purged
+}
+
+/// deletes the history entries of all users that are older than HISTORY_RETENTION_DAYS days, returns the number of deleted entries
+pub async fn purge_history<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    now: NaiveDateTime,
+) -> usize {
+    let days = env::var("HISTORY_RETENTION_DAYS")
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(DEFAULT_HISTORY_RETENTION_DAYS);
+    let Some(cutoff) = now.checked_sub_days(Days::new(days)) else {
+        return 0;
+    };
+
+    state
+        .db
+        .del_change_logs_before(&cutoff)
+        .await
+        .unwrap_or_else(|_| {
+            error!("Failed to purge history!");
+            0
+        })
+}
+
+/// entries deleted before the returned time can no longer be restored, SOFT_DELETE_GRACE_DAYS days before now
+pub fn soft_delete_cutoff(now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let days = env::var("SOFT_DELETE_GRACE_DAYS")
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(DEFAULT_SOFT_DELETE_GRACE_DAYS);
+    now.checked_sub_days(Days::new(days))
+}
+
+/// deletes the soft deleted entries of all users for real once their grace period is over, returns the number of deleted entries
+pub async fn purge_soft_deleted<DBT: SQLGenerate, DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    now: NaiveDateTime,
+) -> usize {
+    let Some(cutoff) = soft_delete_cutoff(now) else {
+        return 0;
+    };
+
+    state
+        .db
+        .delete_entry::<DBT>(vec![WhereParam::new("deleted_at", WhereOp::Lt, cutoff)])
+        .await
+        .unwrap_or_else(|_| {
+            error!("Failed to purge deleted entries!");
+            0
+        })
+}
+
+/// deletes the completed todos of a user with a deadline before cutoff
+async fn purge_for_user<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    cutoff: NaiveDate,
+) -> usize {
+    let todos = state
+        .db
+        .select_entries::<ToDoDB>(
+            db_param_map! { user_id: user_id, completed: true },
+            &[],
+            None,
+        )
+        .await;
+    let todos = match todos {
+        Ok(todos) => todos,
+        Err(_) => {
+            error!("Failed to load todos for retention! (user id: {})", user_id);
+            return 0;
+        }
+    };
+
+    let mut purged_ids = HashSet::new();
+    for todo in todos.iter().filter(|todo| todo.deadline < cutoff) {
+        // already deleted with its parent
+        if purged_ids.contains(&todo.id) {
+            continue;
+        }
+        // the database deletes the subtasks with their todo
+        let subtasks = state
+            .db
+            .select_entries::<ToDoDB>(
+                db_param_map! { parent_todo_id: todo.id, user_id: user_id },
+                &[],
+                None,
+            )
+            .await;
+        let Ok(subtasks) = subtasks else {
+            error!(
+                "Failed to load subtasks for retention! (user id: {})",
+                user_id
+            );
+            continue;
+        };
+        let result = state
+            .db
+            .delete_entry::<ToDoDB>(db_param_map! { id: todo.id, user_id: user_id })
+            .await;
+        if result.is_err() {
+            error!("Failed to purge todo! (user id: {})", user_id);
+            continue;
+        }
+        // purged todos are deletions like any other, sync clients get a tombstone
+        for id in iter::once(todo.id).chain(subtasks.iter().map(|subtask| subtask.id)) {
+            purged_ids.insert(id);
+            record_change(
+                state,
+                user_id,
+                &ToDoDB::get_db_ident(),
+                WebhookAction::Delete,
+                id,
+            )
+            .await;
+        }
+    }
+    let purged = purged_ids.len() as i32;
+
+    if purged > 0 {
+        let logged = state.db.new_retention_log(user_id, purged).await;
+        if logged.is_err() {
+            error!("Failed to write retention log! (user id: {})", user_id);
+        }
+    }
+
+    purged as usize
+}
+
+/// handler for reading the retention log of the user
+pub async fn handle_get_retention_log<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+) -> Result<Json<Vec<RetentionLogSend>>, ApiError> {
+    info!("Retention log read requested!");
+
+    let AuthenticatedUser { user_id, .. } = user;
+
+    let logs = state
+        .db
+        .get_retention_logs_by_user(user_id)
+        .await
+        .map_err(|_| {
+            error!("Failed to load retention log! (user id: {})", user_id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(
+        logs.into_iter()
+            .map(|log| RetentionLogSend {
+                purged_count: log.purged_count,
+                ran_at: log.ran_at,
+            })
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use chrono::NaiveDate;
+    use serde_json::json;
+
+    use crate::{
+        data_handler::data_router,
+        test_util::{register_user, send_json, test_state},
+    };
+
+    use super::{purge_completed_todos, purge_history};
+
+    /// creates a todo, returns its id
+    async fn create_todo(
+        router: axum::Router,
+        token: &str,
+        deadline: &str,
+        completed: bool,
+    ) -> i64 {
+        let (_, todo) = send_json(
+            router,
+            Method::POST,
+            "/todo",
+            token,
+            Some(json!({ "id": null, "name": "todo", "deadline": deadline, "details": "", "completed": completed })),
+        )
+        .await;
+        todo["id"].as_i64().unwrap()
+    }
+
+    #[tokio::test]
+    async fn purges_only_completed_todos_older_than_retention() {
+        let state = test_state().await;
+        let token = register_user(&state, "retention_user").await;
+        let keeper_token = register_user(&state, "retention_keeper").await;
+        let router = data_router(state.clone()).await;
+
+        let (status, _) = send_json(
+            router.clone(),
+            Method::PUT,
+            "/settings",
+            &token,
+            Some(json!({ "completed_todo_retention_days": 10 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        // today is 2025-06-20, the cutoff is 2025-06-10
+        let old = create_todo(router.clone(), &token, "2025-06-09", true).await;
+        let boundary = create_todo(router.clone(), &token, "2025-06-10", true).await;
+        let open = create_todo(router.clone(), &token, "2025-01-01", false).await;
+        // users without the setting keep everything
+        create_todo(router.clone(), &keeper_token, "2020-01-01", true).await;
+
+        let (_, initial) = send_json(router.clone(), Method::GET, "/sync", &token, None).await;
+
+        let purged =
+            purge_completed_todos(&state, NaiveDate::from_ymd_opt(2025, 6, 20).unwrap()).await;
+        assert_eq!(purged, 1);
+
+        let (_, todos) = send_json(router.clone(), Method::GET, "/todo", &token, None).await;
+        let mut ids: Vec<i64> = todos
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|todo| todo["id"].as_i64().unwrap())
+            .collect();
+        ids.sort();
+        assert_eq!(ids, vec![boundary, open]);
+
+        let (_, todos) = send_json(router.clone(), Method::GET, "/todo", &keeper_token, None).await;
+        assert_eq!(todos.as_array().unwrap().len(), 1);
+
+        // sync clients get a tombstone for the purged todo
+        let (_, delta) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!("/sync?since={}", initial["cursor"].as_str().unwrap()),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(delta["todo"]["deleted"], json!([old]));
+
+        let (_, log) =
+            send_json(router, Method::GET, "/settings/retention-log", &token, None).await;
+        assert_eq!(log.as_array().unwrap().len(), 1);
+        assert_eq!(log[0]["purged_count"], 1);
+    }
+
+    #[tokio::test]
+    async fn purges_old_history() {
+        let state = test_state().await;
+        let token = register_user(&state, "retention_history").await;
+        let router = data_router(state.clone()).await;
+
+        let todo = create_todo(router.clone(), &token, "2025-06-09", false).await;
+        let (status, _) = send_json(
+            router.clone(),
+            Method::DELETE,
+            "/todo",
+            &token,
+            Some(json!({ "id": todo })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let now = chrono::Utc::now().naive_utc();
+        assert_eq!(purge_history(&state, now).await, 0);
+        // 91 days later the entry is older than the default of 90 days
+        let later = now + chrono::Days::new(91);
+        assert_eq!(purge_history(&state, later).await, 1);
+
+        let (_, history) = send_json(
+            router,
+            Method::GET,
+            &format!("/todo/{}/history", todo),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(history, json!([]));
+    }
+
+    #[tokio::test]
+    async fn negative_retention_is_rejected() {
+        let state = test_state().await;
+        let token = register_user(&state, "retention_invalid").await;
+        let router = data_router(state.clone()).await;
+
+        let (status, _) = send_json(
+            router.clone(),
+            Method::PUT,
+            "/settings",
+            &token,
+            Some(json!({ "completed_todo_retention_days": -1 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let (_, settings) = send_json(router, Method::GET, "/settings", &token, None).await;
+        assert_eq!(settings["completed_todo_retention_days"], json!(null));
+    }
+}
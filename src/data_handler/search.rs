@@ -0,0 +1,489 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::{runtime::Handle, task::spawn_blocking};
+
+use crate::{
+    AppState,
+    api_error::{ApiError, Json, Query},
+    auth_handler::AuthenticatedUser,
+    db::{
+        DBInterface, Pagination,
+        sql_helper::{SQLGenerate, SortDirection},
+    },
+};
+
+use super::{
+    FromDB, LocalKey, Sendable, get_entries,
+    objects::{CourseDB, CourseSend, ExamDB, ExamSend, ToDoDB, ToDoSend, TopicDB, TopicSend},
+};
+
+/// queries need at least this many characters
+const MIN_QUERY_LENGTH: usize = 2;
+/// number of results per type, if the client does not request a limit
+const DEFAULT_LIMIT: usize = 10;
+/// clients can request at most this many results per type
+const MAX_LIMIT: usize = 50;
+/// number of characters shown before and after the match in a snippet
+const SNIPPET_CONTEXT: usize = 20;
+/// at most this many objects (the newest) are decrypted and scanned per type
+/// every scanned object is decrypted, so the cost of a search grows with this number
+const MAX_SCANNED_ROWS: u32 = 2000;
+/// names of the searchable types, in the order of the response
+const SEARCH_TYPES: [&str; 4] = ["course", "topic", "exam", "todo"];
+
+/// query parameters of the search request
+#[derive(Deserialize, Debug)]
+pub struct SearchQuery {
+    q: String,
+    limit: Option<usize>,
+    /// comma separated names of the types to search, all types if not given
+    types: Option<String>,
+}
+
+/// field of an object that matched the query
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchField {
+    Name,
+    Details,
+}
+
+/// single search result
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchHit {
+    id: i32,
+    field: SearchField,
+    /// part of the field around the match
+    snippet: String,
+    /// lower is better, prefix matches in the name come first
+    rank: u8,
+}
+
+/// response of the search request, results are grouped by type and sorted by rank
+/// types that were not requested are left out
+#[derive(Serialize, Debug)]
+pub struct SearchResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    course: Option<Vec<SearchHit>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    topic: Option<Vec<SearchHit>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exam: Option<Vec<SearchHit>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    todo: Option<Vec<SearchHit>>,
+}
+
+/// implemented by send types that can be searched, returns the name and the details of the object
+trait Searchable {
+    fn search_fields(&self) -> (&str, Option<&str>);
+}
+impl Searchable for CourseSend {
+    fn search_fields(&self) -> (&str, Option<&str>) {
+        (&self.name, None)
+    }
+}
+impl Searchable for TopicSend {
+    fn search_fields(&self) -> (&str, Option<&str>) {
+        (&self.name, Some(&self.details))
+    }
+}
+impl Searchable for ExamSend {
+    fn search_fields(&self) -> (&str, Option<&str>) {
+        (&self.name, self.prep_notes.as_deref())
+    }
+}
+impl Searchable for ToDoSend {
+    fn search_fields(&self) -> (&str, Option<&str>) {
+        (&self.name, Some(&self.details))
+    }
+}
+
+/// position of a case insensitive match in a text, in chars of the original text
+#[derive(Debug, PartialEq)]
+struct TextMatch {
+    start: usize,
+    end: usize,
+}
+
+/// finds the first case insensitive occurrence of the (lowercase) query in the text
+/// lowercasing can change the length of a char, so the positions are mapped back to the original chars
+fn find_match(text: &str, query: &[char]) -> Option<TextMatch> {
+    let mut lowered = Vec::new();
+    let mut origin = Vec::new();
+    for (index, c) in text.chars().enumerate() {
+        for lower in c.to_lowercase() {
+            lowered.push(lower);
+            origin.push(index);
+        }
+    }
+
+    let position = lowered
+        .windows(query.len())
+        .position(|window| window == query)?;
+    Some(TextMatch {
+        start: origin[position],
+        end: origin[position + query.len() - 1] + 1,
+    })
+}
+
+/// checks if the match starts at the beginning of a word
+fn is_word_start(text: &str, text_match: &TextMatch) -> bool {
+    text_match.start == 0
+        || text
+            .chars()
+            .nth(text_match.start - 1)
+            .is_some_and(|c| !c.is_alphanumeric())
+}
+
+/// cuts the text around the match, cut off parts are replaced by an ellipsis
+fn snippet(text: &str, text_match: &TextMatch) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let start = text_match.start.saturating_sub(SNIPPET_CONTEXT);
+    let end = (text_match.end + SNIPPET_CONTEXT).min(chars.len());
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    snippet.extend(&chars[start..end]);
+    if end < chars.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+/// searches the name and details of an object, returns the best hit
+fn search_object<ST: Searchable + Sendable>(object: &ST, query: &[char]) -> Option<SearchHit> {
+    let (name, details) = object.search_fields();
+
+    let fields = [
+        (SearchField::Name, Some(name)),
+        (SearchField::Details, details),
+    ];
+    fields
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, (field, text))| {
+            let text = text?;
+            let text_match = find_match(text, query)?;
+            // name prefix, name substring, details prefix, details substring
+            let rank = index as u8 * 2
+                + if is_word_start(text, &text_match) {
+                    0
+                } else {
+                    1
+                };
+            Some(SearchHit {
+                id: object.get_id()?,
+                field,
+                snippet: snippet(text, &text_match),
+                rank,
+            })
+        })
+        .min_by_key(|hit| hit.rank)
+}
+
+/// decrypts the newest objects of the type (up to MAX_SCANNED_ROWS) and returns the best hits
+async fn search_type<DBT, ST, DB>(
+    state: &Arc<AppState<DB>>,
+    user: AuthenticatedUser,
+    query: &[char],
+    limit: usize,
+) -> Result<Vec<SearchHit>, StatusCode>
+where
+    DBT: SQLGenerate,
+    ST: FromDB<DBT> + Searchable + Sendable,
+    DB: DBInterface + Send + Sync,
+{
+    let key = LocalKey::<DBT>::new(state, user).await?;
+    let objects = get_entries::<DBT, ST, DB>(
+        state,
+        &key,
+        Vec::new(),
+        &[("id".to_string(), SortDirection::Desc)],
+        Some(Pagination {
+            limit: MAX_SCANNED_ROWS,
+            offset: 0,
+        }),
+    )
+    .await?;
+
+    let mut hits: Vec<SearchHit> = objects
+        .iter()
+        .filter_map(|object| search_object(object, query))
+        .collect();
+    hits.sort_by_key(|hit| (hit.rank, hit.id));
+    hits.truncate(limit);
+    Ok(hits)
+}
+
+/// runs search_type on the blocking thread pool, decrypting is cpu heavy
+async fn spawn_search<DBT, ST, DB>(
+    state: &Arc<AppState<DB>>,
+    user: &AuthenticatedUser,
+    query: &Arc<Vec<char>>,
+    limit: usize,
+) -> Result<Vec<SearchHit>, StatusCode>
+where
+    DBT: SQLGenerate + 'static,
+    ST: FromDB<DBT> + Searchable + Sendable + 'static,
+    DB: DBInterface + Send + Sync + 'static,
+{
+    let (state, user, query) = (state.clone(), user.clone(), query.clone());
+    let user_id = user.user_id;
+    // the db calls inside of it still run on the runtime of the handler
+    let runtime = Handle::current();
+    spawn_blocking(move || {
+        runtime.block_on(search_type::<DBT, ST, DB>(&state, user, &query, limit))
+    })
+    .await
+    .map_err(|_| {
+        error!("Search task failed! (user id: {})", user_id);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+}
+
+/// searches the type if it was requested, None otherwise
+/// types a scoped token has no access to are empty instead of failing the whole search
+async fn search_requested<DBT, ST, DB>(
+    requested: bool,
+    state: &Arc<AppState<DB>>,
+    user: &AuthenticatedUser,
+    query: &Arc<Vec<char>>,
+    limit: usize,
+) -> Result<Option<Vec<SearchHit>>, StatusCode>
+where
+    DBT: SQLGenerate + 'static,
+    ST: FromDB<DBT> + Searchable + Sendable + 'static,
+    DB: DBInterface + Send + Sync + 'static,
+{
+    if !requested {
+        return Ok(None);
+    }
+    match spawn_search::<DBT, ST, DB>(state, user, query, limit).await {
+        Err(StatusCode::FORBIDDEN) => Ok(Some(vec![])),
+        result => result.map(Some),
+    }
+}
+
+/// parses the types parameter, returns for every entry of SEARCH_TYPES if it is searched
+fn requested_types(types: Option<&str>) -> Result<[bool; SEARCH_TYPES.len()], StatusCode> {
+    let Some(types) = types else {
+        return Ok([true; SEARCH_TYPES.len()]);
+    };
+    let mut requested = [false; SEARCH_TYPES.len()];
+    for name in types.split(',').map(str::trim) {
+        let index = SEARCH_TYPES
+            .iter()
+            .position(|search_type| *search_type == name)
+            .ok_or_else(|| {
+                warn!("Unknown search type: {}", name);
+                StatusCode::BAD_REQUEST
+            })?;
+        requested[index] = true;
+    }
+    Ok(requested)
+}
+
+/// handler for searching the object types at once, all of them or the requested ones
+pub async fn handle_search<DB: DBInterface + Send + Sync + 'static>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<SearchResponse>, ApiError> {
+    info!("Search requested!");
+
+    let search: Arc<Vec<char>> = Arc::new(query.q.trim().to_lowercase().chars().collect());
+    if search.len() < MIN_QUERY_LENGTH {
+        info!("Search query too short!");
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let [course, topic, exam, todo] = requested_types(query.types.as_deref())?;
+
+    // every type has its own local token, the types are decrypted and searched concurrently
+    let (course, topic, exam, todo) = tokio::join!(
+        search_requested::<CourseDB, CourseSend, DB>(course, &state, &user, &search, limit),
+        search_requested::<TopicDB, TopicSend, DB>(topic, &state, &user, &search, limit),
+        search_requested::<ExamDB, ExamSend, DB>(exam, &state, &user, &search, limit),
+        search_requested::<ToDoDB, ToDoSend, DB>(todo, &state, &user, &search, limit),
+    );
+
+    info!("Search successful, building response!");
+    Ok(Json(SearchResponse {
+        course: course?,
+        topic: topic?,
+        exam: exam?,
+        todo: todo?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::{Value, json};
+
+    use crate::{
+        data_handler::data_router,
+        test_util::{register_user, send_json, test_state},
+    };
+
+    use super::{TextMatch, find_match, snippet};
+
+    #[test]
+    fn snippets_respect_multibyte_chars() {
+        let query: Vec<char> = "übung".chars().collect();
+        let text = "Die Klausur enthält die ÜBUNGSAUFGABEN aus Kapitel drei und vier";
+
+        let text_match = find_match(text, &query).unwrap();
+        assert_eq!(text_match, TextMatch { start: 24, end: 29 });
+        assert_eq!(
+            snippet(text, &text_match),
+            "…Klausur enthält die ÜBUNGSAUFGABEN aus Kapite…"
+        );
+
+        // lowercasing İ results in two chars, the match still maps to the original chars
+        let query: Vec<char> = "stanbul".chars().collect();
+        let text_match = find_match("İİstanbul", &query).unwrap();
+        assert_eq!(text_match, TextMatch { start: 2, end: 9 });
+        assert_eq!(snippet("İİstanbul", &text_match), "İİstanbul");
+
+        assert_eq!(find_match("Analysis", &query), None);
+    }
+
+    #[tokio::test]
+    async fn search_groups_and_ranks_results() {
+        let state = test_state().await;
+        let token = register_user(&state, "search_user").await;
+        let other_token = register_user(&state, "search_other").await;
+        let router = data_router(state.clone()).await;
+
+        let create = |token: String, uri: &'static str, body: Value| {
+            let router = router.clone();
+            async move {
+                let (status, created) =
+                    send_json(router, Method::POST, uri, &token, Some(body)).await;
+                assert_eq!(status, StatusCode::OK);
+                created["id"].as_i64().unwrap()
+            }
+        };
+
+        let course = create(
+            token.clone(),
+            "/course",
+            json!({ "id": null, "name": "Lineare Algebra" }),
+        )
+        .await;
+        let details_hit = create(
+            token.clone(),
+            "/topic",
+            json!({ "id": null, "course_id": course, "name": "Vektorräume", "details": "Basis und Matrizen" }),
+        )
+        .await;
+        let substring_hit = create(
+            token.clone(),
+            "/topic",
+            json!({ "id": null, "course_id": course, "name": "Dreiecksmatrizen", "details": "" }),
+        )
+        .await;
+        let prefix_hit = create(
+            token.clone(),
+            "/topic",
+            json!({ "id": null, "course_id": course, "name": "Matrizenrechnung", "details": "" }),
+        )
+        .await;
+        create(
+            token.clone(),
+            "/topic",
+            json!({ "id": null, "course_id": course, "name": "Determinanten", "details": "" }),
+        )
+        .await;
+        let todo = create(
+            token.clone(),
+            "/todo",
+            json!({ "id": null, "name": "Übungsblatt", "deadline": "2025-07-01", "details": "Matrizen multiplizieren", "completed": false }),
+        )
+        .await;
+        // objects of other users are never found
+        create(
+            other_token,
+            "/course",
+            json!({ "id": null, "name": "Matrizen" }),
+        )
+        .await;
+
+        let (status, results) = send_json(
+            router.clone(),
+            Method::GET,
+            "/search/all?q=MATRIZEN",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let ids = |hits: &Value| -> Vec<i64> {
+            hits.as_array()
+                .unwrap()
+                .iter()
+                .map(|hit| hit["id"].as_i64().unwrap())
+                .collect()
+        };
+        assert_eq!(
+            ids(&results["topic"]),
+            vec![prefix_hit, substring_hit, details_hit]
+        );
+        assert_eq!(results["topic"][2]["field"], "details");
+        assert_eq!(results["topic"][2]["snippet"], "Basis und Matrizen");
+        assert_eq!(ids(&results["todo"]), vec![todo]);
+        assert_eq!(results["course"], json!([]));
+        assert_eq!(results["exam"], json!([]));
+
+        let (_, limited) = send_json(
+            router.clone(),
+            Method::GET,
+            "/search/all?q=matrizen&limit=1",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(ids(&limited["topic"]), vec![prefix_hit]);
+
+        let (_, umlauts) = send_json(
+            router.clone(),
+            Method::GET,
+            "/search/all?q=%C3%9Cbung",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(umlauts["todo"][0]["snippet"], "Übungsblatt");
+
+        // only the requested types are searched and returned
+        let (status, selected) = send_json(
+            router.clone(),
+            Method::GET,
+            "/search?q=matrizen&types=todo,course",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            selected,
+            json!({ "course": [], "todo": [{ "id": todo, "field": "details", "snippet": "Matrizen multiplizieren", "rank": 2 }] })
+        );
+
+        for uri in [
+            "/search?q=%20a%20",
+            "/search?q=matrizen&types=topic,grade",
+            "/search?q=matrizen&types=",
+        ] {
+            let (status, _) = send_json(router.clone(), Method::GET, uri, &token, None).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+        }
+    }
+}
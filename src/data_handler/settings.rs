@@ -0,0 +1,273 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode};
+use chrono_tz::Tz;
+use log::{error, info, warn};
+
+use crate::{
+    AppState,
+    api_error::{ApiError, Json},
+    auth_handler::AuthenticatedUser,
+    db::DBInterface,
+    db_param_map,
+};
+
+use super::objects::{UserSettingsDB, UserSettingsSend};
+
+/// maximum number of reminder offsets
+const MAX_REMINDER_OFFSETS: usize = 10;
+/// reminders can be at most one year (in minutes) before the deadline
+const MAX_REMINDER_OFFSET: i32 = 365 * 24 * 60;
+
+/// loads the settings of the user, creates the default settings if the user has none yet
+pub async fn load_settings<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+) -> Result<UserSettingsDB, StatusCode> {
+    let query_failed = |_| {
+        error!("Failed to load settings! (user id: {})", user_id);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let where_params = db_param_map! { user_id: user_id };
+    if let Some(settings) = state
+        .db
+        .select_entries::<UserSettingsDB>(where_params.clone(), &[], None)
+        .await
+        .map_err(query_failed)?
+        .pop()
+    {
+        return Ok(settings);
+    }
+
+    info!("Creating default settings. (user id: {})", user_id);
+    let mut params = db_param_map! { user_id: user_id };
+    params.extend(UserSettingsSend::default().to_param_vec());
+    // fails if a parallel request created the settings in the meantime (user_id is unique), they are loaded below
+    if state.db.new_entry::<UserSettingsDB>(params).await.is_err() {
+        warn!(
+            "Failed to create default settings, they probably exist already. (user id: {})",
+            user_id
+        );
+    }
+
+    state
+        .db
+        .select_entries::<UserSettingsDB>(where_params, &[], None)
+        .await
+        .map_err(query_failed)?
+        .pop()
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// checks every setting, returns a description of the first invalid one
+fn validate_settings(settings: &UserSettingsSend) -> Result<(), String> {
+    if settings.timezone.parse::<Tz>().is_err() {
+        return Err(format!("unknown timezone {}", settings.timezone));
+    }
+    if settings.reminder_offsets.len() > MAX_REMINDER_OFFSETS {
+        return Err(format!(
+            "at most {} reminder_offsets are allowed",
+            MAX_REMINDER_OFFSETS
+        ));
+    }
+    if settings
+        .reminder_offsets
+        .iter()
+        .any(|offset| !(0..=MAX_REMINDER_OFFSET).contains(offset))
+    {
+        return Err(format!(
+            "reminder_offsets must be between 0 and {} minutes",
+            MAX_REMINDER_OFFSET
+        ));
+    }
+    if settings
+        .completed_todo_retention_days
+        .is_some_and(|days| days < 0)
+    {
+        return Err("completed_todo_retention_days must not be negative".to_string());
+    }
+    if !settings.extras.is_object() {
+        return Err("extras must be an object".to_string());
+    }
+
+    Ok(())
+}
+
+/// handler for reading the settings of the user
+pub async fn handle_get_settings<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+) -> Result<Json<UserSettingsSend>, ApiError> {
+    info!("Settings read requested!");
+
+    let AuthenticatedUser { user_id, .. } = user;
+    // settings are not encrypted, no local token needed
+
+    let settings = load_settings(&state, user_id).await?;
+
+    Ok(Json(UserSettingsSend::from(&settings)))
+}
+
+/// handler for replacing the settings of the user
+pub async fn handle_put_settings<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Json(request): Json<UserSettingsSend>,
+) -> Result<Json<UserSettingsSend>, ApiError> {
+    info!("Settings update requested!");
+
+    let AuthenticatedUser { user_id, .. } = user;
+
+    validate_settings(&request).map_err(|err| {
+        info!("Invalid settings rejected: {}", err);
+        (StatusCode::UNPROCESSABLE_ENTITY, err)
+    })?;
+
+    // make sure the row exists, so it can be updated
+    let settings = load_settings(&state, user_id)
+        .await
+        .map_err(|status| (status, String::new()))?;
+
+    state
+        .db
+        .update_entry::<UserSettingsDB>(
+            request.to_param_vec(),
+            db_param_map! { id: settings.id, user_id: user_id },
+        )
+        .await
+        .map_err(|_| {
+            error!("Failed to update settings! (user id: {})", user_id);
+            (StatusCode::INTERNAL_SERVER_ERROR, String::new())
+        })?;
+
+    info!("Settings update successful.");
+    Ok(Json(request))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    use crate::{
+        data_handler::{data_router, objects::UserSettingsDB},
+        db::DBInterface,
+        db_param_map,
+        test_util::{register_user, send_json, test_state},
+    };
+
+    use super::load_settings;
+
+    #[tokio::test]
+    async fn settings_are_created_lazily_with_defaults() {
+        let state = test_state().await;
+        let token = register_user(&state, "settings_lazy").await;
+        let router = data_router(state.clone()).await;
+
+        assert!(
+            state
+                .db
+                .select_entries::<UserSettingsDB>(Vec::new(), &[], None)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let (status, settings) =
+            send_json(router.clone(), Method::GET, "/settings", &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            settings,
+            json!({
+                "timezone": "UTC",
+                "week_start": "monday",
+                "reminder_offsets": [1440],
+                "completed_todo_retention_days": null,
+                "email_digest": false,
+                "extras": {}
+            })
+        );
+        assert_eq!(
+            state
+                .db
+                .select_entries::<UserSettingsDB>(Vec::new(), &[], None)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let new_settings = json!({
+            "timezone": "Europe/Berlin",
+            "week_start": "sunday",
+            "reminder_offsets": [60, 0],
+            "completed_todo_retention_days": 30,
+            "email_digest": true,
+            "extras": { "theme": "dark" }
+        });
+        let (status, _) = send_json(
+            router.clone(),
+            Method::PUT,
+            "/settings",
+            &token,
+            Some(new_settings.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (_, settings) = send_json(router, Method::GET, "/settings", &token, None).await;
+        assert_eq!(settings, new_settings);
+    }
+
+    #[tokio::test]
+    async fn invalid_settings_are_rejected() {
+        let state = test_state().await;
+        let token = register_user(&state, "settings_invalid").await;
+        let router = data_router(state.clone()).await;
+
+        let invalid = [
+            json!({ "timezone": "Mars/Olympus" }),
+            json!({ "week_start": "wednesday" }),
+            json!({ "reminder_offsets": [-5] }),
+            json!({ "completed_todo_retention_days": -1 }),
+            json!({ "extras": [1, 2] }),
+        ];
+        for settings in invalid {
+            let (status, _) = send_json(
+                router.clone(),
+                Method::PUT,
+                "/settings",
+                &token,
+                Some(settings.clone()),
+            )
+            .await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", settings);
+        }
+
+        // missing fields fall back to their defaults
+        let (status, settings) = send_json(
+            router,
+            Method::PUT,
+            "/settings",
+            &token,
+            Some(json!({ "timezone": "America/New_York" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(settings["week_start"], "monday");
+    }
+
+    #[tokio::test]
+    async fn settings_are_unique_per_user() {
+        let state = test_state().await;
+
+        let settings = load_settings(&state, 1).await.unwrap();
+        // loading again returns the same row
+        assert_eq!(load_settings(&state, 1).await.unwrap().id, settings.id);
+
+        let mut params = db_param_map! { user_id: 1 };
+        params.extend(super::UserSettingsSend::default().to_param_vec());
+        assert!(state.db.new_entry::<UserSettingsDB>(params).await.is_err());
+    }
+}
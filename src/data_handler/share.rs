@@ -0,0 +1,602 @@
+use std::{
+    collections::HashMap,
+    env,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Extension, Router,
+    extract::{ConnectInfo, State},
+    http::StatusCode,
+    middleware::map_response,
+    routing::get,
+};
+use chrono::{Days, NaiveDateTime, Utc};
+use log::{error, info, warn};
+use rand::{TryRngCore, rngs::OsRng};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    AppState,
+    api_error::{ApiError, FieldError, Json, Path, Query},
+    auth_handler::{AuthenticatedUser, retry_after_unavailable},
+    crypt::{Cryptable, crypt_types::CryptString},
+    db::{DBInterface, DbError, PublicShare, sql_helper::SQLGenerate},
+    db_param_map,
+};
+
+use super::{
+    IDBody, LocalKey, get_entries,
+    objects::{CourseDB, CourseSend, TopicDB, TopicSend},
+};
+
+/// public requests per minute and client if SHARE_RATE_LIMIT is not set
+const DEFAULT_RATE_LIMIT: u32 = 30;
+/// number of random bytes of a share secret
+const SECRET_LENGTH: usize = 32;
+/// longest lifetime of a share link with an expiry, about 10 years
+const MAX_VALID_DAYS: u64 = 3650;
+
+/// This function defines the public share routes, they do not need authentication
+pub fn share_router<DB: DBInterface + Send + Sync + 'static>(state: Arc<AppState<DB>>) -> Router {
+    Router::new()
+        .route("/{secret}", get(handle_public_share::<DB>))
+        .layer(Extension(Arc::new(RateLimiter::from_env())))
+        .layer(map_response(retry_after_unavailable))
+        .with_state(state)
+}
+
+/// query parameters for creating a share link
+#[derive(Deserialize, Debug)]
+pub struct ShareLinkQuery {
+    /// the link expires after this many days, never if missing
+    valid_days: Option<u64>,
+}
+
+/// response of a newly created share link
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ShareLinkResponse {
+    id: i32,
+    /// part of the public url, only returned once
+    secret: String,
+    expires_at: Option<NaiveDateTime>,
+}
+
+/// share link without its secret, used for listing the links of a user
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ShareLinkInfo {
+    id: i32,
+    ident: String,
+    object_id: i32,
+    expires_at: Option<NaiveDateTime>,
+    created_at: NaiveDateTime,
+}
+
+/// snapshot of a course, which gets shared
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SharedCourse {
+    name: String,
+    topics: Vec<SharedTopic>,
+}
+
+/// topic inside of a shared course
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SharedTopic {
+    name: String,
+    details: String,
+}
+
+/// fixed window rate limiter per client address
+pub struct RateLimiter {
+    max_requests: u32,
+    window: Duration,
+    hits: Mutex<HashMap<IpAddr, (Instant, u32)>>,
+}
+
+impl RateLimiter {
+    /// creates a rate limiter with max_requests per window
+    pub fn new(max_requests: u32, window: Duration) -> Self {
+        Self {
+            max_requests,
+            window,
+            hits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// reads the limit per minute from SHARE_RATE_LIMIT
+    pub fn from_env() -> Self {
+        let max_requests = env::var("SHARE_RATE_LIMIT")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_RATE_LIMIT);
+
+        Self::new(max_requests, Duration::from_secs(60))
+    }
+
+    /// counts a request of the client, returns false if the client exceeded the limit
+    pub fn check(&self, client: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut hits = self.hits.lock().expect("Rate limiter lock poisoned");
+
+        // forget clients whose window is over, keeps the map small
+        hits.retain(|_, (start, _)| now.duration_since(*start) < self.window);
+
+        let (_, count) = hits.entry(client).or_insert((now, 0));
+        *count += 1;
+        *count <= self.max_requests
+    }
+}
+
+/// hash of a secret, used to find the share without storing the secret
+fn lookup_hash(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+/// generates a random secret for a share link
+fn generate_secret() -> Result<String, StatusCode> {
+    let mut secret = [0u8; SECRET_LENGTH];
+    OsRng.try_fill_bytes(&mut secret).map_err(|_| {
+        error!("Failed to generate share secret!");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(hex::encode(secret))
+}
+
+/// handler for creating a share link of a course, the course is stored encrypted with the secret of the link
+pub async fn handle_new_share_link<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Path(course_id): Path<i32>,
+    Query(query): Query<ShareLinkQuery>,
+) -> Result<Json<ShareLinkResponse>, ApiError> {
+    info!("Share link creation requested!");
+    let user_id = user.user_id;
+
+    let now = Utc::now().naive_utc();
+    let expires_at = query
+        .valid_days
+        .map(|days| {
+            Some(days)
+                .filter(|days| *days <= MAX_VALID_DAYS)
+                .and_then(|days| now.checked_add_days(Days::new(days)))
+                .ok_or_else(|| {
+                    ApiError::unprocessable(vec![FieldError {
+                        field: "valid_days".to_string(),
+                        message: format!("is larger than {}", MAX_VALID_DAYS),
+                    }])
+                })
+        })
+        .transpose()?;
+
+    let course_key = LocalKey::<CourseDB>::new(&state, user.clone()).await?;
+    let course = get_entries::<CourseDB, CourseSend, DB>(
+        &state,
+        &course_key,
+        db_param_map! { id: course_id },
+        &[],
+        None,
+    )
+    .await?
+    .pop()
+    .ok_or(StatusCode::NOT_FOUND)?;
+    let topic_key = LocalKey::<TopicDB>::new(&state, user).await?;
+    let topics = get_entries::<TopicDB, TopicSend, DB>(
+        &state,
+        &topic_key,
+        db_param_map! { course_id: course_id },
+        &[],
+        None,
+    )
+    .await?;
+
+    let shared = SharedCourse {
+        name: course.name,
+        topics: topics
+            .into_iter()
+            .map(|topic| SharedTopic {
+                name: topic.name,
+                details: topic.details,
+            })
+            .collect(),
+    };
+    let content = serde_json::to_string(&shared).expect("Shared course is always serializable");
+
+    let secret = generate_secret()?;
+    let content_crypt = CryptString::encrypt(&content, secret.as_bytes(), &state.crypt_provider);
+    let id = state
+        .db
+        .new_public_share(
+            user_id,
+            &CourseDB::get_db_ident(),
+            course_id,
+            &lookup_hash(&secret),
+            &content_crypt,
+            expires_at.as_ref(),
+        )
+        .await
+        .map_err(|_| {
+            error!(
+                "Failed to insert share link into db! (user id: {})",
+                user_id
+            );
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    info!("Share link {} created.", id);
+    Ok(Json(ShareLinkResponse {
+        id,
+        secret,
+        expires_at,
+    }))
+}
+
+/// handler for listing the share links of the user
+pub async fn handle_get_share_links<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+) -> Result<Json<Vec<ShareLinkInfo>>, ApiError> {
+    info!("Share link read requested!");
+
+    let AuthenticatedUser { user_id, .. } = user;
+
+    let shares = state
+        .db
+        .get_public_shares_by_user(user_id)
+        .await
+        .map_err(|_| {
+            error!("Failed to load share links! (user id: {})", user_id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(
+        shares
+            .into_iter()
+            .map(|share| ShareLinkInfo {
+                id: share.id,
+                ident: share.used_for.db_identifier,
+                object_id: share.object_id,
+                expires_at: share.expires_at,
+                created_at: share.created_at,
+            })
+            .collect(),
+    ))
+}
+
+/// handler for revoking a share link
+pub(super) async fn handle_delete_share_link<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Json(request): Json<IDBody>,
+) -> Result<Json<IDBody>, ApiError> {
+    info!("Share link revocation requested!");
+
+    let AuthenticatedUser { user_id, .. } = user;
+
+    let deleted = state
+        .db
+        .del_public_share(user_id, request.id)
+        .await
+        .map_err(|_| {
+            error!("Failed to delete share link in DB!");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if deleted == 0 {
+        // the share link does not exist or belongs to someone else
+        warn!(
+            "Revocation of unknown share link! (user id: {}, id: {})",
+            user_id, request.id
+        );
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
+    info!("Share link {} revoked.", request.id);
+    Ok(Json(request))
+}
+
+/// public handler, returns the shared object by decrypting it with the secret from the url
+/// the secret must never be logged
+async fn handle_public_share<DB: DBInterface + Send + Sync>(
+    State(state): State<Arc<AppState<DB>>>,
+    Extension(limiter): Extension<Arc<RateLimiter>>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    Path(secret): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    // requests without connection info (e.g. tests) share one bucket
+    let client = connect_info
+        .map(|Extension(ConnectInfo(addr))| addr.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    if !limiter.check(client) {
+        warn!("Share link rate limit exceeded by {}", client);
+        return Err(StatusCode::TOO_MANY_REQUESTS.into());
+    }
+
+    let share = state
+        .db
+        .get_public_share_by_lookup(&lookup_hash(&secret))
+        .await
+        .map_err(|err| match err {
+            DbError::NotFound => StatusCode::NOT_FOUND,
+            err => {
+                error!("Failed to load share link from DB: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+    info!("Share link {} accessed.", share.id);
+
+    if share
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= Utc::now().naive_utc())
+    {
+        info!("Share link {} expired, deleting it.", share.id);
+        let _ = state.db.del_public_share(share.user_id, share.id).await;
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
+    // the shared object might have been deleted in the meantime
+    if !shared_object_exists(&state, &share).await? {
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
+    let content = share
+        .content_crypt
+        .decrypt(secret.as_bytes(), &state.crypt_provider)
+        .map_err(|_| {
+            error!("Failed to decrypt share link {}!", share.id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let content = serde_json::from_str(&content).map_err(|_| {
+        error!("Share link {} contains invalid json!", share.id);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(content))
+}
+
+/// checks that the object of a share still exists
+async fn shared_object_exists<DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    share: &PublicShare,
+) -> Result<bool, StatusCode> {
+    let courses = state
+        .db
+        .select_entries::<CourseDB>(
+            db_param_map! { id: share.object_id, user_id: share.user_id },
+            &[],
+            None,
+        )
+        .await
+        .map_err(|_| {
+            error!("Error while querying DB! Tried to check shared course.");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(!courses.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::Duration,
+    };
+
+    use axum::http::{Method, StatusCode};
+    use chrono::{Days, Utc};
+    use serde_json::{Value, json};
+
+    use crate::{
+        crypt::{Cryptable, crypt_types::CryptString},
+        data_handler::{data_router, objects::CourseDB},
+        db::{DBInterface, DbError, sql_helper::SQLGenerate},
+        test_util::{mock_state, register_user, send_json, test_state},
+    };
+
+    use super::{RateLimiter, lookup_hash, share_router};
+
+    /// creates a course with one topic, returns the course id
+    async fn create_course(router: axum::Router, token: &str) -> i64 {
+        let (_, course) = send_json(
+            router.clone(),
+            Method::POST,
+            "/course",
+            token,
+            Some(json!({ "id": null, "name": "Analysis" })),
+        )
+        .await;
+        let course_id = course["id"].as_i64().unwrap();
+        send_json(
+            router,
+            Method::POST,
+            "/topic",
+            token,
+            Some(json!({ "id": null, "course_id": course_id, "name": "Integrals", "details": "by parts" })),
+        )
+        .await;
+
+        course_id
+    }
+
+    #[tokio::test]
+    async fn share_link_can_be_created_and_accessed() {
+        let state = test_state().await;
+        let token = register_user(&state, "share_access").await;
+        let router = data_router(state.clone()).await;
+        let course_id = create_course(router.clone(), &token).await;
+
+        let (status, link) = send_json(
+            router.clone(),
+            Method::POST,
+            &format!("/course/{}/share-link?valid_days=7", course_id),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(link["expires_at"].is_string());
+        let (status, body) = send_json(
+            router.clone(),
+            Method::POST,
+            &format!("/course/{}/share-link?valid_days={}", course_id, u64::MAX),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["field_errors"][0]["field"], "valid_days");
+        let secret = link["secret"].as_str().unwrap();
+
+        // the public route does not need a token
+        let (status, shared) = send_json(
+            share_router(state.clone()),
+            Method::GET,
+            &format!("/{}", secret),
+            "",
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            shared,
+            json!({ "name": "Analysis", "topics": [{ "name": "Integrals", "details": "by parts" }] })
+        );
+
+        let (status, _) = send_json(share_router(state), Method::GET, "/unknown", "", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // links can only be created for own courses
+        let (status, _) = send_json(
+            router,
+            Method::POST,
+            "/course/12345/share-link",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn expired_share_link_returns_not_found() {
+        let state = test_state().await;
+
+        let secret = "expired_secret";
+        let content = CryptString::encrypt(
+            &json!({ "name": "Analysis", "topics": [] }).to_string(),
+            secret.as_bytes(),
+            &state.crypt_provider,
+        );
+        let yesterday = Utc::now().naive_utc() - Days::new(1);
+        state
+            .db
+            .new_public_share(
+                1,
+                &CourseDB::get_db_ident(),
+                1,
+                &lookup_hash(secret),
+                &content,
+                Some(&yesterday),
+            )
+            .await
+            .unwrap();
+
+        let (status, _) = send_json(
+            share_router(state.clone()),
+            Method::GET,
+            &format!("/{}", secret),
+            "",
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        // expired links get cleaned up
+        assert!(
+            state
+                .db
+                .get_public_shares_by_user(1)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn revoked_share_link_returns_not_found() {
+        let state = test_state().await;
+        let token = register_user(&state, "share_revoke").await;
+        let router = data_router(state.clone()).await;
+        let course_id = create_course(router.clone(), &token).await;
+
+        let (_, link) = send_json(
+            router.clone(),
+            Method::POST,
+            &format!("/course/{}/share-link", course_id),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(link["expires_at"], Value::Null);
+        let secret = link["secret"].as_str().unwrap();
+
+        let (_, links) = send_json(router.clone(), Method::GET, "/share-link", &token, None).await;
+        assert_eq!(links.as_array().unwrap().len(), 1);
+        assert!(!links.to_string().contains(secret));
+
+        // only the owner can revoke the link, unknown links are not found
+        let other = register_user(&state, "share_revoke_other").await;
+        for (token, expected) in [
+            (&other, StatusCode::NOT_FOUND),
+            (&token, StatusCode::OK),
+            (&token, StatusCode::NOT_FOUND),
+        ] {
+            let (status, _) = send_json(
+                router.clone(),
+                Method::DELETE,
+                "/share-link",
+                token,
+                Some(json!({ "id": link["id"] })),
+            )
+            .await;
+            assert_eq!(status, expected);
+        }
+
+        let (status, _) = send_json(
+            share_router(state),
+            Method::GET,
+            &format!("/{}", secret),
+            "",
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn failed_share_lookup_is_not_reported_as_not_found() {
+        let state = mock_state().await;
+        state.db.fail_next(
+            "get_public_share_by_lookup",
+            DbError::Pool("timed out".to_string()),
+        );
+
+        let (status, _) = send_json(share_router(state), Method::GET, "/secret", "", None).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn rate_limiter_blocks_after_limit() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let client = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let other = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+        assert!(limiter.check(client));
+        assert!(limiter.check(client));
+        assert!(!limiter.check(client));
+        // other clients are not affected
+        assert!(limiter.check(other));
+    }
+}
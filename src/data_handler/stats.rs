@@ -0,0 +1,438 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{extract::State, http::StatusCode};
+use chrono::{Days, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState,
+    api_error::{ApiError, Json, Query},
+    auth_handler::{AuthenticatedUser, verify_local_token_access},
+    db::{
+        DBInterface, DbError,
+        sql_helper::{SQLGenerate, WhereOp, WhereParam},
+    },
+    db_param_map,
+};
+
+use super::{
+    course::ArchivedQuery,
+    decrypt_failed_status,
+    objects::{CourseDB, ExamDB, GoalStatus, StudyGoalDB, ToDoDB, TopicDB},
+};
+
+/// exams within this many days from today are counted as upcoming
+const UPCOMING_EXAM_DAYS: u64 = 30;
+
+/// number of topics of a course
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct CourseTopics {
+    pub course_id: i32,
+    pub topics: i64,
+}
+
+/// response of the stats request, numbers for the dashboard of the user
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct StatsResponse {
+    /// courses selected like in the course list, archived courses only on request
+    pub courses: i64,
+    /// every selected course, also the ones without topics
+    pub topics_per_course: Vec<CourseTopics>,
+    pub open_todos: i64,
+    pub completed_todos: i64,
+    /// exams from today on within the next 30 days
+    pub upcoming_exams: i64,
+    /// open study goals with a deadline before today
+    pub overdue_study_goals: i64,
+}
+
+/// checks that the token has access to the type, without decrypting its local token
+pub(super) async fn check_access<DBT: SQLGenerate, DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user: &AuthenticatedUser,
+) -> Result<(), StatusCode> {
+    verify_local_token_access(
+        user.user_id,
+        &DBT::get_db_ident(),
+        user.remote_token_id,
+        state,
+    )
+    .await
+    .map_err(|err| decrypt_failed_status(err, &DBT::get_db_ident(), user.remote_token_id))
+}
+
+/// counts the entries of the user matching the params
+async fn count<DBT: SQLGenerate, DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    params: Vec<WhereParam>,
+) -> Result<i64, DbError> {
+    let mut where_params = db_param_map! { user_id: user_id };
+    where_params.extend(params);
+    state.db.count_entries::<DBT>(where_params).await
+}
+
+/// loads the topic counts of every course matching the params, only ids are read so nothing is decrypted
+async fn topics_per_course<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    params: Vec<WhereParam>,
+) -> Result<Vec<CourseTopics>, DbError> {
+    let mut topics: HashMap<i32, i64> = HashMap::new();
+    for topic in state
+        .db
+        .select_entries::<TopicDB>(db_param_map! { user_id: user_id }, &[], None)
+        .await?
+    {
+        *topics.entry(topic.course_id).or_default() += 1;
+    }
+
+    let mut where_params = db_param_map! { user_id: user_id };
+    where_params.extend(params);
+    let courses = state
+        .db
+        .select_entries::<CourseDB>(where_params, &[], None)
+        .await?;
+    Ok(courses
+        .iter()
+        .map(|course| CourseTopics {
+            course_id: course.id,
+            topics: topics.get(&course.id).copied().unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// study progress of a course
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct CourseProgress {
+    pub course_id: i32,
+    pub topics_total: i64,
+    pub topics_studied: i64,
+    /// share of studied topics from 0 to 100, null for courses without topics
+    pub percent: Option<f64>,
+}
+
+/// query parameters of the course progress request
+#[derive(Deserialize, Debug)]
+pub struct ProgressQuery {
+    course_id: Option<i32>,
+}
+
+/// handler returning the study progress of every course of the user or only of one course
+/// only ids are read and topics are counted, so no local token is decrypted
+pub async fn handle_course_progress<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Query(query): Query<ProgressQuery>,
+) -> Result<Json<Vec<CourseProgress>>, ApiError> {
+    info!("Course progress requested!");
+
+    check_access::<CourseDB, DB>(&state, &user).await?;
+    check_access::<TopicDB, DB>(&state, &user).await?;
+
+    let user_id = user.user_id;
+    let progress = async {
+        let mut params = db_param_map! { user_id: user_id };
+        if let Some(course_id) = query.course_id {
+            params.extend(db_param_map! { id: course_id });
+        }
+        let mut course_ids: Vec<i32> = state
+            .db
+            .select_entries::<CourseDB>(params, &[], None)
+            .await?
+            .iter()
+            .map(|course| course.id)
+            .collect();
+        course_ids.sort_unstable();
+
+        let mut progress = Vec::with_capacity(course_ids.len());
+        for course_id in course_ids {
+            let topics_total =
+                count::<TopicDB, DB>(&state, user_id, db_param_map! { course_id: course_id })
+                    .await?;
+            let topics_studied = count::<TopicDB, DB>(
+                &state,
+                user_id,
+                db_param_map! { course_id: course_id, studied: true },
+            )
+            .await?;
+            progress.push(CourseProgress {
+                course_id,
+                topics_total,
+                topics_studied,
+                percent: (topics_total > 0)
+                    .then(|| topics_studied as f64 * 100.0 / topics_total as f64),
+            });
+        }
+        Ok::<_, DbError>(progress)
+    }
+    .await
+    .map_err(|err| {
+        error!("Error while counting topics of user {}!", user_id);
+        StatusCode::from(err)
+    })?;
+
+    if query.course_id.is_some() && progress.is_empty() {
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+    info!("Course progress successful, building response!");
+    Ok(Json(progress))
+}
+
+/// handler returning counts of the objects of the user, e.g. for dashboard cards
+/// only counts and ids are needed, so no local token is decrypted
+pub async fn handle_stats<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Query(query): Query<ArchivedQuery>,
+) -> Result<Json<StatsResponse>, ApiError> {
+    info!("Stats requested!");
+
+    check_access::<CourseDB, DB>(&state, &user).await?;
+    check_access::<TopicDB, DB>(&state, &user).await?;
+    check_access::<ToDoDB, DB>(&state, &user).await?;
+    check_access::<ExamDB, DB>(&state, &user).await?;
+    check_access::<StudyGoalDB, DB>(&state, &user).await?;
+
+    let (user_id, today) = (user.user_id, Utc::now().date_naive());
+    let stats = async {
+        let topics_per_course = topics_per_course(&state, user_id, query.where_params()).await?;
+        Ok::<_, DbError>(StatsResponse {
+            courses: topics_per_course.len() as i64,
+            topics_per_course,
+            open_todos: count::<ToDoDB, DB>(&state, user_id, db_param_map! { completed: false })
+                .await?,
+            completed_todos: count::<ToDoDB, DB>(
+                &state,
+                user_id,
+                db_param_map! { completed: true },
+            )
+            .await?,
+            upcoming_exams: count::<ExamDB, DB>(
+                &state,
+                user_id,
+                vec![
+                    WhereParam::new("date", WhereOp::Ge, today),
+                    WhereParam::new("date", WhereOp::Le, today + Days::new(UPCOMING_EXAM_DAYS)),
+                ],
+            )
+            .await?,
+            overdue_study_goals: count::<StudyGoalDB, DB>(
+                &state,
+                user_id,
+                vec![
+                    WhereParam::new("deadline", WhereOp::Lt, today),
+                    WhereParam::new("status", WhereOp::Eq, GoalStatus::Open),
+                ],
+            )
+            .await?,
+        })
+    }
+    .await
+    .map_err(|err| {
+        error!("Error while counting objects of user {}!", user_id);
+        StatusCode::from(err)
+    })?;
+
+    info!("Stats successful, building response!");
+    Ok(Json(stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+
+    use crate::{
+        data_handler::data_router,
+        test_util::{create_course, register_user, send_json, test_state},
+    };
+
+    #[tokio::test]
+    async fn stats_count_the_objects_of_the_user() {
+        let state = test_state().await;
+        let token = register_user(&state, "stats_user").await;
+        let other = register_user(&state, "stats_other").await;
+        let router = data_router(state.clone()).await;
+        let in_days = |days: i64| (Utc::now().date_naive() + Duration::days(days)).to_string();
+
+        let (status, stats) = send_json(router.clone(), Method::GET, "/stats", &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(stats["courses"], 0);
+        assert_eq!(stats["topics_per_course"], json!([]));
+
+        let course_id = create_course(router.clone(), &token).await;
+        let empty_course_id = create_course(router.clone(), &token).await;
+        let foreign_course_id = create_course(router.clone(), &other).await;
+        let mut topic_id = None;
+        for (token, body) in [
+            (
+                &token,
+                json!({ "id": null, "course_id": course_id, "name": "Limits", "details": "" }),
+            ),
+            (
+                &token,
+                json!({ "id": null, "course_id": course_id, "name": "Series", "details": "" }),
+            ),
+            (
+                &other,
+                json!({ "id": null, "course_id": foreign_course_id, "name": "Other", "details": "" }),
+            ),
+        ] {
+            let (_, topic) =
+                send_json(router.clone(), Method::POST, "/topic", token, Some(body)).await;
+            topic_id.get_or_insert(topic["id"].clone());
+        }
+        for (date, completed) in [
+            (in_days(1), false),
+            (in_days(-3), false),
+            (in_days(2), true),
+        ] {
+            send_json(
+                router.clone(),
+                Method::POST,
+                "/todo",
+                &token,
+                Some(json!({ "id": null, "name": "Todo", "deadline": date, "details": "", "completed": completed })),
+            )
+            .await;
+        }
+        for date in [in_days(0), in_days(30), in_days(31), in_days(-1)] {
+            send_json(
+                router.clone(),
+                Method::POST,
+                "/exam",
+                &token,
+                Some(json!({ "id": null, "course_id": course_id, "name": "Exam", "date": date })),
+            )
+            .await;
+        }
+        for deadline in [in_days(-1), in_days(-10), in_days(0)] {
+            send_json(
+                router.clone(),
+                Method::POST,
+                "/study_goal",
+                &token,
+                Some(json!({ "id": null, "topic_id": topic_id, "deadline": deadline })),
+            )
+            .await;
+        }
+
+        let (status, stats) = send_json(router, Method::GET, "/stats", &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let mut per_course = stats["topics_per_course"].as_array().unwrap().clone();
+        per_course.sort_by_key(|course| course["course_id"].as_i64());
+        assert_eq!(
+            per_course,
+            [
+                json!({ "course_id": course_id, "topics": 2 }),
+                json!({ "course_id": empty_course_id, "topics": 0 }),
+            ]
+        );
+        assert_eq!(stats["courses"], 2);
+        assert_eq!(stats["open_todos"], 2);
+        assert_eq!(stats["completed_todos"], 1);
+        assert_eq!(stats["upcoming_exams"], 2);
+        assert_eq!(stats["overdue_study_goals"], 2);
+    }
+
+    #[tokio::test]
+    async fn course_progress_counts_studied_topics() {
+        let state = test_state().await;
+        let token = register_user(&state, "progress_user").await;
+        let other = register_user(&state, "progress_other").await;
+        let router = data_router(state.clone()).await;
+        let course_id = create_course(router.clone(), &token).await;
+        let empty_course_id = create_course(router.clone(), &token).await;
+        let foreign_course_id = create_course(router.clone(), &other).await;
+
+        let mut topic_ids = Vec::new();
+        for name in ["Limits", "Series", "Integrals"] {
+            let (_, topic) = send_json(
+                router.clone(),
+                Method::POST,
+                "/topic",
+                &token,
+                Some(json!({ "id": null, "course_id": course_id, "name": name, "details": "" })),
+            )
+            .await;
+            topic_ids.push(topic["id"].clone());
+        }
+        let (_, studied) = send_json(
+            router.clone(),
+            Method::GET,
+            "/topic?studied=false",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(studied.as_array().unwrap().len(), 3);
+
+        // studied is set by partial and by full edits
+        let (status, _) = send_json(
+            router.clone(),
+            Method::PATCH,
+            "/topic",
+            &token,
+            Some(json!({ "id": topic_ids[0], "studied": true })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let edit = json!({ "id": topic_ids[1], "course_id": course_id, "name": "Series",
+            "details": "", "studied": true });
+        let (status, _) =
+            send_json(router.clone(), Method::POST, "/topic", &token, Some(edit)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, studied) = send_json(
+            router.clone(),
+            Method::GET,
+            "/topic?studied=true",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(studied.as_array().unwrap().len(), 2);
+
+        let (status, progress) = send_json(
+            router.clone(),
+            Method::GET,
+            "/course/progress",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let percent = 200.0 / 3.0;
+        assert_eq!(
+            progress,
+            json!([
+                { "course_id": course_id, "topics_total": 3, "topics_studied": 2, "percent": percent },
+                { "course_id": empty_course_id, "topics_total": 0, "topics_studied": 0, "percent": null },
+            ])
+        );
+
+        let (status, progress) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!("/course/progress?course_id={}", empty_course_id),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(progress.as_array().unwrap().len(), 1);
+        assert_eq!(progress[0]["percent"], json!(null));
+
+        let (status, _) = send_json(
+            router,
+            Method::GET,
+            &format!("/course/progress?course_id={}", foreign_course_id),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
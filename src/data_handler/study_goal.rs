@@ -0,0 +1,755 @@
+use std::{any::type_name, collections::HashMap, collections::HashSet, sync::Arc};
+
+use axum::{extract::State, http::StatusCode};
+use chrono::Utc;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState,
+    api_error::{ApiError, Json, Path, Query},
+    auth_handler::AuthenticatedUser,
+    db::{
+        DBInterface, StudyGoalTopic,
+        sql_helper::{SQLGenerate, SortDirection, WhereOp, WhereParam},
+    },
+    db_param_map,
+};
+
+use super::{
+    LocalKey, filter_params, get_entries,
+    objects::{GoalStatus, StudyGoalDB, StudyGoalSend, StudySessionDB, TopicDB},
+    record_change,
+    webhook::WebhookAction,
+};
+
+/// body of the request setting the topics of a study goal, also used as response
+#[derive(Deserialize, Serialize, Debug)]
+pub struct StudyGoalTopicsBody {
+    topic_ids: Vec<i32>,
+}
+
+/// loads every link between study goals and topics of the user, grouped by goal id
+async fn load_links<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+) -> Result<HashMap<i32, Vec<i32>>, StatusCode> {
+    let links = state
+        .db
+        .get_study_goal_topics_by_user(user_id)
+        .await
+        .map_err(|_| {
+            error!("Failed to load study goal topics! (user id: {})", user_id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(group_links(links))
+}
+
+/// groups links between study goals and topics by goal id, the order of the topics is kept
+pub fn group_links(links: Vec<StudyGoalTopic>) -> HashMap<i32, Vec<i32>> {
+    let mut grouped: HashMap<i32, Vec<i32>> = HashMap::new();
+    for StudyGoalTopic { goal_id, topic_id } in links {
+        grouped.entry(goal_id).or_default().push(topic_id);
+    }
+    grouped
+}
+
+/// replaces the topic ids of the study goals with their linked topics, goals without links keep their legacy topic
+pub async fn with_topic_ids<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    mut goals: Vec<StudyGoalSend>,
+) -> Result<Vec<StudyGoalSend>, StatusCode> {
+    let mut links = load_links(state, user_id).await?;
+    for goal in goals.iter_mut() {
+        if let Some(topic_ids) = goal.id.and_then(|id| links.remove(&id)) {
+            goal.topic_ids = topic_ids;
+        }
+    }
+    Ok(goals)
+}
+
+/// sets the progress of the study goals to the minutes of the sessions of their topics up to the deadline
+/// only topic ids and durations of the sessions are read, nothing is decrypted
+pub async fn with_progress<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    mut goals: Vec<StudyGoalSend>,
+) -> Result<Vec<StudyGoalSend>, StatusCode> {
+    let mut sessions: HashMap<i32, Vec<StudySessionDB>> = HashMap::new();
+    for session in state
+        .db
+        .select_entries::<StudySessionDB>(db_param_map! { user_id: user_id }, &[], None)
+        .await
+        .map_err(|_| {
+            error!("Failed to load study sessions! (user id: {})", user_id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+    {
+        sessions.entry(session.topic_id).or_default().push(session);
+    }
+
+    for goal in goals.iter_mut() {
+        goal.progress_minutes = goal
+            .topic_ids
+            .iter()
+            .filter_map(|topic_id| sessions.get(topic_id))
+            .flatten()
+            .filter(|session| session.started_at.date() <= goal.deadline)
+            .map(|session| session.duration_minutes as i64)
+            .sum();
+    }
+    Ok(goals)
+}
+
+/// handler for get requests of study goals, includes the topics and the progress of every goal
+pub async fn handle_get_study_goals<DB: DBInterface + Send + Sync>(
+    key: LocalKey<StudyGoalDB>,
+    State(state): State<Arc<AppState<DB>>>,
+    Query(params_query): Query<HashMap<String, String>>,
+) -> Result<Json<Vec<StudyGoalSend>>, ApiError> {
+    info!("{} read requested!", type_name::<StudyGoalDB>());
+
+    let user_id = key.user.user_id;
+    let goals = get_entries::<StudyGoalDB, StudyGoalSend, DB>(
+        &state,
+        &key,
+        filter_params::<StudyGoalDB>(params_query)?,
+        &[],
+        None,
+    )
+    .await?;
+
+    info!(
+        "{} read successful, building response!",
+        type_name::<StudyGoalDB>()
+    );
+    let goals = with_topic_ids(&state, user_id, goals).await?;
+    Ok(Json(with_progress(&state, user_id, goals).await?))
+}
+
+/// handler returning the open study goals whose deadline has passed, the longest overdue first
+pub async fn handle_overdue_study_goals<DB: DBInterface + Send + Sync>(
+    key: LocalKey<StudyGoalDB>,
+    State(state): State<Arc<AppState<DB>>>,
+) -> Result<Json<Vec<StudyGoalSend>>, ApiError> {
+    info!("Overdue study goals requested!");
+
+    let user_id = key.user.user_id;
+    let mut params = vec![WhereParam::new(
+        "deadline",
+        WhereOp::Lt,
+        Utc::now().date_naive(),
+    )];
+    params.extend(db_param_map! { status: GoalStatus::Open });
+    let order = [("deadline".to_string(), SortDirection::Asc)];
+    let goals = get_entries(&state, &key, params, &order, None).await?;
+
+    info!("Overdue study goals successful, building response!");
+    let goals = with_topic_ids(&state, user_id, goals).await?;
+    Ok(Json(with_progress(&state, user_id, goals).await?))
+}
+
+/// handler for replacing the topics of a study goal, every topic has to belong to the user
+pub async fn handle_set_study_goal_topics<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Path(goal_id): Path<i32>,
+    Json(request): Json<StudyGoalTopicsBody>,
+) -> Result<Json<StudyGoalTopicsBody>, ApiError> {
+    info!("Study goal topics update requested!");
+
+    let AuthenticatedUser { user_id, .. } = user;
+    // only ids are stored, no local token needed
+
+    let query_failed = |_| {
+        error!("Error while querying DB! (user id: {})", user_id);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let goal_exists = !state
+        .db
+        .select_entries::<StudyGoalDB>(db_param_map! { id: goal_id, user_id: user_id }, &[], None)
+        .await
+        .map_err(query_failed)?
+        .is_empty();
+    if !goal_exists {
+        warn!("Study goal {} not found! (user id: {})", goal_id, user_id);
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
+    // duplicates are dropped, the first occurrence keeps its position
+    let mut seen = HashSet::new();
+    let topic_ids: Vec<i32> = request
+        .topic_ids
+        .into_iter()
+        .filter(|id| seen.insert(*id))
+        .collect();
+    if topic_ids.is_empty() {
+        warn!("Study goal topics without any topic rejected!");
+        return Err(StatusCode::UNPROCESSABLE_ENTITY.into());
+    }
+
+    let own_topics: HashSet<i32> = state
+        .db
+        .select_entries::<TopicDB>(db_param_map! { user_id: user_id }, &[], None)
+        .await
+        .map_err(query_failed)?
+        .iter()
+        .map(|topic| topic.id)
+        .collect();
+    if !topic_ids.iter().all(|id| own_topics.contains(id)) {
+        warn!(
+            "Study goal topics with unknown topic rejected! (user id: {})",
+            user_id
+        );
+        return Err(StatusCode::UNPROCESSABLE_ENTITY.into());
+    }
+
+    state
+        .db
+        .set_study_goal_topics(user_id, goal_id, &topic_ids)
+        .await
+        .map_err(query_failed)?;
+    // keep the deprecated single link pointing at a valid topic for older clients
+    state
+        .db
+        .update_entry::<StudyGoalDB>(
+            db_param_map! { topic_id: topic_ids[0], topics_missing: false },
+            db_param_map! { id: goal_id, user_id: user_id },
+        )
+        .await
+        .map_err(query_failed)?;
+    record_change(
+        &state,
+        user_id,
+        &StudyGoalDB::get_db_ident(),
+        WebhookAction::Edit,
+        goal_id,
+    )
+    .await;
+
+    info!("Study goal topics update successful.");
+    Ok(Json(StudyGoalTopicsBody { topic_ids }))
+}
+
+/// removes the links of a deleted study goal or topic, called after every deletion
+pub async fn remove_links_for<DBT: SQLGenerate, DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    id: i32,
+) -> Result<(), StatusCode> {
+    let ident = DBT::get_db_ident().db_identifier;
+    if ident == StudyGoalDB::get_db_ident().db_identifier {
+        state
+            .db
+            .del_study_goal_topics_by_goal(user_id, id)
+            .await
+            .map_err(|_| {
+                error!("Failed to delete study goal topics! (user id: {})", user_id);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    } else if ident == TopicDB::get_db_ident().db_identifier {
+        unlink_topic(state, user_id, id).await?;
+    }
+
+    Ok(())
+}
+
+/// removes a deleted topic from all study goals, goals without remaining topics are kept but flagged
+async fn unlink_topic<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    topic_id: i32,
+) -> Result<(), StatusCode> {
+    let query_failed = |_| {
+        error!(
+            "Failed to unlink topic from study goals! (user id: {})",
+            user_id
+        );
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let links = load_links(state, user_id).await?;
+    let goals = state
+        .db
+        .select_entries::<StudyGoalDB>(db_param_map! { user_id: user_id }, &[], None)
+        .await
+        .map_err(query_failed)?;
+    state
+        .db
+        .del_study_goal_topics_by_topic(user_id, topic_id)
+        .await
+        .map_err(query_failed)?;
+
+    for goal in goals {
+        let params = match links.get(&goal.id) {
+            // goal without links, only the legacy topic counts
+            None if goal.topic_id == topic_id && !goal.topics_missing => {
+                db_param_map! { topics_missing: true }
+            }
+            Some(topics) if topics.contains(&topic_id) => {
+                match topics.iter().find(|id| **id != topic_id) {
+                    Some(remaining) => db_param_map! { topic_id: *remaining },
+                    None => db_param_map! { topics_missing: true },
+                }
+            }
+            _ => continue,
+        };
+
+        state
+            .db
+            .update_entry::<StudyGoalDB>(params, db_param_map! { id: goal.id, user_id: user_id })
+            .await
+            .map_err(query_failed)?;
+        record_change(
+            state,
+            user_id,
+            &StudyGoalDB::get_db_ident(),
+            WebhookAction::Edit,
+            goal.id,
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        http::{Method, StatusCode},
+    };
+    use serde_json::{Value, json};
+
+    use crate::{
+        data_handler::data_router,
+        db::DBInterface,
+        test_util::{register_user, send_json, test_state},
+    };
+
+    /// creates an object, returns its id
+    async fn create(router: Router, token: &str, uri: &str, body: Value) -> i64 {
+        let (status, created) = send_json(router, Method::POST, uri, token, Some(body)).await;
+        assert_eq!(status, StatusCode::OK);
+        created["id"].as_i64().unwrap()
+    }
+
+    /// creates a course with the number of topics, returns the topic ids
+    async fn create_topics(router: Router, token: &str, count: usize) -> Vec<i64> {
+        let course = create(
+            router.clone(),
+            token,
+            "/course",
+            json!({ "id": null, "name": "Analysis" }),
+        )
+        .await;
+        let mut topics = Vec::new();
+        for i in 0..count {
+            topics.push(
+                create(
+                    router.clone(),
+                    token,
+                    "/topic",
+                    json!({ "id": null, "course_id": course, "name": format!("chapter {}", i), "details": "" }),
+                )
+                .await,
+            );
+        }
+        topics
+    }
+
+    /// returns the study goal with the id
+    async fn get_goal(router: Router, token: &str, id: i64) -> Value {
+        let (status, goals) = send_json(
+            router,
+            Method::GET,
+            &format!("/study_goal?id={}", id),
+            token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        goals[0].clone()
+    }
+
+    #[tokio::test]
+    async fn topics_are_replaced_and_validated() {
+        let state = test_state().await;
+        let token = register_user(&state, "goal_topics").await;
+        let other_token = register_user(&state, "goal_topics_other").await;
+        let router = data_router(state.clone()).await;
+
+        let topics = create_topics(router.clone(), &token, 4).await;
+        let foreign = create_topics(router.clone(), &other_token, 1).await;
+        let goal = create(
+            router.clone(),
+            &token,
+            "/study_goal",
+            json!({ "id": null, "topic_id": topics[0], "deadline": "2025-07-01" }),
+        )
+        .await;
+
+        // goals created by older clients only have the legacy topic
+        let created = get_goal(router.clone(), &token, goal).await;
+        assert_eq!(created["topic_ids"], json!([topics[0]]));
+        assert_eq!(created["topics_missing"], false);
+
+        let uri = format!("/study_goal/{}/topics", goal);
+        let (status, body) = send_json(
+            router.clone(),
+            Method::PUT,
+            &uri,
+            &token,
+            Some(json!({ "topic_ids": [topics[2], topics[1], topics[2]] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["topic_ids"], json!([topics[2], topics[1]]));
+
+        // partial overlap, the old list is replaced completely
+        let (status, _) = send_json(
+            router.clone(),
+            Method::PUT,
+            &uri,
+            &token,
+            Some(json!({ "topic_ids": [topics[1], topics[3]] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let updated = get_goal(router.clone(), &token, goal).await;
+        assert_eq!(updated["topic_ids"], json!([topics[1], topics[3]]));
+        assert_eq!(updated["topic_id"], topics[1]);
+
+        // topics of other users, empty lists and foreign goals are rejected, the links stay untouched
+        for topic_ids in [json!([topics[0], foreign[0]]), json!([])] {
+            let (status, _) = send_json(
+                router.clone(),
+                Method::PUT,
+                &uri,
+                &token,
+                Some(json!({ "topic_ids": topic_ids })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        }
+        let (status, _) = send_json(
+            router.clone(),
+            Method::PUT,
+            &uri,
+            &other_token,
+            Some(json!({ "topic_ids": foreign })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let unchanged = get_goal(router, &token, goal).await;
+        assert_eq!(unchanged["topic_ids"], json!([topics[1], topics[3]]));
+    }
+
+    #[tokio::test]
+    async fn deleting_topics_unlinks_and_flags_goals() {
+        let state = test_state().await;
+        let token = register_user(&state, "goal_cascade").await;
+        let router = data_router(state.clone()).await;
+
+        let topics = create_topics(router.clone(), &token, 4).await;
+        let linked = create(
+            router.clone(),
+            &token,
+            "/study_goal",
+            json!({ "id": null, "topic_id": topics[0], "deadline": "2025-07-01" }),
+        )
+        .await;
+        let legacy = create(
+            router.clone(),
+            &token,
+            "/study_goal",
+            json!({ "id": null, "topic_id": topics[2], "deadline": "2025-07-01" }),
+        )
+        .await;
+        let (status, _) = send_json(
+            router.clone(),
+            Method::PUT,
+            &format!("/study_goal/{}/topics", linked),
+            &token,
+            Some(json!({ "topic_ids": [topics[0], topics[1]] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let delete_topic = |id: i64| {
+            send_json(
+                router.clone(),
+                Method::DELETE,
+                "/topic",
+                &token,
+                Some(json!({ "id": id })),
+            )
+        };
+
+        delete_topic(topics[0]).await;
+        let goal = get_goal(router.clone(), &token, linked).await;
+        assert_eq!(goal["topic_ids"], json!([topics[1]]));
+        assert_eq!(goal["topic_id"], topics[1]);
+        assert_eq!(goal["topics_missing"], false);
+
+        // the last topic is gone, the goal is kept but flagged
+        delete_topic(topics[1]).await;
+        let goal = get_goal(router.clone(), &token, linked).await;
+        assert_eq!(goal["topic_ids"], json!([]));
+        assert_eq!(goal["topics_missing"], true);
+
+        // goals of older clients are flagged as well
+        delete_topic(topics[2]).await;
+        let goal = get_goal(router.clone(), &token, legacy).await;
+        assert_eq!(goal["topic_ids"], json!([]));
+        assert_eq!(goal["topics_missing"], true);
+
+        // deleting a goal removes its links
+        let (status, _) = send_json(
+            router.clone(),
+            Method::PUT,
+            &format!("/study_goal/{}/topics", linked),
+            &token,
+            Some(json!({ "topic_ids": [topics[3]] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            get_goal(router.clone(), &token, linked).await["topics_missing"],
+            false
+        );
+        send_json(
+            router,
+            Method::DELETE,
+            "/study_goal",
+            &token,
+            Some(json!({ "id": linked })),
+        )
+        .await;
+        assert!(
+            state
+                .db
+                .get_study_goal_topics_by_user(1)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn exam_is_optional() {
+        let state = test_state().await;
+        let token = register_user(&state, "goal_exam").await;
+        let router = data_router(state.clone()).await;
+
+        let topics = create_topics(router.clone(), &token, 1).await;
+        let course = create(
+            router.clone(),
+            &token,
+            "/course",
+            json!({ "id": null, "name": "Algebra" }),
+        )
+        .await;
+        let exam = create(
+            router.clone(),
+            &token,
+            "/exam",
+            json!({ "id": null, "course_id": course, "name": "Algebra", "date": "2025-07-01" }),
+        )
+        .await;
+
+        // older clients do not send the field at all
+        let goal = create(
+            router.clone(),
+            &token,
+            "/study_goal",
+            json!({ "id": null, "topic_id": topics[0], "deadline": "2025-06-01" }),
+        )
+        .await;
+        assert_eq!(
+            get_goal(router.clone(), &token, goal).await["exam_id"],
+            json!(null)
+        );
+
+        let edit = |exam_id: Value| {
+            create(
+                router.clone(),
+                &token,
+                "/study_goal",
+                json!({ "id": goal, "topic_id": topics[0], "deadline": "2025-06-01", "exam_id": exam_id }),
+            )
+        };
+        edit(json!(exam)).await;
+        assert_eq!(
+            get_goal(router.clone(), &token, goal).await["exam_id"],
+            exam
+        );
+        let (_, unset) = send_json(
+            router.clone(),
+            Method::GET,
+            "/study_goal?exam_id=null",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(unset, json!([]));
+
+        edit(json!(null)).await;
+        assert_eq!(
+            get_goal(router.clone(), &token, goal).await["exam_id"],
+            json!(null)
+        );
+
+        // deleting the exam unsets it
+        edit(json!(exam)).await;
+        let (status, _) = send_json(
+            router.clone(),
+            Method::DELETE,
+            "/exam",
+            &token,
+            Some(json!({ "id": exam })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(get_goal(router, &token, goal).await["exam_id"], json!(null));
+    }
+
+    #[tokio::test]
+    async fn goals_track_title_target_status_and_progress() {
+        let state = test_state().await;
+        let token = register_user(&state, "goal_progress").await;
+        let other_token = register_user(&state, "goal_progress_other").await;
+        let router = data_router(state.clone()).await;
+
+        let topics = create_topics(router.clone(), &token, 2).await;
+        let goal = create(
+            router.clone(),
+            &token,
+            "/study_goal",
+            json!({ "id": null, "topic_id": topics[0], "deadline": "2025-06-15",
+                "title": "Read chapter 1", "target_minutes": 120 }),
+        )
+        .await;
+        let created = get_goal(router.clone(), &token, goal).await;
+        assert_eq!(created["title"], "Read chapter 1");
+        assert_eq!(created["target_minutes"], 120);
+        assert_eq!(created["status"], "open");
+        assert_eq!(created["progress_minutes"], 0);
+
+        // the target has to be positive
+        let (status, body) = send_json(
+            router.clone(),
+            Method::PATCH,
+            "/study_goal",
+            &token,
+            Some(json!({ "id": goal, "target_minutes": 0 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["field_errors"][0]["field"], "target_minutes");
+
+        // sessions of every topic of the goal count until the deadline
+        let (status, _) = send_json(
+            router.clone(),
+            Method::PUT,
+            &format!("/study_goal/{}/topics", goal),
+            &token,
+            Some(json!({ "topic_ids": topics })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        for (topic, started_at, minutes) in [
+            (topics[0], "2025-06-01T10:00:00", 45),
+            (topics[1], "2025-06-15T20:00:00", 30),
+            (topics[1], "2025-06-16T08:00:00", 60),
+        ] {
+            create(
+                router.clone(),
+                &token,
+                "/study_session",
+                json!({ "id": null, "topic_id": topic, "started_at": started_at, "duration_minutes": minutes }),
+            )
+            .await;
+        }
+        assert_eq!(
+            get_goal(router.clone(), &token, goal).await["progress_minutes"],
+            75
+        );
+
+        // the deadline of both goals has passed, only open ones are overdue
+        let done = create(
+            router.clone(),
+            &token,
+            "/study_goal",
+            json!({ "id": null, "topic_id": topics[0], "deadline": "2025-06-01" }),
+        )
+        .await;
+        let overdue = |token: String| {
+            let router = router.clone();
+            async move {
+                let (status, goals) =
+                    send_json(router, Method::GET, "/study_goal/overdue", &token, None).await;
+                assert_eq!(status, StatusCode::OK);
+                goals
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|goal| goal["id"].as_i64().unwrap())
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(overdue(token.clone()).await, [done, goal]);
+        assert!(overdue(other_token).await.is_empty());
+
+        let (status, _) = send_json(
+            router.clone(),
+            Method::PATCH,
+            "/study_goal",
+            &token,
+            Some(json!({ "id": done, "status": "achieved", "title": "Done" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let edited = create(
+            router.clone(),
+            &token,
+            "/study_goal",
+            json!({ "id": goal, "topic_id": topics[0], "deadline": "2025-06-15",
+                "title": "Read chapter 2", "target_minutes": null, "status": "missed" }),
+        )
+        .await;
+        assert_eq!(edited, goal);
+        assert!(overdue(token.clone()).await.is_empty());
+
+        let updated = get_goal(router.clone(), &token, goal).await;
+        assert_eq!(updated["title"], "Read chapter 2");
+        assert_eq!(updated["target_minutes"], json!(null));
+        assert_eq!(updated["status"], "missed");
+        let (_, achieved) = send_json(
+            router.clone(),
+            Method::GET,
+            "/study_goal?status=achieved",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(achieved.as_array().unwrap().len(), 1);
+        assert_eq!(achieved[0]["title"], "Done");
+
+        let (status, _) = send_json(
+            router.clone(),
+            Method::DELETE,
+            "/study_goal",
+            &token,
+            Some(json!({ "id": done })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, goals) = send_json(router, Method::GET, "/study_goal", &token, None).await;
+        assert_eq!(goals.as_array().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,231 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{extract::State, http::StatusCode};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState,
+    api_error::{ApiError, Json, Query},
+    auth_handler::AuthenticatedUser,
+    db::{DBInterface, DbError},
+    db_param_map,
+};
+
+use super::{
+    objects::{CourseDB, StudySessionDB, TopicDB},
+    stats::check_access,
+};
+
+/// query parameters of the summary request
+#[derive(Deserialize, Debug)]
+pub struct SummaryQuery {
+    course_id: i32,
+}
+
+/// minutes studied for a topic
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct TopicMinutes {
+    pub topic_id: i32,
+    pub minutes: i64,
+}
+
+/// response of the summary request
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct StudySummary {
+    pub course_id: i32,
+    /// sum over all topics of the course
+    pub minutes: i64,
+    /// every topic of the course ordered by id, also the ones without sessions
+    pub topics: Vec<TopicMinutes>,
+}
+
+/// sums the minutes of the sessions per topic of the course, none if the course does not exist
+async fn summarize<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    course_id: i32,
+) -> Result<Option<StudySummary>, DbError> {
+    if state
+        .db
+        .count_entries::<CourseDB>(db_param_map! { id: course_id, user_id: user_id })
+        .await?
+        == 0
+    {
+        return Ok(None);
+    }
+
+    let mut minutes: HashMap<i32, i64> = HashMap::new();
+    for session in state
+        .db
+        .select_entries::<StudySessionDB>(db_param_map! { user_id: user_id }, &[], None)
+        .await?
+    {
+        *minutes.entry(session.topic_id).or_default() += session.duration_minutes as i64;
+    }
+
+    let mut topics: Vec<TopicMinutes> = state
+        .db
+        .select_entries::<TopicDB>(
+            db_param_map! { course_id: course_id, user_id: user_id },
+            &[],
+            None,
+        )
+        .await?
+        .iter()
+        .map(|topic| TopicMinutes {
+            topic_id: topic.id,
+            minutes: minutes.get(&topic.id).copied().unwrap_or_default(),
+        })
+        .collect();
+    topics.sort_by_key(|topic| topic.topic_id);
+
+    Ok(Some(StudySummary {
+        course_id,
+        minutes: topics.iter().map(|topic| topic.minutes).sum(),
+        topics,
+    }))
+}
+
+/// handler returning the minutes studied per topic of a course
+/// only ids and durations are needed, so no local token is decrypted
+pub async fn handle_summary<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Query(query): Query<SummaryQuery>,
+) -> Result<Json<StudySummary>, ApiError> {
+    info!("Study session summary requested!");
+
+    check_access::<CourseDB, DB>(&state, &user).await?;
+    check_access::<TopicDB, DB>(&state, &user).await?;
+    check_access::<StudySessionDB, DB>(&state, &user).await?;
+
+    let summary = summarize(&state, user.user_id, query.course_id)
+        .await
+        .map_err(|err| {
+            error!(
+                "Error while summing the study sessions of user {}!",
+                user.user_id
+            );
+            StatusCode::from(err)
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    info!("Study session summary successful, building response!");
+    Ok(Json(summary))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    use crate::{
+        data_handler::data_router,
+        test_util::{create_course, register_user, send_json, test_state},
+    };
+
+    #[tokio::test]
+    async fn study_sessions_are_summed_per_topic() {
+        let state = test_state().await;
+        let token = register_user(&state, "session_user").await;
+        let other = register_user(&state, "session_other").await;
+        let router = data_router(state.clone()).await;
+        let course_id = create_course(router.clone(), &token).await;
+        let other_course_id = create_course(router.clone(), &token).await;
+
+        let mut topic_ids = Vec::new();
+        for course_id in [course_id, course_id, course_id, other_course_id] {
+            let (_, topic) = send_json(
+                router.clone(),
+                Method::POST,
+                "/topic",
+                &token,
+                Some(json!({ "id": null, "course_id": course_id, "name": "Topic", "details": "" })),
+            )
+            .await;
+            topic_ids.push(topic["id"].clone());
+        }
+        for (topic_id, started_at, minutes) in [
+            (&topic_ids[0], "2025-06-01T09:00:00", 45),
+            (&topic_ids[0], "2025-06-02T09:00:00", 30),
+            (&topic_ids[1], "2025-06-03T18:30:00", 90),
+            (&topic_ids[3], "2025-06-03T20:00:00", 20),
+        ] {
+            let (status, _) = send_json(
+                router.clone(),
+                Method::POST,
+                "/study_session",
+                &token,
+                Some(json!({ "id": null, "topic_id": topic_id, "started_at": started_at, "duration_minutes": minutes, "notes": "chapter 2" })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+        }
+
+        let (status, sessions) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!(
+                "/study_session?topic_id={}&started_at_after=2025-06-01T12:00:00",
+                topic_ids[0]
+            ),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(sessions.as_array().unwrap().len(), 1);
+        assert_eq!(sessions[0]["duration_minutes"], 30);
+        assert_eq!(sessions[0]["notes"], "chapter 2");
+
+        let (status, summary) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!("/study_session/summary?course_id={}", course_id),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            summary,
+            json!({
+                "course_id": course_id,
+                "minutes": 165,
+                "topics": [
+                    { "topic_id": topic_ids[0], "minutes": 75 },
+                    { "topic_id": topic_ids[1], "minutes": 90 },
+                    { "topic_id": topic_ids[2], "minutes": 0 },
+                ]
+            })
+        );
+
+        // sessions need a positive duration and a topic of the user
+        for (token, topic_id, minutes) in [(&token, &topic_ids[0], 0), (&other, &topic_ids[0], 10)]
+        {
+            let (status, _) = send_json(
+                router.clone(),
+                Method::POST,
+                "/study_session",
+                token,
+                Some(json!({ "id": null, "topic_id": topic_id, "started_at": "2025-06-04T10:00:00", "duration_minutes": minutes })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        }
+
+        let (status, _) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!("/study_session/summary?course_id={}", course_id),
+            &other,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) =
+            send_json(router, Method::GET, "/study_session/summary", &token, None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,499 @@
+use std::{any::type_name, collections::BTreeMap, sync::Arc};
+
+use axum::{extract::State, http::StatusCode};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState,
+    api_error::{ApiError, Json, Query},
+    auth_handler::AuthenticatedUser,
+    db::{
+        DBInterface, SyncChange,
+        sql_helper::{SQLGenerate, WhereParam},
+    },
+    db_param_map,
+};
+
+use super::{
+    FromDB, local_token_for,
+    objects::{
+        CourseDB, CourseSend, ExamDB, ExamSend, StudyGoalDB, StudyGoalSend, ToDoDB, ToDoSend,
+        TopicDB, TopicSend,
+    },
+    study_goal::{with_progress, with_topic_ids},
+};
+
+/// number of journal entries that are read from the db at once
+const SYNC_PAGE_SIZE: i64 = 500;
+/// a single sync response contains at most this many changes, the client has to sync again if has_more is set
+const MAX_CHANGES_PER_SYNC: usize = 5000;
+/// prefix of the cursor, makes it possible to change the format later on
+const CURSOR_PREFIX: &str = "sync1:";
+
+/// query parameters of the sync request
+#[derive(Deserialize, Debug)]
+pub struct SyncQuery {
+    /// cursor returned by the previous sync, full sync if missing
+    since: Option<String>,
+}
+
+/// changes of a single object type
+#[derive(Serialize)]
+pub struct SyncDelta<ST> {
+    /// objects that were created or edited
+    updated: Vec<ST>,
+    /// ids of objects that were deleted
+    deleted: Vec<i32>,
+}
+
+/// response of the sync request
+#[derive(Serialize)]
+pub struct SyncResponse {
+    /// has to be passed as since on the next sync
+    cursor: String,
+    /// there are more changes, sync again with the new cursor
+    has_more: bool,
+    course: SyncDelta<CourseSend>,
+    topic: SyncDelta<TopicSend>,
+    study_goal: SyncDelta<StudyGoalSend>,
+    exam: SyncDelta<ExamSend>,
+    todo: SyncDelta<ToDoSend>,
+}
+
+/// encodes the id of the last seen journal entry as an opaque cursor
+fn encode_cursor(change_id: i64) -> String {
+    hex::encode(format!("{}{}", CURSOR_PREFIX, change_id))
+}
+
+/// decodes a cursor created by encode_cursor
+fn decode_cursor(cursor: &str) -> Option<i64> {
+    let decoded = String::from_utf8(hex::decode(cursor).ok()?).ok()?;
+    decoded.strip_prefix(CURSOR_PREFIX)?.parse().ok()
+}
+
+/// handler for sync requests, returns every change since the cursor
+pub async fn handle_sync<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Query(query): Query<SyncQuery>,
+) -> Result<Json<SyncResponse>, ApiError> {
+    info!("Sync requested!");
+
+    let AuthenticatedUser {
+        user_id,
+        remote_token_id,
+        remote_token,
+    } = user;
+
+    let mut response = match query.since {
+        None => full_sync(&state, user_id, remote_token_id, &remote_token).await?,
+        Some(cursor) => {
+            let Some(since) = decode_cursor(&cursor) else {
+                warn!("Sync with invalid cursor requested!");
+                return Err(StatusCode::BAD_REQUEST.into());
+            };
+            delta_sync(&state, user_id, remote_token_id, &remote_token, since).await?
+        }
+    };
+
+    // the topics of study goals are stored separately, their progress is computed from the sessions
+    let goals = with_topic_ids(&state, user_id, response.study_goal.updated).await?;
+    response.study_goal.updated = with_progress(&state, user_id, goals).await?;
+
+    info!("Sync successful, building response!");
+    Ok(Json(response))
+}
+
+/// returns every object of the user
+async fn full_sync<DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    user_id: i32,
+    remote_token_id: i32,
+    remote_token: &str,
+) -> Result<SyncResponse, StatusCode> {
+    // read the cursor first, changes happening while loading are sent again on the next sync
+    let latest = state
+        .db
+        .get_latest_sync_change_id(user_id)
+        .await
+        .map_err(|_| {
+            error!("Failed to read sync journal! (user id: {})", user_id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(SyncResponse {
+        cursor: encode_cursor(latest),
+        has_more: false,
+        course: load_delta::<CourseDB, CourseSend, DB>(
+            state,
+            user_id,
+            remote_token_id,
+            remote_token,
+            None,
+        )
+        .await?,
+        topic: load_delta::<TopicDB, TopicSend, DB>(
+            state,
+            user_id,
+            remote_token_id,
+            remote_token,
+            None,
+        )
+        .await?,
+        study_goal: load_delta::<StudyGoalDB, StudyGoalSend, DB>(
+            state,
+            user_id,
+            remote_token_id,
+            remote_token,
+            None,
+        )
+        .await?,
+        exam: load_delta::<ExamDB, ExamSend, DB>(
+            state,
+            user_id,
+            remote_token_id,
+            remote_token,
+            None,
+        )
+        .await?,
+        todo: load_delta::<ToDoDB, ToDoSend, DB>(
+            state,
+            user_id,
+            remote_token_id,
+            remote_token,
+            None,
+        )
+        .await?,
+    })
+}
+
+/// returns the changes of the user after the journal entry with the id since
+async fn delta_sync<DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    user_id: i32,
+    remote_token_id: i32,
+    remote_token: &str,
+    since: i64,
+) -> Result<SyncResponse, StatusCode> {
+    // latest change per object, true if it was deleted
+    let mut changed: BTreeMap<String, BTreeMap<i32, bool>> = BTreeMap::new();
+    let mut cursor = since;
+    let mut read = 0;
+    let mut has_more = false;
+
+    // page through the journal, so huge deltas are not read at once
+    loop {
+        let page: Vec<SyncChange> = state
+            .db
+            .get_sync_changes(user_id, cursor, SYNC_PAGE_SIZE)
+            .await
+            .map_err(|_| {
+                error!("Failed to read sync journal! (user id: {})", user_id);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        let page_len = page.len();
+
+        for change in page {
+            cursor = change.id;
+            changed
+                .entry(change.used_for.db_identifier)
+                .or_default()
+                .insert(change.object_id, change.deleted);
+        }
+        read += page_len;
+
+        if (page_len as i64) < SYNC_PAGE_SIZE {
+            break;
+        }
+        if read >= MAX_CHANGES_PER_SYNC {
+            has_more = true;
+            break;
+        }
+    }
+
+    let empty = BTreeMap::new();
+    let changes_for = |ident: String| Some(changed.get(&ident).unwrap_or(&empty));
+
+    Ok(SyncResponse {
+        cursor: encode_cursor(cursor),
+        has_more,
+        course: load_delta::<CourseDB, CourseSend, DB>(
+            state,
+            user_id,
+            remote_token_id,
+            remote_token,
+            changes_for(CourseDB::get_db_ident().db_identifier),
+        )
+        .await?,
+        topic: load_delta::<TopicDB, TopicSend, DB>(
+            state,
+            user_id,
+            remote_token_id,
+            remote_token,
+            changes_for(TopicDB::get_db_ident().db_identifier),
+        )
+        .await?,
+        study_goal: load_delta::<StudyGoalDB, StudyGoalSend, DB>(
+            state,
+            user_id,
+            remote_token_id,
+            remote_token,
+            changes_for(StudyGoalDB::get_db_ident().db_identifier),
+        )
+        .await?,
+        exam: load_delta::<ExamDB, ExamSend, DB>(
+            state,
+            user_id,
+            remote_token_id,
+            remote_token,
+            changes_for(ExamDB::get_db_ident().db_identifier),
+        )
+        .await?,
+        todo: load_delta::<ToDoDB, ToDoSend, DB>(
+            state,
+            user_id,
+            remote_token_id,
+            remote_token,
+            changes_for(ToDoDB::get_db_ident().db_identifier),
+        )
+        .await?,
+    })
+}
+
+/// loads the changed objects of a type, every object if changes is None
+async fn load_delta<DBT: SQLGenerate, ST: FromDB<DBT>, DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    user_id: i32,
+    remote_token_id: i32,
+    remote_token: &str,
+    changes: Option<&BTreeMap<i32, bool>>,
+) -> Result<SyncDelta<ST>, StatusCode> {
+    let deleted = changes
+        .map(|changes| {
+            changes
+                .iter()
+                .filter(|(_, deleted)| **deleted)
+                .map(|(id, _)| *id)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // nothing to load, the local token is not needed
+    if changes.is_some_and(|changes| changes.values().all(|deleted| *deleted)) {
+        return Ok(SyncDelta {
+            updated: Vec::new(),
+            deleted,
+        });
+    }
+
+    let local_token =
+        local_token_for::<DBT, DB>(state, user_id, remote_token_id, remote_token).await?;
+
+    // select every object or only the changed ones, always restricted to the user
+    let selects: Vec<Vec<WhereParam>> = match changes {
+        None => vec![db_param_map! { user_id: user_id }],
+        Some(changes) => changes
+            .iter()
+            .filter(|(_, deleted)| !**deleted)
+            .map(|(id, _)| db_param_map! { user_id: user_id, id: *id })
+            .collect(),
+    };
+
+    let mut updated = Vec::new();
+    for where_params in selects {
+        let entries = state
+            .db
+            .select_entries::<DBT>(where_params, &[], None)
+            .await
+            .map_err(|_| {
+                error!(
+                    "Error while querying DB! Tried to sync {} information.",
+                    type_name::<DBT>()
+                );
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        // objects that were deleted after being edited are missing here, their tombstone follows
+        for entry in entries {
+            updated.push(
+                ST::from_dbt(&entry, local_token.as_bytes(), &state.crypt_provider).map_err(
+                    |_| {
+                        error!("Failed to convert database type to send type");
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    },
+                )?,
+            );
+        }
+    }
+
+    Ok(SyncDelta { updated, deleted })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::{Value, json};
+
+    use crate::{
+        data_handler::data_router,
+        test_util::{register_user, send_json, test_state},
+    };
+
+    /// ids of the objects in a delta
+    fn updated_ids(delta: &Value) -> Vec<i64> {
+        delta["updated"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|object| object["id"].as_i64().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn initial_sync_returns_everything() {
+        let state = test_state().await;
+        let token = register_user(&state, "sync_initial").await;
+        let router = data_router(state.clone()).await;
+
+        let (_, course) = send_json(
+            router.clone(),
+            Method::POST,
+            "/course",
+            &token,
+            Some(json!({ "id": null, "name": "Analysis" })),
+        )
+        .await;
+        let (_, todo) = send_json(
+            router.clone(),
+            Method::POST,
+            "/todo",
+            &token,
+            Some(json!({ "id": null, "name": "read", "deadline": "2025-12-01", "details": "", "completed": false })),
+        )
+        .await;
+
+        let (status, sync) = send_json(router, Method::GET, "/sync", &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            updated_ids(&sync["course"]),
+            vec![course["id"].as_i64().unwrap()]
+        );
+        assert_eq!(sync["course"]["updated"][0]["name"], "Analysis");
+        assert_eq!(
+            updated_ids(&sync["todo"]),
+            vec![todo["id"].as_i64().unwrap()]
+        );
+        assert!(updated_ids(&sync["topic"]).is_empty());
+        assert_eq!(sync["has_more"], false);
+        assert!(sync["cursor"].is_string());
+    }
+
+    #[tokio::test]
+    async fn incremental_sync_returns_edits_and_tombstones() {
+        let state = test_state().await;
+        let token = register_user(&state, "sync_incremental").await;
+        let router = data_router(state.clone()).await;
+
+        let (_, course) = send_json(
+            router.clone(),
+            Method::POST,
+            "/course",
+            &token,
+            Some(json!({ "id": null, "name": "Analysis" })),
+        )
+        .await;
+        let course_id = course["id"].as_i64().unwrap();
+        send_json(
+            router.clone(),
+            Method::POST,
+            "/course",
+            &token,
+            Some(json!({ "id": null, "name": "Algebra" })),
+        )
+        .await;
+        let (_, exam) = send_json(
+            router.clone(),
+            Method::POST,
+            "/exam",
+            &token,
+            Some(json!({ "id": null, "course_id": course_id, "name": "Klausur", "date": "2025-07-01" })),
+        )
+        .await;
+        let exam_id = exam["id"].as_i64().unwrap();
+
+        let (_, initial) = send_json(router.clone(), Method::GET, "/sync", &token, None).await;
+        let cursor = initial["cursor"].as_str().unwrap();
+
+        // one edit and one delete
+        send_json(
+            router.clone(),
+            Method::POST,
+            "/course",
+            &token,
+            Some(json!({ "id": course_id, "name": "Analysis I" })),
+        )
+        .await;
+        send_json(
+            router.clone(),
+            Method::DELETE,
+            "/exam",
+            &token,
+            Some(json!({ "id": exam_id })),
+        )
+        .await;
+
+        let (status, delta) = send_json(
+            router,
+            Method::GET,
+            &format!("/sync?since={}", cursor),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(updated_ids(&delta["course"]), vec![course_id]);
+        assert_eq!(delta["course"]["updated"][0]["name"], "Analysis I");
+        assert_eq!(delta["course"]["deleted"], json!([]));
+        assert!(updated_ids(&delta["exam"]).is_empty());
+        assert_eq!(delta["exam"]["deleted"], json!([exam_id]));
+        assert_ne!(delta["cursor"].as_str().unwrap(), cursor);
+    }
+
+    #[tokio::test]
+    async fn cursor_is_stable_without_changes() {
+        let state = test_state().await;
+        let token = register_user(&state, "sync_stable").await;
+        let router = data_router(state.clone()).await;
+
+        send_json(
+            router.clone(),
+            Method::POST,
+            "/course",
+            &token,
+            Some(json!({ "id": null, "name": "Analysis" })),
+        )
+        .await;
+
+        let (_, initial) = send_json(router.clone(), Method::GET, "/sync", &token, None).await;
+        let cursor = initial["cursor"].as_str().unwrap();
+
+        for _ in 0..2 {
+            let (status, delta) = send_json(
+                router.clone(),
+                Method::GET,
+                &format!("/sync?since={}", cursor),
+                &token,
+                None,
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(delta["cursor"].as_str().unwrap(), cursor);
+            assert!(updated_ids(&delta["course"]).is_empty());
+            assert_eq!(delta["course"]["deleted"], json!([]));
+        }
+
+        let (status, _) = send_json(router, Method::GET, "/sync?since=invalid", &token, None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,855 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{extract::State, http::StatusCode};
+use chrono::{Days, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState,
+    api_error::{ApiError, FieldError, Json, Query},
+    auth_handler::AuthenticatedUser,
+    db::{
+        DBInterface, DbError,
+        sql_helper::{SQLGenerate, WhereOp, WhereParam},
+    },
+    db_param_map,
+};
+
+use super::{
+    IDBody, LocalKey, Sendable, create_entry, get_entries,
+    history::load_object,
+    objects::{ToDoDB, ToDoPatch, ToDoSend},
+    patch_entry, payload_too_large, record_change, remove_entry, restore_deleted,
+    retention::soft_delete_cutoff,
+    save_entry,
+    stats::check_access,
+    update_fields,
+    webhook::WebhookAction,
+};
+
+/// number of days of the completed per day request, if the client does not send days
+const DEFAULT_COMPLETION_DAYS: u64 = 30;
+/// clients can request at most this many days
+const MAX_COMPLETION_DAYS: u64 = 365;
+
+/// response of a todo creation / edit
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SavedToDo {
+    pub id: i32,
+    /// id of the next occurrence, if the edit completed a recurring todo
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_id: Option<i32>,
+    /// id of the parent, if the edit completed its last open subtask and auto_complete_parent was sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed_parent_id: Option<i32>,
+}
+
+/// errors of a parent of the todo with the id (none for new todos) which exists, but can not be its parent
+/// subtasks are only one level deep, so neither subtasks nor todos with subtasks can become parents or subtasks
+pub async fn parent_errors<DB: DBInterface + Send + Sync>(
+    db: &DB,
+    user_id: i32,
+    id: Option<i32>,
+    parent_todo_id: Option<i32>,
+) -> Result<Vec<FieldError>, DbError> {
+    let Some(parent_todo_id) = parent_todo_id else {
+        return Ok(Vec::new());
+    };
+    let error = |reason: &str| {
+        Ok(vec![FieldError {
+            field: "parent_todo_id".to_string(),
+            message: reason.to_string(),
+        }])
+    };
+
+    if id == Some(parent_todo_id) {
+        return error("is the todo itself");
+    }
+    let parent = db
+        .select_entries::<ToDoDB>(
+            db_param_map! { id: parent_todo_id, user_id: user_id },
+            &[],
+            None,
+        )
+        .await?;
+    if parent.iter().any(|parent| parent.parent_todo_id.is_some()) {
+        return error("is a subtask");
+    }
+    if let Some(id) = id
+        && db
+            .count_entries::<ToDoDB>(db_param_map! { parent_todo_id: id, user_id: user_id })
+            .await?
+            > 0
+    {
+        return error("can not be set on a todo with subtasks");
+    }
+    Ok(Vec::new())
+}
+
+/// ids of the subtasks of the todo
+async fn subtask_ids<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    id: i32,
+) -> Result<Vec<i32>, StatusCode> {
+    let subtasks = state
+        .db
+        .select_entries::<ToDoDB>(
+            db_param_map! { parent_todo_id: id, user_id: user_id },
+            &[],
+            None,
+        )
+        .await?;
+    Ok(subtasks.into_iter().map(|subtask| subtask.id).collect())
+}
+
+/// completion state of a stored todo
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Completion {
+    pub completed: bool,
+    /// none for open todos and todos completed before the time was recorded
+    pub completed_at: Option<NaiveDateTime>,
+}
+
+/// completion state of the todo with the id before an edit, open for new and unknown todos
+pub async fn stored_completion<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    id: Option<i32>,
+) -> Result<Completion, StatusCode> {
+    let Some(id) = id else {
+        return Ok(Completion::default());
+    };
+    let todo = load_object::<ToDoDB, DB>(state, user_id, id).await?;
+    Ok(todo.map_or_else(Completion::default, |todo| Completion {
+        completed: todo.completed,
+        completed_at: todo.completed_at,
+    }))
+}
+
+/// creates the next occurrence of the todo with the id, if it is recurring and was completed by the last edit
+/// the occurrence is a copy of the todo, open and due after the deadline, none after recurrence_until
+pub async fn next_occurrence<DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    key: &LocalKey<ToDoDB>,
+    id: i32,
+    completed_before: bool,
+) -> Result<Option<i32>, ApiError> {
+    if completed_before {
+        return Ok(None);
+    }
+    let todos: Vec<ToDoSend> = get_entries(state, key, db_param_map! { id: id }, &[], None).await?;
+    let Some(todo) = todos.into_iter().next().filter(|todo| todo.completed) else {
+        return Ok(None);
+    };
+    let Some(deadline) = todo
+        .recurrence
+        .and_then(|recurrence| recurrence.next(todo.deadline))
+        .filter(|deadline| todo.recurrence_until.is_none_or(|until| *deadline <= until))
+    else {
+        return Ok(None);
+    };
+
+    info!("Recurring todo completed, creating the next occurrence.");
+    let next = ToDoSend {
+        deadline,
+        completed: false,
+        completed_at: None,
+        ..todo.strip_id()
+    };
+    create_entry::<ToDoDB, ToDoSend, DB>(state, key, next)
+        .await
+        .map(Some)
+}
+
+/// handler for creating and editing todos, completing a recurring todo creates its next occurrence
+/// completed_at is set when the todo is completed and kept until it is opened again
+pub async fn handle_save_todo<DB: DBInterface + Send + Sync>(
+    key: LocalKey<ToDoDB>,
+    State(state): State<Arc<AppState<DB>>>,
+    Json(mut request): Json<ToDoSend>,
+) -> Result<Json<SavedToDo>, ApiError> {
+    info!("ToDoDB creation / edit requested!");
+
+    state
+        .payload_limits
+        .check(&request)
+        .map_err(payload_too_large)?;
+    let before = stored_completion(&state, key.user.user_id, request.get_id()).await?;
+    request.completed_at = before.completed_at;
+    let id = save_entry::<ToDoDB, ToDoSend, DB>(&state, &key, request).await?;
+    let next_id = next_occurrence(&state, &key, id, before.completed).await?;
+
+    Ok(Json(SavedToDo {
+        id,
+        next_id,
+        completed_parent_id: None,
+    }))
+}
+
+/// handler for partial edits of todos, like handle_save_todo for the next occurrence
+/// with auto_complete_parent, completing the last open subtask of a todo completes the todo as well
+pub async fn handle_patch_todo<DB: DBInterface + Send + Sync>(
+    key: LocalKey<ToDoDB>,
+    State(state): State<Arc<AppState<DB>>>,
+    Json(mut request): Json<ToDoPatch>,
+) -> Result<Json<SavedToDo>, ApiError> {
+    info!("ToDoDB partial edit requested!");
+
+    let auto_complete_parent = request.auto_complete_parent;
+    let completed = stored_completion(&state, key.user.user_id, Some(request.id))
+        .await?
+        .completed;
+    request.completed_at = match request.completed {
+        Some(true) if !completed => Some(Some(Utc::now().naive_utc())),
+        Some(false) => Some(None),
+        _ => None,
+    };
+    let id = patch_entry::<ToDoDB, ToDoPatch, DB>(&state, &key, request).await?;
+    let next_id = next_occurrence(&state, &key, id, completed).await?;
+    let completed_parent_id = if auto_complete_parent && !completed {
+        complete_parent(&state, &key, id).await?
+    } else {
+        None
+    };
+
+    Ok(Json(SavedToDo {
+        id,
+        next_id,
+        completed_parent_id,
+    }))
+}
+
+/// completes the parent of the subtask with the id, if the subtask and all other subtasks of the parent are completed
+/// returns the id of the parent if it was completed, recurring parents get their next occurrence like on an edit
+async fn complete_parent<DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    key: &LocalKey<ToDoDB>,
+    id: i32,
+) -> Result<Option<i32>, ApiError> {
+    let user_id = key.user.user_id;
+    let load = |id| load_object::<ToDoDB, DB>(state, user_id, id);
+    let Some(parent_id) = load(id)
+        .await?
+        .filter(|subtask| subtask.completed)
+        .and_then(|subtask| subtask.parent_todo_id)
+    else {
+        return Ok(None);
+    };
+    let open_subtasks = state
+        .db
+        .count_entries::<ToDoDB>(
+            db_param_map! { parent_todo_id: parent_id, user_id: user_id, completed: false },
+        )
+        .await?;
+    let parent = load(parent_id).await?;
+    if open_subtasks > 0 || parent.is_none_or(|parent| parent.completed) {
+        return Ok(None);
+    }
+
+    info!("Last open subtask completed, completing its parent.");
+    let params = db_param_map! { completed: true, completed_at: Utc::now().naive_utc() };
+    update_fields::<ToDoDB, DB>(state, key, parent_id, params).await?;
+    next_occurrence(state, key, parent_id, false).await?;
+    Ok(Some(parent_id))
+}
+
+/// query parameters of the completed per day request
+#[derive(Deserialize, Debug)]
+pub struct CompletedPerDayQuery {
+    days: Option<u64>,
+}
+
+/// handler returning the number of todos completed on each of the last days (today included, UTC), ordered by date
+/// days without completions are 0, only ids and times are needed, so no local token is decrypted
+pub(super) async fn handle_completed_per_day<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Query(query): Query<CompletedPerDayQuery>,
+) -> Result<Json<BTreeMap<NaiveDate, usize>>, ApiError> {
+    info!("Completed todos per day requested!");
+
+    check_access::<ToDoDB, DB>(&state, &user).await?;
+    let days = query
+        .days
+        .unwrap_or(DEFAULT_COMPLETION_DAYS)
+        .min(MAX_COMPLETION_DAYS);
+    let first_day = Utc::now().date_naive() - Days::new(days.saturating_sub(1));
+    let mut per_day: BTreeMap<NaiveDate, usize> = first_day
+        .iter_days()
+        .take(days as usize)
+        .map(|day| (day, 0))
+        .collect();
+
+    let mut params = db_param_map! { user_id: user.user_id };
+    params.push(WhereParam::new(
+        "completed_at",
+        WhereOp::Ge,
+        first_day.and_time(NaiveTime::MIN),
+    ));
+    let todos = state
+        .db
+        .select_entries::<ToDoDB>(params, &[], None)
+        .await
+        .map_err(|err| {
+            error!(
+                "Error while counting the completed todos of user {}!",
+                user.user_id
+            );
+            StatusCode::from(err)
+        })?;
+    for completed_at in todos.iter().filter_map(|todo| todo.completed_at) {
+        if let Some(count) = per_day.get_mut(&completed_at.date()) {
+            *count += 1;
+        }
+    }
+
+    info!("Completed todos per day successful, building response!");
+    Ok(Json(per_day))
+}
+
+/// handler for deleting todos, the subtasks of a todo are moved into the trash with it
+pub(super) async fn handle_delete_todo<DB: DBInterface + Send + Sync>(
+    key: LocalKey<ToDoDB>,
+    State(state): State<Arc<AppState<DB>>>,
+    Json(request): Json<IDBody>,
+) -> Result<Json<IDBody>, ApiError> {
+    info!("ToDoDB deletion requested!");
+
+    remove_todo(&state, &key, request.id).await?;
+
+    Ok(Json(IDBody { id: request.id }))
+}
+
+/// deletes the todo with the id and its subtasks like remove_entry, if it belongs to the user
+pub async fn remove_todo<DB: DBInterface + Send + Sync>(
+    state: &Arc<AppState<DB>>,
+    key: &LocalKey<ToDoDB>,
+    id: i32,
+) -> Result<(), StatusCode> {
+    remove_entry::<ToDoDB, DB>(state, key, id).await?;
+    let subtasks = subtask_ids(state, key.user.user_id, id).await?;
+    if !subtasks.is_empty() {
+        info!("Deleting {} subtasks with their todo.", subtasks.len());
+    }
+    for subtask in subtasks {
+        remove_entry::<ToDoDB, DB>(state, key, subtask).await?;
+    }
+    Ok(())
+}
+
+/// handler for restoring deleted todos within the grace period
+/// the subtasks in the trash are restored with their todo, restoring a subtask restores its deleted todo as well
+pub(super) async fn handle_restore_todo<DB: DBInterface + Send + Sync>(
+    key: LocalKey<ToDoDB>,
+    State(state): State<Arc<AppState<DB>>>,
+    Json(request): Json<IDBody>,
+) -> Result<Json<IDBody>, ApiError> {
+    info!("ToDoDB restore requested!");
+
+    let user_id = key.user.user_id;
+    restore_deleted::<ToDoDB, DB>(&state, user_id, request.id).await?;
+    let todo = load_object::<ToDoDB, DB>(&state, user_id, request.id)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let parent_id = match todo.parent_todo_id {
+        None => request.id,
+        Some(parent_id)
+            if load_object::<ToDoDB, DB>(&state, user_id, parent_id)
+                .await?
+                .is_none() =>
+        {
+            restore_deleted::<ToDoDB, DB>(&state, user_id, parent_id).await?;
+            parent_id
+        }
+        Some(_) => return Ok(Json(IDBody { id: request.id })),
+    };
+
+    // the restored subtasks are the ones that were not there before
+    let before = subtask_ids(&state, user_id, parent_id).await?;
+    let cutoff = soft_delete_cutoff(Utc::now().naive_utc()).ok_or(StatusCode::NOT_FOUND)?;
+    state
+        .db
+        .restore_entry::<ToDoDB>(
+            db_param_map! { parent_todo_id: parent_id, user_id: user_id },
+            &cutoff,
+        )
+        .await?;
+    for subtask in subtask_ids(&state, user_id, parent_id).await? {
+        if !before.contains(&subtask) {
+            record_change(
+                &state,
+                user_id,
+                &ToDoDB::get_db_ident(),
+                WebhookAction::Create,
+                subtask,
+            )
+            .await;
+        }
+    }
+
+    Ok(Json(IDBody { id: request.id }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use chrono::Utc;
+    use serde_json::{Value, json};
+
+    use crate::{
+        data_handler::data_router,
+        test_util::{register_user, send_json, test_state},
+    };
+
+    #[tokio::test]
+    async fn completing_a_recurring_todo_creates_the_next_occurrence() {
+        let state = test_state().await;
+        let token = register_user(&state, "recurring_user").await;
+        let router = data_router(state.clone()).await;
+
+        let (status, saved) = send_json(
+            router.clone(),
+            Method::POST,
+            "/todo",
+            &token,
+            Some(
+                json!({ "id": null, "name": "Rent", "deadline": "2025-01-31", "details": "flat",
+                "recurrence": "monthly", "recurrence_until": "2025-04-15" }),
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(saved, json!({ "id": saved["id"] }));
+        let rent = saved["id"].clone();
+
+        // completing with an edit
+        let (_, saved) = send_json(
+            router.clone(),
+            Method::POST,
+            "/todo",
+            &token,
+            Some(
+                json!({ "id": rent, "name": "Rent", "deadline": "2025-01-31", "details": "flat",
+                "completed": true, "recurrence": "monthly", "recurrence_until": "2025-04-15" }),
+            ),
+        )
+        .await;
+        assert_eq!(saved["id"], rent);
+        let february = saved["next_id"].clone();
+        let (_, todos) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!("/todo?id={}", february),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(todos[0]["name"], "Rent");
+        assert_eq!(todos[0]["details"], "flat");
+        assert_eq!(todos[0]["deadline"], "2025-02-28");
+        assert_eq!(todos[0]["completed"], false);
+        assert_eq!(todos[0]["recurrence"], "monthly");
+
+        // completing with a partial edit, editing a completed todo again creates nothing
+        let (status, saved) = send_json(
+            router.clone(),
+            Method::PATCH,
+            "/todo",
+            &token,
+            Some(json!({ "id": february, "completed": true })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(saved["next_id"].is_i64());
+        let (_, saved) = send_json(
+            router.clone(),
+            Method::PATCH,
+            "/todo",
+            &token,
+            Some(json!({ "id": february, "details": "flat and garage" })),
+        )
+        .await;
+        assert_eq!(saved, json!({ "id": february }));
+
+        // the occurrence after March 28 would be due after recurrence_until (April 15)
+        let (_, saved) = send_json(
+            router.clone(),
+            Method::PATCH,
+            "/todo",
+            &token,
+            Some(json!({ "id": saved_next(&router, &token).await, "completed": true })),
+        )
+        .await;
+        assert!(saved.get("next_id").is_none());
+        let (_, todos) = send_json(router.clone(), Method::GET, "/todo", &token, None).await;
+        let deadlines: Vec<&Value> = todos
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|todo| &todo["deadline"])
+            .collect();
+        assert_eq!(deadlines, ["2025-01-31", "2025-02-28", "2025-03-28"]);
+
+        // todos without recurrence stay as they are
+        let (_, saved) = send_json(
+            router.clone(),
+            Method::POST,
+            "/todo",
+            &token,
+            Some(json!({ "id": null, "name": "Taxes", "deadline": "2025-05-31", "details": "" })),
+        )
+        .await;
+        let (_, saved) = send_json(
+            router,
+            Method::PATCH,
+            "/todo",
+            &token,
+            Some(json!({ "id": saved["id"], "completed": true })),
+        )
+        .await;
+        assert!(saved.get("next_id").is_none());
+    }
+
+    #[tokio::test]
+    async fn todos_are_filtered_and_sorted_by_a_validated_priority() {
+        let state = test_state().await;
+        let token = register_user(&state, "priority_user").await;
+        let router = data_router(state.clone()).await;
+
+        let mut ids = Vec::new();
+        for (name, priority) in [("Dishes", None), ("Thesis", Some(3)), ("Mail", Some(1))] {
+            let mut body =
+                json!({ "id": null, "name": name, "deadline": "2025-07-01", "details": "" });
+            if let Some(priority) = priority {
+                body["priority"] = json!(priority);
+            }
+            let (status, saved) =
+                send_json(router.clone(), Method::POST, "/todo", &token, Some(body)).await;
+            assert_eq!(status, StatusCode::OK);
+            ids.push(saved["id"].clone());
+        }
+
+        let (_, todos) = send_json(
+            router.clone(),
+            Method::GET,
+            "/todo?sort=priority&dir=desc",
+            &token,
+            None,
+        )
+        .await;
+        let priorities: Vec<(&Value, &Value)> = todos
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|todo| (&todo["name"], &todo["priority"]))
+            .collect();
+        assert_eq!(
+            priorities,
+            [
+                (&json!("Thesis"), &json!(3)),
+                (&json!("Mail"), &json!(1)),
+                (&json!("Dishes"), &json!(0))
+            ]
+        );
+        let (_, todos) = send_json(
+            router.clone(),
+            Method::GET,
+            "/todo?priority=1",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(todos.as_array().unwrap().len(), 1);
+        assert_eq!(todos[0]["id"], ids[2]);
+
+        let out_of_range = json!([{ "field": "priority", "message": "is out of range" }]);
+        let (status, errors) = send_json(
+            router.clone(),
+            Method::POST,
+            "/todo",
+            &token,
+            Some(json!({ "id": null, "name": "Urgent", "deadline": "2025-07-01", "details": "", "priority": 4 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(errors["field_errors"], out_of_range);
+        let (status, errors) = send_json(
+            router.clone(),
+            Method::PATCH,
+            "/todo",
+            &token,
+            Some(json!({ "id": ids[0], "priority": -1 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(errors["field_errors"], out_of_range);
+        let (_, count) =
+            send_json(router, Method::GET, "/todo/count?priority=0", &token, None).await;
+        assert_eq!(count, json!({ "count": 1 }));
+    }
+
+    #[tokio::test]
+    async fn subtasks_are_one_level_deep_and_follow_their_todo() {
+        let state = test_state().await;
+        let token = register_user(&state, "subtask_user").await;
+        let router = data_router(state.clone()).await;
+        let new_todo = |name: &str, parent: &Value| json!({ "id": null, "name": name, "deadline": "2025-07-01", "details": "", "parent_todo_id": parent });
+        // the subtasks belong to the first todo
+        let mut ids: Vec<Value> = Vec::new();
+        for (name, is_subtask) in [
+            ("Thesis", false),
+            ("Outline", true),
+            ("Sources", true),
+            ("Holiday", false),
+        ] {
+            let parent = if is_subtask {
+                ids[0].clone()
+            } else {
+                Value::Null
+            };
+            let (status, saved) = send_json(
+                router.clone(),
+                Method::POST,
+                "/todo",
+                &token,
+                Some(new_todo(name, &parent)),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            ids.push(saved["id"].clone());
+        }
+        let (_, subtasks) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!("/todo?parent_todo_id={}", ids[0]),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(subtasks.as_array().unwrap().len(), 2);
+
+        // no subtasks of subtasks, of themselves or of unknown todos
+        let (status, errors) = send_json(
+            router.clone(),
+            Method::POST,
+            "/todo",
+            &token,
+            Some(new_todo("Chapter 1", &ids[1])),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            errors["field_errors"],
+            json!([{ "field": "parent_todo_id", "message": "is a subtask" }])
+        );
+        for (id, parent, reason) in [
+            (&ids[0], &ids[0], "is the todo itself"),
+            (&ids[0], &ids[3], "can not be set on a todo with subtasks"),
+            (&ids[3], &json!(9999), "does not exist"),
+        ] {
+            let (status, errors) = send_json(
+                router.clone(),
+                Method::PATCH,
+                "/todo",
+                &token,
+                Some(json!({ "id": id, "parent_todo_id": parent })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+            assert_eq!(
+                errors["field_errors"],
+                json!([{ "field": "parent_todo_id", "message": reason }])
+            );
+        }
+
+        // the todo is completed with its last open subtask
+        let complete =
+            |id: &Value| json!({ "id": id, "completed": true, "auto_complete_parent": true });
+        let (_, saved) = send_json(
+            router.clone(),
+            Method::PATCH,
+            "/todo",
+            &token,
+            Some(complete(&ids[1])),
+        )
+        .await;
+        assert_eq!(saved, json!({ "id": ids[1] }));
+        let (_, saved) = send_json(
+            router.clone(),
+            Method::PATCH,
+            "/todo",
+            &token,
+            Some(complete(&ids[2])),
+        )
+        .await;
+        assert_eq!(
+            saved,
+            json!({ "id": ids[2], "completed_parent_id": ids[0] })
+        );
+        let (_, todos) = send_json(
+            router.clone(),
+            Method::GET,
+            &format!("/todo?id={}", ids[0]),
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(todos[0]["completed"], true);
+
+        // subtasks are deleted with their todo and restored with it
+        let (status, _) = send_json(
+            router.clone(),
+            Method::DELETE,
+            "/todo",
+            &token,
+            Some(json!({ "id": ids[0] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, todos) = send_json(router.clone(), Method::GET, "/todo", &token, None).await;
+        assert_eq!(todos.as_array().unwrap().len(), 1);
+        let (status, _) = send_json(
+            router.clone(),
+            Method::POST,
+            "/todo/restore",
+            &token,
+            Some(json!({ "id": ids[1] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, todos) = send_json(router, Method::GET, "/todo", &token, None).await;
+        assert_eq!(todos.as_array().unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn completion_times_are_set_by_the_server() {
+        let state = test_state().await;
+        let token = register_user(&state, "completion_user").await;
+        let router = data_router(state.clone()).await;
+        let today = Utc::now().date_naive().to_string();
+        let todo = |id: &Value, name: &str, completed: bool| {
+            json!({ "id": id, "name": name, "deadline": "2025-07-01", "details": "",
+                "completed": completed, "completed_at": "2000-01-01T00:00:00" })
+        };
+        let load = async |id: &Value| {
+            let (_, todos) = send_json(
+                router.clone(),
+                Method::GET,
+                &format!("/todo?id={}", id),
+                &token,
+                None,
+            )
+            .await;
+            todos[0]["completed_at"].clone()
+        };
+
+        let (_, saved) = send_json(
+            router.clone(),
+            Method::POST,
+            "/todo",
+            &token,
+            Some(todo(&Value::Null, "Laundry", false)),
+        )
+        .await;
+        let id = saved["id"].clone();
+        assert_eq!(load(&id).await, Value::Null);
+
+        // the time sent by the client is ignored, edits of a completed todo keep it
+        send_json(
+            router.clone(),
+            Method::POST,
+            "/todo",
+            &token,
+            Some(todo(&id, "Laundry", true)),
+        )
+        .await;
+        let completed_at = load(&id).await;
+        assert!(completed_at.as_str().unwrap().starts_with(&today));
+        send_json(
+            router.clone(),
+            Method::POST,
+            "/todo",
+            &token,
+            Some(todo(&id, "Laundry, whites", true)),
+        )
+        .await;
+        assert_eq!(load(&id).await, completed_at);
+
+        // opening the todo again unsets it, completing it again sets it again
+        let patch = |completed: bool| json!({ "id": id, "completed": completed });
+        send_json(
+            router.clone(),
+            Method::PATCH,
+            "/todo",
+            &token,
+            Some(patch(false)),
+        )
+        .await;
+        assert_eq!(load(&id).await, Value::Null);
+        send_json(
+            router.clone(),
+            Method::PATCH,
+            "/todo",
+            &token,
+            Some(patch(true)),
+        )
+        .await;
+        let completed_again = load(&id).await;
+        assert!(completed_again.as_str().unwrap().starts_with(&today));
+        assert!(completed_again.as_str() >= completed_at.as_str());
+        send_json(
+            router.clone(),
+            Method::PATCH,
+            "/todo",
+            &token,
+            Some(patch(true)),
+        )
+        .await;
+        assert_eq!(load(&id).await, completed_again);
+
+        // completed bulk creates count as well
+        send_json(
+            router.clone(),
+            Method::POST,
+            "/todo/bulk",
+            &token,
+            Some(json!([
+                todo(&Value::Null, "Dishes", true),
+                todo(&Value::Null, "Taxes", false)
+            ])),
+        )
+        .await;
+        let (status, per_day) = send_json(
+            router.clone(),
+            Method::GET,
+            "/todo/completed-per-day?days=7",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let per_day = per_day.as_object().unwrap();
+        assert_eq!(per_day.len(), 7);
+        assert_eq!(per_day[&today], 2);
+        assert_eq!(per_day.values().filter(|count| **count == 0).count(), 6);
+        let (_, per_day) =
+            send_json(router, Method::GET, "/todo/completed-per-day", &token, None).await;
+        assert_eq!(per_day.as_object().unwrap().len(), 30);
+    }
+
+    /// id of the only open todo
+    async fn saved_next(router: &axum::Router, token: &str) -> Value {
+        let (_, todos) = send_json(
+            router.clone(),
+            Method::GET,
+            "/todo?completed=false",
+            token,
+            None,
+        )
+        .await;
+        assert_eq!(todos.as_array().unwrap().len(), 1);
+        todos[0]["id"].clone()
+    }
+}
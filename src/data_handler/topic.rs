@@ -0,0 +1,315 @@
+use std::{collections::HashSet, sync::Arc};
+
+use axum::extract::State;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState,
+    api_error::{ApiError, FieldError, Json},
+    auth_handler::AuthenticatedUser,
+    db::{DBInterface, DbError, sql_helper::SQLGenerate},
+    db_param_map,
+};
+
+use super::{
+    missing_reference,
+    objects::{CourseDB, TopicDB},
+    record_change,
+    stats::check_access,
+    webhook::WebhookAction,
+};
+
+/// body of the reorder request, every topic of the course in the new order, also used as response
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ReorderBody {
+    course_id: i32,
+    ordered_ids: Vec<i32>,
+}
+
+/// field errors of a reorder request, ordered_ids has to contain every topic of the course exactly once
+async fn reorder_errors<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    request: &ReorderBody,
+) -> Result<Vec<FieldError>, DbError> {
+    let error = |field: String, reason: &str| FieldError {
+        field,
+        message: reason.to_string(),
+    };
+    if let Some(field) =
+        missing_reference::<CourseDB, DB>(&state.db, user_id, "course_id", Some(request.course_id))
+            .await?
+    {
+        return Ok(vec![error(field.to_string(), "does not exist")]);
+    }
+
+    let mut topics: HashSet<i32> = state
+        .db
+        .select_entries::<TopicDB>(
+            db_param_map! { user_id: user_id, course_id: request.course_id },
+            &[],
+            None,
+        )
+        .await?
+        .iter()
+        .map(|topic| topic.id)
+        .collect();
+    // every id removes its topic, so the remaining topics are missing in the request
+    let mut seen = HashSet::new();
+    let mut errors: Vec<FieldError> = request
+        .ordered_ids
+        .iter()
+        .enumerate()
+        .filter_map(|(i, id)| {
+            let reason = match (seen.insert(*id), topics.remove(id)) {
+                (_, true) => return None,
+                (false, false) => "is a duplicate",
+                (true, false) => "is not a topic of the course",
+            };
+            Some(error(format!("ordered_ids.{}", i), reason))
+        })
+        .collect();
+    if !topics.is_empty() {
+        errors.push(error(
+            "ordered_ids".to_string(),
+            "does not contain every topic of the course",
+        ));
+    }
+    Ok(errors)
+}
+
+/// handler for reordering the topics of a course, they get the positions 0, 1, ... in the order of the request
+/// the positions are written in one transaction, only ids are read so no local token is decrypted
+pub async fn handle_reorder_topics<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Json(request): Json<ReorderBody>,
+) -> Result<Json<ReorderBody>, ApiError> {
+    info!("Topic reorder requested!");
+
+    check_access::<TopicDB, DB>(&state, &user).await?;
+    let user_id = user.user_id;
+    let query_failed = |err: DbError| {
+        error!("Error while reordering topics! (user id: {})", user_id);
+        ApiError::from(err)
+    };
+
+    let errors = reorder_errors(&state, user_id, &request)
+        .await
+        .map_err(query_failed)?;
+    if !errors.is_empty() {
+        warn!("Invalid topic reorder rejected! (user id: {})", user_id);
+        return Err(ApiError::unprocessable(errors));
+    }
+
+    let ordered_ids = request.ordered_ids.clone();
+    state
+        .db
+        .with_transaction(move |db| {
+            Box::pin(async move {
+                for (position, id) in ordered_ids.into_iter().enumerate() {
+                    db.update_entry::<TopicDB>(
+                        db_param_map! { position: position as i32 },
+                        db_param_map! { id: id, user_id: user_id },
+                    )
+                    .await?;
+                }
+                Ok(())
+            })
+        })
+        .await
+        .map_err(query_failed)?;
+    for id in request.ordered_ids.iter() {
+        record_change(
+            &state,
+            user_id,
+            &TopicDB::get_db_ident(),
+            WebhookAction::Edit,
+            *id,
+        )
+        .await;
+    }
+
+    info!("Topic reorder successful.");
+    Ok(Json(request))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        http::{Method, StatusCode},
+    };
+    use serde_json::{Value, json};
+
+    use crate::{
+        data_handler::data_router,
+        test_util::{create_course, register_user, send_json, test_state},
+    };
+
+    /// creates a topic in the course, returns its id
+    async fn create_topic(router: Router, token: &str, course_id: i64, name: &str) -> i64 {
+        let (status, topic) = send_json(
+            router,
+            Method::POST,
+            "/topic",
+            token,
+            Some(json!({ "id": null, "course_id": course_id, "name": name, "details": "" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        topic["id"].as_i64().unwrap()
+    }
+
+    /// returns the ids and positions of the topics of the course, in the order of the response
+    async fn topic_order(router: Router, token: &str, course_id: i64) -> Vec<(i64, i64)> {
+        let (status, topics) = send_json(
+            router,
+            Method::GET,
+            &format!("/topic?course_id={}", course_id),
+            token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        topics
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|topic| {
+                (
+                    topic["id"].as_i64().unwrap(),
+                    topic["position"].as_i64().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn topics_are_reordered_within_their_course() {
+        let state = test_state().await;
+        let token = register_user(&state, "reorder_user").await;
+        let other = register_user(&state, "reorder_other").await;
+        let router = data_router(state.clone()).await;
+        let course_id = create_course(router.clone(), &token).await;
+        let other_course_id = create_course(router.clone(), &token).await;
+        let foreign_course_id = create_course(router.clone(), &other).await;
+
+        let mut topics = Vec::new();
+        for name in ["Limits", "Series", "Integrals"] {
+            topics.push(create_topic(router.clone(), &token, course_id, name).await);
+        }
+        let other_topic = create_topic(router.clone(), &token, other_course_id, "Groups").await;
+        let foreign_topic = create_topic(router.clone(), &other, foreign_course_id, "Other").await;
+        assert_eq!(
+            topic_order(router.clone(), &token, course_id).await,
+            [(topics[0], 0), (topics[1], 1), (topics[2], 2)]
+        );
+        assert_eq!(
+            topic_order(router.clone(), &token, other_course_id).await,
+            [(other_topic, 0)]
+        );
+
+        let reorder = |body: Value| {
+            send_json(
+                router.clone(),
+                Method::POST,
+                "/topic/reorder",
+                &token,
+                Some(body),
+            )
+        };
+        let (status, body) = reorder(
+            json!({ "course_id": course_id, "ordered_ids": [topics[2], topics[0], topics[1]] }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["ordered_ids"],
+            json!([topics[2], topics[0], topics[1]])
+        );
+        assert_eq!(
+            topic_order(router.clone(), &token, course_id).await,
+            [(topics[2], 0), (topics[0], 1), (topics[1], 2)]
+        );
+
+        // new topics are placed last, edits keep the position
+        let derivatives = create_topic(router.clone(), &token, course_id, "Derivatives").await;
+        let (status, _) = send_json(
+            router.clone(),
+            Method::POST,
+            "/topic",
+            &token,
+            Some(json!({ "id": topics[2], "course_id": course_id, "name": "Integrals", "details": "", "position": 7 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let order = [
+            (topics[2], 0),
+            (topics[0], 1),
+            (topics[1], 2),
+            (derivatives, 3),
+        ];
+        assert_eq!(topic_order(router.clone(), &token, course_id).await, order);
+
+        // missing, repeated, foreign topics and topics of other courses change nothing
+        let not_in_course = "is not a topic of the course";
+        for (ordered_ids, field, reason) in [
+            (
+                json!([topics[0], topics[1], topics[2]]),
+                "ordered_ids",
+                "does not contain every topic of the course",
+            ),
+            (
+                json!([topics[0], topics[0], topics[1], topics[2], derivatives]),
+                "ordered_ids.1",
+                "is a duplicate",
+            ),
+            (
+                json!([topics[0], topics[1], topics[2], derivatives, foreign_topic]),
+                "ordered_ids.4",
+                not_in_course,
+            ),
+            (
+                json!([other_topic, topics[0], topics[1], topics[2], derivatives]),
+                "ordered_ids.0",
+                not_in_course,
+            ),
+        ] {
+            let (status, body) =
+                reorder(json!({ "course_id": course_id, "ordered_ids": ordered_ids })).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+            assert_eq!(
+                body["field_errors"],
+                json!([{ "field": field, "message": reason }])
+            );
+        }
+        let (status, body) =
+            reorder(json!({ "course_id": foreign_course_id, "ordered_ids": [foreign_topic] }))
+                .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            body["field_errors"],
+            json!([{ "field": "course_id", "message": "does not exist" }])
+        );
+        assert_eq!(topic_order(router.clone(), &token, course_id).await, order);
+        assert_eq!(
+            topic_order(router.clone(), &other, foreign_course_id).await,
+            [(foreign_topic, 0)]
+        );
+
+        // without a filter the topics are ordered by course first
+        let (_, all) = send_json(router, Method::GET, "/topic", &token, None).await;
+        let ids: Vec<i64> = all
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|topic| topic["id"].as_i64().unwrap())
+            .collect();
+        assert_eq!(
+            ids,
+            [topics[2], topics[0], topics[1], derivatives, other_topic]
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use std::{cmp::Reverse, collections::HashMap, sync::Arc};
+
+use axum::extract::State;
+use chrono::{Days, NaiveDate, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState,
+    api_error::{ApiError, Json, Query},
+    auth_handler::AuthenticatedUser,
+    db::{
+        DBInterface,
+        sql_helper::{WhereOp, WhereParam},
+    },
+    db_param_map,
+};
+
+use super::{
+    LocalKey, get_entries,
+    objects::{
+        CourseDB, CourseSend, ExamDB, ExamSend, StudyGoalDB, StudyGoalSend, ToDoDB, ToDoSend,
+    },
+    study_goal::with_topic_ids,
+};
+
+/// number of days looked ahead, if the client does not send days
+const DEFAULT_DAYS: u64 = 7;
+/// clients can look ahead at most this many days
+const MAX_DAYS: u64 = 365;
+
+/// query parameters of the upcoming request
+#[derive(Deserialize, Debug)]
+pub struct UpcomingQuery {
+    days: Option<u64>,
+}
+
+/// something due soon, tagged with its type
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum UpcomingItem {
+    Exam {
+        id: i32,
+        date: NaiveDate,
+        name: String,
+        course_id: i32,
+        /// none if the course could not be found
+        course_name: Option<String>,
+    },
+    StudyGoal {
+        id: i32,
+        /// deadline of the goal
+        date: NaiveDate,
+        topic_ids: Vec<i32>,
+    },
+    Todo {
+        id: i32,
+        /// deadline of the todo
+        date: NaiveDate,
+        name: String,
+        /// 0 none, 1 low, 2 medium, 3 high
+        priority: i32,
+    },
+}
+
+impl UpcomingItem {
+    /// items are sorted by date, on the same day exams come first, todos by descending priority
+    fn sort_key(&self) -> (NaiveDate, u8, Reverse<i32>, i32) {
+        match self {
+            Self::Exam { id, date, .. } => (*date, 0, Reverse(0), *id),
+            Self::StudyGoal { id, date, .. } => (*date, 1, Reverse(0), *id),
+            Self::Todo {
+                id, date, priority, ..
+            } => (*date, 2, Reverse(*priority), *id),
+        }
+    }
+}
+
+/// where params selecting the dates of the field from today to the last day (both included)
+fn due_between(field: &str, today: NaiveDate, last_day: NaiveDate) -> Vec<WhereParam> {
+    vec![
+        WhereParam::new(field, WhereOp::Ge, today),
+        WhereParam::new(field, WhereOp::Le, last_day),
+    ]
+}
+
+/// handler returning the exams, study goals and open todos due in the next days, sorted by date
+pub async fn handle_upcoming<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Query(query): Query<UpcomingQuery>,
+) -> Result<Json<Vec<UpcomingItem>>, ApiError> {
+    info!("Upcoming deadlines requested!");
+
+    let days = query.days.unwrap_or(DEFAULT_DAYS).min(MAX_DAYS);
+    let today = Utc::now().date_naive();
+    let last_day = today + Days::new(days);
+
+    let exams: Vec<ExamSend> = get_entries(
+        &state,
+        &LocalKey::<ExamDB>::new(&state, user.clone()).await?,
+        due_between("date", today, last_day),
+        &[],
+        None,
+    )
+    .await?;
+    let goals: Vec<StudyGoalSend> = get_entries(
+        &state,
+        &LocalKey::<StudyGoalDB>::new(&state, user.clone()).await?,
+        due_between("deadline", today, last_day),
+        &[],
+        None,
+    )
+    .await?;
+    let goals = with_topic_ids(&state, user.user_id, goals).await?;
+    let mut todo_params = db_param_map! { completed: false };
+    todo_params.extend(due_between("deadline", today, last_day));
+    let todos: Vec<ToDoSend> = get_entries(
+        &state,
+        &LocalKey::<ToDoDB>::new(&state, user.clone()).await?,
+        todo_params,
+        &[],
+        None,
+    )
+    .await?;
+
+    // course names are only decrypted if an exam needs them
+    let course_names: HashMap<i32, String> = if exams.is_empty() {
+        HashMap::new()
+    } else {
+        get_entries::<CourseDB, CourseSend, DB>(
+            &state,
+            &LocalKey::new(&state, user).await?,
+            vec![],
+            &[],
+            None,
+        )
+        .await?
+        .into_iter()
+        .filter_map(|course| Some((course.id?, course.name)))
+        .collect()
+    };
+
+    let mut items: Vec<UpcomingItem> = exams
+        .into_iter()
+        .map(|exam| UpcomingItem::Exam {
+            id: exam.id.unwrap_or_default(),
+            date: exam.date,
+            course_name: course_names.get(&exam.course_id).cloned(),
+            name: exam.name,
+            course_id: exam.course_id,
+        })
+        .chain(goals.into_iter().map(|goal| UpcomingItem::StudyGoal {
+            id: goal.id.unwrap_or_default(),
+            date: goal.deadline,
+            topic_ids: goal.topic_ids,
+        }))
+        .chain(todos.into_iter().map(|todo| UpcomingItem::Todo {
+            id: todo.id.unwrap_or_default(),
+            date: todo.deadline,
+            name: todo.name,
+            priority: todo.priority,
+        }))
+        .collect();
+    items.sort_by_key(UpcomingItem::sort_key);
+
+    info!("Upcoming deadlines successful, building response!");
+    Ok(Json(items))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use chrono::{Days, Utc};
+    use serde_json::{Value, json};
+
+    use crate::{
+        data_handler::data_router,
+        test_util::{create_course, register_user, send_json, test_state},
+    };
+
+    #[tokio::test]
+    async fn upcoming_merges_the_deadlines_of_the_next_days() {
+        let state = test_state().await;
+        let token = register_user(&state, "upcoming_user").await;
+        let router = data_router(state.clone()).await;
+        let in_days = |days: u64| (Utc::now().date_naive() + Days::new(days)).to_string();
+        let yesterday = (Utc::now().date_naive() - Days::new(1)).to_string();
+
+        let course_id = create_course(router.clone(), &token).await;
+        let mut created = Vec::new();
+        for (uri, body) in [
+            (
+                "/exam",
+                json!({ "id": null, "course_id": course_id, "name": "Analysis", "date": in_days(3) }),
+            ),
+            (
+                "/exam",
+                json!({ "id": null, "course_id": course_id, "name": "Old", "date": yesterday }),
+            ),
+            (
+                "/topic",
+                json!({ "id": null, "course_id": course_id, "name": "Limits", "details": "" }),
+            ),
+            (
+                "/todo",
+                json!({ "id": null, "name": "Laundry", "deadline": in_days(1), "details": "", "completed": false }),
+            ),
+            (
+                "/todo",
+                json!({ "id": null, "name": "Dishes", "deadline": in_days(2), "details": "", "completed": true }),
+            ),
+            (
+                "/todo",
+                json!({ "id": null, "name": "Taxes", "deadline": in_days(10), "details": "", "completed": false }),
+            ),
+            (
+                "/todo",
+                json!({ "id": null, "name": "Rent", "deadline": in_days(1), "details": "", "priority": 3 }),
+            ),
+        ] {
+            let (status, body) =
+                send_json(router.clone(), Method::POST, uri, &token, Some(body)).await;
+            assert_eq!(status, StatusCode::OK);
+            created.push(body["id"].clone());
+        }
+        let (_, goal) = send_json(
+            router.clone(),
+            Method::POST,
+            "/study_goal",
+            &token,
+            Some(json!({ "id": null, "topic_id": created[2], "deadline": in_days(1) })),
+        )
+        .await;
+
+        let (status, items) =
+            send_json(router.clone(), Method::GET, "/upcoming", &token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            items,
+            json!([
+                { "kind": "study_goal", "id": goal["id"], "date": in_days(1), "topic_ids": [created[2]] },
+                { "kind": "todo", "id": created[6], "date": in_days(1), "name": "Rent", "priority": 3 },
+                { "kind": "todo", "id": created[3], "date": in_days(1), "name": "Laundry", "priority": 0 },
+                { "kind": "exam", "id": created[0], "date": in_days(3), "name": "Analysis", "course_id": course_id, "course_name": "Course" },
+            ])
+        );
+
+        let (_, items) = send_json(
+            router.clone(),
+            Method::GET,
+            "/upcoming?days=14",
+            &token,
+            None,
+        )
+        .await;
+        let names: Vec<&Value> = items
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| &item["name"])
+            .collect();
+        assert_eq!(
+            names,
+            [
+                &Value::Null,
+                &json!("Rent"),
+                &json!("Laundry"),
+                &json!("Analysis"),
+                &json!("Taxes")
+            ]
+        );
+
+        // larger ranges are capped
+        let (status, _) = send_json(
+            router.clone(),
+            Method::GET,
+            "/upcoming?days=100000",
+            &token,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send_json(router, Method::GET, "/upcoming?days=-1", &token, None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,891 @@
+use std::{collections::HashMap, env, net::IpAddr, sync::Arc, time::Duration};
+
+use axum::{extract::State, http::StatusCode};
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use hmac::{Hmac, Mac};
+use log::{error, info, warn};
+use reqwest::{Url, redirect::Policy};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{
+    AppState,
+    api_error::{ApiError, Json, Query},
+    auth_handler::AuthenticatedUser,
+    db::{DBInterface, DBObjIdent, WebhookDelivery},
+    db_param_map,
+};
+
+use super::objects::WebhookSubscriptionDB;
+
+/// header containing the HMAC-SHA256 signature of the body
+pub const SIGNATURE_HEADER: &str = "X-Eduflow-Signature";
+/// longest delay between two attempts of a delivery, the backoff stops growing here
+const MAX_RETRY_DELAY: TimeDelta = TimeDelta::days(1);
+
+/// what happened to an object
+#[derive(Debug, Clone, Copy)]
+pub enum WebhookAction {
+    Create,
+    Edit,
+    Delete,
+}
+
+impl WebhookAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            WebhookAction::Create => "create",
+            WebhookAction::Edit => "edit",
+            WebhookAction::Delete => "delete",
+        }
+    }
+}
+
+/// body of a webhook request, intentionally contains no (decrypted) content
+#[derive(Deserialize, Serialize, Debug)]
+pub struct WebhookEvent {
+    pub id: i32,
+    pub ident: String,
+    pub action: String,
+    pub timestamp: NaiveDateTime,
+}
+
+/// settings of the delivery worker, read from the env
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// how often the outbox is checked for due deliveries
+    pub poll_interval: Duration,
+    /// delay before the first retry, doubled for every further attempt
+    pub retry_base: Duration,
+    /// a delivery is given up after this many attempts
+    pub max_attempts: i32,
+    /// a subscription is disabled after this many consecutive failed attempts
+    pub disable_after: i32,
+    /// timeout of a single request
+    pub request_timeout: Duration,
+    /// local hosts webhooks may be sent to, see allowed_hosts
+    pub allowed_hosts: Vec<String>,
+}
+
+impl WebhookConfig {
+    /// reads the config from WEBHOOK_POLL_SECS, WEBHOOK_RETRY_BASE_SECS, WEBHOOK_MAX_ATTEMPTS, WEBHOOK_DISABLE_AFTER and WEBHOOK_ALLOWED_HOSTS
+    /// panics on invalid values, so misconfigurations are noticed at startup
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: u64| -> u64 {
+            env::var(name)
+                .map(|value| {
+                    value
+                        .parse()
+                        .unwrap_or_else(|_| panic!("{} has to be a positive number", name))
+                })
+                .unwrap_or(default)
+        };
+
+        Self {
+            poll_interval: Duration::from_secs(var("WEBHOOK_POLL_SECS", 5)),
+            retry_base: Duration::from_secs(var("WEBHOOK_RETRY_BASE_SECS", 30)),
+            max_attempts: var("WEBHOOK_MAX_ATTEMPTS", 8) as i32,
+            disable_after: var("WEBHOOK_DISABLE_AFTER", 20) as i32,
+            request_timeout: Duration::from_secs(10),
+            allowed_hosts: allowed_hosts(),
+        }
+    }
+}
+
+/// local hosts webhooks may be sent to anyway (e.g. receivers on the same machine), comma separated in WEBHOOK_ALLOWED_HOSTS
+pub fn allowed_hosts() -> Vec<String> {
+    env::var("WEBHOOK_ALLOWED_HOSTS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|host| host.trim().to_lowercase())
+        .filter(|host| !host.is_empty())
+        .collect()
+}
+
+/// checks the url of a subscription, only http(s) urls of public hosts are accepted
+/// local hosts (loopback, private, link-local, ...) are rejected unless allowed, webhooks must not reach internal services
+pub fn check_webhook_url(url: &str, allowed_hosts: &[String]) -> Result<Url, &'static str> {
+    let url = Url::parse(url).map_err(|_| "is not a valid url")?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("has to be an http or https url");
+    }
+    let host = url.host_str().ok_or("is not a valid url")?;
+    if allowed_hosts.iter().any(|allowed| allowed == host) {
+        return Ok(url);
+    }
+    // ipv6 hosts are in brackets, ipv4 hosts are already normalized by the parser (e.g. 2130706433 -> 127.0.0.1)
+    let local = match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(ip) => is_local_ip(ip),
+        Err(_) => {
+            let domain = host.trim_end_matches('.');
+            domain == "localhost" || domain.ends_with(".localhost")
+        }
+    };
+    if local {
+        return Err("points to a local address");
+    }
+    Ok(url)
+}
+
+/// checks if an address is not reachable from the internet, e.g. loopback, private or link-local
+fn is_local_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // "this network" and carrier-grade nat
+                || first == 0
+                || (first == 100 && second & 0xc0 == 64)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_local_ip(IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                // unique local and link-local
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80
+        }
+    }
+}
+
+/// checks the url of a subscription again before a delivery, including the addresses its host resolves to
+/// catches subscriptions saved before the check and hosts whose address changed
+async fn delivery_target_error(url: &str, allowed_hosts: &[String]) -> Option<String> {
+    let url = match check_webhook_url(url, allowed_hosts) {
+        Ok(url) => url,
+        Err(reason) => return Some(format!("url {}", reason)),
+    };
+    let host = url.host_str()?;
+    if allowed_hosts.iter().any(|allowed| allowed == host) {
+        return None;
+    }
+    let port = url.port_or_known_default()?;
+    // the brackets of ipv6 hosts are not part of the address
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    // failed lookups fail the request as well, it is retried like other failures
+    let mut addrs = tokio::net::lookup_host((host, port)).await.ok()?;
+    addrs
+        .any(|addr| is_local_ip(addr.ip()))
+        .then(|| "url points to a local address".to_string())
+}
+
+/// client of the delivery worker, redirects are not followed as they could lead to local addresses
+pub fn delivery_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(Policy::none())
+        .build()
+        .expect("Webhook client can always be built")
+}
+
+/// checks if a comma separated filter of ident:action pairs matches an event, * matches everything, an empty filter matches every event
+fn filter_matches(event_filter: &str, ident: &str, action: &str) -> bool {
+    let mut entries = event_filter.split(',').filter(|e| !e.is_empty()).peekable();
+    if entries.peek().is_none() {
+        return true;
+    }
+
+    entries.any(|entry| {
+        let (filter_ident, filter_action) = entry.split_once(':').unwrap_or((entry, "*"));
+        (filter_ident == "*" || filter_ident == ident)
+            && (filter_action == "*" || filter_action == action)
+    })
+}
+
+/// returns the signature header value for a payload, "sha256=" followed by the hex encoded HMAC
+pub fn sign_payload(secret: &str, payload: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(payload.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// queues an event for every active subscription of the user matching it
+/// failures are only logged, the data change itself already happened
+pub async fn enqueue_event<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    user_id: i32,
+    ident: &DBObjIdent,
+    action: WebhookAction,
+    object_id: i32,
+) {
+    let subscriptions = state
+        .db
+        .select_entries::<WebhookSubscriptionDB>(db_param_map! { user_id: user_id }, &[], None)
+        .await;
+    let subscriptions = match subscriptions {
+        Ok(subscriptions) => subscriptions,
+        Err(_) => {
+            error!(
+                "Failed to load webhook subscriptions (user id: {})",
+                user_id
+            );
+            return;
+        }
+    };
+
+    let event = WebhookEvent {
+        id: object_id,
+        ident: ident.db_identifier.clone(),
+        action: action.as_str().to_string(),
+        timestamp: Utc::now().naive_utc(),
+    };
+    let payload = serde_json::to_string(&event).expect("Webhook event is always serializable");
+
+    let matching = subscriptions
+        .iter()
+        .filter(|sub| sub.active && filter_matches(&sub.event_filter, &event.ident, &event.action));
+    for sub in matching {
+        if state
+            .db
+            .new_webhook_delivery(sub.id, &payload)
+            .await
+            .is_err()
+        {
+            error!(
+                "Failed to queue webhook delivery for subscription {}",
+                sub.id
+            );
+        }
+    }
+}
+
+/// background task delivering queued webhook events, never returns
+pub async fn delivery_worker<DB: DBInterface + Send + Sync>(
+    state: Arc<AppState<DB>>,
+    config: WebhookConfig,
+) {
+    let client = delivery_client();
+    let mut interval = tokio::time::interval(config.poll_interval);
+
+    loop {
+        interval.tick().await;
+        deliver_pending(&state, &client, &config).await;
+    }
+}
+
+/// delivers every due event once, returns the number of successful deliveries
+pub async fn deliver_pending<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    client: &reqwest::Client,
+    config: &WebhookConfig,
+) -> usize {
+    let now = Utc::now().naive_utc();
+    let deliveries = match state.db.get_due_webhook_deliveries(&now).await {
+        Ok(deliveries) => deliveries,
+        Err(_) => {
+            error!("Failed to load due webhook deliveries!");
+            return 0;
+        }
+    };
+
+    let mut delivered = 0;
+    for mut delivery in deliveries {
+        let subscription = state
+            .db
+            .select_entries::<WebhookSubscriptionDB>(
+                db_param_map! { id: delivery.subscription_id },
+                &[],
+                None,
+            )
+            .await
+            .ok()
+            .and_then(|mut subs| subs.pop());
+
+        let Some(subscription) = subscription.filter(|sub| sub.active) else {
+            delivery.status = "failed".into();
+            delivery.last_error = Some("subscription deleted or disabled".into());
+            store_delivery(state, &delivery).await;
+            continue;
+        };
+
+        if let Some(error) = delivery_target_error(&subscription.url, &config.allowed_hosts).await {
+            warn!(
+                "Webhook delivery {} to a forbidden target rejected: {}",
+                delivery.id, error
+            );
+            delivery.status = "failed".into();
+            delivery.last_error = Some(error);
+            store_delivery(state, &delivery).await;
+            continue;
+        }
+
+        let result = client
+            .post(&subscription.url)
+            .timeout(config.request_timeout)
+            .header("Content-Type", "application/json")
+            .header(
+                SIGNATURE_HEADER,
+                sign_payload(&subscription.secret, &delivery.payload),
+            )
+            .body(delivery.payload.clone())
+            .send()
+            .await;
+
+        delivery.attempts += 1;
+        let error = match result {
+            Ok(response) if response.status().is_success() => None,
+            Ok(response) => Some(format!("receiver responded with {}", response.status())),
+            Err(err) => Some(format!("request failed: {}", err)),
+        };
+
+        match error {
+            None => {
+                delivery.status = "delivered".into();
+                delivery.last_error = None;
+                delivered += 1;
+                set_failure_count(state, config, &subscription, 0).await;
+            }
+            Some(error) => {
+                warn!(
+                    "Webhook delivery {} failed (attempt {}): {}",
+                    delivery.id, delivery.attempts, error
+                );
+                delivery.last_error = Some(error);
+                if delivery.attempts >= config.max_attempts {
+                    delivery.status = "failed".into();
+                } else {
+                    delivery.next_attempt_at = Utc::now()
+                        .naive_utc()
+                        .checked_add_signed(retry_delay(config.retry_base, delivery.attempts))
+                        .unwrap_or(NaiveDateTime::MAX);
+                }
+                set_failure_count(state, config, &subscription, subscription.failure_count + 1)
+                    .await;
+            }
+        }
+
+        store_delivery(state, &delivery).await;
+    }
+
+    delivered
+}
+
+/// exponential backoff after the given number of attempts: base, 2 * base, 4 * base, ... at most MAX_RETRY_DELAY
+fn retry_delay(retry_base: Duration, attempts: i32) -> TimeDelta {
+    // 2^31 times the base is above the maximum for any base of at least a millisecond
+    let exponent = (attempts - 1).clamp(0, 31) as u32;
+    retry_base
+        .checked_mul(1 << exponent)
+        .and_then(|delay| TimeDelta::from_std(delay).ok())
+        .map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY))
+}
+
+/// stores the new state of a delivery, only logs on failure
+async fn store_delivery<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    delivery: &WebhookDelivery,
+) {
+    if state.db.update_webhook_delivery(delivery).await.is_err() {
+        error!("Failed to store state of webhook delivery {}", delivery.id);
+    }
+}
+
+/// stores the consecutive failure count of a subscription, disables it once the configured limit is reached
+async fn set_failure_count<DB: DBInterface + Send + Sync>(
+    state: &AppState<DB>,
+    config: &WebhookConfig,
+    subscription: &WebhookSubscriptionDB,
+    failure_count: i32,
+) {
+    if failure_count == subscription.failure_count {
+        return;
+    }
+
+    let active = failure_count < config.disable_after;
+    if !active {
+        warn!(
+            "Disabling webhook subscription {} after {} failed attempts.",
+            subscription.id, failure_count
+        );
+    }
+
+    let result = state
+        .db
+        .update_entry::<WebhookSubscriptionDB>(
+            db_param_map! { failure_count: failure_count, active: active },
+            db_param_map! { id: subscription.id },
+        )
+        .await;
+    if result.is_err() {
+        error!(
+            "Failed to update failure count of webhook subscription {}",
+            subscription.id
+        );
+    }
+}
+
+/// delivery state of a webhook event, returned to the client
+#[derive(Deserialize, Serialize, Debug)]
+pub struct WebhookDeliverySend {
+    id: i32,
+    event: serde_json::Value,
+    status: String,
+    attempts: i32,
+    next_attempt_at: NaiveDateTime,
+    last_error: Option<String>,
+    created_at: NaiveDateTime,
+}
+
+/// handler returning the deliveries of a subscription, requires the subscription_id query parameter
+pub async fn handle_get_deliveries<DB: DBInterface + Send + Sync>(
+    user: AuthenticatedUser,
+    State(state): State<Arc<AppState<DB>>>,
+    Query(params_query): Query<HashMap<String, String>>,
+) -> Result<Json<Vec<WebhookDeliverySend>>, ApiError> {
+    info!("Webhook delivery status requested!");
+
+    let AuthenticatedUser { user_id, .. } = user;
+    // nothing is encrypted, no local token needed
+
+    let subscription_id: i32 = params_query
+        .get("subscription_id")
+        .and_then(|id| id.parse().ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    // make sure the subscription belongs to the user
+    let subscriptions = state
+        .db
+        .select_entries::<WebhookSubscriptionDB>(
+            db_param_map! { id: subscription_id, user_id: user_id },
+            &[],
+            None,
+        )
+        .await
+        .map_err(|_| {
+            error!("Error while querying DB! Tried to get webhook subscription.");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if subscriptions.is_empty() {
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
+    let deliveries = state
+        .db
+        .get_webhook_deliveries_by_subscription(subscription_id)
+        .await
+        .map_err(|_| {
+            error!("Error while querying DB! Tried to get webhook deliveries.");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(
+        deliveries
+            .into_iter()
+            .map(|delivery| WebhookDeliverySend {
+                id: delivery.id,
+                event: serde_json::from_str(&delivery.payload).unwrap_or_default(),
+                status: delivery.status,
+                attempts: delivery.attempts,
+                next_attempt_at: delivery.next_attempt_at,
+                last_error: delivery.last_error,
+                created_at: delivery.created_at,
+            })
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use axum::{
+        Router,
+        body::Bytes,
+        http::{HeaderMap, Method},
+        routing::post,
+    };
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        auth_handler::{HashConfig, LoginLockoutConfig, TokenConfig},
+        crypt::crypt_provider::CryptProviders,
+        data_handler::{data_router, limits::PayloadLimits},
+        db::sqlite::SqliteDatabase,
+        test_util::{register_user, send_json},
+    };
+
+    /// requests received by the mock receiver (signature header, body)
+    type Received = Arc<Mutex<Vec<(String, String)>>>;
+
+    /// starts a receiver which answers with the given status codes (last one repeats), returns its url
+    async fn mock_receiver(statuses: Vec<u16>) -> (String, Received) {
+        let received: Received = Arc::new(Mutex::new(Vec::new()));
+        let received_handler = received.clone();
+
+        let app = Router::new().route(
+            "/hook",
+            post(move |headers: HeaderMap, body: Bytes| async move {
+                let mut received = received_handler.lock().unwrap();
+                let signature = headers
+                    .get(SIGNATURE_HEADER)
+                    .map(|s| s.to_str().unwrap().to_string())
+                    .unwrap_or_default();
+                received.push((signature, String::from_utf8(body.to_vec()).unwrap()));
+
+                let status = statuses[(received.len() - 1).min(statuses.len() - 1)];
+                StatusCode::from_u16(status).unwrap()
+            }),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (url, received)
+    }
+
+    async fn test_state() -> AppState<SqliteDatabase> {
+        let state = AppState {
+            db: Box::new(SqliteDatabase::new_in_memory()),
+            crypt_provider: CryptProviders::SimpleCryptProv,
+            token_config: TokenConfig::default(),
+            lockout_config: LoginLockoutConfig::default(),
+            hash_config: HashConfig::default(),
+            payload_limits: PayloadLimits::default(),
+        };
+        state
+            .db
+            .create_table_for_type::<WebhookSubscriptionDB>()
+            .await
+            .unwrap();
+        state
+    }
+
+    fn test_config() -> WebhookConfig {
+        WebhookConfig {
+            poll_interval: Duration::from_millis(10),
+            retry_base: Duration::ZERO,
+            max_attempts: 3,
+            disable_after: 20,
+            request_timeout: Duration::from_secs(5),
+            // the test receivers run on this machine
+            allowed_hosts: vec!["127.0.0.1".to_string()],
+        }
+    }
+
+    async fn subscribe(state: &AppState<SqliteDatabase>, url: &str, event_filter: &str) -> i32 {
+        state
+            .db
+            .new_entry::<WebhookSubscriptionDB>(db_param_map! {
+                user_id: 1,
+                url: url,
+                secret: "top secret",
+                event_filter: event_filter,
+                active: true,
+                failure_count: 0,
+            })
+            .await
+            .unwrap()
+    }
+
+    async fn get_subscription(state: &AppState<SqliteDatabase>, id: i32) -> WebhookSubscriptionDB {
+        state
+            .db
+            .select_entries::<WebhookSubscriptionDB>(db_param_map! { id: id }, &[], None)
+            .await
+            .unwrap()
+            .pop()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        assert!(filter_matches("", "ToDoDB", "create"));
+        assert!(filter_matches("ToDoDB:*", "ToDoDB", "delete"));
+        assert!(filter_matches("ToDoDB", "ToDoDB", "edit"));
+        assert!(filter_matches("CourseDB:edit,*:delete", "ToDoDB", "delete"));
+        assert!(!filter_matches(
+            "CourseDB:edit,*:delete",
+            "ToDoDB",
+            "create"
+        ));
+        assert!(!filter_matches("ToDoDB:create", "CourseDB", "create"));
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let base = Duration::from_secs(30);
+        assert_eq!(retry_delay(base, 1), TimeDelta::seconds(30));
+        assert_eq!(retry_delay(base, 3), TimeDelta::seconds(120));
+        assert_eq!(retry_delay(base, 40), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(base, i32::MAX), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(Duration::MAX, 1), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(Duration::ZERO, 100), TimeDelta::zero());
+    }
+
+    #[test]
+    fn test_check_webhook_url() {
+        let allowed = ["127.0.0.1".to_string()];
+        for url in [
+            "https://hooks.example.com/eduflow",
+            "http://93.184.216.34:8080/hook",
+            "http://[2606:4700::1111]/hook",
+            "http://127.0.0.1:3000/hook",
+        ] {
+            assert!(check_webhook_url(url, &allowed).is_ok(), "{}", url);
+        }
+        for (url, reason) in [
+            ("not a url", "is not a valid url"),
+            ("ftp://example.com/hook", "has to be an http or https url"),
+            ("file:///etc/passwd", "has to be an http or https url"),
+            ("http://localhost:8080/hook", "points to a local address"),
+            ("http://api.localhost./hook", "points to a local address"),
+            ("http://127.0.0.2/hook", "points to a local address"),
+            ("http://10.0.0.1/hook", "points to a local address"),
+            ("http://172.16.5.4/hook", "points to a local address"),
+            ("http://192.168.1.1/hook", "points to a local address"),
+            ("http://169.254.169.254/latest", "points to a local address"),
+            ("http://100.64.0.1/hook", "points to a local address"),
+            ("http://0.0.0.0/hook", "points to a local address"),
+            ("http://[::1]/hook", "points to a local address"),
+            ("http://[fd00::1]/hook", "points to a local address"),
+            ("http://[fe80::1]/hook", "points to a local address"),
+            (
+                "http://[::ffff:127.0.0.1]/hook",
+                "points to a local address",
+            ),
+        ] {
+            assert_eq!(
+                check_webhook_url(url, &allowed).err(),
+                Some(reason),
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_local_targets_are_not_delivered() {
+        let state = test_state().await;
+        let (url, received) = mock_receiver(vec![200]).await;
+        let subscription_id = subscribe(&state, &url, "").await;
+
+        let todo_ident = DBObjIdent {
+            db_identifier: "ToDoDB".into(),
+        };
+        enqueue_event(&state, 1, &todo_ident, WebhookAction::Create, 42).await;
+
+        // without the allowed host the receiver on this machine is never contacted
+        let config = WebhookConfig {
+            allowed_hosts: Vec::new(),
+            ..test_config()
+        };
+        assert_eq!(
+            deliver_pending(&state, &delivery_client(), &config).await,
+            0
+        );
+        assert!(received.lock().unwrap().is_empty());
+        let deliveries = state
+            .db
+            .get_webhook_deliveries_by_subscription(subscription_id)
+            .await
+            .unwrap();
+        assert_eq!(deliveries[0].status, "failed");
+        assert_eq!(
+            deliveries[0].last_error.as_deref(),
+            Some("url points to a local address")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_local_urls_are_rejected() {
+        // the data router needs the shared state with every table
+        let state = crate::test_util::test_state().await;
+        let token = register_user(&state, "webhook_url_user").await;
+        let router = data_router(state.clone()).await;
+
+        for (url, status) in [
+            (
+                "http://169.254.169.254/latest",
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+            ("https://hooks.example.com/eduflow", StatusCode::OK),
+        ] {
+            let (response, body) = send_json(
+                router.clone(),
+                Method::POST,
+                "/webhook",
+                &token,
+                Some(
+                    json!({ "id": null, "url": url, "secret": "s", "events": [], "active": true }),
+                ),
+            )
+            .await;
+            assert_eq!(response, status, "{}", url);
+            if status != StatusCode::OK {
+                assert_eq!(body["field_errors"][0]["field"], "url");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delivery_success_and_signature() {
+        let state = test_state().await;
+        let (url, received) = mock_receiver(vec![200]).await;
+        let subscription_id = subscribe(&state, &url, "ToDoDB:*").await;
+        // filtered out
+        subscribe(&state, &url, "CourseDB:*").await;
+
+        let todo_ident = DBObjIdent {
+            db_identifier: "ToDoDB".into(),
+        };
+        enqueue_event(&state, 1, &todo_ident, WebhookAction::Create, 42).await;
+        // other users subscriptions are not triggered
+        enqueue_event(&state, 2, &todo_ident, WebhookAction::Create, 43).await;
+
+        let client = delivery_client();
+        assert_eq!(deliver_pending(&state, &client, &test_config()).await, 1);
+
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 1);
+        let (signature, body) = &received[0];
+
+        // the receiver can verify the signature with the shared secret
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"top secret").unwrap();
+        mac.update(body.as_bytes());
+        let expected = hex::decode(signature.strip_prefix("sha256=").unwrap()).unwrap();
+        assert!(mac.verify_slice(&expected).is_ok());
+
+        let event: WebhookEvent = serde_json::from_str(body).unwrap();
+        assert_eq!(event.id, 42);
+        assert_eq!(event.ident, "ToDoDB");
+        assert_eq!(event.action, "create");
+
+        let deliveries = state
+            .db
+            .get_webhook_deliveries_by_subscription(subscription_id)
+            .await
+            .unwrap();
+        assert_eq!(deliveries[0].status, "delivered");
+        assert_eq!(deliveries[0].attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_delivery_retry() {
+        let state = test_state().await;
+        let (url, received) = mock_receiver(vec![500, 503, 200]).await;
+        let subscription_id = subscribe(&state, &url, "").await;
+
+        let course_ident = DBObjIdent {
+            db_identifier: "CourseDB".into(),
+        };
+        enqueue_event(&state, 1, &course_ident, WebhookAction::Delete, 7).await;
+
+        let client = delivery_client();
+        let config = test_config();
+        assert_eq!(deliver_pending(&state, &client, &config).await, 0);
+
+        let delivery = &state
+            .db
+            .get_webhook_deliveries_by_subscription(subscription_id)
+            .await
+            .unwrap()[0];
+        assert_eq!(delivery.status, "pending");
+        assert_eq!(delivery.attempts, 1);
+        assert!(delivery.last_error.as_ref().unwrap().contains("500"));
+        assert_eq!(
+            get_subscription(&state, subscription_id)
+                .await
+                .failure_count,
+            1
+        );
+
+        assert_eq!(deliver_pending(&state, &client, &config).await, 0);
+        assert_eq!(deliver_pending(&state, &client, &config).await, 1);
+
+        let delivery = &state
+            .db
+            .get_webhook_deliveries_by_subscription(subscription_id)
+            .await
+            .unwrap()[0];
+        assert_eq!(delivery.status, "delivered");
+        assert_eq!(delivery.attempts, 3);
+        assert_eq!(received.lock().unwrap().len(), 3);
+        // success resets the failure counter
+        assert_eq!(
+            get_subscription(&state, subscription_id)
+                .await
+                .failure_count,
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delivery_gives_up() {
+        let state = test_state().await;
+        let (url, received) = mock_receiver(vec![500]).await;
+        let subscription_id = subscribe(&state, &url, "").await;
+
+        let course_ident = DBObjIdent {
+            db_identifier: "CourseDB".into(),
+        };
+        enqueue_event(&state, 1, &course_ident, WebhookAction::Edit, 7).await;
+
+        let client = delivery_client();
+        let config = test_config();
+        for _ in 0..5 {
+            deliver_pending(&state, &client, &config).await;
+        }
+
+        let delivery = &state
+            .db
+            .get_webhook_deliveries_by_subscription(subscription_id)
+            .await
+            .unwrap()[0];
+        assert_eq!(delivery.status, "failed");
+        assert_eq!(delivery.attempts, config.max_attempts);
+        assert_eq!(received.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_subscription_gets_disabled() {
+        let state = test_state().await;
+        let (url, received) = mock_receiver(vec![500]).await;
+        let subscription_id = subscribe(&state, &url, "").await;
+
+        let course_ident = DBObjIdent {
+            db_identifier: "CourseDB".into(),
+        };
+        enqueue_event(&state, 1, &course_ident, WebhookAction::Create, 1).await;
+        enqueue_event(&state, 1, &course_ident, WebhookAction::Create, 2).await;
+
+        let client = delivery_client();
+        let config = WebhookConfig {
+            disable_after: 2,
+            ..test_config()
+        };
+        // both deliveries fail once, which disables the subscription
+        deliver_pending(&state, &client, &config).await;
+        assert!(!get_subscription(&state, subscription_id).await.active);
+
+        // remaining deliveries are not attempted anymore
+        deliver_pending(&state, &client, &config).await;
+        assert_eq!(received.lock().unwrap().len(), 2);
+        let deliveries = state
+            .db
+            .get_webhook_deliveries_by_subscription(subscription_id)
+            .await
+            .unwrap();
+        assert!(deliveries.iter().all(|d| d.status == "failed"));
+
+        // new events are not queued for disabled subscriptions
+        enqueue_event(&state, 1, &course_ident, WebhookAction::Create, 3).await;
+        assert_eq!(
+            state
+                .db
+                .get_webhook_deliveries_by_subscription(subscription_id)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+}
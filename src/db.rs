@@ -1,114 +1,438 @@
-use std::error::Error;
+use std::{collections::HashMap, future::Future, path::Path, pin::Pin};
 
+use async_trait::async_trait;
 use chrono::NaiveDateTime;
-use sql_helper::{SQLGenerate, SQLValue};
+use rusqlite::types::Value;
+use serde::{Deserialize, Serialize};
+use sql_helper::{SQLGenError, SQLGenerate, SQLValue, SortDirection, WhereClause, WhereParam};
 
 use crate::crypt::crypt_types::CryptString;
 
+pub mod migrations;
+#[cfg(test)]
+pub mod mock;
 pub mod sql_helper;
 pub mod sqlite;
 
+/// error of every db call, the database implementations map their own errors to it
+/// only the messages of the underlying errors are kept, so the error can be sent across threads
+#[derive(Debug, thiserror::Error)]
+pub enum DbError {
+    /// no row matched, e.g. an unknown id
+    #[error("Entry not found")]
+    NotFound,
+    /// a unique constraint or primary key already has this value, e.g. a taken username
+    #[error("Unique constraint violated: {0}")]
+    UniqueViolation(String),
+    /// a referenced row does not exist, or is still referenced by others
+    #[error("Foreign key constraint violated: {0}")]
+    ForeignKeyViolation(String),
+    /// a value is out of the range of a check constraint, holds the column of a field check
+    /// or the expression of a table check
+    #[error("Check constraint violated: {0}")]
+    CheckViolation(String),
+    /// no connection could be acquired in time, the database is overloaded rather than broken
+    #[error("No database connection available: {0}")]
+    Pool(String),
+    /// a value could not be converted from or to its column type
+    #[error("Serialization failed: {0}")]
+    Serialization(String),
+    /// an existing table lacks a column of its object that can not be added automatically,
+    /// or its rows violate a unique constraint added to the object later
+    #[error("Schema mismatch: {0}")]
+    SchemaMismatch(String),
+    /// no statement could be generated from the given fields
+    #[error(transparent)]
+    Statement(#[from] SQLGenError),
+    #[error("Database error: {0}")]
+    Other(String),
+}
+
+/// future returned by the closure of with_transaction
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
 /// Database interface trait that defines the methods for database operations.
-pub trait DBInterface {
+#[async_trait]
+pub trait DBInterface: Send + Sync {
+    /// runs f in a transaction, everything written through the handle passed to f is rolled back if f fails
+    /// only that handle may be used inside of f, nested calls join the outer transaction
+    /// f returns a boxed future, so it can borrow the handle: `|db| Box::pin(async move { ... })`
+    async fn with_transaction<R, F>(&self, f: F) -> Result<R, DbError>
+    where
+        R: Send,
+        F: for<'a> FnOnce(&'a Self) -> BoxFuture<'a, Result<R, DbError>> + Send;
+
+    /// writes a consistent copy of the whole database to path, while other calls keep running
+    /// an existing file at path is overwritten
+    async fn backup_to<P: AsRef<Path> + Send>(&self, path: P) -> Result<(), DbError>;
+
     // AUTH
 
     // user related
     /// create a new user, returns the user id
-    fn new_user(&self, username: &str, password_hash: &str) -> Result<i32, Box<dyn Error>>;
+    /// fails if the username or the email is already taken
+    async fn new_user(
+        &self,
+        username: &str,
+        password_hash: &str,
+        email: Option<&str>,
+    ) -> Result<i32, DbError>;
     /// Get a user by their username.
-    fn get_user_by_username(&self, username: &str) -> Result<User, Box<dyn Error>>;
+    async fn get_user_by_username(&self, username: &str) -> Result<User, DbError>;
+    /// Get a user by their id.
+    async fn get_user_by_id(&self, user_id: i32) -> Result<User, DbError>;
+    /// count a wrong password of a user, returns the number of consecutive failures
+    async fn increment_failed_logins(&self, user_id: i32) -> Result<i32, DbError>;
+    /// refuse logins of a user until locked_until, resets the failure counter
+    async fn lock_user(&self, user_id: i32, locked_until: &NaiveDateTime) -> Result<(), DbError>;
+    /// clear the failure counter and a lockout of a user, e.g. after a successful login
+    async fn reset_failed_logins(&self, user_id: i32) -> Result<(), DbError>;
+    /// delete a user and every row of the user in the non generic tables (sync journal, shares, retention log, study goal topics, history, auth events)
+    /// tokens and data objects have to be deleted beforehand
+    async fn del_user(&self, user_id: i32) -> Result<(), DbError>;
+    /// store a new password hash of a user together with the local tokens re-encrypted with the new password
+    /// local_tokens contains (local token id, token encrypted with the new password), either everything or nothing is written
+    async fn update_user_password_hash(
+        &self,
+        user_id: i32,
+        password_hash: &str,
+        local_tokens: &[(i32, CryptString)],
+    ) -> Result<(), DbError>;
 
     // token related
 
     // write tokens
     /// create new password encrypted local token
-    fn new_local_token_pwcrypt(
+    async fn new_local_token_pwcrypt(
         &self,
         user_id: i32,
         token_crypt: &CryptString,
         used_for: &DBObjIdent,
-    ) -> Result<(), Box<dyn Error>>;
+    ) -> Result<(), DbError>;
     /// create a new encrypted version of an already existing local token (encrypted by a remote token)
-    fn new_local_token_rtcrypt(
+    async fn new_local_token_rtcrypt(
         &self,
         local_token_id: i32,
         local_token_crypt: &CryptString,
         decryptable_by_rt_id: i32,
-    ) -> Result<(), Box<dyn Error>>;
+    ) -> Result<(), DbError>;
     /// create new remote token, results in write access, returns remote token id
-    fn new_remote_token(
+    /// local_tokens are the (local token id, encrypted local token) pairs the token can decrypt, stored together with it
+    async fn new_remote_token(
         &self,
         rt_hash: &str,
         user_id: i32,
         valid_until: &NaiveDateTime,
-    ) -> Result<i64, Box<dyn Error>>;
+        local_tokens: &[(i32, CryptString)],
+    ) -> Result<i64, DbError>;
+
+    /// create a copy of an already existing local token encrypted by the recovery key of the user
+    async fn new_local_token_reccrypt(
+        &self,
+        local_token_id: i32,
+        local_token_crypt: &CryptString,
+    ) -> Result<(), DbError>;
+    /// replace the password encrypted version of a local token
+    async fn update_local_token_pwcrypt(
+        &self,
+        local_token_id: i32,
+        token_crypt: &CryptString,
+    ) -> Result<(), DbError>;
 
     // get tokens
     /// get all local tokens for a user encrypted by password
-    fn get_local_tokens_by_user_pwcrypt(
+    async fn get_local_tokens_by_user_pwcrypt(
         &self,
         user_id: i32,
-    ) -> Result<Vec<LocalTokenPWCrypt>, Box<dyn Error>>;
+    ) -> Result<Vec<LocalTokenPWCrypt>, DbError>;
     /// get a single local token by id encrypted by password
-    fn get_local_token_by_used_for_pwcrypt(
+    async fn get_local_token_by_used_for_pwcrypt(
         &self,
         user_id: i32,
         used_for: &DBObjIdent,
-    ) -> Result<LocalTokenPWCrypt, Box<dyn Error>>;
-    /// get a single local token encrypted by a remote token
-    fn get_local_token_by_id_rtcrypt(
+    ) -> Result<LocalTokenPWCrypt, DbError>;
+    /// get all recovery key encrypted local tokens of a user
+    async fn get_local_tokens_by_user_reccrypt(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<LocalTokenRecCrypt>, DbError>;
+    /// get a single local token encrypted by a remote token, none if the remote token has no access to it
+    async fn get_local_token_by_id_rtcrypt(
         &self,
         local_token_id: i32,
         remote_token_id: i32,
-    ) -> Result<LocalTokenRTCrypt, Box<dyn Error>>;
-    /// get remote token by id
-    fn get_remote_token(&self, token_id: i32) -> Result<RemoteToken, Box<dyn Error>>;
+    ) -> Result<Option<LocalTokenRTCrypt>, DbError>;
+    /// get the ids of all local tokens a remote token has access to
+    async fn get_local_token_ids_by_rt(&self, remote_token_id: i32) -> Result<Vec<i32>, DbError>;
+    /// get remote token by id, none if it does not exist
+    async fn get_remote_token(&self, token_id: i32) -> Result<Option<RemoteToken>, DbError>;
+    /// get all remote tokens (of every user) that expired before now
+    async fn get_expired_remote_tokens(
+        &self,
+        now: &NaiveDateTime,
+    ) -> Result<Vec<RemoteToken>, DbError>;
+    /// get all remote tokens of a user, including expired ones
+    async fn get_remote_tokens_by_user(&self, user_id: i32) -> Result<Vec<RemoteToken>, DbError>;
+    /// move the expiry of a remote token, e.g. to extend an actively used token
+    async fn update_remote_token_validity(
+        &self,
+        token_id: i32,
+        new_valid_until: &NaiveDateTime,
+    ) -> Result<(), DbError>;
 
     // delete tokens
     /// delete all local tokens encrypted by a certain remote token
-    fn del_local_token_rtcrypt_by_rt(&self, remote_token_id: i32) -> Result<(), Box<dyn Error>>;
+    async fn del_local_token_rtcrypt_by_rt(&self, remote_token_id: i32) -> Result<(), DbError>;
+    /// delete all remote token encrypted versions of a local token
+    async fn del_local_token_rtcrypt_by_lt(&self, local_token_id: i32) -> Result<(), DbError>;
+    /// delete the recovery key encrypted version of a local token, e.g. because the local token was replaced
+    async fn del_local_token_reccrypt(&self, local_token_id: i32) -> Result<(), DbError>;
+    /// delete all password encrypted local tokens of a user and every remote token or recovery key encrypted version of them
+    async fn del_local_tokens_by_user(&self, user_id: i32) -> Result<(), DbError>;
     /// delete remote token by its id
-    fn del_remote_token(&self, remote_token_id: i32) -> Result<(), Box<dyn Error>>;
+    async fn del_remote_token(&self, remote_token_id: i32) -> Result<(), DbError>;
+    /// delete all remote tokens of a user (and the local tokens encrypted by them), except for keep_rt_id
+    async fn del_remote_tokens_by_user(
+        &self,
+        user_id: i32,
+        keep_rt_id: Option<i32>,
+    ) -> Result<(), DbError>;
+
+    // WEBHOOK related
+    /// queue a new webhook delivery for a subscription, returns the delivery id
+    async fn new_webhook_delivery(
+        &self,
+        subscription_id: i32,
+        payload: &str,
+    ) -> Result<i32, DbError>;
+    /// get all pending deliveries whose next attempt is due
+    async fn get_due_webhook_deliveries(
+        &self,
+        now: &NaiveDateTime,
+    ) -> Result<Vec<WebhookDelivery>, DbError>;
+    /// get all deliveries of a subscription, newest first
+    async fn get_webhook_deliveries_by_subscription(
+        &self,
+        subscription_id: i32,
+    ) -> Result<Vec<WebhookDelivery>, DbError>;
+    /// delete all deliveries of a subscription
+    async fn del_webhook_deliveries_by_subscription(
+        &self,
+        subscription_id: i32,
+    ) -> Result<(), DbError>;
+    /// stores the delivery state (status, attempts, next_attempt_at, last_error) of a delivery
+    async fn update_webhook_delivery(&self, delivery: &WebhookDelivery) -> Result<(), DbError>;
+
+    // SYNC related
+    /// append a change of an object to the sync journal
+    async fn new_sync_change(
+        &self,
+        user_id: i32,
+        used_for: &DBObjIdent,
+        object_id: i32,
+        deleted: bool,
+    ) -> Result<(), DbError>;
+    /// get at most limit changes of a user with an id bigger than after_id, ordered by id
+    async fn get_sync_changes(
+        &self,
+        user_id: i32,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<SyncChange>, DbError>;
+    /// get the id of the latest change of a user, 0 if there is none
+    async fn get_latest_sync_change_id(&self, user_id: i32) -> Result<i64, DbError>;
+
+    // SHARE related
+    /// create a new public share of an object, returns the share id
+    async fn new_public_share(
+        &self,
+        user_id: i32,
+        used_for: &DBObjIdent,
+        object_id: i32,
+        lookup_hash: &str,
+        content_crypt: &CryptString,
+        expires_at: Option<&NaiveDateTime>,
+    ) -> Result<i32, DbError>;
+    /// get a public share by the hash of its secret
+    async fn get_public_share_by_lookup(&self, lookup_hash: &str) -> Result<PublicShare, DbError>;
+    /// get all public shares of a user
+    async fn get_public_shares_by_user(&self, user_id: i32) -> Result<Vec<PublicShare>, DbError>;
+    /// delete a public share, only if it belongs to the user, returns the number of deleted shares
+    async fn del_public_share(&self, user_id: i32, share_id: i32) -> Result<usize, DbError>;
+
+    // AUTH EVENT related
+    /// log an authentication event, user_id is None if the user is unknown
+    async fn new_auth_event(
+        &self,
+        user_id: Option<i32>,
+        event: &str,
+        ip: Option<&str>,
+    ) -> Result<(), DbError>;
+    /// get the newest `limit` authentication events of a user, newest first
+    async fn get_auth_events_by_user(
+        &self,
+        user_id: i32,
+        limit: u32,
+    ) -> Result<Vec<AuthEvent>, DbError>;
+
+    // RETENTION related
+    /// log how many entries of a user have been purged by the retention task
+    async fn new_retention_log(&self, user_id: i32, purged_count: i32) -> Result<(), DbError>;
+    /// get the retention log of a user, newest first
+    async fn get_retention_logs_by_user(&self, user_id: i32) -> Result<Vec<RetentionLog>, DbError>;
+
+    // HISTORY related
+    /// store the state of an object before a change, returns the id of the history entry
+    async fn new_change_log(
+        &self,
+        user_id: i32,
+        used_for: &DBObjIdent,
+        object_id: i32,
+        action: &str,
+        snapshot: &CryptString,
+    ) -> Result<i32, DbError>;
+    /// get the history of an object of a user, newest first
+    async fn get_change_logs_by_object(
+        &self,
+        user_id: i32,
+        used_for: &DBObjIdent,
+        object_id: i32,
+    ) -> Result<Vec<ChangeLog>, DbError>;
+    /// get a single history entry, only if it belongs to the user
+    async fn get_change_log(&self, user_id: i32, change_id: i32) -> Result<ChangeLog, DbError>;
+    /// delete every history entry older than before, returns the number of deleted entries
+    async fn del_change_logs_before(&self, before: &NaiveDateTime) -> Result<usize, DbError>;
+
+    // STUDY GOAL related
+    /// replaces the topics of a study goal, the order of topic_ids is kept
+    async fn set_study_goal_topics(
+        &self,
+        user_id: i32,
+        goal_id: i32,
+        topic_ids: &[i32],
+    ) -> Result<(), DbError>;
+    /// get every link between study goals and topics of a user, ordered by goal and position
+    async fn get_study_goal_topics_by_user(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<StudyGoalTopic>, DbError>;
+    /// delete the links of a topic to all study goals of a user
+    async fn del_study_goal_topics_by_topic(
+        &self,
+        user_id: i32,
+        topic_id: i32,
+    ) -> Result<(), DbError>;
+    /// delete the links of a study goal to all topics of a user
+    async fn del_study_goal_topics_by_goal(
+        &self,
+        user_id: i32,
+        goal_id: i32,
+    ) -> Result<(), DbError>;
+
+    // EXPORT related
+    /// every row of the user in the given tables (see SQLGenerate::get_db_table) and the study goal links, read at once so the rows are consistent
+    /// rows in the trash are included, tables without rows of the user are contained empty
+    async fn export_user_data(
+        &self,
+        user_id: i32,
+        tables: &[&'static str],
+    ) -> Result<UserDataExport, DbError>;
 
     // DATA related, using generics and a few macros
     /// creates a new database table for the type T, which has to have the DBObject derive macro
-    fn create_table_for_type<T: SQLGenerate>(&self) -> Result<(), Box<dyn Error>>;
+    async fn create_table_for_type<T: SQLGenerate>(&self) -> Result<(), DbError>;
+    /// adds a column to the table of type T if it does not exist yet, used to migrate tables created by older versions
+    /// definition is the sql column definition, NOT NULL columns need a default value
+    async fn add_column_for_type<T: SQLGenerate>(
+        &self,
+        column: &str,
+        definition: &str,
+    ) -> Result<(), DbError>;
+    /// compares the table of type T with the columns of T, missing nullable or defaulted columns are added
+    /// fails with the statements needed to add the other missing columns
+    /// unique constraints the table lacks are added as unique indices
+    /// columns with another declared type than in T::field_types are only logged, sqlite still converts their values
+    async fn verify_schema_for_type<T: SQLGenerate>(&self) -> Result<(), DbError>;
     /// enters a new entry into the database table of the type T, a table using create_table_for_type has to be created beforehand.
-    fn new_entry<T: SQLGenerate>(
+    async fn new_entry<T: SQLGenerate>(
+        &self,
+        params: Vec<(String, SQLValue)>,
+    ) -> Result<i32, DbError>;
+    /// enters several new entries at once, either all of them or none are inserted
+    /// every row is a complete param list like for new_entry, returns the new ids in the order of the rows
+    async fn new_entries<T: SQLGenerate>(
+        &self,
+        rows: Vec<Vec<(String, SQLValue)>>,
+    ) -> Result<Vec<i32>, DbError>;
+    /// inserts an entry with an explicit id or updates the existing entry with this id, returns the id
+    /// params need to be a complete list like for new_entry including the id
+    /// returns none if the id belongs to an entry of another user, nothing is changed then
+    #[allow(dead_code)] // not used by the handlers yet
+    async fn upsert_entry<T: SQLGenerate>(
         &self,
         params: Vec<(String, SQLValue)>,
-    ) -> Result<i32, Box<dyn Error>>;
-    /// selects entries with where statement depending on which params are passed (values are params from query url, so we do not know which type, therefore everything is handled as String)
-    fn select_entries<T: SQLGenerate>(
+    ) -> Result<Option<i32>, DbError>;
+    /// selects entries with where statement depending on which params are passed (compared with their operator, values are bound with their type)
+    /// order lists the fields to sort by (validated against T::field_names), with pagination only one page of the entries is selected
+    /// ordered or paginated selects are additionally ordered by id, so the order is stable
+    async fn select_entries<T: SQLGenerate>(
+        &self,
+        params: Vec<WhereParam>,
+        order: &[(String, SortDirection)],
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<T>, DbError>;
+    /// like select_entries, with nested AND and OR groups in the WHERE statement
+    async fn select_entries_where<T: SQLGenerate>(
         &self,
-        params: Vec<(String, String)>,
-    ) -> Result<Vec<T>, Box<dyn Error>>;
+        where_clause: WhereClause,
+        order: &[(String, SortDirection)],
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<T>, DbError>;
+    /// counts the entries matching the where params, same params as select_entries
+    async fn count_entries<T: SQLGenerate>(&self, params: Vec<WhereParam>) -> Result<i64, DbError>;
     /// updates a single row, params are the changed parameters, where_params is the WHERE statement which selects what rows to update
-    fn update_entry<T: SQLGenerate>(
+    /// returns the number of updated rows, 0 if nothing matched
+    async fn update_entry<T: SQLGenerate>(
         &self,
         params: Vec<(String, SQLValue)>,
-        where_params: Vec<(String, SQLValue)>,
-    ) -> Result<(), Box<dyn Error>>;
+        where_params: Vec<WhereParam>,
+    ) -> Result<usize, DbError>;
     /// deletes one or more entries, params determines the where clause which selects what entries to delete
-    fn delete_entry<T: SQLGenerate>(
+    /// returns the number of deleted rows, 0 if nothing matched
+    async fn delete_entry<T: SQLGenerate>(&self, params: Vec<WhereParam>)
+    -> Result<usize, DbError>;
+    /// moves entries of a type with soft delete into the trash by setting deleted_at to now
+    /// returns the number of deleted rows, rows which are already in the trash are not counted
+    async fn soft_delete_entry<T: SQLGenerate>(
         &self,
-        params: Vec<(String, SQLValue)>,
-    ) -> Result<(), Box<dyn Error>>;
+        params: Vec<WhereParam>,
+    ) -> Result<usize, DbError>;
+    /// takes soft deleted entries out of the trash, if they were deleted at or after deleted_since
+    /// returns the number of restored rows, 0 if nothing matched
+    async fn restore_entry<T: SQLGenerate>(
+        &self,
+        params: Vec<WhereParam>,
+        deleted_since: &NaiveDateTime,
+    ) -> Result<usize, DbError>;
 }
 
 // AUTH structs, which are stored inside of the database
 /// struct stores username, id and password hash, used for login and registration
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct User {
     pub id: i32,
     pub username: String,
     pub password_hash: String,
     pub created_at: NaiveDateTime,
+    pub email: Option<String>,
+    /// consecutive wrong passwords since the last successful login or lockout
+    pub failed_logins: i32,
+    pub locked_until: Option<NaiveDateTime>,
 }
 /// struct that stores the local tokens encrypted by the users password
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LocalTokenPWCrypt {
     pub id: i32,
     pub user_id: i32,
@@ -117,16 +441,24 @@ pub struct LocalTokenPWCrypt {
 }
 /// struct that stores the local tokens encrypted by a remote token
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LocalTokenRTCrypt {
     pub id: i32,
     pub local_token_id: i32,
     pub local_token_crypt: CryptString,
     pub decryptable_by_rt_id: i32,
 }
+/// struct that stores the local tokens encrypted by the recovery key of the user
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct LocalTokenRecCrypt {
+    pub id: i32,
+    pub local_token_id: i32,
+    pub local_token_crypt: CryptString,
+}
 /// struct that stores a hash of a remote token, used for confirming that a remote token is valid
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RemoteToken {
     pub id: i32,
     pub rt_hash: String,
@@ -134,8 +466,103 @@ pub struct RemoteToken {
     pub valid_until: NaiveDateTime,
 }
 
+/// struct that stores a queued webhook event and its delivery state
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub subscription_id: i32,
+    pub payload: String,
+    pub status: String, // pending, delivered or failed
+    pub attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// struct that stores a single entry of the sync journal, ids are strictly increasing
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct SyncChange {
+    pub id: i64,
+    pub user_id: i32,
+    pub used_for: DBObjIdent,
+    pub object_id: i32,
+    pub deleted: bool,
+}
+
+/// struct that stores a snapshot of an object, encrypted with the secret of the share link
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct PublicShare {
+    pub id: i32,
+    pub user_id: i32,
+    pub used_for: DBObjIdent,
+    pub object_id: i32,
+    pub lookup_hash: String, // hash of the secret, the secret itself is never stored
+    pub content_crypt: CryptString,
+    pub expires_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+/// struct that stores a single authentication event (login, logout, ...)
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct AuthEvent {
+    pub id: i32,
+    pub user_id: Option<i32>,
+    pub event: String,
+    pub ip: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// struct that stores a single run of the retention task for a user
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct RetentionLog {
+    pub id: i32,
+    pub user_id: i32,
+    pub purged_count: i32,
+    pub ran_at: NaiveDateTime,
+}
+
+/// struct that stores the state of an object before it was changed
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct ChangeLog {
+    pub id: i32,
+    pub user_id: i32,
+    pub used_for: DBObjIdent,
+    pub object_id: i32,
+    pub action: String,        // edit, delete or revert
+    pub snapshot: CryptString, // json of the previous db row, encrypted with the local token of the type
+    pub changed_at: NaiveDateTime,
+}
+
+/// struct that stores a link between a study goal and one of its topics
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StudyGoalTopic {
+    pub goal_id: i32,
+    pub topic_id: i32,
+}
+
+/// raw rows of a user, as returned by export_user_data, nothing in here is decrypted
+#[derive(Debug, Clone, Default)]
+pub struct UserDataExport {
+    /// rows by table name, every row maps its column names to the stored values
+    pub tables: HashMap<String, Vec<HashMap<String, Value>>>,
+    pub study_goal_topics: Vec<StudyGoalTopic>,
+}
+
+/// page of a select, limit is the maximum number of entries, offset the number of skipped entries
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pagination {
+    pub limit: u32,
+    pub offset: u32,
+}
+
 /// DB object identifier, unique per DBObject
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DBObjIdent {
     pub db_identifier: String,
 }
@@ -0,0 +1,340 @@
+use std::error::Error;
+
+use log::{debug, info, warn};
+use rusqlite::{Connection, TransactionBehavior, params};
+
+/// one step of the schema of the fixed (non generic) tables, applied once in the order of its version
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    /// executed as batch, may be empty
+    pub sql: &'static str,
+    /// optional rust part, runs after the sql in the same transaction
+    pub run: Option<fn(&Connection) -> rusqlite::Result<()>>,
+}
+
+/// every migration, ordered by version, never change or remove released entries, append new ones
+/// databases created before versioning (version 0) already have some of the changes, so every step has to be idempotent
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        sql: "
+            CREATE TABLE IF NOT EXISTS user (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                username TEXT NOT NULL UNIQUE,
+                password_hash TEXT NOT NULL,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );
+
+            -- audit trail of logins, logouts etc., user_id is NULL for unknown usernames
+            CREATE TABLE IF NOT EXISTS auth_event (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER,
+                event TEXT NOT NULL,
+                ip TEXT,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );
+
+            -- local token table pw encrypted (stores encrypted local tokens)
+            -- these tokens are encrypted with the users password
+            CREATE TABLE IF NOT EXISTS pwcrypt_local_token (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                local_token BLOB NOT NULL,
+                used_for TEXT NOT NULL
+            );
+
+            -- local token table remote token encrypted (stores encrypted local tokens)
+            -- these tokens are encrypted with the remote token, which can be invalidated by deleting db entries in this table
+            -- resulting in a remote token only having access to local tokens, which have been encrypted with it.
+            CREATE TABLE IF NOT EXISTS rtcrypt_local_token (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                local_token_id INTEGER NOT NULL,
+                local_token BLOB NOT NULL,
+                decrypt_by_rt_id INTEGER NOT NULL
+            );
+
+            -- local token table recovery key encrypted, used for resetting a forgotten password
+            CREATE TABLE IF NOT EXISTS reccrypt_local_token (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                local_token_id INTEGER NOT NULL UNIQUE,
+                local_token BLOB NOT NULL
+            );
+
+            -- remote token hashes are stored in this table, used to write access
+            CREATE TABLE IF NOT EXISTS remote_token (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                rt_hash TEXT NOT NULL,
+                user_id INTEGER NOT NULL,
+                valid_until TIMESTAMP NOT NULL
+            );
+
+            -- outbox of webhook events, filled by the data handlers and emptied by the delivery worker
+            CREATE TABLE IF NOT EXISTS webhook_delivery (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                subscription_id INTEGER NOT NULL,
+                payload TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'pending',
+                attempts INTEGER NOT NULL DEFAULT 0,
+                next_attempt_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                last_error TEXT,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );
+
+            -- append only journal of all data changes, the id is used as sync cursor
+            -- deleted entries act as tombstones, as the object rows are gone
+            CREATE TABLE IF NOT EXISTS sync_change (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                used_for TEXT NOT NULL,
+                object_id INTEGER NOT NULL,
+                deleted INTEGER NOT NULL,
+                changed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );
+            CREATE INDEX IF NOT EXISTS sync_change_user ON sync_change (user_id, id);
+
+            -- snapshots of shared objects, encrypted with the secret of the link
+            CREATE TABLE IF NOT EXISTS public_share (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                used_for TEXT NOT NULL,
+                object_id INTEGER NOT NULL,
+                lookup_hash TEXT NOT NULL UNIQUE,
+                content BLOB NOT NULL,
+                expires_at TIMESTAMP,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );
+
+            -- one entry per retention run that purged something, visible to the user
+            CREATE TABLE IF NOT EXISTS retention_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                purged_count INTEGER NOT NULL,
+                ran_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );
+
+            -- a study goal can span several topics, position keeps the order chosen by the user
+            CREATE TABLE IF NOT EXISTS study_goal_topic (
+                user_id INTEGER NOT NULL,
+                goal_id INTEGER NOT NULL,
+                topic_id INTEGER NOT NULL,
+                position INTEGER NOT NULL,
+                PRIMARY KEY (goal_id, topic_id)
+            );
+            CREATE INDEX IF NOT EXISTS study_goal_topic_topic ON study_goal_topic (user_id, topic_id);
+
+            -- previous versions of edited and deleted objects, used for undo
+            CREATE TABLE IF NOT EXISTS change_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                used_for TEXT NOT NULL,
+                object_id INTEGER NOT NULL,
+                action TEXT NOT NULL,
+                snapshot BLOB NOT NULL,
+                changed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );
+            CREATE INDEX IF NOT EXISTS change_log_object ON change_log (user_id, used_for, object_id);
+        ",
+        run: None,
+    },
+    Migration {
+        version: 2,
+        description: "email of users",
+        sql: "",
+        run: Some(|conn| {
+            add_column(conn, "user", "email", "TEXT")?;
+            // sqlite can not add a unique column, NULLs do not collide in the index
+            conn.execute(
+                "CREATE UNIQUE INDEX IF NOT EXISTS user_unique_email ON user (email)",
+                [],
+            )?;
+            Ok(())
+        }),
+    },
+    Migration {
+        version: 3,
+        description: "login lockout",
+        sql: "",
+        run: Some(|conn| {
+            add_column(conn, "user", "failed_logins", "INTEGER NOT NULL DEFAULT 0")?;
+            add_column(conn, "user", "locked_until", "TIMESTAMP")
+        }),
+    },
+];
+
+/// version of the newest migration
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// version the database is at, 0 if it has never been migrated
+pub fn schema_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// applies every migration newer than the version of the database, each one in its own transaction
+pub fn run_migrations(conn: &mut Connection) -> Result<(), Box<dyn Error>> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    let version = schema_version(conn)?;
+    if version > latest_version() {
+        warn!(
+            "Database schema version {} is newer than the supported version {}!",
+            version,
+            latest_version()
+        );
+    }
+
+    for migration in MIGRATIONS {
+        // immediate, so two processes starting at once do not apply a migration twice
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        if migration.version <= schema_version(&tx)? {
+            continue;
+        }
+
+        tx.execute_batch(migration.sql)?;
+        if let Some(run) = migration.run {
+            run(&tx)?;
+        }
+        tx.execute(
+            "INSERT INTO schema_version (version) VALUES (?1)",
+            params![migration.version],
+        )?;
+        tx.commit()?;
+
+        info!(
+            "Migrated database to version {} ({})",
+            migration.version, migration.description
+        );
+    }
+
+    Ok(())
+}
+
+/// add a column to an existing table, does nothing if it already exists
+pub fn add_column(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    let exists: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+        params![table, column],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        debug!("Adding column {} to table {}", column, table);
+        let sql = format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition);
+        conn.execute(&sql, [])?;
+    }
+
+    Ok(())
+}
+
+/// add a unique index over the columns of an existing table, does nothing if a unique index or constraint over them exists
+pub fn add_unique_index(conn: &Connection, table: &str, columns: &[&str]) -> rusqlite::Result<()> {
+    let mut wanted: Vec<&str> = columns.to_vec();
+    wanted.sort_unstable();
+
+    // constraints of the create statement are indices as well (sqlite_autoindex_...)
+    let mut stmt = conn.prepare("SELECT name FROM pragma_index_list(?1) WHERE \"unique\" = 1")?;
+    let indices = stmt
+        .query_map(params![table], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut stmt = conn.prepare("SELECT name FROM pragma_index_info(?1)")?;
+    for index in indices {
+        let mut indexed = stmt
+            .query_map(params![index], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        indexed.sort_unstable();
+        if indexed == wanted {
+            return Ok(());
+        }
+    }
+
+    debug!("Adding unique index over {:?} to table {}", columns, table);
+    let sql = format!(
+        "CREATE UNIQUE INDEX IF NOT EXISTS {}_unique_{} ON {} ({})",
+        table,
+        columns.join("_"),
+        table,
+        columns.join(", ")
+    );
+    conn.execute(&sql, [])?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use crate::db::{DBInterface, sqlite::SqliteDatabase};
+
+    use super::{MIGRATIONS, latest_version, run_migrations, schema_version};
+
+    #[test]
+    fn migrations_are_ordered() {
+        assert!(
+            MIGRATIONS
+                .windows(2)
+                .all(|pair| pair[0].version < pair[1].version)
+        );
+    }
+
+    #[tokio::test]
+    async fn v0_database_is_migrated_to_the_current_version() {
+        let path =
+            std::env::temp_dir().join(format!("eduflow_migration_test_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        // database of the first release, before versioning, email and lockout existed
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE user (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                username TEXT NOT NULL UNIQUE,
+                password_hash TEXT NOT NULL,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );
+            INSERT INTO user (username, password_hash) VALUES ('old_user', 'hash');",
+        )
+        .unwrap();
+        drop(conn);
+
+        let db = SqliteDatabase::new(&path).unwrap();
+        let user = db.get_user_by_username("old_user").await.unwrap();
+        assert_eq!(user.email, None);
+        assert_eq!(user.failed_logins, 0);
+        assert!(
+            db.new_user("new_user", "hash", Some("a@b.de"))
+                .await
+                .is_ok()
+        );
+        drop(db);
+
+        // opening it again applies nothing
+        let mut conn = Connection::open(&path).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+        run_migrations(&mut conn).unwrap();
+        let applied: u32 = conn
+            .query_row("SELECT COUNT(*) FROM schema_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(applied as usize, MIGRATIONS.len());
+        drop(conn);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,1497 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::{Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use rusqlite::types::Value;
+
+use crate::crypt::crypt_types::CryptString;
+
+use super::{
+    AuthEvent, BoxFuture, ChangeLog, DBInterface, DBObjIdent, DbError, LocalTokenPWCrypt,
+    LocalTokenRTCrypt, LocalTokenRecCrypt, Pagination, PublicShare, RemoteToken, RetentionLog,
+    StudyGoalTopic, SyncChange, User, UserDataExport, WebhookDelivery,
+    sql_helper::{
+        SQLGenError, SQLGenerate, SQLValue, SortDirection, WhereClause, WhereOp, WhereParam,
+        order_or_default,
+    },
+};
+
+/// values of a row of a generic table by column name
+type Row = HashMap<String, Value>;
+
+/// DBInterface keeping everything in memory, used to test the handlers without sqlite
+/// failures of single methods can be injected with fail_next, to test the error branches of the handlers
+/// foreign keys and checks are not enforced, unique constraints only where the sqlite schema has them
+#[derive(Default)]
+pub struct MockDatabase {
+    data: RwLock<MockData>,
+    /// errors returned by the next call of a method, by method name
+    failures: Mutex<HashMap<&'static str, DbError>>,
+}
+
+/// every table of the database, cloned to roll back a failed transaction
+#[derive(Default, Clone)]
+struct MockData {
+    users: Vec<User>,
+    local_tokens_pwcrypt: Vec<LocalTokenPWCrypt>,
+    local_tokens_rtcrypt: Vec<LocalTokenRTCrypt>,
+    local_tokens_reccrypt: Vec<LocalTokenRecCrypt>,
+    remote_tokens: Vec<RemoteToken>,
+    webhook_deliveries: Vec<WebhookDelivery>,
+    sync_changes: Vec<SyncChange>,
+    public_shares: Vec<PublicShare>,
+    auth_events: Vec<AuthEvent>,
+    retention_logs: Vec<RetentionLog>,
+    change_logs: Vec<ChangeLog>,
+    /// (user_id, link), in the order of the positions
+    study_goal_topics: Vec<(i32, StudyGoalTopic)>,
+    /// tables of the data objects by table name
+    tables: HashMap<String, MockTable>,
+    /// last autoincrement id by table
+    last_ids: HashMap<&'static str, i64>,
+}
+
+/// table of a data object
+#[derive(Default, Clone)]
+struct MockTable {
+    /// rows by id, iterated in id order like the rowid order of sqlite
+    rows: BTreeMap<i32, Row>,
+    /// name and definition of every column besides id
+    columns: Vec<(String, String)>,
+    /// columns of the unique indices
+    unique: Vec<Vec<String>>,
+    /// column numbered within the rows of the user with the same value in the group column
+    appended: Option<(&'static str, &'static str)>,
+    last_id: i32,
+}
+
+impl MockDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the next call of method (e.g. "del_remote_token") fails with err, later calls work again
+    pub fn fail_next(&self, method: &'static str, err: DbError) {
+        self.failures
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(method, err);
+    }
+
+    /// returns the injected failure of the method, if there is one
+    fn check(&self, method: &'static str) -> Result<(), DbError> {
+        match self
+            .failures
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(method)
+        {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, MockData> {
+        self.data.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, MockData> {
+        self.data.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl MockData {
+    fn next_id(&mut self, table: &'static str) -> i64 {
+        let id = self.last_ids.entry(table).or_default();
+        *id += 1;
+        *id
+    }
+
+    fn table<T: SQLGenerate>(&self) -> Result<&MockTable, DbError> {
+        let name = T::get_db_table();
+        self.tables
+            .get(name)
+            .ok_or_else(|| DbError::Other(format!("no such table: {}", name)))
+    }
+
+    fn table_mut<T: SQLGenerate>(&mut self) -> Result<&mut MockTable, DbError> {
+        let name = T::get_db_table();
+        self.tables
+            .get_mut(name)
+            .ok_or_else(|| DbError::Other(format!("no such table: {}", name)))
+    }
+}
+
+impl MockTable {
+    /// true if the type has the timestamps attribute, only then created_at has a default
+    fn timestamps(&self) -> bool {
+        self.columns
+            .iter()
+            .any(|(column, definition)| column == "created_at" && definition.contains("DEFAULT"))
+    }
+
+    /// inserts a row with the given id or the next autoincrement id, missing columns are NULL
+    fn insert(&mut self, id: Option<i32>, params: &[(String, SQLValue)]) -> Result<i32, DbError> {
+        let id = id.unwrap_or(self.last_id + 1);
+        if self.rows.contains_key(&id) {
+            return Err(DbError::UniqueViolation("id".to_string()));
+        }
+
+        let mut row: Row = params
+            .iter()
+            .map(|(field, value)| (field.clone(), to_value(value)))
+            .collect();
+        if let Some((column, group)) = self.appended
+            && !row.contains_key(column)
+            && let (Some(user_id), Some(group_value)) = (row.get("user_id"), row.get(group))
+        {
+            let next = self
+                .rows
+                .values()
+                .filter(|other| {
+                    other.get("user_id") == Some(user_id) && other.get(group) == Some(group_value)
+                })
+                .filter_map(|other| match other.get(column) {
+                    Some(Value::Integer(value)) => Some(value + 1),
+                    _ => None,
+                })
+                .max()
+                .unwrap_or_default();
+            row.insert(column.to_string(), Value::Integer(next));
+        }
+        for (column, definition) in &self.columns {
+            if !row.contains_key(column)
+                && let Some(default) = column_default(definition)
+            {
+                row.insert(column.clone(), default);
+            }
+        }
+        for (column, definition) in &self.columns {
+            if definition.contains("NOT NULL") && row.get(column).is_none_or(|v| *v == Value::Null)
+            {
+                return Err(DbError::Other(format!(
+                    "NOT NULL constraint failed: {}",
+                    column
+                )));
+            }
+        }
+        row.insert("id".to_string(), Value::Integer(id.into()));
+        self.check_unique(&row)?;
+
+        self.rows.insert(id, row);
+        self.last_id = self.last_id.max(id);
+        Ok(id)
+    }
+
+    /// sets the params of the row with the id, updated_at is set unless it is one of the params
+    fn update(&mut self, id: i32, params: &[(String, SQLValue)]) -> Result<(), DbError> {
+        let mut row = self.rows[&id].clone();
+        for (field, value) in params {
+            row.insert(field.clone(), to_value(value));
+        }
+        if self.timestamps() && !params.iter().any(|(field, _)| field == "updated_at") {
+            row.insert("updated_at".to_string(), now());
+        }
+        self.check_unique(&row)?;
+
+        self.rows.insert(id, row);
+        Ok(())
+    }
+
+    /// fails if another row has the same values in the columns of a unique index, NULLs never collide
+    fn check_unique(&self, row: &Row) -> Result<(), DbError> {
+        for columns in &self.unique {
+            let collides = self.rows.values().any(|other| {
+                other["id"] != row["id"]
+                    && columns
+                        .iter()
+                        .all(|column| match (other.get(column), row.get(column)) {
+                            (Some(a), Some(b)) => compare(a, b) == Some(Ordering::Equal),
+                            _ => false,
+                        })
+            });
+            if collides {
+                return Err(DbError::UniqueViolation(columns.join(", ")));
+            }
+        }
+        Ok(())
+    }
+
+    /// ids of the rows matching the clause, soft deleted rows are left out if hide_deleted is set
+    fn matching(&self, where_clause: &WhereClause, hide_deleted: bool) -> Vec<i32> {
+        self.rows
+            .iter()
+            .filter(|(_, row)| {
+                !hide_deleted || row.get("deleted_at").is_none_or(|v| *v == Value::Null)
+            })
+            .filter(|(_, row)| matches(where_clause, row))
+            .map(|(id, _)| *id)
+            .collect()
+    }
+}
+
+/// converts a value like it is bound by the sqlite implementation, dates are stored as text
+fn to_value(value: &SQLValue) -> Value {
+    match value {
+        SQLValue::Text(text) => Value::Text(text.clone()),
+        SQLValue::Int32(int) => Value::Integer((*int).into()),
+        SQLValue::Int64(int) => Value::Integer(*int),
+        SQLValue::Blob(blob) => Value::Blob(blob.clone()),
+        SQLValue::Float64(float) => Value::Real(*float),
+        SQLValue::Date(date) => Value::Text(date.format("%F").to_string()),
+        SQLValue::DateTime(date_time) => Value::Text(date_time.format("%F %T%.f").to_string()),
+        SQLValue::Bool(flag) => Value::Integer((*flag).into()),
+        SQLValue::Null => Value::Null,
+        SQLValue::List(_) => unreachable!("lists are only compared with IN"),
+    }
+}
+
+/// the current time as it is stored by CURRENT_TIMESTAMP
+fn now() -> Value {
+    to_value(&SQLValue::from(Utc::now().naive_utc()))
+}
+
+/// value of the DEFAULT clause of a column definition, only the constants the derive allows
+fn column_default(definition: &str) -> Option<Value> {
+    let (_, default) = definition.split_once(" DEFAULT ")?;
+    // a CHECK or REFERENCES clause may follow
+    let default = default.split(" CONSTRAINT ").next()?;
+    let default = default.split(" REFERENCES ").next()?;
+    let now = Utc::now().naive_utc();
+    Some(match default {
+        "NULL" => Value::Null,
+        "TRUE" => Value::Integer(1),
+        "FALSE" => Value::Integer(0),
+        "CURRENT_TIMESTAMP" => to_value(&SQLValue::from(now)),
+        "CURRENT_DATE" => to_value(&SQLValue::from(now.date())),
+        "CURRENT_TIME" => Value::Text(now.format("%H:%M:%S").to_string()),
+        text if text.starts_with('\'') => Value::Text(text[1..text.len() - 1].replace("''", "'")),
+        number => match number.parse::<i64>() {
+            Ok(integer) => Value::Integer(integer),
+            Err(_) => Value::Real(number.parse().ok()?),
+        },
+    })
+}
+
+/// compares two values like sqlite, none if one of them is NULL
+/// values of different types are ordered numbers, text, blobs
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    let rank = |value: &Value| match value {
+        Value::Null => 0,
+        Value::Integer(_) | Value::Real(_) => 1,
+        Value::Text(_) => 2,
+        Value::Blob(_) => 3,
+    };
+
+    match (a, b) {
+        (Value::Null, _) | (_, Value::Null) => None,
+        (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+        (Value::Integer(a), Value::Real(b)) => (*a as f64).partial_cmp(b),
+        (Value::Real(a), Value::Integer(b)) => a.partial_cmp(&(*b as f64)),
+        (Value::Real(a), Value::Real(b)) => a.partial_cmp(b),
+        (Value::Text(a), Value::Text(b)) => Some(a.cmp(b)),
+        (Value::Blob(a), Value::Blob(b)) => Some(a.cmp(b)),
+        (a, b) => Some(rank(a).cmp(&rank(b))),
+    }
+}
+
+/// sql LIKE, % matches any text and _ a single character, ascii letters are compared case insensitive
+fn like(value: &[char], pattern: &[char]) -> bool {
+    match pattern.split_first() {
+        None => value.is_empty(),
+        Some(('%', rest)) => (0..=value.len()).any(|skip| like(&value[skip..], rest)),
+        Some((c, rest)) => value.split_first().is_some_and(|(v, value)| {
+            (*c == '_' || c.eq_ignore_ascii_case(v)) && like(value, rest)
+        }),
+    }
+}
+
+/// true if the row fulfills the condition, comparisons with NULL are never true
+fn condition(param: &WhereParam, row: &Row) -> bool {
+    let value = row.get(&param.field).unwrap_or(&Value::Null);
+    let ordering = || compare(value, &to_value(&param.value));
+
+    match param.op {
+        WhereOp::Eq => ordering() == Some(Ordering::Equal),
+        WhereOp::Ne => ordering().is_some_and(Ordering::is_ne),
+        WhereOp::Lt => ordering() == Some(Ordering::Less),
+        WhereOp::Le => ordering().is_some_and(Ordering::is_le),
+        WhereOp::Gt => ordering() == Some(Ordering::Greater),
+        WhereOp::Ge => ordering().is_some_and(Ordering::is_ge),
+        WhereOp::Like => match (value, &param.value) {
+            (Value::Text(text), SQLValue::Text(pattern)) => like(
+                &text.chars().collect::<Vec<_>>(),
+                &pattern.chars().collect::<Vec<_>>(),
+            ),
+            _ => false,
+        },
+        WhereOp::In(_) => match &param.value {
+            SQLValue::List(values) => values
+                .iter()
+                .any(|other| compare(value, &to_value(other)) == Some(Ordering::Equal)),
+            other => compare(value, &to_value(other)) == Some(Ordering::Equal),
+        },
+        WhereOp::IsNull => *value == Value::Null,
+        WhereOp::IsNotNull => *value != Value::Null,
+    }
+}
+
+/// true if the row fulfills the clause
+fn matches(where_clause: &WhereClause, row: &Row) -> bool {
+    match where_clause {
+        WhereClause::And(clauses) => clauses.iter().all(|clause| matches(clause, row)),
+        WhereClause::Or(clauses) => clauses.iter().any(|clause| matches(clause, row)),
+        WhereClause::Cond(param) => condition(param, row),
+    }
+}
+
+/// the sqlite implementation validates the same way while generating the statement
+fn where_fields(params: &[WhereParam]) -> Vec<(&String, WhereOp)> {
+    params.iter().map(WhereParam::as_where_field).collect()
+}
+
+#[async_trait]
+impl DBInterface for MockDatabase {
+    // a failing f rolls back everything, also the changes of concurrent calls
+    async fn with_transaction<R, F>(&self, f: F) -> Result<R, DbError>
+    where
+        R: Send,
+        F: for<'a> FnOnce(&'a Self) -> BoxFuture<'a, Result<R, DbError>> + Send,
+    {
+        self.check("with_transaction")?;
+        let snapshot = self.read().clone();
+        let result = f(self).await;
+        if result.is_err() {
+            *self.write() = snapshot;
+        }
+        result
+    }
+
+    async fn backup_to<P: AsRef<Path> + Send>(&self, _path: P) -> Result<(), DbError> {
+        self.check("backup_to")?;
+        Err(DbError::Other(
+            "the mock database can not be backed up".to_string(),
+        ))
+    }
+
+    // AUTH OBJECTS
+
+    // user related
+    async fn new_user(
+        &self,
+        username: &str,
+        password_hash: &str,
+        email: Option<&str>,
+    ) -> Result<i32, DbError> {
+        self.check("new_user")?;
+        let mut data = self.write();
+        if data.users.iter().any(|user| user.username == username) {
+            return Err(DbError::UniqueViolation("user.username".to_string()));
+        }
+        if email.is_some() && data.users.iter().any(|user| user.email.as_deref() == email) {
+            return Err(DbError::UniqueViolation("user.email".to_string()));
+        }
+
+        let id = data.next_id("user") as i32;
+        data.users.push(User {
+            id,
+            username: username.to_string(),
+            password_hash: password_hash.to_string(),
+            created_at: Utc::now().naive_utc(),
+            email: email.map(str::to_string),
+            failed_logins: 0,
+            locked_until: None,
+        });
+        Ok(id)
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> Result<User, DbError> {
+        self.check("get_user_by_username")?;
+        self.read()
+            .users
+            .iter()
+            .find(|user| user.username == username)
+            .cloned()
+            .ok_or(DbError::NotFound)
+    }
+
+    async fn get_user_by_id(&self, user_id: i32) -> Result<User, DbError> {
+        self.check("get_user_by_id")?;
+        self.read()
+            .users
+            .iter()
+            .find(|user| user.id == user_id)
+            .cloned()
+            .ok_or(DbError::NotFound)
+    }
+
+    async fn increment_failed_logins(&self, user_id: i32) -> Result<i32, DbError> {
+        self.check("increment_failed_logins")?;
+        let mut data = self.write();
+        let user = data
+            .users
+            .iter_mut()
+            .find(|user| user.id == user_id)
+            .ok_or(DbError::NotFound)?;
+        user.failed_logins += 1;
+        Ok(user.failed_logins)
+    }
+
+    async fn lock_user(&self, user_id: i32, locked_until: &NaiveDateTime) -> Result<(), DbError> {
+        self.check("lock_user")?;
+        if let Some(user) = self
+            .write()
+            .users
+            .iter_mut()
+            .find(|user| user.id == user_id)
+        {
+            user.failed_logins = 0;
+            user.locked_until = Some(*locked_until);
+        }
+        Ok(())
+    }
+
+    async fn reset_failed_logins(&self, user_id: i32) -> Result<(), DbError> {
+        self.check("reset_failed_logins")?;
+        if let Some(user) = self
+            .write()
+            .users
+            .iter_mut()
+            .find(|user| user.id == user_id)
+        {
+            user.failed_logins = 0;
+            user.locked_until = None;
+        }
+        Ok(())
+    }
+
+    async fn del_user(&self, user_id: i32) -> Result<(), DbError> {
+        self.check("del_user")?;
+        let mut data = self.write();
+        data.sync_changes.retain(|change| change.user_id != user_id);
+        data.public_shares.retain(|share| share.user_id != user_id);
+        data.retention_logs.retain(|log| log.user_id != user_id);
+        data.auth_events
+            .retain(|event| event.user_id != Some(user_id));
+        data.study_goal_topics
+            .retain(|(link_user_id, _)| *link_user_id != user_id);
+        data.change_logs.retain(|change| change.user_id != user_id);
+        data.users.retain(|user| user.id != user_id);
+        Ok(())
+    }
+
+    async fn update_user_password_hash(
+        &self,
+        user_id: i32,
+        password_hash: &str,
+        local_tokens: &[(i32, CryptString)],
+    ) -> Result<(), DbError> {
+        self.check("update_user_password_hash")?;
+        let mut data = self.write();
+        // checked first, nothing is written if one of the tokens does not belong to the user
+        let owned = |id: &i32| {
+            data.local_tokens_pwcrypt
+                .iter()
+                .any(|lt| lt.id == *id && lt.user_id == user_id)
+        };
+        if !local_tokens.iter().all(|(id, _)| owned(id)) {
+            return Err(DbError::NotFound);
+        }
+
+        for (local_token_id, token_crypt) in local_tokens {
+            if let Some(lt) = data
+                .local_tokens_pwcrypt
+                .iter_mut()
+                .find(|lt| lt.id == *local_token_id)
+            {
+                lt.token_crypt = token_crypt.clone();
+            }
+        }
+        if let Some(user) = data.users.iter_mut().find(|user| user.id == user_id) {
+            user.password_hash = password_hash.to_string();
+        }
+        Ok(())
+    }
+
+    // token related
+    async fn new_local_token_pwcrypt(
+        &self,
+        user_id: i32,
+        token_crypt: &CryptString,
+        used_for: &DBObjIdent,
+    ) -> Result<(), DbError> {
+        self.check("new_local_token_pwcrypt")?;
+        let mut data = self.write();
+        let id = data.next_id("pwcrypt_local_token") as i32;
+        data.local_tokens_pwcrypt.push(LocalTokenPWCrypt {
+            id,
+            user_id,
+            token_crypt: token_crypt.clone(),
+            used_for: used_for.clone(),
+        });
+        Ok(())
+    }
+
+    async fn new_local_token_rtcrypt(
+        &self,
+        local_token_id: i32,
+        local_token_crypt: &CryptString,
+        decryptable_by_rt_id: i32,
+    ) -> Result<(), DbError> {
+        self.check("new_local_token_rtcrypt")?;
+        let mut data = self.write();
+        let id = data.next_id("rtcrypt_local_token") as i32;
+        data.local_tokens_rtcrypt.push(LocalTokenRTCrypt {
+            id,
+            local_token_id,
+            local_token_crypt: local_token_crypt.clone(),
+            decryptable_by_rt_id,
+        });
+        Ok(())
+    }
+
+    async fn new_remote_token(
+        &self,
+        rt_hash: &str,
+        user_id: i32,
+        valid_until: &NaiveDateTime,
+        local_tokens: &[(i32, CryptString)],
+    ) -> Result<i64, DbError> {
+        self.check("new_remote_token")?;
+        let mut data = self.write();
+        let id = data.next_id("remote_token");
+        data.remote_tokens.push(RemoteToken {
+            id: id as i32,
+            rt_hash: rt_hash.to_string(),
+            user_id,
+            valid_until: *valid_until,
+        });
+        for (local_token_id, local_token_crypt) in local_tokens {
+            let lt_id = data.next_id("rtcrypt_local_token") as i32;
+            data.local_tokens_rtcrypt.push(LocalTokenRTCrypt {
+                id: lt_id,
+                local_token_id: *local_token_id,
+                local_token_crypt: local_token_crypt.clone(),
+                decryptable_by_rt_id: id as i32,
+            });
+        }
+        Ok(id)
+    }
+
+    async fn new_local_token_reccrypt(
+        &self,
+        local_token_id: i32,
+        local_token_crypt: &CryptString,
+    ) -> Result<(), DbError> {
+        self.check("new_local_token_reccrypt")?;
+        let mut data = self.write();
+        if data
+            .local_tokens_reccrypt
+            .iter()
+            .any(|lt| lt.local_token_id == local_token_id)
+        {
+            return Err(DbError::UniqueViolation(
+                "reccrypt_local_token.local_token_id".to_string(),
+            ));
+        }
+        let id = data.next_id("reccrypt_local_token") as i32;
+        data.local_tokens_reccrypt.push(LocalTokenRecCrypt {
+            id,
+            local_token_id,
+            local_token_crypt: local_token_crypt.clone(),
+        });
+        Ok(())
+    }
+
+    async fn update_local_token_pwcrypt(
+        &self,
+        local_token_id: i32,
+        token_crypt: &CryptString,
+    ) -> Result<(), DbError> {
+        self.check("update_local_token_pwcrypt")?;
+        if let Some(lt) = self
+            .write()
+            .local_tokens_pwcrypt
+            .iter_mut()
+            .find(|lt| lt.id == local_token_id)
+        {
+            lt.token_crypt = token_crypt.clone();
+        }
+        Ok(())
+    }
+
+    async fn get_local_tokens_by_user_pwcrypt(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<LocalTokenPWCrypt>, DbError> {
+        self.check("get_local_tokens_by_user_pwcrypt")?;
+        Ok(self
+            .read()
+            .local_tokens_pwcrypt
+            .iter()
+            .filter(|lt| lt.user_id == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_local_token_by_used_for_pwcrypt(
+        &self,
+        user_id: i32,
+        used_for: &DBObjIdent,
+    ) -> Result<LocalTokenPWCrypt, DbError> {
+        self.check("get_local_token_by_used_for_pwcrypt")?;
+        self.read()
+            .local_tokens_pwcrypt
+            .iter()
+            .find(|lt| lt.user_id == user_id && lt.used_for.db_identifier == used_for.db_identifier)
+            .cloned()
+            .ok_or(DbError::NotFound)
+    }
+
+    async fn get_local_tokens_by_user_reccrypt(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<LocalTokenRecCrypt>, DbError> {
+        self.check("get_local_tokens_by_user_reccrypt")?;
+        let data = self.read();
+        Ok(data
+            .local_tokens_reccrypt
+            .iter()
+            .filter(|lt| {
+                data.local_tokens_pwcrypt
+                    .iter()
+                    .any(|pw| pw.id == lt.local_token_id && pw.user_id == user_id)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn get_local_token_by_id_rtcrypt(
+        &self,
+        local_token_id: i32,
+        remote_token_id: i32,
+    ) -> Result<Option<LocalTokenRTCrypt>, DbError> {
+        self.check("get_local_token_by_id_rtcrypt")?;
+        Ok(self
+            .read()
+            .local_tokens_rtcrypt
+            .iter()
+            .find(|lt| {
+                lt.local_token_id == local_token_id && lt.decryptable_by_rt_id == remote_token_id
+            })
+            .cloned())
+    }
+
+    async fn get_local_token_ids_by_rt(&self, remote_token_id: i32) -> Result<Vec<i32>, DbError> {
+        self.check("get_local_token_ids_by_rt")?;
+        Ok(self
+            .read()
+            .local_tokens_rtcrypt
+            .iter()
+            .filter(|lt| lt.decryptable_by_rt_id == remote_token_id)
+            .map(|lt| lt.local_token_id)
+            .collect())
+    }
+
+    async fn get_remote_token(&self, token_id: i32) -> Result<Option<RemoteToken>, DbError> {
+        self.check("get_remote_token")?;
+        Ok(self
+            .read()
+            .remote_tokens
+            .iter()
+            .find(|rt| rt.id == token_id)
+            .cloned())
+    }
+
+    async fn get_expired_remote_tokens(
+        &self,
+        now: &NaiveDateTime,
+    ) -> Result<Vec<RemoteToken>, DbError> {
+        self.check("get_expired_remote_tokens")?;
+        Ok(self
+            .read()
+            .remote_tokens
+            .iter()
+            .filter(|rt| rt.valid_until <= *now)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_remote_tokens_by_user(&self, user_id: i32) -> Result<Vec<RemoteToken>, DbError> {
+        self.check("get_remote_tokens_by_user")?;
+        Ok(self
+            .read()
+            .remote_tokens
+            .iter()
+            .filter(|rt| rt.user_id == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn update_remote_token_validity(
+        &self,
+        token_id: i32,
+        new_valid_until: &NaiveDateTime,
+    ) -> Result<(), DbError> {
+        self.check("update_remote_token_validity")?;
+        if let Some(rt) = self
+            .write()
+            .remote_tokens
+            .iter_mut()
+            .find(|rt| rt.id == token_id)
+        {
+            rt.valid_until = *new_valid_until;
+        }
+        Ok(())
+    }
+
+    async fn del_local_token_rtcrypt_by_rt(&self, remote_token_id: i32) -> Result<(), DbError> {
+        self.check("del_local_token_rtcrypt_by_rt")?;
+        self.write()
+            .local_tokens_rtcrypt
+            .retain(|lt| lt.decryptable_by_rt_id != remote_token_id);
+        Ok(())
+    }
+
+    async fn del_local_token_rtcrypt_by_lt(&self, local_token_id: i32) -> Result<(), DbError> {
+        self.check("del_local_token_rtcrypt_by_lt")?;
+        self.write()
+            .local_tokens_rtcrypt
+            .retain(|lt| lt.local_token_id != local_token_id);
+        Ok(())
+    }
+
+    async fn del_local_token_reccrypt(&self, local_token_id: i32) -> Result<(), DbError> {
+        self.check("del_local_token_reccrypt")?;
+        self.write()
+            .local_tokens_reccrypt
+            .retain(|lt| lt.local_token_id != local_token_id);
+        Ok(())
+    }
+
+    async fn del_local_tokens_by_user(&self, user_id: i32) -> Result<(), DbError> {
+        self.check("del_local_tokens_by_user")?;
+        let mut data = self.write();
+        let ids: Vec<i32> = data
+            .local_tokens_pwcrypt
+            .iter()
+            .filter(|lt| lt.user_id == user_id)
+            .map(|lt| lt.id)
+            .collect();
+        data.local_tokens_rtcrypt
+            .retain(|lt| !ids.contains(&lt.local_token_id));
+        data.local_tokens_reccrypt
+            .retain(|lt| !ids.contains(&lt.local_token_id));
+        data.local_tokens_pwcrypt.retain(|lt| lt.user_id != user_id);
+        Ok(())
+    }
+
+    async fn del_remote_token(&self, remote_token_id: i32) -> Result<(), DbError> {
+        self.check("del_remote_token")?;
+        self.write()
+            .remote_tokens
+            .retain(|rt| rt.id != remote_token_id);
+        Ok(())
+    }
+
+    async fn del_remote_tokens_by_user(
+        &self,
+        user_id: i32,
+        keep_rt_id: Option<i32>,
+    ) -> Result<(), DbError> {
+        self.check("del_remote_tokens_by_user")?;
+        let mut data = self.write();
+        let removed = |rt: &RemoteToken| rt.user_id == user_id && Some(rt.id) != keep_rt_id;
+        let ids: Vec<i32> = data
+            .remote_tokens
+            .iter()
+            .filter(|rt| removed(rt))
+            .map(|rt| rt.id)
+            .collect();
+        data.local_tokens_rtcrypt
+            .retain(|lt| !ids.contains(&lt.decryptable_by_rt_id));
+        data.remote_tokens.retain(|rt| !removed(rt));
+        Ok(())
+    }
+
+    // WEBHOOKS
+    async fn new_webhook_delivery(
+        &self,
+        subscription_id: i32,
+        payload: &str,
+    ) -> Result<i32, DbError> {
+        self.check("new_webhook_delivery")?;
+        let mut data = self.write();
+        let id = data.next_id("webhook_delivery") as i32;
+        let now = Utc::now().naive_utc();
+        data.webhook_deliveries.push(WebhookDelivery {
+            id,
+            subscription_id,
+            payload: payload.to_string(),
+            status: "pending".to_string(),
+            attempts: 0,
+            next_attempt_at: now,
+            last_error: None,
+            created_at: now,
+        });
+        Ok(id)
+    }
+
+    async fn get_due_webhook_deliveries(
+        &self,
+        now: &NaiveDateTime,
+    ) -> Result<Vec<WebhookDelivery>, DbError> {
+        self.check("get_due_webhook_deliveries")?;
+        Ok(self
+            .read()
+            .webhook_deliveries
+            .iter()
+            .filter(|delivery| delivery.status == "pending" && delivery.next_attempt_at <= *now)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_webhook_deliveries_by_subscription(
+        &self,
+        subscription_id: i32,
+    ) -> Result<Vec<WebhookDelivery>, DbError> {
+        self.check("get_webhook_deliveries_by_subscription")?;
+        Ok(self
+            .read()
+            .webhook_deliveries
+            .iter()
+            .rev()
+            .filter(|delivery| delivery.subscription_id == subscription_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn del_webhook_deliveries_by_subscription(
+        &self,
+        subscription_id: i32,
+    ) -> Result<(), DbError> {
+        self.check("del_webhook_deliveries_by_subscription")?;
+        self.write()
+            .webhook_deliveries
+            .retain(|delivery| delivery.subscription_id != subscription_id);
+        Ok(())
+    }
+
+    async fn update_webhook_delivery(&self, delivery: &WebhookDelivery) -> Result<(), DbError> {
+        self.check("update_webhook_delivery")?;
+        if let Some(stored) = self
+            .write()
+            .webhook_deliveries
+            .iter_mut()
+            .find(|stored| stored.id == delivery.id)
+        {
+            stored.status = delivery.status.clone();
+            stored.attempts = delivery.attempts;
+            stored.next_attempt_at = delivery.next_attempt_at;
+            stored.last_error = delivery.last_error.clone();
+        }
+        Ok(())
+    }
+
+    // SYNC
+    async fn new_sync_change(
+        &self,
+        user_id: i32,
+        used_for: &DBObjIdent,
+        object_id: i32,
+        deleted: bool,
+    ) -> Result<(), DbError> {
+        self.check("new_sync_change")?;
+        let mut data = self.write();
+        let id = data.next_id("sync_change");
+        data.sync_changes.push(SyncChange {
+            id,
+            user_id,
+            used_for: used_for.clone(),
+            object_id,
+            deleted,
+        });
+        Ok(())
+    }
+
+    async fn get_sync_changes(
+        &self,
+        user_id: i32,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<SyncChange>, DbError> {
+        self.check("get_sync_changes")?;
+        Ok(self
+            .read()
+            .sync_changes
+            .iter()
+            .filter(|change| change.user_id == user_id && change.id > after_id)
+            .take(limit.try_into().unwrap_or(usize::MAX))
+            .cloned()
+            .collect())
+    }
+
+    async fn get_latest_sync_change_id(&self, user_id: i32) -> Result<i64, DbError> {
+        self.check("get_latest_sync_change_id")?;
+        Ok(self
+            .read()
+            .sync_changes
+            .iter()
+            .filter(|change| change.user_id == user_id)
+            .map(|change| change.id)
+            .max()
+            .unwrap_or(0))
+    }
+
+    // SHARES
+    async fn new_public_share(
+        &self,
+        user_id: i32,
+        used_for: &DBObjIdent,
+        object_id: i32,
+        lookup_hash: &str,
+        content_crypt: &CryptString,
+        expires_at: Option<&NaiveDateTime>,
+    ) -> Result<i32, DbError> {
+        self.check("new_public_share")?;
+        let mut data = self.write();
+        if data
+            .public_shares
+            .iter()
+            .any(|share| share.lookup_hash == lookup_hash)
+        {
+            return Err(DbError::UniqueViolation(
+                "public_share.lookup_hash".to_string(),
+            ));
+        }
+        let id = data.next_id("public_share") as i32;
+        data.public_shares.push(PublicShare {
+            id,
+            user_id,
+            used_for: used_for.clone(),
+            object_id,
+            lookup_hash: lookup_hash.to_string(),
+            content_crypt: content_crypt.clone(),
+            expires_at: expires_at.copied(),
+            created_at: Utc::now().naive_utc(),
+        });
+        Ok(id)
+    }
+
+    async fn get_public_share_by_lookup(&self, lookup_hash: &str) -> Result<PublicShare, DbError> {
+        self.check("get_public_share_by_lookup")?;
+        self.read()
+            .public_shares
+            .iter()
+            .find(|share| share.lookup_hash == lookup_hash)
+            .cloned()
+            .ok_or(DbError::NotFound)
+    }
+
+    async fn get_public_shares_by_user(&self, user_id: i32) -> Result<Vec<PublicShare>, DbError> {
+        self.check("get_public_shares_by_user")?;
+        Ok(self
+            .read()
+            .public_shares
+            .iter()
+            .filter(|share| share.user_id == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn del_public_share(&self, user_id: i32, share_id: i32) -> Result<usize, DbError> {
+        self.check("del_public_share")?;
+        let mut data = self.write();
+        let before = data.public_shares.len();
+        data.public_shares
+            .retain(|share| share.id != share_id || share.user_id != user_id);
+        Ok(before - data.public_shares.len())
+    }
+
+    // AUTH EVENTS
+    async fn new_auth_event(
+        &self,
+        user_id: Option<i32>,
+        event: &str,
+        ip: Option<&str>,
+    ) -> Result<(), DbError> {
+        self.check("new_auth_event")?;
+        let mut data = self.write();
+        let id = data.next_id("auth_event") as i32;
+        data.auth_events.push(AuthEvent {
+            id,
+            user_id,
+            event: event.to_string(),
+            ip: ip.map(str::to_string),
+            created_at: Utc::now().naive_utc(),
+        });
+        Ok(())
+    }
+
+    async fn get_auth_events_by_user(
+        &self,
+        user_id: i32,
+        limit: u32,
+    ) -> Result<Vec<AuthEvent>, DbError> {
+        self.check("get_auth_events_by_user")?;
+        Ok(self
+            .read()
+            .auth_events
+            .iter()
+            .rev()
+            .filter(|event| event.user_id == Some(user_id))
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    // RETENTION
+    async fn new_retention_log(&self, user_id: i32, purged_count: i32) -> Result<(), DbError> {
+        self.check("new_retention_log")?;
+        let mut data = self.write();
+        let id = data.next_id("retention_log") as i32;
+        data.retention_logs.push(RetentionLog {
+            id,
+            user_id,
+            purged_count,
+            ran_at: Utc::now().naive_utc(),
+        });
+        Ok(())
+    }
+
+    async fn get_retention_logs_by_user(&self, user_id: i32) -> Result<Vec<RetentionLog>, DbError> {
+        self.check("get_retention_logs_by_user")?;
+        Ok(self
+            .read()
+            .retention_logs
+            .iter()
+            .rev()
+            .filter(|log| log.user_id == user_id)
+            .cloned()
+            .collect())
+    }
+
+    // HISTORY
+    async fn new_change_log(
+        &self,
+        user_id: i32,
+        used_for: &DBObjIdent,
+        object_id: i32,
+        action: &str,
+        snapshot: &CryptString,
+    ) -> Result<i32, DbError> {
+        self.check("new_change_log")?;
+        let mut data = self.write();
+        let id = data.next_id("change_log") as i32;
+        data.change_logs.push(ChangeLog {
+            id,
+            user_id,
+            used_for: used_for.clone(),
+            object_id,
+            action: action.to_string(),
+            snapshot: snapshot.clone(),
+            changed_at: Utc::now().naive_utc(),
+        });
+        Ok(id)
+    }
+
+    async fn get_change_logs_by_object(
+        &self,
+        user_id: i32,
+        used_for: &DBObjIdent,
+        object_id: i32,
+    ) -> Result<Vec<ChangeLog>, DbError> {
+        self.check("get_change_logs_by_object")?;
+        Ok(self
+            .read()
+            .change_logs
+            .iter()
+            .rev()
+            .filter(|change| {
+                change.user_id == user_id
+                    && change.used_for.db_identifier == used_for.db_identifier
+                    && change.object_id == object_id
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn get_change_log(&self, user_id: i32, change_id: i32) -> Result<ChangeLog, DbError> {
+        self.check("get_change_log")?;
+        self.read()
+            .change_logs
+            .iter()
+            .find(|change| change.id == change_id && change.user_id == user_id)
+            .cloned()
+            .ok_or(DbError::NotFound)
+    }
+
+    async fn del_change_logs_before(&self, before: &NaiveDateTime) -> Result<usize, DbError> {
+        self.check("del_change_logs_before")?;
+        let mut data = self.write();
+        let count = data.change_logs.len();
+        data.change_logs
+            .retain(|change| change.changed_at >= *before);
+        Ok(count - data.change_logs.len())
+    }
+
+    // STUDY GOALS
+    async fn set_study_goal_topics(
+        &self,
+        user_id: i32,
+        goal_id: i32,
+        topic_ids: &[i32],
+    ) -> Result<(), DbError> {
+        self.check("set_study_goal_topics")?;
+        let mut data = self.write();
+        let mut links = data.study_goal_topics.clone();
+        links.retain(|(link_user_id, link)| *link_user_id != user_id || link.goal_id != goal_id);
+        for topic_id in topic_ids {
+            // goal and topic are the primary key
+            if links
+                .iter()
+                .any(|(_, link)| link.goal_id == goal_id && link.topic_id == *topic_id)
+            {
+                return Err(DbError::UniqueViolation(
+                    "study_goal_topic.goal_id, study_goal_topic.topic_id".to_string(),
+                ));
+            }
+            links.push((
+                user_id,
+                StudyGoalTopic {
+                    goal_id,
+                    topic_id: *topic_id,
+                },
+            ));
+        }
+        data.study_goal_topics = links;
+        Ok(())
+    }
+
+    async fn get_study_goal_topics_by_user(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<StudyGoalTopic>, DbError> {
+        self.check("get_study_goal_topics_by_user")?;
+        let mut links: Vec<StudyGoalTopic> = self
+            .read()
+            .study_goal_topics
+            .iter()
+            .filter(|(link_user_id, _)| *link_user_id == user_id)
+            .map(|(_, link)| link.clone())
+            .collect();
+        // stable, the links of a goal stay in the order of their positions
+        links.sort_by_key(|link| link.goal_id);
+        Ok(links)
+    }
+
+    async fn del_study_goal_topics_by_topic(
+        &self,
+        user_id: i32,
+        topic_id: i32,
+    ) -> Result<(), DbError> {
+        self.check("del_study_goal_topics_by_topic")?;
+        self.write()
+            .study_goal_topics
+            .retain(|(link_user_id, link)| *link_user_id != user_id || link.topic_id != topic_id);
+        Ok(())
+    }
+
+    async fn del_study_goal_topics_by_goal(
+        &self,
+        user_id: i32,
+        goal_id: i32,
+    ) -> Result<(), DbError> {
+        self.check("del_study_goal_topics_by_goal")?;
+        self.write()
+            .study_goal_topics
+            .retain(|(link_user_id, link)| *link_user_id != user_id || link.goal_id != goal_id);
+        Ok(())
+    }
+
+    // EXPORT
+    async fn export_user_data(
+        &self,
+        user_id: i32,
+        table_names: &[&'static str],
+    ) -> Result<UserDataExport, DbError> {
+        self.check("export_user_data")?;
+        let data = self.read();
+        let user = Value::Integer(user_id.into());
+
+        let mut tables = HashMap::new();
+        for name in table_names {
+            let table = data
+                .tables
+                .get(*name)
+                .ok_or_else(|| DbError::Other(format!("no such table: {}", name)))?;
+            let rows = table
+                .rows
+                .values()
+                .filter(|row| row.get("user_id") == Some(&user))
+                .cloned()
+                .collect();
+            tables.insert(name.to_string(), rows);
+        }
+        let mut study_goal_topics: Vec<StudyGoalTopic> = data
+            .study_goal_topics
+            .iter()
+            .filter(|(link_user_id, _)| *link_user_id == user_id)
+            .map(|(_, link)| link.clone())
+            .collect();
+        study_goal_topics.sort_by_key(|link| link.goal_id);
+
+        Ok(UserDataExport {
+            tables,
+            study_goal_topics,
+        })
+    }
+
+    // DATA OBJECTS
+    async fn create_table_for_type<T: SQLGenerate>(&self) -> Result<(), DbError> {
+        self.check("create_table_for_type")?;
+        self.write()
+            .tables
+            .entry(T::get_db_table().to_string())
+            .or_insert_with(|| MockTable {
+                columns: T::get_column_defs(),
+                unique: T::unique_columns()
+                    .iter()
+                    .map(|columns| columns.iter().map(|column| column.to_string()).collect())
+                    .collect(),
+                appended: T::appended_column(),
+                ..MockTable::default()
+            });
+        Ok(())
+    }
+
+    // the tables of the mock always have every column of their type
+    async fn add_column_for_type<T: SQLGenerate>(
+        &self,
+        _column: &str,
+        _definition: &str,
+    ) -> Result<(), DbError> {
+        self.check("add_column_for_type")?;
+        self.read().table::<T>()?;
+        Ok(())
+    }
+
+    async fn verify_schema_for_type<T: SQLGenerate>(&self) -> Result<(), DbError> {
+        self.check("verify_schema_for_type")?;
+        self.read().table::<T>()?;
+        Ok(())
+    }
+
+    async fn new_entry<T: SQLGenerate>(
+        &self,
+        params: Vec<(String, SQLValue)>,
+    ) -> Result<i32, DbError> {
+        self.check("new_entry")?;
+        self.write().table_mut::<T>()?.insert(None, &params)
+    }
+
+    async fn new_entries<T: SQLGenerate>(
+        &self,
+        rows: Vec<Vec<(String, SQLValue)>>,
+    ) -> Result<Vec<i32>, DbError> {
+        self.check("new_entries")?;
+        let mut data = self.write();
+        let table = data.table_mut::<T>()?;
+        // inserted into a copy, so a failing row leaves the table untouched
+        let mut copy = table.clone();
+        let ids = rows
+            .iter()
+            .map(|params| copy.insert(None, params))
+            .collect::<Result<Vec<_>, _>>()?;
+        *table = copy;
+        Ok(ids)
+    }
+
+    async fn upsert_entry<T: SQLGenerate>(
+        &self,
+        params: Vec<(String, SQLValue)>,
+    ) -> Result<Option<i32>, DbError> {
+        self.check("upsert_entry")?;
+        let mut data = self.write();
+        let table = data.table_mut::<T>()?;
+        let value_of = |name: &str| {
+            params
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| to_value(value))
+        };
+        let Some(Value::Integer(id)) = value_of("id") else {
+            return Err(DbError::Other("upsert without id".to_string()));
+        };
+        let id = id as i32;
+
+        let Some(row) = table.rows.get(&id) else {
+            let params: Vec<_> = params
+                .into_iter()
+                .filter(|(field, _)| field != "id")
+                .collect();
+            return table.insert(Some(id), &params).map(Some);
+        };
+        // rows of other users are not taken over
+        if row.get("user_id") != value_of("user_id").as_ref() {
+            return Ok(None);
+        }
+        let updates: Vec<_> = params
+            .into_iter()
+            .filter(|(field, _)| !["id", "user_id", "created_at"].contains(&field.as_str()))
+            .collect();
+        if updates.is_empty() {
+            return Ok(None);
+        }
+        table.update(id, &updates)?;
+        Ok(Some(id))
+    }
+
+    async fn select_entries<T: SQLGenerate>(
+        &self,
+        params: Vec<WhereParam>,
+        order: &[(String, SortDirection)],
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<T>, DbError> {
+        self.check("select_entries")?;
+        self.select_entries_where(WhereClause::from(params), order, pagination)
+            .await
+    }
+
+    async fn select_entries_where<T: SQLGenerate>(
+        &self,
+        where_clause: WhereClause,
+        order: &[(String, SortDirection)],
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<T>, DbError> {
+        self.check("select_entries_where")?;
+        let data = self.read();
+        let table = data.table::<T>()?;
+
+        let mut rows: Vec<&Row> = table
+            .matching(&where_clause, T::soft_delete())
+            .iter()
+            .map(|id| &table.rows[id])
+            .collect();
+        // NULLs first like in sqlite, ties keep the id order
+        for (field, direction) in order_or_default::<T>(order).iter().rev() {
+            rows.sort_by(|a, b| {
+                let ordering = match (a.get(field), b.get(field)) {
+                    (Some(a), Some(b)) => compare(a, b)
+                        .unwrap_or_else(|| (*a != Value::Null).cmp(&(*b != Value::Null))),
+                    (a, b) => a.is_some().cmp(&b.is_some()),
+                };
+                match direction {
+                    SortDirection::Asc => ordering,
+                    SortDirection::Desc => ordering.reverse(),
+                }
+            });
+        }
+        if let Some(pagination) = pagination {
+            rows = rows
+                .into_iter()
+                .skip(pagination.offset as usize)
+                .take(pagination.limit as usize)
+                .collect();
+        }
+
+        rows.into_iter()
+            .map(|row| {
+                T::values_to_struct(row).map_err(|err| DbError::Serialization(err.to_string()))
+            })
+            .collect()
+    }
+
+    async fn count_entries<T: SQLGenerate>(&self, params: Vec<WhereParam>) -> Result<i64, DbError> {
+        self.check("count_entries")?;
+        let data = self.read();
+        let matching = data
+            .table::<T>()?
+            .matching(&WhereClause::from(params), T::soft_delete());
+        Ok(matching.len() as i64)
+    }
+
+    async fn update_entry<T: SQLGenerate>(
+        &self,
+        params: Vec<(String, SQLValue)>,
+        where_params: Vec<WhereParam>,
+    ) -> Result<usize, DbError> {
+        self.check("update_entry")?;
+        T::get_db_update(
+            params.iter().map(|entry| &entry.0).collect(),
+            where_fields(&where_params),
+        )?;
+        let mut data = self.write();
+        let table = data.table_mut::<T>()?;
+
+        // updated in a copy, so a unique violation leaves every row untouched
+        let mut copy = table.clone();
+        let ids = copy.matching(&WhereClause::from(where_params), T::soft_delete());
+        for id in &ids {
+            copy.update(*id, &params)?;
+        }
+        *table = copy;
+        Ok(ids.len())
+    }
+
+    async fn delete_entry<T: SQLGenerate>(
+        &self,
+        params: Vec<WhereParam>,
+    ) -> Result<usize, DbError> {
+        self.check("delete_entry")?;
+        T::get_db_delete(where_fields(&params))?;
+        let mut data = self.write();
+        let table = data.table_mut::<T>()?;
+
+        let ids = table.matching(&WhereClause::from(params), false);
+        for id in &ids {
+            table.rows.remove(id);
+        }
+        Ok(ids.len())
+    }
+
+    async fn soft_delete_entry<T: SQLGenerate>(
+        &self,
+        params: Vec<WhereParam>,
+    ) -> Result<usize, DbError> {
+        self.check("soft_delete_entry")?;
+        if !T::soft_delete() {
+            return Err(SQLGenError::NoSoftDelete.into());
+        }
+        self.update_entry::<T>(
+            vec![(
+                "deleted_at".to_string(),
+                SQLValue::from(Utc::now().naive_utc()),
+            )],
+            params,
+        )
+        .await
+    }
+
+    async fn restore_entry<T: SQLGenerate>(
+        &self,
+        params: Vec<WhereParam>,
+        deleted_since: &NaiveDateTime,
+    ) -> Result<usize, DbError> {
+        self.check("restore_entry")?;
+        T::get_db_restore(where_fields(&params))?;
+        let mut data = self.write();
+        let table = data.table_mut::<T>()?;
+
+        let deleted_since = WhereParam::new("deleted_at", WhereOp::Ge, *deleted_since);
+        let mut clause = params;
+        clause.push(deleted_since);
+        let ids = table.matching(&WhereClause::from(clause), false);
+        for id in &ids {
+            if let Some(row) = table.rows.get_mut(id) {
+                row.insert("deleted_at".to_string(), Value::Null);
+            }
+        }
+        Ok(ids.len())
+    }
+}
@@ -1,14 +1,24 @@
-use chrono::NaiveDate;
+use std::str::FromStr;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use rusqlite::types::{FromSqlError, FromSqlResult, ValueRef};
+
+use crate::crypt::crypt_types::CryptString;
 
 /// enum of all possible values that can be passed to the db
 #[derive(Debug)]
 pub enum SQLValue {
     Text(String),
     Int32(i32),
+    Int64(i64),
     Blob(Vec<u8>),
     Float64(f64),
     Date(NaiveDate),
+    DateTime(NaiveDateTime),
     Bool(bool),
+    Null,
+    /// values of an IN condition, bound one by one
+    List(Vec<SQLValue>),
 }
 
 impl Clone for SQLValue {
@@ -16,10 +26,38 @@ impl Clone for SQLValue {
         match self {
             Self::Text(arg0) => Self::Text(arg0.clone()),
             Self::Int32(arg0) => Self::Int32(*arg0),
+            Self::Int64(arg0) => Self::Int64(*arg0),
             Self::Blob(arg0) => Self::Blob(arg0.clone()),
             Self::Float64(arg0) => Self::Float64(*arg0),
             Self::Date(arg0) => Self::Date(*arg0),
+            Self::DateTime(arg0) => Self::DateTime(*arg0),
             Self::Bool(arg0) => Self::Bool(*arg0),
+            Self::Null => Self::Null,
+            Self::List(arg0) => Self::List(arg0.clone()),
+        }
+    }
+}
+
+impl SQLValue {
+    /// parses a query string value as the rust type with the given name
+    /// none for unparsable values and types which can not be filtered on (encrypted values)
+    pub fn parse_as(type_name: &str, value: &str) -> Option<Self> {
+        match type_name {
+            "String" => Some(Self::Text(value.to_string())),
+            "i32" => value.parse().ok().map(Self::Int32),
+            "i64" => value.parse().ok().map(Self::Int64),
+            "f64" => value.parse().ok().map(Self::Float64),
+            "bool" => match value {
+                "true" | "1" => Some(Self::Bool(true)),
+                "false" | "0" => Some(Self::Bool(false)),
+                _ => None,
+            },
+            "NaiveDate" => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .map(Self::Date),
+            // like the json serialization of NaiveDateTime, e.g. 2025-07-01T12:00:00
+            "NaiveDateTime" => value.parse().ok().map(Self::DateTime),
+            _ => None,
         }
     }
 }
@@ -39,6 +77,16 @@ impl From<i32> for SQLValue {
         Self::Int32(val)
     }
 }
+impl From<i64> for SQLValue {
+    fn from(val: i64) -> Self {
+        Self::Int64(val)
+    }
+}
+impl From<f64> for SQLValue {
+    fn from(val: f64) -> Self {
+        Self::Float64(val)
+    }
+}
 impl From<Vec<u8>> for SQLValue {
     fn from(val: Vec<u8>) -> Self {
         Self::Blob(val)
@@ -51,47 +99,446 @@ impl From<NaiveDate> for SQLValue {
     }
 }
 
+impl From<NaiveDateTime> for SQLValue {
+    fn from(val: NaiveDateTime) -> Self {
+        Self::DateTime(val)
+    }
+}
+
 impl From<bool> for SQLValue {
     fn from(val: bool) -> Self {
         Self::Bool(val)
     }
 }
 
+impl<T: Into<SQLValue>> From<Option<T>> for SQLValue {
+    fn from(val: Option<T>) -> Self {
+        val.map_or(Self::Null, Into::into)
+    }
+}
+
+impl From<CryptString> for SQLValue {
+    fn from(val: CryptString) -> Self {
+        Self::Blob(val.data_crypt)
+    }
+}
+
+/// implements From<T> for SQLValue as the text of T, for types stored with #[db(enum_text)]
+#[macro_export]
+macro_rules! sql_value_from_text {
+    ( $type:ty ) => {
+        impl From<$type> for $crate::db::sql_helper::SQLValue {
+            fn from(val: $type) -> Self {
+                Self::Text(val.to_string())
+            }
+        }
+    };
+}
+
+/// reads a column of a #[db(enum_text)] field, the text is parsed with FromStr
+pub fn enum_from_sql<T: FromStr>(value: ValueRef) -> FromSqlResult<T> {
+    let text = value.as_str()?;
+    text.parse()
+        .map_err(|_| FromSqlError::Other(format!("invalid value {:?}", text).into()))
+}
+
+/// like enum_from_sql for Option fields, NULL is None
+pub fn optional_enum_from_sql<T: FromStr>(value: ValueRef) -> FromSqlResult<Option<T>> {
+    match value {
+        ValueRef::Null => Ok(None),
+        value => enum_from_sql(value).map(Some),
+    }
+}
+
 /// macro for creating a parameter map
+/// the entries are (name, value) pairs or, when used as where params, WhereParams comparing on equality
 #[macro_export]
 macro_rules! db_param_map {
     ( $( $name:ident : $value:expr ),* $(,)? ) => {
         {
-            let mut map: Vec<(String, $crate::db::sql_helper::SQLValue)> = Vec::new();
+            let mut map = Vec::new();
             $(
                 let wrapped = $crate::db::sql_helper::SQLValue::from($value);
-                map.push((stringify!($name).to_string(), wrapped));
+                map.push((stringify!($name).to_string(), wrapped).into());
+            )*
+            map
+        }
+    };
+}
+
+/// like db_param_map, for values that are options, fields that are None are left out
+/// nullable fields are Option<Option<T>>, Some(None) sets them to NULL
+#[macro_export]
+macro_rules! db_partial_param_map {
+    ( $( $name:ident : $value:expr ),* $(,)? ) => {
+        {
+            let mut map: Vec<(String, $crate::db::sql_helper::SQLValue)> = Vec::new();
+            $(
+                if let Some(value) = $value {
+                    map.push((stringify!($name).to_string(), $crate::db::sql_helper::SQLValue::from(value)));
+                }
             )*
             map
         }
     };
 }
 
+/// comparison of a field with its value in a WHERE clause
+#[allow(dead_code)] // not every comparison is used by the handlers yet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WhereOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Like,
+    /// with the number of values, which are passed as SQLValue::List
+    In(usize),
+    /// Eq and Ne with SQLValue::Null, the value is not bound
+    IsNull,
+    IsNotNull,
+}
+
+impl WhereOp {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            WhereOp::Eq => "=",
+            WhereOp::Ne => "!=",
+            WhereOp::Lt => "<",
+            WhereOp::Le => "<=",
+            WhereOp::Gt => ">",
+            WhereOp::Ge => ">=",
+            WhereOp::Like => "LIKE",
+            WhereOp::In(_) => "IN",
+            WhereOp::IsNull => "IS NULL",
+            WhereOp::IsNotNull => "IS NOT NULL",
+        }
+    }
+
+    /// number of values bound for the condition
+    pub fn placeholders(&self) -> usize {
+        match self {
+            WhereOp::In(count) => *count,
+            WhereOp::IsNull | WhereOp::IsNotNull => 0,
+            _ => 1,
+        }
+    }
+}
+
+/// joins the where fields with AND, placeholders are numbered from offset + 1 on
+/// IN conditions get one placeholder per value, e.g. `id IN (?1, ?2)`
+pub fn where_clause(where_fields: &[(&String, WhereOp)], offset: usize) -> String {
+    let mut next = placeholders(offset);
+
+    where_fields
+        .iter()
+        .map(|(field, op)| condition_sql(field, *op, &mut next))
+        .collect::<Vec<_>>()
+        .join(" AND ")
+}
+
+/// returns the next placeholder on every call, starting with ?offset+1
+fn placeholders(offset: usize) -> impl FnMut() -> String {
+    let mut i = offset;
+    move || {
+        i += 1;
+        format!("?{}", i)
+    }
+}
+
+/// sql of a single condition, takes as many placeholders from next as the operator binds values
+fn condition_sql(field: &str, op: WhereOp, next: &mut impl FnMut() -> String) -> String {
+    match op {
+        WhereOp::In(count) => {
+            let placeholders: Vec<String> = (0..count).map(|_| next()).collect();
+            format!("{} IN ({})", field, placeholders.join(", "))
+        }
+        WhereOp::IsNull | WhereOp::IsNotNull => format!("{} {}", field, op.as_sql()),
+        op => format!("{} {} {}", field, op.as_sql(), next()),
+    }
+}
+
+/// WHERE clause with nested AND and OR groups, a flat list of where params is an And of conditions
+#[allow(dead_code)] // the handlers only filter with flat lists yet
+#[derive(Debug, Clone)]
+pub enum WhereClause {
+    /// true if every clause is true, an empty And is always true
+    And(Vec<WhereClause>),
+    /// true if one of the clauses is true, an empty Or is always false
+    Or(Vec<WhereClause>),
+    Cond(WhereParam),
+}
+
+impl From<Vec<WhereParam>> for WhereClause {
+    fn from(params: Vec<WhereParam>) -> Self {
+        Self::And(params.into_iter().map(Self::Cond).collect())
+    }
+}
+
+impl From<WhereParam> for WhereClause {
+    fn from(param: WhereParam) -> Self {
+        Self::Cond(param)
+    }
+}
+
+impl WhereClause {
+    /// true if there is nothing to filter on, the WHERE can be left out
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Self::And(clauses) if clauses.iter().all(Self::is_empty))
+    }
+
+    /// true for clauses no row can fulfill, the query can be skipped
+    pub fn matches_nothing(&self) -> bool {
+        match self {
+            Self::And(clauses) => clauses.iter().any(Self::matches_nothing),
+            Self::Or(clauses) => clauses.iter().all(Self::matches_nothing),
+            Self::Cond(param) => param.matches_nothing(),
+        }
+    }
+
+    /// all conditions in the order of their placeholders, their values have to be bound in this order
+    pub fn params(&self) -> Vec<&WhereParam> {
+        match self {
+            Self::And(clauses) | Self::Or(clauses) => {
+                clauses.iter().flat_map(Self::params).collect()
+            }
+            Self::Cond(param) => vec![param],
+        }
+    }
+
+    /// number of values bound for the clause
+    pub fn placeholders(&self) -> usize {
+        self.params()
+            .iter()
+            .map(|param| param.op.placeholders())
+            .sum()
+    }
+
+    /// the clause with `deleted_at IS NULL` in front, hides soft deleted rows without taking a placeholder
+    pub fn not_deleted(&self) -> WhereClause {
+        let not_deleted = Self::Cond(WhereParam::new(
+            "deleted_at",
+            WhereOp::IsNull,
+            SQLValue::Null,
+        ));
+        match self {
+            Self::And(clauses) => Self::And(
+                std::iter::once(not_deleted)
+                    .chain(clauses.iter().cloned())
+                    .collect(),
+            ),
+            clause => Self::And(vec![not_deleted, clause.clone()]),
+        }
+    }
+
+    /// sql of the clause (without WHERE), placeholders are numbered from offset + 1 on
+    /// a flat And results in the same sql as where_clause, nested groups are put in parentheses
+    pub fn to_sql(&self, offset: usize) -> String {
+        self.group_sql(&mut placeholders(offset), false)
+    }
+
+    fn group_sql(&self, next: &mut impl FnMut() -> String, nested: bool) -> String {
+        let (clauses, joiner, empty) = match self {
+            Self::And(clauses) => (clauses, " AND ", "1"),
+            Self::Or(clauses) => (clauses, " OR ", "0"),
+            Self::Cond(param) => return condition_sql(&param.field, param.op, next),
+        };
+
+        match clauses.as_slice() {
+            [] => empty.to_string(),
+            [clause] => clause.group_sql(next, nested),
+            clauses => {
+                let sql = clauses
+                    .iter()
+                    .map(|clause| clause.group_sql(next, true))
+                    .collect::<Vec<_>>()
+                    .join(joiner);
+                if nested { format!("({})", sql) } else { sql }
+            }
+        }
+    }
+}
+
+/// one condition of a WHERE clause, conditions are connected with AND
+#[derive(Debug, Clone)]
+pub struct WhereParam {
+    pub field: String,
+    pub op: WhereOp,
+    pub value: SQLValue,
+}
+
+impl WhereParam {
+    /// comparing with NULL (e.g. a None of db_param_map!) is turned into IS NULL and IS NOT NULL, as = NULL never matches
+    pub fn new(field: impl Into<String>, op: WhereOp, value: impl Into<SQLValue>) -> Self {
+        let value = value.into();
+        let op = match (op, &value) {
+            (WhereOp::Eq, SQLValue::Null) => WhereOp::IsNull,
+            (WhereOp::Ne, SQLValue::Null) => WhereOp::IsNotNull,
+            (op, _) => op,
+        };
+
+        Self {
+            field: field.into(),
+            op,
+            value,
+        }
+    }
+
+    /// field IN (values)
+    pub fn is_in(field: impl Into<String>, values: Vec<SQLValue>) -> Self {
+        Self::new(field, WhereOp::In(values.len()), SQLValue::List(values))
+    }
+
+    /// field and operator, as passed to the sql generation
+    pub fn as_where_field(&self) -> (&String, WhereOp) {
+        (&self.field, self.op)
+    }
+
+    /// true for conditions no row can fulfill (IN with an empty list), the query can be skipped
+    pub fn matches_nothing(&self) -> bool {
+        self.op == WhereOp::In(0)
+    }
+}
+
+impl From<(String, SQLValue)> for WhereParam {
+    fn from((field, value): (String, SQLValue)) -> Self {
+        Self::new(field, WhereOp::Eq, value)
+    }
+}
+
+/// direction of a column in an ORDER BY clause
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+impl SortDirection {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            SortDirection::Asc => "ASC",
+            SortDirection::Desc => "DESC",
+        }
+    }
+}
+
+/// the order of a select, the default order of the type if none is given
+pub fn order_or_default<T: SQLGenerate>(
+    order: &[(String, SortDirection)],
+) -> Vec<(String, SortDirection)> {
+    if !order.is_empty() {
+        return order.to_vec();
+    }
+    T::default_order()
+        .iter()
+        .map(|field| (field.to_string(), SortDirection::Asc))
+        .collect()
+}
+
+/// statements which can not be generated from the given fields
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum SQLGenError {
+    /// update or delete without where fields, it would affect every row of the table
+    #[error("{0} without where fields")]
+    MissingWhere(&'static str),
+    /// update without fields to set
+    #[error("update without fields")]
+    NoFields,
+    /// restore of a type without soft delete, its rows are deleted for real
+    #[error("type has no soft delete")]
+    NoSoftDelete,
+}
+
 /// implemented by DBObject
-pub trait SQLGenerate {
+pub trait SQLGenerate: Send + 'static {
     /// returns a sql string to create a database table for the struct
     fn get_db_table_create() -> String;
     /// returns a sql string to insert a new row into the database table
     /// parameters are substituted with ?1, ?2, ... ?n
     /// all fields need to be specified, the parameter just ensures that the order can be changed
     fn get_db_insert(fields: Vec<&String>) -> String;
-    /// returns a sql string to select rows in a table
-    /// where parameters have to be passed into where fields and values will be substituted with ?1, ?2, ... ?n
-    fn get_db_select(where_fields: Vec<&String>) -> String;
+    /// returns a sql string to insert a row or update it if the id exists, substituted like get_db_insert
+    /// the update only happens if the existing row has the same user_id
+    #[allow(dead_code)] // only used by upsert_entry
+    fn get_db_upsert(fields: Vec<&String>) -> String;
+    /// returns a sql string to select rows in a table matching the where clause
+    /// values will be substituted with ?1, ?2, ... ?n in the order of where_clause.params()
+    fn get_db_select(where_clause: &WhereClause) -> String;
+    /// returns a sql string counting the rows matching the where fields (with their comparison, connected with AND)
+    /// values will be substituted with ?1, ?2, ... ?n
+    fn get_db_count(where_fields: Vec<(&String, WhereOp)>) -> String;
+    /// like get_db_select, ordered by the fields in order and by id last
+    /// panics on fields that are not in field_names, they are inserted into the sql
+    fn get_db_select_ordered(
+        where_clause: &WhereClause,
+        order: &[(String, SortDirection)],
+    ) -> String;
+    /// like get_db_select_ordered, but limited to one page
+    /// limit and offset are substituted after the where parameters (?n+1 and ?n+2)
+    fn get_db_select_paginated(
+        where_clause: &WhereClause,
+        order: &[(String, SortDirection)],
+    ) -> String {
+        let n = where_clause.placeholders();
+        format!(
+            "{} LIMIT ?{} OFFSET ?{}",
+            Self::get_db_select_ordered(where_clause, order),
+            n + 1,
+            n + 2
+        )
+    }
+    /// name and definition (type and constraints) of every column besides id, as in the create statement
+    fn get_column_defs() -> Vec<(String, String)>;
+    /// names of all fields in table order, including id (deleted_at of soft_delete is no field)
+    fn field_names() -> &'static [&'static str];
+    /// declared sql type of every field in the order of field_names, without NOT NULL,
+    /// e.g. INTEGER for id, BLOB for encrypted fields and TEXT for enum_text fields
+    fn field_types() -> &'static [&'static str];
+    /// columns which can be ordered by, encrypted columns are left out
+    fn sortable_fields() -> &'static [&'static str];
+    /// columns of every unique constraint, one entry per unique field or unique_together attribute
+    fn unique_columns() -> &'static [&'static [&'static str]];
+    /// columns of the order_by attribute, selects without an order are sorted by them (ascending)
+    fn default_order() -> &'static [&'static str];
+    /// column of the append_within attribute and the column grouping it,
+    /// inserts without the column get the highest value of the user's rows in the same group plus one
+    #[allow(dead_code)] // sqlite gets it from get_db_insert, only the mock database reads it
+    fn appended_column() -> Option<(&'static str, &'static str)>;
+    /// converts a value of a query string into the type of the field
+    /// none for unknown fields, encrypted fields and values that do not parse
+    fn parse_filter_value(field: &str, value: &str) -> Option<SQLValue>;
     /// generates a sql UPDATE statement depending on fields (which will be updated) and where_fields (which will be filtered for)
-    fn get_db_update(fields: Vec<&String>, where_fields: Vec<&String>) -> String;
+    /// fails without fields or without where_fields, instead of updating every row
+    fn get_db_update(
+        fields: Vec<&String>,
+        where_fields: Vec<(&String, WhereOp)>,
+    ) -> Result<String, SQLGenError>;
     /// generates a delete statement depending on fields which will be used as where clause
-    fn get_db_delete(fields: Vec<&String>) -> String;
+    /// fails without fields, instead of deleting every row
+    fn get_db_delete(fields: Vec<(&String, WhereOp)>) -> Result<String, SQLGenError>;
+    /// true if the type has the soft_delete attribute, deleted rows are kept with deleted_at set
+    /// select, count and update leave them out, delete removes them for real
+    fn soft_delete() -> bool;
+    /// generates an UPDATE statement clearing deleted_at of the rows matching the where fields, if they were deleted at or after ?1
+    /// where values are substituted from ?2 on, fails for types without soft delete and without where fields
+    fn get_db_restore(where_fields: Vec<(&String, WhereOp)>) -> Result<String, SQLGenError>;
 
+    /// returns the name of the table of the struct, see the table attribute of the derive
+    fn get_db_table() -> &'static str;
     /// returns DBObjIdent, which is unique to a struct (used for local token used_for)
     fn get_db_ident() -> crate::db::DBObjIdent;
+    /// returns every field besides id and user_id as parameter map, encrypted values stay encrypted
+    fn to_params(&self) -> Vec<(String, SQLValue)>;
     /// converts a rusqlite Row into an object of itself
     fn row_to_struct(row: &rusqlite::Row) -> Result<Self, rusqlite::Error>
     where
         Self: Sized;
+    /// converts the values of a row by column name into an object of itself, missing columns are NULL
+    fn values_to_struct(
+        values: &std::collections::HashMap<String, rusqlite::types::Value>,
+    ) -> rusqlite::types::FromSqlResult<Self>
+    where
+        Self: Sized;
 }
@@ -1,394 +1,3095 @@
-use std::{error::Error, path::Path, sync::Arc};
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    ops::{Deref, DerefMut},
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
 
-use chrono::NaiveDateTime;
-use log::debug;
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use log::{debug, info, warn};
 use r2d2::{Pool, PooledConnection};
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::{ToSql, params};
+use rusqlite::{
+    Connection, OptionalExtension, ToSql,
+    backup::{Backup, StepResult},
+    ffi, params,
+    types::Value,
+};
 
 use crate::crypt::crypt_types::CryptString;
 
 use super::{
-    DBInterface, DBObjIdent, LocalTokenPWCrypt, LocalTokenRTCrypt, RemoteToken, User,
-    sql_helper::{SQLGenerate, SQLValue},
+    AuthEvent, BoxFuture, ChangeLog, DBInterface, DBObjIdent, DbError, LocalTokenPWCrypt,
+    LocalTokenRTCrypt, LocalTokenRecCrypt, Pagination, PublicShare, RemoteToken, RetentionLog,
+    StudyGoalTopic, SyncChange, User, UserDataExport, WebhookDelivery, migrations,
+    sql_helper::{
+        SQLGenError, SQLGenerate, SQLValue, SortDirection, WhereClause, WhereParam,
+        order_or_default,
+    },
 };
 
+/// time a connection waits for the lock of another writer, before failing with "database is locked"
+const DEFAULT_BUSY_TIMEOUT_MS: u64 = 5000;
+/// sqlite has a single writer, more connections mostly wait for each other
+const DEFAULT_MAX_CONNECTIONS: u32 = 4;
+/// time a db call waits for a free connection of the pool, before failing with DbError::Pool
+const DEFAULT_ACQUIRE_TIMEOUT_MS: u64 = 2000;
+
+/// settings of the pool and of every connection in it
+#[derive(Debug, Clone)]
+pub struct SqliteConfig {
+    pub busy_timeout: Duration,
+    /// write ahead log, readers and the writer do not block each other
+    pub wal: bool,
+    /// size of the pool
+    pub max_connections: u32,
+    /// idle connections kept open, all of them (max_connections) if None
+    pub min_idle: Option<u32>,
+    /// time a db call waits for a free connection
+    pub acquire_timeout: Duration,
+}
+
+impl Default for SqliteConfig {
+    fn default() -> Self {
+        Self {
+            busy_timeout: Duration::from_millis(DEFAULT_BUSY_TIMEOUT_MS),
+            wal: true,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            min_idle: None,
+            acquire_timeout: Duration::from_millis(DEFAULT_ACQUIRE_TIMEOUT_MS),
+        }
+    }
+}
+
+impl SqliteConfig {
+    /// reads SQLITE_BUSY_TIMEOUT_MS, SQLITE_MAX_CONNECTIONS, SQLITE_MIN_IDLE and SQLITE_ACQUIRE_TIMEOUT_MS
+    /// panics on invalid values, so misconfigurations are noticed at startup
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let number = |name: &str| {
+            env::var(name).ok().map(|value| {
+                value
+                    .parse::<u64>()
+                    .unwrap_or_else(|_| panic!("{} has to be a number", name))
+            })
+        };
+        let connections = |name: &str| {
+            number(name).map(|value| {
+                u32::try_from(value).unwrap_or_else(|_| panic!("{} is too large", name))
+            })
+        };
+
+        let max_connections = match connections("SQLITE_MAX_CONNECTIONS") {
+            Some(0) => panic!("SQLITE_MAX_CONNECTIONS has to be a positive number"),
+            Some(max) => max,
+            None => default.max_connections,
+        };
+        let min_idle = connections("SQLITE_MIN_IDLE");
+        if min_idle.is_some_and(|min_idle| min_idle > max_connections) {
+            panic!("SQLITE_MIN_IDLE can not be larger than SQLITE_MAX_CONNECTIONS");
+        }
+
+        Self {
+            busy_timeout: number("SQLITE_BUSY_TIMEOUT_MS")
+                .map_or(default.busy_timeout, Duration::from_millis),
+            max_connections,
+            min_idle,
+            acquire_timeout: number("SQLITE_ACQUIRE_TIMEOUT_MS")
+                .map_or(default.acquire_timeout, Duration::from_millis),
+            ..default
+        }
+    }
+
+    /// runs on every new connection of the pool
+    fn init_connection(&self, conn: &mut Connection) -> rusqlite::Result<()> {
+        // first, switching the journal mode waits for other connections as well
+        conn.busy_timeout(self.busy_timeout)?;
+        if self.wal {
+            // returns the new mode, in memory databases stay in memory mode
+            conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+            // durable enough with WAL, a power loss can only lose the last commits
+            conn.pragma_update(None, "synchronous", "NORMAL")?;
+        }
+        // sqlite does not enforce foreign keys by default
+        conn.pragma_update(None, "foreign_keys", true)
+    }
+}
+
+/// maps the error codes of sqlite to the kinds handlers distinguish
+impl From<rusqlite::Error> for DbError {
+    fn from(err: rusqlite::Error) -> Self {
+        match err {
+            rusqlite::Error::QueryReturnedNoRows => DbError::NotFound,
+            rusqlite::Error::SqliteFailure(ref failure, _) => match failure.extended_code {
+                ffi::SQLITE_CONSTRAINT_UNIQUE | ffi::SQLITE_CONSTRAINT_PRIMARYKEY => {
+                    DbError::UniqueViolation(err.to_string())
+                }
+                ffi::SQLITE_CONSTRAINT_FOREIGNKEY => DbError::ForeignKeyViolation(err.to_string()),
+                // the message names the constraint, checks of fields are named after their column
+                ffi::SQLITE_CONSTRAINT_CHECK => match err {
+                    rusqlite::Error::SqliteFailure(_, Some(ref message)) => {
+                        DbError::CheckViolation(
+                            message
+                                .strip_prefix("CHECK constraint failed: ")
+                                .unwrap_or(message)
+                                .to_string(),
+                        )
+                    }
+                    _ => DbError::CheckViolation(err.to_string()),
+                },
+                _ => DbError::Other(err.to_string()),
+            },
+            rusqlite::Error::FromSqlConversionFailure(..)
+            | rusqlite::Error::ToSqlConversionFailure(_)
+            | rusqlite::Error::InvalidColumnType(..)
+            | rusqlite::Error::IntegralValueOutOfRange(..) => {
+                DbError::Serialization(err.to_string())
+            }
+            err => DbError::Other(err.to_string()),
+        }
+    }
+}
+
+/// the pool only fails if no connection got free within the acquire timeout
+impl From<r2d2::Error> for DbError {
+    fn from(err: r2d2::Error) -> Self {
+        DbError::Pool(err.to_string())
+    }
+}
+
+/// cheap to clone, clones share the pool (and the transaction)
+#[derive(Clone)]
 pub struct SqliteDatabase {
     pool: Arc<Pool<SqliteConnectionManager>>,
+    /// connection of the running transaction, only set on the handle passed to with_transaction
+    transaction: Option<Arc<Mutex<TransactionConn>>>,
+}
+
+/// connection with an open transaction, rolled back if it is dropped without a commit (error or panic)
+struct TransactionConn(PooledConnection<SqliteConnectionManager>);
+
+impl Drop for TransactionConn {
+    fn drop(&mut self) {
+        if !self.0.is_autocommit() {
+            let _ = self.0.execute_batch("ROLLBACK");
+        }
+    }
+}
+
+/// connection of a single db call, either from the pool or the one of the running transaction
+enum DbConn<'a> {
+    Pooled(PooledConnection<SqliteConnectionManager>),
+    Transaction(MutexGuard<'a, TransactionConn>),
+}
+
+impl Deref for DbConn<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        match self {
+            DbConn::Pooled(conn) => conn,
+            DbConn::Transaction(conn) => &conn.0,
+        }
+    }
+}
+
+impl DerefMut for DbConn<'_> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        match self {
+            DbConn::Pooled(conn) => conn,
+            DbConn::Transaction(conn) => &mut conn.0,
+        }
+    }
 }
 
 impl SqliteDatabase {
-    /// Create a new SqliteConnectionManager (for thread safe access) with the corresponding path as file name.
+    /// Create a new database with the default config, used for tests.
+    #[cfg(test)]
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Self::new_with_config(path, SqliteConfig::default())
+    }
+
+    /// Create a new SqliteConnectionManager (for thread safe access) with the corresponding path as file name.
+    /// config sizes the pool and is applied to every connection of it
+    pub fn new_with_config<P: AsRef<Path>>(
+        path: P,
+        config: SqliteConfig,
+    ) -> Result<Self, Box<dyn Error>> {
+        let builder = Pool::builder()
+            .max_size(config.max_connections)
+            .min_idle(config.min_idle)
+            .connection_timeout(config.acquire_timeout);
         // Create a connection manager for SQLite
-        let manager = SqliteConnectionManager::file(path);
-        let pool = Pool::new(manager)?;
+        let manager =
+            SqliteConnectionManager::file(path).with_init(move |conn| config.init_connection(conn));
+        let pool = builder.build(manager)?;
 
         // Initialize the database
         let db = Self {
             pool: Arc::new(pool),
+            transaction: None,
         };
-        db.create_auth_tables()?;
+        migrations::run_migrations(&mut *db.get_conn()?)?;
 
         Ok(db)
     }
 
-    /// Get a connection from the pool
-    fn get_conn(&self) -> Result<PooledConnection<SqliteConnectionManager>, r2d2::Error> {
-        self.pool.get()
-    }
-
-    /// create tables in the database if they do not exist
-    fn create_auth_tables(&self) -> Result<(), Box<dyn Error>> {
-        let conn = self.get_conn()?;
-        // Create user table
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS user (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                username TEXT NOT NULL UNIQUE,
-                password_hash TEXT NOT NULL,
-                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-            )",
-            [],
-        )?;
-
-        // local token table pw encrypted (stores encrypted local tokens)
-        // these tokens are encrypted with the users password
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS pwcrypt_local_token (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                user_id INTEGER NOT NULL,
-                local_token BLOB NOT NULL,
-                used_for TEXT NOT NULL
-            )",
-            [],
-        )?;
-
-        // local token table remote token encrypted (stores encrypted local tokens)
-        // these tokens are encrypted with the remote token, which can be invalidated by deleting db entries in this table
-        // resulting in a remote token only having access to local tokens, which have been encrypted with it.
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS rtcrypt_local_token (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                local_token_id INTEGER NOT NULL,
-                local_token BLOB NOT NULL,
-                decrypt_by_rt_id INTEGER NOT NULL
-            )",
-            [],
-        )?;
-
-        // remote token hashes are stored in this table, used to write access
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS remote_token (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                rt_hash TEXT NOT NULL,
-                user_id INTEGER NOT NULL,
-                valid_until TIMESTAMP NOT NULL
-            )",
-            [],
-        )?;
+    /// Create a new in memory database, shared by all connections of the pool, used for tests.
+    #[cfg(test)]
+    pub fn new_in_memory() -> Self {
+        Self::new_in_memory_with_config(SqliteConfig::default())
+    }
+
+    /// Create a new in memory database with a custom config (e.g. a smaller pool), used for tests.
+    #[cfg(test)]
+    pub fn new_in_memory_with_config(config: SqliteConfig) -> Self {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static DB_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        // every call gets its own database, the name is needed for the shared cache
+        let name = format!(
+            "file:eduflow_test_{}_{}?mode=memory&cache=shared",
+            std::process::id(),
+            DB_COUNTER.fetch_add(1, Ordering::SeqCst)
+        );
+        Self::new_with_config(name, config).expect("Failed to create in memory database")
+    }
+
+    /// Run raw sql on the database, used by tests to provoke failures
+    #[cfg(test)]
+    pub fn execute_batch(&self, sql: &str) {
+        self.get_conn()
+            .expect("Failed to get connection")
+            .execute_batch(sql)
+            .expect("Failed to execute sql");
+    }
+
+    /// Take a connection out of the pool until it is dropped, used by tests to exhaust the pool
+    #[cfg(test)]
+    pub fn hold_connection(&self) -> PooledConnection<SqliteConnectionManager> {
+        self.pool.get().expect("Failed to get connection")
+    }
+
+    /// runs f on the blocking thread pool, rusqlite blocks while sqlite works or waits for a lock
+    /// f gets a clone of this handle, so it runs inside of the same transaction
+    async fn blocking<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&SqliteDatabase) -> Result<R, DbError> + Send + 'static,
+    ) -> Result<R, DbError> {
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || f(&db))
+            .await
+            .map_err(|err| DbError::Other(err.to_string()))?
+    }
+
+    /// Get a connection from the pool, or the connection of the transaction this handle belongs to
+    fn get_conn(&self) -> Result<DbConn<'_>, DbError> {
+        match &self.transaction {
+            Some(conn) => Ok(DbConn::Transaction(
+                conn.lock().unwrap_or_else(PoisonError::into_inner),
+            )),
+            None => self.pooled_conn().map(DbConn::Pooled),
+        }
+    }
+
+    /// Get a connection from the pool, fails if none gets free within the acquire timeout
+    fn pooled_conn(&self) -> Result<PooledConnection<SqliteConnectionManager>, DbError> {
+        Ok(self.pool.get()?)
+    }
+
+    /// add a column to an existing table, does nothing if it already exists
+    fn add_column(&self, table: &str, column: &str, definition: &str) -> Result<(), DbError> {
+        migrations::add_column(&*self.get_conn()?, table, column, definition)?;
 
         Ok(())
     }
 }
 
+/// loads the links between study goals and topics of a user, ordered by goal and position
+fn study_goal_topics_by_user(
+    conn: &Connection,
+    user_id: i32,
+) -> Result<Vec<StudyGoalTopic>, DbError> {
+    let mut stmt = conn.prepare("SELECT l.goal_id, l.topic_id FROM study_goal_topic l WHERE l.user_id = ?1 ORDER BY l.goal_id, l.position")?;
+    let links = stmt.query_map(params![user_id], |row| {
+        Ok(StudyGoalTopic {
+            goal_id: row.get(0)?,
+            topic_id: row.get(1)?,
+        })
+    })?;
+
+    Ok(links.collect::<Result<Vec<_>, _>>()?)
+}
+
+/// converts a row of the change_log table
+fn row_to_change_log(row: &rusqlite::Row) -> Result<ChangeLog, rusqlite::Error> {
+    Ok(ChangeLog {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        used_for: DBObjIdent {
+            db_identifier: row.get(2)?,
+        },
+        object_id: row.get(3)?,
+        action: row.get(4)?,
+        snapshot: CryptString {
+            data_crypt: row.get(5)?,
+        },
+        changed_at: row.get(6)?,
+    })
+}
+
+/// converts a row of the public_share table
+fn row_to_public_share(row: &rusqlite::Row) -> Result<PublicShare, rusqlite::Error> {
+    Ok(PublicShare {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        used_for: DBObjIdent {
+            db_identifier: row.get(2)?,
+        },
+        object_id: row.get(3)?,
+        lookup_hash: row.get(4)?,
+        content_crypt: CryptString {
+            data_crypt: row.get(5)?,
+        },
+        expires_at: row.get(6)?,
+        created_at: row.get(7)?,
+    })
+}
+
+/// converts a row of the webhook_delivery table, columns have to be selected in struct order
+fn row_to_webhook_delivery(row: &rusqlite::Row) -> Result<WebhookDelivery, rusqlite::Error> {
+    Ok(WebhookDelivery {
+        id: row.get(0)?,
+        subscription_id: row.get(1)?,
+        payload: row.get(2)?,
+        status: row.get(3)?,
+        attempts: row.get(4)?,
+        next_attempt_at: row.get(5)?,
+        last_error: row.get(6)?,
+        created_at: row.get(7)?,
+    })
+}
+
+#[async_trait]
 impl DBInterface for SqliteDatabase {
+    // the methods with several statements use savepoints, which also work inside of this transaction
+    async fn with_transaction<R, F>(&self, f: F) -> Result<R, DbError>
+    where
+        R: Send,
+        F: for<'a> FnOnce(&'a Self) -> BoxFuture<'a, Result<R, DbError>> + Send,
+    {
+        if self.transaction.is_some() {
+            return f(self).await;
+        }
+
+        let conn = self
+            .blocking(|db| {
+                let conn = db.pooled_conn()?;
+                // immediate, the write lock is taken now instead of failing on the first write
+                conn.execute_batch("BEGIN IMMEDIATE")?;
+                Ok(conn)
+            })
+            .await?;
+        let db = Self {
+            pool: self.pool.clone(),
+            transaction: Some(Arc::new(Mutex::new(TransactionConn(conn)))),
+        };
+
+        // on an error the handle is dropped, which rolls back
+        let result = f(&db).await?;
+        db.blocking(|db| Ok(db.get_conn()?.execute_batch("COMMIT")?))
+            .await?;
+
+        Ok(result)
+    }
+
+    async fn backup_to<P: AsRef<Path> + Send>(&self, path: P) -> Result<(), DbError> {
+        let path = path.as_ref().to_path_buf();
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let mut target = Connection::open(path)?;
+            let backup = Backup::new(&conn, &mut target)?;
+
+            // all pages in one step, the read transaction of the step keeps the copy consistent
+            // with WAL the writers of the other connections are not blocked meanwhile
+            loop {
+                match backup.step(-1)? {
+                    StepResult::Done => return Ok(()),
+                    // busy or locked by another connection, try again
+                    _ => std::thread::sleep(Duration::from_millis(50)),
+                }
+            }
+        })
+        .await
+    }
+
     // AUTH OBJECTS
 
     // user related
-    fn get_user_by_username(&self, username: &str) -> Result<User, Box<dyn Error>> {
-        let conn = self.get_conn()?;
-
-        let sql = "SELECT u.id, u.username, u.password_hash, u.created_at FROM user u WHERE u.username = ?1";
-        let user = conn.query_row(sql, params![username], |row| {
-            Ok(User {
-                id: row.get(0)?,
-                username: row.get(1)?,
-                password_hash: row.get(2)?,
-                created_at: row.get(3)?,
-            })
-        })?;
+    async fn get_user_by_username(&self, username: &str) -> Result<User, DbError> {
+        let username = username.to_owned();
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+
+            let sql = "SELECT u.id, u.username, u.password_hash, u.created_at, u.email, u.failed_logins, u.locked_until FROM user u WHERE u.username = ?1";
+            let user = conn.query_row(sql, params![username], |row| {
+                Ok(User {
+                    id: row.get(0)?,
+                    username: row.get(1)?,
+                    password_hash: row.get(2)?,
+                    created_at: row.get(3)?,
+                    email: row.get(4)?,
+                    failed_logins: row.get(5)?,
+                    locked_until: row.get(6)?,
+                })
+            })?;
+
+            Ok(user)
+        })
+        .await
+    }
+
+    async fn get_user_by_id(&self, user_id: i32) -> Result<User, DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+
+            let sql = "SELECT u.id, u.username, u.password_hash, u.created_at, u.email, u.failed_logins, u.locked_until FROM user u WHERE u.id = ?1";
+            let user = conn.query_row(sql, params![user_id], |row| {
+                Ok(User {
+                    id: row.get(0)?,
+                    username: row.get(1)?,
+                    password_hash: row.get(2)?,
+                    created_at: row.get(3)?,
+                    email: row.get(4)?,
+                    failed_logins: row.get(5)?,
+                    locked_until: row.get(6)?,
+                })
+            })?;
+
+            Ok(user)
+        })
+        .await
+    }
+
+    async fn new_user(
+        &self,
+        username: &str,
+        password_hash: &str,
+        email: Option<&str>,
+    ) -> Result<i32, DbError> {
+        let username = username.to_owned();
+        let password_hash = password_hash.to_owned();
+        let email = email.map(str::to_owned);
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+
+            let sql = "INSERT INTO user (username, password_hash, email) VALUES (?1, ?2, ?3)";
+            conn.execute(sql, params![username, password_hash, email])?;
+
+            debug!("Created new user");
+
+            let id = conn.last_insert_rowid();
+            Ok(id.try_into().expect("DB Ids exceed i32"))
+        })
+        .await
+    }
+
+    async fn increment_failed_logins(&self, user_id: i32) -> Result<i32, DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            // incremented in sql, concurrent attempts must not get lost
+            let sql = "UPDATE user SET failed_logins = failed_logins + 1 WHERE id = ?1 RETURNING failed_logins";
+            let failed_logins = conn.query_row(sql, params![user_id], |row| row.get(0))?;
 
-        Ok(user)
+            Ok(failed_logins)
+        })
+        .await
     }
 
-    fn new_user(&self, username: &str, password_hash: &str) -> Result<i32, Box<dyn Error>> {
-        let conn = self.get_conn()?;
+    async fn lock_user(&self, user_id: i32, locked_until: &NaiveDateTime) -> Result<(), DbError> {
+        let locked_until = *locked_until;
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "UPDATE user SET failed_logins = 0, locked_until = ?2 WHERE id = ?1";
+            conn.execute(sql, params![user_id, locked_until])?;
 
-        let sql = "INSERT INTO user (username, password_hash) VALUES (?1, ?2)";
-        conn.execute(sql, params![username, password_hash])?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn reset_failed_logins(&self, user_id: i32) -> Result<(), DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "UPDATE user SET failed_logins = 0, locked_until = NULL WHERE id = ?1";
+            conn.execute(sql, params![user_id])?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn del_user(&self, user_id: i32) -> Result<(), DbError> {
+        self.blocking(move |db| {
+            let mut conn = db.get_conn()?;
+            let tx = conn.savepoint()?;
+            for table in [
+                "sync_change",
+                "public_share",
+                "retention_log",
+                "auth_event",
+                "study_goal_topic",
+                "change_log",
+            ] {
+                tx.execute(
+                    &format!("DELETE FROM {} WHERE user_id = ?1", table),
+                    params![user_id],
+                )?;
+            }
+            tx.execute("DELETE FROM user WHERE id = ?1", params![user_id])?;
+            tx.commit()?;
+
+            debug!("Deleted user");
+
+            Ok(())
+        })
+        .await
+    }
 
-        debug!("Created new user");
+    async fn update_user_password_hash(
+        &self,
+        user_id: i32,
+        password_hash: &str,
+        local_tokens: &[(i32, CryptString)],
+    ) -> Result<(), DbError> {
+        let password_hash = password_hash.to_owned();
+        let local_tokens = local_tokens.to_vec();
+        self.blocking(move |db| {
+            let mut conn = db.get_conn()?;
+            // the local tokens can only be decrypted with the password of the same hash, so they are written together
+            let tx = conn.savepoint()?;
+            for (local_token_id, token_crypt) in local_tokens {
+                let changed = tx.execute(
+                    "UPDATE pwcrypt_local_token SET local_token = ?1 WHERE id = ?2 AND user_id = ?3",
+                    params![token_crypt.data_crypt, local_token_id, user_id],
+                )?;
+                if changed != 1 {
+                    return Err(DbError::NotFound);
+                }
+            }
+            tx.execute(
+                "UPDATE user SET password_hash = ?1 WHERE id = ?2",
+                params![password_hash, user_id],
+            )?;
+            tx.commit()?;
 
-        let id = conn.last_insert_rowid();
-        Ok(id.try_into().expect("DB Ids exceed i32"))
+            debug!("Changed user password");
+
+            Ok(())
+        })
+        .await
     }
 
-    fn new_local_token_pwcrypt(
+    async fn new_local_token_pwcrypt(
         &self,
         user_id: i32,
         token_crypt: &CryptString,
         used_for: &DBObjIdent,
-    ) -> Result<(), Box<dyn Error>> {
-        let conn = self.get_conn()?;
+    ) -> Result<(), DbError> {
+        let token_crypt = token_crypt.clone();
+        let used_for = used_for.clone();
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
 
-        let sql =
-            "INSERT INTO pwcrypt_local_token (user_id, local_token, used_for) VALUES (?1, ?2, ?3)";
-        conn.execute(
-            sql,
-            params![user_id, token_crypt.data_crypt, used_for.db_identifier],
-        )?;
+            let sql =
+                "INSERT INTO pwcrypt_local_token (user_id, local_token, used_for) VALUES (?1, ?2, ?3)";
+            conn.execute(
+                sql,
+                params![user_id, token_crypt.data_crypt, used_for.db_identifier],
+            )?;
 
-        debug!("Created new user bound local token (password encrypted)");
+            debug!("Created new user bound local token (password encrypted)");
 
-        Ok(())
+            Ok(())
+        })
+        .await
     }
 
-    fn new_local_token_rtcrypt(
+    async fn new_local_token_rtcrypt(
         &self,
         local_token_id: i32,
         local_token_crypt: &CryptString,
         decryptable_by_rt_id: i32,
-    ) -> Result<(), Box<dyn Error>> {
-        let conn = self.get_conn()?;
+    ) -> Result<(), DbError> {
+        let local_token_crypt = local_token_crypt.clone();
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
 
-        let sql = "INSERT INTO rtcrypt_local_token (local_token_id, local_token, decrypt_by_rt_id) VALUES (?1, ?2, ?3)";
-        conn.execute(
-            sql,
-            params![
-                local_token_id,
-                local_token_crypt.data_crypt,
-                decryptable_by_rt_id
-            ],
-        )?;
+            let sql = "INSERT INTO rtcrypt_local_token (local_token_id, local_token, decrypt_by_rt_id) VALUES (?1, ?2, ?3)";
+            conn.execute(
+                sql,
+                params![
+                    local_token_id,
+                    local_token_crypt.data_crypt,
+                    decryptable_by_rt_id
+                ],
+            )?;
 
-        debug!("Created new remote token encrypted local token");
+            debug!("Created new remote token encrypted local token");
 
-        Ok(())
+            Ok(())
+        })
+        .await
+    }
+
+    async fn new_local_token_reccrypt(
+        &self,
+        local_token_id: i32,
+        local_token_crypt: &CryptString,
+    ) -> Result<(), DbError> {
+        let local_token_crypt = local_token_crypt.clone();
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+
+            let sql =
+                "INSERT INTO reccrypt_local_token (local_token_id, local_token) VALUES (?1, ?2)";
+            conn.execute(sql, params![local_token_id, local_token_crypt.data_crypt])?;
+
+            debug!("Created new recovery key encrypted local token");
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_local_token_pwcrypt(
+        &self,
+        local_token_id: i32,
+        token_crypt: &CryptString,
+    ) -> Result<(), DbError> {
+        let token_crypt = token_crypt.clone();
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+
+            let sql = "UPDATE pwcrypt_local_token SET local_token = ?1 WHERE id = ?2";
+            conn.execute(sql, params![token_crypt.data_crypt, local_token_id])?;
+
+            debug!("Replaced password encrypted local token");
+
+            Ok(())
+        })
+        .await
     }
 
-    fn get_local_tokens_by_user_pwcrypt(
+    async fn get_local_tokens_by_user_pwcrypt(
         &self,
         user_id: i32,
-    ) -> Result<Vec<LocalTokenPWCrypt>, Box<dyn Error>> {
-        let conn = self.get_conn()?;
-        let mut stmt = conn.prepare("SELECT lt.id, lt.user_id, lt.local_token, lt.used_for FROM pwcrypt_local_token lt WHERE lt.user_id = ?1")?;
-        let local_tokens = stmt.query_map(params![user_id], |row| {
-            Ok(LocalTokenPWCrypt {
-                id: row.get(0)?,
-                user_id: row.get(1)?,
-                token_crypt: CryptString {
-                    data_crypt: row.get(2)?,
-                },
-                //used_for: DBStructs::from(row.get::<usize, String>(3)?),
-                used_for: DBObjIdent {
-                    db_identifier: row.get(3)?,
-                },
-            })
-        })?;
+    ) -> Result<Vec<LocalTokenPWCrypt>, DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let mut stmt = conn.prepare("SELECT lt.id, lt.user_id, lt.local_token, lt.used_for FROM pwcrypt_local_token lt WHERE lt.user_id = ?1")?;
+            let local_tokens = stmt.query_map(params![user_id], |row| {
+                Ok(LocalTokenPWCrypt {
+                    id: row.get(0)?,
+                    user_id: row.get(1)?,
+                    token_crypt: CryptString {
+                        data_crypt: row.get(2)?,
+                    },
+                    //used_for: DBStructs::from(row.get::<usize, String>(3)?),
+                    used_for: DBObjIdent {
+                        db_identifier: row.get(3)?,
+                    },
+                })
+            })?;
 
-        let local_tokens: Vec<LocalTokenPWCrypt> = local_tokens.collect::<Result<Vec<_>, _>>()?;
+            let local_tokens: Vec<LocalTokenPWCrypt> = local_tokens.collect::<Result<Vec<_>, _>>()?;
 
-        Ok(local_tokens)
+            Ok(local_tokens)
+        })
+        .await
     }
 
-    fn get_local_token_by_used_for_pwcrypt(
+    async fn get_local_token_by_used_for_pwcrypt(
         &self,
         user_id: i32,
         used_for: &DBObjIdent,
-    ) -> Result<LocalTokenPWCrypt, Box<dyn Error>> {
-        let conn = self.get_conn()?;
-        let sql = "SELECT lt.id, lt.user_id, lt.local_token, lt.used_for FROM pwcrypt_local_token lt WHERE lt.user_id = ?1 AND lt.used_for = ?2";
-        let local_token = conn.query_row(sql, params![user_id, used_for.db_identifier], |row| {
-            Ok(LocalTokenPWCrypt {
-                id: row.get(0)?,
-                user_id: row.get(1)?,
-                token_crypt: CryptString {
-                    data_crypt: row.get(2)?,
-                },
-                used_for: DBObjIdent {
-                    db_identifier: row.get(3)?,
-                },
-            })
-        })?;
+    ) -> Result<LocalTokenPWCrypt, DbError> {
+        let used_for = used_for.clone();
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "SELECT lt.id, lt.user_id, lt.local_token, lt.used_for FROM pwcrypt_local_token lt WHERE lt.user_id = ?1 AND lt.used_for = ?2";
+            let local_token = conn.query_row(sql, params![user_id, used_for.db_identifier], |row| {
+                Ok(LocalTokenPWCrypt {
+                    id: row.get(0)?,
+                    user_id: row.get(1)?,
+                    token_crypt: CryptString {
+                        data_crypt: row.get(2)?,
+                    },
+                    used_for: DBObjIdent {
+                        db_identifier: row.get(3)?,
+                    },
+                })
+            })?;
+
+            Ok(local_token)
+        })
+        .await
+    }
+
+    async fn get_local_tokens_by_user_reccrypt(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<LocalTokenRecCrypt>, DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let mut stmt = conn.prepare("SELECT lt.id, lt.local_token_id, lt.local_token FROM reccrypt_local_token lt JOIN pwcrypt_local_token pw ON pw.id = lt.local_token_id WHERE pw.user_id = ?1")?;
+            let local_tokens = stmt
+                .query_map(params![user_id], |row| {
+                    Ok(LocalTokenRecCrypt {
+                        id: row.get(0)?,
+                        local_token_id: row.get(1)?,
+                        local_token_crypt: CryptString {
+                            data_crypt: row.get(2)?,
+                        },
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(local_token)
+            Ok(local_tokens)
+        })
+        .await
     }
 
-    fn get_local_token_by_id_rtcrypt(
+    async fn get_local_token_by_id_rtcrypt(
         &self,
         local_token_id: i32,
         remote_token_id: i32,
-    ) -> Result<LocalTokenRTCrypt, Box<dyn Error>> {
-        let conn = self.get_conn()?;
-        let sql = "SELECT lt.id, lt.local_token_id, lt.local_token, lt.decrypt_by_rt_id FROM rtcrypt_local_token lt WHERE lt.local_token_id = ?1 AND lt.decrypt_by_rt_id = ?2";
-        let local_token = conn.query_row(sql, params![local_token_id, remote_token_id], |row| {
-            Ok(LocalTokenRTCrypt {
-                id: row.get(0)?,
-                local_token_id: row.get(1)?,
-                local_token_crypt: CryptString {
-                    data_crypt: row.get(2)?,
-                },
-                decryptable_by_rt_id: row.get(3)?,
-            })
-        })?;
+    ) -> Result<Option<LocalTokenRTCrypt>, DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "SELECT lt.id, lt.local_token_id, lt.local_token, lt.decrypt_by_rt_id FROM rtcrypt_local_token lt WHERE lt.local_token_id = ?1 AND lt.decrypt_by_rt_id = ?2";
+            let local_token = conn
+                .query_row(sql, params![local_token_id, remote_token_id], |row| {
+                    Ok(LocalTokenRTCrypt {
+                        id: row.get(0)?,
+                        local_token_id: row.get(1)?,
+                        local_token_crypt: CryptString {
+                            data_crypt: row.get(2)?,
+                        },
+                        decryptable_by_rt_id: row.get(3)?,
+                    })
+                })
+                .optional()?;
 
-        Ok(local_token)
+            Ok(local_token)
+        })
+        .await
     }
 
-    fn new_remote_token(
+    async fn new_remote_token(
         &self,
         rt_hash: &str,
         user_id: i32,
         valid_until: &NaiveDateTime,
-    ) -> Result<i64, Box<dyn Error>> {
-        let conn = self.get_conn()?;
-        let sql = "INSERT INTO remote_token (rt_hash, user_id, valid_until) VALUES (?1, ?2, ?3)";
-        conn.execute(sql, params![rt_hash, user_id, valid_until])?;
-
-        debug!("Created new user bound remote token (hashed)");
-
-        let id = conn.last_insert_rowid();
-        Ok(id)
-    }
-
-    fn get_remote_token(&self, token_id: i32) -> Result<RemoteToken, Box<dyn Error>> {
-        let conn = self.get_conn()?;
-        let sql = "SELECT rt.id, rt.rt_hash, rt.user_id, rt.valid_until FROM remote_token rt WHERE rt.id = ?1";
-        let remote_token = conn.query_row(sql, params![token_id], |row| {
-            Ok(RemoteToken {
-                id: row.get(0)?,
-                rt_hash: row.get(1)?,
-                user_id: row.get(2)?,
-                valid_until: row.get(3)?,
-            })
-        })?;
+        local_tokens: &[(i32, CryptString)],
+    ) -> Result<i64, DbError> {
+        let rt_hash = rt_hash.to_owned();
+        let valid_until = *valid_until;
+        let local_tokens = local_tokens.to_vec();
+        self.blocking(move |db| {
+            let mut conn = db.get_conn()?;
+            // a token without all local tokens would fail on data requests later on
+            let tx = conn.savepoint()?;
+            let sql = "INSERT INTO remote_token (rt_hash, user_id, valid_until) VALUES (?1, ?2, ?3)";
+            tx.execute(sql, params![rt_hash, user_id, valid_until])?;
+            let id = tx.last_insert_rowid();
+
+            // one task for all local tokens, instead of one per token
+            let sql = "INSERT INTO rtcrypt_local_token (local_token_id, local_token, decrypt_by_rt_id) VALUES (?1, ?2, ?3)";
+            for (local_token_id, local_token_crypt) in local_tokens {
+                tx.execute(sql, params![local_token_id, local_token_crypt.data_crypt, id])?;
+            }
+            tx.commit()?;
 
-        Ok(remote_token)
+            debug!("Created new user bound remote token (hashed)");
+
+            Ok(id)
+        })
+        .await
     }
 
-    fn del_local_token_rtcrypt_by_rt(&self, remote_token_id: i32) -> Result<(), Box<dyn Error>> {
-        let conn = self.get_conn()?;
-        let sql = "DELETE FROM rtcrypt_local_token WHERE decrypt_by_rt_id = ?1";
-        conn.execute(sql, params![remote_token_id])?;
+    async fn get_local_token_ids_by_rt(&self, remote_token_id: i32) -> Result<Vec<i32>, DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let mut stmt = conn.prepare(
+                "SELECT lt.local_token_id FROM rtcrypt_local_token lt WHERE lt.decrypt_by_rt_id = ?1",
+            )?;
+            let ids = stmt
+                .query_map(params![remote_token_id], |row| row.get(0))?
+                .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(())
+            Ok(ids)
+        })
+        .await
     }
 
-    fn del_remote_token(&self, remote_token_id: i32) -> Result<(), Box<dyn Error>> {
-        let conn = self.get_conn()?;
-        let sql = "DELETE FROM remote_token WHERE id = ?1";
-        conn.execute(sql, params![remote_token_id])?;
+    async fn get_remote_token(&self, token_id: i32) -> Result<Option<RemoteToken>, DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "SELECT rt.id, rt.rt_hash, rt.user_id, rt.valid_until FROM remote_token rt WHERE rt.id = ?1";
+            let remote_token = conn
+                .query_row(sql, params![token_id], |row| {
+                    Ok(RemoteToken {
+                        id: row.get(0)?,
+                        rt_hash: row.get(1)?,
+                        user_id: row.get(2)?,
+                        valid_until: row.get(3)?,
+                    })
+                })
+                .optional()?;
 
-        Ok(())
+            Ok(remote_token)
+        })
+        .await
     }
 
-    // DATA OBJECTS
-    /// creates and prepares a db table
-    fn create_table_for_type<T: SQLGenerate>(&self) -> Result<(), Box<dyn Error>> {
-        let conn = self.get_conn()?;
-        let sql = T::get_db_table_create();
-        conn.execute(&sql, [])?;
+    async fn update_remote_token_validity(
+        &self,
+        token_id: i32,
+        new_valid_until: &NaiveDateTime,
+    ) -> Result<(), DbError> {
+        let new_valid_until = *new_valid_until;
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "UPDATE remote_token SET valid_until = ?2 WHERE id = ?1";
+            conn.execute(sql, params![token_id, new_valid_until])?;
 
-        Ok(())
+            Ok(())
+        })
+        .await
     }
 
-    /// creates a new db_entry, returns the resulting id
-    /// params need to be a complete list of all fields in the struct of type T (order does not matter), do not include the id field (it is autoincrement).
-    fn new_entry<T: SQLGenerate>(
+    async fn get_expired_remote_tokens(
         &self,
-        params: Vec<(String, SQLValue)>,
-    ) -> Result<i32, Box<dyn Error>> {
-        let conn = self.get_conn()?;
-        let sql = T::get_db_insert(params.iter().map(|e| &e.0).collect());
-        let params: Vec<&dyn ToSql> = params
-            .iter()
-            .map(|param| sql_value_to_to_sql(&param.1))
-            .collect();
+        now: &NaiveDateTime,
+    ) -> Result<Vec<RemoteToken>, DbError> {
+        let now = *now;
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "SELECT rt.id, rt.rt_hash, rt.user_id, rt.valid_until FROM remote_token rt WHERE rt.valid_until <= ?1";
+            let mut stmt = conn.prepare(sql)?;
+            let remote_tokens = stmt
+                .query_map(params![now], |row| {
+                    Ok(RemoteToken {
+                        id: row.get(0)?,
+                        rt_hash: row.get(1)?,
+                        user_id: row.get(2)?,
+                        valid_until: row.get(3)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
 
-        conn.execute(&sql, params.as_slice())?;
+            Ok(remote_tokens)
+        })
+        .await
+    }
+
+    async fn get_remote_tokens_by_user(&self, user_id: i32) -> Result<Vec<RemoteToken>, DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "SELECT rt.id, rt.rt_hash, rt.user_id, rt.valid_until FROM remote_token rt WHERE rt.user_id = ?1 ORDER BY rt.id";
+            let mut stmt = conn.prepare(sql)?;
+            let remote_tokens = stmt
+                .query_map(params![user_id], |row| {
+                    Ok(RemoteToken {
+                        id: row.get(0)?,
+                        rt_hash: row.get(1)?,
+                        user_id: row.get(2)?,
+                        valid_until: row.get(3)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
 
-        let id = conn.last_insert_rowid();
-        Ok(id.try_into().expect("Id value exceeding i32"))
+            Ok(remote_tokens)
+        })
+        .await
     }
 
-    /// selects an amount of entries and returns them
-    /// params are used to select the correct entries (will be inserted at the WHERE clause)
-    fn select_entries<T: SQLGenerate>(
+    async fn del_local_token_rtcrypt_by_rt(&self, remote_token_id: i32) -> Result<(), DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "DELETE FROM rtcrypt_local_token WHERE decrypt_by_rt_id = ?1";
+            conn.execute(sql, params![remote_token_id])?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn del_local_token_rtcrypt_by_lt(&self, local_token_id: i32) -> Result<(), DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "DELETE FROM rtcrypt_local_token WHERE local_token_id = ?1";
+            conn.execute(sql, params![local_token_id])?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn del_local_token_reccrypt(&self, local_token_id: i32) -> Result<(), DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "DELETE FROM reccrypt_local_token WHERE local_token_id = ?1";
+            conn.execute(sql, params![local_token_id])?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn del_local_tokens_by_user(&self, user_id: i32) -> Result<(), DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+
+            let sql = "DELETE FROM rtcrypt_local_token WHERE local_token_id IN (SELECT lt.id FROM pwcrypt_local_token lt WHERE lt.user_id = ?1)";
+            conn.execute(sql, params![user_id])?;
+            let sql = "DELETE FROM reccrypt_local_token WHERE local_token_id IN (SELECT lt.id FROM pwcrypt_local_token lt WHERE lt.user_id = ?1)";
+            conn.execute(sql, params![user_id])?;
+            let sql = "DELETE FROM pwcrypt_local_token WHERE user_id = ?1";
+            conn.execute(sql, params![user_id])?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn del_remote_token(&self, remote_token_id: i32) -> Result<(), DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "DELETE FROM remote_token WHERE id = ?1";
+            conn.execute(sql, params![remote_token_id])?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn del_remote_tokens_by_user(
         &self,
-        params: Vec<(String, String)>,
-    ) -> Result<Vec<T>, Box<dyn Error>> {
-        let conn = self.get_conn()?;
-        let sql = T::get_db_select(params.iter().map(|entry| &entry.0).collect());
-        let mut stmt = conn.prepare(&sql)?;
+        user_id: i32,
+        keep_rt_id: Option<i32>,
+    ) -> Result<(), DbError> {
+        self.blocking(move |db| {
+            let mut conn = db.get_conn()?;
+            // -1 is never a valid id, so nothing is kept
+            let keep_rt_id = keep_rt_id.unwrap_or(-1);
 
-        let params: Vec<&dyn ToSql> = params
-            .iter()
-            .map(|e| &e.1)
-            .map(|param| param as &dyn ToSql)
-            .collect();
+            // the local token copies of a remote token go together with it
+            let tx = conn.savepoint()?;
+            let sql = "DELETE FROM rtcrypt_local_token WHERE decrypt_by_rt_id IN (SELECT rt.id FROM remote_token rt WHERE rt.user_id = ?1 AND rt.id != ?2)";
+            tx.execute(sql, params![user_id, keep_rt_id])?;
+            let sql = "DELETE FROM remote_token WHERE user_id = ?1 AND id != ?2";
+            tx.execute(sql, params![user_id, keep_rt_id])?;
+            tx.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
 
-        let entries = stmt.query_map(params.as_slice(), |row| T::row_to_struct(row))?;
+    // WEBHOOKS
+    async fn new_webhook_delivery(
+        &self,
+        subscription_id: i32,
+        payload: &str,
+    ) -> Result<i32, DbError> {
+        let payload = payload.to_owned();
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "INSERT INTO webhook_delivery (subscription_id, payload, next_attempt_at) VALUES (?1, ?2, ?3)";
+            conn.execute(
+                sql,
+                params![subscription_id, payload, Utc::now().naive_utc()],
+            )?;
 
-        let local_tokens: Vec<T> = entries.collect::<Result<Vec<_>, _>>()?;
-        Ok(local_tokens)
+            let id = conn.last_insert_rowid();
+            Ok(id.try_into().expect("DB Ids exceed i32"))
+        })
+        .await
     }
 
-    /// updates entries and returns ok on success
-    /// params are the params which should be changed
-    /// where_params are the params which will be filtered on in the WHERE clause
-    fn update_entry<T: SQLGenerate>(
+    async fn get_due_webhook_deliveries(
         &self,
-        params: Vec<(String, SQLValue)>,
-        where_params: Vec<(String, SQLValue)>,
-    ) -> Result<(), Box<dyn Error>> {
-        let conn = self.get_conn()?;
-        let sql = T::get_db_update(
-            params.iter().map(|entry| &entry.0).collect(),
-            where_params.iter().map(|entry| &entry.0).collect(),
-        );
+        now: &NaiveDateTime,
+    ) -> Result<Vec<WebhookDelivery>, DbError> {
+        let now = *now;
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let mut stmt = conn.prepare("SELECT d.id, d.subscription_id, d.payload, d.status, d.attempts, d.next_attempt_at, d.last_error, d.created_at FROM webhook_delivery d WHERE d.status = 'pending' AND d.next_attempt_at <= ?1 ORDER BY d.id")?;
+            let deliveries = stmt.query_map(params![now], row_to_webhook_delivery)?;
 
-        let params: Vec<&dyn ToSql> = params
-            .iter()
-            .chain(where_params.iter())
-            .map(|e| &e.1)
-            .map(sql_value_to_to_sql)
-            .collect();
+            Ok(deliveries.collect::<Result<Vec<_>, _>>()?)
+        })
+        .await
+    }
 
-        conn.execute(&sql, params.as_slice())?;
+    async fn get_webhook_deliveries_by_subscription(
+        &self,
+        subscription_id: i32,
+    ) -> Result<Vec<WebhookDelivery>, DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let mut stmt = conn.prepare("SELECT d.id, d.subscription_id, d.payload, d.status, d.attempts, d.next_attempt_at, d.last_error, d.created_at FROM webhook_delivery d WHERE d.subscription_id = ?1 ORDER BY d.id DESC")?;
+            let deliveries = stmt.query_map(params![subscription_id], row_to_webhook_delivery)?;
 
-        Ok(())
+            Ok(deliveries.collect::<Result<Vec<_>, _>>()?)
+        })
+        .await
     }
 
-    /// deletes an entry and returns ok on success
-    /// params is the WHERE clause, which select what entry to delete
-    fn delete_entry<T: SQLGenerate>(
+    async fn del_webhook_deliveries_by_subscription(
         &self,
-        params: Vec<(String, SQLValue)>,
-    ) -> Result<(), Box<dyn Error>> {
-        let conn = self.get_conn()?;
-        let sql = T::get_db_delete(params.iter().map(|e| &e.0).collect());
+        subscription_id: i32,
+    ) -> Result<(), DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "DELETE FROM webhook_delivery WHERE subscription_id = ?1";
+            conn.execute(sql, params![subscription_id])?;
 
-        let params: Vec<&dyn ToSql> = params
-            .iter()
-            .map(|e| &e.1)
-            .map(sql_value_to_to_sql)
-            .collect();
+            Ok(())
+        })
+        .await
+    }
 
-        conn.execute(&sql, params.as_slice())?;
+    async fn update_webhook_delivery(&self, delivery: &WebhookDelivery) -> Result<(), DbError> {
+        let delivery = delivery.clone();
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "UPDATE webhook_delivery SET status = ?1, attempts = ?2, next_attempt_at = ?3, last_error = ?4 WHERE id = ?5";
+            conn.execute(
+                sql,
+                params![
+                    delivery.status,
+                    delivery.attempts,
+                    delivery.next_attempt_at,
+                    delivery.last_error,
+                    delivery.id
+                ],
+            )?;
 
-        Ok(())
+            Ok(())
+        })
+        .await
     }
-}
 
-/// converts the SQLValue type to ToSql, depending on its type
-fn sql_value_to_to_sql(param: &SQLValue) -> &dyn ToSql {
-    match param {
-        super::sql_helper::SQLValue::Text(s) => s,
-        super::sql_helper::SQLValue::Int32(i) => i,
-        super::sql_helper::SQLValue::Blob(items) => items,
-        super::sql_helper::SQLValue::Float64(f) => f,
-        super::sql_helper::SQLValue::Date(d) => d,
-        super::sql_helper::SQLValue::Bool(b) => b,
+    // SYNC
+    async fn new_sync_change(
+        &self,
+        user_id: i32,
+        used_for: &DBObjIdent,
+        object_id: i32,
+        deleted: bool,
+    ) -> Result<(), DbError> {
+        let used_for = used_for.clone();
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "INSERT INTO sync_change (user_id, used_for, object_id, deleted) VALUES (?1, ?2, ?3, ?4)";
+            conn.execute(
+                sql,
+                params![user_id, used_for.db_identifier, object_id, deleted],
+            )?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_sync_changes(
+        &self,
+        user_id: i32,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<SyncChange>, DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let mut stmt = conn.prepare("SELECT c.id, c.user_id, c.used_for, c.object_id, c.deleted FROM sync_change c WHERE c.user_id = ?1 AND c.id > ?2 ORDER BY c.id LIMIT ?3")?;
+            let changes = stmt.query_map(params![user_id, after_id, limit], |row| {
+                Ok(SyncChange {
+                    id: row.get(0)?,
+                    user_id: row.get(1)?,
+                    used_for: DBObjIdent {
+                        db_identifier: row.get(2)?,
+                    },
+                    object_id: row.get(3)?,
+                    deleted: row.get(4)?,
+                })
+            })?;
+
+            Ok(changes.collect::<Result<Vec<_>, _>>()?)
+        })
+        .await
+    }
+
+    async fn get_latest_sync_change_id(&self, user_id: i32) -> Result<i64, DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "SELECT COALESCE(MAX(c.id), 0) FROM sync_change c WHERE c.user_id = ?1";
+            let id = conn.query_row(sql, params![user_id], |row| row.get(0))?;
+
+            Ok(id)
+        })
+        .await
+    }
+
+    // SHARES
+    async fn new_public_share(
+        &self,
+        user_id: i32,
+        used_for: &DBObjIdent,
+        object_id: i32,
+        lookup_hash: &str,
+        content_crypt: &CryptString,
+        expires_at: Option<&NaiveDateTime>,
+    ) -> Result<i32, DbError> {
+        let used_for = used_for.clone();
+        let lookup_hash = lookup_hash.to_owned();
+        let content_crypt = content_crypt.clone();
+        let expires_at = expires_at.copied();
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "INSERT INTO public_share (user_id, used_for, object_id, lookup_hash, content, expires_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)";
+            conn.execute(
+                sql,
+                params![
+                    user_id,
+                    used_for.db_identifier,
+                    object_id,
+                    lookup_hash,
+                    content_crypt.data_crypt,
+                    expires_at
+                ],
+            )?;
+
+            let id = conn.last_insert_rowid();
+            Ok(id.try_into().expect("DB Ids exceed i32"))
+        })
+        .await
+    }
+
+    async fn get_public_share_by_lookup(&self, lookup_hash: &str) -> Result<PublicShare, DbError> {
+        let lookup_hash = lookup_hash.to_owned();
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "SELECT s.id, s.user_id, s.used_for, s.object_id, s.lookup_hash, s.content, s.expires_at, s.created_at FROM public_share s WHERE s.lookup_hash = ?1";
+            let share = conn.query_row(sql, params![lookup_hash], row_to_public_share)?;
+
+            Ok(share)
+        })
+        .await
+    }
+
+    async fn get_public_shares_by_user(&self, user_id: i32) -> Result<Vec<PublicShare>, DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let mut stmt = conn.prepare("SELECT s.id, s.user_id, s.used_for, s.object_id, s.lookup_hash, s.content, s.expires_at, s.created_at FROM public_share s WHERE s.user_id = ?1 ORDER BY s.id")?;
+            let shares = stmt.query_map(params![user_id], row_to_public_share)?;
+
+            Ok(shares.collect::<Result<Vec<_>, _>>()?)
+        })
+        .await
+    }
+
+    async fn del_public_share(&self, user_id: i32, share_id: i32) -> Result<usize, DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "DELETE FROM public_share WHERE id = ?1 AND user_id = ?2";
+
+            Ok(conn.execute(sql, params![share_id, user_id])?)
+        })
+        .await
+    }
+
+    // AUTH EVENTS
+    async fn new_auth_event(
+        &self,
+        user_id: Option<i32>,
+        event: &str,
+        ip: Option<&str>,
+    ) -> Result<(), DbError> {
+        let event = event.to_owned();
+        let ip = ip.map(str::to_owned);
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "INSERT INTO auth_event (user_id, event, ip) VALUES (?1, ?2, ?3)";
+            conn.execute(sql, params![user_id, event, ip])?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_auth_events_by_user(
+        &self,
+        user_id: i32,
+        limit: u32,
+    ) -> Result<Vec<AuthEvent>, DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let mut stmt = conn.prepare("SELECT e.id, e.user_id, e.event, e.ip, e.created_at FROM auth_event e WHERE e.user_id = ?1 ORDER BY e.id DESC LIMIT ?2")?;
+            let events = stmt.query_map(params![user_id, limit], |row| {
+                Ok(AuthEvent {
+                    id: row.get(0)?,
+                    user_id: row.get(1)?,
+                    event: row.get(2)?,
+                    ip: row.get(3)?,
+                    created_at: row.get(4)?,
+                })
+            })?;
+
+            Ok(events.collect::<Result<Vec<_>, _>>()?)
+        })
+        .await
+    }
+
+    // RETENTION
+    async fn new_retention_log(&self, user_id: i32, purged_count: i32) -> Result<(), DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "INSERT INTO retention_log (user_id, purged_count) VALUES (?1, ?2)";
+            conn.execute(sql, params![user_id, purged_count])?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_retention_logs_by_user(&self, user_id: i32) -> Result<Vec<RetentionLog>, DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let mut stmt = conn.prepare("SELECT l.id, l.user_id, l.purged_count, l.ran_at FROM retention_log l WHERE l.user_id = ?1 ORDER BY l.id DESC")?;
+            let logs = stmt.query_map(params![user_id], |row| {
+                Ok(RetentionLog {
+                    id: row.get(0)?,
+                    user_id: row.get(1)?,
+                    purged_count: row.get(2)?,
+                    ran_at: row.get(3)?,
+                })
+            })?;
+
+            Ok(logs.collect::<Result<Vec<_>, _>>()?)
+        })
+        .await
+    }
+
+    // HISTORY
+    async fn new_change_log(
+        &self,
+        user_id: i32,
+        used_for: &DBObjIdent,
+        object_id: i32,
+        action: &str,
+        snapshot: &CryptString,
+    ) -> Result<i32, DbError> {
+        let used_for = used_for.clone();
+        let action = action.to_owned();
+        let snapshot = snapshot.clone();
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "INSERT INTO change_log (user_id, used_for, object_id, action, snapshot) VALUES (?1, ?2, ?3, ?4, ?5)";
+            conn.execute(
+                sql,
+                params![
+                    user_id,
+                    used_for.db_identifier,
+                    object_id,
+                    action,
+                    snapshot.data_crypt
+                ],
+            )?;
+
+            let id = conn.last_insert_rowid();
+            Ok(id.try_into().expect("DB Ids exceed i32"))
+        })
+        .await
+    }
+
+    async fn get_change_logs_by_object(
+        &self,
+        user_id: i32,
+        used_for: &DBObjIdent,
+        object_id: i32,
+    ) -> Result<Vec<ChangeLog>, DbError> {
+        let used_for = used_for.clone();
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let mut stmt = conn.prepare("SELECT c.id, c.user_id, c.used_for, c.object_id, c.action, c.snapshot, c.changed_at FROM change_log c WHERE c.user_id = ?1 AND c.used_for = ?2 AND c.object_id = ?3 ORDER BY c.id DESC")?;
+            let changes = stmt.query_map(
+                params![user_id, used_for.db_identifier, object_id],
+                row_to_change_log,
+            )?;
+
+            Ok(changes.collect::<Result<Vec<_>, _>>()?)
+        })
+        .await
+    }
+
+    async fn get_change_log(&self, user_id: i32, change_id: i32) -> Result<ChangeLog, DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "SELECT c.id, c.user_id, c.used_for, c.object_id, c.action, c.snapshot, c.changed_at FROM change_log c WHERE c.id = ?1 AND c.user_id = ?2";
+            let change = conn.query_row(sql, params![change_id, user_id], row_to_change_log)?;
+
+            Ok(change)
+        })
+        .await
+    }
+
+    async fn del_change_logs_before(&self, before: &NaiveDateTime) -> Result<usize, DbError> {
+        let before = *before;
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "DELETE FROM change_log WHERE changed_at < ?1";
+            let deleted = conn.execute(sql, params![before])?;
+
+            Ok(deleted)
+        })
+        .await
+    }
+
+    // STUDY GOALS
+    async fn set_study_goal_topics(
+        &self,
+        user_id: i32,
+        goal_id: i32,
+        topic_ids: &[i32],
+    ) -> Result<(), DbError> {
+        let topic_ids = topic_ids.to_vec();
+        self.blocking(move |db| {
+            let mut conn = db.get_conn()?;
+            // the old links are only gone if the new ones could be inserted
+            let tx = conn.savepoint()?;
+            tx.execute(
+                "DELETE FROM study_goal_topic WHERE user_id = ?1 AND goal_id = ?2",
+                params![user_id, goal_id],
+            )?;
+            for (position, topic_id) in topic_ids.iter().enumerate() {
+                tx.execute(
+                    "INSERT INTO study_goal_topic (user_id, goal_id, topic_id, position) VALUES (?1, ?2, ?3, ?4)",
+                    params![user_id, goal_id, topic_id, position as i64],
+                )?;
+            }
+            tx.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_study_goal_topics_by_user(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<StudyGoalTopic>, DbError> {
+        self.blocking(move |db| study_goal_topics_by_user(&*db.get_conn()?, user_id))
+            .await
+    }
+
+    async fn del_study_goal_topics_by_topic(
+        &self,
+        user_id: i32,
+        topic_id: i32,
+    ) -> Result<(), DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "DELETE FROM study_goal_topic WHERE user_id = ?1 AND topic_id = ?2";
+            conn.execute(sql, params![user_id, topic_id])?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn del_study_goal_topics_by_goal(
+        &self,
+        user_id: i32,
+        goal_id: i32,
+    ) -> Result<(), DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = "DELETE FROM study_goal_topic WHERE user_id = ?1 AND goal_id = ?2";
+            conn.execute(sql, params![user_id, goal_id])?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    // EXPORT
+    async fn export_user_data(
+        &self,
+        user_id: i32,
+        tables: &[&'static str],
+    ) -> Result<UserDataExport, DbError> {
+        let table_names = tables.to_vec();
+        self.blocking(move |db| {
+            let mut conn = db.get_conn()?;
+            // a single read transaction, so no row is exported without the rows it references
+            let tx = conn.savepoint()?;
+
+            let mut tables = HashMap::new();
+            for table in table_names {
+                // the names are generated by the derive macro, never taken from a request
+                let sql = format!("SELECT * FROM {} WHERE user_id = ?1 ORDER BY id", table);
+                let mut stmt = tx.prepare(&sql)?;
+                let columns: Vec<String> = stmt
+                    .column_names()
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect();
+                let rows = stmt
+                    .query_map(params![user_id], |row| {
+                        columns
+                            .iter()
+                            .enumerate()
+                            .map(|(i, column)| Ok((column.clone(), row.get::<_, Value>(i)?)))
+                            .collect()
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+                tables.insert(table.to_string(), rows);
+            }
+            let study_goal_topics = study_goal_topics_by_user(&tx, user_id)?;
+            tx.commit()?;
+
+            Ok(UserDataExport {
+                tables,
+                study_goal_topics,
+            })
+        })
+        .await
+    }
+
+    // DATA OBJECTS
+    /// creates and prepares a db table
+    async fn create_table_for_type<T: SQLGenerate>(&self) -> Result<(), DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = T::get_db_table_create();
+            conn.execute(&sql, [])?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn add_column_for_type<T: SQLGenerate>(
+        &self,
+        column: &str,
+        definition: &str,
+    ) -> Result<(), DbError> {
+        let column = column.to_owned();
+        let definition = definition.to_owned();
+        self.blocking(move |db| db.add_column(T::get_db_table(), &column, &definition))
+            .await
+    }
+
+    async fn verify_schema_for_type<T: SQLGenerate>(&self) -> Result<(), DbError> {
+        self.blocking(|db| {
+            let table = T::get_db_table();
+            let conn = db.get_conn()?;
+            let mut stmt = conn.prepare("SELECT name, type FROM pragma_table_info(?1)")?;
+            let existing = stmt
+                .query_map(params![table], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<Result<HashMap<_, _>, _>>()?;
+
+            // sqlite converts values by the declared type, a changed type still works but has to be migrated by hand
+            for (column, field_type) in T::field_names().iter().zip(T::field_types()) {
+                if let Some(existing_type) = existing.get(*column)
+                    && !existing_type.eq_ignore_ascii_case(field_type)
+                {
+                    warn!(
+                        "Column {} of table {} is {}, expected {}",
+                        column, table, existing_type, field_type
+                    );
+                }
+            }
+
+            let mut missing = Vec::new();
+            for (column, definition) in T::get_column_defs() {
+                if existing.contains_key(&column) {
+                    continue;
+                }
+                // sqlite can only add columns which have a value for the existing rows
+                // a non constant default like CURRENT_TIMESTAMP is not allowed either
+                let addable = (!definition.contains("NOT NULL") || definition.contains("DEFAULT"))
+                    && !definition.contains("DEFAULT CURRENT_");
+                if addable {
+                    info!("Adding missing column {} to table {}", column, table);
+                    migrations::add_column(&conn, table, &column, &definition)?;
+                } else {
+                    // the value for the existing rows has to be chosen by hand
+                    let definition = definition.split(" DEFAULT").next().unwrap_or_default();
+                    missing.push(format!(
+                        "ALTER TABLE {} ADD COLUMN {} {} DEFAULT <value>;",
+                        table, column, definition
+                    ));
+                }
+            }
+
+            if !missing.is_empty() {
+                return Err(DbError::SchemaMismatch(format!(
+                    "table {} is missing columns, add them with: {}",
+                    table,
+                    missing.join(" ")
+                )));
+            }
+
+            // tables created before a unique attribute was added get an index instead of the constraint
+            for columns in T::unique_columns() {
+                migrations::add_unique_index(&conn, table, columns).map_err(|err| {
+                    match DbError::from(err) {
+                        DbError::UniqueViolation(_) => DbError::SchemaMismatch(format!(
+                            "rows of table {} are not unique in ({}), remove the duplicates",
+                            table,
+                            columns.join(", ")
+                        )),
+                        err => err,
+                    }
+                })?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// creates a new db_entry, returns the resulting id
+    /// params need to be a complete list of all fields in the struct of type T (order does not matter), do not include the id field (it is autoincrement).
+    async fn new_entry<T: SQLGenerate>(
+        &self,
+        params: Vec<(String, SQLValue)>,
+    ) -> Result<i32, DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            let sql = T::get_db_insert(params.iter().map(|e| &e.0).collect());
+            let params: Vec<&dyn ToSql> = params
+                .iter()
+                .map(|param| sql_value_to_to_sql(&param.1))
+                .collect();
+
+            conn.execute(&sql, params.as_slice())?;
+
+            let id = conn.last_insert_rowid();
+            Ok(id.try_into().expect("Id value exceeding i32"))
+        })
+        .await
+    }
+
+    /// creates all rows in one savepoint, the statement is only prepared once for rows with the same fields
+    async fn new_entries<T: SQLGenerate>(
+        &self,
+        rows: Vec<Vec<(String, SQLValue)>>,
+    ) -> Result<Vec<i32>, DbError> {
+        self.blocking(move |db| {
+            let mut conn = db.get_conn()?;
+            let tx = conn.savepoint()?;
+
+            let mut ids = Vec::with_capacity(rows.len());
+            for params in &rows {
+                let mut stmt =
+                    tx.prepare_cached(&T::get_db_insert(params.iter().map(|e| &e.0).collect()))?;
+                let params: Vec<&dyn ToSql> = params
+                    .iter()
+                    .map(|param| sql_value_to_to_sql(&param.1))
+                    .collect();
+
+                ids.push(
+                    stmt.insert(params.as_slice())?
+                        .try_into()
+                        .expect("Id value exceeding i32"),
+                );
+            }
+            tx.commit()?;
+
+            Ok(ids)
+        })
+        .await
+    }
+
+    async fn upsert_entry<T: SQLGenerate>(
+        &self,
+        params: Vec<(String, SQLValue)>,
+    ) -> Result<Option<i32>, DbError> {
+        self.blocking(move |db| {
+            let conn = db.get_conn()?;
+            // returns no row if the update is skipped because of another user_id
+            let sql = format!(
+                "{} RETURNING id",
+                T::get_db_upsert(params.iter().map(|e| &e.0).collect())
+            );
+            let params: Vec<&dyn ToSql> = params
+                .iter()
+                .map(|param| sql_value_to_to_sql(&param.1))
+                .collect();
+
+            let id = conn
+                .query_row(&sql, params.as_slice(), |row| row.get(0))
+                .optional()?;
+            Ok(id)
+        })
+        .await
+    }
+
+    /// selects an amount of entries and returns them
+    /// params are used to select the correct entries (will be inserted at the WHERE clause)
+    async fn select_entries<T: SQLGenerate>(
+        &self,
+        params: Vec<WhereParam>,
+        order: &[(String, SortDirection)],
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<T>, DbError> {
+        self.select_entries_where(WhereClause::from(params), order, pagination)
+            .await
+    }
+
+    /// the where clause is flattened for binding, in the same order as the placeholders
+    async fn select_entries_where<T: SQLGenerate>(
+        &self,
+        where_clause: WhereClause,
+        order: &[(String, SortDirection)],
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<T>, DbError> {
+        let order = order_or_default::<T>(order);
+        self.blocking(move |db| {
+            if where_clause.matches_nothing() {
+                return Ok(Vec::new());
+            }
+
+            let conn = db.get_conn()?;
+            let sql = match pagination {
+                Some(_) => T::get_db_select_paginated(&where_clause, &order),
+                None if !order.is_empty() => T::get_db_select_ordered(&where_clause, &order),
+                None => T::get_db_select(&where_clause),
+            };
+            let mut stmt = conn.prepare(&sql)?;
+
+            let mut params = where_values(where_clause.params());
+            if let Some(pagination) = &pagination {
+                params.push(&pagination.limit);
+                params.push(&pagination.offset);
+            }
+
+            let entries = stmt.query_map(params.as_slice(), |row| T::row_to_struct(row))?;
+
+            let local_tokens: Vec<T> = entries.collect::<Result<Vec<_>, _>>()?;
+            Ok(local_tokens)
+        })
+        .await
+    }
+
+    async fn count_entries<T: SQLGenerate>(&self, params: Vec<WhereParam>) -> Result<i64, DbError> {
+        self.blocking(move |db| {
+            if params.iter().any(WhereParam::matches_nothing) {
+                return Ok(0);
+            }
+
+            let conn = db.get_conn()?;
+            let sql = T::get_db_count(params.iter().map(WhereParam::as_where_field).collect());
+
+            let params = where_values(&params);
+            let count = conn.query_row(&sql, params.as_slice(), |row| row.get(0))?;
+
+            Ok(count)
+        })
+        .await
+    }
+
+    /// updates entries and returns ok on success
+    /// params are the params which should be changed
+    /// where_params are the params which will be filtered on in the WHERE clause
+    async fn update_entry<T: SQLGenerate>(
+        &self,
+        params: Vec<(String, SQLValue)>,
+        where_params: Vec<WhereParam>,
+    ) -> Result<usize, DbError> {
+        self.blocking(move |db| {
+            if where_params.iter().any(WhereParam::matches_nothing) {
+                return Ok(0);
+            }
+
+            let conn = db.get_conn()?;
+            let sql = T::get_db_update(
+                params.iter().map(|entry| &entry.0).collect(),
+                where_params
+                    .iter()
+                    .map(WhereParam::as_where_field)
+                    .collect(),
+            )?;
+
+            let params: Vec<&dyn ToSql> = params
+                .iter()
+                .map(|e| sql_value_to_to_sql(&e.1))
+                .chain(where_values(&where_params))
+                .collect();
+
+            let updated = conn.execute(&sql, params.as_slice())?;
+
+            Ok(updated)
+        })
+        .await
+    }
+
+    /// deletes entries and returns how many were deleted
+    /// params is the WHERE clause, which select what entry to delete
+    async fn delete_entry<T: SQLGenerate>(
+        &self,
+        params: Vec<WhereParam>,
+    ) -> Result<usize, DbError> {
+        self.blocking(move |db| {
+            if params.iter().any(WhereParam::matches_nothing) {
+                return Ok(0);
+            }
+
+            let conn = db.get_conn()?;
+            let sql = T::get_db_delete(params.iter().map(WhereParam::as_where_field).collect())?;
+
+            let params = where_values(&params);
+
+            let deleted = conn.execute(&sql, params.as_slice())?;
+
+            Ok(deleted)
+        })
+        .await
+    }
+
+    async fn soft_delete_entry<T: SQLGenerate>(
+        &self,
+        params: Vec<WhereParam>,
+    ) -> Result<usize, DbError> {
+        if !T::soft_delete() {
+            return Err(SQLGenError::NoSoftDelete.into());
+        }
+        // updates leave out rows which are already deleted
+        self.update_entry::<T>(
+            vec![(
+                "deleted_at".to_string(),
+                SQLValue::from(Utc::now().naive_utc()),
+            )],
+            params,
+        )
+        .await
+    }
+
+    async fn restore_entry<T: SQLGenerate>(
+        &self,
+        params: Vec<WhereParam>,
+        deleted_since: &NaiveDateTime,
+    ) -> Result<usize, DbError> {
+        let deleted_since = *deleted_since;
+        self.blocking(move |db| {
+            if params.iter().any(WhereParam::matches_nothing) {
+                return Ok(0);
+            }
+
+            let conn = db.get_conn()?;
+            let sql = T::get_db_restore(params.iter().map(WhereParam::as_where_field).collect())?;
+
+            let params: Vec<&dyn ToSql> = std::iter::once(&deleted_since as &dyn ToSql)
+                .chain(where_values(&params))
+                .collect();
+
+            let restored = conn.execute(&sql, params.as_slice())?;
+
+            Ok(restored)
+        })
+        .await
+    }
+}
+
+/// converts the SQLValue type to ToSql, depending on its type
+fn sql_value_to_to_sql(param: &SQLValue) -> &dyn ToSql {
+    match param {
+        super::sql_helper::SQLValue::Text(s) => s,
+        super::sql_helper::SQLValue::Int32(i) => i,
+        super::sql_helper::SQLValue::Int64(i) => i,
+        super::sql_helper::SQLValue::Blob(items) => items,
+        super::sql_helper::SQLValue::Float64(f) => f,
+        super::sql_helper::SQLValue::Date(d) => d,
+        super::sql_helper::SQLValue::DateTime(dt) => dt,
+        super::sql_helper::SQLValue::Bool(b) => b,
+        super::sql_helper::SQLValue::Null => &rusqlite::types::Null,
+        super::sql_helper::SQLValue::List(_) => {
+            unreachable!("lists are bound value by value, see where_values")
+        }
+    }
+}
+
+/// converts the values of where params to ToSql, the values of lists (IN) are flattened
+/// conditions without placeholder (IS NULL) bind nothing
+fn where_values<'a>(params: impl IntoIterator<Item = &'a WhereParam>) -> Vec<&'a dyn ToSql> {
+    params
+        .into_iter()
+        .flat_map(|param| match (&param.value, param.op.placeholders()) {
+            (_, 0) => Vec::new(),
+            (SQLValue::List(values), _) => values.iter().map(sql_value_to_to_sql).collect(),
+            (value, _) => vec![sql_value_to_to_sql(value)],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime};
+    use eduflow_derive::DBObject;
+
+    use crate::{
+        crypt::crypt_types::CryptString,
+        data_handler::objects::{
+            CourseDB, ExamDB, GoalStatus, PrepStatus, SemesterDB, StudyGoalDB, ToDoDB, TopicDB,
+        },
+        db::{
+            DBInterface, DbError, Pagination,
+            sql_helper::{
+                SQLGenError, SQLGenerate, SQLValue, SortDirection, WhereClause, WhereOp,
+                WhereParam, where_clause,
+            },
+        },
+        db_param_map, sql_value_from_text,
+    };
+
+    use super::SqliteDatabase;
+
+    /// covers the column types which are not used by the data objects
+    #[derive(DBObject)]
+    struct MeasurementDB {
+        id: i32,
+        user_id: i32,
+        count: i64,
+        ratio: f64,
+        taken_at: NaiveDateTime,
+        checked_at: Option<NaiveDateTime>,
+    }
+
+    #[tokio::test]
+    async fn wide_numbers_and_timestamps_round_trip() {
+        let db = SqliteDatabase::new_in_memory();
+        db.create_table_for_type::<MeasurementDB>().await.unwrap();
+        let taken_at = NaiveDate::from_ymd_opt(2025, 7, 1)
+            .unwrap()
+            .and_hms_milli_opt(12, 30, 15, 250)
+            .unwrap();
+
+        let id = db
+            .new_entry::<MeasurementDB>(db_param_map! {
+                user_id: 1,
+                count: i64::from(i32::MAX) + 1,
+                ratio: 0.25,
+                taken_at: taken_at,
+                checked_at: None::<NaiveDateTime>,
+            })
+            .await
+            .unwrap();
+        db.new_entry::<MeasurementDB>(db_param_map! {
+            user_id: 1,
+            count: 0i64,
+            ratio: 1.5,
+            taken_at: taken_at - chrono::Days::new(1),
+            checked_at: Some(taken_at),
+        })
+        .await
+        .unwrap();
+
+        // every new type can be compared in where clauses
+        let measurements: Vec<MeasurementDB> = db
+            .select_entries(
+                vec![
+                    WhereParam::new("count", WhereOp::Gt, SQLValue::from(i64::from(i32::MAX))),
+                    WhereParam::new("ratio", WhereOp::Lt, SQLValue::from(1.0)),
+                    WhereParam::new("taken_at", WhereOp::Eq, SQLValue::from(taken_at)),
+                    WhereParam::new("checked_at", WhereOp::Eq, SQLValue::Null),
+                ],
+                &[],
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(measurements.len(), 1);
+        let measurement = &measurements[0];
+        assert_eq!((measurement.id, measurement.user_id), (id, 1));
+        assert_eq!(measurement.count, i64::from(i32::MAX) + 1);
+        assert_eq!(measurement.ratio, 0.25);
+        assert_eq!(measurement.taken_at, taken_at);
+        assert_eq!(measurement.checked_at, None);
+
+        // query strings are parsed with the type of the field
+        assert!(matches!(
+            MeasurementDB::parse_filter_value("count", "3000000000"),
+            Some(SQLValue::Int64(3_000_000_000))
+        ));
+        assert!(matches!(
+            MeasurementDB::parse_filter_value("taken_at", "2025-07-01T12:30:15.250"),
+            Some(SQLValue::DateTime(parsed)) if parsed == taken_at
+        ));
+        assert!(matches!(
+            MeasurementDB::parse_filter_value("checked_at", "null"),
+            Some(SQLValue::Null)
+        ));
+    }
+
+    #[derive(DBObject)]
+    #[db(table = "reading", soft_delete)]
+    struct ReadingDB {
+        id: i32,
+        user_id: i32,
+        pages: i32,
+    }
+
+    #[allow(dead_code)] // only the generated names are used
+    #[derive(DBObject)]
+    #[db(table = "renamed_reading", ident = "ReadingDB")]
+    struct RenamedReadingDB {
+        id: i32,
+        user_id: i32,
+    }
+
+    #[tokio::test]
+    async fn table_attribute_names_the_table() {
+        let (user_id, pages) = ("user_id".to_string(), "pages".to_string());
+        assert_eq!(
+            ReadingDB::get_db_table_create(),
+            "CREATE TABLE IF NOT EXISTS reading (id INTEGER PRIMARY KEY AUTOINCREMENT,user_id INTEGER NOT NULL,pages INTEGER NOT NULL,deleted_at DATETIME)"
+        );
+        assert_eq!(
+            ReadingDB::get_db_insert(vec![&user_id, &pages]),
+            "INSERT INTO reading (user_id,pages) VALUES (?1,?2)"
+        );
+        assert_eq!(
+            ReadingDB::get_db_select(&WhereClause::from(db_param_map! { user_id: 1 })),
+            "SELECT id, user_id,pages FROM reading WHERE deleted_at IS NULL AND user_id = ?1"
+        );
+        assert_eq!(
+            ReadingDB::get_db_update(vec![&pages], vec![(&user_id, WhereOp::Eq)]).unwrap(),
+            "UPDATE reading SET pages = ?1 WHERE deleted_at IS NULL AND user_id = ?2"
+        );
+        assert_eq!(
+            ReadingDB::get_db_delete(vec![(&user_id, WhereOp::Eq)]).unwrap(),
+            "DELETE FROM reading WHERE user_id = ?1"
+        );
+        assert_eq!(
+            ReadingDB::get_db_restore(vec![(&user_id, WhereOp::Eq)]).unwrap(),
+            "UPDATE reading SET deleted_at = NULL WHERE deleted_at >= ?1 AND user_id = ?2"
+        );
+        // the ident stays the struct name, unless it is set as well
+        assert_eq!(ReadingDB::get_db_ident().db_identifier, "ReadingDB");
+        assert_eq!(RenamedReadingDB::get_db_table(), "renamed_reading");
+        assert_eq!(RenamedReadingDB::get_db_ident().db_identifier, "ReadingDB");
+
+        let db = SqliteDatabase::new_in_memory();
+        db.create_table_for_type::<ReadingDB>().await.unwrap();
+        db.verify_schema_for_type::<ReadingDB>().await.unwrap();
+        db.new_entry::<ReadingDB>(db_param_map! { user_id: 1, pages: 12 })
+            .await
+            .unwrap();
+        let readings: Vec<ReadingDB> = db
+            .select_entries(db_param_map! { user_id: 1 }, &[], None)
+            .await
+            .unwrap();
+        assert_eq!(
+            (readings[0].id, readings[0].user_id, readings[0].pages),
+            (1, 1, 12)
+        );
+        let export = db.export_user_data(1, &["reading"]).await.unwrap();
+        assert_eq!(export.tables["reading"].len(), 1);
+    }
+
+    #[allow(dead_code)] // only written, the rows are never read
+    #[derive(DBObject)]
+    #[db(table = "shelf", unique_together("user_id", "name"))]
+    struct ShelfDB {
+        id: i32,
+        user_id: i32,
+        #[db(unique)]
+        code: String,
+        name: String,
+        label: Option<CryptString>,
+    }
+
+    #[tokio::test]
+    async fn unique_attributes_reject_duplicates() {
+        assert_eq!(
+            ShelfDB::get_db_table_create(),
+            "CREATE TABLE IF NOT EXISTS shelf (id INTEGER PRIMARY KEY AUTOINCREMENT,user_id INTEGER NOT NULL,code TEXT NOT NULL UNIQUE,name TEXT NOT NULL,label BLOB,UNIQUE(user_id,name))"
+        );
+        assert_eq!(
+            ShelfDB::unique_columns(),
+            &[&["code"][..], &["user_id", "name"][..]]
+        );
+
+        let db = SqliteDatabase::new_in_memory();
+        db.create_table_for_type::<ShelfDB>().await.unwrap();
+        db.verify_schema_for_type::<ShelfDB>().await.unwrap();
+        db.new_entry::<ShelfDB>(db_param_map! { user_id: 1, code: "A1", name: "Books" })
+            .await
+            .unwrap();
+        let id = db
+            .new_entry::<ShelfDB>(db_param_map! { user_id: 1, code: "A2", name: "Games" })
+            .await
+            .unwrap();
+        // the same name is fine for another user, the code is unique across users
+        db.new_entry::<ShelfDB>(db_param_map! { user_id: 2, code: "B1", name: "Books" })
+            .await
+            .unwrap();
+        for params in [
+            db_param_map! { user_id: 1, code: "A3", name: "Books" },
+            db_param_map! { user_id: 2, code: "A1", name: "Music" },
+        ] {
+            assert!(matches!(
+                db.new_entry::<ShelfDB>(params).await,
+                Err(DbError::UniqueViolation(_))
+            ));
+        }
+        assert!(matches!(
+            db.update_entry::<ShelfDB>(db_param_map! { name: "Books" }, db_param_map! { id: id })
+                .await,
+            Err(DbError::UniqueViolation(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn schema_verification_adds_unique_indices() {
+        let db = SqliteDatabase::new_in_memory();
+        // shelf table as created before the unique attributes
+        db.execute_batch(
+            "CREATE TABLE shelf (id INTEGER PRIMARY KEY AUTOINCREMENT,user_id INTEGER NOT NULL,code TEXT NOT NULL,name TEXT NOT NULL,label BLOB);
+            INSERT INTO shelf (user_id, code, name) VALUES (1, 'A1', 'Books'), (1, 'A1', 'Games');",
+        );
+
+        match db.verify_schema_for_type::<ShelfDB>().await {
+            Err(DbError::SchemaMismatch(message)) => assert!(
+                message.contains("rows of table shelf are not unique in (code)"),
+                "{}",
+                message
+            ),
+            other => panic!("expected a schema mismatch, got {:?}", other),
+        }
+
+        db.execute_batch("UPDATE shelf SET code = 'A2' WHERE name = 'Games';");
+        db.verify_schema_for_type::<ShelfDB>().await.unwrap();
+        // a second run finds the indices
+        db.verify_schema_for_type::<ShelfDB>().await.unwrap();
+        assert!(matches!(
+            db.new_entry::<ShelfDB>(db_param_map! { user_id: 1, code: "A3", name: "Books" })
+                .await,
+            Err(DbError::UniqueViolation(_))
+        ));
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Mood {
+        Happy,
+        Tired,
+    }
+    impl std::fmt::Display for Mood {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str(match self {
+                Mood::Happy => "happy",
+                Mood::Tired => "tired",
+            })
+        }
+    }
+    impl std::str::FromStr for Mood {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "happy" => Ok(Mood::Happy),
+                "tired" => Ok(Mood::Tired),
+                _ => Err(()),
+            }
+        }
+    }
+    sql_value_from_text!(Mood);
+
+    #[derive(DBObject)]
+    struct DiaryDB {
+        id: i32,
+        user_id: i32,
+        #[db(enum_text)]
+        mood: Mood,
+        #[db(enum_text)]
+        previous_mood: Option<Mood>,
+    }
+
+    #[tokio::test]
+    async fn enum_text_fields_round_trip() {
+        assert_eq!(
+            DiaryDB::get_db_table_create(),
+            "CREATE TABLE IF NOT EXISTS DiaryDB (id INTEGER PRIMARY KEY AUTOINCREMENT,user_id INTEGER NOT NULL,mood TEXT NOT NULL,previous_mood TEXT)"
+        );
+        assert!(matches!(
+            DiaryDB::parse_filter_value("mood", "tired"),
+            Some(SQLValue::Text(text)) if text == "tired"
+        ));
+        assert!(DiaryDB::parse_filter_value("mood", "grumpy").is_none());
+        assert!(matches!(
+            DiaryDB::parse_filter_value("previous_mood", "null"),
+            Some(SQLValue::Null)
+        ));
+
+        let db = SqliteDatabase::new_in_memory();
+        db.create_table_for_type::<DiaryDB>().await.unwrap();
+        let entry = DiaryDB {
+            id: 0,
+            user_id: 1,
+            mood: Mood::Happy,
+            previous_mood: None,
+        };
+        let mut params = entry.to_params();
+        params.push(("user_id".to_string(), SQLValue::from(entry.user_id)));
+        let id = db.new_entry::<DiaryDB>(params).await.unwrap();
+        let stored: (String, Option<String>) = db
+            .get_conn()
+            .unwrap()
+            .query_row("SELECT mood, previous_mood FROM DiaryDB", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(stored, ("happy".to_string(), None));
+
+        db.update_entry::<DiaryDB>(
+            db_param_map! { mood: Mood::Tired, previous_mood: Some(Mood::Happy) },
+            db_param_map! { id: id },
+        )
+        .await
+        .unwrap();
+        let entries: Vec<DiaryDB> = db
+            .select_entries(db_param_map! { mood: Mood::Tired }, &[], None)
+            .await
+            .unwrap();
+        assert_eq!(
+            (
+                entries[0].id,
+                entries[0].user_id,
+                entries[0].mood,
+                entries[0].previous_mood
+            ),
+            (id, 1, Mood::Tired, Some(Mood::Happy))
+        );
+        let export = db.export_user_data(1, &["DiaryDB"]).await.unwrap();
+        let exported = DiaryDB::values_to_struct(&export.tables["DiaryDB"][0]).unwrap();
+        assert_eq!(exported.previous_mood, Some(Mood::Happy));
+
+        // unknown texts can not be read
+        db.execute_batch("UPDATE DiaryDB SET mood = 'grumpy'");
+        assert!(matches!(
+            db.select_entries::<DiaryDB>(Vec::new(), &[], None).await,
+            Err(DbError::Serialization(_))
+        ));
+    }
+
+    #[test]
+    fn ordered_select_ends_with_id() {
+        let sql = ExamDB::get_db_select_ordered(
+            &WhereClause::from(db_param_map! { user_id: 1 }),
+            &[("date".to_string(), SortDirection::Desc)],
+        );
+        assert!(sql.ends_with("WHERE user_id = ?1 ORDER BY date DESC, id"));
+        assert!(ExamDB::sortable_fields().contains(&"date"));
+        // encrypted
+        assert!(!ExamDB::sortable_fields().contains(&"name"));
+    }
+
+    #[test]
+    fn field_lists_include_id() {
+        assert_eq!(
+            ExamDB::field_names(),
+            &[
+                "id",
+                "user_id",
+                "course_id",
+                "name",
+                "date",
+                "prep_status",
+                "prep_notes",
+                "created_at",
+                "updated_at"
+            ]
+        );
+        assert_eq!(
+            ExamDB::field_types(),
+            &[
+                "INTEGER", "INTEGER", "INTEGER", "BLOB", "DATE", "TEXT", "BLOB", "DATETIME",
+                "DATETIME"
+            ]
+        );
+    }
+
+    #[test]
+    fn where_fields_use_their_operator() {
+        let (user_id, date, name) = (
+            "user_id".to_string(),
+            "date".to_string(),
+            "name".to_string(),
+        );
+        let date_value = NaiveDate::from_ymd_opt(2025, 7, 1).unwrap();
+        assert!(
+            ExamDB::get_db_select(&WhereClause::from(vec![
+                WhereParam::new("user_id", WhereOp::Eq, 1),
+                WhereParam::new("date", WhereOp::Ge, date_value),
+                WhereParam::new("date", WhereOp::Lt, date_value),
+            ]))
+            .ends_with("WHERE user_id = ?1 AND date >= ?2 AND date < ?3")
+        );
+        assert_eq!(
+            ExamDB::get_db_update(vec![&name], vec![(&user_id, WhereOp::Ne)]).unwrap(),
+            "UPDATE ExamDB SET name = ?1, updated_at = CURRENT_TIMESTAMP WHERE user_id != ?2"
+        );
+        assert_eq!(
+            ExamDB::get_db_delete(vec![(&date, WhereOp::Le), (&name, WhereOp::Like)]).unwrap(),
+            "DELETE FROM ExamDB WHERE date <= ?1 AND name LIKE ?2"
+        );
+        assert_eq!(
+            ExamDB::get_db_count(vec![(&user_id, WhereOp::Eq), (&date, WhereOp::Gt)]),
+            "SELECT COUNT(*) FROM ExamDB WHERE user_id = ?1 AND date > ?2"
+        );
+        // the parameter map compares on equality, None with IS NULL
+        let params: Vec<WhereParam> = db_param_map! { user_id: 1, prep_notes: None::<Vec<u8>> };
+        assert_eq!(params[0].op, WhereOp::Eq);
+        assert_eq!(params[1].op, WhereOp::IsNull);
+        let not_null = WhereParam::new("prep_notes", WhereOp::Ne, SQLValue::Null);
+        assert!(
+            ExamDB::get_db_select(&WhereClause::from(vec![
+                params[1].clone(),
+                not_null,
+                params[0].clone(),
+            ]))
+            .ends_with("WHERE prep_notes IS NULL AND prep_notes IS NOT NULL AND user_id = ?1")
+        );
+    }
+
+    #[test]
+    fn or_groups_are_put_in_parentheses() {
+        let param = |field: &str, op: WhereOp| WhereClause::Cond(WhereParam::new(field, op, 1));
+
+        // a flat list is joined with AND, like the where fields of update, delete and count
+        let flat = WhereClause::from(db_param_map! { user_id: 1, completed: false });
+        let (user_id, completed) = ("user_id".to_string(), "completed".to_string());
+        assert_eq!(
+            flat.to_sql(0),
+            where_clause(&[(&user_id, WhereOp::Eq), (&completed, WhereOp::Eq)], 0)
+        );
+
+        let clause = WhereClause::And(vec![
+            param("user_id", WhereOp::Eq),
+            WhereClause::Or(vec![
+                param("deadline", WhereOp::Lt),
+                WhereClause::And(vec![
+                    param("completed", WhereOp::Eq),
+                    WhereClause::Cond(WhereParam::is_in("id", vec![1.into(), 2.into()])),
+                ]),
+            ]),
+        ]);
+        // soft deleted todos are left out without a placeholder
+        assert!(ToDoDB::get_db_select(&clause).ends_with(
+            "WHERE deleted_at IS NULL AND user_id = ?1 AND (deadline < ?2 OR (completed = ?3 AND id IN (?4, ?5)))"
+        ));
+        assert_eq!(clause.placeholders(), 5);
+        // groups with a single clause need no parentheses, empty groups are constant
+        assert_eq!(
+            WhereClause::Or(vec![param("id", WhereOp::Eq)]).to_sql(2),
+            "id = ?3"
+        );
+        assert_eq!(
+            WhereClause::And(vec![param("id", WhereOp::Eq), WhereClause::Or(Vec::new())]).to_sql(0),
+            "id = ?1 AND 0"
+        );
+        assert!(WhereClause::Or(Vec::new()).matches_nothing());
+        assert!(
+            !WhereClause::Or(vec![
+                WhereClause::Cond(WhereParam::is_in("id", Vec::new())),
+                param("id", WhereOp::Eq),
+            ])
+            .matches_nothing()
+        );
+    }
+
+    #[tokio::test]
+    async fn or_groups_select_either_condition() {
+        let db = SqliteDatabase::new_in_memory();
+        db.create_table_for_type::<ToDoDB>().await.unwrap();
+        let date = |day: u32| NaiveDate::from_ymd_opt(2025, 7, day).unwrap();
+        for (user_id, day, completed) in
+            [(1, 1, true), (1, 20, false), (1, 20, true), (2, 1, false)]
+        {
+            db.new_entry::<ToDoDB>(db_param_map! {
+                user_id: user_id,
+                name: vec![0u8],
+                deadline: date(day),
+                details: vec![0u8],
+                completed: completed,
+            })
+            .await
+            .unwrap();
+        }
+
+        // overdue or not completed todos of user 1
+        let todos: Vec<ToDoDB> = db
+            .select_entries_where(
+                WhereClause::And(vec![
+                    WhereParam::new("user_id", WhereOp::Eq, 1).into(),
+                    WhereClause::Or(vec![
+                        WhereParam::new("deadline", WhereOp::Lt, date(10)).into(),
+                        WhereParam::new("completed", WhereOp::Eq, false).into(),
+                    ]),
+                ]),
+                &[("deadline".to_string(), SortDirection::Asc)],
+                Some(Pagination {
+                    limit: 10,
+                    offset: 0,
+                }),
+            )
+            .await
+            .unwrap();
+        let found: Vec<_> = todos
+            .iter()
+            .map(|todo| (todo.deadline, todo.completed))
+            .collect();
+        assert_eq!(found, [(date(1), true), (date(20), false)]);
+    }
+
+    #[tokio::test]
+    async fn in_conditions_bind_every_value() {
+        let where_clause = WhereClause::from(vec![
+            WhereParam::is_in("id", vec![1.into(), 2.into(), 3.into()]),
+            WhereParam::new("user_id", WhereOp::Eq, 1),
+        ]);
+        assert!(
+            ExamDB::get_db_select(&where_clause)
+                .ends_with("WHERE id IN (?1, ?2, ?3) AND user_id = ?4")
+        );
+        assert!(
+            ExamDB::get_db_select_paginated(&where_clause, &[]).ends_with("LIMIT ?5 OFFSET ?6")
+        );
+
+        let db = SqliteDatabase::new_in_memory();
+        db.create_table_for_type::<ToDoDB>().await.unwrap();
+        let date = NaiveDate::from_ymd_opt(2025, 7, 1).unwrap();
+        let mut ids: Vec<i32> = Vec::new();
+        for _ in 0..3 {
+            ids.push(
+                db.new_entry::<ToDoDB>(db_param_map! {
+                    user_id: 1,
+                    name: vec![0u8],
+                    deadline: date,
+                    details: vec![0u8],
+                    completed: false,
+                })
+                .await
+                .unwrap(),
+            );
+        }
+
+        let mut params: Vec<WhereParam> = db_param_map! { user_id: 1 };
+        params.push(WhereParam::is_in(
+            "id",
+            vec![SQLValue::Int32(ids[0]), SQLValue::Int32(ids[2])],
+        ));
+        let todos = db
+            .select_entries::<ToDoDB>(params.clone(), &[], None)
+            .await
+            .unwrap();
+        assert_eq!(
+            todos.iter().map(|todo| todo.id).collect::<Vec<_>>(),
+            [ids[0], ids[2]]
+        );
+        assert_eq!(db.count_entries::<ToDoDB>(params).await.unwrap(), 2);
+
+        // an empty list matches nothing, without running invalid sql
+        let nothing = vec![WhereParam::is_in("id", Vec::new())];
+        assert!(
+            db.select_entries::<ToDoDB>(nothing.clone(), &[], None)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(db.delete_entry::<ToDoDB>(nothing).await.unwrap(), 0);
+        assert_eq!(db.count_entries::<ToDoDB>(Vec::new()).await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn typed_where_params_match_rows() {
+        let db = SqliteDatabase::new_in_memory();
+        db.create_table_for_type::<ToDoDB>().await.unwrap();
+        let date = NaiveDate::from_ymd_opt(2025, 7, 1).unwrap();
+        for (user_id, completed) in [(1, true), (1, false), (2, true)] {
+            db.new_entry::<ToDoDB>(db_param_map! {
+                user_id: user_id,
+                name: vec![0u8],
+                deadline: date,
+                details: vec![0u8],
+                completed: completed,
+            })
+            .await
+            .unwrap();
+        }
+
+        let select = async |params| {
+            db.select_entries::<ToDoDB>(params, &[], None)
+                .await
+                .unwrap()
+        };
+        assert_eq!(select(db_param_map! { completed: true }).await.len(), 2);
+        assert_eq!(select(db_param_map! { deadline: date }).await.len(), 3);
+        assert_eq!(select(db_param_map! { user_id: 1 }).await.len(), 2);
+        assert_eq!(
+            db.count_entries::<ToDoDB>(db_param_map! { user_id: 1, completed: false })
+                .await
+                .unwrap(),
+            1
+        );
+
+        // values from query strings get the type of the column
+        assert!(matches!(
+            ToDoDB::parse_filter_value("completed", "true"),
+            Some(SQLValue::Bool(true))
+        ));
+        assert!(matches!(
+            ToDoDB::parse_filter_value("deadline", "2025-07-01"),
+            Some(SQLValue::Date(parsed)) if parsed == date
+        ));
+        assert!(ToDoDB::parse_filter_value("user_id", "one").is_none());
+        // encrypted
+        assert!(ToDoDB::parse_filter_value("name", "secret").is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn concurrent_writers_wait_for_the_lock() {
+        let path = std::env::temp_dir().join(format!(
+            "eduflow_concurrency_test_{}.db",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let db = SqliteDatabase::new(&path).unwrap();
+        db.create_table_for_type::<ToDoDB>().await.unwrap();
+        let date = NaiveDate::from_ymd_opt(2025, 7, 1).unwrap();
+
+        let writers: Vec<_> = (0..16)
+            .map(|user_id| {
+                let db = db.clone();
+                tokio::spawn(async move {
+                    for _ in 0..20 {
+                        db.new_entry::<ToDoDB>(db_param_map! {
+                            user_id: user_id,
+                            name: vec![0u8],
+                            deadline: date,
+                            details: vec![0u8],
+                            completed: false,
+                        })
+                        .await
+                        .expect("insert failed while other tasks were writing");
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.await.unwrap();
+        }
+        assert_eq!(
+            db.count_entries::<ToDoDB>(Vec::new()).await.unwrap(),
+            16 * 20
+        );
+
+        let journal_mode: String = db
+            .get_conn()
+            .unwrap()
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(journal_mode, "wal");
+        drop(db);
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_transaction_is_rolled_back() {
+        let db = SqliteDatabase::new_in_memory();
+
+        let result: Result<(), _> = db
+            .with_transaction(|db| {
+                Box::pin(async move {
+                    db.new_user("rolled_back", "hash", None).await?;
+                    Err(DbError::Other("failure after the first insert".to_string()))
+                })
+            })
+            .await;
+        assert!(result.is_err());
+        assert!(db.get_user_by_username("rolled_back").await.is_err());
+
+        // methods with their own savepoint and nested calls are part of the outer transaction
+        let user_id = db
+            .with_transaction(|db| {
+                Box::pin(async move {
+                    let user_id = db.new_user("committed", "hash", None).await?;
+                    db.with_transaction(|db| {
+                        Box::pin(db.update_user_password_hash(user_id, "new_hash", &[]))
+                    })
+                    .await?;
+                    Ok(user_id)
+                })
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_user_by_id(user_id).await.unwrap().password_hash,
+            "new_hash"
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_where_fields_are_refused() {
+        let name = "name".to_string();
+        assert_eq!(
+            ExamDB::get_db_update(vec![&name], Vec::new()),
+            Err(SQLGenError::MissingWhere("update"))
+        );
+        assert_eq!(
+            ExamDB::get_db_update(Vec::new(), vec![(&name, WhereOp::Eq)]),
+            Err(SQLGenError::NoFields)
+        );
+        assert_eq!(
+            ExamDB::get_db_delete(Vec::new()),
+            Err(SQLGenError::MissingWhere("delete"))
+        );
+        // selecting everything is fine
+        assert!(ExamDB::get_db_select(&WhereClause::And(Vec::new())).ends_with("FROM ExamDB"));
+
+        // the db returns the error instead of touching every row
+        let db = SqliteDatabase::new_in_memory();
+        db.create_table_for_type::<ToDoDB>().await.unwrap();
+        db.new_entry::<ToDoDB>(db_param_map! {
+            user_id: 1,
+            name: vec![0u8],
+            deadline: NaiveDate::from_ymd_opt(2025, 7, 1).unwrap(),
+            details: vec![0u8],
+            completed: false,
+        })
+        .await
+        .unwrap();
+        let err = db.delete_entry::<ToDoDB>(Vec::new()).await.unwrap_err();
+        assert!(matches!(
+            err,
+            DbError::Statement(SQLGenError::MissingWhere("delete"))
+        ));
+        let err = db
+            .update_entry::<ToDoDB>(db_param_map! { completed: true }, Vec::new())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            DbError::Statement(SQLGenError::MissingWhere(_))
+        ));
+        let todos: Vec<ToDoDB> = db.select_entries(Vec::new(), &[], None).await.unwrap();
+        assert_eq!(todos.len(), 1);
+        assert!(!todos[0].completed);
+    }
+
+    #[tokio::test]
+    async fn soft_deleted_rows_are_hidden_until_restored() {
+        let db = SqliteDatabase::new_in_memory();
+        db.create_table_for_type::<ToDoDB>().await.unwrap();
+        let id = db
+            .new_entry::<ToDoDB>(db_param_map! {
+                user_id: 1,
+                name: vec![0u8],
+                deadline: NaiveDate::from_ymd_opt(2025, 7, 1).unwrap(),
+                details: vec![0u8],
+                completed: false,
+            })
+            .await
+            .unwrap();
+        let this = || db_param_map! { id: id, user_id: 1 };
+
+        assert_eq!(db.soft_delete_entry::<ToDoDB>(this()).await.unwrap(), 1);
+        // deleting twice does not move the deletion time
+        assert_eq!(db.soft_delete_entry::<ToDoDB>(this()).await.unwrap(), 0);
+        assert!(
+            db.select_entries::<ToDoDB>(this(), &[], None)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(db.count_entries::<ToDoDB>(Vec::new()).await.unwrap(), 0);
+        assert_eq!(
+            db.update_entry::<ToDoDB>(db_param_map! { completed: true }, this())
+                .await
+                .unwrap(),
+            0
+        );
+
+        // deleted before deleted_since, e.g. after the grace period
+        let future = NaiveDate::from_ymd_opt(9999, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        assert_eq!(
+            db.restore_entry::<ToDoDB>(this(), &future).await.unwrap(),
+            0
+        );
+        let past = NaiveDateTime::default();
+        assert_eq!(db.restore_entry::<ToDoDB>(this(), &past).await.unwrap(), 1);
+        assert_eq!(
+            db.select_entries::<ToDoDB>(this(), &[], None)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // types without soft delete are deleted for real
+        let err = db.soft_delete_entry::<ExamDB>(this()).await.unwrap_err();
+        assert!(matches!(err, DbError::Statement(SQLGenError::NoSoftDelete)));
+        assert_eq!(
+            ExamDB::get_db_restore(Vec::new()),
+            Err(SQLGenError::NoSoftDelete)
+        );
+    }
+
+    #[tokio::test]
+    async fn timestamps_are_set_by_inserts_and_updates() {
+        let db = SqliteDatabase::new_in_memory();
+        db.create_table_for_type::<ToDoDB>().await.unwrap();
+        let id = db
+            .new_entry::<ToDoDB>(db_param_map! {
+                user_id: 1,
+                name: vec![0u8],
+                deadline: NaiveDate::from_ymd_opt(2025, 7, 1).unwrap(),
+                details: vec![0u8],
+                completed: false,
+            })
+            .await
+            .unwrap();
+        let select = async || {
+            db.select_entries::<ToDoDB>(db_param_map! { id: id }, &[], None)
+                .await
+                .unwrap()
+                .remove(0)
+        };
+
+        let created = select().await;
+        assert!(created.created_at > NaiveDateTime::default());
+        assert!(created.updated_at.is_none());
+        // the timestamps are not part of the params, reverts and copies do not carry them over
+        assert!(
+            created
+                .to_params()
+                .iter()
+                .all(|(field, _)| field != "created_at" && field != "updated_at")
+        );
+
+        db.update_entry::<ToDoDB>(db_param_map! { completed: true }, db_param_map! { id: id })
+            .await
+            .unwrap();
+        let updated = select().await;
+        assert_eq!(updated.created_at, created.created_at);
+        assert!(updated.updated_at.unwrap() >= created.created_at);
+    }
+
+    #[tokio::test]
+    async fn sqlite_errors_are_mapped_to_their_kind() {
+        let db = SqliteDatabase::new_in_memory();
+        db.new_user("taken", "hash", None).await.unwrap();
+        assert!(matches!(
+            db.new_user("taken", "hash", None).await,
+            Err(DbError::UniqueViolation(_))
+        ));
+        assert!(matches!(
+            db.get_user_by_id(4242).await,
+            Err(DbError::NotFound)
+        ));
+
+        db.create_table_for_type::<CourseDB>().await.unwrap();
+        db.create_table_for_type::<TopicDB>().await.unwrap();
+        let topic = db
+            .new_entry::<TopicDB>(db_param_map! {
+                user_id: 1,
+                course_id: 4242,
+                name: vec![0u8],
+                details: vec![0u8],
+            })
+            .await;
+        assert!(matches!(topic, Err(DbError::ForeignKeyViolation(_))));
+    }
+
+    #[tokio::test]
+    async fn bulk_insert_is_all_or_nothing() {
+        let db = SqliteDatabase::new_in_memory();
+        db.create_table_for_type::<ToDoDB>().await.unwrap();
+        let row = |name: u8| {
+            db_param_map! {
+                user_id: 1,
+                name: vec![name],
+                deadline: NaiveDate::from_ymd_opt(2025, 7, 1).unwrap(),
+                details: vec![0u8],
+                completed: false,
+            }
+        };
+
+        let ids = db
+            .new_entries::<ToDoDB>(vec![row(1), row(2), row(3)])
+            .await
+            .unwrap();
+        assert_eq!(ids.len(), 3);
+        assert!(ids.windows(2).all(|ids| ids[0] < ids[1]));
+        let first: Vec<ToDoDB> = db
+            .select_entries(db_param_map! { id: ids[0] }, &[], None)
+            .await
+            .unwrap();
+        assert_eq!(first[0].name.data_crypt, vec![1]);
+
+        // the last row misses a not null column, the rows before it are rolled back
+        let mut broken = row(5);
+        broken.retain(|(field, _)| field != "deadline");
+        assert!(
+            db.new_entries::<ToDoDB>(vec![row(4), broken])
+                .await
+                .is_err()
+        );
+        assert_eq!(db.count_entries::<ToDoDB>(Vec::new()).await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn upsert_inserts_and_updates_by_id() {
+        let (id, user_id, name) = ("id".to_string(), "user_id".to_string(), "name".to_string());
+        assert_eq!(
+            ExamDB::get_db_upsert(vec![&id, &user_id, &name]),
+            "INSERT INTO ExamDB (id,user_id,name,created_at) VALUES (?1,?2,?3,CURRENT_TIMESTAMP) ON CONFLICT(id) DO UPDATE SET name = excluded.name, updated_at = CURRENT_TIMESTAMP WHERE user_id = excluded.user_id"
+        );
+
+        let db = SqliteDatabase::new_in_memory();
+        db.create_table_for_type::<ToDoDB>().await.unwrap();
+        let row = |user_id: i32, completed: bool| {
+            db_param_map! {
+                id: 42,
+                user_id: user_id,
+                name: vec![0u8],
+                deadline: NaiveDate::from_ymd_opt(2025, 7, 1).unwrap(),
+                details: vec![0u8],
+                completed: completed,
+            }
+        };
+
+        // insert and update path
+        assert_eq!(
+            db.upsert_entry::<ToDoDB>(row(1, false)).await.unwrap(),
+            Some(42)
+        );
+        assert_eq!(
+            db.upsert_entry::<ToDoDB>(row(1, true)).await.unwrap(),
+            Some(42)
+        );
+        let todos: Vec<ToDoDB> = db.select_entries(Vec::new(), &[], None).await.unwrap();
+        assert_eq!(todos.len(), 1);
+        assert!(todos[0].completed);
+
+        // the entry of user 1 can not be taken over
+        assert_eq!(
+            db.upsert_entry::<ToDoDB>(row(2, false)).await.unwrap(),
+            None
+        );
+        let todos: Vec<ToDoDB> = db.select_entries(Vec::new(), &[], None).await.unwrap();
+        assert_eq!((todos[0].user_id, todos[0].completed), (1, true));
+    }
+
+    #[tokio::test]
+    async fn defaults_fill_omitted_fields() {
+        assert!(
+            ToDoDB::get_db_table_create().contains(",completed INTEGER NOT NULL DEFAULT FALSE,")
+        );
+        assert!(ExamDB::get_column_defs().contains(&(
+            "prep_status".to_string(),
+            "TEXT NOT NULL DEFAULT 'not_started'".to_string()
+        )));
+
+        let db = SqliteDatabase::new_in_memory();
+        db.create_table_for_type::<ToDoDB>().await.unwrap();
+        db.new_entry::<ToDoDB>(db_param_map! {
+            user_id: 1,
+            name: vec![0u8],
+            deadline: NaiveDate::from_ymd_opt(2025, 7, 1).unwrap(),
+            details: vec![0u8],
+        })
+        .await
+        .unwrap();
+        let todos = db
+            .select_entries::<ToDoDB>(Vec::new(), &[], None)
+            .await
+            .unwrap();
+        assert!(!todos[0].completed);
+
+        // columns with a constant default are added to existing tables by the schema verification
+        db.execute_batch(
+            "CREATE TABLE ExamDB (id INTEGER PRIMARY KEY AUTOINCREMENT,user_id INTEGER NOT NULL,course_id INTEGER NOT NULL,name BLOB NOT NULL,date DATE NOT NULL,created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP);
+            INSERT INTO ExamDB (user_id, course_id, name, date) VALUES (1, 1, x'00', '2025-07-01');",
+        );
+        db.verify_schema_for_type::<ExamDB>().await.unwrap();
+        let exams = db
+            .select_entries::<ExamDB>(Vec::new(), &[], None)
+            .await
+            .unwrap();
+        assert_eq!(exams[0].prep_status, PrepStatus::NotStarted);
+    }
+
+    #[allow(dead_code)] // only written, the rows are never read
+    #[derive(DBObject)]
+    #[db(table = "session", check = "started <= finished")]
+    struct SessionDB {
+        id: i32,
+        user_id: i32,
+        #[db(check = "progress BETWEEN 0 AND 100")]
+        progress: i32,
+        started: i32,
+        finished: Option<i32>,
+    }
+
+    #[tokio::test]
+    async fn check_attributes_reject_out_of_range_values() {
+        assert_eq!(
+            SessionDB::get_db_table_create(),
+            "CREATE TABLE IF NOT EXISTS session (id INTEGER PRIMARY KEY AUTOINCREMENT,user_id INTEGER NOT NULL,progress INTEGER NOT NULL CONSTRAINT progress CHECK (progress BETWEEN 0 AND 100),started INTEGER NOT NULL,finished INTEGER,CHECK (started <= finished))"
+        );
+
+        let db = SqliteDatabase::new_in_memory();
+        db.create_table_for_type::<SessionDB>().await.unwrap();
+        let id = db
+            .new_entry::<SessionDB>(db_param_map! { user_id: 1, progress: 100, started: 5 })
+            .await
+            .unwrap();
+        // field checks are reported by their column, table checks by their expression
+        assert!(matches!(
+            db.new_entry::<SessionDB>(db_param_map! { user_id: 1, progress: 101, started: 5 }).await,
+            Err(DbError::CheckViolation(constraint)) if constraint == "progress"
+        ));
+        assert!(matches!(
+            db.update_entry::<SessionDB>(db_param_map! { finished: 4 }, db_param_map! { id: id })
+                .await,
+            Err(DbError::CheckViolation(constraint)) if constraint == "started <= finished"
+        ));
+    }
+
+    #[tokio::test]
+    async fn add_column_migrates_existing_table() {
+        let db = SqliteDatabase::new_in_memory();
+        // exam table as created before the preparation fields existed
+        db.get_conn()
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE ExamDB (id INTEGER PRIMARY KEY AUTOINCREMENT,user_id INTEGER NOT NULL,course_id INTEGER NOT NULL,name BLOB NOT NULL,date DATE NOT NULL);
+                INSERT INTO ExamDB (user_id, course_id, name, date) VALUES (1, 1, x'00', '2025-07-01');",
+            )
+            .unwrap();
+        assert!(
+            db.select_entries::<ExamDB>(Vec::new(), &[], None)
+                .await
+                .is_err()
+        );
+
+        db.add_column_for_type::<ExamDB>("prep_status", "TEXT NOT NULL DEFAULT 'not_started'")
+            .await
+            .unwrap();
+        db.add_column_for_type::<ExamDB>("prep_notes", "BLOB")
+            .await
+            .unwrap();
+        // running the migration again does nothing
+        db.add_column_for_type::<ExamDB>("prep_notes", "BLOB")
+            .await
+            .unwrap();
+        db.add_column_for_type::<ExamDB>(
+            "created_at",
+            "DATETIME NOT NULL DEFAULT '2025-07-01 12:00:00'",
+        )
+        .await
+        .unwrap();
+        db.add_column_for_type::<ExamDB>("updated_at", "DATETIME")
+            .await
+            .unwrap();
+
+        let exams = db
+            .select_entries::<ExamDB>(Vec::new(), &[], None)
+            .await
+            .unwrap();
+        assert_eq!(exams.len(), 1);
+        assert_eq!(exams[0].prep_status, PrepStatus::NotStarted);
+        assert!(exams[0].prep_notes.is_none());
+        assert_eq!(
+            exams[0].created_at,
+            NaiveDate::from_ymd_opt(2025, 7, 1)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap()
+        );
+        assert!(exams[0].updated_at.is_none());
+
+        // the migrated table accepts rows of the current schema
+        assert!(
+            db.get_conn()
+                .unwrap()
+                .execute(&ExamDB::get_db_table_create(), [])
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn schema_verification_adds_missing_columns() {
+        let db = SqliteDatabase::new_in_memory();
+        // todo table as created before todos had timestamps and could be soft deleted
+        db.execute_batch(
+            "CREATE TABLE ToDoDB (id INTEGER PRIMARY KEY AUTOINCREMENT,user_id INTEGER NOT NULL,name BLOB NOT NULL,deadline DATE NOT NULL,details BLOB NOT NULL,completed INTEGER NOT NULL);
+            INSERT INTO ToDoDB (user_id, name, deadline, details, completed) VALUES (1, x'00', '2025-07-01', x'00', 0);",
+        );
+        let columns = || {
+            db.get_conn()
+                .unwrap()
+                .prepare("SELECT name FROM pragma_table_info('ToDoDB')")
+                .unwrap()
+                .query_map([], |row| row.get::<_, String>(0))
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        };
+
+        // created_at needs a value for the existing rows, the error names the statement to add it
+        match db.verify_schema_for_type::<ToDoDB>().await {
+            Err(DbError::SchemaMismatch(message)) => assert!(message.contains(
+                "ALTER TABLE ToDoDB ADD COLUMN created_at DATETIME NOT NULL DEFAULT <value>;"
+            )),
+            other => panic!("expected a schema mismatch, got {:?}", other),
+        }
+        // the nullable columns are added anyways
+        assert!(columns().contains(&"updated_at".to_string()));
+        assert!(columns().contains(&"deleted_at".to_string()));
+
+        db.add_column_for_type::<ToDoDB>(
+            "created_at",
+            "DATETIME NOT NULL DEFAULT '2025-07-01 12:00:00'",
+        )
+        .await
+        .unwrap();
+        db.verify_schema_for_type::<ToDoDB>().await.unwrap();
+        let todos = db
+            .select_entries::<ToDoDB>(Vec::new(), &[], None)
+            .await
+            .unwrap();
+        assert_eq!(todos.len(), 1);
+        assert!(todos[0].updated_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn schema_verification_adds_the_semester_of_courses() {
+        let db = SqliteDatabase::new_in_memory();
+        // course table as created before courses could belong to a semester
+        db.execute_batch(
+            "CREATE TABLE CourseDB (id INTEGER PRIMARY KEY AUTOINCREMENT,user_id INTEGER NOT NULL,name BLOB NOT NULL,created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,updated_at DATETIME);
+            INSERT INTO CourseDB (user_id, name) VALUES (1, x'00');",
+        );
+
+        db.verify_schema_for_type::<CourseDB>().await.unwrap();
+        let references: Vec<(String, String)> = db
+            .get_conn()
+            .unwrap()
+            .prepare("SELECT \"from\", \"table\" FROM pragma_foreign_key_list('CourseDB')")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            references,
+            [("semester_id".to_string(), "SemesterDB".to_string())]
+        );
+        let courses = db
+            .select_entries::<CourseDB>(Vec::new(), &[], None)
+            .await
+            .unwrap();
+        assert_eq!(courses.len(), 1);
+        assert!(courses[0].semester_id.is_none());
+    }
+
+    #[tokio::test]
+    async fn schema_verification_adds_the_fields_of_study_goals() {
+        let db = SqliteDatabase::new_in_memory();
+        // study goal table as created before goals had a title, a target and a status
+        db.execute_batch(
+            "CREATE TABLE StudyGoalDB (id INTEGER PRIMARY KEY AUTOINCREMENT,user_id INTEGER NOT NULL,topic_id INTEGER NOT NULL,deadline DATE NOT NULL,topics_missing INTEGER NOT NULL DEFAULT FALSE,exam_id INTEGER,created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,updated_at DATETIME);
+            INSERT INTO StudyGoalDB (user_id, topic_id, deadline) VALUES (1, 1, '2025-07-01');",
+        );
+
+        db.verify_schema_for_type::<StudyGoalDB>().await.unwrap();
+        let goals = db
+            .select_entries::<StudyGoalDB>(Vec::new(), &[], None)
+            .await
+            .unwrap();
+        assert_eq!(goals.len(), 1);
+        assert!(goals[0].title.is_none());
+        assert!(goals[0].target_minutes.is_none());
+        assert_eq!(goals[0].status, GoalStatus::Open);
+    }
+
+    #[tokio::test]
+    async fn appended_rows_are_placed_last_and_selected_in_order() {
+        assert_eq!(TopicDB::default_order(), ["course_id", "position"]);
+        let insert = TopicDB::get_db_insert(vec![&"user_id".to_string(), &"course_id".to_string()]);
+        assert!(insert.ends_with(",position) VALUES (?1,?2,CURRENT_TIMESTAMP,(SELECT COALESCE(MAX(position) + 1, 0) FROM TopicDB WHERE user_id = ?1 AND course_id = ?2))"));
+
+        let db = SqliteDatabase::new_in_memory();
+        db.create_table_for_type::<SemesterDB>().await.unwrap();
+        db.create_table_for_type::<CourseDB>().await.unwrap();
+        db.create_table_for_type::<TopicDB>().await.unwrap();
+        for _ in 0..2 {
+            db.new_entry::<CourseDB>(db_param_map! { user_id: 1, name: vec![0u8] })
+                .await
+                .unwrap();
+        }
+        let topic = |user_id: i32, course_id: i32| {
+            db.new_entry::<TopicDB>(db_param_map! {
+                user_id: user_id,
+                course_id: course_id,
+                name: vec![0u8],
+                details: vec![0u8],
+            })
+        };
+        let first = topic(1, 1).await.unwrap();
+        let other_course = topic(1, 2).await.unwrap();
+        let other_user = topic(2, 1).await.unwrap();
+        let second = topic(1, 1).await.unwrap();
+        // an explicit position is kept
+        db.update_entry::<TopicDB>(db_param_map! { position: 5 }, db_param_map! { id: first })
+            .await
+            .unwrap();
+        let third = topic(1, 1).await.unwrap();
+
+        let topics = db
+            .select_entries::<TopicDB>(Vec::new(), &[], None)
+            .await
+            .unwrap();
+        let order: Vec<(i32, i32)> = topics
+            .iter()
+            .map(|topic| (topic.id, topic.position))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                (other_user, 0),
+                (second, 1),
+                (first, 5),
+                (third, 6),
+                (other_course, 0)
+            ]
+        );
     }
 }
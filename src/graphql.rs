@@ -0,0 +1,570 @@
+use std::{collections::HashMap, marker::PhantomData, sync::Arc};
+
+use async_graphql::{
+    ComplexObject, Context, EmptySubscription, Error, ErrorExtensions, Object, Result, Schema,
+    dataloader::{DataLoader, Loader},
+};
+use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use axum::{
+    Extension, Router,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::post,
+};
+use chrono::Utc;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::{
+    AppState,
+    auth_handler::{AuthError, AuthenticatedUser, verify_token},
+    data_handler::{
+        CheckReferences, FromDB, LocalKey, Sendable, ToDB,
+        course::remove_course,
+        get_entries,
+        objects::{
+            CourseDB, CourseSend, ExamDB, ExamSend, StudyGoalDB, StudyGoalSend, ToDoDB, ToDoSend,
+            TopicDB, TopicSend,
+        },
+        remove_entry, save_entry,
+        study_goal::{with_progress, with_topic_ids},
+        todo::{next_occurrence, remove_todo, stored_completion},
+    },
+    db::{
+        BoxFuture, DBInterface,
+        sql_helper::{SQLGenerate, WhereOp, WhereParam},
+    },
+};
+
+/// schema type of the graphql endpoint
+pub type EduflowSchema<DB> = Schema<QueryRoot<DB>, MutationRoot<DB>, EmptySubscription>;
+
+/// This function defines the graphql route, mounted at /graphql
+pub fn graphql_router<DB: DBInterface + Send + Sync + 'static>(state: Arc<AppState<DB>>) -> Router {
+    Router::new()
+        .route("/", post(handle_graphql::<DB>))
+        .layer(Extension(build_schema(state.clone())))
+        .with_state(state)
+}
+
+/// builds the graphql schema, the app state is available to every resolver
+pub fn build_schema<DB: DBInterface + Send + Sync + 'static>(
+    state: Arc<AppState<DB>>,
+) -> EduflowSchema<DB> {
+    Schema::build(
+        QueryRoot(PhantomData),
+        MutationRoot(PhantomData),
+        EmptySubscription,
+    )
+    .data(state)
+    .finish()
+}
+
+/// verified remote token of the request, resolvers refuse to work without it
+pub type Session = AuthenticatedUser;
+
+/// handler for graphql requests, checks the bearer token and adds the session to the request context
+async fn handle_graphql<DB: DBInterface + Send + Sync + 'static>(
+    headers: HeaderMap,
+    State(state): State<Arc<AppState<DB>>>,
+    Extension(schema): Extension<EduflowSchema<DB>>,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    info!("GraphQL request received!");
+
+    let mut request = request.into_inner();
+
+    // an invalid token is not rejected here, every resolver answers with an unauthorized error instead
+    match verify_token(&headers, state.clone()).await {
+        Ok((user_id, remote_token_id, remote_token)) => {
+            let session = Session {
+                user_id,
+                remote_token_id,
+                remote_token,
+            };
+            request = request
+                .data(DataLoader::new(
+                    children_by_course::<TopicDB, TopicSend, DB>(&state, &session, |t| t.course_id),
+                    tokio::spawn,
+                ))
+                .data(DataLoader::new(
+                    children_by_course::<ExamDB, ExamSend, DB>(&state, &session, |e| e.course_id),
+                    tokio::spawn,
+                ))
+                .data(session);
+        }
+        Err(err) => {
+            if matches!(err, AuthError::InvalidToken | AuthError::Expired) {
+                warn!("Authentication failure, invalid token!");
+            }
+            // every resolver answers with the status of the failed authentication
+            request = request.data(StatusCode::from(err));
+        }
+    }
+
+    schema.execute(request).await.into()
+}
+
+/// converts a status code of the shared data handler logic into a graphql error
+fn status_error(status: StatusCode) -> Error {
+    Error::new(status.canonical_reason().unwrap_or("Error"))
+        .extend_with(|_, e| e.set("status", status.as_u16()))
+}
+
+/// returns the session of the request or the error of the failed authentication (unauthorized without token)
+fn session<'a>(ctx: &Context<'a>) -> Result<&'a Session> {
+    ctx.data::<Session>().map_err(|_| {
+        status_error(
+            ctx.data_opt::<StatusCode>()
+                .copied()
+                .unwrap_or(StatusCode::UNAUTHORIZED),
+        )
+    })
+}
+
+/// loads entries of the type through the same logic as handle_get
+async fn query<DBT: SQLGenerate, ST: FromDB<DBT>, DB: DBInterface + Send + Sync + 'static>(
+    ctx: &Context<'_>,
+    params: Vec<(&str, Option<i32>)>,
+) -> Result<Vec<ST>> {
+    let session = session(ctx)?;
+    let state = ctx.data::<Arc<AppState<DB>>>()?;
+    let params = params
+        .into_iter()
+        .filter_map(|(name, value)| Some(WhereParam::new(name, WhereOp::Eq, value?)))
+        .collect();
+
+    get_entries::<DBT, ST, DB>(
+        state,
+        &LocalKey::<DBT>::new(state, session.clone())
+            .await
+            .map_err(status_error)?,
+        params,
+        &[],
+        None,
+    )
+    .await
+    .map_err(status_error)
+}
+
+/// creates or edits an entry through the same logic as handle_new
+async fn save<
+    DBT: SQLGenerate + Serialize,
+    ST: Sendable + ToDB + CheckReferences + Serialize,
+    DB: DBInterface + Send + Sync + 'static,
+>(
+    ctx: &Context<'_>,
+    input: ST,
+) -> Result<i32> {
+    let session = session(ctx)?;
+    let state = ctx.data::<Arc<AppState<DB>>>()?;
+    if let Err(errors) = state.payload_limits.check(&input) {
+        let fields: Vec<String> = errors.into_iter().map(|error| error.field).collect();
+        return Err(status_error(StatusCode::PAYLOAD_TOO_LARGE)
+            .extend_with(|_, e| e.set("fields", fields.join(","))));
+    }
+
+    save_entry::<DBT, ST, DB>(
+        state,
+        &LocalKey::<DBT>::new(state, session.clone())
+            .await
+            .map_err(status_error)?,
+        input,
+    )
+    .await
+    .map_err(|response| status_error(response.status()))
+}
+
+/// deletes an entry through the same logic as handle_delete
+async fn remove<DBT: SQLGenerate + Serialize, DB: DBInterface + Send + Sync + 'static>(
+    ctx: &Context<'_>,
+    id: i32,
+) -> Result<i32> {
+    let session = session(ctx)?;
+    let state = ctx.data::<Arc<AppState<DB>>>()?;
+
+    remove_entry::<DBT, DB>(
+        state,
+        &LocalKey::<DBT>::new(state, session.clone())
+            .await
+            .map_err(status_error)?,
+        id,
+    )
+    .await
+    .map_err(status_error)?;
+    Ok(id)
+}
+
+/// type erased load function of a dataloader, maps course ids to their children
+type LoadFn<ST> =
+    Box<dyn Fn(Vec<i32>) -> BoxFuture<'static, Result<HashMap<i32, Vec<ST>>, Error>> + Send + Sync>;
+
+/// dataloader batching the children (topics, exams) of all requested courses into one select
+pub struct ChildrenByCourse<ST> {
+    load: LoadFn<ST>,
+}
+
+impl<ST: Clone + Send + Sync + 'static> Loader<i32> for ChildrenByCourse<ST> {
+    type Value = Vec<ST>;
+    type Error = Error;
+
+    async fn load(&self, keys: &[i32]) -> Result<HashMap<i32, Vec<ST>>, Error> {
+        (self.load)(keys.to_vec()).await
+    }
+}
+
+/// creates a loader selecting the entries of all requested courses at once and grouping them by course id
+fn children_by_course<DBT, ST, DB>(
+    state: &Arc<AppState<DB>>,
+    session: &Session,
+    course_id: fn(&ST) -> i32,
+) -> ChildrenByCourse<ST>
+where
+    DBT: SQLGenerate,
+    ST: FromDB<DBT> + Clone + Send + Sync + 'static,
+    DB: DBInterface + Send + Sync + 'static,
+{
+    let (state, session) = (state.clone(), session.clone());
+
+    ChildrenByCourse {
+        load: Box::new(move |course_ids| {
+            let (state, session) = (state.clone(), session.clone());
+            Box::pin(async move {
+                let key = LocalKey::<DBT>::new(&state, session)
+                    .await
+                    .map_err(status_error)?;
+                let course_filter = WhereParam::is_in(
+                    "course_id",
+                    course_ids.iter().map(|id| (*id).into()).collect(),
+                );
+                let entries =
+                    get_entries::<DBT, ST, DB>(&state, &key, vec![course_filter], &[], None)
+                        .await
+                        .map_err(status_error)?;
+
+                let mut children: HashMap<i32, Vec<ST>> =
+                    course_ids.iter().map(|id| (*id, Vec::new())).collect();
+                for entry in entries {
+                    if let Some(list) = children.get_mut(&course_id(&entry)) {
+                        list.push(entry);
+                    }
+                }
+                Ok(children)
+            })
+        }),
+    }
+}
+
+#[ComplexObject]
+impl CourseSend {
+    /// topics of the course
+    async fn topics(&self, ctx: &Context<'_>) -> Result<Vec<TopicSend>> {
+        let loader = ctx.data::<DataLoader<ChildrenByCourse<TopicSend>>>()?;
+        let Some(id) = self.id else {
+            return Ok(Vec::new());
+        };
+        Ok(loader.load_one(id).await?.unwrap_or_default())
+    }
+
+    /// exams of the course, only exams from today on if upcoming is true
+    async fn exams(&self, ctx: &Context<'_>, upcoming: Option<bool>) -> Result<Vec<ExamSend>> {
+        let loader = ctx.data::<DataLoader<ChildrenByCourse<ExamSend>>>()?;
+        let Some(id) = self.id else {
+            return Ok(Vec::new());
+        };
+        let mut exams = loader.load_one(id).await?.unwrap_or_default();
+
+        if upcoming.unwrap_or(false) {
+            let today = Utc::now().date_naive();
+            exams.retain(|exam| exam.date >= today);
+        }
+        Ok(exams)
+    }
+}
+
+/// read access to all object types, arguments are filters checked on equality
+pub struct QueryRoot<DB>(PhantomData<DB>);
+
+#[Object]
+impl<DB: DBInterface + Send + Sync + 'static> QueryRoot<DB> {
+    async fn courses(
+        &self,
+        ctx: &Context<'_>,
+        id: Option<i32>,
+        semester_id: Option<i32>,
+    ) -> Result<Vec<CourseSend>> {
+        query::<CourseDB, CourseSend, DB>(ctx, vec![("id", id), ("semester_id", semester_id)]).await
+    }
+
+    async fn topics(
+        &self,
+        ctx: &Context<'_>,
+        id: Option<i32>,
+        course_id: Option<i32>,
+    ) -> Result<Vec<TopicSend>> {
+        query::<TopicDB, TopicSend, DB>(ctx, vec![("id", id), ("course_id", course_id)]).await
+    }
+
+    async fn study_goals(
+        &self,
+        ctx: &Context<'_>,
+        id: Option<i32>,
+        topic_id: Option<i32>,
+    ) -> Result<Vec<StudyGoalSend>> {
+        let goals =
+            query::<StudyGoalDB, StudyGoalSend, DB>(ctx, vec![("id", id), ("topic_id", topic_id)])
+                .await?;
+        let (state, user_id) = (ctx.data::<Arc<AppState<DB>>>()?, session(ctx)?.user_id);
+        let goals = with_topic_ids(state, user_id, goals)
+            .await
+            .map_err(status_error)?;
+        with_progress(state, user_id, goals)
+            .await
+            .map_err(status_error)
+    }
+
+    async fn exams(
+        &self,
+        ctx: &Context<'_>,
+        id: Option<i32>,
+        course_id: Option<i32>,
+    ) -> Result<Vec<ExamSend>> {
+        query::<ExamDB, ExamSend, DB>(ctx, vec![("id", id), ("course_id", course_id)]).await
+    }
+
+    async fn todos(&self, ctx: &Context<'_>, id: Option<i32>) -> Result<Vec<ToDoSend>> {
+        query::<ToDoDB, ToDoSend, DB>(ctx, vec![("id", id)]).await
+    }
+}
+
+/// write access to all object types, save creates (id is null) or edits, both return the id
+pub struct MutationRoot<DB>(PhantomData<DB>);
+
+#[Object]
+impl<DB: DBInterface + Send + Sync + 'static> MutationRoot<DB> {
+    async fn save_course(&self, ctx: &Context<'_>, input: CourseSend) -> Result<i32> {
+        save::<CourseDB, CourseSend, DB>(ctx, input).await
+    }
+
+    /// deletes the course with its topics, exams and the study goals of its topics
+    async fn delete_course(&self, ctx: &Context<'_>, id: i32) -> Result<i32> {
+        let session = session(ctx)?;
+        let state = ctx.data::<Arc<AppState<DB>>>()?;
+
+        remove_course(
+            state,
+            &LocalKey::<CourseDB>::new(state, session.clone())
+                .await
+                .map_err(status_error)?,
+            id,
+        )
+        .await
+        .map_err(status_error)?;
+        Ok(id)
+    }
+
+    async fn save_topic(&self, ctx: &Context<'_>, input: TopicSend) -> Result<i32> {
+        save::<TopicDB, TopicSend, DB>(ctx, input).await
+    }
+
+    async fn delete_topic(&self, ctx: &Context<'_>, id: i32) -> Result<i32> {
+        remove::<TopicDB, DB>(ctx, id).await
+    }
+
+    async fn save_study_goal(&self, ctx: &Context<'_>, input: StudyGoalSend) -> Result<i32> {
+        save::<StudyGoalDB, StudyGoalSend, DB>(ctx, input).await
+    }
+
+    async fn delete_study_goal(&self, ctx: &Context<'_>, id: i32) -> Result<i32> {
+        remove::<StudyGoalDB, DB>(ctx, id).await
+    }
+
+    async fn save_exam(&self, ctx: &Context<'_>, input: ExamSend) -> Result<i32> {
+        save::<ExamDB, ExamSend, DB>(ctx, input).await
+    }
+
+    async fn delete_exam(&self, ctx: &Context<'_>, id: i32) -> Result<i32> {
+        remove::<ExamDB, DB>(ctx, id).await
+    }
+
+    /// completing a recurring todo creates its next occurrence and completed_at is kept, like the rest route
+    async fn save_todo(&self, ctx: &Context<'_>, mut input: ToDoSend) -> Result<i32> {
+        let state = ctx.data::<Arc<AppState<DB>>>()?;
+        let session = session(ctx)?;
+        let before = stored_completion(state, session.user_id, input.id)
+            .await
+            .map_err(status_error)?;
+        input.completed_at = before.completed_at;
+        let id = save::<ToDoDB, ToDoSend, DB>(ctx, input).await?;
+
+        let key = LocalKey::<ToDoDB>::new(state, session.clone())
+            .await
+            .map_err(status_error)?;
+        next_occurrence(state, &key, id, before.completed)
+            .await
+            .map_err(|response| status_error(response.status()))?;
+        Ok(id)
+    }
+
+    /// subtasks are deleted with their todo, like the rest route
+    async fn delete_todo(&self, ctx: &Context<'_>, id: i32) -> Result<i32> {
+        let state = ctx.data::<Arc<AppState<DB>>>()?;
+        let key = LocalKey::<ToDoDB>::new(state, session(ctx)?.clone())
+            .await
+            .map_err(status_error)?;
+        remove_todo(state, &key, id).await.map_err(status_error)?;
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::{Body, to_bytes},
+        http::Request,
+    };
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::test_util::{register_user, test_state};
+
+    /// sends a graphql request through the router and returns the json response
+    async fn execute(router: Router, token: Option<&str>, query: &str) -> Value {
+        let mut request = Request::post("/").header("content-type", "application/json");
+        if let Some(token) = token {
+            request = request.header("authorization", format!("Bearer {}", token));
+        }
+        let request = request
+            .body(Body::from(json!({ "query": query }).to_string()))
+            .unwrap();
+
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_mutation_and_nested_query() {
+        let state = test_state().await;
+        let token = register_user(&state, "graphql_user").await;
+        let router = graphql_router(state.clone());
+
+        let response = execute(
+            router.clone(),
+            Some(&token),
+            r#"mutation { saveCourse(input: { name: "Analysis" }) }"#,
+        )
+        .await;
+        let course_id = response["data"]["saveCourse"].as_i64().unwrap();
+        // second course without children
+        execute(
+            router.clone(),
+            Some(&token),
+            r#"mutation { saveCourse(input: { name: "Empty" }) }"#,
+        )
+        .await;
+
+        let mutation = format!(
+            r#"mutation {{
+                t1: saveTopic(input: {{ courseId: {course_id}, name: "Limits", details: "epsilon delta" }})
+                t2: saveTopic(input: {{ courseId: {course_id}, name: "Series", details: "" }})
+                past: saveExam(input: {{ courseId: {course_id}, name: "Midterm", date: "2000-01-01" }})
+                future: saveExam(input: {{ courseId: {course_id}, name: "Final", date: "2999-01-01" }})
+            }}"#
+        );
+        let response = execute(router.clone(), Some(&token), &mutation).await;
+        assert!(response["errors"].is_null(), "{}", response);
+
+        let response = execute(
+            router.clone(),
+            Some(&token),
+            r#"{ courses { name topics { name details } exams(upcoming: true) { name date } } }"#,
+        )
+        .await;
+        assert_eq!(
+            response["data"]["courses"],
+            json!([
+                {
+                    "name": "Analysis",
+                    "topics": [
+                        { "name": "Limits", "details": "epsilon delta" },
+                        { "name": "Series", "details": "" }
+                    ],
+                    "exams": [{ "name": "Final", "date": "2999-01-01" }]
+                },
+                { "name": "Empty", "topics": [], "exams": [] }
+            ])
+        );
+
+        // deletion goes through the same path as the REST handler
+        let response = execute(
+            router.clone(),
+            Some(&token),
+            &format!("mutation {{ deleteCourse(id: {course_id}) }}"),
+        )
+        .await;
+        assert_eq!(response["data"]["deleteCourse"], course_id);
+        let response = execute(router, Some(&token), "{ courses { name } }").await;
+        assert_eq!(response["data"]["courses"], json!([{ "name": "Empty" }]));
+    }
+
+    #[tokio::test]
+    async fn test_children_are_loaded_for_the_requested_courses_only() {
+        let state = test_state().await;
+        let token = register_user(&state, "graphql_loader_user").await;
+        let router = graphql_router(state.clone());
+
+        let mut course_ids = Vec::new();
+        for name in ["Analysis", "Algebra"] {
+            let response = execute(
+                router.clone(),
+                Some(&token),
+                &format!(r#"mutation {{ saveCourse(input: {{ name: "{name}" }}) }}"#),
+            )
+            .await;
+            let course_id = response["data"]["saveCourse"].as_i64().unwrap();
+            execute(
+                router.clone(),
+                Some(&token),
+                &format!(
+                    r#"mutation {{ saveTopic(input: {{ courseId: {course_id}, name: "{name} topic", details: "" }}) }}"#
+                ),
+            )
+            .await;
+            course_ids.push(course_id);
+        }
+
+        // a topic of the other course that can not be decrypted, loading it would fail the query
+        state.db.execute_batch(&format!(
+            "UPDATE TopicDB SET name = x'00' WHERE course_id = {};",
+            course_ids[1]
+        ));
+        let response = execute(
+            router,
+            Some(&token),
+            &format!(
+                "{{ courses(id: {}) {{ name topics {{ name }} }} }}",
+                course_ids[0]
+            ),
+        )
+        .await;
+        assert!(response["errors"].is_null(), "{}", response);
+        assert_eq!(
+            response["data"]["courses"],
+            json!([{ "name": "Analysis", "topics": [{ "name": "Analysis topic" }] }])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unauthorized() {
+        let state = test_state().await;
+        let router = graphql_router(state);
+
+        let response = execute(router.clone(), None, "{ courses { name } }").await;
+        assert_eq!(response["errors"][0]["extensions"]["status"], 401);
+
+        let response = execute(router, Some("1_invalid"), "{ todos { name } }").await;
+        assert_eq!(response["errors"][0]["extensions"]["status"], 401);
+    }
+}
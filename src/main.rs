@@ -1,5 +1,8 @@
-use std::{env, sync::Arc};
+use std::{env, net::SocketAddr, sync::Arc};
 
+use admin_handler::{AdminConfig, backup_worker};
+
+use auth_handler::{HashConfig, LoginLockoutConfig, TokenConfig};
 use axum::{
     Router,
     http::{
@@ -9,14 +12,26 @@ use axum::{
     routing::get,
 };
 use crypt::crypt_provider::CryptProviders;
-use db::{DBInterface, sqlite::SqliteDatabase};
+use data_handler::{
+    limits::{PayloadLimits, with_body_limit},
+    webhook::{WebhookConfig, delivery_worker},
+};
+use db::{
+    DBInterface,
+    sqlite::{SqliteConfig, SqliteDatabase},
+};
 use log::info;
 use tower_http::cors::CorsLayer;
 
+mod admin_handler;
+mod api_error;
 mod auth_handler;
 mod crypt;
 mod data_handler;
 mod db;
+mod graphql;
+#[cfg(test)]
+mod test_util;
 
 // Define the application state that will be shared across handlers
 struct AppState<DB: DBInterface + Send + Sync> {
@@ -24,6 +39,10 @@ struct AppState<DB: DBInterface + Send + Sync> {
     // this can be any struct that implements DBInterface
     db: Box<DB>,
     crypt_provider: CryptProviders,
+    token_config: TokenConfig,
+    lockout_config: LoginLockoutConfig,
+    hash_config: HashConfig,
+    payload_limits: PayloadLimits,
 }
 
 #[tokio::main]
@@ -31,8 +50,15 @@ async fn main() {
     env_logger::init();
 
     let shared_state = Arc::new(AppState {
-        db: Box::new(SqliteDatabase::new("data/db.sqlite").expect("Failed to create database")),
+        db: Box::new(
+            SqliteDatabase::new_with_config("data/db.sqlite", SqliteConfig::from_env())
+                .expect("Failed to create database"),
+        ),
         crypt_provider: CryptProviders::SimpleCryptProv,
+        token_config: TokenConfig::from_env(),
+        lockout_config: LoginLockoutConfig::from_env(),
+        hash_config: HashConfig::from_env(),
+        payload_limits: PayloadLimits::from_env(),
     });
 
     let origins = [
@@ -43,24 +69,68 @@ async fn main() {
     ];
     let cors = CorsLayer::new()
         .allow_origin(origins)
-        .allow_methods([Method::GET, Method::POST, Method::DELETE])
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
         .allow_headers([AUTHORIZATION, CONTENT_TYPE])
         .allow_credentials(true);
 
     let auth_router = auth_handler::auth_router(shared_state.clone());
-    let data_router = data_handler::data_router(shared_state.clone());
+    // oversized bodies are rejected before they are read completely
+    let data_router = with_body_limit(
+        data_handler::data_router(shared_state.clone()).await,
+        shared_state.payload_limits.max_body_bytes,
+    );
+
+    let admin_config = AdminConfig::from_env();
+    // back up the database regularly
+    tokio::spawn(backup_worker(
+        shared_state.clone(),
+        admin_config.backup.clone(),
+    ));
+
+    // deliver webhooks in the background
+    tokio::spawn(delivery_worker(
+        shared_state.clone(),
+        WebhookConfig::from_env(),
+    ));
+
+    // purge old completed todos according to the user settings
+    tokio::spawn(data_handler::retention::retention_worker(
+        shared_state.clone(),
+    ));
+
+    // delete expired tokens of sessions that are never used again
+    tokio::spawn(auth_handler::token_cleanup_worker(shared_state.clone()));
 
     let app = Router::new()
         .route("/hello", get(|| async { "Hello, World!" }))
         .nest("/auth", auth_router)
         .nest("/data", data_router)
+        .nest(
+            "/admin",
+            admin_handler::admin_router(shared_state.clone(), admin_config),
+        )
+        .nest("/graphql", graphql::graphql_router(shared_state.clone()))
+        .nest(
+            "/share",
+            data_handler::share::share_router(shared_state.clone()),
+        )
         .layer(cors);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000")
         .await
         .expect("Failed to bind TCP listener");
 
-    let server = axum::serve(listener, app);
+    // the client address is used for rate limiting public routes
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    );
 
     info!("Server running on http://localhost:3000");
 
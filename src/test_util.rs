@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Method, Request, StatusCode},
+};
+use serde_json::{Value, json};
+use tower::ServiceExt;
+
+use crate::{
+    AppState, auth_handler,
+    crypt::crypt_provider::CryptProviders,
+    data_handler,
+    db::{DBInterface, mock::MockDatabase, sqlite::SqliteDatabase},
+};
+
+/// creates an app state with an empty in memory database, including all data tables
+pub async fn test_state() -> Arc<AppState<SqliteDatabase>> {
+    test_state_with_db(SqliteDatabase::new_in_memory()).await
+}
+
+/// creates an app state with an empty mock database, including all data tables
+pub async fn mock_state() -> Arc<AppState<MockDatabase>> {
+    test_state_with_db(MockDatabase::new()).await
+}
+
+/// creates an app state with the given database, including all data tables
+pub async fn test_state_with_db<DB: DBInterface + 'static>(db: DB) -> Arc<AppState<DB>> {
+    let state = Arc::new(AppState {
+        db: Box::new(db),
+        crypt_provider: CryptProviders::SimpleCryptProv,
+        token_config: auth_handler::TokenConfig::default(),
+        lockout_config: auth_handler::LoginLockoutConfig::default(),
+        hash_config: auth_handler::HashConfig::default(),
+        payload_limits: data_handler::limits::PayloadLimits::default(),
+    });
+    // the router is not needed, but building it creates the tables of all data objects
+    let _ = data_handler::data_router(state.clone()).await;
+
+    state
+}
+
+/// registers a new user through the auth router and returns its remote token
+pub async fn register_user<DB: DBInterface + 'static>(
+    state: &Arc<AppState<DB>>,
+    username: &str,
+) -> String {
+    let request = Request::post("/register")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({ "username": username, "password": "password" }).to_string(),
+        ))
+        .unwrap();
+
+    let response = auth_handler::auth_router(state.clone())
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body: Value =
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+    body["token"].as_str().unwrap().to_string()
+}
+
+/// sends a request with the bearer token and an optional json body, returns the status and the json response (null if empty)
+pub async fn send_json(
+    router: Router,
+    method: Method,
+    uri: &str,
+    token: &str,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("authorization", format!("Bearer {}", token))
+        .header("content-type", "application/json")
+        .body(body.map_or(Body::empty(), |body| Body::from(body.to_string())))
+        .unwrap();
+
+    let response = router.oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+/// creates a course through the data router and returns its id, needed as parent of topics and exams
+pub async fn create_course(router: Router, token: &str) -> i64 {
+    let (status, body) = send_json(
+        router,
+        Method::POST,
+        "/course",
+        token,
+        Some(json!({ "id": null, "name": "Course" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    body["id"].as_i64().unwrap()
+}